@@ -0,0 +1,381 @@
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    io::{Error, ErrorKind},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    case::{exact_case_term, fold_case},
+    filter::{FilterPatterns, TermFilter},
+    metadata::IndexMetadata,
+};
+
+/// One step of the chain words go through on their way to terms.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "step", rename_all = "snake_case")]
+pub enum Step {
+    /// Lowercases every letter on its own, see [`fold_case`]. With
+    /// `keep_original` a word it changes is also indexed under its original
+    /// spelling, see [`exact_case_term`].
+    FoldCase {
+        #[serde(default)]
+        keep_original: bool,
+    },
+    /// Drops `words`, as they are by this step.
+    Stopwords { words: Vec<String> },
+    /// Cuts the longest of `suffixes` the word ends with, as long as
+    /// `min_stem` characters are left of it.
+    StripSuffix { suffixes: Vec<String>, min_stem: usize },
+    /// Drops the words `patterns` reject, see [`TermFilter`].
+    Filter { patterns: FilterPatterns },
+}
+
+/// The steps of an [`Analyzer`] in order, as `metadata.json` keeps them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnalyzerConfig {
+    pub steps: Vec<Step>,
+}
+
+impl Default for AnalyzerConfig {
+    /// Case folding alone, what indexes were built with before the chain
+    /// could be set.
+    fn default() -> Self {
+        Self {
+            steps: vec![Step::FoldCase { keep_original: false }],
+        }
+    }
+}
+
+/// [`Step`] ready to be applied.
+#[derive(Debug, Clone)]
+enum Stage {
+    FoldCase { keep_original: bool },
+    Stopwords(HashSet<String>),
+    /// Longest suffix first.
+    StripSuffix { suffixes: Vec<String>, min_stem: usize },
+    Filter(TermFilter),
+}
+
+/// What a word is indexed under.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Analyzed {
+    pub term: String,
+    /// The exact-case term of the word, when it is indexed too.
+    pub original: Option<String>,
+}
+
+/// Normalization shared by the indexer and the query parser, so a word of a
+/// query is looked up under the term the same word was indexed under.
+#[derive(Debug, Clone)]
+pub struct Analyzer {
+    config: AnalyzerConfig,
+    stages: Vec<Stage>,
+}
+
+impl Analyzer {
+    /// Fails with [`ErrorKind::InvalidInput`] on filter patterns that don't
+    /// compile and on empty suffixes.
+    pub fn new(config: AnalyzerConfig) -> Result<Self, Error> {
+        let stages = config
+            .steps
+            .iter()
+            .map(|step| {
+                Ok(match step {
+                    Step::FoldCase { keep_original } => Stage::FoldCase {
+                        keep_original: *keep_original,
+                    },
+                    Step::Stopwords { words } => Stage::Stopwords(words.iter().cloned().collect()),
+                    Step::StripSuffix { suffixes, min_stem } => {
+                        if suffixes.iter().any(String::is_empty) {
+                            return Err(Error::new(ErrorKind::InvalidInput, "empty suffix to strip"));
+                        }
+                        let mut suffixes = suffixes.clone();
+                        suffixes.sort_by_key(|v| std::cmp::Reverse(v.len()));
+                        Stage::StripSuffix {
+                            suffixes,
+                            min_stem: *min_stem,
+                        }
+                    }
+                    Step::Filter { patterns } => Stage::Filter(TermFilter::new(patterns.clone())?),
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(Self { config, stages })
+    }
+
+    /// The analyzer the index in `directory` was built with. Indexes from
+    /// before the chain was recorded get the one their filter and case
+    /// settings made up.
+    pub async fn from_metadata(directory: &str) -> Result<Self, Error> {
+        let metadata = IndexMetadata::load_or_legacy(directory).await?;
+        match metadata.analyzer {
+            Some(config) => Self::new(config),
+            None => {
+                let mut steps = vec![Step::FoldCase {
+                    keep_original: metadata.case_preserving,
+                }];
+                if let Some(patterns) = metadata.filter {
+                    steps.push(Step::Filter { patterns });
+                }
+                Self::new(AnalyzerConfig { steps })
+            }
+        }
+    }
+
+    pub fn config(&self) -> &AnalyzerConfig {
+        &self.config
+    }
+
+    /// Drops the words `filter` rejects, in place of the filter step there
+    /// was or after every step.
+    pub fn with_filter(mut self, filter: TermFilter) -> Self {
+        let step = Step::Filter {
+            patterns: filter.patterns().clone(),
+        };
+        match self.stages.iter().position(|v| matches!(v, Stage::Filter(_))) {
+            Some(i) => {
+                self.config.steps[i] = step;
+                self.stages[i] = Stage::Filter(filter);
+            }
+            None => {
+                self.config.steps.push(step);
+                self.stages.push(Stage::Filter(filter));
+            }
+        }
+        self
+    }
+
+    /// Also index the original spelling of the words case folding changes,
+    /// folding case first if no step did.
+    pub fn with_original_case(mut self) -> Self {
+        match self.stages.iter().position(|v| matches!(v, Stage::FoldCase { .. })) {
+            Some(i) => {
+                self.config.steps[i] = Step::FoldCase { keep_original: true };
+                self.stages[i] = Stage::FoldCase { keep_original: true };
+            }
+            None => {
+                self.config.steps.insert(0, Step::FoldCase { keep_original: true });
+                self.stages.insert(0, Stage::FoldCase { keep_original: true });
+            }
+        }
+        self
+    }
+
+    /// Patterns of the filter step, if there is one.
+    pub fn filter_patterns(&self) -> Option<FilterPatterns> {
+        self.stages.iter().find_map(|v| match v {
+            Stage::Filter(filter) => Some(filter.patterns().clone()),
+            _ => None,
+        })
+    }
+
+    /// Whether original spellings are indexed too.
+    pub fn keeps_original(&self) -> bool {
+        self.stages
+            .iter()
+            .any(|v| matches!(v, Stage::FoldCase { keep_original: true }))
+    }
+
+    /// Runs `word` through every step, `None` if one of them drops it.
+    pub fn analyze(&self, word: String) -> Option<Analyzed> {
+        let mut analyzed = Analyzed {
+            term: word,
+            original: None,
+        };
+        for stage in self.stages.iter() {
+            match stage {
+                Stage::FoldCase { keep_original } => {
+                    if let Some(folded) = changed(fold_case(&analyzed.term)) {
+                        if *keep_original && analyzed.original.is_none() {
+                            analyzed.original = Some(exact_case_term(&analyzed.term));
+                        }
+                        analyzed.term = folded;
+                    }
+                }
+                Stage::Stopwords(words) => {
+                    if words.contains(&analyzed.term) {
+                        return None;
+                    }
+                }
+                Stage::StripSuffix { suffixes, min_stem } => {
+                    let stem = suffixes.iter().find_map(|suffix| {
+                        let stem = analyzed.term.strip_suffix(suffix.as_str())?;
+                        (stem.chars().count() >= *min_stem).then_some(stem.len())
+                    });
+                    if let Some(len) = stem {
+                        analyzed.term.truncate(len);
+                    }
+                }
+                Stage::Filter(filter) => {
+                    if !filter.allows(&analyzed.term) {
+                        return None;
+                    }
+                }
+            }
+        }
+        Some(analyzed)
+    }
+
+    /// The term a word of a query is looked up under.
+    pub fn term(&self, word: &str) -> Option<String> {
+        self.analyze(word.to_string()).map(|v| v.term)
+    }
+}
+
+impl Default for Analyzer {
+    fn default() -> Self {
+        Self::new(AnalyzerConfig::default()).unwrap()
+    }
+}
+
+fn changed(word: Cow<'_, str>) -> Option<String> {
+    match word {
+        Cow::Borrowed(_) => None,
+        Cow::Owned(v) => Some(v),
+    }
+}
+
+#[cfg(test)]
+mod tst {
+    use std::{collections::BTreeSet, io::Error};
+
+    use tokio::fs;
+
+    use crate::{
+        config::IndexerConfig,
+        execute::{execute, QueryLimits},
+        filter::FilterPatterns,
+        indexed::{Dictionary, IndexMerger, IndexParser, IndexTermProvider, IndexedBuilder},
+        parser::{ParseController, TermProvider},
+        query::parse_query_analyzed,
+        segment::{CommonSegmentSelector, CommonSegments},
+        testsupport::scratch,
+        zones::ZoneSet,
+    };
+
+    use super::{Analyzed, Analyzer, AnalyzerConfig, Step};
+
+    fn chain() -> AnalyzerConfig {
+        AnalyzerConfig {
+            steps: vec![
+                Step::FoldCase { keep_original: true },
+                Step::Stopwords {
+                    words: vec!["the".to_string(), "of".to_string()],
+                },
+                Step::StripSuffix {
+                    suffixes: vec!["s".to_string(), "ing".to_string(), "ings".to_string()],
+                    min_stem: 3,
+                },
+                Step::Filter {
+                    patterns: FilterPatterns {
+                        drop: vec!["^x".to_string()],
+                        keep: vec![],
+                    },
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn steps_apply_in_order() {
+        let analyzer = Analyzer::new(chain()).unwrap();
+        let analyzed = |word: &str| analyzer.analyze(word.to_string());
+        assert_eq!(
+            analyzed("Paintings"),
+            Some(Analyzed {
+                term: "paint".to_string(),
+                original: Some("=Paintings".to_string()),
+            })
+        );
+        assert_eq!(analyzed("runs").map(|v| (v.term, v.original)), Some(("run".to_string(), None)));
+        // Too short a stem is left whole.
+        assert_eq!(analyzer.term("ring"), Some("ring".to_string()));
+        assert_eq!(analyzer.term("its"), Some("its".to_string()));
+        assert_eq!(analyzer.term("The"), None);
+        assert_eq!(analyzer.term("Xylophones"), None);
+
+        let json = serde_json::to_string(analyzer.config()).unwrap();
+        assert_eq!(serde_json::from_str::<AnalyzerConfig>(&json).unwrap(), chain());
+        assert!(analyzer.keeps_original());
+        assert_eq!(analyzer.filter_patterns().unwrap().drop, ["^x"]);
+        assert!(Analyzer::new(AnalyzerConfig {
+            steps: vec![Step::StripSuffix {
+                suffixes: vec![String::new()],
+                min_stem: 1
+            }]
+        })
+        .is_err());
+
+        let default = Analyzer::default();
+        assert_eq!(default.term("Straße"), Some("straße".to_string()));
+        assert!(!default.keeps_original());
+    }
+
+    #[tokio::test]
+    async fn query_terms_match_index_terms() -> Result<(), Error> {
+        let root = scratch("analyzer_chain").await?;
+        let texts = [
+            ("The Painting", "paintings of the Runners running xenon Runs"),
+            ("Rings", "the ring sings of Strings"),
+        ];
+        let content = texts
+            .iter()
+            .map(|(title, text)| format!("<title>\n{title}\n</title>\n<text>\n{text}\n</text>\n"))
+            .collect::<String>();
+        let path = root.join("0.xml").to_str().unwrap().to_string();
+        fs::write(&path, content).await?;
+        let destination = root.join("res").to_str().unwrap().to_string();
+        let config = IndexerConfig::new(1000, 6)?;
+        ParseController::<IndexParser, _, _>::new(
+            vec![path],
+            destination.clone(),
+            root.join("buffer").to_str().unwrap().to_string(),
+            1,
+            IndexedBuilder::new(config, ZoneSet::new(["title", "text"])?)?
+                .with_analyzer(chain())?,
+            IndexMerger::new(config.merger()),
+        )
+        .create_dictionary()
+        .await?;
+
+        let analyzer = Analyzer::from_metadata(&destination).await?;
+        assert_eq!(*analyzer.config(), chain());
+        let mut indexed = BTreeSet::new();
+        let mut provider = IndexTermProvider::<CommonSegments>::new(&destination).await?;
+        while let Some(term) = provider.next_term().await {
+            indexed.insert(term.term);
+        }
+        let mut expected = BTreeSet::new();
+        for word in texts.iter().flat_map(|(title, text)| title.split(' ').chain(text.split(' '))) {
+            if let Some(analyzed) = analyzer.analyze(word.to_string()) {
+                expected.extend(analyzed.original);
+                expected.insert(analyzed.term);
+            }
+        }
+        assert_eq!(indexed, expected);
+        for term in ["paint", "run", "runn", "ring", "sing", "str", "=Runners"] {
+            assert!(indexed.contains(term), "{term}");
+        }
+
+        let selector = CommonSegmentSelector::new();
+        let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+        for (raw, documents) in [
+            ("The PAINTINGS", vec![0]),
+            ("title:rings", vec![1]),
+            ("sings OR running", vec![0, 1]),
+            ("strings xenon", vec![1]),
+        ] {
+            let query = parse_query_analyzed(raw, &selector, &[], &analyzer).unwrap();
+            let found = execute(&query, &mut dictionary, QueryLimits::default()).await.unwrap();
+            assert_eq!(found.documents, documents, "{raw}");
+        }
+        for raw in ["the OF", "xenon"] {
+            assert!(parse_query_analyzed(raw, &selector, &[], &analyzer).is_err(), "{raw}");
+        }
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,202 @@
+use std::{
+    collections::BTreeMap,
+    io::{Error, ErrorKind, SeekFrom},
+};
+
+use roaring::RoaringBitmap;
+use save::writer::variable_load;
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt, BufReader},
+};
+
+use crate::layout::IndexLayout;
+#[cfg(feature = "build")]
+use crate::{
+    indexed::{Dictionary, TermBound},
+    segment::Segments,
+};
+#[cfg(feature = "build")]
+use save::writer::variable_encode_u64;
+
+/// Documents of the frequent terms of an index as roaring bitmaps, stored as
+/// `bitmap_part.bin` by a merge [`crate::indexed::IndexMerger::with_bitmaps`].
+///
+/// Boolean queries over these terms alone are answered with bitmap set
+/// operations instead of decoding postings, see [`crate::execute`]. Rare
+/// terms keep only their postings, which are small enough to decode.
+///
+/// The file starts with the number of terms and, for each one in dictionary
+/// order, its length, its bytes and the length of its bitmap; the bitmaps
+/// follow in the same order.
+pub struct PostingBitmaps {
+    file: BufReader<File>,
+    /// Where the bitmap of every term starts in the file and its length.
+    entries: BTreeMap<String, (u64, usize)>,
+}
+
+fn invalid(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Error {
+    Error::new(ErrorKind::InvalidData, e)
+}
+
+impl PostingBitmaps {
+    /// The bitmaps of the index in `directory`, or `None` if it was built
+    /// without them.
+    pub async fn load(directory: &String) -> Result<Option<Self>, Error> {
+        let mut file = match File::open(IndexLayout::detect(directory).await?.bitmaps(directory)).await {
+            Ok(v) => BufReader::new(v),
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let len = file.read_u64().await?;
+        let mut lengths = Vec::new();
+        for _ in 0..len {
+            let mut term = vec![0; variable_load(&mut file).await?];
+            file.read_exact(&mut term).await?;
+            let term = String::from_utf8(term).map_err(invalid)?;
+            lengths.push((term, variable_load(&mut file).await?));
+        }
+        let mut offset = file.stream_position().await?;
+        let mut entries = BTreeMap::new();
+        for (term, length) in lengths {
+            entries.insert(term, (offset, length));
+            offset += length as u64;
+        }
+        if file.get_ref().metadata().await?.len() != offset {
+            return Err(invalid(format!("bitmaps of {directory} don't fill their file")));
+        }
+        Ok(Some(Self { file, entries }))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, term: &str) -> bool {
+        self.entries.contains_key(term)
+    }
+
+    /// The documents of `term`, `None` if it has no bitmap.
+    pub async fn get(&mut self, term: &str) -> Result<Option<RoaringBitmap>, Error> {
+        let Some((offset, length)) = self.entries.get(term).copied() else {
+            return Ok(None);
+        };
+        self.file.seek(SeekFrom::Start(offset)).await?;
+        let mut bytes = vec![0; length];
+        self.file.read_exact(&mut bytes).await?;
+        RoaringBitmap::deserialize_from(&bytes[..]).map(Some).map_err(invalid)
+    }
+}
+
+/// Writes a bitmap of every term of the index in `directory` found in at
+/// least `min_df` documents, and returns how many it wrote.
+#[cfg(feature = "build")]
+pub(crate) async fn write_bitmaps<S: Segments>(directory: &String, min_df: usize) -> Result<usize, Error> {
+    let mut dictionary = Dictionary::<S>::new(directory).await?;
+    let mut frequent = Vec::new();
+    {
+        let mut terms = dictionary.range(TermBound::Unbounded, TermBound::Unbounded).await?;
+        while let Some((term, stats)) = terms.next().await? {
+            if stats.document_frequency >= min_df {
+                frequent.push(term);
+            }
+        }
+    }
+    let mut head = Vec::new();
+    let mut bitmaps = Vec::new();
+    head.extend_from_slice(&(frequent.len() as u64).to_be_bytes());
+    for term in frequent.iter() {
+        let mut bitmap = RoaringBitmap::new();
+        let mut postings = dictionary.postings(term).await?.unwrap();
+        while let Some((document, _)) = postings.next().await? {
+            bitmap.insert(document as u32);
+        }
+        let start = bitmaps.len();
+        bitmap.serialize_into(&mut bitmaps)?;
+        variable_encode_u64(term.len() as u64, &mut head);
+        head.extend_from_slice(term.as_bytes());
+        variable_encode_u64((bitmaps.len() - start) as u64, &mut head);
+    }
+    head.extend_from_slice(&bitmaps);
+    tokio::fs::write(dictionary.layout().bitmaps(directory), head).await?;
+    Ok(frequent.len())
+}
+
+#[cfg(test)]
+mod tst {
+    use std::io::Error;
+
+    use tokio::fs;
+
+    use crate::{
+        execute::{execute, QueryLimits},
+        indexed::Dictionary,
+        query::parse_query,
+        segment::{CommonSegmentSelector, CommonSegments},
+        testsupport::{scratch, word, CorpusSpec},
+    };
+
+    use super::PostingBitmaps;
+
+    #[tokio::test]
+    async fn bitmap_queries_match_list_queries() -> Result<(), Error> {
+        let root = scratch("bitmaps").await?;
+        let corpus = CorpusSpec {
+            docs: 300,
+            ..CorpusSpec::default()
+        }
+        .generate(&root.join("corpus"))
+        .await?;
+        let with = corpus.index_with(&root.join("with"), |v| v.with_bitmaps(20)).await?;
+        let without = corpus.index(&root.join("without")).await?;
+
+        let bitmaps = PostingBitmaps::load(&with).await?.unwrap();
+        let frequent = corpus.postings.values().filter(|v| v.len() >= 20).count();
+        assert_eq!(bitmaps.len(), frequent);
+        assert!(bitmaps.contains(&word(0)) && !bitmaps.contains(&word(150)));
+        assert!(PostingBitmaps::load(&without).await?.is_none());
+
+        let (a, b, c, rare) = (word(0), word(1), word(2), word(150));
+        let queries = [
+            a.clone(),
+            format!("{a} {b}"),
+            format!("{a} {b} {c}"),
+            format!("{a} OR {b}"),
+            format!("{a} NOT {b}"),
+            format!("({a} OR {c}) NOT {b}"),
+            format!("{a} {rare}"),
+            format!("{a} OR {rare}"),
+            format!("title:{a} {b}"),
+            format!("{a} NOT title:{b}"),
+        ];
+        let mut with = Dictionary::<CommonSegments>::new(&with).await?;
+        let mut without = Dictionary::<CommonSegments>::new(&without).await?;
+        let selector = CommonSegmentSelector::new();
+        for raw in queries.iter() {
+            let query = parse_query(raw, &selector).unwrap();
+            let expected = execute(&query, &mut without, QueryLimits::default()).await.unwrap();
+            let found = execute(&query, &mut with, QueryLimits::default()).await.unwrap();
+            assert_eq!(found.documents, expected.documents, "{raw}");
+            assert_eq!(found.expanded_terms, expected.expanded_terms, "{raw}");
+        }
+        // A bitmap is one read, where finding a term takes a search.
+        let reads = with.reads();
+        execute(&parse_query(&queries[1], &selector).unwrap(), &mut with, QueryLimits::default())
+            .await
+            .unwrap();
+        assert_eq!(with.reads() - reads, 2);
+        assert_eq!(
+            execute(&parse_query(&a, &selector).unwrap(), &mut with, QueryLimits::default())
+                .await
+                .unwrap()
+                .documents,
+            corpus.documents(&a)
+        );
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,206 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// Lexical blocks a [`crate::watcher::DictionaryPool`] keeps by default.
+pub const DEFAULT_BLOCK_CACHE: usize = 64;
+
+/// Terms of one lexical block, decoded from its start up to the last one
+/// asked for.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct LexicalBlock {
+    pub(crate) prefix: Vec<u8>,
+    pub(crate) terms: Vec<String>,
+    /// Offset in the lexical part of the entry after the last decoded term.
+    pub(crate) end: u64,
+}
+
+/// What [`BlockCache::lookup`] holds for a term.
+pub(crate) enum Lookup {
+    Hit(Arc<LexicalBlock>),
+    /// The block is there, but not decoded as far as the term.
+    Partial(Arc<LexicalBlock>),
+    Miss,
+}
+
+/// How a [`BlockCache`] did since it was created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BlockCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    /// Blocks held right now.
+    pub blocks: usize,
+}
+
+/// Decoded lexical blocks keyed by their lexical pointer, the least
+/// recently used one dropped once `capacity` are held. Clones share the
+/// blocks, so the readers of one index can use the same cache; a capacity
+/// of 0 turns it off.
+#[derive(Debug, Clone, Default)]
+pub struct BlockCache {
+    shared: Arc<Mutex<Shared>>,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    capacity: usize,
+    /// Blocks with the tick they were last used at.
+    blocks: HashMap<usize, (Arc<LexicalBlock>, u64)>,
+    tick: u64,
+    stats: BlockCacheStats,
+}
+
+impl Shared {
+    fn evict_to(&mut self, capacity: usize) {
+        while self.blocks.len() > capacity {
+            let oldest = *self
+                .blocks
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .unwrap()
+                .0;
+            self.blocks.remove(&oldest);
+            self.stats.evictions += 1;
+        }
+    }
+}
+
+impl BlockCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            shared: Arc::new(Mutex::new(Shared {
+                capacity,
+                ..Default::default()
+            })),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.lock().unwrap().capacity
+    }
+
+    /// Changes the capacity for every clone, dropping the least recently
+    /// used blocks over it.
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.capacity = capacity;
+        shared.evict_to(capacity);
+    }
+
+    pub fn stats(&self) -> BlockCacheStats {
+        let shared = self.shared.lock().unwrap();
+        BlockCacheStats {
+            blocks: shared.blocks.len(),
+            ..shared.stats
+        }
+    }
+
+    /// Looks up term `index` of the block at `lexical_pointer`; anything
+    /// short of the term counts as a miss.
+    pub(crate) fn lookup(&self, lexical_pointer: usize, index: usize) -> Lookup {
+        let mut shared = self.shared.lock().unwrap();
+        shared.tick += 1;
+        let tick = shared.tick;
+        let found = match shared.blocks.get_mut(&lexical_pointer) {
+            Some((block, used)) => {
+                *used = tick;
+                if index < block.terms.len() {
+                    Lookup::Hit(block.clone())
+                } else {
+                    Lookup::Partial(block.clone())
+                }
+            }
+            None => Lookup::Miss,
+        };
+        match found {
+            Lookup::Hit(_) => shared.stats.hits += 1,
+            _ => shared.stats.misses += 1,
+        }
+        found
+    }
+
+    /// Holds `block`, replacing a shorter decoding of it.
+    pub(crate) fn insert(&self, lexical_pointer: usize, block: Arc<LexicalBlock>) {
+        let mut shared = self.shared.lock().unwrap();
+        if shared.capacity == 0 {
+            return;
+        }
+        shared.tick += 1;
+        let tick = shared.tick;
+        let kept = shared
+            .blocks
+            .entry(lexical_pointer)
+            .or_insert_with(|| (block.clone(), tick));
+        if kept.0.terms.len() < block.terms.len() {
+            kept.0 = block;
+        }
+        kept.1 = tick;
+        let capacity = shared.capacity;
+        shared.evict_to(capacity);
+    }
+}
+
+#[cfg(test)]
+mod tst {
+    use super::*;
+
+    fn block(terms: &[&str]) -> Arc<LexicalBlock> {
+        Arc::new(LexicalBlock {
+            prefix: Vec::new(),
+            terms: terms.iter().map(|v| v.to_string()).collect(),
+            end: 0,
+        })
+    }
+
+    fn hit(cache: &BlockCache, pointer: usize, index: usize) -> Option<String> {
+        match cache.lookup(pointer, index) {
+            Lookup::Hit(block) => Some(block.terms[index].clone()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn drops_least_recently_used() {
+        let cache = BlockCache::new(2);
+        cache.insert(0, block(&["a"]));
+        cache.insert(10, block(&["b"]));
+        assert_eq!(hit(&cache, 0, 0).as_deref(), Some("a"));
+        cache.insert(20, block(&["c"]));
+        assert_eq!(hit(&cache, 10, 0), None);
+        assert_eq!(hit(&cache, 0, 0).as_deref(), Some("a"));
+        assert_eq!(hit(&cache, 20, 0).as_deref(), Some("c"));
+        assert_eq!(
+            cache.stats(),
+            BlockCacheStats {
+                hits: 3,
+                misses: 1,
+                evictions: 1,
+                blocks: 2
+            }
+        );
+    }
+
+    #[test]
+    fn partial_blocks_grow() {
+        let cache = BlockCache::new(4);
+        cache.insert(0, block(&["a", "b"]));
+        assert!(matches!(cache.lookup(0, 2), Lookup::Partial(v) if v.terms.len() == 2));
+        cache.insert(0, block(&["a", "b", "c"]));
+        cache.insert(0, block(&["a"]));
+        assert_eq!(hit(&cache, 0, 2).as_deref(), Some("c"));
+    }
+
+    #[test]
+    fn clones_share_and_zero_disables() {
+        let cache = BlockCache::new(4);
+        let other = cache.clone();
+        cache.insert(0, block(&["a"]));
+        assert_eq!(hit(&other, 0, 0).as_deref(), Some("a"));
+        other.set_capacity(0);
+        assert_eq!(cache.stats().blocks, 0);
+        cache.insert(0, block(&["a"]));
+        assert_eq!(hit(&cache, 0, 0), None);
+    }
+}
@@ -0,0 +1,217 @@
+use std::io::{Error, ErrorKind};
+
+use save::writer::{variable_load, CountedWriter, FinishedFile};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, BufReader, BufWriter},
+};
+
+use crate::{layout::IndexLayout, term_ord::term_cmp};
+
+#[derive(Debug, Clone, PartialEq)]
+struct Block {
+    first: String,
+    cursor: u64,
+    lexical_pointer: u64,
+}
+
+/// First term of every lexical block with where the block starts, stored as
+/// `block_dir.bin`. Small enough to keep in memory next to an open dictionary.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BlockDirectory {
+    blocks: Vec<Block>,
+}
+
+/// The block a term would be in: its lexical pointer, the ordinal of its
+/// first term and how many terms it holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockRange {
+    pub lexical_pointer: u64,
+    pub cursor: u64,
+    pub len: u64,
+}
+
+impl BlockDirectory {
+    /// Blocks have to be pushed in the order they were written.
+    pub fn push(&mut self, first: &str, cursor: u64, lexical_pointer: u64) {
+        self.blocks.push(Block {
+            first: first.to_string(),
+            cursor,
+            lexical_pointer,
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// The only block that may hold `term`, for a dictionary of `terms` terms.
+    pub fn range(&self, term: &str, terms: u64) -> Option<BlockRange> {
+        let i = self
+            .blocks
+            .partition_point(|v| term_cmp(&v.first, term).is_le())
+            .checked_sub(1)?;
+        let block = &self.blocks[i];
+        let end = self.blocks.get(i + 1).map_or(terms, |v| v.cursor);
+        Some(BlockRange {
+            lexical_pointer: block.lexical_pointer,
+            cursor: block.cursor,
+            len: end - block.cursor,
+        })
+    }
+
+    pub async fn save(&self, directory: &str) -> Result<FinishedFile, Error> {
+        let mut writer = CountedWriter::new(BufWriter::new(
+            File::create(IndexLayout::detect(directory).await?.block_dir(directory)).await?,
+        ));
+        writer.push_u64(self.blocks.len() as u64).await?;
+        for v in self.blocks.iter() {
+            writer.push_variable_u64(v.first.len() as u64).await?;
+            writer.push(v.first.as_bytes()).await?;
+            writer.push_u64(v.cursor).await?;
+            writer.push_u64(v.lexical_pointer).await?;
+        }
+        writer.finish().await
+    }
+
+    pub async fn load(directory: &str) -> Result<Self, Error> {
+        let mut reader = BufReader::new(File::open(IndexLayout::detect(directory).await?.block_dir(directory)).await?);
+        let len = reader.read_u64().await? as usize;
+        let mut blocks = Vec::with_capacity(len);
+        for _ in 0..len {
+            let mut first = vec![0u8; variable_load(&mut reader).await?];
+            reader.read_exact(&mut first).await?;
+            blocks.push(Block {
+                first: String::from_utf8(first)
+                    .map_err(|e| Error::new(ErrorKind::InvalidData, e))?,
+                cursor: reader.read_u64().await?,
+                lexical_pointer: reader.read_u64().await?,
+            });
+        }
+        Ok(Self { blocks })
+    }
+}
+
+#[cfg(test)]
+mod tst {
+    use std::io::Error;
+
+    use tokio::fs;
+
+    use crate::{
+        config::IndexerConfig,
+        indexed::{Dictionary, IndexMergeSaver, IndexMerger, IndexParser, IndexedBuilder, IndexedTerm, UsageData},
+        parser::ParseController,
+        segment::CommonSegments,
+        zones::ZoneSet,
+    };
+
+    fn word(mut i: usize) -> String {
+        let mut word = String::new();
+        for _ in 0..3 {
+            word.push((b'a' + (i % 26) as u8) as char);
+            i /= 26;
+        }
+        word
+    }
+
+    #[tokio::test]
+    async fn block_lookups_match_binary_search() -> Result<(), Error> {
+        let root = std::env::temp_dir().join(format!("block_dir_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root).await;
+        fs::create_dir_all(&root).await?;
+        let path = root.join("0.xml").to_str().unwrap().to_string();
+        let content = (0..40)
+            .map(|d| {
+                let text = (0..50).map(|i| word(d * 50 + i)).collect::<Vec<_>>().join(" ");
+                format!("<title>\nshared\n</title>\n<text>\n{text}\n</text>\n")
+            })
+            .collect::<String>();
+        fs::write(&path, content).await?;
+
+        let destination = root.join("res").to_str().unwrap().to_string();
+        let config = IndexerConfig::new(1000, 6)?;
+        ParseController::<IndexParser, _, _>::new(
+            vec![path],
+            destination.clone(),
+            root.join("buffer").to_str().unwrap().to_string(),
+            1,
+            IndexedBuilder::new(config, ZoneSet::new(["title", "text"])?)?,
+            IndexMerger::new(config.merger()),
+        )
+        .create_dictionary()
+        .await?;
+
+        let blocks = fs::metadata(format!("{destination}/block_dir.bin")).await?.len();
+        let index = fs::metadata(format!("{destination}/dictionary.txt")).await?.len()
+            + fs::metadata(format!("{destination}/lexical_part.txt")).await?.len()
+            + fs::metadata(format!("{destination}/index_part.txt")).await?.len();
+        assert!(blocks * 4 < index, "{blocks} of {index}");
+
+        let mut two_level = Dictionary::<CommonSegments>::new(&destination).await?;
+        fs::remove_file(format!("{destination}/block_dir.bin")).await?;
+        let mut plain = Dictionary::<CommonSegments>::new(&destination).await?;
+        assert_eq!(two_level.len(), 2001);
+
+        let mut queries = (0..2000).map(word).collect::<Vec<_>>();
+        queries.extend(["", "a", "zzzz", "shared", "sharee", "mmm", "aab~"].map(String::from));
+        for term in queries.iter() {
+            let (before_two, before_plain) = (two_level.reads(), plain.reads());
+            let found = two_level.find(term).await?;
+            let expected = plain.find(term).await?;
+            assert!(two_level.reads() - before_two <= 4, "{term}");
+            assert!(plain.reads() > before_plain, "{term}");
+            match (found, expected) {
+                (Some(found), Some(expected)) => {
+                    assert_eq!(found.term, expected.term);
+                    assert_eq!(
+                        found.indexes.iter().map(|v| v.0).collect::<Vec<_>>(),
+                        expected.indexes.iter().map(|v| v.0).collect::<Vec<_>>()
+                    );
+                }
+                (None, None) => {}
+                (found, expected) => panic!("{term}: {:?} vs {:?}", found.is_some(), expected.is_some()),
+            }
+        }
+        assert!(two_level.reads() * 3 < plain.reads(), "{} vs {}", two_level.reads(), plain.reads());
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+
+    /// A growing shared prefix makes the saver hold a term back while it
+    /// flushes the others; the block still has to start at the right ordinal.
+    #[tokio::test]
+    async fn held_back_terms_keep_block_ordinals() -> Result<(), Error> {
+        let directory = std::env::temp_dir()
+            .join(format!("block_dir_held_{}", std::process::id()))
+            .to_str()
+            .unwrap()
+            .to_string();
+        fs::create_dir_all(&directory).await?;
+        let terms = ["async", "asyncs", "asyncss", "runtime", "shared"];
+        let mut saver = IndexMergeSaver::<CommonSegments>::new(directory.clone(), 6).await?;
+        for (i, t) in terms.iter().enumerate() {
+            let mut term = IndexedTerm::new(t.to_string());
+            let mut usage = UsageData::new();
+            *usage.use_count_mut() = 1;
+            term.indexes.push(i, usage);
+            term.use_count = 1;
+            saver.push(term).await?;
+        }
+        saver.finish().await?;
+
+        let mut dictionary = Dictionary::<CommonSegments>::new(&directory).await?;
+        for (i, term) in terms.iter().enumerate() {
+            let found = dictionary.find(term).await?.map(|v| (v.term, v.indexes.iter().map(|v| v.0).collect::<Vec<_>>()));
+            assert_eq!(found, Some((term.to_string(), vec![i])));
+        }
+
+        fs::remove_dir_all(&directory).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,248 @@
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind},
+    path::PathBuf,
+};
+
+use tokio::{
+    fs::{self, File},
+    io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter},
+};
+
+use crate::layout::IndexLayout;
+
+/// `title` the way the tokenizer sees it: lowercase words split on anything
+/// that is not a letter.
+pub fn normalize_title(title: &str) -> String {
+    title
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|v| !v.is_empty())
+        .map(|v| v.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Static boosts read from a `boosts.tsv` sidecar: a source path or a
+/// document title, a tab and the boost, one per line.
+///
+/// A title entry wins over the entry of the file the document came from.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Boosts {
+    entries: Vec<(String, f64)>,
+}
+
+impl Boosts {
+    pub fn parse(content: &str) -> Result<Self, Error> {
+        let mut entries = Vec::new();
+        for (i, line) in content.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |reason: &str| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("boosts line {}: {reason}", i + 1),
+                )
+            };
+            let (key, boost) = line.rsplit_once('\t').ok_or_else(|| invalid("no tab"))?;
+            let boost = boost
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|v| v.is_finite() && *v >= 0.0)
+                .ok_or_else(|| invalid("boost is not a non-negative number"))?;
+            entries.push((key.to_string(), boost));
+        }
+        Ok(Self { entries })
+    }
+
+    pub async fn load(path: &str) -> Result<Self, Error> {
+        Self::parse(&fs::read_to_string(path).await?)
+    }
+
+    /// Whether some entry can only be matched against document titles.
+    pub fn has_titles(&self, paths: &[PathBuf]) -> bool {
+        let paths = paths.iter().map(|v| crate::paths::encode(v)).collect::<Vec<_>>();
+        self.entries.iter().any(|(key, _)| !paths.contains(key))
+    }
+
+    /// Boosts by doc id for `documents`, given as (doc id, source path, title),
+    /// with the entries that matched no document.
+    pub fn resolve<'a>(
+        &self,
+        ids: usize,
+        documents: impl IntoIterator<Item = (usize, &'a str, Option<&'a str>)>,
+    ) -> (DocumentBoosts, Vec<String>) {
+        let mut paths = HashMap::new();
+        let mut titles = HashMap::new();
+        for (i, (key, boost)) in self.entries.iter().enumerate() {
+            paths.insert(key.as_str(), (i, *boost));
+            titles.insert(normalize_title(key), (i, *boost));
+        }
+        let mut used = vec![false; self.entries.len()];
+        let mut boosts = vec![1.0; ids];
+        for (document, path, title) in documents {
+            let found = title
+                .and_then(|v| titles.get(v))
+                .or_else(|| paths.get(path));
+            if let Some((i, boost)) = found {
+                used[*i] = true;
+                boosts[document] = *boost as f32;
+            }
+        }
+        let unknown = self
+            .entries
+            .iter()
+            .zip(used)
+            .filter(|(_, used)| !used)
+            .map(|((key, _), _)| key.clone())
+            .collect();
+        (DocumentBoosts { boosts }, unknown)
+    }
+}
+
+/// Boost of every document, by document id, stored as `boosts.txt`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DocumentBoosts {
+    pub boosts: Vec<f32>,
+}
+
+impl DocumentBoosts {
+    /// Documents without an entry are boosted by 1.
+    pub fn get(&self, document: usize) -> f64 {
+        self.boosts.get(document).map_or(1.0, |v| *v as f64)
+    }
+
+    pub async fn save(&self, directory: &str) -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(IndexLayout::detect(directory).await?.boosts(directory)).await?);
+        writer.write_u64(self.boosts.len() as u64).await?;
+        for v in self.boosts.iter() {
+            writer.write_f32(*v).await?;
+        }
+        writer.flush().await
+    }
+
+    pub async fn load(directory: &str) -> Result<Self, Error> {
+        let mut reader = BufReader::new(File::open(IndexLayout::detect(directory).await?.boosts(directory)).await?);
+        let len = reader.read_u64().await? as usize;
+        let mut boosts = Vec::with_capacity(len);
+        for _ in 0..len {
+            boosts.push(reader.read_f32().await?);
+        }
+        Ok(Self { boosts })
+    }
+}
+
+#[cfg(test)]
+mod tst {
+    use std::io::Error;
+
+    use tokio::fs;
+
+    use crate::{
+        config::IndexerConfig,
+        indexed::{Dictionary, IndexMerger, IndexParser, IndexedBuilder},
+        parser::ParseController,
+        rank::{DocumentLengths, Scorer},
+        report::ParseReport,
+        segment::{CommonSegmentSelector, CommonSegments},
+        zones::ZoneSet,
+    };
+
+    use super::{normalize_title, Boosts, DocumentBoosts};
+
+    #[test]
+    fn sidecar_lines() {
+        let boosts = Boosts::parse("# featured\nBeta Guide\t3\n\n/data/b.xml\t0.5\n").unwrap();
+        assert_eq!(
+            boosts.entries,
+            [("Beta Guide".to_string(), 3.0), ("/data/b.xml".to_string(), 0.5)]
+        );
+        assert!(Boosts::parse("no tab here").is_err());
+        assert!(Boosts::parse("title\tmuch").is_err());
+        assert!(Boosts::parse("title\t-1").is_err());
+        assert_eq!(normalize_title("Rust: The Book, 2nd ed."), "rust the book nd ed");
+    }
+
+    #[tokio::test]
+    async fn boosts_flip_ranking() -> Result<(), Error> {
+        let root = std::env::temp_dir().join(format!("boost_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root).await;
+        fs::create_dir_all(&root).await?;
+        let document = |title: &str, text: &str| {
+            format!("<title>\n{title}\n</title>\n<text>\n{text}\n</text>\n")
+        };
+        let first = root.join("a.xml").to_str().unwrap().to_string();
+        let second = root.join("b.xml").to_str().unwrap().to_string();
+        fs::write(
+            &first,
+            document("alpha guide", "rust tokio") + &document("beta guide", "rust tokio"),
+        )
+        .await?;
+        fs::write(
+            &second,
+            document("gamma guide", "rust tokio") + &document("delta notes", "python asyncio"),
+        )
+        .await?;
+
+        let config = IndexerConfig::new(1000, 6)?;
+        let build = |name: &str, boosts: Option<Boosts>| {
+            let destination = root.join(name).to_str().unwrap().to_string();
+            let mut controller = ParseController::<IndexParser, _, _>::new(
+                vec![first.clone(), second.clone()],
+                destination.clone(),
+                root.join("buffer").to_str().unwrap().to_string(),
+                1,
+                IndexedBuilder::new(config, ZoneSet::new(["title", "text"]).unwrap()).unwrap(),
+                IndexMerger::new(config.merger()),
+            );
+            if let Some(boosts) = boosts {
+                controller = controller.with_boosts(boosts);
+            }
+            async move {
+                controller.create_dictionary().await?;
+                Ok::<_, Error>(destination)
+            }
+        };
+        let rank = |destination: String, boosts: Option<DocumentBoosts>| async move {
+            let mut scorer = Scorer::new(
+                &CommonSegmentSelector::new(),
+                &[("title", 2.0), ("text", 1.0)],
+                DocumentLengths::load(&destination).await?,
+            )?;
+            if let Some(boosts) = boosts {
+                scorer = scorer.with_boosts(boosts);
+            }
+            let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+            scorer.search(&mut dictionary, &["rust"]).await
+        };
+
+        let plain = rank(build("plain", None).await?, None).await?;
+        assert_eq!(plain.len(), 3);
+        assert!(plain[0].1 > 0.0);
+        assert!(plain.iter().all(|v| (v.1 - plain[0].1).abs() < 1e-12));
+        let (alpha, beta, gamma) = (plain[0].0, plain[1].0, plain[2].0);
+        assert_eq!((alpha, beta), (0, 1));
+
+        let sidecar = format!("Beta Guide\t3\n{second}\t2\nmissing title\t5\n");
+        let destination = build("boosted", Some(Boosts::parse(&sidecar)?)).await?;
+        let boosts = DocumentBoosts::load(&destination).await?;
+        assert_eq!(
+            [boosts.get(alpha), boosts.get(beta), boosts.get(gamma)],
+            [1.0, 3.0, 2.0]
+        );
+        let boosted = rank(destination.clone(), Some(boosts)).await?;
+        assert_eq!(
+            boosted.iter().map(|v| v.0).collect::<Vec<_>>(),
+            [beta, gamma, alpha]
+        );
+        assert!((boosted[0].1 - 3.0 * plain[0].1).abs() < 1e-9);
+        assert_eq!(
+            ParseReport::load(&destination).await?.unknown_boosts,
+            ["missing title"]
+        );
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
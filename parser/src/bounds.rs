@@ -0,0 +1,79 @@
+use std::io::{Error, ErrorKind};
+
+use save::writer::{variable_load, variable_save_usize};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter},
+};
+
+use crate::layout::IndexLayout;
+
+/// Highest stored count of every term in one document, stored as
+/// `max_tf.bin` by a merge [`crate::indexed::IndexMerger::with_score_bounds`].
+/// With the idf it bounds what the term can add to a score, which lets
+/// [`crate::rank::Scorer::search_top`] pass over documents that can't make
+/// the top.
+///
+/// Terms are keyed by where their postings start, which grows with the
+/// term order, so it takes no term ordinals to look one up.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TermBounds {
+    pointers: Vec<u64>,
+    max_tf: Vec<u32>,
+}
+
+impl TermBounds {
+    /// Terms have to be pushed in the order their postings were written.
+    pub fn push(&mut self, indexes_pointer: u64, max_tf: usize) {
+        self.pointers.push(indexes_pointer);
+        self.max_tf.push(max_tf.min(u32::MAX as usize) as u32);
+    }
+
+    pub fn len(&self) -> usize {
+        self.pointers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pointers.is_empty()
+    }
+
+    /// The bound of the term whose postings start at `indexes_pointer`.
+    pub fn get(&self, indexes_pointer: u64) -> Option<usize> {
+        let i = self.pointers.binary_search(&indexes_pointer).ok()?;
+        Some(self.max_tf[i] as usize)
+    }
+
+    pub async fn save(&self, directory: &str) -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(IndexLayout::detect(directory).await?.max_tf(directory)).await?);
+        writer.write_u64(self.pointers.len() as u64).await?;
+        let mut previous = 0;
+        for (pointer, max_tf) in self.pointers.iter().zip(self.max_tf.iter()) {
+            variable_save_usize((pointer - previous) as usize, &mut writer).await?;
+            variable_save_usize(*max_tf as usize, &mut writer).await?;
+            previous = *pointer;
+        }
+        writer.flush().await
+    }
+
+    pub async fn load(directory: &String) -> Result<Self, Error> {
+        let mut reader = BufReader::new(File::open(IndexLayout::detect(directory).await?.max_tf(directory)).await?);
+        let len = reader.read_u64().await? as usize;
+        let mut bounds = Self {
+            pointers: Vec::with_capacity(len),
+            max_tf: Vec::with_capacity(len),
+        };
+        let mut pointer = 0;
+        for _ in 0..len {
+            pointer += variable_load(&mut reader).await? as u64;
+            let max_tf = variable_load(&mut reader).await?;
+            if bounds.pointers.last().is_some_and(|v| *v >= pointer) {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("bounds out of postings order at {pointer} in {directory}"),
+                ));
+            }
+            bounds.push(pointer, max_tf);
+        }
+        Ok(bounds)
+    }
+}
@@ -0,0 +1,87 @@
+use std::{
+    io::{Error, ErrorKind},
+    ops::Range,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::zones::ZoneSet;
+
+/// Name of the record [`BufferMeta::save`] leaves in a buffer directory.
+pub const BUFFER_META: &str = "buffer_meta.json";
+
+/// What a buffer was written by, kept in it by
+/// [`crate::parser::Parser::flush_to`] so that buffers of builds set up
+/// differently aren't merged into one index by mistake.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BufferMeta {
+    /// Fingerprint of the settings the terms were read with, see
+    /// [`crate::indexed::IndexedBuilder::fingerprint`].
+    pub fingerprint: String,
+    /// Zones of the documents, None if the parser read none.
+    pub zones: Option<ZoneSet>,
+    /// Ids of the documents read into the buffer, empty if none were.
+    pub documents: Range<usize>,
+    pub terms: usize,
+    /// Version of the crate that wrote the buffer.
+    pub version: String,
+}
+
+impl BufferMeta {
+    pub fn new(fingerprint: String, zones: Option<ZoneSet>, documents: Range<usize>, terms: usize) -> Self {
+        Self {
+            fingerprint,
+            zones,
+            documents,
+            terms,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+
+    pub async fn save(&self, directory: &str) -> Result<(), Error> {
+        let data = serde_json::to_vec_pretty(self).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        fs::write(Path::new(directory).join(BUFFER_META), data).await
+    }
+
+    /// The record of the buffer in `directory`, None for a buffer written
+    /// without one.
+    pub async fn load(directory: &str) -> Result<Option<Self>, Error> {
+        let data = match fs::read(Path::new(directory).join(BUFFER_META)).await {
+            Ok(v) => v,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        serde_json::from_slice(&data)
+            .map(Some)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+}
+
+/// Fails with InvalidInput, naming both fingerprints and a buffer of each,
+/// if `buffers` weren't all written with the same fingerprint. Buffers
+/// without a record are passed over.
+pub async fn check_buffers(buffers: &[String]) -> Result<(), Error> {
+    let mut first = None::<(&String, BufferMeta)>;
+    for buffer in buffers {
+        let Some(meta) = BufferMeta::load(buffer).await? else {
+            continue;
+        };
+        match &first {
+            None => first = Some((buffer, meta)),
+            Some((seen, v)) if v.fingerprint != meta.fingerprint => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "buffers were written with different settings: {seen} with fingerprint {}, \
+                         {buffer} with {}; merge with --force to combine them anyway",
+                        v.fingerprint, meta.fingerprint
+                    ),
+                ));
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(())
+}
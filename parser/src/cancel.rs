@@ -0,0 +1,71 @@
+use std::{
+    io::{Error, ErrorKind},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use tokio::sync::Notify;
+
+/// Asks a build or a query to stop at its next check. Clones share the flag,
+/// so a token handed to a [`crate::parser::ParseController`] can be
+/// cancelled from anywhere else, e.g. on Ctrl-C or a timeout.
+///
+/// Parsing checks it at every document boundary, the merge before every
+/// term and a query before reading every postings list. Stopped work fails
+/// with [`interrupted`].
+#[derive(Debug, Default, Clone)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+        self.inner.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Resolves once the token is cancelled.
+    pub async fn cancelled(&self) {
+        loop {
+            let notified = self.inner.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Fails with [`interrupted`] once the token is cancelled.
+    pub fn check(&self) -> Result<(), Error> {
+        match self.is_cancelled() {
+            true => Err(interrupted()),
+            false => Ok(()),
+        }
+    }
+}
+
+/// The error of work stopped by a [`CancellationToken`].
+pub fn interrupted() -> Error {
+    Error::new(ErrorKind::Interrupted, "cancelled")
+}
+
+/// Whether `e` is the error of cancelled work.
+pub fn is_interrupted(e: &Error) -> bool {
+    e.kind() == ErrorKind::Interrupted
+}
@@ -0,0 +1,288 @@
+use std::borrow::Cow;
+#[cfg(feature = "build")]
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    io::Error,
+    path::Path,
+};
+
+#[cfg(feature = "build")]
+use save::u8::CommU8Provider;
+#[cfg(feature = "build")]
+use tokio::{fs::File, io::BufReader};
+
+#[cfg(feature = "build")]
+use crate::{
+    reader::{CaseKeepingInterpreter, Reader, ReaderResult},
+    rep_reader::{RepeatedXmlReader, ZoneRepeatedReader},
+    zones::ZoneSet,
+};
+
+/// Prefix of the original spelling of a word in a case-preserving index.
+/// It is a delimiter to the reader, so no indexed word starts with it, and
+/// it sorts the exact-case terms ahead of every word.
+pub const EXACT_CASE_MARKER: char = '=';
+
+/// The form words are indexed under: every letter lowercased on its own, the
+/// way [`crate::reader::CommCharInterpreter`] reads them. `ß` stays `ß` and
+/// a Turkish `İ` becomes `i̇`.
+pub fn fold_case(word: &str) -> Cow<'_, str> {
+    if word.is_ascii() {
+        if !word.bytes().any(|v| v.is_ascii_uppercase()) {
+            return Cow::Borrowed(word);
+        }
+        return Cow::Owned(word.to_ascii_lowercase());
+    }
+    if word.chars().all(|c| c.to_lowercase().eq(std::iter::once(c))) {
+        return Cow::Borrowed(word);
+    }
+    Cow::Owned(word.chars().flat_map(char::to_lowercase).collect())
+}
+
+/// Folding as far as the audit compares words: [`fold_case`] with `ß`
+/// spelled out, so `Straße` and `strasse` meet.
+#[cfg(feature = "build")]
+pub fn full_fold(word: &str) -> String {
+    fold_case(word).replace('ß', "ss")
+}
+
+/// The term the original spelling of `word` is indexed under.
+pub fn exact_case_term(word: &str) -> String {
+    format!("{EXACT_CASE_MARKER}{word}")
+}
+
+/// A fully folded form reached from more than one spelling.
+#[cfg(feature = "build")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaseCollision {
+    pub folded: String,
+    /// Spellings with how often each was read, most frequent first.
+    pub originals: Vec<(String, usize)>,
+}
+
+#[cfg(feature = "build")]
+impl CaseCollision {
+    pub fn total(&self) -> usize {
+        self.originals.iter().map(|(_, count)| count).sum()
+    }
+}
+
+/// What `audit-case` reports: how many words were read, how many distinct
+/// spellings they had and which folded forms those spellings collide on,
+/// most frequent first.
+#[cfg(feature = "build")]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CaseAudit {
+    pub words: usize,
+    pub spellings: usize,
+    pub collisions: Vec<CaseCollision>,
+}
+
+#[cfg(feature = "build")]
+impl Display for CaseAudit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} words, {} spellings, {} folded forms collide",
+            self.words,
+            self.spellings,
+            self.collisions.len()
+        )?;
+        for collision in self.collisions.iter() {
+            let originals = collision
+                .originals
+                .iter()
+                .map(|(original, count)| format!("{original} {count}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            writeln!(f, "{}\t{}\t{originals}", collision.folded, collision.total())?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the zones of `files` without folding case and collects the
+/// spellings of every word.
+#[cfg(feature = "build")]
+pub async fn audit_case(files: &[impl AsRef<Path>], attributes: ZoneSet) -> Result<CaseAudit, Error> {
+    let mut spellings = HashMap::<String, usize>::new();
+    let mut words = 0;
+    for file in files {
+        let file = file.as_ref();
+        let mut reader = RepeatedXmlReader::<_, CaseKeepingInterpreter>::new(
+            CommU8Provider::new(BufReader::new(File::open(file).await?)),
+            attributes.clone(),
+        )
+        .await?;
+        while let Some(v) = reader.next_word().await? {
+            match v {
+                ReaderResult::Word(word) => {
+                    words += 1;
+                    *spellings.entry(word).or_default() += 1;
+                }
+                ReaderResult::AttributeEnd => reader.transform_zone().await,
+                ReaderResult::Malformed(warning) => log::warn!("{}: {warning}", file.display()),
+            }
+        }
+    }
+
+    let mut folded = BTreeMap::<String, Vec<(String, usize)>>::new();
+    for (original, count) in spellings.iter() {
+        folded
+            .entry(full_fold(original))
+            .or_default()
+            .push((original.clone(), *count));
+    }
+    let mut collisions = folded
+        .into_iter()
+        .filter(|(_, originals)| originals.len() > 1)
+        .map(|(folded, mut originals)| {
+            originals.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            CaseCollision { folded, originals }
+        })
+        .collect::<Vec<_>>();
+    collisions.sort_by_key(|v| Reverse(v.total()));
+    Ok(CaseAudit {
+        words,
+        spellings: spellings.len(),
+        collisions,
+    })
+}
+
+#[cfg(test)]
+mod tst {
+    use std::io::Error;
+
+    use tokio::fs;
+
+    use crate::{
+        config::IndexerConfig,
+        indexed::{Dictionary, IndexMerger, IndexParser, IndexedBuilder},
+        metadata::IndexMetadata,
+        parser::ParseController,
+        rank::{DocumentLengths, Scorer},
+        segment::{CommonSegmentSelector, CommonSegments},
+        testsupport::scratch,
+        zones::ZoneSet,
+    };
+
+    use super::{audit_case, fold_case, full_fold, CaseCollision};
+
+    const DOCUMENTS: [&str; 5] = [
+        "the US army",
+        "us and them",
+        "Us again us",
+        "Straße nach Berlin",
+        "strasse und straße",
+    ];
+
+    async fn fixture(name: &str) -> Result<(std::path::PathBuf, Vec<String>), Error> {
+        let root = scratch(name).await?;
+        let path = root.join("0.xml").to_str().unwrap().to_string();
+        let content = DOCUMENTS
+            .iter()
+            .map(|text| format!("<title>\nnote\n</title>\n<text>\n{text}\n</text>\n"))
+            .collect::<String>();
+        fs::write(&path, content).await?;
+        Ok((root, vec![path]))
+    }
+
+    #[test]
+    fn folding() {
+        assert_eq!(fold_case("rust"), "rust");
+        assert_eq!(fold_case("US"), "us");
+        assert_eq!(fold_case("Straße"), "straße");
+        assert_eq!(full_fold("Straße"), "strasse");
+        assert_eq!(fold_case("İstanbul"), "i\u{307}stanbul");
+    }
+
+    #[tokio::test]
+    async fn audit_reports_colliding_spellings() -> Result<(), Error> {
+        let (root, files) = fixture("case_audit").await?;
+        let audit = audit_case(&files, ZoneSet::new(["title", "text"])?).await?;
+        assert_eq!(audit.words, 5 + 15);
+        assert_eq!(
+            audit.collisions,
+            [
+                CaseCollision {
+                    folded: "us".to_string(),
+                    originals: vec![("us".to_string(), 2), ("US".to_string(), 1), ("Us".to_string(), 1)],
+                },
+                CaseCollision {
+                    folded: "strasse".to_string(),
+                    originals: vec![("Straße".to_string(), 1), ("strasse".to_string(), 1), ("straße".to_string(), 1)],
+                },
+            ]
+        );
+        let report = audit.to_string();
+        assert!(report.contains("2 folded forms collide"), "{report}");
+        assert!(report.contains("us\t4\tus 2, US 1, Us 1"), "{report}");
+        assert!(report.contains("strasse\t3\tStraße 1, strasse 1, straße 1"), "{report}");
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn exact_case_matches_are_boosted() -> Result<(), Error> {
+        let (root, files) = fixture("case_index").await?;
+        let attributes = ZoneSet::new(["title", "text"])?;
+        let mut found = Vec::new();
+        let config = IndexerConfig::new(1000, 6)?;
+        for preserving in [false, true] {
+            let destination = root.join(format!("res_{preserving}")).to_str().unwrap().to_string();
+            let mut builder = IndexedBuilder::new(config, attributes.clone())?;
+            if preserving {
+                builder = builder.with_case_preserving();
+            }
+            ParseController::<IndexParser, _, _>::new(
+                files.clone(),
+                destination.clone(),
+                root.join("buffer").to_str().unwrap().to_string(),
+                1,
+                builder,
+                IndexMerger::new(config.merger()),
+            )
+            .create_dictionary()
+            .await?;
+            assert_eq!(IndexMetadata::load(&destination).await?.case_preserving, preserving);
+
+            let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+            let documents = |v: Option<crate::indexed::IndexedTerm<CommonSegments>>| {
+                v.map(|v| v.indexes.iter().map(|v| v.0).collect::<Vec<_>>())
+            };
+            assert_eq!(documents(dictionary.find("us").await?), Some(vec![0, 1, 2]));
+            assert_eq!(documents(dictionary.find("=US").await?), preserving.then(|| vec![0]));
+            assert_eq!(documents(dictionary.find("=us").await?), None);
+            assert_eq!(documents(dictionary.find("=Straße").await?), preserving.then(|| vec![3]));
+            let lengths = DocumentLengths::load(&destination).await?;
+            found.push((dictionary.len() - 4 * preserving as usize, lengths.lengths));
+
+            let scorer = Scorer::new(
+                &CommonSegmentSelector::new(),
+                &[("title", 2.0), ("text", 1.0)],
+                DocumentLengths::load(&destination).await?,
+            )?;
+            let plain = scorer.search(&mut dictionary, &["us"]).await?;
+            let scorer = scorer.with_exact_case(4.0);
+            let ranked = scorer.search(&mut dictionary, &["US"]).await?;
+            assert_eq!(scorer.search(&mut dictionary, &["us"]).await?, plain);
+            if preserving {
+                assert_eq!(ranked[0].0, 0);
+                let unboosted = plain.iter().find(|v| v.0 == 0).unwrap().1;
+                assert!((ranked[0].1 - 4.0 * unboosted).abs() < 1e-9);
+                let ranked = scorer.search(&mut dictionary, &["Straße"]).await?;
+                assert_eq!(ranked.iter().map(|v| v.0).collect::<Vec<_>>(), [3, 4]);
+            } else {
+                assert_eq!(ranked, plain);
+            }
+        }
+        // The extra terms leave the folded index and the lengths as they were.
+        assert_eq!(found[0], found[1]);
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,269 @@
+use std::{
+    io::{Error, ErrorKind},
+    num::{NonZeroU8, NonZeroUsize},
+};
+
+use tokio::{
+    fs::File,
+    io::{BufReader, BufWriter},
+};
+
+/// Sizes an index is built with, checked once when the config is made.
+/// The parse stage takes it whole. The merge stage takes
+/// [`IndexerConfig::merger`] of it to write blocks of the same size, or a
+/// [`MergerConfig::new`] of its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexerConfig {
+    /// Distinct terms a parser keeps in memory before it flushes them to
+    /// the buffer.
+    tree_max_terms: NonZeroUsize,
+    /// Terms front-coded together in one lexical block. A term's place in
+    /// its block is stored in a byte, hence the bound.
+    lexical_block_size: NonZeroU8,
+    io: IoTuning,
+}
+
+impl IndexerConfig {
+    /// Fails if either size is 0 or `lexical_block_size` is over 255.
+    pub fn new(tree_max_terms: usize, lexical_block_size: usize) -> Result<Self, Error> {
+        let tree_max_terms = NonZeroUsize::new(tree_max_terms).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "tree_max_terms is 0, a parser has to hold at least one term before it flushes",
+            )
+        })?;
+        Ok(Self {
+            tree_max_terms,
+            lexical_block_size: lexical_block_size_of(lexical_block_size)?,
+            io: IoTuning::default(),
+        })
+    }
+
+    /// Buffer files with `io` instead of [`IoTuning::default`]. The merge
+    /// stage gets it along with the block size.
+    pub fn with_io(mut self, io: IoTuning) -> Self {
+        self.io = io;
+        self
+    }
+
+    pub fn tree_max_terms(&self) -> usize {
+        self.tree_max_terms.get()
+    }
+
+    pub fn lexical_block_size(&self) -> u8 {
+        self.lexical_block_size.get()
+    }
+
+    pub fn io(&self) -> IoTuning {
+        self.io
+    }
+
+    /// What the merge stage needs of this config, with the block size the
+    /// buffers were written with.
+    pub fn merger(&self) -> MergerConfig {
+        MergerConfig {
+            lexical_block_size: self.lexical_block_size,
+            format: OutputFormat::Binary,
+            io: self.io,
+        }
+    }
+}
+
+/// Sizes of the merge stage. The buffers are only read through once, so
+/// their block size matters little; the one the merge writes is what every
+/// lookup pays for.
+///
+/// A lexical block stores the prefix its terms share once, then their
+/// suffixes. Every probe of a lookup's binary search reads the prefix of a
+/// block and skips the suffixes ahead of the probed term, half a block on
+/// average. The size is a bound, a block also ends where a shorter shared
+/// prefix would not pay: a bound of 1 skips nothing but stores every term
+/// whole, larger bounds let runs of terms with a long common prefix share it
+/// for more skipping per probe. A term's place in its block is stored in a
+/// byte, so blocks hold 255 terms at most.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MergerConfig {
+    lexical_block_size: NonZeroU8,
+    format: OutputFormat,
+    io: IoTuning,
+}
+
+impl MergerConfig {
+    /// Fails if `lexical_block_size` is 0 or over 255.
+    pub fn new(lexical_block_size: usize) -> Result<Self, Error> {
+        Ok(Self {
+            lexical_block_size: lexical_block_size_of(lexical_block_size)?,
+            format: OutputFormat::Binary,
+            io: IoTuning::default(),
+        })
+    }
+
+    /// Write the terms as `format` instead of the binary index.
+    pub fn with_format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn lexical_block_size(&self) -> u8 {
+        self.lexical_block_size.get()
+    }
+
+    pub fn format(&self) -> OutputFormat {
+        self.format
+    }
+
+    /// Buffer files with `io` instead of [`IoTuning::default`].
+    pub fn with_io(mut self, io: IoTuning) -> Self {
+        self.io = io;
+        self
+    }
+
+    pub fn io(&self) -> IoTuning {
+        self.io
+    }
+}
+
+/// Bytes the files of a build are buffered with, to suit the storage they
+/// are on: a network filesystem wants far larger reads than an NVMe drive.
+///
+/// Readers of index files seek about, so their buffer stays small by
+/// default; writers and the chunks read through once go in long runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoTuning {
+    reader_buf: NonZeroUsize,
+    writer_buf: NonZeroUsize,
+    chunk_buf: NonZeroUsize,
+}
+
+impl Default for IoTuning {
+    fn default() -> Self {
+        Self {
+            reader_buf: NonZeroUsize::new(8 << 10).unwrap(),
+            writer_buf: NonZeroUsize::new(1 << 20).unwrap(),
+            chunk_buf: NonZeroUsize::new(1 << 20).unwrap(),
+        }
+    }
+}
+
+impl IoTuning {
+    /// Fails if any of the sizes is 0, which no buffer reads or writes with.
+    pub fn new(reader_buf: usize, writer_buf: usize, chunk_buf: usize) -> Result<Self, Error> {
+        let size = |name: &str, raw: usize| {
+            NonZeroUsize::new(raw).ok_or_else(|| {
+                Error::new(ErrorKind::InvalidInput, format!("{name} is 0, a buffer needs a byte at least"))
+            })
+        };
+        Ok(Self {
+            reader_buf: size("reader_buf", reader_buf)?,
+            writer_buf: size("writer_buf", writer_buf)?,
+            chunk_buf: size("chunk_buf", chunk_buf)?,
+        })
+    }
+
+    /// Bytes index files are read with.
+    pub fn reader_buf(&self) -> usize {
+        self.reader_buf.get()
+    }
+
+    /// Bytes buffers and index files are written with.
+    pub fn writer_buf(&self) -> usize {
+        self.writer_buf.get()
+    }
+
+    /// Bytes the input files and chunks are read with.
+    pub fn chunk_buf(&self) -> usize {
+        self.chunk_buf.get()
+    }
+
+    pub fn reader(&self, file: File) -> BufReader<File> {
+        BufReader::with_capacity(self.reader_buf(), file)
+    }
+
+    pub fn writer(&self, file: File) -> BufWriter<File> {
+        BufWriter::with_capacity(self.writer_buf(), file)
+    }
+
+    pub fn chunk_reader(&self, file: File) -> BufReader<File> {
+        BufReader::with_capacity(self.chunk_buf(), file)
+    }
+}
+
+/// What the merge writes the terms as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// The index [`crate::indexed::Dictionary`] looks terms up in.
+    Binary,
+    /// Every term as a block of lines in [`crate::text_sink::TERMS_FILE`],
+    /// to be read by eye. Nothing looks terms up in it.
+    Text,
+}
+
+impl OutputFormat {
+    /// What [`OutputFormat::parse`] reads back as this format.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Binary => "binary",
+            Self::Text => "text",
+        }
+    }
+
+    pub fn parse(raw: &str) -> Result<Self, Error> {
+        match raw {
+            "binary" => Ok(Self::Binary),
+            "text" => Ok(Self::Text),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("unknown output format {raw:?}, expected binary or text"),
+            )),
+        }
+    }
+}
+
+fn lexical_block_size_of(raw: usize) -> Result<NonZeroU8, Error> {
+    u8::try_from(raw).ok().and_then(NonZeroU8::new).ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("lexical_block_size is {raw}, expected 1 to {} terms per block", u8::MAX),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tst {
+    use std::io::ErrorKind;
+
+    use super::{IndexerConfig, IoTuning, MergerConfig, OutputFormat};
+
+    #[test]
+    fn sizes_are_checked() {
+        let config = IndexerConfig::new(1000, 6).unwrap();
+        assert_eq!((config.tree_max_terms(), config.lexical_block_size()), (1000, 6));
+        assert_eq!(config.merger().lexical_block_size(), 6);
+        assert_eq!(IndexerConfig::new(1, 255).unwrap().lexical_block_size(), 255);
+        assert_eq!(MergerConfig::new(100).unwrap().lexical_block_size(), 100);
+        assert_eq!(MergerConfig::new(256).unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(config.merger().format(), OutputFormat::Binary);
+        assert_eq!(config.merger().with_format(OutputFormat::Text).format(), OutputFormat::Text);
+        assert_eq!(OutputFormat::parse("text").unwrap(), OutputFormat::Text);
+        assert_eq!(OutputFormat::parse("xml").unwrap_err().kind(), ErrorKind::InvalidInput);
+
+        let io = IoTuning::new(4 << 10, 2 << 20, 16 << 20).unwrap();
+        assert_eq!(config.io(), IoTuning::default());
+        assert_eq!(config.with_io(io).merger().io(), io);
+        assert_eq!((io.reader_buf(), io.writer_buf(), io.chunk_buf()), (4 << 10, 2 << 20, 16 << 20));
+        let e = IoTuning::new(1, 0, 1).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+        assert!(e.to_string().starts_with("writer_buf is 0"), "{e}");
+
+        for (tree, block, message) in [
+            (1000, 0, "lexical_block_size is 0"),
+            (1000, 256, "lexical_block_size is 256"),
+            (1000, usize::MAX, "expected 1 to 255"),
+            (0, 6, "tree_max_terms is 0"),
+        ] {
+            let e = IndexerConfig::new(tree, block).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::InvalidInput);
+            assert!(e.to_string().contains(message), "{e}");
+        }
+    }
+}
@@ -0,0 +1,302 @@
+use std::{
+    collections::BTreeMap,
+    io::Error,
+    ops::Range,
+};
+
+#[cfg(feature = "build")]
+use std::io::ErrorKind;
+
+#[cfg(feature = "build")]
+use tokio::fs;
+
+use crate::{
+    analyzer::Analyzer,
+    generation,
+    indexed::{Dictionary, UsageData},
+    metadata::IndexMetadata,
+    permuterm::matches,
+    rank::{idf, DocumentLengths},
+    resolve::DocResolver,
+    segment::Segments,
+    zones::ZoneSet,
+};
+#[cfg(feature = "build")]
+use crate::{
+    config::MergerConfig,
+    indexed::IndexedBuilder,
+    reader::{CaseKeepingInterpreter, Reader, ReaderResult},
+    reindex::{append_file, ReindexReport},
+    rep_reader::{RepeatedXmlReader, ZoneRepeatedReader},
+};
+#[cfg(feature = "build")]
+use save::u8::MemoryU8Provider;
+
+/// Documents added to the index in `directory` since it was last merged,
+/// held in memory and queried along with it by
+/// [`crate::execute::execute_with_delta`].
+///
+/// They get the ids the index would give them next, from the id past its
+/// last one, and are read and analyzed as the index reads its chunks, so a
+/// query finds the same documents once they are committed.
+pub struct DeltaIndex<S: Segments> {
+    directory: String,
+    zones: ZoneSet,
+    analyzer: Analyzer,
+    /// Ids the index holds, the first one of the delta.
+    first_id: usize,
+    /// Documents the index holds, for the idf.
+    indexed_documents: usize,
+    terms: BTreeMap<String, BTreeMap<usize, UsageData<S>>>,
+    /// Every document as the chunk [`Self::commit`] writes holds it.
+    documents: Vec<String>,
+}
+
+impl<S: Segments> DeltaIndex<S> {
+    /// An empty delta over the index in `directory`, or its live generation,
+    /// reading documents with the zones and the analyzer it was built with.
+    pub async fn open(directory: &str) -> Result<Self, Error> {
+        let current = generation::resolve(directory).await?;
+        let metadata = IndexMetadata::load_or_legacy(&current).await?;
+        let zones = match metadata.zones {
+            Some(v) => v,
+            None => ZoneSet::new(["title", "text"])?,
+        };
+        Ok(Self {
+            directory: directory.to_string(),
+            zones,
+            analyzer: Analyzer::from_metadata(&current).await?,
+            first_id: DocResolver::load(&current).await?.len(),
+            indexed_documents: DocumentLengths::load(&current).await?.documents,
+            terms: BTreeMap::new(),
+            documents: Vec::new(),
+        })
+    }
+
+    pub fn directory(&self) -> &str {
+        &self.directory
+    }
+
+    pub fn zones(&self) -> &ZoneSet {
+        &self.zones
+    }
+
+    /// What documents are read with, for the terms of queries to match theirs.
+    pub fn analyzer(&self) -> &Analyzer {
+        &self.analyzer
+    }
+
+    /// Documents held.
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    /// Ids of the documents held.
+    pub fn ids(&self) -> Range<usize> {
+        self.first_id..self.first_id + self.documents.len()
+    }
+
+    /// Documents of `term` in the delta, with its use in each.
+    pub fn postings(&self, term: &str) -> impl Iterator<Item = (usize, &UsageData<S>)> {
+        self.terms.get(term).into_iter().flatten().map(|(id, usage)| (*id, usage))
+    }
+
+    /// Terms of the delta a `*` pattern matches, in order.
+    pub fn wildcard(&self, pattern: &str) -> Vec<String> {
+        self.terms.keys().filter(|v| matches(pattern, v)).cloned().collect()
+    }
+
+    /// Document frequency and idf of `term` over the index and the delta
+    /// together, as the index would give them with the delta committed.
+    pub async fn idf_of(&self, dictionary: &mut Dictionary<S>, term: &str) -> Result<(usize, f64), Error> {
+        let indexed = dictionary.find(term).await?.map_or(0, |v| v.indexes.len());
+        let df = indexed + self.terms.get(term).map_or(0, BTreeMap::len);
+        Ok((df, idf(self.indexed_documents + self.documents.len(), df)))
+    }
+}
+
+#[cfg(feature = "build")]
+impl<S: Segments> DeltaIndex<S> {
+    /// Adds a document of the text of every zone in `zones` and returns its
+    /// id. Zones left out are empty. Fails on a zone the index doesn't have
+    /// or one given twice.
+    pub async fn add_document(&mut self, zones: &[(&str, &str)]) -> Result<usize, Error> {
+        let mut texts = vec![""; self.zones.len()];
+        for (zone, text) in zones {
+            let Some(index) = self.zones.index_of(zone) else {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("unknown zone {zone}")));
+            };
+            if zones.iter().filter(|(v, _)| v == zone).count() > 1 {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("zone {zone} given twice")));
+            }
+            texts[index] = text;
+        }
+        let mut document = String::new();
+        for (zone, text) in self.zones.iter().zip(texts) {
+            document.push_str(&format!("<{zone}>\n{}\n</{zone}>\n", escape(text)));
+        }
+
+        let id = self.first_id + self.documents.len();
+        let mut reader = RepeatedXmlReader::<_, CaseKeepingInterpreter>::new(
+            MemoryU8Provider::new(document.clone()),
+            self.zones.clone(),
+        )
+        .await?;
+        let setters = self.zones.iter().map(S::selector_for).collect::<Vec<_>>();
+        let mut found = BTreeMap::<String, UsageData<S>>::new();
+        let mut zones_left = self.zones.len();
+        while zones_left > 0 {
+            match reader.next_word().await? {
+                None => break,
+                Some(ReaderResult::Word(word)) => {
+                    let setter = setters[reader.zone_id().index()];
+                    let Some(analyzed) = self.analyzer.analyze(word) else {
+                        continue;
+                    };
+                    for term in std::iter::once(analyzed.term).chain(analyzed.original) {
+                        let usage = found.entry(term).or_default();
+                        *usage.use_count_mut() += 1;
+                        setter(usage.segments_mut(), 1);
+                    }
+                }
+                Some(ReaderResult::AttributeEnd) => {
+                    reader.transform_zone().await;
+                    zones_left -= 1;
+                }
+                Some(ReaderResult::Malformed(warning)) => return Err(Error::new(ErrorKind::InvalidData, warning)),
+            }
+        }
+        for (term, usage) in found {
+            self.terms.entry(term).or_default().insert(id, usage);
+        }
+        self.documents.push(document);
+        Ok(id)
+    }
+
+    /// Writes the documents held to a chunk in `<directory>.delta`, merges
+    /// it into the index with [`append_file`] and empties the delta. The
+    /// chunk stays, it is a source of the index from then on. `builder`
+    /// and `config` have to be set up as the build of the index was.
+    /// Returns None without touching the index if the delta is empty.
+    pub async fn commit(
+        &mut self,
+        builder: IndexedBuilder,
+        config: MergerConfig,
+    ) -> Result<Option<ReindexReport>, Error> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+        let sources = format!("{}.delta", self.directory);
+        fs::create_dir_all(&sources).await?;
+        let chunk = format!("{sources}/{}.xml", self.first_id);
+        fs::write(&chunk, self.documents.concat()).await?;
+        let report = append_file(&self.directory, &chunk, builder, config).await?;
+        self.indexed_documents += self.documents.len();
+        self.first_id = report.added.end;
+        self.terms.clear();
+        self.documents.clear();
+        Ok(Some(report))
+    }
+}
+
+/// `text` with what would read as markup written as entities.
+#[cfg(feature = "build")]
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(all(test, feature = "build"))]
+mod tst {
+    use std::io::Error;
+
+    use tokio::fs;
+
+    use crate::{
+        cancel::CancellationToken,
+        config::IndexerConfig,
+        execute::{execute_with_delta, QueryLimits},
+        indexed::{verify_index, Dictionary, IndexedBuilder},
+        query::parse_query,
+        rank::DocumentLengths,
+        segment::{CommonSegmentSelector, CommonSegments},
+        synonym::Thesaurus,
+        testsupport::{scratch, word, CorpusSpec},
+    };
+
+    use super::DeltaIndex;
+
+    async fn answers(
+        directory: &String,
+        delta: &DeltaIndex<CommonSegments>,
+        queries: &[String],
+    ) -> Result<Vec<Vec<usize>>, Error> {
+        let mut dictionary = Dictionary::<CommonSegments>::new(directory).await?;
+        let selector = CommonSegmentSelector::new();
+        let mut answers = Vec::new();
+        for raw in queries {
+            let query = parse_query(raw, &selector).unwrap();
+            let limits = QueryLimits::default();
+            let found = execute_with_delta(&query, &mut dictionary, delta, limits, &Thesaurus::default(), &CancellationToken::new())
+                .await
+                .unwrap();
+            answers.push(found.documents);
+        }
+        Ok(answers)
+    }
+
+    #[tokio::test]
+    async fn added_documents_are_found_before_and_after_commit() -> Result<(), Error> {
+        let root = scratch("delta").await?;
+        let corpus = CorpusSpec::default().generate(&root.join("corpus")).await?;
+        let index = corpus.index_with(&root, |v| v.with_permuterm()).await?;
+        let documents = DocumentLengths::load(&index).await?.documents;
+        let mut delta = DeltaIndex::<CommonSegments>::open(&index).await?;
+        let first = delta.ids().start;
+
+        // Past the vocabulary of the corpus, which ends in "a".
+        let (fresh, known) = (word(5000), word(0));
+        let text = format!("{known} {fresh} <{known}> & {known}");
+        assert_eq!(delta.add_document(&[("title", &fresh), ("text", &text)]).await?, first);
+        assert_eq!(delta.add_document(&[("text", &known)]).await?, first + 1);
+        assert!(delta.add_document(&[("body", &known)]).await.is_err());
+        assert!(delta.add_document(&[("text", &known), ("text", &fresh)]).await.is_err());
+        assert_eq!(delta.ids(), first..first + 2);
+
+        let queries = [
+            fresh.clone(),
+            known.clone(),
+            format!("title:{fresh}"),
+            format!("text:{fresh} {known}"),
+            format!("{known} NOT {fresh}"),
+            format!("{}*", &fresh[..2]),
+        ];
+        let before = answers(&index, &delta, &queries).await?;
+        assert_eq!(before[0], [first]);
+        assert!(before[1].ends_with(&[first, first + 1]), "{:?}", before[1]);
+        assert_eq!(before[2], [first]);
+        assert!(before[5].contains(&first));
+        let mut dictionary = Dictionary::<CommonSegments>::new(&index).await?;
+        let idf = delta.idf_of(&mut dictionary, &known).await?;
+        assert_eq!(delta.idf_of(&mut dictionary, &fresh).await?.0, 1);
+
+        let config = IndexerConfig::new(1000, 6)?;
+        let builder = || IndexedBuilder::new(config, corpus.attributes.clone());
+        let report = delta.commit(builder()?, config.merger()).await?.unwrap();
+        assert_eq!(report.added, first..first + 2);
+        assert!(delta.is_empty());
+        assert_eq!(delta.ids(), first + 2..first + 2);
+        assert!(delta.commit(builder()?, config.merger()).await?.is_none());
+        verify_index::<CommonSegments>(&index).await?;
+        assert_eq!(DocumentLengths::load(&index).await?.documents, documents + 2);
+
+        assert_eq!(answers(&index, &delta, &queries).await?, before);
+        let mut dictionary = Dictionary::<CommonSegments>::new(&index).await?;
+        assert_eq!(delta.idf_of(&mut dictionary, &known).await?, idf);
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
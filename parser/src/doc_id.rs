@@ -0,0 +1,99 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    io::{Error, ErrorKind},
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+/// Highest document id an index can hold.
+///
+/// Ids are `usize` in memory and varints in the postings, but the lengths
+/// and other per-document files, and what reads them, take them as `u32`.
+pub const MAX_DOCUMENT_ID: usize = u32::MAX as usize;
+
+/// An id past what its format can represent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdRangeError {
+    /// Document `id` was handed out or met where `at` says, over
+    /// [`MAX_DOCUMENT_ID`].
+    Document { id: u64, at: &'static str },
+    /// The chunk counter of a translation is at `u32::MAX` already.
+    Chunk { index: u32 },
+}
+
+impl Display for IdRangeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            IdRangeError::Document { id, at } => {
+                write!(f, "document id {id} in {at} is over the highest supported, {MAX_DOCUMENT_ID}")
+            }
+            IdRangeError::Chunk { index } => write!(f, "chunk counter at {index} can't count another chunk"),
+        }
+    }
+}
+
+impl std::error::Error for IdRangeError {}
+
+impl From<IdRangeError> for Error {
+    fn from(e: IdRangeError) -> Self {
+        Error::new(ErrorKind::InvalidData, e)
+    }
+}
+
+/// `id` as stored, or where it doesn't fit.
+pub fn document_id(id: usize, at: &'static str) -> Result<u32, IdRangeError> {
+    u32::try_from(id).map_err(|_| IdRangeError::Document { id: id as u64, at })
+}
+
+/// Fails on `ids` that would need an id over [`MAX_DOCUMENT_ID`].
+pub fn check_count(ids: usize, at: &'static str) -> Result<(), IdRangeError> {
+    match ids.checked_sub(1) {
+        Some(last) => document_id(last, at).map(|_| ()),
+        None => Ok(()),
+    }
+}
+
+/// Takes the next chunk index from `counter`, which stays put rather than
+/// wrapping once it is exhausted.
+pub fn next_chunk(counter: &AtomicU32) -> Result<u32, IdRangeError> {
+    counter
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |v| v.checked_add(1))
+        .map_err(|index| IdRangeError::Chunk { index })
+}
+
+/// The error of `e`, if it is an [`IdRangeError`].
+pub fn as_id_range(e: &Error) -> Option<&IdRangeError> {
+    e.get_ref()?.downcast_ref()
+}
+
+#[cfg(test)]
+mod tst {
+    use std::sync::atomic::AtomicU32;
+
+    use super::*;
+
+    #[test]
+    fn ids_over_u32_fail() {
+        assert_eq!(document_id(7, "test"), Ok(7));
+        assert_eq!(document_id(MAX_DOCUMENT_ID, "test"), Ok(u32::MAX));
+        assert_eq!(
+            document_id(MAX_DOCUMENT_ID + 1, "test"),
+            Err(IdRangeError::Document {
+                id: 1 << 32,
+                at: "test"
+            })
+        );
+        assert!(check_count(0, "test").is_ok());
+        assert!(check_count(MAX_DOCUMENT_ID + 1, "test").is_ok());
+        let e = Error::from(check_count(MAX_DOCUMENT_ID + 2, "lengths").unwrap_err());
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        assert!(matches!(as_id_range(&e), Some(IdRangeError::Document { at: "lengths", .. })));
+    }
+
+    #[test]
+    fn chunk_counter_stops_at_the_end() {
+        let counter = AtomicU32::new(u32::MAX - 1);
+        assert_eq!(next_chunk(&counter), Ok(u32::MAX - 1));
+        assert_eq!(next_chunk(&counter), Err(IdRangeError::Chunk { index: u32::MAX }));
+        assert_eq!(next_chunk(&counter), Err(IdRangeError::Chunk { index: u32::MAX }));
+    }
+}
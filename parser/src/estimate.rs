@@ -0,0 +1,339 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    io::Error,
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use tokio::fs;
+
+use crate::{
+    config::IndexerConfig,
+    indexed::{IndexMergeSaver, IndexedBuilder, IndexedTerm},
+    layout::IndexLayout,
+    parser::{Parser, ParserBuilder, ParserCallback},
+    reader::Reader,
+    segment::CommonSegments,
+    warnings::Warnings,
+    zones::ZoneSet,
+};
+
+pub struct EstimateConfig {
+    /// Fraction of every input file (from its start) that is actually read.
+    pub sample_fraction: f64,
+    /// Sizes the build being estimated would be run with.
+    pub indexer: IndexerConfig,
+    pub tasks_count: u16,
+    pub attributes: ZoneSet,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimate {
+    pub input_bytes: u64,
+    pub sampled_bytes: u64,
+    pub sampled_tokens: u64,
+    pub sampled_documents: u64,
+    pub sampled_terms: u64,
+    pub tokens: u64,
+    pub documents: u64,
+    pub distinct_terms: u64,
+    /// Heaps' law `V = k * n^beta` fitted on the sample.
+    pub heaps_k: f64,
+    pub heaps_beta: f64,
+    pub buffer_flushes: u64,
+    pub index_bytes: u64,
+    pub peak_task_memory: u64,
+}
+
+impl Display for Estimate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Sampled {} of {} kb: {} tokens, {} documents, {} terms",
+            self.sampled_bytes / 1024,
+            self.input_bytes / 1024,
+            self.sampled_tokens,
+            self.sampled_documents,
+            self.sampled_terms
+        )?;
+        writeln!(
+            f,
+            "Heaps fit: V = {:.2} * n^{:.3}",
+            self.heaps_k, self.heaps_beta
+        )?;
+        writeln!(f, "Expected tokens: {}", self.tokens)?;
+        writeln!(f, "Expected documents: {}", self.documents)?;
+        writeln!(f, "Expected distinct terms: {}", self.distinct_terms)?;
+        writeln!(f, "Expected buffer flushes: {}", self.buffer_flushes)?;
+        writeln!(f, "Expected index size: {} kb", self.index_bytes / 1024)?;
+        write!(
+            f,
+            "Expected peak memory per task: {} kb",
+            self.peak_task_memory / 1024
+        )
+    }
+}
+
+/// Parses a prefix of every file with the [`crate::indexed::IndexParser`] a
+/// build runs and extrapolates the cost of a full run without writing any
+/// buffers.
+pub async fn estimate(files: &[impl AsRef<Path>], config: &EstimateConfig) -> Result<Estimate, Error> {
+    let mut builder = IndexedBuilder::new(config.indexer, config.attributes.clone())?;
+    let mut parser = builder.build();
+    parser.keep_all();
+    let mut growth = Vec::<(f64, f64)>::new();
+    let mut next_checkpoint = 64u64;
+    let (mut input_bytes, mut sampled_bytes) = (0u64, 0u64);
+    let (mut tokens, mut documents, mut id) = (0u64, 0u64, 0usize);
+
+    for file in files {
+        let file = file.as_ref();
+        let size = fs::metadata(file).await?.len();
+        let budget = ((size as f64) * config.sample_fraction).ceil() as u64;
+        input_bytes += size;
+
+        let mut reader = builder.reader_from_file(file, Warnings::default()).await?;
+        // Whole documents, until the prefix is read.
+        let mut sampled = size;
+        loop {
+            if reader.position() >= budget {
+                sampled = reader.position().min(size);
+                break;
+            }
+            if !parser.next_document(&mut reader).await {
+                break;
+            }
+            let callback = parser.parse(&mut reader, id).await;
+            id += 1;
+            tokens += parser.take_document_lengths().iter().map(|v| v.1 as u64).sum::<u64>();
+            if tokens >= next_checkpoint {
+                growth.push(((tokens as f64).ln(), (parser.len() as f64).ln()));
+                while next_checkpoint <= tokens {
+                    next_checkpoint *= 2;
+                }
+            }
+            if callback == ParserCallback::FileEnd {
+                break;
+            }
+        }
+        if let Some(e) = parser.take_error() {
+            return Err(e);
+        }
+        sampled_bytes += sampled;
+        documents += parser.take_report(String::new()).documents as u64;
+    }
+    if tokens > 0 {
+        growth.push(((tokens as f64).ln(), (parser.len() as f64).ln()));
+    }
+
+    let parsed_bytes = parser.estimated_bytes() as u64;
+    let tree = parser.take_terms();
+    let sampled_terms = tree.len() as u64;
+    let (heaps_k, heaps_beta) = fit_heaps(&growth);
+    let scale = if sampled_bytes == 0 {
+        0.0
+    } else {
+        input_bytes as f64 / sampled_bytes as f64
+    };
+    let total_tokens = (tokens as f64 * scale).round() as u64;
+    let distinct_terms = if scale <= 1.0 {
+        sampled_terms
+    } else {
+        (heaps_k * (total_tokens as f64).powf(heaps_beta))
+            .round()
+            .max(sampled_terms as f64) as u64
+    };
+
+    let tasks = config.tasks_count.max(1) as f64;
+    let task_tokens = total_tokens as f64 / tasks;
+    let buffer_tokens =
+        (config.indexer.tree_max_terms() as f64 / heaps_k.max(f64::MIN_POSITIVE)).powf(1.0 / heaps_beta);
+    let buffer_flushes = (tasks * (task_tokens / buffer_tokens).ceil().max(1.0)) as u64;
+
+    let (lexicon_bytes, postings_bytes) = serialized_size(tree, config.indexer.lexical_block_size()).await?;
+    let index_bytes = if sampled_terms == 0 {
+        0
+    } else {
+        (lexicon_bytes as f64 / sampled_terms as f64 * distinct_terms as f64
+            + postings_bytes as f64 * scale) as u64
+    };
+
+    let peak_task_memory = if sampled_terms == 0 {
+        0
+    } else {
+        let per_term = parsed_bytes as f64 / sampled_terms as f64;
+        let resident = (config.indexer.tree_max_terms() as f64)
+            .min(heaps_k * task_tokens.max(1.0).powf(heaps_beta));
+        (per_term * resident) as u64
+    };
+
+    Ok(Estimate {
+        input_bytes,
+        sampled_bytes,
+        sampled_tokens: tokens,
+        sampled_documents: documents,
+        sampled_terms,
+        tokens: total_tokens,
+        documents: (documents as f64 * scale).round() as u64,
+        distinct_terms,
+        heaps_k,
+        heaps_beta,
+        buffer_flushes,
+        index_bytes,
+        peak_task_memory,
+    })
+}
+
+/// Least squares over `(ln n, ln V)`; degenerates to `V = n` when the sample
+/// is too small to fit.
+fn fit_heaps(points: &[(f64, f64)]) -> (f64, f64) {
+    if points.len() < 2 {
+        return (1.0, 1.0);
+    }
+    let n = points.len() as f64;
+    let mx = points.iter().map(|v| v.0).sum::<f64>() / n;
+    let my = points.iter().map(|v| v.1).sum::<f64>() / n;
+    let sxx = points.iter().map(|v| (v.0 - mx).powi(2)).sum::<f64>();
+    let sxy = points.iter().map(|v| (v.0 - mx) * (v.1 - my)).sum::<f64>();
+    if sxx == 0.0 {
+        return (1.0, 1.0);
+    }
+    let beta = (sxy / sxx).clamp(0.05, 1.0);
+    ((my - beta * mx).exp(), beta)
+}
+
+/// Runs the real serializer over the sample and returns the
+/// (pointer + lexical, postings) byte counts.
+async fn serialized_size(
+    tree: BTreeMap<String, IndexedTerm<CommonSegments>>,
+    lexical_max_size: u8,
+) -> Result<(u64, u64), Error> {
+    static RUN: AtomicUsize = AtomicUsize::new(0);
+    let directory = std::env::temp_dir()
+        .join(format!(
+            "estimate_{}_{}",
+            std::process::id(),
+            RUN.fetch_add(1, Ordering::SeqCst)
+        ))
+        .to_str()
+        .unwrap()
+        .to_string();
+    fs::create_dir_all(&directory).await?;
+    let mut saver = IndexMergeSaver::new(directory.clone(), lexical_max_size).await?;
+    for (_, term) in tree.into_iter() {
+        saver.push(term).await?;
+    }
+    saver.finish().await?;
+    let layout = IndexLayout::default();
+    let size = |path: String| fs::metadata(path);
+    let lexicon = size(layout.dictionary(&directory)).await?.len()
+        + size(layout.lexical_part(&directory)).await?.len();
+    let postings = size(layout.index_part(&directory)).await?.len();
+    fs::remove_dir_all(&directory).await?;
+    Ok((lexicon, postings))
+}
+
+#[cfg(test)]
+mod tst {
+    use std::io::Error;
+
+    use tokio::fs;
+
+    use crate::{
+        config::IndexerConfig,
+        indexed::{Dictionary, IndexMerger, IndexParser, IndexedBuilder},
+        parser::ParseController,
+        segment::CommonSegments,
+        zones::ZoneSet,
+    };
+
+    use super::{estimate, EstimateConfig};
+
+    fn word(mut v: usize) -> String {
+        let mut out = "w".to_string();
+        loop {
+            out.push((b'a' + (v % 26) as u8) as char);
+            v /= 26;
+            if v == 0 {
+                return out;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn matches_full_index() -> Result<(), Error> {
+        let root = std::env::temp_dir().join(format!("estimate_tst_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root).await;
+        fs::create_dir_all(&root).await?;
+
+        let mut seed = 12345u64;
+        // Pareto-tailed ranks, so the vocabulary keeps growing like real text.
+        let mut next = || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let r = ((seed >> 33) + 1) as f64 / (1u64 << 31) as f64;
+            word(r.powf(-1.2) as usize)
+        };
+        let mut files = vec![];
+        let mut tokens = 0;
+        for f in 0..4 {
+            let mut content = String::new();
+            for _ in 0..50 {
+                let title = (0..3).map(|_| next()).collect::<Vec<_>>().join(" ");
+                let text = (0..40).map(|_| next()).collect::<Vec<_>>().join(" ");
+                tokens += 43;
+                content.push_str(&format!(
+                    "<title>\n{title}\n</title>\n<text>\n{text}\n</text>\n"
+                ));
+            }
+            let path = root.join(format!("{f}.xml")).to_str().unwrap().to_string();
+            fs::write(&path, content).await?;
+            files.push(path);
+        }
+
+        // A single task keeps the reference index deterministic.
+        let attributes = ZoneSet::new(["title", "text"])?;
+        let indexer = IndexerConfig::new(100, 6)?;
+        let destination = root.join("res").to_str().unwrap().to_string();
+        ParseController::<IndexParser, _, _>::new(
+            files.clone(),
+            destination.clone(),
+            root.join("buffer").to_str().unwrap().to_string(),
+            1,
+            IndexedBuilder::new(indexer, attributes.clone())?,
+            IndexMerger::new(indexer.merger()),
+        )
+        .create_dictionary()
+        .await?;
+        let terms = Dictionary::<CommonSegments>::new(&destination).await?.len() as u64;
+        let mut index_bytes = 0;
+        for name in ["dictionary.txt", "lexical_part.txt", "index_part.txt"] {
+            index_bytes += fs::metadata(format!("{destination}/{name}")).await?.len();
+        }
+
+        let mut config = EstimateConfig {
+            sample_fraction: 1.0,
+            indexer,
+            tasks_count: 1,
+            attributes,
+        };
+        let full = estimate(&files, &config).await?;
+        assert_eq!(full.tokens, tokens);
+        assert_eq!(full.documents, 200);
+        assert_eq!(full.distinct_terms, terms);
+        assert!((full.index_bytes as f64 - index_bytes as f64).abs() < index_bytes as f64 * 0.05);
+        assert!(full.buffer_flushes >= 2);
+
+        config.sample_fraction = 0.5;
+        let half = estimate(&files, &config).await?;
+        let within = |v: u64, expected: u64, tolerance: f64| {
+            (v as f64 - expected as f64).abs() <= expected as f64 * tolerance
+        };
+        assert!(within(half.tokens, tokens, 0.05));
+        assert!(within(half.distinct_terms, terms, 0.3));
+        assert!(within(half.index_bytes, index_bytes, 0.3));
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
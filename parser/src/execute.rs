@@ -0,0 +1,683 @@
+use std::{
+    collections::BTreeSet,
+    fmt::Display,
+    io::Error,
+    time::{Duration, Instant},
+};
+
+use futures::{future::BoxFuture, FutureExt};
+#[cfg(feature = "roaring")]
+use roaring::RoaringBitmap;
+
+use crate::{
+    cancel::{is_interrupted, CancellationToken},
+    delta::DeltaIndex,
+    gallop::intersect_documents,
+    indexed::Dictionary,
+    numeric::NumericValues,
+    query::{Query, QueryCache, QueryError},
+    segment::{SegmentSelector, Segments},
+    synonym::Thesaurus,
+    warnings::MAX_TOKEN_BYTES,
+};
+
+/// Bounds on the work a single query may cause.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueryLimits {
+    /// Terms a wildcard may expand to; the rest are ignored and the result is partial.
+    pub max_expanded_terms: usize,
+    /// Postings entries read over the whole query.
+    pub max_postings_scanned: usize,
+    pub deadline: Duration,
+    /// Longest term, in bytes; a query with a longer one is refused before
+    /// it reaches the dictionary. See
+    /// [`crate::metadata::IndexMetadata::token_limit`].
+    pub max_term_bytes: usize,
+}
+
+impl Default for QueryLimits {
+    fn default() -> Self {
+        Self {
+            max_expanded_terms: 1000,
+            max_postings_scanned: 10_000_000,
+            deadline: Duration::from_secs(5),
+            max_term_bytes: MAX_TOKEN_BYTES,
+        }
+    }
+}
+
+/// Which limit stopped a query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Limit {
+    PostingsScanned(usize),
+    Deadline(Duration),
+}
+
+impl Display for Limit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Limit::PostingsScanned(v) => write!(f, "more than {v} postings scanned"),
+            Limit::Deadline(v) => write!(f, "deadline of {v:?} exceeded"),
+        }
+    }
+}
+
+/// Where the time of a query went.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct QueryTimings {
+    /// Parsing the raw query, zero when it came from the cache or was parsed
+    /// by the caller.
+    pub parse: Duration,
+    /// Finding terms in the dictionary, wildcard expansion included.
+    pub lookup: Duration,
+    /// Reading and decoding postings.
+    pub decode: Duration,
+    /// Everything else the executor does: zone filters and set operations.
+    pub score: Duration,
+    pub total: Duration,
+}
+
+/// `parse 0.012ms lookup 0.301ms decode 0.150ms score 0.020ms total 0.483ms`.
+impl Display for QueryTimings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ms = |v: Duration| v.as_secs_f64() * 1000.0;
+        write!(
+            f,
+            "parse {:.3}ms lookup {:.3}ms decode {:.3}ms score {:.3}ms total {:.3}ms",
+            ms(self.parse),
+            ms(self.lookup),
+            ms(self.decode),
+            ms(self.score),
+            ms(self.total)
+        )
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct QueryResult {
+    pub documents: Vec<usize>,
+    /// Set when a wildcard expanded past `max_expanded_terms`.
+    pub partial: bool,
+    /// Terms looked up after wildcard expansion.
+    pub expanded_terms: usize,
+    /// Whether the parsed query came from a [`QueryCache`].
+    pub cached: bool,
+    pub timings: QueryTimings,
+}
+
+struct Execution<'a, S: Segments> {
+    dictionary: &'a mut Dictionary<S>,
+    delta: Option<&'a DeltaIndex<S>>,
+    synonyms: &'a Thesaurus,
+    limits: QueryLimits,
+    cancel: &'a CancellationToken,
+    started: Instant,
+    scanned: usize,
+    partial: bool,
+    expanded_terms: usize,
+    timings: QueryTimings,
+}
+
+fn from_io(e: Error) -> QueryError {
+    match e.get_ref().and_then(|v| v.downcast_ref::<QueryError>()) {
+        Some(v) => v.clone(),
+        None if is_interrupted(&e) => QueryError::Interrupted,
+        None => QueryError::Io(e.to_string()),
+    }
+}
+
+impl<'a, S: Segments> Execution<'a, S> {
+    async fn term(&mut self, term: &str, zone: &Option<S>) -> Result<BTreeSet<usize>, QueryError> {
+        if self.started.elapsed() >= self.limits.deadline {
+            return Err(QueryError::Limit(Limit::Deadline(self.limits.deadline)));
+        }
+        let started = Instant::now();
+        let terms = match term.contains('*') {
+            true => {
+                let mut terms = self.dictionary.wildcard(term).await.map_err(from_io)?;
+                if let Some(delta) = self.delta {
+                    terms.extend(delta.wildcard(term));
+                    terms.sort_unstable();
+                    terms.dedup();
+                }
+                if terms.len() > self.limits.max_expanded_terms {
+                    terms.truncate(self.limits.max_expanded_terms);
+                    self.partial = true;
+                }
+                terms
+            }
+            false => std::iter::once(term)
+                .chain(self.synonyms.synonyms(term).iter().map(String::as_str))
+                .map(str::to_string)
+                .collect(),
+        };
+        self.timings.lookup += started.elapsed();
+        self.expanded_terms += terms.len();
+        let mut documents = BTreeSet::new();
+        for term in terms {
+            if let Some(delta) = self.delta {
+                for (document, usage) in delta.postings(&term) {
+                    self.scanned += 1;
+                    if zone.as_ref().is_none_or(|v| usage.segments().intersects(v)) {
+                        documents.insert(document);
+                    }
+                }
+            }
+            let started = Instant::now();
+            let cursor = self.dictionary.find_cursor(&term).await.map_err(from_io)?;
+            self.timings.lookup += started.elapsed();
+            let Some(cursor) = cursor else {
+                continue;
+            };
+            self.cancel.check().map_err(from_io)?;
+            let started = Instant::now();
+            let found = self.dictionary.get_term(cursor).await.map_err(from_io)?;
+            self.timings.decode += started.elapsed();
+            self.scanned += found.indexes.len();
+            if self.scanned > self.limits.max_postings_scanned {
+                return Err(QueryError::Limit(Limit::PostingsScanned(
+                    self.limits.max_postings_scanned,
+                )));
+            }
+            for (document, mut usage) in found.indexes.iter() {
+                if zone.as_ref().is_none_or(|v| usage.segments_mut().intersects(v)) {
+                    documents.insert(document);
+                }
+            }
+        }
+        Ok(documents)
+    }
+
+    async fn numeric(&mut self, field: &str) -> Result<&NumericValues, QueryError> {
+        let values = self.dictionary.numeric().await.map_err(from_io)?;
+        match values.field == field {
+            true => Ok(values),
+            false => Err(QueryError::UnknownZone(field.to_string())),
+        }
+    }
+
+    /// Runs `query` over bitmaps if every term of it has one, see
+    /// [`Self::bitmaps`], or else over decoded postings.
+    async fn run(&mut self, query: &Query<S>) -> Result<BTreeSet<usize>, QueryError> {
+        #[cfg(feature = "roaring")]
+        if self.delta.is_none_or(DeltaIndex::is_empty) && self.has_bitmaps(query) {
+            let documents = self.bitmaps(query).await?;
+            return Ok(documents.iter().map(|v| v as usize).collect());
+        }
+        self.eval(query).await
+    }
+
+    /// Whether [`Self::bitmaps`] can answer `query`: its terms have bitmaps,
+    /// as do their synonyms, and it has no zones, wildcards or ranges.
+    #[cfg(feature = "roaring")]
+    fn has_bitmaps(&self, query: &Query<S>) -> bool {
+        match query {
+            Query::Term { term, zone: None } => {
+                !term.contains('*')
+                    && self.dictionary.has_bitmap(term)
+                    && self.synonyms.synonyms(term).iter().all(|v| self.dictionary.has_bitmap(v))
+            }
+            Query::Term { .. } | Query::Range { .. } | Query::Not(_) => false,
+            Query::Or(items) => items.iter().all(|v| self.has_bitmaps(v)),
+            Query::And(items) => {
+                items.iter().any(|v| !matches!(v, Query::Not(_)))
+                    && items.iter().all(|v| match v {
+                        Query::Not(v) => self.has_bitmaps(v),
+                        v => self.has_bitmaps(v),
+                    })
+            }
+        }
+    }
+
+    /// [`Self::eval`] with bitmap set operations, for a query that
+    /// [`Self::has_bitmaps`].
+    #[cfg(feature = "roaring")]
+    fn bitmaps<'b>(&'b mut self, query: &'b Query<S>) -> BoxFuture<'b, Result<RoaringBitmap, QueryError>> {
+        async move {
+            match query {
+                Query::Term { term, .. } => {
+                    if self.started.elapsed() >= self.limits.deadline {
+                        return Err(QueryError::Limit(Limit::Deadline(self.limits.deadline)));
+                    }
+                    let mut documents = RoaringBitmap::new();
+                    let synonyms = self.synonyms.synonyms(term);
+                    self.expanded_terms += 1 + synonyms.len();
+                    for term in std::iter::once(term).chain(synonyms.iter()) {
+                        self.cancel.check().map_err(from_io)?;
+                        let started = Instant::now();
+                        let found = self.dictionary.bitmap(term).await.map_err(from_io)?.unwrap_or_default();
+                        self.timings.decode += started.elapsed();
+                        self.scanned += found.len() as usize;
+                        if self.scanned > self.limits.max_postings_scanned {
+                            return Err(QueryError::Limit(Limit::PostingsScanned(
+                                self.limits.max_postings_scanned,
+                            )));
+                        }
+                        documents |= found;
+                    }
+                    Ok(documents)
+                }
+                Query::Or(items) => {
+                    let mut documents = RoaringBitmap::new();
+                    for v in items {
+                        documents |= self.bitmaps(v).await?;
+                    }
+                    Ok(documents)
+                }
+                Query::And(items) => {
+                    let mut documents: Option<RoaringBitmap> = None;
+                    let mut excluded = RoaringBitmap::new();
+                    for v in items {
+                        match v {
+                            Query::Not(v) => excluded |= self.bitmaps(v).await?,
+                            v => {
+                                let found = self.bitmaps(v).await?;
+                                documents = Some(match documents {
+                                    Some(d) => d & found,
+                                    None => found,
+                                });
+                            }
+                        }
+                    }
+                    Ok(documents.unwrap_or_default() - excluded)
+                }
+                Query::Range { .. } | Query::Not(_) => {
+                    Err(QueryError::Syntax("no bitmap answers this query".to_string()))
+                }
+            }
+        }
+        .boxed()
+    }
+
+    fn eval<'b>(&'b mut self, query: &'b Query<S>) -> BoxFuture<'b, Result<BTreeSet<usize>, QueryError>> {
+        async move {
+            match query {
+                Query::Term { term, zone } => self.term(term, zone).await,
+                Query::Range { field, range } => {
+                    Ok(self.numeric(field).await?.matching(range).collect())
+                }
+                Query::Or(items) => {
+                    let mut documents = BTreeSet::new();
+                    for v in items {
+                        documents.extend(self.eval(v).await?);
+                    }
+                    Ok(documents)
+                }
+                Query::And(items) => {
+                    let mut positive = Vec::new();
+                    let mut excluded = BTreeSet::new();
+                    let mut ranges = Vec::new();
+                    for v in items {
+                        match v {
+                            Query::Not(v) => excluded.extend(self.eval(v).await?),
+                            Query::Range { field, range } => ranges.push((field, range)),
+                            v => positive.push(self.eval(v).await?.into_iter().collect::<Vec<_>>()),
+                        }
+                    }
+                    let mut documents = match positive.is_empty() {
+                        true => None,
+                        false => {
+                            let lists = positive.iter().map(Vec::as_slice).collect::<Vec<_>>();
+                            Some(intersect_documents(&lists).into_iter().collect::<BTreeSet<_>>())
+                        }
+                    };
+                    // Ranges only filter the candidates when there are some.
+                    for (field, range) in ranges {
+                        let values = self.numeric(field).await?;
+                        documents = Some(match documents {
+                            Some(d) => d
+                                .into_iter()
+                                .filter(|v| values.get(*v).is_some_and(|v| range.contains(v)))
+                                .collect(),
+                            None => values.matching(range).collect(),
+                        });
+                    }
+                    match documents {
+                        Some(d) => Ok(d.difference(&excluded).copied().collect()),
+                        None => Err(QueryError::Syntax("NOT needs a positive term".to_string())),
+                    }
+                }
+                Query::Not(_) => Err(QueryError::Syntax("NOT needs a positive term".to_string())),
+            }
+        }
+        .boxed()
+    }
+}
+
+/// Runs `query` against `dictionary` within `limits`. The deadline is checked
+/// before every term and also bounds any single lookup that stalls.
+pub async fn execute<S: Segments>(
+    query: &Query<S>,
+    dictionary: &mut Dictionary<S>,
+    limits: QueryLimits,
+) -> Result<QueryResult, QueryError> {
+    execute_with_synonyms(query, dictionary, limits, &Thesaurus::default()).await
+}
+
+/// Like [`execute`], a term other than a wildcard also matching the
+/// documents of its `synonyms`. They count towards
+/// [`QueryResult::expanded_terms`] like the terms of a wildcard.
+pub async fn execute_with_synonyms<S: Segments>(
+    query: &Query<S>,
+    dictionary: &mut Dictionary<S>,
+    limits: QueryLimits,
+    synonyms: &Thesaurus,
+) -> Result<QueryResult, QueryError> {
+    execute_cancellable(query, dictionary, limits, synonyms, &CancellationToken::new()).await
+}
+
+/// Like [`execute_with_synonyms`], failing with [`QueryError::Interrupted`]
+/// once `cancel` is cancelled. It is checked before reading every postings
+/// list.
+pub async fn execute_cancellable<S: Segments>(
+    query: &Query<S>,
+    dictionary: &mut Dictionary<S>,
+    limits: QueryLimits,
+    synonyms: &Thesaurus,
+    cancel: &CancellationToken,
+) -> Result<QueryResult, QueryError> {
+    run(query, dictionary, None, limits, synonyms, cancel).await
+}
+
+/// Like [`execute_cancellable`], also finding the documents of `delta`.
+/// Their postings join those of the index term by term, wildcards expand
+/// over the terms of both, and a range matches no document of the delta.
+pub async fn execute_with_delta<S: Segments>(
+    query: &Query<S>,
+    dictionary: &mut Dictionary<S>,
+    delta: &DeltaIndex<S>,
+    limits: QueryLimits,
+    synonyms: &Thesaurus,
+    cancel: &CancellationToken,
+) -> Result<QueryResult, QueryError> {
+    run(query, dictionary, Some(delta), limits, synonyms, cancel).await
+}
+
+async fn run<S: Segments>(
+    query: &Query<S>,
+    dictionary: &mut Dictionary<S>,
+    delta: Option<&DeltaIndex<S>>,
+    limits: QueryLimits,
+    synonyms: &Thesaurus,
+    cancel: &CancellationToken,
+) -> Result<QueryResult, QueryError> {
+    // The `*` of a wildcard matches no bytes of a term.
+    let bytes = |v: &str| v.len() - v.matches('*').count();
+    if let Some(term) = query.terms().into_iter().find(|v| bytes(v) > limits.max_term_bytes) {
+        return Err(QueryError::TermTooLong {
+            bytes: bytes(term),
+            max: limits.max_term_bytes,
+        });
+    }
+    let mut execution = Execution {
+        dictionary,
+        delta,
+        synonyms,
+        limits,
+        cancel,
+        started: Instant::now(),
+        scanned: 0,
+        partial: false,
+        expanded_terms: 0,
+        timings: QueryTimings::default(),
+    };
+    let documents = tokio::time::timeout(limits.deadline, execution.run(query))
+        .await
+        .map_err(|_| QueryError::Limit(Limit::Deadline(limits.deadline)))??;
+    let mut timings = execution.timings;
+    timings.total = execution.started.elapsed();
+    timings.score = timings.total.saturating_sub(timings.lookup + timings.decode);
+    Ok(QueryResult {
+        documents: documents.into_iter().collect(),
+        partial: execution.partial,
+        expanded_terms: execution.expanded_terms,
+        cached: false,
+        timings,
+    })
+}
+
+/// How [`execute_raw`] runs a query.
+#[derive(Clone, Copy)]
+pub struct QueryOptions<'a> {
+    pub limits: QueryLimits,
+    /// Expands the terms of the query.
+    pub synonyms: &'a Thesaurus,
+    /// Stops the query with [`QueryError::Interrupted`] once cancelled.
+    pub cancel: &'a CancellationToken,
+}
+
+/// Parses `raw` through `cache` and runs it with `options`, timing the
+/// parse as well.
+pub async fn execute_raw<S: Segments, Sel: SegmentSelector<Segments = S>>(
+    raw: &str,
+    cache: &QueryCache<S>,
+    generation: u64,
+    selector: &Sel,
+    dictionary: &mut Dictionary<S>,
+    options: QueryOptions<'_>,
+) -> Result<QueryResult, QueryError> {
+    let started = Instant::now();
+    let (query, cached) = cache.lookup(raw, generation, selector)?;
+    let parse = started.elapsed();
+    let QueryOptions { limits, synonyms, cancel } = options;
+    let mut result = execute_cancellable(&query, dictionary, limits, synonyms, cancel).await?;
+    result.cached = cached;
+    result.timings.parse = parse;
+    result.timings.total += parse;
+    Ok(result)
+}
+
+/// What to log about the queries that were run.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct QueryLog {
+    /// Log every query at info level.
+    pub all: bool,
+    /// Warn with the timings of queries taking longer.
+    pub slow: Option<Duration>,
+}
+
+impl QueryLog {
+    pub fn is_slow(&self, result: &QueryResult) -> bool {
+        self.slow.is_some_and(|v| result.timings.total > v)
+    }
+
+    pub fn record(&self, raw: &str, result: &QueryResult) {
+        if self.all {
+            log::info!(
+                "query {raw:?}: {:.3}ms, {} documents, {} terms, cached {}",
+                result.timings.total.as_secs_f64() * 1000.0,
+                result.documents.len(),
+                result.expanded_terms,
+                result.cached
+            );
+        }
+        if self.is_slow(result) {
+            log::warn!("slow query {raw:?}: {}", result.timings);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tst {
+    use std::{io::Error, time::Duration};
+
+    use tokio::fs;
+
+    use crate::{
+        cancel::CancellationToken,
+        config::IndexerConfig,
+        indexed::{Dictionary, IndexMerger, IndexParser, IndexedBuilder},
+        parser::ParseController,
+        query::{parse_query, QueryCache, QueryError},
+        segment::{CommonSegmentSelector, CommonSegments},
+        synonym::Thesaurus,
+        zones::ZoneSet,
+    };
+
+    use super::{execute, execute_cancellable, execute_raw, Limit, QueryLimits, QueryLog, QueryOptions};
+
+    #[tokio::test]
+    async fn limits_are_enforced() -> Result<(), Error> {
+        let root = std::env::temp_dir().join(format!("execute_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root).await;
+        fs::create_dir_all(&root).await?;
+        let path = root.join("0.xml").to_str().unwrap().to_string();
+        let documents = [
+            ("tokio", "async runtime"),
+            ("rust", "async tokio"),
+            ("java", "threads runtime"),
+            ("asyncio", "python"),
+        ];
+        let content = documents
+            .iter()
+            .map(|(title, text)| format!("<title>\n{title}\n</title>\n<text>\n{text}\n</text>\n"))
+            .collect::<String>();
+        fs::write(&path, content).await?;
+
+        let destination = root.join("res").to_str().unwrap().to_string();
+        let config = IndexerConfig::new(1000, 6)?;
+        ParseController::<IndexParser, _, _>::new(
+            vec![path],
+            destination.clone(),
+            root.join("buffer").to_str().unwrap().to_string(),
+            1,
+            IndexedBuilder::new(config, ZoneSet::new(["title", "text"])?)?,
+            IndexMerger::new(config.merger()).with_permuterm(),
+        )
+        .create_dictionary()
+        .await?;
+
+        let selector = CommonSegmentSelector::new();
+        let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+        let run = |raw: &str| parse_query(raw, &selector).unwrap();
+
+        let result = execute(&run("async NOT java"), &mut dictionary, QueryLimits::default())
+            .await
+            .unwrap();
+        assert_eq!((result.documents, result.partial), (vec![0, 1], false));
+        let result = execute(&run("title:tokio OR runtime"), &mut dictionary, QueryLimits::default())
+            .await
+            .unwrap();
+        assert_eq!(result.documents, [0, 2]);
+
+        let limits = QueryLimits {
+            max_expanded_terms: 1,
+            ..QueryLimits::default()
+        };
+        let result = execute(&run("async*"), &mut dictionary, limits).await.unwrap();
+        assert_eq!((result.documents, result.partial), (vec![0, 1], true));
+
+        let limits = QueryLimits {
+            max_postings_scanned: 3,
+            ..QueryLimits::default()
+        };
+        assert_eq!(
+            execute(&run("async OR runtime"), &mut dictionary, limits).await,
+            Err(QueryError::Limit(Limit::PostingsScanned(3)))
+        );
+
+        let limits = QueryLimits {
+            deadline: Duration::ZERO,
+            ..QueryLimits::default()
+        };
+        assert_eq!(
+            execute(&run("async"), &mut dictionary, limits).await,
+            Err(QueryError::Limit(Limit::Deadline(Duration::ZERO)))
+        );
+
+        assert_eq!(
+            execute(&run("*"), &mut dictionary, QueryLimits::default()).await,
+            Err(QueryError::TooBroad("*".to_string()))
+        );
+
+        let long = "a".repeat(10_000);
+        let limits = QueryLimits {
+            max_term_bytes: 5,
+            ..QueryLimits::default()
+        };
+        assert_eq!(
+            execute(&run(&long), &mut dictionary, QueryLimits::default()).await,
+            Err(QueryError::TermTooLong { bytes: 10_000, max: QueryLimits::default().max_term_bytes })
+        );
+        assert_eq!(execute(&run("async NOT java"), &mut dictionary, limits).await.unwrap().documents, [0, 1]);
+        assert_eq!(execute(&run("async*"), &mut dictionary, limits).await.unwrap().documents, [0, 1, 3]);
+        assert_eq!(
+            execute(&run("asyncio"), &mut dictionary, limits).await,
+            Err(QueryError::TermTooLong { bytes: 7, max: 5 })
+        );
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let none = Thesaurus::default();
+        assert_eq!(
+            execute_cancellable(&run("async"), &mut dictionary, QueryLimits::default(), &none, &cancel).await,
+            Err(QueryError::Interrupted)
+        );
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn phases_are_timed() -> Result<(), Error> {
+        let root = std::env::temp_dir().join(format!("execute_timings_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root).await;
+        fs::create_dir_all(&root).await?;
+        let path = root.join("0.xml").to_str().unwrap().to_string();
+        let content = (0..50)
+            .map(|i| format!("<title>
+async{}
+</title>
+<text>
+runtime{} shared
+</text>
+", "s".repeat(i % 3), i % 2))
+            .collect::<String>();
+        fs::write(&path, content).await?;
+        let destination = root.join("res").to_str().unwrap().to_string();
+        let config = IndexerConfig::new(1000, 6)?;
+        ParseController::<IndexParser, _, _>::new(
+            vec![path],
+            destination.clone(),
+            root.join("buffer").to_str().unwrap().to_string(),
+            1,
+            IndexedBuilder::new(config, ZoneSet::new(["title", "text"])?)?,
+            IndexMerger::new(config.merger()).with_permuterm(),
+        )
+        .create_dictionary()
+        .await?;
+
+        let selector = CommonSegmentSelector::new();
+        let cache = QueryCache::<CommonSegments>::new(4);
+        let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+        let raw = "async* AND shared NOT missing";
+        let none = Thesaurus::default();
+        let cancel = CancellationToken::new();
+        let options = QueryOptions { limits: QueryLimits::default(), synonyms: &none, cancel: &cancel };
+        let first = execute_raw(raw, &cache, 0, &selector, &mut dictionary, options).await.unwrap();
+        let second = execute_raw(raw, &cache, 0, &selector, &mut dictionary, options).await.unwrap();
+        assert_eq!(first.documents.len(), 50);
+        assert_eq!(first.documents, second.documents);
+        assert_eq!((first.cached, second.cached), (false, true));
+        assert_eq!(first.expanded_terms, 5);
+        for timings in [first.timings, second.timings] {
+            assert!(timings.lookup > Duration::ZERO && timings.decode > Duration::ZERO);
+            assert!(timings.parse + timings.lookup + timings.decode + timings.score <= timings.total);
+        }
+        assert!(first.timings.parse > Duration::ZERO);
+
+        let plain = execute(&parse_query("shared", &selector).unwrap(), &mut dictionary, QueryLimits::default())
+            .await
+            .unwrap();
+        assert_eq!((plain.timings.parse, plain.expanded_terms, plain.cached), (Duration::ZERO, 1, false));
+
+        let log = QueryLog { all: true, slow: Some(Duration::ZERO) };
+        assert!(log.is_slow(&first));
+        assert!(!QueryLog { slow: Some(Duration::from_secs(60)), ..log }.is_slow(&first));
+        assert!(!QueryLog::default().is_slow(&first));
+        log.record(raw, &first);
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+use std::io::{Error, ErrorKind};
+
+use regex::RegexSet;
+use serde::{Deserialize, Serialize};
+
+/// Regexes deciding which terms reach the index, recorded in `metadata.json`.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FilterPatterns {
+    /// Terms matching any of these are dropped.
+    pub drop: Vec<String>,
+    /// When not empty, only terms matching one of these are kept.
+    pub keep: Vec<String>,
+}
+
+/// Compiled [`FilterPatterns`].
+#[derive(Debug, Clone)]
+pub struct TermFilter {
+    patterns: FilterPatterns,
+    drop: RegexSet,
+    keep: Option<RegexSet>,
+}
+
+fn compile(patterns: &[String]) -> Result<RegexSet, Error> {
+    RegexSet::new(patterns).map_err(|e| Error::new(ErrorKind::InvalidInput, e))
+}
+
+impl TermFilter {
+    pub fn new(patterns: FilterPatterns) -> Result<Self, Error> {
+        let drop = compile(&patterns.drop)?;
+        let keep = match patterns.keep.is_empty() {
+            true => None,
+            false => Some(compile(&patterns.keep)?),
+        };
+        Ok(Self {
+            patterns,
+            drop,
+            keep,
+        })
+    }
+
+    pub fn allows(&self, term: &str) -> bool {
+        !self.drop.is_match(term) && self.keep.as_ref().is_none_or(|v| v.is_match(term))
+    }
+
+    /// Terms among `terms` that could not have been indexed.
+    pub fn rejected<'a>(&self, terms: impl IntoIterator<Item = &'a str>) -> Vec<&'a str> {
+        terms.into_iter().filter(|v| !self.allows(v)).collect()
+    }
+
+    pub fn patterns(&self) -> &FilterPatterns {
+        &self.patterns
+    }
+}
+
+#[cfg(test)]
+mod tst {
+    use std::io::Error;
+
+    use tokio::fs;
+
+    use crate::{
+        config::IndexerConfig,
+        indexed::{Dictionary, IndexMerger, IndexParser, IndexedBuilder},
+        metadata::IndexMetadata,
+        parser::ParseController,
+        query::parse_query,
+        report::ParseReport,
+        segment::{CommonSegmentSelector, CommonSegments},
+        zones::ZoneSet,
+    };
+
+    use super::{FilterPatterns, TermFilter};
+
+    #[test]
+    fn drop_and_keep() {
+        let filter = TermFilter::new(FilterPatterns {
+            drop: vec!["^[a-f0-9]{32}$".to_string(), "^wp.*".to_string()],
+            keep: vec![],
+        })
+        .unwrap();
+        assert!(filter.allows("rust"));
+        assert!(!filter.allows("d41d8cd98f00b204e9800998ecf8427e"));
+        assert!(!filter.allows("wpuser"));
+
+        let filter = TermFilter::new(FilterPatterns {
+            drop: vec![],
+            keep: vec!["^[a-z]+$".to_string()],
+        })
+        .unwrap();
+        assert_eq!(filter.rejected(["rust", "r2d2", "tokio"]), ["r2d2"]);
+        let query = parse_query("rust (r2d2 OR x86) NOT tokio", &CommonSegmentSelector::new()).unwrap();
+        assert_eq!(filter.rejected(query.terms()), ["r2d2", "x86"]);
+        assert!(TermFilter::new(FilterPatterns {
+            drop: vec!["(".to_string()],
+            keep: vec![],
+        })
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn hashes_never_reach_dictionary() -> Result<(), Error> {
+        let root = std::env::temp_dir().join(format!("filter_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root).await;
+        fs::create_dir_all(&root).await?;
+        let path = root.join("0.xml").to_str().unwrap().to_string();
+        fs::write(
+            &path,
+            "<title>\nchecksum deadbeefcafebabedeadbeefcafebabe\n</title>\n<text>\n\
+             file abcdefabcdefabcdefabcdefabcdefab matched wpadmin\n</text>\n",
+        )
+        .await?;
+
+        let patterns = FilterPatterns {
+            drop: vec!["^[a-f0-9]{32}$".to_string(), "^wp.*".to_string()],
+            keep: vec![],
+        };
+        let destination = root.join("res").to_str().unwrap().to_string();
+        let config = IndexerConfig::new(1000, 6)?;
+        ParseController::<IndexParser, _, _>::new(
+            vec![path],
+            destination.clone(),
+            root.join("buffer").to_str().unwrap().to_string(),
+            1,
+            IndexedBuilder::new(config, ZoneSet::new(["title", "text"])?)?
+                .with_filter(TermFilter::new(patterns.clone())?),
+            IndexMerger::new(config.merger()),
+        )
+        .create_dictionary()
+        .await?;
+
+        let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+        assert_eq!(dictionary.len(), 3);
+        for term in ["checksum", "file", "matched"] {
+            assert!(dictionary.find(term).await?.is_some(), "{term}");
+        }
+        for term in [
+            "deadbeefcafebabedeadbeefcafebabe",
+            "abcdefabcdefabcdefabcdefabcdefab",
+            "wpadmin",
+        ] {
+            assert!(dictionary.find(term).await?.is_none(), "{term}");
+        }
+
+        let report = ParseReport::load(&destination).await?;
+        assert_eq!(report.files[0].dropped_terms, 3);
+        assert_eq!(report.files[0].tokens, 6);
+        let metadata = IndexMetadata::load(&destination).await?;
+        assert_eq!(metadata.filter, Some(patterns));
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,190 @@
+use std::io::{Error, ErrorKind, SeekFrom};
+
+use save::writer::variable_load;
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt, BufReader},
+};
+
+use crate::{indexed::Dictionary, layout::IndexLayout, segment::Segments};
+#[cfg(feature = "build")]
+use save::writer::variable_encode_u64;
+
+/// Terms of every document by id, stored as `forward_part.bin` by a merge
+/// [`crate::indexed::IndexMerger::with_forward_index`]: the inverse of the
+/// postings, to tell why a document matched what it did.
+///
+/// The file starts with the number of documents and, for each one and past
+/// the last, where its block starts after them. A block holds the number of
+/// terms of the document, then the dictionary ordinal of each in order, as
+/// the delta from the one before, and its count in the document.
+pub struct ForwardIndex {
+    file: BufReader<File>,
+    documents: usize,
+}
+
+/// Bytes ahead of the block offsets: the document count.
+const HEADER_SIZE: u64 = 8;
+
+fn invalid(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Error {
+    Error::new(ErrorKind::InvalidData, e)
+}
+
+impl ForwardIndex {
+    /// The forward index of the index in `directory`, or `None` if it was
+    /// built without one.
+    pub async fn load(directory: &String) -> Result<Option<Self>, Error> {
+        let mut file = match File::open(IndexLayout::detect(directory).await?.forward(directory)).await {
+            Ok(v) => BufReader::new(v),
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let documents = file.read_u64().await?;
+        let offsets_end = documents
+            .checked_add(1)
+            .and_then(|v| v.checked_mul(8))
+            .and_then(|v| v.checked_add(HEADER_SIZE));
+        match offsets_end {
+            Some(end) if end <= file.get_ref().metadata().await?.len() => {}
+            _ => return Err(invalid(format!("forward index of {directory} holds fewer than {documents} documents"))),
+        }
+        Ok(Some(Self {
+            file,
+            documents: documents as usize,
+        }))
+    }
+
+    /// Documents, the highest id with terms and those before it.
+    pub fn len(&self) -> usize {
+        self.documents
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents == 0
+    }
+
+    /// Dictionary ordinals of the terms of `document`, in order, with the
+    /// count of each in it. Fails with InvalidInput past the last document.
+    pub async fn get(&mut self, document: usize) -> Result<Vec<(usize, usize)>, Error> {
+        if document >= self.documents {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("document {document} is past the {} of the forward index", self.documents),
+            ));
+        }
+        self.file.seek(SeekFrom::Start(HEADER_SIZE + 8 * document as u64)).await?;
+        let start = self.file.read_u64().await?;
+        let blocks = HEADER_SIZE + 8 * (self.documents as u64 + 1);
+        self.file.seek(SeekFrom::Start(blocks + start)).await?;
+        let len = variable_load(&mut self.file).await?;
+        let mut terms = Vec::with_capacity(len.min(1 << 16));
+        let mut ordinal = 0;
+        for _ in 0..len {
+            ordinal += variable_load(&mut self.file).await?;
+            terms.push((ordinal, variable_load(&mut self.file).await?));
+        }
+        Ok(terms)
+    }
+}
+
+/// The terms of `document` in the index of `dictionary`, in dictionary
+/// order, with the count of each in it. Fails with NotFound if the index
+/// has no [`ForwardIndex`].
+pub async fn terms_of_document<S: Segments>(
+    dictionary: &mut Dictionary<S>,
+    document: usize,
+) -> Result<Vec<(String, usize)>, Error> {
+    let directory = dictionary.directory().clone();
+    let Some(mut forward) = ForwardIndex::load(&directory).await? else {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("{directory} was built without a forward index"),
+        ));
+    };
+    let mut terms = Vec::new();
+    for (ordinal, count) in forward.get(document).await? {
+        terms.push((dictionary.term_at(ordinal).await.map_err(invalid)?, count));
+    }
+    Ok(terms)
+}
+
+/// Writes the [`ForwardIndex`] of the index in `directory` and returns the
+/// documents it holds. Every posting is held in memory until then, so it
+/// takes about as much as the postings of the index decoded.
+#[cfg(feature = "build")]
+pub(crate) async fn write_forward<S: Segments>(directory: &String) -> Result<usize, Error> {
+    let mut dictionary = Dictionary::<S>::new(directory).await?;
+    let mut documents = Vec::<Vec<(usize, usize)>>::new();
+    for ordinal in 0..dictionary.len() {
+        let mut postings = dictionary.postings_at(ordinal).await?;
+        while let Some((document, usage)) = postings.next().await? {
+            if documents.len() <= document {
+                documents.resize_with(document + 1, Vec::new);
+            }
+            documents[document].push((ordinal, usage.use_count()));
+        }
+    }
+
+    let mut head = Vec::with_capacity(8 * (documents.len() + 2));
+    let mut blocks = Vec::new();
+    head.extend_from_slice(&(documents.len() as u64).to_be_bytes());
+    for terms in documents.iter() {
+        head.extend_from_slice(&(blocks.len() as u64).to_be_bytes());
+        variable_encode_u64(terms.len() as u64, &mut blocks);
+        let mut previous = 0;
+        for (ordinal, count) in terms {
+            variable_encode_u64((ordinal - previous) as u64, &mut blocks);
+            variable_encode_u64(*count as u64, &mut blocks);
+            previous = *ordinal;
+        }
+    }
+    head.extend_from_slice(&(blocks.len() as u64).to_be_bytes());
+    head.extend_from_slice(&blocks);
+    tokio::fs::write(dictionary.layout().forward(directory), head).await?;
+    Ok(documents.len())
+}
+
+#[cfg(all(test, feature = "build"))]
+mod tst {
+    use std::{collections::BTreeMap, io::{Error, ErrorKind}};
+
+    use tokio::fs;
+
+    use crate::{
+        indexed::Dictionary,
+        segment::CommonSegments,
+        testsupport::{scratch, CorpusSpec},
+    };
+
+    use super::{terms_of_document, ForwardIndex};
+
+    #[tokio::test]
+    async fn documents_list_the_terms_they_were_indexed_with() -> Result<(), Error> {
+        let root = scratch("forward").await?;
+        let corpus = CorpusSpec::default().generate(&root.join("corpus")).await?;
+        let index = corpus.index_with(&root, |v| v.with_forward_index()).await?;
+        let mut expected = BTreeMap::<usize, Vec<(String, usize)>>::new();
+        for (term, postings) in corpus.postings.iter() {
+            for (document, count) in postings {
+                expected.entry(*document).or_default().push((term.clone(), *count));
+            }
+        }
+
+        let forward = ForwardIndex::load(&index).await?.unwrap();
+        assert_eq!(forward.len(), expected.keys().last().unwrap() + 1);
+        let mut dictionary = Dictionary::<CommonSegments>::new(&index).await?;
+        for document in 0..forward.len() {
+            let terms = terms_of_document(&mut dictionary, document).await?;
+            assert_eq!(terms, expected.remove(&document).unwrap_or_default(), "{document}");
+        }
+        let past = terms_of_document(&mut dictionary, forward.len()).await.unwrap_err();
+        assert_eq!(past.kind(), ErrorKind::InvalidInput);
+
+        let plain = corpus.index(&root.join("plain")).await?;
+        let mut dictionary = Dictionary::<CommonSegments>::new(&plain).await?;
+        assert!(ForwardIndex::load(&plain).await?.is_none());
+        assert_eq!(terms_of_document(&mut dictionary, 0).await.unwrap_err().kind(), ErrorKind::NotFound);
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
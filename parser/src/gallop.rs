@@ -0,0 +1,175 @@
+/// First position from `from` on whose document is `target` or past it.
+///
+/// Steps double from `from` until one lands past `target`, then a binary
+/// search finds it within the last step, so advancing over `d` entries
+/// takes `O(log d)` comparisons however long the list is.
+pub fn gallop<T>(list: &[T], from: usize, target: usize, document: impl Fn(&T) -> usize) -> usize {
+    let (mut low, mut high, mut step) = (from, from, 1);
+    while high < list.len() && document(&list[high]) < target {
+        low = high + 1;
+        high += step;
+        step *= 2;
+    }
+    let high = high.min(list.len());
+    low + list[low..high].partition_point(|v| document(v) < target)
+}
+
+/// A position in a decoded postings list, sorted by document.
+pub struct PostingsCursor<'a, T> {
+    list: &'a [T],
+    at: usize,
+    document: fn(&T) -> usize,
+}
+
+impl<'a, T> PostingsCursor<'a, T> {
+    pub fn new(list: &'a [T], document: fn(&T) -> usize) -> Self {
+        Self { list, at: 0, document }
+    }
+
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// The posting the cursor is at, none once past the end.
+    pub fn current(&self) -> Option<&'a T> {
+        self.list.get(self.at)
+    }
+
+    /// Position in the list, the length of it once past the end.
+    pub fn position(&self) -> usize {
+        self.at
+    }
+
+    /// Moves to the first posting of `target` or after it, galloping. The
+    /// cursor never moves back.
+    pub fn advance_to(&mut self, target: usize) -> Option<&'a T> {
+        self.at = gallop(self.list, self.at, target, self.document);
+        self.current()
+    }
+}
+
+/// Positions of the documents in every one of `lists`, document order.
+///
+/// The shortest list leads and the others [`PostingsCursor::advance_to`]
+/// its documents; one landing past the document makes that the next one
+/// looked for.
+pub fn intersect<T>(lists: &[&[T]], document: fn(&T) -> usize) -> Vec<Vec<usize>> {
+    let mut found = Vec::new();
+    if lists.is_empty() {
+        return found;
+    }
+    let mut cursors = lists
+        .iter()
+        .map(|v| PostingsCursor::new(v, document))
+        .collect::<Vec<_>>();
+    let mut order = (0..cursors.len()).collect::<Vec<_>>();
+    order.sort_by_key(|i| cursors[*i].len());
+    let mut target = 0;
+    'next: loop {
+        for i in order.iter().copied() {
+            match cursors[i].advance_to(target).map(document) {
+                None => return found,
+                Some(at) if at > target => {
+                    target = at;
+                    continue 'next;
+                }
+                Some(_) => {}
+            }
+        }
+        found.push(cursors.iter().map(PostingsCursor::position).collect());
+        target += 1;
+    }
+}
+
+/// Documents in every one of the sorted `lists`, see [`intersect`].
+pub fn intersect_documents(lists: &[&[usize]]) -> Vec<usize> {
+    intersect(lists, |v| *v)
+        .into_iter()
+        .map(|positions| lists[0][positions[0]])
+        .collect()
+}
+
+#[cfg(test)]
+mod tst {
+    use super::{gallop, intersect_documents, PostingsCursor};
+
+    /// Two-pointer merge of every list into the first.
+    fn linear(lists: &[&[usize]]) -> Vec<usize> {
+        let mut found = lists.first().map_or(Vec::new(), |v| v.to_vec());
+        for list in lists.iter().skip(1) {
+            let (mut i, mut j, mut kept) = (0, 0, Vec::new());
+            while i < found.len() && j < list.len() {
+                match found[i].cmp(&list[j]) {
+                    std::cmp::Ordering::Less => i += 1,
+                    std::cmp::Ordering::Greater => j += 1,
+                    std::cmp::Ordering::Equal => {
+                        kept.push(found[i]);
+                        i += 1;
+                        j += 1;
+                    }
+                }
+            }
+            found = kept;
+        }
+        found
+    }
+
+    /// `len` sorted distinct documents below `range`.
+    fn random_list(state: &mut u64, len: usize, range: usize) -> Vec<usize> {
+        let mut list = (0..len)
+            .map(|_| {
+                *state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                (*state >> 33) as usize % range
+            })
+            .collect::<Vec<_>>();
+        list.sort_unstable();
+        list.dedup();
+        list
+    }
+
+    #[test]
+    fn gallop_lands_on_the_first_not_before() {
+        let list = [1, 3, 4, 9, 20, 21, 40];
+        for from in 0..list.len() {
+            for target in 0..45 {
+                let expected = from + list[from..].partition_point(|v| *v < target);
+                assert_eq!(gallop(&list, from, target, |v| *v), expected, "{from} {target}");
+            }
+        }
+        assert_eq!(gallop(&[], 0, 3, |v: &usize| *v), 0);
+
+        let mut cursor = PostingsCursor::new(&list, |v| *v);
+        assert_eq!(cursor.advance_to(5), Some(&9));
+        assert_eq!(cursor.advance_to(2), Some(&9));
+        assert_eq!(cursor.advance_to(41), None);
+        assert_eq!(cursor.position(), list.len());
+    }
+
+    #[test]
+    fn matches_linear_intersection_on_skewed_lists() {
+        let mut state = 7;
+        for round in 0..200 {
+            let range = [50, 1000, 100_000][round % 3];
+            let sizes = match round % 4 {
+                0 => vec![3, 5000],
+                1 => vec![10, 200, 20_000],
+                2 => vec![1000, 1000],
+                _ => vec![0, 40],
+            };
+            let lists = sizes
+                .iter()
+                .map(|v| random_list(&mut state, *v, range))
+                .collect::<Vec<_>>();
+            let lists = lists.iter().map(Vec::as_slice).collect::<Vec<_>>();
+            assert_eq!(intersect_documents(&lists), linear(&lists), "round {round}");
+            let reversed = lists.iter().rev().copied().collect::<Vec<_>>();
+            assert_eq!(intersect_documents(&reversed), linear(&lists), "round {round}");
+        }
+        assert!(intersect_documents(&[]).is_empty());
+        assert_eq!(intersect_documents(&[&[1, 4]]), [1, 4]);
+    }
+}
@@ -0,0 +1,213 @@
+use std::io::{Error, ErrorKind};
+
+use tokio::fs;
+
+/// Name of the pointer file in a destination parent. It holds the name of
+/// the live generation directory, e.g. `gen-000123`.
+pub const CURRENT: &str = "CURRENT";
+
+/// Numbered index directories under one parent, with a `CURRENT` pointer
+/// naming the live one. A build writes a fresh generation and only then
+/// flips the pointer, so readers that opened the previous generation keep
+/// their files while new opens see the new one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Generations {
+    parent: String,
+}
+
+impl Generations {
+    pub fn new(parent: impl Into<String>) -> Self {
+        Self {
+            parent: parent.into(),
+        }
+    }
+
+    pub fn parent(&self) -> &str {
+        &self.parent
+    }
+
+    pub fn name(generation: u64) -> String {
+        format!("gen-{generation:06}")
+    }
+
+    fn number(name: &str) -> Option<u64> {
+        let digits = name.strip_prefix("gen-")?;
+        if digits.len() < 6 || !digits.bytes().all(|v| v.is_ascii_digit()) {
+            return None;
+        }
+        digits.parse().ok()
+    }
+
+    pub fn directory(&self, generation: u64) -> String {
+        format!("{}/{}", self.parent, Self::name(generation))
+    }
+
+    /// Generation numbers found in the parent, oldest first.
+    pub async fn list(&self) -> Result<Vec<u64>, Error> {
+        let mut entries = match fs::read_dir(&self.parent).await {
+            Ok(v) => v,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut found = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            if !entry.file_type().await?.is_dir() {
+                continue;
+            }
+            if let Some(v) = entry.file_name().to_str().and_then(Self::number) {
+                found.push(v);
+            }
+        }
+        found.sort_unstable();
+        Ok(found)
+    }
+
+    /// Generation the pointer names, if one was flipped yet.
+    pub async fn current(&self) -> Result<Option<u64>, Error> {
+        let raw = match fs::read_to_string(format!("{}/{CURRENT}", self.parent)).await {
+            Ok(v) => v,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        Self::number(raw.trim()).map(Some).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("{}/{CURRENT} names {:?}, not a generation", self.parent, raw.trim()),
+            )
+        })
+    }
+
+    /// Directory of the live generation.
+    pub async fn current_directory(&self) -> Result<String, Error> {
+        match self.current().await? {
+            Some(v) => Ok(self.directory(v)),
+            None => Err(Error::new(
+                ErrorKind::NotFound,
+                format!("{}/{CURRENT} does not exist", self.parent),
+            )),
+        }
+    }
+
+    /// Creates the directory of the generation after every existing one.
+    pub async fn create_next(&self) -> Result<(u64, String), Error> {
+        let generation = self.list().await?.last().map_or(1, |v| v + 1);
+        let directory = self.directory(generation);
+        fs::create_dir_all(&directory).await?;
+        Ok((generation, directory))
+    }
+
+    /// Points `CURRENT` at `generation`. The pointer is written next to
+    /// itself and renamed over, so a reader sees either the old or the new
+    /// name and never a partial one.
+    pub async fn flip(&self, generation: u64) -> Result<(), Error> {
+        let staged = format!("{}/{CURRENT}.tmp", self.parent);
+        fs::write(&staged, Self::name(generation)).await?;
+        fs::rename(&staged, format!("{}/{CURRENT}", self.parent)).await
+    }
+
+    /// Deletes all but the newest `keep` generations. The live one is never
+    /// removed, whatever its age.
+    pub async fn retain(&self, keep: usize) -> Result<Vec<u64>, Error> {
+        let current = self.current().await?;
+        let all = self.list().await?;
+        let mut removed = Vec::new();
+        for generation in all.iter().rev().skip(keep.max(1)) {
+            if Some(*generation) == current {
+                continue;
+            }
+            fs::remove_dir_all(self.directory(*generation)).await?;
+            removed.push(*generation);
+        }
+        removed.reverse();
+        Ok(removed)
+    }
+}
+
+/// The live generation under `directory` if it holds a `CURRENT` pointer,
+/// otherwise `directory` itself.
+pub async fn resolve(directory: &str) -> Result<String, Error> {
+    let generations = Generations::new(directory);
+    Ok(match generations.current().await? {
+        Some(v) => generations.directory(v),
+        None => directory.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tst {
+    use std::io::Error;
+
+    use tokio::fs;
+
+    use crate::{
+        config::IndexerConfig,
+        indexed::{Dictionary, IndexMerger, IndexParser, IndexedBuilder},
+        parser::ParseController,
+        segment::CommonSegments,
+        watcher::IndexWatcher,
+        zones::ZoneSet,
+    };
+
+    use super::{resolve, Generations};
+
+    #[tokio::test]
+    async fn readers_survive_the_flip() -> Result<(), Error> {
+        let root = std::env::temp_dir().join(format!("generation_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root).await;
+        fs::create_dir_all(&root).await?;
+        let parent = root.join("res").to_str().unwrap().to_string();
+        let config = IndexerConfig::new(1000, 6)?;
+        let build = |text: &'static str| {
+            let path = root.join(format!("{text}.xml")).to_str().unwrap().to_string();
+            let parent = parent.clone();
+            let buffer = root.join("buffer").to_str().unwrap().to_string();
+            async move {
+                fs::write(&path, format!("<title>\nshared\n</title>\n<text>\n{text}\n</text>\n")).await?;
+                ParseController::<IndexParser, _, _>::new(
+                    vec![path],
+                    parent,
+                    buffer,
+                    1,
+                    IndexedBuilder::new(config, ZoneSet::new(["title", "text"])?)?,
+                    IndexMerger::new(config.merger()),
+                )
+                .with_generations(2)
+                .create_dictionary()
+                .await
+            }
+        };
+        let generations = Generations::new(parent.clone());
+        assert!(Dictionary::<CommonSegments>::open_current(&parent).await.is_err());
+
+        build("first").await?;
+        assert_eq!(generations.current().await?, Some(1));
+        let mut before = Dictionary::<CommonSegments>::open_current(&parent).await?;
+        let mut watcher = IndexWatcher::<CommonSegments>::follow_current(parent.clone(), 1).await?;
+        assert!(before.find("first").await?.is_some());
+
+        build("second").await?;
+        assert_eq!(generations.current().await?, Some(2));
+        assert_eq!(resolve(&parent).await?, generations.directory(2));
+        assert!(before.find("first").await?.is_some());
+        assert!(before.find("second").await?.is_none());
+        let mut after = Dictionary::<CommonSegments>::open_current(&parent).await?;
+        assert!(after.find("second").await?.is_some());
+        assert!(after.find("first").await?.is_none());
+
+        assert!(watcher.poll().await?);
+        assert!(watcher.current().reader().await.find("second").await?.is_some());
+        assert!(!watcher.poll().await?);
+
+        build("third").await?;
+        assert_eq!(generations.list().await?, [2, 3]);
+        assert!(before.find("shared").await?.is_some());
+        assert!(after.find("second").await?.is_some());
+
+        fs::write(format!("{parent}/CURRENT"), "elsewhere").await?;
+        assert!(generations.current().await.is_err());
+        assert_eq!(resolve(&root.join("buffer").to_str().unwrap()).await?, root.join("buffer").to_str().unwrap());
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
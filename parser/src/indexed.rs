@@ -1,17 +1,22 @@
 use std::future::Future;
+use std::{
+    fmt::{Debug, Display},
+    io::{Error, ErrorKind, SeekFrom},
+    marker::{PhantomData, Send},
+    ops::Range,
+    sync::Arc,
+};
+#[cfg(feature = "build")]
 use std::{
     cmp::Reverse,
     collections::{BTreeMap, BinaryHeap},
-    fmt::Debug,
-    io::{Error, SeekFrom},
-    marker::{PhantomData, Send},
     mem::size_of,
-    sync::Arc,
+    path::Path,
 };
 
 use async_trait::async_trait;
+#[cfg(feature = "build")]
 use chrono::Local;
-use futures::future::join_all;
 use modular_bitfield::{
     bitfield,
     prelude::{B1, B6},
@@ -19,25 +24,87 @@ use modular_bitfield::{
 };
 use tokio::{
     fs::{self, File},
-    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader, BufWriter},
-    sync::Mutex,
-    task::{self, JoinHandle},
+    io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter},
 };
+#[cfg(feature = "build")]
+use tokio::sync::Mutex;
 
 use mcr::VariableSaveD;
 use save::save::VariableSave;
-use save::u8::{read_char_reader, CommU8Provider};
-use save::writer::{variable_load, CountedWriter, variable_save_usize};
+use save::u8::read_char_reader;
+use save::writer::variable_load;
+#[cfg(feature = "build")]
+use save::{
+    u8::{CommU8Provider, FileU8Provider, HashingU8Provider, ReadRate, SyncU8Provider, ThrottledU8Provider},
+    writer::{variable_encode_u64, CountedWriter},
+};
+#[cfg(feature = "build")]
+use serde_json::{json, Value};
+#[cfg(feature = "build")]
+use smol_str::SmolStr;
 
-use crate::parser::IndexPositions;
-use crate::reader::ReaderResult;
-use crate::rep_reader::ZoneRepeatedReader;
+use crate::block_cache::{BlockCache, LexicalBlock, Lookup};
+use crate::block_dir::{BlockDirectory, BlockRange};
+use crate::bounds::TermBounds;
+use crate::generation::Generations;
+use crate::layout::IndexLayout;
+use crate::metadata::IndexMetadata;
+use crate::permuterm::{matches, rotation_key};
+use crate::numeric::NumericValues;
+use crate::phonetic::PhoneticIndex;
+#[cfg(feature = "fst")]
+use crate::term_fst::TermFst;
+#[cfg(feature = "roaring")]
+use crate::bitmaps::PostingBitmaps;
+#[cfg(all(feature = "roaring", feature = "build"))]
+use crate::bitmaps::write_bitmaps;
+#[cfg(feature = "roaring")]
+use roaring::RoaringBitmap;
+use crate::term_ord::{bytes_cmp, term_cmp};
+#[cfg(all(feature = "fst", feature = "build"))]
+use crate::term_fst::TermFstBuilder;
+#[cfg(feature = "build")]
+use crate::{
+    buffer_meta::{check_buffers, BufferMeta},
+    cancel::{is_interrupted, CancellationToken},
+    forward::write_forward,
+    config::{IndexerConfig, MergerConfig, OutputFormat},
+    filter::{FilterPatterns, TermFilter},
+    open_files,
+    parser::IndexPositions,
+    paths,
+    permuterm::Rotations,
+    postings::{self, RawBlock, SPILL_BUDGET, SPILL_FILE},
+    provenance::fingerprint,
+    rank::{TfPolicy, TopTerms},
+    reader::ReaderResult,
+    report::FileReport,
+    rep_reader::ZoneRepeatedReader,
+    stats::{IndexStats, SectionBytes},
+    term_ord::TermOrd,
+    text_sink::TextTermSink,
+    token_stream::{is_token_stream, ChunkReader, TokenStreamReader},
+    warnings::Warnings,
+    zones::{ZoneId, ZoneSet},
+};
+#[cfg(test)]
+use crate::testsupport::{scratch, CorpusSpec};
 use crate::{
+    config::IoTuning,
     listmap::SortedLinkedMap,
-    parser::{remove_buffer, Merger, Parser, ParserBuilder, ParserCallback, Term, TermProvider},
-    reader::{CommCharInterpreter, Reader},
-    rep_reader::RepeatedXmlReader,
-    segment::{CommonSegmentSelector, CommonSegments, SegmentSelector, Segments},
+    segment::Segments,
+};
+#[cfg(feature = "build")]
+use crate::{
+    analyzer::{Analyzer, AnalyzerConfig},
+    case::fold_case,
+    segment::{CommonSegmentSelector, CommonSegments, SegmentSelector},
+};
+#[cfg(feature = "build")]
+use crate::{
+    parser::{merge_terms, remove_buffer, Merger, Parser, ParserBuilder, ParserCallback, Term, TermProvider, TermSink},
+    reader::{CaseKeepingInterpreter, CommCharInterpreter, Reader},
+    rep_reader::{is_zone_mismatch, reads_tag, RepeatedXmlReader},
 };
 
 #[derive(Debug)]
@@ -49,7 +116,7 @@ pub struct IndexedTerm<S: Segments> {
 
 impl<S: Segments> Ord for IndexedTerm<S> {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.term.cmp(&other.term)
+        term_cmp(&self.term, &other.term)
     }
 }
 
@@ -77,83 +144,318 @@ impl<S: Segments> IndexedTerm<S> {
     }
 }
 
-impl<S: Segments> Term for IndexedTerm<S> {
-    fn combine(&mut self, other: Self) {
-        self.use_count += other.use_count;
-        self.indexes.or(other.indexes, |_, _| {});
+/// `term cf=12 df=3 [1:title+text, 7:text]`: collection and document
+/// frequency, then the zones of every posting.
+impl<S: Segments> Display for IndexedTerm<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} cf={} df={} [", self.term, self.use_count, self.indexes.len())?;
+        for (i, (document, usage)) in self.indexes.iter_ref().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{document}:{}", usage.segments)?;
+        }
+        write!(f, "]")
     }
+}
 
-    fn get_use_count(&self) -> u64 {
-        self.use_count
+/// Counts of a term that can't be right, caught before the term is written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TermCountError {
+    /// Adding `added` uses to the `use_count` of `term`, or of a posting of
+    /// it, passes the most the count holds.
+    Overflow { term: String, use_count: u64, added: u64 },
+    /// Every posting is at least one use, so `term` can't be used less
+    /// often than it has postings.
+    FewerUsesThanPostings { term: String, use_count: u64, postings: usize },
+}
+
+impl Display for TermCountError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TermCountError::Overflow { term, use_count, added } => {
+                write!(f, "use count of {term:?} overflows adding {added} to {use_count}")
+            }
+            TermCountError::FewerUsesThanPostings { term, use_count, postings } => {
+                write!(f, "{term:?} is used {use_count} times but has {postings} postings")
+            }
+        }
     }
 }
 
-pub struct IndexedTermSaver {}
+impl std::error::Error for TermCountError {}
 
-// #[async_trait]
-// impl TermSaver for IndexedTermSaver {
-//     type Provider = IndexTermProvider<CommU8Provider>;
-//     type Term = IndexedTerm;
+impl From<TermCountError> for Error {
+    fn from(e: TermCountError) -> Self {
+        Error::new(ErrorKind::InvalidData, e)
+    }
+}
 
-//     async fn save(
-//         writer: &String,
-//         unique: <<Self as TermSaver>::Provider as TermProvider>::Term,
-//     ) {
-//         async fn line(writer: &mut BufWriter<File>) {
-//             writer.write("\n".as_bytes()).await.unwrap();
-//         }
+#[cfg(feature = "build")]
+fn add_uses(term: &str, use_count: u64, added: u64) -> Result<u64, TermCountError> {
+    use_count.checked_add(added).ok_or_else(|| TermCountError::Overflow {
+        term: term.to_string(),
+        use_count,
+        added,
+    })
+}
 
-//         writer.write_all(unique.term.as_bytes()).await.unwrap();
-//         line(writer).await;
+fn check_uses(term: &str, use_count: u64, postings: usize) -> Result<(), TermCountError> {
+    if use_count < postings as u64 {
+        return Err(TermCountError::FewerUsesThanPostings {
+            term: term.to_string(),
+            use_count,
+            postings,
+        });
+    }
+    Ok(())
+}
 
-//         writer
-//             .write_all(unique.use_count.to_string().as_bytes())
-//             .await
-//             .unwrap();
-//         line(writer).await;
+#[cfg(feature = "build")]
+impl<S: Segments> Term for IndexedTerm<S> {
+    fn combine(&mut self, other: Self) -> Result<(), Error> {
+        self.use_count = add_uses(&self.term, self.use_count, other.use_count)?;
+        let term = &self.term;
+        self.indexes.or(other.indexes, |a, b| a.merge(term, b))?;
+        Ok(())
+    }
 
-//         writer
-//             .write(unique.indexes.len().to_string().as_bytes())
-//             .await
-//             .unwrap();
-//         line(writer).await;
+    fn get_use_count(&self) -> u64 {
+        self.use_count
+    }
+}
 
-//         for i in unique
-//             .indexes
-//             .iter()
-//             .collect::<Vec<usize>>()
-//             .into_iter()
-//             .rev()
-//         {
-//             writer.write(i.to_string().as_bytes()).await.unwrap();
-//             writer.write(" ".as_bytes()).await.unwrap();
-//         }
-//     }
+/// Key of a term in the tree of an [`IndexParser`]. Terms of up to 23
+/// bytes are kept inline, so most of them take no allocation of their own.
+#[cfg(feature = "build")]
+pub type TermKey = SmolStr;
 
-//     async fn provider(file: BufReader<File>) -> Self::Provider {
-//         IndexTermProvider::new(CommU8Provider::new(file)).await
-//     }
+/// A term in the tree of an [`IndexParser`]. Its text is only the key it is
+/// stored under, and goes back into an [`IndexedTerm`] when it leaves.
+#[cfg(feature = "build")]
+#[derive(Debug)]
+struct TreeTerm {
+    use_count: u64,
+    indexes: SortedLinkedMap<usize, UsageData<CommonSegments>>,
+}
 
-// }
+#[cfg(feature = "build")]
+impl TreeTerm {
+    fn into_term(self, term: String) -> IndexedTerm<CommonSegments> {
+        IndexedTerm {
+            term,
+            use_count: self.use_count,
+            indexes: self.indexes,
+        }
+    }
+}
 
+#[cfg(feature = "build")]
 pub struct IndexParser {
-    b_tree: BTreeMap<String, IndexedTerm<<IndexParser as Parser>::Segments>>,
+    b_tree: BTreeMap<TermKey, TreeTerm>,
     tree_max_size: usize,
     lexical_max_size: u8,
+    io: IoTuning,
     segment_selector: <IndexParser as Parser>::SegmentSelector,
+    skip_document: bool,
+    report: FileReport,
+    document_tokens: usize,
+    document_terms: Vec<TermKey>,
+    /// Zones left of a document cut off by a full tree.
+    zones_left: Option<usize>,
+    /// First result of the next document, read by [`Parser::next_document`].
+    peeked: Option<ReaderResult>,
+    /// A read error that fails the build, for [`Parser::take_error`].
+    failure: Option<Error>,
+    estimated_bytes: usize,
+    analyzer: Arc<Analyzer>,
+    tf: TfPolicy,
+    document_lengths: Vec<(usize, u32)>,
+    titles: Option<Vec<(usize, String)>>,
+    document_title: String,
+    numeric_values: Vec<(usize, u64)>,
+    /// Zones the document being parsed has a token in.
+    document_zones: Vec<ZoneId>,
+    zone_documents: BTreeMap<&'static str, usize>,
+    /// Tokens of the document being parsed within each zone.
+    zone_tokens: Vec<(ZoneId, u32)>,
+    zone_lengths: Vec<(usize, &'static str, u32)>,
+    /// Applier of every zone by id, and the id of `title`; taken from the
+    /// first reader, as every reader of a builder has the same zones.
+    appliers: Vec<fn(&mut CommonSegments)>,
+    title_zone: Option<ZoneId>,
+    /// What [`Self::flush_to`] records of the buffers it writes: the
+    /// fingerprint of the builder, the zones of the first reader and the
+    /// ids parsed since the last flush.
+    fingerprint: String,
+    zones: Option<ZoneSet>,
+    documents: Option<Range<usize>>,
+}
+
+/// Memory taken by one posting node of a term in the tree.
+#[cfg(feature = "build")]
+const POSTING_BYTES: usize =
+    size_of::<usize>() + size_of::<UsageData<CommonSegments>>() + size_of::<usize>();
+
+/// Memory a term of a full tree takes, guessed: its entry and a few
+/// postings.
+#[cfg(feature = "build")]
+const TREE_TERM_BYTES: usize = size_of::<TermKey>() + size_of::<TreeTerm>() + 4 * POSTING_BYTES;
+
+/// Memory taken by a term entry in the tree, without its postings.
+#[cfg(feature = "build")]
+fn term_bytes(key: &TermKey) -> usize {
+    let heap = if key.is_heap_allocated() { key.len() } else { 0 };
+    heap + size_of::<TermKey>() + size_of::<TreeTerm>()
 }
 
+#[cfg(feature = "build")]
 impl IndexParser {
-    pub fn new(
-        tree_max_size: usize,
-        lexical_max_size: u8,
-        segment_selector: <IndexParser as Parser>::SegmentSelector,
-    ) -> Self {
+    pub fn new(config: IndexerConfig, segment_selector: <IndexParser as Parser>::SegmentSelector) -> Self {
         Self {
             b_tree: BTreeMap::new(),
-            tree_max_size,
-            lexical_max_size,
+            tree_max_size: config.tree_max_terms(),
+            lexical_max_size: config.lexical_block_size(),
+            io: config.io(),
             segment_selector,
+            skip_document: false,
+            report: FileReport::default(),
+            document_tokens: 0,
+            document_terms: vec![],
+            zones_left: None,
+            peeked: None,
+            failure: None,
+            estimated_bytes: 0,
+            analyzer: Arc::new(Analyzer::default()),
+            tf: TfPolicy::default(),
+            document_lengths: vec![],
+            titles: None,
+            numeric_values: vec![],
+            document_title: String::new(),
+            document_zones: vec![],
+            zone_documents: BTreeMap::new(),
+            zone_tokens: vec![],
+            zone_lengths: vec![],
+            appliers: vec![],
+            title_zone: None,
+            fingerprint: String::new(),
+            zones: None,
+            documents: None,
+        }
+    }
+
+    /// Counts an occurrence of `word` in document `ind`.
+    fn insert(&mut self, word: String, ind: usize, applier: fn(&mut CommonSegments)) {
+        match self.b_tree.get_mut(word.as_str()) {
+            Some(term) => {
+                let counted = std::cell::Cell::new(true);
+                term.indexes.push_or_apply(
+                    ind,
+                    || {
+                        self.estimated_bytes += POSTING_BYTES;
+                        self.document_terms.push(TermKey::new(&word));
+                        let mut segment = CommonSegments::default();
+                        applier(&mut segment);
+                        UsageData {
+                            use_count: 1,
+                            segments: segment,
+                        }
+                    },
+                    |v| {
+                        counted.set(self.tf.counts(v.use_count));
+                        if counted.get() {
+                            v.use_count += 1;
+                        }
+                        applier(&mut v.segments);
+                    },
+                );
+                if counted.get() {
+                    term.use_count += 1;
+                }
+            }
+            None => {
+                let key = TermKey::new(&word);
+                let mut term = TreeTerm {
+                    use_count: 0,
+                    indexes: SortedLinkedMap::new(),
+                };
+                let data = UsageData {
+                    use_count: 1,
+                    segments: {
+                        let mut segment = CommonSegments::default();
+                        applier(&mut segment);
+                        segment
+                    },
+                };
+                term.indexes.push(ind, data);
+                term.use_count += 1;
+                self.estimated_bytes += term_bytes(&key) + POSTING_BYTES;
+                self.document_terms.push(key.clone());
+                self.b_tree.insert(key, term);
+            }
+        }
+    }
+
+    fn title_word(&mut self, word: &str) {
+        if self.titles.is_some() {
+            if !self.document_title.is_empty() {
+                self.document_title.push(' ');
+            }
+            self.document_title.push_str(word);
+        }
+    }
+
+    /// Rewrites the counts of the document that just ended as the policy stores them.
+    fn store_counts(&mut self, ind: usize) {
+        if !self.tf.log_scaled {
+            return;
+        }
+        for word in self.document_terms.iter() {
+            let usage = self
+                .b_tree
+                .get_mut(word)
+                .and_then(|v| v.indexes.element_at_mut(ind));
+            if let Some(usage) = usage {
+                usage.use_count = self.tf.stored(usage.use_count);
+            }
+        }
+    }
+
+    /// Reports `e` as unreadable input, or keeps it to fail the build if
+    /// the chunk holds other zones.
+    fn read_failed(&mut self, e: Error) {
+        if is_zone_mismatch(&e) {
+            self.failure = Some(e);
+        } else {
+            self.report.parse_warnings.push(format!("unreadable input: {e}"));
+        }
+    }
+
+    /// Removes the postings of a malformed document. Whatever was already
+    /// flushed to a buffer stays there.
+    fn drop_document(&mut self, ind: usize) {
+        for word in std::mem::take(&mut self.document_terms) {
+            if let Some(term) = self.b_tree.get_mut(&word) {
+                if let Some(usage) = term.indexes.remove(ind) {
+                    term.use_count = term.use_count.saturating_sub(usage.use_count as u64);
+                    self.estimated_bytes -= POSTING_BYTES;
+                }
+                if term.indexes.len() == 0 {
+                    self.b_tree.remove(&word);
+                    self.estimated_bytes -= term_bytes(&word);
+                }
+            }
+        }
+        self.document_tokens = 0;
+        self.document_title.clear();
+        self.document_zones.clear();
+        self.zone_tokens.clear();
+    }
+
+    fn count_zone_token(&mut self, zone: ZoneId) {
+        match self.zone_tokens.iter_mut().find(|v| v.0 == zone) {
+            Some(v) => v.1 += 1,
+            None => self.zone_tokens.push((zone, 1)),
         }
     }
 }
@@ -164,14 +466,35 @@ pub struct UsageData<S: Segments> {
     segments: S,
 }
 
+/// `title+text tf=2`.
+impl<S: Segments> Display for UsageData<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} tf={}", self.segments, self.use_count)
+    }
+}
+
+impl<S: Segments> Default for UsageData<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<S: Segments> UsageData<S> {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Self {
             use_count: 0,
             segments: S::default(),
         }
     }
 
+    pub fn use_count(&self) -> usize {
+        self.use_count
+    }
+
+    pub fn segments(&self) -> &S {
+        &self.segments
+    }
+
     /// Get a mutable reference to the word usage's use count.
     pub fn use_count_mut(&mut self) -> &mut usize {
         &mut self.use_count
@@ -181,86 +504,196 @@ impl<S: Segments> UsageData<S> {
     pub fn segments_mut(&mut self) -> &mut S {
         &mut self.segments
     }
+
+    /// Takes in `other`, the use of `term` in the same document read
+    /// apart: its uses are added and its zones set.
+    pub fn merge(&mut self, term: &str, other: &Self) -> Result<(), TermCountError> {
+        let overflow = || TermCountError::Overflow {
+            term: term.to_string(),
+            use_count: self.use_count as u64,
+            added: other.use_count as u64,
+        };
+        self.use_count = self.use_count.checked_add(other.use_count).ok_or_else(overflow)?;
+        self.segments.union(&other.segments);
+        Ok(())
+    }
 }
 
+#[cfg(feature = "build")]
 #[async_trait]
 impl Parser for IndexParser {
     type Term = IndexedTerm<Self::Segments>;
-    type Reader = RepeatedXmlReader<CommU8Provider, CommCharInterpreter>;
+    type Reader = ChunkReader<ThrottledU8Provider<HashingU8Provider<FileU8Provider>>, CaseKeepingInterpreter>;
     type Provider = IndexTermProvider<Self::Segments>;
     type Segments = CommonSegments;
     type SegmentSelector = CommonSegmentSelector;
 
     async fn parse(&mut self, reader: &mut Self::Reader, ind: usize) -> ParserCallback {
-        // while self.b_tree.len() < self.tree_max_size {
-        //     let word = reader.next_word().await;
-
-        //     match word {
-        //         Some(word) => match self.b_tree.get_mut(&word) {
-        //             Some(term) => {
-        //                 term.indexes.push(ind);
-        //                 term.use_count += 1;
-        //             }
-        //             None => {
-        //                 let mut term = IndexedTerm::new(word.clone());
-        //                 term.indexes.push(ind);
-        //                 term.use_count += 1;
-        //                 self.b_tree.insert(word, term);
-        //             }
-        //         },
-        //         None => {
-        //             return ParserCallback::FileEnd;
-        //         }
-        //     }
-        // }
-        // ParserCallback::Full
-        let mut current_index = reader.zones_len();
-        let mut current_applier = self.segment_selector.applier_for(reader.zone());
-        // let mut current_applier =
+        // A document cut off by a full tree goes on from the zone it stopped
+        // in, under the same id.
+        let mut current_index = self.zones_left.take().unwrap_or_else(|| reader.zones_len());
+        if self.appliers.is_empty() {
+            let zones = reader.zone_set();
+            self.appliers = zones.iter().map(|v| self.segment_selector.applier_for(v)).collect();
+            self.title_zone = zones.id_of("title");
+            self.zones = Some(zones.clone());
+        }
+        self.documents = Some(match self.documents.take() {
+            Some(v) => v.start.min(ind)..v.end.max(ind + 1),
+            None => ind..ind + 1,
+        });
+        let mut current_applier = self.appliers[reader.zone_id().index()];
+        let mut in_title = Some(reader.zone_id()) == self.title_zone;
         while self.b_tree.len() < self.tree_max_size && current_index > 0 {
-            match reader.next_word().await {
-                None => break,
-                Some(v) => match v {
-                    ReaderResult::Word(word) => match self.b_tree.get_mut(&word) {
-                        Some(term) => term.indexes.push_or_apply(
-                            ind,
-                            || {
-                                let mut segment = CommonSegments::default();
-                                current_applier(&mut segment);
-                                UsageData {
-                                    use_count: 1,
-                                    segments: segment,
+            let next = match self.peeked.take() {
+                Some(v) => Ok(Some(v)),
+                None => reader.next_word().await,
+            };
+            match next {
+                Ok(None) => break,
+                Err(e) => {
+                    log::error!("{} while parsing document {}", e, ind);
+                    self.drop_document(ind);
+                    self.read_failed(e);
+                    break;
+                }
+                Ok(Some(v)) => match v {
+                    ReaderResult::Word(_) if self.skip_document => {
+                        self.document_tokens += 1;
+                        self.count_zone_token(reader.zone_id());
+                    }
+                    ReaderResult::Word(word) => {
+                        self.document_tokens += 1;
+                        let zone = reader.zone_id();
+                        self.count_zone_token(zone);
+                        if !self.document_zones.contains(&zone) {
+                            self.document_zones.push(zone);
+                        }
+                        if in_title {
+                            self.title_word(&fold_case(&word));
+                        }
+                        match self.analyzer.analyze(word) {
+                            None => self.report.dropped_terms += 1,
+                            Some(analyzed) => {
+                                self.insert(analyzed.term, ind, current_applier);
+                                if let Some(original) = analyzed.original {
+                                    self.insert(original, ind, current_applier);
                                 }
-                            },
-                            |v| {
-                                v.use_count += 1;
-                                current_applier(&mut v.segments);
-                            },
-                        ),
-                        None => {
-                            let mut term = IndexedTerm::new(word.clone());
-                            let data = UsageData {
-                                use_count: 1,
-                                segments: {
-                                    let mut segment = CommonSegments::default();
-                                    current_applier(&mut segment);
-                                    segment
-                                },
-                            };
-                            term.indexes.push(ind, data);
-                            term.use_count += 1;
-                            self.b_tree.insert(word, term);
+                            }
                         }
-                    },
+                    }
                     ReaderResult::AttributeEnd => {
                         reader.transform_zone().await;
                         current_index -= 1;
-                        current_applier = self.segment_selector.applier_for(reader.zone())
+                        current_applier = self.appliers[reader.zone_id().index()];
+                        in_title = Some(reader.zone_id()) == self.title_zone;
+                        if current_index == 0 {
+                            let tokens = std::mem::take(&mut self.document_tokens);
+                            self.report.documents += 1;
+                            self.report.tokens += tokens;
+                            self.document_lengths.push((ind, tokens as u32));
+                            for (zone, tokens) in self.zone_tokens.drain(..) {
+                                self.zone_lengths.push((ind, reader.zone_set().name(zone), tokens));
+                            }
+                            self.store_counts(ind);
+                            self.document_terms.clear();
+                            for zone in self.document_zones.drain(..) {
+                                *self.zone_documents.entry(reader.zone_set().name(zone)).or_default() += 1;
+                            }
+                            let title = std::mem::take(&mut self.document_title);
+                            if let Some(titles) = &mut self.titles {
+                                titles.push((ind, title));
+                            }
+                            if let Some(v) = reader.take_numeric() {
+                                self.numeric_values.push((ind, v));
+                            }
+                        }
+                    }
+                    ReaderResult::Malformed(warning) => {
+                        self.drop_document(ind);
+                        self.report.skipped_docs += 1;
+                        self.report.parse_warnings.push(warning);
+                        return ParserCallback::ZoneEnd;
                     }
                 },
             }
         }
-        // todo!()
+        if current_index == 0 {
+            return ParserCallback::ZoneEnd;
+        }
+        if self.b_tree.len() >= self.tree_max_size {
+            self.zones_left = Some(current_index);
+            return ParserCallback::Full;
+        }
+        ParserCallback::FileEnd
+    }
+
+    async fn next_document(&mut self, reader: &mut Self::Reader) -> bool {
+        if self.peeked.is_some() {
+            return true;
+        }
+        match reader.next_word().await {
+            Ok(None) => false,
+            Ok(Some(v)) => {
+                self.peeked = Some(v);
+                true
+            }
+            Err(e) => {
+                log::error!("{} while reading the next document", e);
+                self.read_failed(e);
+                false
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.b_tree.len()
+    }
+
+    fn estimated_bytes(&self) -> usize {
+        self.estimated_bytes
+    }
+
+    fn include_document(&mut self, included: bool) {
+        self.skip_document = !included;
+    }
+
+    fn take_error(&mut self) -> Option<Error> {
+        self.failure.take()
+    }
+
+    fn take_report(&mut self, path: String) -> FileReport {
+        FileReport {
+            path,
+            ..std::mem::take(&mut self.report)
+        }
+    }
+
+    fn take_document_lengths(&mut self) -> Vec<(usize, u32)> {
+        std::mem::take(&mut self.document_lengths)
+    }
+
+    fn take_zone_documents(&mut self) -> BTreeMap<String, usize> {
+        std::mem::take(&mut self.zone_documents)
+            .into_iter()
+            .map(|(zone, count)| (zone.to_string(), count))
+            .collect()
+    }
+
+    fn take_zone_lengths(&mut self) -> Vec<(usize, &'static str, u32)> {
+        std::mem::take(&mut self.zone_lengths)
+    }
+
+    fn record_titles(&mut self) {
+        self.titles.get_or_insert_with(Vec::new);
+    }
+
+    fn take_titles(&mut self) -> Vec<(usize, String)> {
+        self.titles.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    fn take_numeric_values(&mut self) -> Vec<(usize, u64)> {
+        std::mem::take(&mut self.numeric_values)
     }
 
     async fn provider_from_file(file: &String) -> Result<Self::Provider, Error> {
@@ -272,26 +705,344 @@ impl Parser for IndexParser {
             Ok(_) => {}
             Err(_) => {}
         }
-        let mut merger = IndexMergeSaver::new(file.clone(), self.lexical_max_size).await?;
+        let mut merger =
+            IndexMergeSaver::create(file.clone(), self.lexical_max_size, IndexLayout::default(), self.io).await?;
         let tree = std::mem::replace(&mut self.b_tree, BTreeMap::new());
-        for v in tree.into_iter() {
-            merger.push(v.1).await?;
+        let terms = tree.len();
+        self.estimated_bytes = 0;
+        // Postings of an unfinished document leave with the tree.
+        self.document_terms.clear();
+        for (term, v) in tree.into_iter() {
+            merger.push(v.into_term(term.into())).await?;
         }
         merger.finish().await?;
-        Ok(())
+        let documents = self.documents.take().unwrap_or(0..0);
+        BufferMeta::new(self.fingerprint.clone(), self.zones.clone(), documents, terms)
+            .save(file)
+            .await
+    }
+
+    fn keep_all(&mut self) {
+        self.tree_max_size = usize::MAX;
+    }
+
+    fn take_terms(&mut self) -> BTreeMap<String, Self::Term> {
+        self.estimated_bytes = 0;
+        self.document_terms.clear();
+        std::mem::take(&mut self.b_tree)
+            .into_iter()
+            .map(|(term, v)| (term.to_string(), v.into_term(term.into())))
+            .collect()
     }
 }
 
+#[cfg(feature = "build")]
 pub struct IndexMerger {
     lexical_max_size: u8,
+    phonetic: bool,
+    permuterm: bool,
+    idf_top: Option<usize>,
+    max_shared_prefix: usize,
+    top_terms: Vec<(String, usize)>,
+    score_bounds: bool,
+    fst: bool,
+    bitmap_min_df: Option<usize>,
+    layout: IndexLayout,
+    fan_in: Option<usize>,
+    open_files_limit: Option<usize>,
+    spill_budget: u64,
+    force: bool,
+    forward: bool,
+    format: OutputFormat,
+    io: IoTuning,
 }
 
+#[cfg(feature = "build")]
 impl IndexMerger {
-    pub fn new(lexical_max_size: u8) -> Self {
-        Self { lexical_max_size }
+    /// Takes the [`IndexerConfig::merger`] of the config the parsers were built with.
+    pub fn new(config: MergerConfig) -> Self {
+        Self {
+            lexical_max_size: config.lexical_block_size(),
+            phonetic: false,
+            permuterm: false,
+            idf_top: None,
+            max_shared_prefix: MAX_SHARED_PREFIX,
+            top_terms: Vec::new(),
+            score_bounds: false,
+            fst: false,
+            bitmap_min_df: None,
+            layout: IndexLayout::default(),
+            fan_in: None,
+            open_files_limit: None,
+            spill_budget: SPILL_BUDGET,
+            force: false,
+            forward: false,
+            format: config.format(),
+            io: config.io(),
+        }
+    }
+
+    /// Write the index with the file names of `layout`.
+    pub fn with_layout(mut self, layout: IndexLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Also write `phonetic_part.txt` for [`Dictionary::phonetic`].
+    pub fn with_phonetic(mut self) -> Self {
+        self.phonetic = true;
+        self
+    }
+
+    /// Also write the rotation dictionary used by [`Dictionary::wildcard`].
+    pub fn with_permuterm(mut self) -> Self {
+        self.permuterm = true;
+        self
+    }
+
+    /// Also precompute idf of the `k` terms found in the most documents, see [`crate::rank::IdfTable`].
+    pub fn with_idf_top(mut self, k: usize) -> Self {
+        self.idf_top = Some(k);
+        self
+    }
+
+    /// Let the terms of a lexical block share a prefix of at most `bytes`
+    /// instead of [`MAX_SHARED_PREFIX`].
+    pub fn with_max_shared_prefix(mut self, bytes: usize) -> Self {
+        self.max_shared_prefix = bytes;
+        self
+    }
+
+    /// Also write the highest count of every term in a document, see
+    /// [`TermBounds`], which [`crate::rank::Scorer::search_top`] prunes by.
+    pub fn with_score_bounds(mut self) -> Self {
+        self.score_bounds = true;
+        self
+    }
+
+    /// Also write `terms.fst`, used by [`Dictionary::find`],
+    /// [`Dictionary::terms_with_prefix`] and [`Dictionary::fuzzy`].
+    #[cfg(feature = "fst")]
+    pub fn with_fst(mut self) -> Self {
+        self.fst = true;
+        self
+    }
+
+    /// Also write a roaring bitmap of the documents of every term found in
+    /// at least `min_df` of them, see [`PostingBitmaps`].
+    #[cfg(feature = "roaring")]
+    pub fn with_bitmaps(mut self, min_df: usize) -> Self {
+        self.bitmap_min_df = Some(min_df);
+        self
+    }
+
+    /// Also write the terms of every document, see [`crate::forward::ForwardIndex`].
+    pub fn with_forward_index(mut self) -> Self {
+        self.forward = true;
+        self
+    }
+
+    /// Open at most `n` buffers at once. More buffers are first merged in
+    /// groups of `n` into larger ones, round after round. The fan-in never
+    /// goes above what the open files limit allows, see [`crate::open_files`].
+    pub fn with_fan_in(mut self, n: usize) -> Self {
+        self.fan_in = Some(n);
+        self
+    }
+
+    /// Take `limit` as the open files limit instead of asking the system.
+    pub fn with_open_files_limit(mut self, limit: usize) -> Self {
+        self.open_files_limit = Some(limit);
+        self
+    }
+
+    /// Spill a term whose postings to combine take more than `bytes`
+    /// instead of [`SPILL_BUDGET`], see [`postings::merge_runs`].
+    pub fn with_spill_budget(mut self, bytes: u64) -> Self {
+        self.spill_budget = bytes.max(1);
+        self
+    }
+
+    /// Merge buffers even if their [`BufferMeta`] fingerprints differ.
+    pub fn with_force(mut self) -> Self {
+        self.force = true;
+        self
+    }
+
+    fn fan_in(&self) -> usize {
+        let allowed = open_files::fan_in_for(self.open_files_limit.unwrap_or_else(open_files::open_files_limit));
+        self.fan_in.map_or(allowed, |v| v.clamp(2, allowed))
+    }
+
+    /// Merges the buffers in groups of the fan-in into larger ones next to
+    /// them, until a single pass can open all that are left, and returns
+    /// those. Buffers made here are added to `buffer_files` and removed as
+    /// soon as they are merged further.
+    async fn reduce_buffers(
+        &self,
+        buffer_files: &Arc<Mutex<Vec<String>>>,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>, Error> {
+        let fan_in = self.fan_in();
+        let mut buffers = buffer_files.lock().await.clone();
+        let mut made = Vec::<String>::new();
+        let mut round = 0;
+        while buffers.len() > fan_in {
+            log::info!("Merge round {} over {} buffers, {} at a time", round, buffers.len(), fan_in);
+            let mut next = Vec::with_capacity(buffers.len() / fan_in + 1);
+            for group in buffers.chunks(fan_in) {
+                if let [single] = group {
+                    next.push(single.clone());
+                    continue;
+                }
+                let directory = format!("{}.{}", group[0], round);
+                fs::create_dir_all(&directory).await?;
+                buffer_files.lock().await.push(directory.clone());
+                made.push(directory.clone());
+                let mut providers = open_buffers(group, fan_in, self.io).await?;
+                let mut saver = IndexMergeSaver::create(directory.clone(), self.lexical_max_size, IndexLayout::default(), self.io)
+                    .await?
+                    .with_spill_budget(self.spill_budget);
+                merge_providers(&mut providers, &mut saver, cancel).await?;
+                saver.finish().await?;
+                drop(providers);
+                for v in group.iter().filter(|v| made.contains(v)) {
+                    fs::remove_dir_all(v).await?;
+                }
+                buffer_files.lock().await.retain(|v| !group.contains(v) || !made.contains(v));
+                next.push(directory);
+            }
+            buffers = next;
+            round += 1;
+        }
+        Ok(buffers)
+    }
+
+    async fn saver(&self, destination: &str) -> Result<IndexMergeSaver<CommonSegments>, Error> {
+        let mut saver =
+            IndexMergeSaver::create(destination.to_string(), self.lexical_max_size, self.layout, self.io)
+                .await?
+                .with_max_shared_prefix(self.max_shared_prefix)
+                .with_spill_budget(self.spill_budget);
+        if self.phonetic {
+            saver = saver.with_phonetic();
+        }
+        if self.permuterm {
+            saver = saver.with_permuterm();
+        }
+        if let Some(k) = self.idf_top {
+            saver = saver.with_top_terms(k);
+        }
+        if self.score_bounds {
+            saver = saver.with_score_bounds();
+        }
+        #[cfg(feature = "fst")]
+        if self.fst {
+            saver = saver.with_fst();
+        }
+        Ok(saver)
+    }
+
+    /// Writes what follows the last term: the saver's own files, the stats,
+    /// the rotations and `info`.
+    async fn finish(
+        &mut self,
+        saver: IndexMergeSaver<CommonSegments>,
+        mut stats: IndexStats,
+        destination: &String,
+    ) -> Result<(), Error> {
+        async fn line(writer: &mut BufWriter<File>) -> Result<(), Error> {
+            writer.write_all(b"\n").await
+        }
+        let finished = saver.finish().await?;
+        stats.bytes = finished.bytes;
+        stats.save(destination).await?;
+        log::info!(
+            "merge done: destination={} terms={} postings={} bytes={}",
+            destination,
+            stats.vocabulary,
+            stats.postings,
+            stats.bytes.total()
+        );
+        if let Some(top) = finished.top_terms {
+            self.top_terms = top.into_sorted();
+        }
+        if let Some(rotations) = finished.permuterm {
+            rotations
+                .save::<CommonSegments>(destination, self.lexical_max_size, self.layout, self.io)
+                .await?;
+        }
+
+        let mut info_writer = self.io.writer(File::create(self.layout.info(destination)).await?);
+        info_writer.write_all(stats.occurrences.to_string().as_bytes()).await?;
+        line(&mut info_writer).await?;
+
+        info_writer.write_all(stats.vocabulary.to_string().as_bytes()).await?;
+        line(&mut info_writer).await?;
+        info_writer.flush().await?;
+
+        #[cfg(feature = "roaring")]
+        if let Some(min_df) = self.bitmap_min_df {
+            let written = write_bitmaps::<CommonSegments>(destination, min_df).await?;
+            log::info!("Bitmaps written for {written} terms in {min_df} documents or more");
+        }
+        if self.forward {
+            let written = write_forward::<CommonSegments>(destination).await?;
+            log::info!("Forward index written for {written} documents");
+        }
+        Ok(())
+    }
+}
+
+/// Creates `destination` unless it is there; tells whether it did.
+#[cfg(feature = "build")]
+async fn create_destination(destination: &String) -> bool {
+    match fs::create_dir(destination).await {
+        Ok(_) => {
+            log::info!("Directory created for parser");
+            true
+        }
+        Err(w) => {
+            log::info!("{}", w);
+            false
+        }
+    }
+}
+
+/// Opens a provider over each of `buffers`. Running out of file handles
+/// becomes [`open_files::TooManyOpenFiles`].
+#[cfg(feature = "build")]
+async fn open_buffers(
+    buffers: &[String],
+    fan_in: usize,
+    io: IoTuning,
+) -> Result<Vec<<IndexParser as Parser>::Provider>, Error> {
+    let mut providers = Vec::with_capacity(buffers.len());
+    for v in buffers.iter() {
+        let provider = IndexTermProvider::new_with_io(v, io)
+            .await
+            .map_err(|e| open_files::explain(e, buffers.len(), fan_in))?;
+        providers.push(provider);
+    }
+    Ok(providers)
+}
+
+/// After an interrupted merge: removes the buffers and, if the merge made
+/// it, the destination.
+#[cfg(feature = "build")]
+async fn discard_merge(
+    buffer_files: &Arc<Mutex<Vec<String>>>,
+    destination: &String,
+    created: bool,
+) -> Result<(), Error> {
+    remove_buffer(buffer_files).await;
+    if created {
+        fs::remove_dir_all(destination).await?;
     }
+    Ok(())
 }
 
+#[cfg(feature = "build")]
 #[async_trait]
 impl Merger for IndexMerger {
     type Parser = IndexParser;
@@ -301,626 +1052,4611 @@ impl Merger for IndexMerger {
         input_file: Arc<Mutex<IndexPositions>>,
         buffer_files: Arc<Mutex<Vec<String>>>,
         destination: String,
+        cancel: &CancellationToken,
     ) -> Result<(), Error> {
-        match fs::create_dir(destination.clone()).await {
-            Ok(_) => {
-                log::info!("Directory created for parser");
-            }
-            Err(w) => {
-                log::info!("{}", w);
-            }
+        if !self.force {
+            check_buffers(&buffer_files.lock().await).await?;
         }
-
+        let created = create_destination(&destination).await;
         log::info!(
             "Merge starts at {}",
             Local::now().format("%H:%M:%S").to_string()
         );
 
-        write_input_files(format!("{}\\files.txt", destination.clone()), input_file).await;
-
-        let mut providers = Vec::<Arc<Mutex<<IndexParser as Parser>::Provider>>>::new();
-        let mut tasks = Vec::<JoinHandle<()>>::new();
-        async fn line(writer: &mut BufWriter<File>) {
-            writer.write("\n".as_bytes()).await.unwrap();
-        }
-        for v in buffer_files.lock().await.iter() {
-            providers.push(Arc::new(Mutex::new(
-                IndexParser::provider_from_file(&v).await?,
-            )));
-        }
-
-        let p_q = Arc::new(Mutex::new(BinaryHeap::<(
-            Reverse<<IndexParser as Parser>::Term>,
-            usize,
-        )>::new()));
-
-        // dbg!("Nya");
-
-        for (v, i) in providers.iter_mut().enumerate() {
-            if let Some(term) = i.lock().await.next_term().await {
-                p_q.lock().await.push((Reverse(term), v));
-            }
-        }
-
-        // let mut writer = BufWriter::with_capacity(
-        //     1024 * 1024 * 50,
-        //     File::create(format!("{}\\dictionary.txt", destination.clone()))
-        //         .await
-        //         .unwrap(),
-        // );
-
-        let mut saver = IndexMergeSaver::new(destination.clone(), self.lexical_max_size).await?;
-
-        let mut values = Vec::<usize>::new();
-        let mut lexeme_count = 0u64;
-        let mut term_count = 0u64;
-        let mut tstind = 0;
-        loop {
-            let mut q = p_q.lock().await;
-            if let Some(mut next) = q.pop() {
-                // dbg!("Hya");
-                values.push(next.1);
-                while let Some(v) = q.peek() {
-                    // dbg!("Kya");
-                    if v.0 == next.0 {
-                        let v = q.pop().unwrap();
-                        values.push(v.1);
-                        next.0 .0.combine(v.0 .0);
-                    } else {
-                        break;
-                    }
+        write_input_files(self.layout.files(&destination), input_file).await;
+
+        let buffers = match self.reduce_buffers(&buffer_files, cancel).await {
+            Ok(v) => v,
+            Err(e) => {
+                if is_interrupted(&e) {
+                    discard_merge(&buffer_files, &destination, created).await?;
                 }
-                // dbg!("Bya");
-                // dbg!("Hya");
-                drop(q);
-
-                // values.iter().map(|v| {
-                //     task::spawn(async move {
-                //         let provider = providers[*v].lock().await;
-                //     })
-                // });
-                for v in values.iter() {
-                    let v = *v;
-                    let provider = providers[v].clone();
-                    let p_q = p_q.clone();
-                    tasks.push(task::spawn(async move {
-                        let next = provider.lock().await.next_term().await;
-                        // dbg!(&next);
-                        if let Some(next) = next {
-                            p_q.lock().await.push((Reverse(next), v))
-                        }
-                        // if let Some(pr)
-                    }))
+                return Err(e);
+            }
+        };
+        let mut providers = open_buffers(&buffers, self.fan_in(), self.io).await?;
+        if self.format == OutputFormat::Text {
+            let sink = TextTermSink::create(&destination).await?;
+            if let Err(e) = merge_terms(&mut providers, sink, cancel).await {
+                if is_interrupted(&e) {
+                    drop(providers);
+                    discard_merge(&buffer_files, &destination, created).await?;
                 }
-                // dbg!("Hya");
-                join_all(tasks).await;
-                // dbg!("Hya");
-                tasks = Vec::new();
-
-                // for i in values.iter() {
-                //     let next = providers[*i].lock().await.next_term().await;
-                //     if let Some(provider) = next {
-                //         p_q.push((Reverse(provider), *i));
-                //     }
-                // }
-                lexeme_count += next.0 .0.get_use_count();
-                term_count += 1;
-                saver.push(next.0 .0).await?;
-                values.clear();
-                tstind += 1;
-                // if tstind % 1 == 0 {
-                //     dbg!(tstind);
-                // }
-            } else {
-                break;
+                return Err(e);
             }
+            remove_buffer(&buffer_files).await;
+            return Ok(());
         }
-        saver.finish().await?;
 
+        let mut saver = self.saver(&destination).await?;
+        let stats = match merge_providers(&mut providers, &mut saver, cancel).await {
+            Ok(v) => v,
+            Err(e) => {
+                if is_interrupted(&e) {
+                    drop((providers, saver));
+                    discard_merge(&buffer_files, &destination, created).await?;
+                }
+                return Err(e);
+            }
+        };
+        self.finish(saver, stats, &destination).await?;
         remove_buffer(&buffer_files).await;
+        Ok(())
+    }
 
-        let mut info_writer = BufWriter::new(
-            File::create(format!("{}\\info.txt", destination))
-                .await
-                .unwrap(),
+    async fn write_terms(
+        &mut self,
+        input_file: Arc<Mutex<IndexPositions>>,
+        terms: BTreeMap<String, <Self::Parser as Parser>::Term>,
+        destination: String,
+        cancel: &CancellationToken,
+    ) -> Result<(), Error> {
+        let created = create_destination(&destination).await;
+        log::info!(
+            "Writing {} terms from memory at {}",
+            terms.len(),
+            Local::now().format("%H:%M:%S")
         );
-        info_writer
-            .write_all(lexeme_count.to_string().as_bytes())
-            .await
-            .unwrap();
-        line(&mut info_writer).await;
 
-        info_writer
-            .write_all(term_count.to_string().as_bytes())
-            .await
-            .unwrap();
-        line(&mut info_writer).await;
-        info_writer.flush().await.unwrap();
-        Ok(())
+        write_input_files(self.layout.files(&destination), input_file).await;
+
+        if self.format == OutputFormat::Text {
+            let mut sink = TextTermSink::create(&destination).await?;
+            for term in terms.into_values() {
+                if let Err(e) = cancel.check() {
+                    drop(sink);
+                    if created {
+                        fs::remove_dir_all(&destination).await?;
+                    }
+                    return Err(e);
+                }
+                sink.push(term).await?;
+            }
+            return sink.finish().await;
+        }
+
+        let mut saver = self.saver(&destination).await?;
+        let stats = match save_terms(terms.into_values(), &mut saver, cancel).await {
+            Ok(v) => v,
+            Err(e) => {
+                if is_interrupted(&e) {
+                    drop(saver);
+                    if created {
+                        fs::remove_dir_all(&destination).await?;
+                    }
+                }
+                return Err(e);
+            }
+        };
+        self.finish(saver, stats, &destination).await
     }
-}
 
-async fn write_input_files(path: String, input_files: Arc<Mutex<IndexPositions>>) {
-    let input_files = input_files.lock().await;
-    let mut file = BufWriter::new(File::create(path).await.unwrap());
-    for (st, i) in input_files
-        .ids
-        .iter()
-        .map(|(i, v)| (&input_files.names[*i].0, *v))
-    {
-        file.write_all(st.as_bytes()).await.unwrap();
-        file.write_u64(i as u64).await.unwrap();
-        file.write_all(&[b'\n']).await.unwrap();
+    fn phonetic_index(&self) -> bool {
+        self.phonetic
     }
 
-    file.flush().await.unwrap();
-}
+    fn permuterm_index(&self) -> bool {
+        self.permuterm
+    }
 
-pub struct IndexedBuilder {
-    tree_max_size: usize,
-    lexical_max_size: u8,
-    attributes: Arc<Vec<String>>,
+    fn take_top_terms(&mut self) -> Vec<(String, usize)> {
+        std::mem::take(&mut self.top_terms)
+    }
+
+    fn fst_index(&self) -> bool {
+        self.fst
+    }
+
+    fn bitmap_min_df(&self) -> Option<usize> {
+        self.bitmap_min_df
+    }
+
+    fn settings(&self) -> Vec<(&'static str, Value)> {
+        vec![
+            ("lexical_block_size", json!(self.lexical_max_size)),
+            ("phonetic", json!(self.phonetic)),
+            ("permuterm", json!(self.permuterm)),
+            ("idf_top", json!(self.idf_top)),
+            ("max_shared_prefix", json!(self.max_shared_prefix)),
+            ("score_bounds", json!(self.score_bounds)),
+            ("fst", json!(self.fst)),
+            ("bitmaps", json!(self.bitmap_min_df)),
+            ("layout", json!(self.layout.name())),
+            ("fan_in", json!(self.fan_in)),
+            ("forward", json!(self.forward)),
+            ("format", json!(self.format.name())),
+        ]
+    }
+}
+
+/// Merges sorted providers term by term.
+///
+/// Postings of a term found in one buffer are copied as they are, and blocks
+/// over disjoint doc ids are joined raw; only overlapping ranges get decoded.
+/// A saver keeping [`TermBounds`] has single blocks scanned for their counts
+/// too, which writes the same bytes.
+/// The counts of the returned stats are filled in, its bytes are left to be
+/// measured once the saver is finished. `cancel` is checked before every term.
+#[cfg(feature = "build")]
+pub(crate) async fn merge_providers<S: Segments>(
+    providers: &mut [IndexTermProvider<S>],
+    saver: &mut IndexMergeSaver<S>,
+    cancel: &CancellationToken,
+) -> Result<IndexStats, Error> {
+    let mut heads = Vec::<Option<TermHead>>::with_capacity(providers.len());
+    let mut queue = BinaryHeap::<(Reverse<TermOrd>, usize)>::new();
+    for (i, provider) in providers.iter_mut().enumerate() {
+        let head = provider.next_head().await?;
+        if let Some(head) = &head {
+            queue.push((Reverse(TermOrd(head.term.clone())), i));
+        }
+        heads.push(head);
+    }
+
+    let mut values = Vec::<usize>::new();
+    let mut stats = IndexStats::default();
+    while let Some((Reverse(TermOrd(term)), first)) = queue.pop() {
+        cancel.check()?;
+        values.push(first);
+        while let Some((Reverse(TermOrd(next)), _)) = queue.peek() {
+            if *next != term {
+                break;
+            }
+            values.push(queue.pop().unwrap().1);
+        }
+
+        let mut use_count = 0u64;
+        for v in values.iter() {
+            use_count = add_uses(&term, use_count, heads[*v].as_ref().unwrap().use_count)?;
+        }
+        let indexes_pointer = saver.postings_writer().passed();
+        let bounded = saver.bounds.is_some();
+        let bytes = values.iter().map(|v| heads[*v].as_ref().unwrap().block_len).sum::<u64>();
+        let (documents, max_tf) = if values.len() > 1 && bytes > saver.spill_budget {
+            spill_postings(&term, providers, &heads, &values, saver).await?
+        } else if let ([single], false) = (&values[..], bounded) {
+            let documents = providers[*single]
+                .copy_postings(saver.postings_writer())
+                .await?;
+            (documents, 0)
+        } else {
+            let mut blocks = Vec::with_capacity(values.len());
+            for v in values.iter() {
+                blocks.push(providers[*v].raw_postings().await?);
+            }
+            if postings::sort_disjoint(&mut blocks) {
+                postings::write_joined(&blocks, saver.postings_writer()).await?;
+                (
+                    blocks.iter().map(|v| v.len()).sum(),
+                    blocks.iter().map(|v| v.max_tf()).max().unwrap_or(0),
+                )
+            } else {
+                drop(blocks);
+                let mut combined = IndexedTerm::<S>::new(term.clone());
+                for v in values.iter() {
+                    let indexes = providers[*v]
+                        .load_postings(heads[*v].as_ref().unwrap())
+                        .await?;
+                    combined.indexes.or(indexes, |a, b| a.merge(&term, b))?;
+                }
+                postings::push_block(saver.postings_writer(), &mut combined.indexes).await?;
+                (combined.indexes.len(), max_tf(&combined))
+            }
+        };
+        check_uses(&term, use_count, documents)?;
+        if let Some(top) = &mut saver.top_terms {
+            top.push(&term, documents);
+        }
+        saver.push_written(term, use_count, indexes_pointer).await?;
+        if let Some(bounds) = &mut saver.bounds {
+            bounds.push(indexes_pointer, max_tf);
+        }
+        stats.occurrences += use_count;
+        stats.postings += documents as u64;
+        stats.vocabulary += 1;
+
+        for v in values.drain(..) {
+            heads[v] = providers[v].next_head().await?;
+            if let Some(head) = &heads[v] {
+                queue.push((Reverse(TermOrd(head.term.clone())), v));
+            }
+        }
+    }
+    Ok(stats)
 }
 
+/// Writes the postings of `term` in the `values` of `providers`, too many
+/// bytes to combine in memory at once, the way [`merge_providers`] would.
+/// They are combined into runs of buffers holding at most the spill budget of
+/// the saver together, a buffer over it alone and copied as it is. The runs go
+/// to a [`SPILL_FILE`] in the directory of the index, removed once they are
+/// merged by [`postings::merge_runs`]. Returns the postings written and the
+/// highest use count among them.
+#[cfg(feature = "build")]
+async fn spill_postings<S: Segments>(
+    term: &str,
+    providers: &mut [IndexTermProvider<S>],
+    heads: &[Option<TermHead>],
+    values: &[usize],
+    saver: &mut IndexMergeSaver<S>,
+) -> Result<(usize, usize), Error> {
+    let mut groups = Vec::<Vec<usize>>::new();
+    let mut bytes = 0;
+    for v in values {
+        let len = heads[*v].as_ref().unwrap().block_len;
+        if groups.is_empty() || bytes + len > saver.spill_budget {
+            groups.push(Vec::new());
+            bytes = 0;
+        }
+        groups.last_mut().unwrap().push(*v);
+        bytes += len;
+    }
+    log::info!("Spilling the postings of {term} in {} runs", groups.len());
+
+    let path = Path::new(&saver.directory).join(SPILL_FILE);
+    let mut spill = CountedWriter::new(saver.io.writer(File::create(&path).await?));
+    let mut offsets = Vec::with_capacity(groups.len());
+    for group in groups {
+        offsets.push(spill.passed());
+        if let [single] = group[..] {
+            providers[single].copy_postings(&mut spill).await?;
+            continue;
+        }
+        let mut combined = SortedLinkedMap::<usize, UsageData<S>>::new();
+        for v in group {
+            let postings = providers[v].load_postings(heads[v].as_ref().unwrap()).await?;
+            combined.or(postings, |a, b| a.merge(term, b))?;
+        }
+        postings::push_block(&mut spill, &mut combined).await?;
+    }
+    spill.finish().await?;
+    let merged = postings::merge_runs::<S>(term, &path, &offsets, saver.postings_writer()).await;
+    fs::remove_file(&path).await?;
+    merged
+}
+
+/// Highest use count among the postings of `term`.
+#[cfg(feature = "build")]
+fn max_tf<S: Segments>(term: &IndexedTerm<S>) -> usize {
+    term.indexes.iter_ref().map(|(_, v)| v.use_count()).max().unwrap_or(0)
+}
+
+/// Writes terms gathered in memory the way [`merge_providers`] writes merged
+/// ones, with the same stats. `cancel` is checked before every term.
+#[cfg(feature = "build")]
+pub(crate) async fn save_terms<S: Segments>(
+    terms: impl IntoIterator<Item = IndexedTerm<S>>,
+    saver: &mut IndexMergeSaver<S>,
+    cancel: &CancellationToken,
+) -> Result<IndexStats, Error> {
+    let mut stats = IndexStats::default();
+    for term in terms {
+        cancel.check()?;
+        let (use_count, documents) = (term.use_count, term.indexes.len());
+        check_uses(&term.term, use_count, documents)?;
+        if let Some(top) = &mut saver.top_terms {
+            top.push(&term.term, documents);
+        }
+        saver.push(term).await?;
+        stats.occurrences += use_count;
+        stats.postings += documents as u64;
+        stats.vocabulary += 1;
+    }
+    Ok(stats)
+}
+
+#[cfg(feature = "build")]
+async fn write_input_files(path: String, input_files: Arc<Mutex<IndexPositions>>) {
+    let input_files = input_files.lock().await;
+    let names = input_files.names.iter().map(|(v, _)| paths::encode(v)).collect::<Vec<_>>();
+    save_input_files(
+        path,
+        input_files
+            .ids
+            .iter()
+            .map(|(i, v)| (names[*i].as_str(), *v as u64)),
+    )
+    .await
+    .unwrap();
+}
+
+/// Writes the source file and the position in it of every document, by id.
+#[cfg(feature = "build")]
+pub(crate) async fn save_input_files<'a>(
+    path: String,
+    sources: impl Iterator<Item = (&'a str, u64)>,
+) -> Result<(), Error> {
+    let mut file = BufWriter::new(File::create(path).await?);
+    for (st, i) in sources {
+        file.write_all(st.as_bytes()).await?;
+        file.write_u64(i).await?;
+        file.write_all(b"\n").await?;
+    }
+    file.flush().await
+}
+
+/// Checks the zones handed to the reader before anything is parsed: each has
+/// to be a tag the reader can match, with a segment in `selector`. The
+/// [`ZoneSet`] already holds every one once and none empty.
+#[cfg(feature = "build")]
+pub fn validate_attributes<Selector: SegmentSelector>(
+    selector: &Selector,
+    attributes: &ZoneSet,
+) -> Result<(), Error> {
+    let mut problems = Vec::new();
+    for attribute in attributes.iter() {
+        if !reads_tag::<CommCharInterpreter>(attribute) {
+            problems.push(format!("\"{attribute}\" is not a tag the reader can match"));
+        }
+        if selector.find_applier(attribute).is_none() {
+            problems.push(format!("\"{attribute}\" has no segment"));
+        }
+    }
+    match problems.is_empty() {
+        true => Ok(()),
+        false => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("invalid attributes: {}", problems.join(", ")),
+        )),
+    }
+}
+
+#[cfg(feature = "build")]
+pub struct IndexedBuilder {
+    config: IndexerConfig,
+    attributes: ZoneSet,
+    analyzer: Arc<Analyzer>,
+    tf: TfPolicy,
+    numeric: Option<String>,
+    excluded: Arc<Vec<String>>,
+    read_rate: Option<ReadRate>,
+    blocking_reads: bool,
+    checked_zones: bool,
+}
+
+#[cfg(feature = "build")]
 impl IndexedBuilder {
-    pub fn new(tree_max_size: usize, lexical_max_size: u8, attributes: Arc<Vec<String>>) -> Self {
-        Self {
-            tree_max_size,
-            lexical_max_size,
+    /// Fails if `attributes` don't pass [`validate_attributes`].
+    pub fn new(config: IndexerConfig, attributes: ZoneSet) -> Result<Self, Error> {
+        validate_attributes(&CommonSegmentSelector::new(), &attributes)?;
+        Ok(Self {
+            config,
             attributes,
+            analyzer: Arc::new(Analyzer::default()),
+            tf: TfPolicy::default(),
+            numeric: None,
+            excluded: Arc::new(Vec::new()),
+            read_rate: None,
+            blocking_reads: false,
+            checked_zones: false,
+        })
+    }
+
+    /// Make the terms of words with the steps of `config` instead of case
+    /// folding alone. Fails if the [`Analyzer`] can't be made of it.
+    pub fn with_analyzer(mut self, config: AnalyzerConfig) -> Result<Self, Error> {
+        self.analyzer = Arc::new(Analyzer::new(config)?);
+        Ok(self)
+    }
+
+    /// Drop terms rejected by `filter` before they are inserted, see
+    /// [`Analyzer::with_filter`].
+    pub fn with_filter(mut self, filter: TermFilter) -> Self {
+        self.analyzer = Arc::new(self.analyzer.as_ref().clone().with_filter(filter));
+        self
+    }
+
+    /// Cap or log-scale the count a document contributes to each of its terms.
+    pub fn with_tf_policy(mut self, tf: TfPolicy) -> Self {
+        self.tf = tf;
+        self
+    }
+
+    /// Keep the value of `<tag>` as a numeric field of every document. The tag
+    /// has to stand outside the attributes and can't be one of them.
+    pub fn with_numeric_field(mut self, tag: &str) -> Result<Self, Error> {
+        if !reads_tag::<CommCharInterpreter>(tag)
+            || self.attributes.contains(tag)
+            || self.excluded.iter().any(|v| v == tag)
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("\"{tag}\" can't be a numeric field"),
+            ));
+        }
+        self.numeric = Some(tag.to_string());
+        Ok(self)
+    }
+
+    /// Skip `<tag>` elements of `tags` whole, nothing inside them is
+    /// indexed. None of them can be an attribute or the numeric field.
+    pub fn with_excluded_elements(mut self, tags: Vec<String>) -> Result<Self, Error> {
+        for tag in tags.iter() {
+            if !reads_tag::<CommCharInterpreter>(tag)
+                || self.attributes.contains(tag)
+                || self.numeric.as_ref() == Some(tag)
+            {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("\"{tag}\" can't be excluded"),
+                ));
+            }
         }
+        self.excluded = Arc::new(tags);
+        Ok(self)
+    }
+
+    /// Also index every word that isn't all lowercase under its original
+    /// spelling, behind [`crate::case::EXACT_CASE_MARKER`]. Document lengths
+    /// count the word once.
+    pub fn with_case_preserving(mut self) -> Self {
+        self.analyzer = Arc::new(self.analyzer.as_ref().clone().with_original_case());
+        self
+    }
+
+    /// Read the XML inputs as chunks of [`RepeatedXmlReader::divide_write`]
+    /// and fail the build on one holding other zones than the attributes,
+    /// see [`RepeatedXmlReader::with_checked_zones`]. Without it a zone tag
+    /// out of place only drops its document.
+    pub fn with_checked_zones(mut self) -> Self {
+        self.checked_zones = true;
+        self
+    }
+
+    pub fn zones(&self) -> &ZoneSet {
+        &self.attributes
+    }
+
+    /// Fingerprint of the settings that decide what terms a document is read
+    /// into, the [`ParserBuilder::settings`] but the bounds on the tree and
+    /// the lexical blocks. Buffers of builders with different ones don't
+    /// belong in the same index.
+    pub fn fingerprint(&self) -> String {
+        let settings = self
+            .settings()
+            .into_iter()
+            .filter(|(name, _)| !matches!(*name, "tree_max_terms" | "lexical_block_size"))
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
+        fingerprint(&settings)
+    }
+
+    /// Writes the documents of `path` to `directory` as token stream chunks
+    /// of `documents` each, numbered by `counter`. The file is read as
+    /// [`ParserBuilder::reader_from_file`] reads XML, so the chunks index
+    /// the same as the file.
+    pub async fn split(
+        &self,
+        path: &Path,
+        directory: &str,
+        documents: u16,
+        counter: Arc<std::sync::atomic::AtomicU32>,
+    ) -> Result<(), Error> {
+        let provider = CommU8Provider::new(self.config.io().chunk_reader(File::open(path).await?));
+        let reader = RepeatedXmlReader::<_, CaseKeepingInterpreter>::new(provider, self.attributes.clone())
+            .await?
+            .with_excluded(self.excluded.clone())
+            .with_io(self.config.io());
+        let mut reader = match &self.numeric {
+            Some(tag) => reader.with_numeric_tag(tag.clone()),
+            None => reader,
+        };
+        reader.divide_write_binary(directory.to_string(), documents, counter).await
     }
 }
 
+#[cfg(feature = "build")]
 #[async_trait]
 impl ParserBuilder for IndexedBuilder {
     type Parser = IndexParser;
 
     fn build(&mut self) -> Self::Parser {
-        IndexParser::new(
-            self.tree_max_size,
-            self.lexical_max_size,
-            CommonSegmentSelector::new(),
-        )
+        let mut parser = IndexParser::new(self.config, CommonSegmentSelector::new());
+        parser.analyzer = self.analyzer.clone();
+        parser.tf = self.tf;
+        parser.fingerprint = self.fingerprint();
+        parser
     }
 
-    async fn reader_from_file(&mut self, file: File) -> <Self::Parser as Parser>::Reader {
-        RepeatedXmlReader::<_, CommCharInterpreter>::new(
-            CommU8Provider::new(BufReader::new(file)),
-            self.attributes.clone(),
-        )
-        .await
-        .unwrap()
+    fn filter_patterns(&self) -> Option<FilterPatterns> {
+        self.analyzer.filter_patterns()
+    }
+
+    fn tf_policy(&self) -> TfPolicy {
+        self.tf
+    }
+
+    fn numeric_field(&self) -> Option<String> {
+        self.numeric.clone()
+    }
+
+    fn case_preserving(&self) -> bool {
+        self.analyzer.keeps_original()
+    }
+
+    fn analyzer(&self) -> Option<AnalyzerConfig> {
+        Some(self.analyzer.config().clone())
+    }
+
+    fn excluded_elements(&self) -> Vec<String> {
+        self.excluded.to_vec()
+    }
+
+    fn read_blocking(&mut self) {
+        self.blocking_reads = true;
+    }
+
+    fn zones(&self) -> Option<ZoneSet> {
+        Some(self.attributes.clone())
+    }
+
+    fn settings(&self) -> Vec<(&'static str, Value)> {
+        vec![
+            ("tree_max_terms", json!(self.config.tree_max_terms())),
+            ("lexical_block_size", json!(self.config.lexical_block_size())),
+            ("attributes", json!(self.attributes)),
+            ("filter", json!(self.filter_patterns())),
+            ("tf", json!(self.tf)),
+            ("numeric_field", json!(self.numeric)),
+            ("case_preserving", json!(self.case_preserving())),
+            ("analyzer", json!(self.analyzer.config())),
+            ("excluded_elements", json!(*self.excluded)),
+            ("checked_zones", json!(self.checked_zones)),
+        ]
+    }
+
+    fn throttle_reads(&mut self, rate: ReadRate) {
+        self.read_rate = Some(rate);
+    }
+
+    fn task_memory(&self) -> Option<u64> {
+        Some((self.config.tree_max_terms() * TREE_TERM_BYTES) as u64)
+    }
+
+    /// Chunks named `*.tok` are read as [`crate::token_stream`], the rest as
+    /// XML. The excluded elements were already left out of a token stream
+    /// when it was written.
+    async fn reader_from_file(
+        &mut self,
+        path: &Path,
+        warnings: Warnings,
+    ) -> Result<<Self::Parser as Parser>::Reader, Error> {
+        let io = self.config.io();
+        let file = if self.blocking_reads {
+            let file = std::fs::File::open(path)?;
+            FileU8Provider::Sync(SyncU8Provider::new(std::io::BufReader::with_capacity(io.chunk_buf(), file)))
+        } else {
+            FileU8Provider::Async(CommU8Provider::new(io.chunk_reader(File::open(path).await?)))
+        };
+        let provider = HashingU8Provider::new(file);
+        let provider = match &self.read_rate {
+            Some(rate) => ThrottledU8Provider::new(provider).with_rate(rate.clone()),
+            None => ThrottledU8Provider::new(provider),
+        };
+        if is_token_stream(path) {
+            return Ok(ChunkReader::Tokens(TokenStreamReader::new(provider, self.attributes.clone()).await?));
+        }
+        let reader = RepeatedXmlReader::<_, CaseKeepingInterpreter>::new(provider, self.attributes.clone())
+            .await?
+            .with_excluded(self.excluded.clone())
+            .with_warnings(warnings);
+        let reader = match self.checked_zones {
+            true => reader.with_checked_zones(),
+            false => reader,
+        };
+        Ok(ChunkReader::Xml(match &self.numeric {
+            Some(tag) => reader.with_numeric_tag(tag.clone()),
+            None => reader,
+        }))
     }
 }
 
-struct Dictionary<S: Segments> {
+pub struct Dictionary<S: Segments> {
     pointer_part: BufReader<File>,
     lexical_part: BufReader<File>,
+    /// Bytes in the lexical part, which no length read from it can pass.
+    lexical_len: u64,
     index_part: BufReader<File>,
+    /// Bytes in the postings file.
+    index_len: u64,
+    /// Whether the postings file starts with [`POSTINGS_HEADER`].
+    framed: bool,
+    len: usize,
+    directory: String,
+    layout: IndexLayout,
+    phonetic: Option<PhoneticIndex>,
+    permuterm: Option<Box<Dictionary<S>>>,
+    numeric: Option<NumericValues>,
+    bounds: Option<TermBounds>,
+    blocks: Option<BlockDirectory>,
+    block_cache: Option<BlockCache>,
+    #[cfg(feature = "fst")]
+    fst: Option<TermFst>,
+    #[cfg(feature = "roaring")]
+    bitmaps: Option<PostingBitmaps>,
+    reads: u64,
     segment: PhantomData<S>,
 }
 
-impl<S: Segments> Dictionary<S> {
-    async fn new(directory: &String) -> Result<Self, Error> {
-        Ok(Self {
-            pointer_part: BufReader::new(File::open(&format!("{directory}/dictionary.txt")).await?),
-            lexical_part: BufReader::new(
-                File::open(&format!("{directory}/lexical_part.txt")).await?,
-            ),
-            index_part: BufReader::new(File::open(&format!("{directory}/index_part.txt")).await?),
-            segment: PhantomData::<S>,
-        })
+/// Bytes ahead of the first cursor in the pointer file: the term count.
+pub const POINTER_HEADER_SIZE: u64 = 8;
+
+/// Terms [`Dictionary::find_many`] sweeps over per query term before it
+/// looks the terms up one at a time instead.
+const SWEEP_SPAN: usize = 64;
+
+/// Why an index could not be opened, carried inside the returned [`Error`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OpenError {
+    /// A required file is not there.
+    Missing(String),
+    /// A file is shorter than the dictionary header needs it to be.
+    TooShort { file: String, len: u64, min: u64 },
+    /// The pointer file does not hold exactly the declared cursors.
+    Header { file: String, declared: u64, len: u64 },
+    /// `metadata.json` is present but unreadable.
+    Metadata(String),
+    /// The term at an ordinal could not be decoded by [`Dictionary::open_checked`].
+    Term { ordinal: usize, reason: String },
+}
+
+impl Display for OpenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OpenError::Missing(v) => write!(f, "index file {v} is missing"),
+            OpenError::TooShort { file, len, min } => {
+                write!(f, "index file {file} has {len} bytes, needs at least {min}")
+            }
+            OpenError::Header { file, declared, len } => write!(
+                f,
+                "{file} header declares {declared} terms but the file has {len} bytes"
+            ),
+            OpenError::Metadata(v) => write!(f, "unreadable index metadata: {v}"),
+            OpenError::Term { ordinal, reason } => write!(f, "term {ordinal} is unreadable: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for OpenError {}
+
+impl From<OpenError> for Error {
+    fn from(e: OpenError) -> Self {
+        let kind = match e {
+            OpenError::Missing(_) => ErrorKind::NotFound,
+            _ => ErrorKind::InvalidData,
+        };
+        Error::new(kind, e)
+    }
+}
+
+/// What `index_part` starts with since every postings block in it is led by
+/// the bytes it takes, format 2. A file without it holds the unframed blocks
+/// of format 1, still read as they are: none of those starts with these
+/// bytes, its count would have to be over 2^50.
+pub const POSTINGS_HEADER: [u8; 8] = *b"IXPT\0\0\0\x02";
+
+/// Why a postings block of a framed index could not be read, carried inside
+/// the returned [`Error`], with the byte of `index_part` it starts at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockError {
+    /// The block, up to `end`, is not within the `len` bytes of postings.
+    Outside { pointer: u64, end: u64, len: u64 },
+    /// The block declares more postings than its `frame` bytes can hold.
+    Count { pointer: u64, postings: usize, frame: u64 },
+    /// The postings decoded take `read` bytes rather than the `frame` ones.
+    Length { pointer: u64, frame: u64, read: u64 },
+}
+
+impl Display for BlockError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlockError::Outside { pointer, end, len } => write!(
+                f,
+                "postings block at byte {pointer} ends at {end}, outside the postings of {len} bytes"
+            ),
+            BlockError::Count { pointer, postings, frame } => write!(
+                f,
+                "postings block at byte {pointer} declares {postings} postings in {frame} bytes"
+            ),
+            BlockError::Length { pointer, frame, read } => write!(
+                f,
+                "postings block at byte {pointer} takes {frame} bytes but {read} were read"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BlockError {}
+
+impl From<BlockError> for Error {
+    fn from(e: BlockError) -> Self {
+        Error::new(ErrorKind::InvalidData, e)
+    }
+}
+
+/// Bytes `v` takes as a varint.
+pub(crate) fn variable_len(v: u64) -> u64 {
+    (u64::BITS - v.leading_zeros()).max(1).div_ceil(7) as u64
+}
+
+/// Bytes `postings` take as a block, not counting its frame.
+pub(crate) fn postings_bytes<S: Segments>(postings: &SortedLinkedMap<usize, UsageData<S>>) -> u64 {
+    let mut bytes = variable_len(postings.len() as u64);
+    let mut previous = 0;
+    for (document, usage) in postings.iter_ref() {
+        bytes += variable_len((document - previous) as u64) + variable_len(usage.use_count as u64);
+        bytes += S::ENCODED_SIZE as u64;
+        previous = *document;
+    }
+    bytes
+}
+
+/// Fails with [`BlockError::Length`] unless `postings`, read from the block
+/// at `pointer`, take the `frame` bytes [`Dictionary::open_block`] gave.
+fn check_block<S: Segments>(
+    pointer: u64,
+    frame: Option<u64>,
+    postings: &SortedLinkedMap<usize, UsageData<S>>,
+) -> Result<(), Error> {
+    match frame {
+        Some(frame) if postings_bytes(postings) != frame => Err(BlockError::Length {
+            pointer,
+            frame,
+            read: postings_bytes(postings),
+        }
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+/// Length of `path`, or [`OpenError::Missing`] naming it.
+async fn required_len(path: String) -> Result<(String, u64), Error> {
+    match fs::metadata(&path).await {
+        Ok(v) => Ok((path, v.len())),
+        Err(e) if e.kind() == ErrorKind::NotFound => Err(OpenError::Missing(path).into()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Checks the index in `directory` has every file it needs, with sizes that
+/// agree with the dictionary header, before anything is read from them.
+async fn check_files(directory: &str, layout: IndexLayout) -> Result<(), Error> {
+    let (dictionary, len) = required_len(layout.dictionary(directory)).await?;
+    let (lexical, lexical_len) = required_len(layout.lexical_part(directory)).await?;
+    // Rotation dictionaries keep no postings, so `index_part` may be empty.
+    required_len(layout.index_part(directory)).await?;
+    if len < POINTER_HEADER_SIZE {
+        return Err(OpenError::TooShort {
+            file: dictionary,
+            len,
+            min: POINTER_HEADER_SIZE,
+        }
+        .into());
+    }
+    let declared = File::open(&dictionary).await?.read_u64().await?;
+    if IndexedCursor::offset(declared) != len {
+        return Err(OpenError::Header {
+            file: dictionary,
+            declared,
+            len,
+        }
+        .into());
+    }
+    if declared > 0 && lexical_len == 0 {
+        return Err(OpenError::TooShort {
+            file: lexical,
+            len: 0,
+            min: 1,
+        }
+        .into());
+    }
+    match fs::read(IndexLayout::metadata(directory)).await {
+        Ok(data) => {
+            serde_json::from_slice::<IndexMetadata>(&data)
+                .map_err(|e| OpenError::Metadata(e.to_string()))?;
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => log::warn!(
+            "{directory} has no metadata.json and is read as a legacy index, which upgrade_index converts"
+        ),
+        Err(e) => return Err(e),
+    }
+    Ok(())
+}
+
+impl<S: Segments> Dictionary<S> {
+    /// Opens the index in `directory` once its files pass [`OpenError`] checks.
+    ///
+    /// Only the pointer, lexical and postings files are needed, so this also
+    /// opens a buffer [`Parser::flush_to`] wrote, to look into a merge that
+    /// failed. Lookups work the same on it; what the merge adds, like
+    /// metadata or auxiliary indexes, is missing. Without `metadata.json`
+    /// the index is taken for a legacy one, see
+    /// [`IndexMetadata::load_or_legacy`], and a warning is logged.
+    pub async fn new(directory: &String) -> Result<Self, Error> {
+        Self::new_with_io(directory, IoTuning::default()).await
+    }
+
+    /// [`Self::new`] reading the files with the buffers of `io`.
+    pub async fn new_with_io(directory: &String, io: IoTuning) -> Result<Self, Error> {
+        let layout = IndexLayout::detect(directory).await?;
+        check_files(directory, layout).await?;
+        let mut pointer_part = io.reader(File::open(layout.dictionary(directory)).await?);
+        let len = pointer_part.read_u64().await? as usize;
+        let blocks = match BlockDirectory::load(directory).await {
+            Ok(v) => Some(v),
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        let lexical_part = io.reader(File::open(layout.lexical_part(directory)).await?);
+        let mut index_part = io.reader(File::open(layout.index_part(directory)).await?);
+        // Read whole, the buffer may be shorter than the header.
+        let mut header = [0; POSTINGS_HEADER.len()];
+        let framed = match index_part.read_exact(&mut header).await {
+            Ok(_) => header == POSTINGS_HEADER,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => false,
+            Err(e) => return Err(e),
+        };
+        if !framed {
+            index_part.seek(SeekFrom::Start(0)).await?;
+        }
+        Ok(Self {
+            pointer_part,
+            lexical_len: lexical_part.get_ref().metadata().await?.len(),
+            lexical_part,
+            index_len: index_part.get_ref().metadata().await?.len(),
+            index_part,
+            framed,
+            len,
+            directory: directory.clone(),
+            layout,
+            phonetic: None,
+            permuterm: None,
+            numeric: None,
+            bounds: None,
+            blocks,
+            block_cache: None,
+            #[cfg(feature = "fst")]
+            fst: TermFst::load(directory).await?,
+            #[cfg(feature = "roaring")]
+            bitmaps: PostingBitmaps::load(directory).await?,
+            reads: 0,
+            segment: PhantomData::<S>,
+        })
+    }
+
+    /// Opens the generation the `CURRENT` pointer in `parent` names, see
+    /// [`Generations`].
+    pub async fn open_current(parent: &str) -> Result<Self, Error> {
+        Self::new(&Generations::new(parent).current_directory().await?).await
+    }
+
+    /// Like [`Self::new`], also decoding the first and the last term with
+    /// their postings as a smoke test.
+    pub async fn open_checked(directory: &String) -> Result<Self, Error> {
+        let mut dictionary = Self::new(directory).await?;
+        for ordinal in [0, dictionary.len.saturating_sub(1)] {
+            if ordinal >= dictionary.len {
+                break;
+            }
+            let decoded = match dictionary.cursor_at(ordinal).await {
+                Ok(cursor) => dictionary.get_term(cursor).await.map(|_| ()),
+                Err(e) => Err(e),
+            };
+            decoded.map_err(|e| OpenError::Term {
+                ordinal,
+                reason: e.to_string(),
+            })?;
+        }
+        Ok(dictionary)
+    }
+
+    /// File names the index was found with.
+    pub fn layout(&self) -> IndexLayout {
+        self.layout
+    }
+
+    /// Number of terms declared in the dictionary header.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Positioned reads done so far, each one a seek into one of the files.
+    pub fn reads(&self) -> u64 {
+        self.reads
+    }
+
+    /// Keeps the lexical blocks terms are decoded from in `cache`, so terms
+    /// of one block cost one read. The cache may be shared with other
+    /// dictionaries over the same files.
+    pub fn with_block_cache(mut self, cache: BlockCache) -> Self {
+        self.block_cache = Some(cache);
+        self
+    }
+
+    pub fn block_cache(&self) -> Option<&BlockCache> {
+        self.block_cache.as_ref()
+    }
+
+    /// Walks every term and checks they are strictly increasing by
+    /// [`term_cmp`], which lookups and the block directory depend on.
+    pub async fn verify_order(&mut self) -> Result<(), Error> {
+        self.verify_order_in(0..self.len).await.map(|_| ())
+    }
+
+    /// [`Self::verify_order`] over the terms at `ordinals`. Returns the
+    /// first and the last of them, for the terms around to be checked
+    /// against; `None` for no terms.
+    pub async fn verify_order_in(&mut self, ordinals: Range<usize>) -> Result<Option<(String, String)>, Error> {
+        let mut first = None;
+        let mut previous: Option<String> = None;
+        for ordinal in ordinals {
+            let cursor = self.cursor_at(ordinal).await?;
+            let term = self.term_of(&cursor).await?;
+            if let Some(previous) = previous.as_ref().filter(|v| term_cmp(v, &term).is_ge()) {
+                return Err(unordered(ordinal, &term, previous));
+            }
+            if first.is_none() {
+                first = Some(term.clone());
+            }
+            previous = Some(term);
+        }
+        Ok(first.zip(previous))
+    }
+
+    /// Checks no term is used less often than it has postings, which the
+    /// merge refuses to write, see [`TermCountError`].
+    pub async fn verify_counts(&mut self) -> Result<(), Error> {
+        self.verify_counts_in(0..self.len).await.map(|_| ())
+    }
+
+    /// [`Self::verify_counts`] over the terms at `ordinals`. Returns their
+    /// uses and their postings entries, summed.
+    pub async fn verify_counts_in(&mut self, ordinals: Range<usize>) -> Result<(u64, u64), Error> {
+        let (mut uses, mut entries) = (0, 0);
+        for ordinal in ordinals {
+            let cursor = self.cursor_at(ordinal).await?;
+            self.reads += 1;
+            let postings = match self.open_block(cursor.indexes_pointer as u64, true).await {
+                Ok((v, _)) => v,
+                Err(e) => return Err(postings_error(&self.term_of(&cursor).await?, &cursor, e)),
+            };
+            if (cursor.use_count as u64) < postings as u64 {
+                let term = self.term_of(&cursor).await?;
+                check_uses(&term, cursor.use_count as u64, postings)?;
+            }
+            uses += cursor.use_count as u64;
+            entries += postings as u64;
+        }
+        Ok((uses, entries))
+    }
+
+    /// Decodes the postings of every term and checks each one ends where
+    /// the next one starts, and the last one at the end of the file.
+    pub async fn verify_postings(&mut self) -> Result<(), Error> {
+        self.verify_postings_in(0..self.len).await
+    }
+
+    /// [`Self::verify_postings`] over the terms at `ordinals`. The postings
+    /// of the last one are checked against the term after, if any.
+    pub async fn verify_postings_in(&mut self, ordinals: Range<usize>) -> Result<(), Error> {
+        let len = self.index_part.get_ref().metadata().await?.len();
+        let mut next = match ordinals.is_empty() {
+            true => None,
+            false => Some(self.cursor_at(ordinals.start).await?),
+        };
+        for ordinal in ordinals {
+            let cursor = next.take().unwrap();
+            if ordinal + 1 < self.len {
+                next = Some(self.cursor_at(ordinal + 1).await?);
+            }
+            let term = self.get_term(cursor).await?.term;
+            let end = self.index_part.stream_position().await?;
+            let expected = next.as_ref().map_or(len, |v| v.indexes_pointer as u64);
+            if end != expected {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("postings of {term:?} end at byte {end}, expected {expected}"),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    async fn cursor_at(&mut self, ordinal: usize) -> Result<IndexedCursor, Error> {
+        self.reads += 1;
+        IndexedCursor::seek_to(&mut self.pointer_part, ordinal).await?;
+        IndexedCursor::load(&mut self.pointer_part).await
+    }
+
+    async fn term_of(&mut self, cursor: &IndexedCursor) -> Result<String, Error> {
+        if self.block_cache.as_ref().is_some_and(|v| v.capacity() > 0) {
+            let index = cursor.lexical_index as usize;
+            let block = self.cached_block(cursor.lexical_pointer, index).await?;
+            return Ok(block.terms[index].clone());
+        }
+        self.reads += 1;
+        self.lexical_part
+            .seek(SeekFrom::Start(cursor.lexical_pointer as u64))
+            .await?;
+        let mut start = String::new();
+        let mut index = lexical_length(&mut self.lexical_part, self.lexical_len).await?;
+        while index > 0 {
+            let next_char = read_char_reader(&mut self.lexical_part).await?;
+            index -= next_char.len_utf8();
+            start.push(next_char);
+        }
+
+        for _ in 0..cursor.lexical_index {
+            let skip = lexical_length(&mut self.lexical_part, self.lexical_len).await?;
+            self.lexical_part
+                .seek(SeekFrom::Current(skip as i64))
+                .await?;
+        }
+        let mut index = lexical_length(&mut self.lexical_part, self.lexical_len).await?;
+        while index > 0 {
+            let next_char = read_char_reader(&mut self.lexical_part).await?;
+            index -= next_char.len_utf8();
+            start.push(next_char);
+        }
+        Ok(start)
+    }
+
+    /// The block at `lexical_pointer` from the block cache, decoded at least
+    /// up to term `index`. Only what the cache is missing is read.
+    async fn cached_block(
+        &mut self,
+        lexical_pointer: usize,
+        index: usize,
+    ) -> Result<Arc<LexicalBlock>, Error> {
+        let cache = self.block_cache.clone().unwrap();
+        let mut block = match cache.lookup(lexical_pointer, index) {
+            Lookup::Hit(block) => return Ok(block),
+            Lookup::Partial(block) => {
+                self.reads += 1;
+                self.lexical_part.seek(SeekFrom::Start(block.end)).await?;
+                Arc::unwrap_or_clone(block)
+            }
+            Lookup::Miss => {
+                self.reads += 1;
+                self.lexical_part
+                    .seek(SeekFrom::Start(lexical_pointer as u64))
+                    .await?;
+                let mut prefix = vec![0u8; lexical_length(&mut self.lexical_part, self.lexical_len).await?];
+                self.lexical_part.read_exact(&mut prefix).await?;
+                LexicalBlock {
+                    prefix,
+                    ..Default::default()
+                }
+            }
+        };
+        let mut term = Vec::new();
+        while block.terms.len() <= index {
+            term.clone_from(&block.prefix);
+            let shared = term.len();
+            term.resize(shared + lexical_length(&mut self.lexical_part, self.lexical_len).await?, 0);
+            self.lexical_part.read_exact(&mut term[shared..]).await?;
+            let term = String::from_utf8(term.clone())
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+            block.terms.push(term);
+        }
+        block.end = self.lexical_part.stream_position().await?;
+        let block = Arc::new(block);
+        cache.insert(lexical_pointer, block.clone());
+        Ok(block)
+    }
+
+    /// Reads the block `range` from start to end, looking for `term`.
+    async fn scan_block(&mut self, range: BlockRange, term: &str) -> Result<Option<usize>, Error> {
+        if range.len > 0 && self.block_cache.as_ref().is_some_and(|v| v.capacity() > 0) {
+            let block = self
+                .cached_block(range.lexical_pointer as usize, range.len as usize - 1)
+                .await?;
+            return Ok(block.terms[..range.len as usize]
+                .binary_search_by(|v| bytes_cmp(v.as_bytes(), term.as_bytes()))
+                .ok()
+                .map(|i| (range.cursor + i as u64) as usize));
+        }
+        self.reads += 1;
+        self.lexical_part
+            .seek(SeekFrom::Start(range.lexical_pointer))
+            .await?;
+        let mut prefix = vec![0u8; lexical_length(&mut self.lexical_part, self.lexical_len).await?];
+        self.lexical_part.read_exact(&mut prefix).await?;
+        let Some(rest) = term.as_bytes().strip_prefix(prefix.as_slice()) else {
+            return Ok(None);
+        };
+        let mut suffix = Vec::new();
+        for i in 0..range.len {
+            suffix.resize(lexical_length(&mut self.lexical_part, self.lexical_len).await?, 0);
+            self.lexical_part.read_exact(&mut suffix).await?;
+            match bytes_cmp(&suffix, rest) {
+                std::cmp::Ordering::Less => {}
+                std::cmp::Ordering::Equal => return Ok(Some((range.cursor + i) as usize)),
+                std::cmp::Ordering::Greater => break,
+            }
+        }
+        Ok(None)
+    }
+
+    pub(crate) async fn find_cursor(&mut self, term: &str) -> Result<Option<IndexedCursor>, Error> {
+        #[cfg(feature = "fst")]
+        if let Some(found) = self.fst.as_ref().map(|v| v.get(term)) {
+            return match found {
+                Some(ordinal) => self.cursor_at(ordinal).await.map(Some),
+                None => Ok(None),
+            };
+        }
+        if let Some(blocks) = &self.blocks {
+            let Some(range) = blocks.range(term, self.len as u64) else {
+                return Ok(None);
+            };
+            return match self.scan_block(range, term).await? {
+                Some(ordinal) => self.cursor_at(ordinal).await.map(Some),
+                None => Ok(None),
+            };
+        }
+        let (mut low, mut high) = (0, self.len);
+        while low < high {
+            let middle = (low + high) / 2;
+            let cursor = self.cursor_at(middle).await?;
+            match term_cmp(&self.term_of(&cursor).await?, term) {
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Equal => return Ok(Some(cursor)),
+                std::cmp::Ordering::Greater => high = middle,
+            }
+        }
+        Ok(None)
+    }
+
+    /// Number of documents holding `term`, read from the head of its postings
+    /// without decoding them.
+    pub async fn document_frequency(&mut self, term: &str) -> Result<Option<usize>, Error> {
+        let Some(cursor) = self.find_cursor(term).await? else {
+            return Ok(None);
+        };
+        self.reads += 1;
+        let (postings, _) = self.open_block(cursor.indexes_pointer as u64, true).await?;
+        Ok(Some(postings))
+    }
+
+    /// Whether the index keeps a bitmap of the documents of `term`, see
+    /// [`IndexMerger::with_bitmaps`].
+    #[cfg(feature = "roaring")]
+    pub fn has_bitmap(&self, term: &str) -> bool {
+        self.bitmaps.as_ref().is_some_and(|v| v.contains(term))
+    }
+
+    /// The documents of `term` as a bitmap, `None` if the index keeps none
+    /// of it.
+    #[cfg(feature = "roaring")]
+    pub async fn bitmap(&mut self, term: &str) -> Result<Option<RoaringBitmap>, Error> {
+        match self.bitmaps.as_mut() {
+            Some(bitmaps) => {
+                self.reads += 1;
+                bitmaps.get(term).await
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Directory the dictionary was opened from.
+    pub fn directory(&self) -> &String {
+        &self.directory
+    }
+
+    /// The term at `ordinal` in dictionary order. Fails with InvalidInput
+    /// past the last one.
+    pub async fn term_at(&mut self, ordinal: usize) -> Result<String, Error> {
+        if ordinal >= self.len {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("term {ordinal} is past the {} of {}", self.len, self.directory),
+            ));
+        }
+        let cursor = self.cursor_at(ordinal).await?;
+        self.term_of(&cursor).await
+    }
+
+    /// Postings of the term at `ordinal`, which has to be below [`Self::len`].
+    #[cfg(feature = "build")]
+    pub(crate) async fn postings_at(&mut self, ordinal: usize) -> Result<PostingsIter<'_, S>, Error> {
+        let cursor = self.cursor_at(ordinal).await?;
+        self.postings_iter(&cursor).await
+    }
+
+    /// Binary searches the pointer part for `term` and loads its postings.
+    pub async fn find(&mut self, term: &str) -> Result<Option<IndexedTerm<S>>, Error> {
+        match self.find_cursor(term).await? {
+            Some(cursor) => self.get_term(cursor).await.map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Cursors of all `terms`, in their order. The terms are sorted and the
+    /// pointer and lexical parts read forward once from the first to the last
+    /// candidate; a range wider than [`SWEEP_SPAN`] terms per query term is
+    /// searched term by term instead.
+    pub(crate) async fn find_many(
+        &mut self,
+        terms: &[&str],
+    ) -> Result<Vec<Option<IndexedCursor>>, Error> {
+        let mut sorted = terms.to_vec();
+        sorted.sort_unstable_by(|a, b| term_cmp(a, b));
+        sorted.dedup();
+        let swept = match self.sweep_range(&sorted).await? {
+            Some((start, end)) if end - start <= SWEEP_SPAN * sorted.len() => {
+                self.sweep(&sorted, start, end).await?
+            }
+            _ => {
+                let mut found = Vec::with_capacity(sorted.len());
+                for term in sorted.iter() {
+                    found.push(self.find_cursor(term).await?);
+                }
+                found
+            }
+        };
+        Ok(terms
+            .iter()
+            .map(|term| {
+                let i = sorted.binary_search_by(|v| bytes_cmp(v.as_bytes(), term.as_bytes())).unwrap();
+                swept[i].clone()
+            })
+            .collect())
+    }
+
+    /// Ordinals `sorted` can lie between, or `None` when the terms are better
+    /// found one by one.
+    async fn sweep_range(&mut self, sorted: &[&str]) -> Result<Option<(usize, usize)>, Error> {
+        let (Some(first), Some(last)) = (sorted.first(), sorted.last()) else {
+            return Ok(None);
+        };
+        #[cfg(feature = "fst")]
+        if self.fst.is_some() {
+            return Ok(None);
+        }
+        if let Some(blocks) = &self.blocks {
+            let start = blocks.range(first, self.len as u64).map_or(0, |v| v.cursor);
+            let end = blocks
+                .range(last, self.len as u64)
+                .map_or(0, |v| v.cursor + v.len);
+            return Ok(Some((start as usize, end.max(start) as usize)));
+        }
+        let start = self.lower_bound(first).await?;
+        let end = (self.lower_bound(last).await? + 1).min(self.len);
+        Ok(Some((start, end.max(start))))
+    }
+
+    /// Ordinal of the first term not before `term`.
+    async fn lower_bound(&mut self, term: &str) -> Result<usize, Error> {
+        self.partition(term, std::cmp::Ordering::is_lt).await
+    }
+
+    /// Ordinal of the first term after `term`.
+    async fn upper_bound(&mut self, term: &str) -> Result<usize, Error> {
+        self.partition(term, std::cmp::Ordering::is_le).await
+    }
+
+    /// Ordinal of the first term whose order against `term` isn't `before`.
+    /// With a block directory only the block `term` falls in is searched.
+    async fn partition(
+        &mut self,
+        term: &str,
+        before: fn(std::cmp::Ordering) -> bool,
+    ) -> Result<usize, Error> {
+        let (mut low, mut high) = match &self.blocks {
+            Some(blocks) => blocks
+                .range(term, self.len as u64)
+                .map_or((0, 0), |v| (v.cursor as usize, (v.cursor + v.len) as usize)),
+            None => (0, self.len),
+        };
+        while low < high {
+            let middle = (low + high) / 2;
+            let cursor = self.cursor_at(middle).await?;
+            if before(term_cmp(&self.term_of(&cursor).await?, term)) {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+        Ok(low)
+    }
+
+    /// Terms between `start` and `end` in dictionary order, read front to
+    /// back with their stats but not their postings.
+    pub async fn range(
+        &mut self,
+        start: TermBound<'_>,
+        end: TermBound<'_>,
+    ) -> Result<TermRange<'_, S>, Error> {
+        let ordinal = match start {
+            TermBound::Included(term) => self.lower_bound(term).await?,
+            TermBound::Excluded(term) => self.upper_bound(term).await?,
+            TermBound::Unbounded => 0,
+        };
+        let end = match end {
+            TermBound::Included(term) => Some((term.as_bytes().to_vec(), true)),
+            TermBound::Excluded(term) => Some((term.as_bytes().to_vec(), false)),
+            TermBound::Unbounded => None,
+        };
+        Ok(TermRange {
+            dictionary: self,
+            ordinal,
+            end,
+            walk: None,
+        })
+    }
+
+    /// Reads the terms `start..end` in order and matches them against `sorted`.
+    async fn sweep(
+        &mut self,
+        sorted: &[&str],
+        start: usize,
+        end: usize,
+    ) -> Result<Vec<Option<IndexedCursor>>, Error> {
+        let mut found = Vec::with_capacity(sorted.len());
+        if start < end {
+            self.reads += 2;
+            IndexedCursor::seek_to(&mut self.pointer_part, start).await?;
+            let first = IndexedCursor::load(&mut self.pointer_part).await?;
+            self.lexical_part
+                .seek(SeekFrom::Start(first.lexical_pointer as u64))
+                .await?;
+            let mut walk = LexicalWalk::default();
+            let mut queries = sorted.iter().peekable();
+            let mut cursor = first;
+            for ordinal in start..end {
+                if ordinal > start {
+                    cursor = IndexedCursor::load(&mut self.pointer_part).await?;
+                }
+                let term = walk.next(&mut self.lexical_part, self.lexical_len, &cursor).await?;
+                while queries
+                    .next_if(|v| bytes_cmp(v.as_bytes(), &term).is_lt())
+                    .is_some()
+                {
+                    found.push(None);
+                }
+                if queries
+                    .next_if(|v| v.as_bytes() == term.as_slice())
+                    .is_some()
+                {
+                    found.push(Some(cursor.clone()));
+                }
+                if queries.peek().is_none() {
+                    break;
+                }
+            }
+        }
+        found.resize(sorted.len(), None);
+        Ok(found)
+    }
+
+    /// Loads the terms of `cursors`, keeping their order. Postings are read in
+    /// ascending offset order, and a term right after the previous one in the
+    /// same block continues the read instead of seeking.
+    pub(crate) async fn get_terms(
+        &mut self,
+        cursors: Vec<Option<IndexedCursor>>,
+    ) -> Result<Vec<Option<IndexedTerm<S>>>, Error> {
+        let mut order = (0..cursors.len())
+            .filter(|i| cursors[*i].is_some())
+            .collect::<Vec<_>>();
+        order.sort_by_key(|i| cursors[*i].as_ref().unwrap().indexes_pointer);
+        let mut loaded = (0..cursors.len()).map(|_| None).collect::<Vec<_>>();
+        let mut previous: Option<&IndexedCursor> = None;
+        for i in order {
+            let cursor = cursors[i].as_ref().unwrap();
+            let term = self.term_of(cursor).await?;
+            let follows = previous.is_some_and(|v| {
+                v.lexical_pointer == cursor.lexical_pointer
+                    && v.lexical_index as usize + 1 == cursor.lexical_index as usize
+            });
+            if !follows {
+                self.reads += 1;
+            }
+            let indexes = self
+                .load_block(cursor.indexes_pointer as u64, !follows)
+                .await
+                .map_err(|e| postings_error(&term, cursor, e))?;
+            loaded[i] = Some(IndexedTerm {
+                term,
+                use_count: cursor.use_count as u64,
+                indexes,
+            });
+            previous = Some(cursor);
+        }
+        Ok(loaded)
+    }
+
+    /// [`Self::find`] for every one of `terms`, sharing the reads between them.
+    pub async fn find_terms(
+        &mut self,
+        terms: &[&str],
+    ) -> Result<Vec<Option<IndexedTerm<S>>>, Error> {
+        let cursors = self.find_many(terms).await?;
+        self.get_terms(cursors).await
+    }
+
+    /// Terms sounding like `term`, read from the phonetic index on first use.
+    pub async fn phonetic(&mut self, term: &str) -> Result<Vec<String>, Error> {
+        if self.phonetic.is_none() {
+            self.phonetic = Some(PhoneticIndex::load(&self.directory).await?);
+        }
+        let ordinals = self.phonetic.as_ref().unwrap().candidates(term).to_vec();
+        let mut terms = Vec::with_capacity(ordinals.len());
+        for ordinal in ordinals {
+            let cursor = self.cursor_at(ordinal).await?;
+            terms.push(self.term_of(&cursor).await?);
+        }
+        Ok(terms)
+    }
+
+    /// Documents with a token in `zone`, as `metadata.json` counted them.
+    /// `None` for a buffer, or an index built before zones were counted.
+    pub async fn zone_document_count(&self, zone: &str) -> Result<Option<usize>, Error> {
+        let counts = match IndexMetadata::load(&self.directory).await {
+            Ok(v) => v.zone_documents,
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        Ok(counts.map(|v| v.get(zone).copied().unwrap_or(0)))
+    }
+
+    /// Highest count of `term` in a document, read from `max_tf.bin` on
+    /// first use. Fails with [`ErrorKind::NotFound`] for an index merged
+    /// without [`IndexMerger::with_score_bounds`].
+    pub async fn max_tf(&mut self, term: &str) -> Result<Option<usize>, Error> {
+        let Some(cursor) = self.find_cursor(term).await? else {
+            return Ok(None);
+        };
+        if self.bounds.is_none() {
+            self.bounds = Some(TermBounds::load(&self.directory).await?);
+        }
+        Ok(self.bounds.as_ref().unwrap().get(cursor.indexes_pointer as u64))
+    }
+
+    /// The numeric field of every document, read from `numeric.txt` on first use.
+    pub async fn numeric(&mut self) -> Result<&NumericValues, Error> {
+        if self.numeric.is_none() {
+            self.numeric = Some(NumericValues::load(&self.directory).await?);
+        }
+        Ok(self.numeric.as_ref().unwrap())
+    }
+
+    async fn with_prefix(&mut self, prefix: &str) -> Result<Vec<(String, u64)>, Error> {
+        let (mut low, mut high) = (0, self.len);
+        while low < high {
+            let middle = (low + high) / 2;
+            let cursor = self.cursor_at(middle).await?;
+            if term_cmp(&self.term_of(&cursor).await?, prefix).is_lt() {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+        let mut found = Vec::new();
+        while low < self.len {
+            let cursor = self.cursor_at(low).await?;
+            let term = self.term_of(&cursor).await?;
+            if !term.starts_with(prefix) {
+                break;
+            }
+            found.push((term, cursor.use_count as u64));
+            low += 1;
+        }
+        Ok(found)
+    }
+
+    /// Terms starting with `prefix`, in dictionary order.
+    pub async fn terms_with_prefix(&mut self, prefix: &str) -> Result<Vec<String>, Error> {
+        #[cfg(feature = "fst")]
+        if let Some(fst) = &self.fst {
+            return Ok(fst.with_prefix(prefix).into_iter().map(|v| v.0).collect());
+        }
+        Ok(self
+            .with_prefix(prefix)
+            .await?
+            .into_iter()
+            .map(|v| v.0)
+            .collect())
+    }
+
+    /// Terms within `distance` edits of `term`, in dictionary order. Without
+    /// an FST every term of the dictionary is compared.
+    pub async fn fuzzy(&mut self, term: &str, distance: u32) -> Result<Vec<String>, Error> {
+        #[cfg(feature = "fst")]
+        if let Some(fst) = &self.fst {
+            return Ok(fst.fuzzy(term, distance)?.into_iter().map(|v| v.0).collect());
+        }
+        let mut found = Vec::new();
+        for ordinal in 0..self.len {
+            let cursor = self.cursor_at(ordinal).await?;
+            let candidate = self.term_of(&cursor).await?;
+            if edit_distance(&candidate, term) <= distance as usize {
+                found.push(candidate);
+            }
+        }
+        Ok(found)
+    }
+
+    /// Terms matching `pattern`, where `*` stands for any run of characters.
+    pub async fn wildcard(&mut self, pattern: &str) -> Result<Vec<String>, Error> {
+        let key = rotation_key(pattern)?;
+        if self.permuterm.is_none() {
+            let directory = self.layout.permuterm(&self.directory);
+            self.permuterm = Some(Box::new(Dictionary::new(&directory).await?));
+        }
+        let rotations = self.permuterm.as_mut().unwrap().with_prefix(&key).await?;
+        let mut ordinals = rotations
+            .into_iter()
+            .map(|(_, v)| v as usize)
+            .collect::<Vec<_>>();
+        ordinals.sort_unstable();
+        ordinals.dedup();
+        let mut terms = Vec::with_capacity(ordinals.len());
+        for ordinal in ordinals {
+            let cursor = self.cursor_at(ordinal).await?;
+            let term = self.term_of(&cursor).await?;
+            if matches(pattern, &term) {
+                terms.push(term);
+            }
+        }
+        Ok(terms)
+    }
+
+    pub(crate) async fn get_term(&mut self, cursor: IndexedCursor) -> Result<IndexedTerm<S>, Error> {
+        let term = self.term_of(&cursor).await?;
+
+        self.reads += 1;
+        let list = self
+            .load_block(cursor.indexes_pointer as u64, true)
+            .await
+            .map_err(|e| postings_error(&term, &cursor, e))?;
+
+        Ok(IndexedTerm {
+            term,
+            use_count: cursor.use_count as u64,
+            indexes: list,
+        })
+    }
+
+    /// The postings of `term` decoded one at a time, or `None` if it is not
+    /// in the dictionary.
+    pub async fn postings(&mut self, term: &str) -> Result<Option<PostingsIter<'_, S>>, Error> {
+        match self.find_cursor(term).await? {
+            Some(cursor) => self.postings_iter(&cursor).await.map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads the number of postings the block at `pointer` starts with,
+    /// seeking to it first if `seek`. In a framed index the block has to be
+    /// within the file and have room for them; the bytes it takes are given
+    /// along, for [`check_block`].
+    async fn open_block(&mut self, pointer: u64, seek: bool) -> Result<(usize, Option<u64>), Error> {
+        if seek {
+            self.index_part.seek(SeekFrom::Start(pointer)).await?;
+        }
+        if !self.framed {
+            return Ok((variable_load(&mut self.index_part).await?, None));
+        }
+        let outside = |end| BlockError::Outside {
+            pointer,
+            end,
+            len: self.index_len,
+        };
+        if pointer < POSTINGS_HEADER.len() as u64 || pointer >= self.index_len {
+            return Err(outside(pointer).into());
+        }
+        let frame = variable_load(&mut self.index_part).await? as u64;
+        let end = (pointer + variable_len(frame)).saturating_add(frame);
+        if end > self.index_len {
+            return Err(outside(end).into());
+        }
+        let postings = variable_load(&mut self.index_part).await?;
+        // A posting takes a byte of delta and one of use count at least.
+        let least = (postings as u64)
+            .saturating_mul(2 + S::ENCODED_SIZE as u64)
+            .saturating_add(variable_len(postings as u64));
+        if least > frame {
+            return Err(BlockError::Count { pointer, postings, frame }.into());
+        }
+        Ok((postings, Some(frame)))
+    }
+
+    /// Decodes the block at `pointer`, see [`Self::open_block`].
+    async fn load_block(&mut self, pointer: u64, seek: bool) -> Result<SortedLinkedMap<usize, UsageData<S>>, Error> {
+        let (len, frame) = self.open_block(pointer, seek).await?;
+        let postings = SortedLinkedMap::load_entries(&mut self.index_part, len).await?;
+        check_block(pointer, frame, &postings)?;
+        Ok(postings)
+    }
+
+    pub(crate) async fn postings_iter(
+        &mut self,
+        cursor: &IndexedCursor,
+    ) -> Result<PostingsIter<'_, S>, Error> {
+        self.reads += 1;
+        let (len, _) = self.open_block(cursor.indexes_pointer as u64, true).await?;
+        Ok(PostingsIter {
+            reader: &mut self.index_part,
+            remaining: len,
+            len,
+            document: 0,
+            segment: PhantomData,
+        })
+    }
+}
+
+/// A length read from a lexical part of `lexical_len` bytes. One the file
+/// couldn't hold is refused as corrupt, rather than allocated or skipped.
+async fn lexical_length(lexical: &mut BufReader<File>, lexical_len: u64) -> Result<usize, Error> {
+    let length = variable_load(lexical).await?;
+    if length as u64 > lexical_len {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("lexical entry of {length} bytes in a lexical part of {lexical_len}"),
+        ));
+    }
+    Ok(length)
+}
+
+/// Lexical entries decoded front to back, one cursor after the other. The
+/// lexical part has to stand at the block of the first cursor.
+#[derive(Default)]
+struct LexicalWalk {
+    prefix: Vec<u8>,
+    block: Option<usize>,
+}
+
+impl LexicalWalk {
+    async fn next(
+        &mut self,
+        lexical: &mut BufReader<File>,
+        lexical_len: u64,
+        cursor: &IndexedCursor,
+    ) -> Result<Vec<u8>, Error> {
+        if self.block != Some(cursor.lexical_pointer) {
+            self.block = Some(cursor.lexical_pointer);
+            self.prefix.resize(lexical_length(lexical, lexical_len).await?, 0);
+            lexical.read_exact(&mut self.prefix).await?;
+            let mut skipped = Vec::new();
+            for _ in 0..cursor.lexical_index {
+                skipped.resize(lexical_length(lexical, lexical_len).await?, 0);
+                lexical.read_exact(&mut skipped).await?;
+            }
+        }
+        let mut term = self.prefix.clone();
+        let shared = term.len();
+        term.resize(shared + lexical_length(lexical, lexical_len).await?, 0);
+        lexical.read_exact(&mut term[shared..]).await?;
+        Ok(term)
+    }
+}
+
+/// Where a range of terms starts or ends, as [`std::ops::Bound`] does for
+/// values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TermBound<'a> {
+    Included(&'a str),
+    Excluded(&'a str),
+    Unbounded,
+}
+
+/// What the dictionary knows of a term without decoding its postings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TermStats {
+    pub use_count: u64,
+    pub document_frequency: usize,
+}
+
+/// Terms of a [`Dictionary::range`], read one at a time. Holds the
+/// dictionary until dropped.
+pub struct TermRange<'a, S: Segments> {
+    dictionary: &'a mut Dictionary<S>,
+    ordinal: usize,
+    /// The end bound and whether it is included.
+    end: Option<(Vec<u8>, bool)>,
+    /// Set once the pointer and lexical parts stand at the range.
+    walk: Option<LexicalWalk>,
+}
+
+impl<S: Segments> TermRange<'_, S> {
+    pub async fn next(&mut self) -> Result<Option<(String, TermStats)>, Error> {
+        let dictionary = &mut *self.dictionary;
+        if self.ordinal >= dictionary.len {
+            return Ok(None);
+        }
+        let cursor = match self.walk {
+            Some(_) => IndexedCursor::load(&mut dictionary.pointer_part).await?,
+            None => {
+                let cursor = dictionary.cursor_at(self.ordinal).await?;
+                dictionary.reads += 1;
+                dictionary
+                    .lexical_part
+                    .seek(SeekFrom::Start(cursor.lexical_pointer as u64))
+                    .await?;
+                self.walk = Some(LexicalWalk::default());
+                cursor
+            }
+        };
+        let term = self
+            .walk
+            .as_mut()
+            .unwrap()
+            .next(&mut dictionary.lexical_part, dictionary.lexical_len, &cursor)
+            .await?;
+        let past = self
+            .end
+            .as_ref()
+            .is_some_and(|(end, included)| match bytes_cmp(&term, end) {
+                std::cmp::Ordering::Less => false,
+                std::cmp::Ordering::Equal => !included,
+                std::cmp::Ordering::Greater => true,
+            });
+        if past {
+            self.ordinal = dictionary.len;
+            return Ok(None);
+        }
+        self.ordinal += 1;
+        dictionary.reads += 1;
+        let stats = TermStats {
+            use_count: cursor.use_count as u64,
+            document_frequency: dictionary.open_block(cursor.indexes_pointer as u64, true).await?.0,
+        };
+        let term = String::from_utf8(term).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        Ok(Some((term, stats)))
+    }
+}
+
+/// Postings of one term read straight from `index_part`, in doc id order,
+/// without building the whole list. Holds the dictionary until dropped.
+pub struct PostingsIter<'a, S: Segments> {
+    reader: &'a mut BufReader<File>,
+    remaining: usize,
+    len: usize,
+    document: usize,
+    segment: PhantomData<S>,
+}
+
+impl<S: Segments> PostingsIter<'_, S> {
+    /// Number of postings of the term, read or not.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub async fn next(&mut self) -> Result<Option<(usize, UsageData<S>)>, Error> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        self.document += variable_load(self.reader).await?;
+        let usage = UsageData::<S>::variable_load(self.reader).await?;
+        Ok(Some((self.document, usage)))
+    }
+
+    /// The first posting of `target` or after it, the ones before skipped.
+    ///
+    /// The postings have no skip table to jump with, so the ones skipped are
+    /// still decoded on the way; intersections gallop over decoded lists
+    /// instead, see [`crate::gallop`].
+    pub async fn advance_to(&mut self, target: usize) -> Result<Option<(usize, UsageData<S>)>, Error> {
+        while let Some((document, usage)) = self.next().await? {
+            if document >= target {
+                return Ok(Some((document, usage)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Levenshtein distance over chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let next = (diagonal + (ca != *cb) as usize)
+                .min(row[j] + 1)
+                .min(row[j + 1] + 1);
+            diagonal = row[j + 1];
+            row[j + 1] = next;
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(feature = "build")]
+pub struct IndexTermProvider<S: Segments> {
+    dictionary: Dictionary<S>,
+    first_part: String,
+    first_part_pointer: Option<usize>,
+    remaining_size: usize,
+    ahead: Option<IndexedCursor>,
+    unread: u64,
+    segment_date: PhantomData<S>,
+}
+
+/// A term read from the dictionary whose postings block is still in `index_part`.
+#[cfg(feature = "build")]
+#[derive(Debug)]
+pub(crate) struct TermHead {
+    pub(crate) term: String,
+    pub(crate) use_count: u64,
+    indexes_pointer: u64,
+    block_len: u64,
+}
+
+#[cfg(feature = "build")]
+impl<S: Segments> IndexTermProvider<S> {
+    pub async fn new(directory: &String) -> Result<Self, Error> {
+        Self::new_with_io(directory, IoTuning::default()).await
+    }
+
+    /// [`Self::new`] reading the buffer with the buffers of `io`.
+    pub async fn new_with_io(directory: &String, io: IoTuning) -> Result<Self, Error> {
+        let dictionary = Dictionary::new_with_io(directory, io).await?;
+        let remaining_size = dictionary.len();
+        Ok(Self {
+            dictionary,
+            first_part: String::new(),
+            first_part_pointer: None,
+            remaining_size,
+            ahead: None,
+            unread: 0,
+            segment_date: PhantomData::<S>,
+        })
+    }
+
+    async fn read_term(&mut self, cursor: &IndexedCursor) -> Result<String, Error> {
+        if self.first_part_pointer != Some(cursor.lexical_pointer) {
+            self.first_part.clear();
+            self.first_part_pointer = Some(cursor.lexical_pointer);
+            let mut skip = variable_load(&mut self.dictionary.lexical_part).await?;
+            while skip > 0 {
+                let next_char = read_char_reader(&mut self.dictionary.lexical_part).await?;
+                skip -= next_char.len_utf8();
+                self.first_part.push(next_char);
+            }
+        }
+        let mut term = self.first_part.clone();
+        let mut skip = variable_load(&mut self.dictionary.lexical_part).await?;
+        while skip > 0 {
+            let next_char = read_char_reader(&mut self.dictionary.lexical_part).await?;
+            skip -= next_char.len_utf8();
+            term.push(next_char);
+        }
+        Ok(term)
+    }
+
+    /// Reads the next term without its postings; any postings left unread are skipped.
+    pub(crate) async fn next_head(&mut self) -> Result<Option<TermHead>, Error> {
+        if self.unread > 0 {
+            self.dictionary
+                .index_part
+                .seek(SeekFrom::Current(self.unread as i64))
+                .await?;
+            self.unread = 0;
+        }
+        if self.remaining_size == 0 {
+            return Ok(None);
+        }
+        let cursor = match self.ahead.take() {
+            Some(cursor) => cursor,
+            None => IndexedCursor::load(&mut self.dictionary.pointer_part).await?,
+        };
+        self.remaining_size -= 1;
+        let end = if self.remaining_size > 0 {
+            let next = IndexedCursor::load(&mut self.dictionary.pointer_part).await?;
+            let end = next.indexes_pointer as u64;
+            self.ahead = Some(next);
+            end
+        } else {
+            self.dictionary.index_len
+        };
+        let term = self.read_term(&cursor).await?;
+        let indexes_pointer = cursor.indexes_pointer as u64;
+        self.unread = end - indexes_pointer;
+        Ok(Some(TermHead {
+            term,
+            use_count: cursor.use_count as u64,
+            indexes_pointer,
+            block_len: self.unread,
+        }))
+    }
+
+    /// Copies the postings of the last head as they are, returning how many
+    /// there were. An unframed block gets its frame on the way.
+    pub(crate) async fn copy_postings(&mut self, writer: &mut CountedWriter) -> Result<usize, Error> {
+        let mut buffer = [0u8; 8192];
+        let mut len = None;
+        if !self.dictionary.framed {
+            writer.push_variable_u64(self.unread).await?;
+        }
+        while self.unread > 0 {
+            let size = buffer.len().min(self.unread as usize);
+            self.dictionary
+                .index_part
+                .read_exact(&mut buffer[..size])
+                .await?;
+            if len.is_none() {
+                len = Some(postings::block_len(&buffer[..size], self.dictionary.framed)?);
+            }
+            writer.push(&buffer[..size]).await?;
+            self.unread -= size as u64;
+        }
+        Ok(len.unwrap_or(0))
+    }
+
+    /// Reads the postings of the last head without decoding them.
+    pub(crate) async fn raw_postings(&mut self) -> Result<RawBlock, Error> {
+        let mut bytes = vec![0u8; self.unread as usize];
+        self.dictionary.index_part.read_exact(&mut bytes).await?;
+        self.unread = 0;
+        RawBlock::parse(bytes, S::ENCODED_SIZE, self.dictionary.framed)
+    }
+
+    /// Decodes the postings of `head`, even if they were already read raw.
+    pub(crate) async fn load_postings(
+        &mut self,
+        head: &TermHead,
+    ) -> Result<SortedLinkedMap<usize, UsageData<S>>, Error> {
+        let seek = self.unread != head.block_len;
+        let indexes = self.dictionary.load_block(head.indexes_pointer, seek).await?;
+        self.unread = 0;
+        Ok(indexes)
+    }
+}
+
+#[cfg(feature = "build")]
+#[async_trait]
+impl<S: Segments> TermProvider for IndexTermProvider<S> {
+    type Term = IndexedTerm<S>;
+
+    async fn next_term(&mut self) -> Option<Self::Term> {
+        let head = self.next_head().await.ok()??;
+        let indexes = self.load_postings(&head).await.ok()?;
+        Some(IndexedTerm {
+            term: head.term,
+            use_count: head.use_count,
+            indexes,
+        })
+    }
+}
+
+/// Bytes of a prefix the terms of a lexical block share at most. Past it
+/// front coding saves next to nothing, and the blocks of terms with long
+/// common garbage prefixes stay cheap to slice.
+#[cfg(feature = "build")]
+pub const MAX_SHARED_PREFIX: usize = 255;
+
+#[cfg(feature = "build")]
+pub(crate) struct IndexMergeSaver<S: Segments> {
+    directory: String,
+    pointer_part: CountedWriter,
+    lexical_part: CountedWriter,
+    index_part: CountedWriter,
+    buffer_items: Vec<SavedTerm>,
+    /// Bytes the terms of the buffered block share, at most `max_shared`.
+    current_substr_size: usize,
+    max_shared: usize,
+    /// Bytes of postings of a term combined in memory at most.
+    spill_budget: u64,
+    io: IoTuning,
+    max_part_size: u8,
+    current_directory_size: u64,
+    /// Terms already written to `pointer_part`, which may lag behind
+    /// `current_directory_size` by a term held back from a flush.
+    flushed: u64,
+    phonetic: Option<PhoneticIndex>,
+    permuterm: Option<Rotations>,
+    top_terms: Option<TopTerms>,
+    bounds: Option<TermBounds>,
+    blocks: BlockDirectory,
+    #[cfg(feature = "fst")]
+    fst: Option<TermFstBuilder>,
+    /// Last pushed term, to keep the output in [`term_cmp`] order.
+    previous: String,
+    segment: PhantomData<S>,
+}
+
+#[cfg(feature = "build")]
+#[async_trait]
+impl<S: Segments> TermSink for IndexMergeSaver<S> {
+    type Term = IndexedTerm<S>;
+
+    async fn push(&mut self, term: Self::Term) -> Result<(), Error> {
+        IndexMergeSaver::push(self, term).await
+    }
+
+    async fn finish(self) -> Result<(), Error> {
+        IndexMergeSaver::finish(self).await.map(|_| ())
+    }
+}
+
+/// What an [`IndexMergeSaver`] leaves once finished: the sizes of its files,
+/// as its writers saw them, and what it collected along the way.
+#[cfg(feature = "build")]
+pub(crate) struct FinishedIndex {
+    pub(crate) bytes: SectionBytes,
+    pub(crate) top_terms: Option<TopTerms>,
+    pub(crate) permuterm: Option<Rotations>,
+}
+
+/// A term whose postings are already in `index_part`.
+#[cfg(feature = "build")]
+struct SavedTerm {
+    term: String,
+    use_count: u64,
+    indexes_pointer: u64,
+}
+
+#[cfg(feature = "build")]
+impl<S: Segments> IndexMergeSaver<S> {
+    pub(crate) async fn new(directory: String, max_size: u8) -> Result<Self, Error> {
+        Self::create(directory, max_size, IndexLayout::default(), IoTuning::default()).await
+    }
+
+    pub(crate) async fn create(
+        directory: String,
+        max_size: u8,
+        layout: IndexLayout,
+        io: IoTuning,
+    ) -> Result<Self, Error> {
+        layout.create_directories(&directory).await?;
+        let mut pointer_part = CountedWriter::new(io.writer(File::create(layout.dictionary(&directory)).await?));
+        pointer_part.push_u64(0).await?;
+        let mut index_part = CountedWriter::new(io.writer(File::create(layout.index_part(&directory)).await?));
+        index_part.push(&POSTINGS_HEADER).await?;
+        Ok(Self {
+            pointer_part,
+            lexical_part: CountedWriter::new(io.writer(File::create(layout.lexical_part(&directory)).await?)),
+            index_part,
+            directory: directory,
+            buffer_items: Vec::with_capacity(max_size.into()),
+            current_substr_size: 0,
+            max_shared: MAX_SHARED_PREFIX,
+            spill_budget: SPILL_BUDGET,
+            io,
+            max_part_size: max_size,
+            current_directory_size: 0,
+            flushed: 0,
+            phonetic: None,
+            permuterm: None,
+            top_terms: None,
+            bounds: None,
+            blocks: BlockDirectory::default(),
+            #[cfg(feature = "fst")]
+            fst: None,
+            previous: String::new(),
+            segment: PhantomData::<S>,
+        })
+    }
+
+    /// Collects soundex codes of the pushed terms, saved by [`Self::finish`].
+    pub(crate) fn with_phonetic(mut self) -> Self {
+        self.phonetic = Some(PhoneticIndex::default());
+        self
+    }
+
+    /// Collects rotations of the pushed terms, to be saved once the dictionary is done.
+    pub(crate) fn with_permuterm(mut self) -> Self {
+        self.permuterm = Some(Rotations::default());
+        self
+    }
+
+    /// Shares at most `bytes` of a prefix within a block instead of
+    /// [`MAX_SHARED_PREFIX`].
+    pub(crate) fn with_max_shared_prefix(mut self, bytes: usize) -> Self {
+        self.max_shared = bytes;
+        self
+    }
+
+    /// Spills a term whose postings to combine take more than `bytes`
+    /// instead of [`SPILL_BUDGET`].
+    pub(crate) fn with_spill_budget(mut self, bytes: u64) -> Self {
+        self.spill_budget = bytes;
+        self
+    }
+
+    /// Keeps the document frequencies of the `k` most frequent merged terms.
+    pub(crate) fn with_top_terms(mut self, k: usize) -> Self {
+        self.top_terms = Some(TopTerms::new(k));
+        self
+    }
+
+    /// Keeps the [`TermBounds`] of the pushed terms, saved by [`Self::finish`].
+    pub(crate) fn with_score_bounds(mut self) -> Self {
+        self.bounds = Some(TermBounds::default());
+        self
+    }
+
+    /// Also builds `terms.fst`, saved by [`Self::finish`].
+    #[cfg(feature = "fst")]
+    pub(crate) fn with_fst(mut self) -> Self {
+        self.fst = Some(TermFstBuilder::new());
+        self
+    }
+
+    /// Writes the buffered block. Its cursors and its lexical part are
+    /// encoded in memory first and go out as one write to each file, both
+    /// at once; postings are already in `index_part` by then.
+    async fn flush(&mut self) -> Result<(), Error> {
+        if self.buffer_items.len() == 0 {
+            return Ok(());
+        }
+        let items = std::mem::take(&mut self.buffer_items);
+        let lexical_pointer = self.lexical_part.passed();
+        self.blocks.push(&items[0].term, self.flushed, lexical_pointer);
+        self.flushed += items.len() as u64;
+        let shared = floor_char_boundary(&items[0].term, self.current_substr_size);
+        if shared != self.current_substr_size {
+            log::warn!(
+                "shared prefix of {} bytes cuts into a character of {:?}, {} bytes kept",
+                self.current_substr_size,
+                items[0].term,
+                shared
+            );
+        }
+        let mut pointers = Vec::with_capacity(items.len() * IndexedCursor::SERIALIZED_SIZE);
+        let mut lexical = Vec::new();
+        variable_encode_u64(shared as u64, &mut lexical);
+        lexical.extend_from_slice(&items[0].term.as_bytes()[..shared]);
+        for (i, v) in items.iter().enumerate() {
+            IndexedCursor::new(
+                lexical_pointer as usize,
+                i as u8,
+                v.indexes_pointer as usize,
+                v.use_count as usize,
+            )
+            .encode(&mut pointers);
+            let other_part = &v.term.as_bytes()[shared..];
+            variable_encode_u64(other_part.len() as u64, &mut lexical);
+            lexical.extend_from_slice(other_part);
+        }
+        let (pointers, lexical) = tokio::join!(
+            self.pointer_part.push(&pointers),
+            self.lexical_part.push(&lexical)
+        );
+        pointers.and(lexical)
+    }
+
+    /// Writes the last block and closes every file. The writers go with the
+    /// saver, so none of them can be left unflushed or written to after.
+    pub(crate) async fn finish(mut self) -> Result<FinishedIndex, Error> {
+        self.flush().await?;
+        self.pointer_part.goto(0).await?;
+        self.pointer_part.push_u64(self.current_directory_size).await?;
+        let (dictionary, lexical, postings) = tokio::join!(
+            self.pointer_part.finish(),
+            self.lexical_part.finish(),
+            self.index_part.finish()
+        );
+        let block_dir = self.blocks.save(&self.directory).await?;
+        #[cfg(feature = "fst")]
+        if let Some(fst) = self.fst.take() {
+            fst.save(&self.directory).await?;
+        }
+        if let Some(phonetic) = &self.phonetic {
+            phonetic.save(&self.directory).await?;
+        }
+        if let Some(bounds) = &self.bounds {
+            bounds.save(&self.directory).await?;
+        }
+        Ok(FinishedIndex {
+            bytes: SectionBytes {
+                dictionary: dictionary?.length,
+                lexical: lexical?.length,
+                postings: postings?.length,
+                block_dir: block_dir.length,
+            },
+            top_terms: self.top_terms,
+            permuterm: self.permuterm,
+        })
+    }
+
+    /// Fails without writing anything if `term` has more postings than uses.
+    pub(crate) async fn push(&mut self, mut term: IndexedTerm<S>) -> Result<(), Error> {
+        check_uses(&term.term, term.use_count, term.indexes.len())?;
+        let indexes_pointer = self.index_part.passed();
+        let bound = self.bounds.is_some().then(|| max_tf(&term));
+        postings::push_block(&mut self.index_part, &mut term.indexes).await?;
+        self.push_written(term.term, term.use_count, indexes_pointer)
+            .await?;
+        if let (Some(bounds), Some(max_tf)) = (&mut self.bounds, bound) {
+            bounds.push(indexes_pointer, max_tf);
+        }
+        Ok(())
+    }
+
+    /// Where postings go; a block written here is registered with [`Self::push_written`].
+    pub(crate) fn postings_writer(&mut self) -> &mut CountedWriter {
+        &mut self.index_part
+    }
+
+    /// Adds a term whose postings were written at `indexes_pointer` of the postings writer.
+    pub(crate) async fn push_written(
+        &mut self,
+        term: String,
+        use_count: u64,
+        indexes_pointer: u64,
+    ) -> Result<(), Error> {
+        if self.current_directory_size > 0 && term_cmp(&self.previous, &term).is_ge() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("term {term:?} pushed after {:?}", self.previous),
+            ));
+        }
+        self.previous.clear();
+        self.previous.push_str(&term);
+        let term = SavedTerm {
+            term,
+            use_count,
+            indexes_pointer,
+        };
+        if self.buffer_items.len() == self.max_part_size as usize {
+            self.flush().await?;
+            self.current_substr_size = 0;
+        } else if self.buffer_items.len() > 0 {
+            let last = self.buffer_items.last().unwrap();
+            let size = count_same(&last.term, &term.term, self.max_shared);
+            if size > self.current_substr_size {
+                let last = self.buffer_items.pop().unwrap();
+                self.flush().await?;
+                self.current_substr_size = size;
+                self.buffer_items.push(last);
+            } else if self.buffer_items.len() * self.current_substr_size < (self.buffer_items.len() + 1) * size {
+                self.current_substr_size = size;
+            } else {
+                self.flush().await?;
+                self.current_substr_size = 0;
+            }
+        } else {
+            self.current_substr_size = 0;
+        }
+        if let Some(phonetic) = &mut self.phonetic {
+            phonetic.push(&term.term, self.current_directory_size as usize);
+        }
+        if let Some(permuterm) = &mut self.permuterm {
+            permuterm.push(&term.term, self.current_directory_size);
+        }
+        #[cfg(feature = "fst")]
+        if let Some(fst) = &mut self.fst {
+            fst.push(&term.term, self.current_directory_size)?;
+        }
+        self.buffer_items.push(term);
+        self.current_directory_size += 1;
+        Ok(())
+    }
+}
+
+/// Full check of the index in `directory`: the files have to pass the
+/// checks of [`Dictionary::new`], the terms have to be in [`term_cmp`] order
+/// and none may have more postings than uses.
+pub async fn verify_index<S: Segments>(directory: &String) -> Result<(), Error> {
+    let mut dictionary = Dictionary::<S>::new(directory).await?;
+    dictionary.verify_order().await?;
+    dictionary.verify_counts().await?;
+    dictionary.verify_postings().await
+}
+
+/// What [`repair_header`] found in the dictionary header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeaderRepair {
+    /// Terms the header declared.
+    pub declared: u64,
+    /// Entries the pointer file holds, now in the header.
+    pub counted: u64,
+}
+
+impl HeaderRepair {
+    pub fn changed(&self) -> bool {
+        self.declared != self.counted
+    }
+}
+
+impl Display for HeaderRepair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.changed() {
+            true => write!(f, "header declared {} terms, rewritten to the {} entries", self.declared, self.counted),
+            false => write!(f, "header declares the {} entries, left as it was", self.counted),
+        }
+    }
+}
+
+/// Rewrites the term count of the dictionary header in `directory` to the
+/// number of entries the pointer file holds, as an index whose build
+/// stopped before [`IndexMergeSaver::finish`] patched it in has a stale one.
+///
+/// Entries all take [`IndexedCursor::SERIALIZED_SIZE`] bytes, so the file
+/// length gives the count; a file ending in part of one, or whose last
+/// entry points past the lexical or postings file, is refused unchanged.
+pub async fn repair_header(directory: &str) -> Result<HeaderRepair, Error> {
+    let layout = IndexLayout::detect(directory).await?;
+    let (dictionary, len) = required_len(layout.dictionary(directory)).await?;
+    let (lexical, lexical_len) = required_len(layout.lexical_part(directory)).await?;
+    let (index, index_len) = required_len(layout.index_part(directory)).await?;
+    if len < POINTER_HEADER_SIZE {
+        return Err(OpenError::TooShort {
+            file: dictionary,
+            len,
+            min: POINTER_HEADER_SIZE,
+        }
+        .into());
+    }
+    let entries = len - POINTER_HEADER_SIZE;
+    let entry = IndexedCursor::SERIALIZED_SIZE as u64;
+    let partial = entries % entry;
+    if partial != 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("{dictionary} ends {partial} bytes into an entry, can't count its terms"),
+        ));
+    }
+    let counted = entries / entry;
+    let mut file = fs::OpenOptions::new().read(true).write(true).open(&dictionary).await?;
+    let declared = file.read_u64().await?;
+    if counted > 0 {
+        IndexedCursor::seek_to(&mut file, counted as usize - 1).await?;
+        let last = IndexedCursor::load(&mut file).await?;
+        if last.lexical_pointer() >= lexical_len {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("last entry points at byte {} of {lexical}, which has {lexical_len}", last.lexical_pointer()),
+            ));
+        }
+        if last.indexes_pointer() > index_len {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("last entry points at byte {} of {index}, which has {index_len}", last.indexes_pointer()),
+            ));
+        }
+    }
+    let repair = HeaderRepair { declared, counted };
+    if repair.changed() {
+        file.seek(SeekFrom::Start(0)).await?;
+        file.write_u64(counted).await?;
+        file.flush().await?;
+        log::warn!("{dictionary}: {repair}");
+    }
+    Ok(repair)
+}
+
+/// Term `ordinal` found at or before the one before it.
+pub(crate) fn unordered(ordinal: usize, term: &str, previous: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("term {ordinal} {term:?} is not after {previous:?}"),
+    )
+}
+
+/// `e` of loading the postings of `term`, with where they start.
+fn postings_error(term: &str, cursor: &IndexedCursor, e: Error) -> Error {
+    // It names the byte already, and stays a BlockError to be told apart.
+    if e.get_ref().is_some_and(|v| v.is::<BlockError>()) {
+        return e;
+    }
+    Error::new(
+        e.kind(),
+        format!("postings of {term:?} at byte {}: {e}", cursor.indexes_pointer),
+    )
+}
+
+/// Bytes of whole characters `f` and `s` start with alike, at most `max`.
+#[cfg(feature = "build")]
+fn count_same(f: &str, s: &str, max: usize) -> usize {
+    let mut fc = f.chars();
+    let mut sc = s.chars();
+    let mut i = 0;
+    while let (Some(f), Some(s)) = (fc.next(), sc.next()) {
+        if f != s || i + f.len_utf8() > max {
+            break;
+        }
+        i += f.len_utf8();
+    }
+    i
+}
+
+/// The largest char boundary of `s` at or below `index`.
+#[cfg(feature = "build")]
+fn floor_char_boundary(s: &str, index: usize) -> usize {
+    let mut index = index.min(s.len());
+    while !s.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+#[tokio::test]
+async fn zeroed_header_is_repaired() -> Result<(), Error> {
+    let root = scratch("repair_header").await?;
+    let corpus = CorpusSpec {
+        docs: 40,
+        vocab: 50,
+        ..CorpusSpec::default()
+    }
+    .generate(&root.join("corpus"))
+    .await?;
+    let destination = corpus.index(&root).await?;
+    let terms = corpus.postings.len() as u64;
+    let pointers = IndexLayout::detect(&destination).await?.dictionary(&destination);
+    let valid = fs::read(&pointers).await?;
+    let mut zeroed = valid.clone();
+    zeroed[..8].fill(0);
+    fs::write(&pointers, &zeroed).await?;
+    let e = Dictionary::<CommonSegments>::new(&destination).await.err().unwrap();
+    assert!(matches!(
+        e.get_ref().unwrap().downcast_ref::<OpenError>(),
+        Some(OpenError::Header { declared: 0, .. })
+    ));
+
+    let repair = repair_header(&destination).await?;
+    assert_eq!(repair, HeaderRepair { declared: 0, counted: terms });
+    assert!(repair.to_string().contains("declared 0 terms"));
+    assert_eq!(fs::read(&pointers).await?, valid);
+    verify_index::<CommonSegments>(&destination).await?;
+    let mut provider = IndexTermProvider::<CommonSegments>::new(&destination).await?;
+    let mut found = 0;
+    while provider.next_term().await.is_some() {
+        found += 1;
+    }
+    assert_eq!(found, terms);
+    assert!(!repair_header(&destination).await?.changed());
+
+    // Half an entry or a last entry pointing past the postings can't be counted.
+    fs::write(&pointers, &valid[..valid.len() - 3]).await?;
+    assert_eq!(repair_header(&destination).await.unwrap_err().kind(), ErrorKind::InvalidData);
+    let mut past = zeroed.clone();
+    let last = IndexedCursor::offset(terms - 1) as usize;
+    past[last + 9..last + 17].copy_from_slice(&u64::MAX.to_be_bytes());
+    fs::write(&pointers, &past).await?;
+    assert!(repair_header(&destination).await.unwrap_err().to_string().contains("last entry"));
+    assert_eq!(fs::read(&pointers).await?, past);
+
+    fs::remove_dir_all(&root).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn corrupt_lexical_lengths_are_refused() -> Result<(), Error> {
+    let root = scratch("corrupt_lexical").await?;
+    let corpus = CorpusSpec {
+        docs: 40,
+        vocab: 50,
+        ..CorpusSpec::default()
+    }
+    .generate(&root.join("corpus"))
+    .await?;
+    let destination = corpus.index(&root).await?;
+    let lexical_part = IndexLayout::detect(&destination).await?.lexical_part(&destination);
+    let bytes = fs::read(&lexical_part).await?;
+    let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+    let (first, second) = (dictionary.cursor_at(0).await?, dictionary.cursor_at(1).await?);
+    assert_eq!(first.lexical_pointer, second.lexical_pointer);
+
+    // The first block starts with the length of its prefix, then the length
+    // of the suffix of its first term, which reading the second skips.
+    let varint = |at: usize| bytes[at..].iter().position(|v| v & 0x80 != 0).unwrap() + 1;
+    let prefix_end = varint(0);
+    let prefix_len = bytes[..prefix_end]
+        .iter()
+        .enumerate()
+        .map(|(i, v)| ((v & 0x7f) as usize) << (7 * i))
+        .sum::<usize>();
+    let skip = prefix_end + prefix_len;
+    for (at, cursor) in [(0, &first), (skip, &second)] {
+        let mut corrupt = bytes[..at].to_vec();
+        variable_encode_u64(1 << 40, &mut corrupt);
+        corrupt.extend_from_slice(&bytes[at + varint(at)..]);
+        fs::write(&lexical_part, &corrupt).await?;
+        let expected = format!("lexical entry of {} bytes in a lexical part of {}", 1u64 << 40, corrupt.len());
+        let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+        let error = dictionary.term_of(cursor).await.unwrap_err();
+        assert_eq!((error.kind(), error.to_string()), (ErrorKind::InvalidData, expected.clone()), "{at}");
+        let mut terms = dictionary.range(TermBound::Unbounded, TermBound::Unbounded).await?;
+        let error = terms.next().await.unwrap_err();
+        assert_eq!((error.kind(), error.to_string()), (ErrorKind::InvalidData, expected), "{at}");
+    }
+
+    fs::remove_dir_all(&root).await?;
+    Ok(())
+}
+
+/// Rewrites the postings of the index in `directory` as they were before
+/// blocks were framed, see [`POSTINGS_HEADER`], with the pointers to them.
+#[cfg(test)]
+async fn unframe(directory: &String) -> Result<(), Error> {
+    let layout = IndexLayout::detect(directory).await?;
+    let mut pointers = fs::read(layout.dictionary(directory)).await?;
+    let framed = fs::read(layout.index_part(directory)).await?;
+    let terms = (pointers.len() as u64 - POINTER_HEADER_SIZE) / IndexedCursor::SERIALIZED_SIZE as u64;
+    let mut postings = Vec::new();
+    for ordinal in 0..terms {
+        let at = IndexedCursor::offset(ordinal) as usize + 9;
+        let mut body = u64::from_be_bytes(pointers[at..at + 8].try_into().unwrap()) as usize;
+        let (mut frame, mut shift) = (0, 0);
+        while framed[body] & 0x80 == 0 {
+            frame += (framed[body] as usize) << shift;
+            (body, shift) = (body + 1, shift + 7);
+        }
+        frame += ((framed[body] & 0x7f) as usize) << shift;
+        pointers[at..at + 8].copy_from_slice(&(postings.len() as u64).to_be_bytes());
+        postings.extend_from_slice(&framed[body + 1..body + 1 + frame]);
+    }
+    fs::write(layout.dictionary(directory), pointers).await?;
+    fs::write(layout.index_part(directory), postings).await
+}
+
+#[tokio::test]
+async fn truncated_postings_say_where() -> Result<(), Error> {
+    let root = scratch("truncated_postings").await?;
+    let corpus = CorpusSpec {
+        docs: 40,
+        vocab: 50,
+        ..CorpusSpec::default()
+    }
+    .generate(&root.join("corpus"))
+    .await?;
+    let destination = corpus.index(&root).await?;
+    // Unframed blocks are read until they run out; a framed one can't pass
+    // the end of the file, see `corrupt_postings_pointers_are_caught`.
+    unframe(&destination).await?;
+    verify_index::<CommonSegments>(&destination).await?;
+    let index_part = IndexLayout::detect(&destination).await?.index_part(&destination);
+    let bytes = fs::read(&index_part).await?;
+
+    // The last term has the last postings in the file.
+    let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+    let cursor = dictionary.cursor_at(dictionary.len() - 1).await?;
+    let term = dictionary.term_of(&cursor).await?;
+    let start = cursor.indexes_pointer;
+    let size = corpus.documents(&term).len();
+    let mut header = Vec::new();
+    variable_encode_u64(size as u64, &mut header);
+    let first = start + header.len();
+    let end = bytes.len() - 1;
+    let cases = [
+        (start, String::new()),
+        (first, format!("entry 0 of {size} at byte {first}, {size} expected still: ")),
+        (end, format!("entry {} of {size} at byte {end}, 1 expected still: ", size - 1)),
+    ];
+    for (cut, expected) in cases {
+        fs::write(&index_part, &bytes[..cut]).await?;
+        let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+        let error = dictionary.find(&term).await.err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        let prefix = format!("postings of {term:?} at byte {start}: {expected}");
+        assert!(error.to_string().starts_with(&prefix), "{error} at {cut}");
+        let error = verify_index::<CommonSegments>(&destination).await.unwrap_err();
+        assert!(error.to_string().starts_with(&prefix), "{error} at {cut}");
+    }
+
+    let mut longer = bytes.clone();
+    longer.push(0);
+    fs::write(&index_part, &longer).await?;
+    let error = verify_index::<CommonSegments>(&destination).await.unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+    assert_eq!(
+        error.to_string(),
+        format!("postings of {term:?} end at byte {}, expected {}", bytes.len(), longer.len())
+    );
+
+    fs::remove_dir_all(&root).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn vartst() -> Result<(), Error> {
+    use save::writer::variable_save_usize;
+
+    let root = scratch("vartst").await?;
+    let path = root.join("vartst.txt");
+    let values = [0, 1, 127, 128, 255, 16_383, 16_384, u32::MAX as usize];
+    let mut b = BufWriter::new(File::create(&path).await?);
+    for v in values {
+        variable_save_usize(v, &mut b).await?;
+    }
+    b.flush().await?;
+    assert_eq!(fs::metadata(&path).await?.len(), 1 + 1 + 1 + 2 + 2 + 2 + 3 + 5);
+
+    let mut reader = BufReader::new(File::open(&path).await?);
+    for v in values {
+        assert_eq!(variable_load(&mut reader).await?, v);
+    }
+    fs::remove_dir_all(&root).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn parse_reads_a_document_per_call() -> Result<(), Error> {
+    let root = std::env::temp_dir().join(format!("parse_two_{}", std::process::id()));
+    fs::create_dir_all(&root).await?;
+    let path = root.join("0.xml");
+    fs::write(
+        &path,
+        "<title>\nRust book\n</title>\n<text>\nrust borrow rust\n</text>\n<title>\nGuide\n</title>\n<text>\nbook guide\n</text>\n",
+    )
+    .await?;
+    let config = IndexerConfig::new(1000, 6)?;
+    let mut builder = IndexedBuilder::new(config, ZoneSet::new(["title", "text"])?)?;
+    let mut parser = builder.build();
+    let mut reader = builder.reader_from_file(&path, Warnings::default()).await?;
+    assert!(parser.parse(&mut reader, 0).await == ParserCallback::ZoneEnd);
+    assert!(parser.parse(&mut reader, 1).await == ParserCallback::ZoneEnd);
+    assert!(parser.parse(&mut reader, 2).await == ParserCallback::FileEnd);
+
+    let mut terms = std::mem::take(&mut parser.b_tree);
+    assert_eq!(terms.keys().collect::<Vec<_>>(), ["book", "borrow", "guide", "rust"]);
+    // Document, title and text bits and tf of every posting.
+    for (term, use_count, postings) in [
+        ("book", 2, vec![(0, 1, 0, 1), (1, 0, 1, 1)]),
+        ("borrow", 1, vec![(0, 0, 1, 1)]),
+        ("guide", 2, vec![(1, 1, 1, 2)]),
+        ("rust", 3, vec![(0, 1, 1, 3)]),
+    ] {
+        let found = terms.remove(term).unwrap();
+        assert_eq!(found.use_count, use_count, "{term}");
+        let found = found
+            .indexes
+            .iter()
+            .map(|(id, v)| (id, format!("{:?}", v.segments), v.use_count))
+            .collect::<Vec<_>>();
+        let postings = postings
+            .into_iter()
+            .map(|(id, title, text, tf)| {
+                (id, format!("CommonSegments {{ title: {title}, text: {text}, nothing: 0 }}"), tf)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(found, postings, "{term}");
+    }
+    fs::remove_dir_all(&root).await?;
+    Ok(())
+}
+
+/// Entry of the pointer file, one per term in term order after the
+/// [`POINTER_HEADER_SIZE`] header. Every entry takes the same
+/// [`IndexedCursor::SERIALIZED_SIZE`] bytes, which is what lets a lookup
+/// seek straight to the `n`th term:
+///
+/// | bytes  | field                                          |
+/// |--------|------------------------------------------------|
+/// | 0..8   | start of the term's lexical block, big endian  |
+/// | 8      | place of the term in that block                |
+/// | 9..17  | start of the term's postings, big endian       |
+/// | 17..25 | use count of the term, big endian              |
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexedCursor {
+    lexical_pointer: usize,
+    lexical_index: u8,
+    indexes_pointer: usize,
+    use_count: usize,
+}
+
+impl IndexedCursor {
+    pub const SERIALIZED_SIZE: usize = 8 + 1 + 8 + 8;
+
+    /// Where the cursor of term `ordinal` starts in the pointer file. The
+    /// offset of the term count is the length of the whole file.
+    pub fn offset(ordinal: u64) -> u64 {
+        POINTER_HEADER_SIZE + ordinal * Self::SERIALIZED_SIZE as u64
+    }
+
+    /// Moves `reader` over the pointer file to the cursor of term `ordinal`.
+    pub async fn seek_to<R: AsyncSeek + Unpin + ?Sized>(reader: &mut R, ordinal: usize) -> Result<(), Error> {
+        reader.seek(SeekFrom::Start(Self::offset(ordinal as u64))).await?;
+        Ok(())
+    }
+
+    pub fn new(
+        lexical_pointer: usize,
+        lexical_index: u8,
+        indexes_pointer: usize,
+        use_count: usize,
+    ) -> Self {
+        Self {
+            lexical_pointer,
+            lexical_index,
+            indexes_pointer,
+            use_count,
+        }
+    }
+
+    pub fn lexical_pointer(&self) -> u64 {
+        self.lexical_pointer as u64
+    }
+
+    pub fn lexical_index(&self) -> u8 {
+        self.lexical_index
+    }
+
+    pub fn indexes_pointer(&self) -> u64 {
+        self.indexes_pointer as u64
+    }
+
+    pub fn use_count(&self) -> u64 {
+        self.use_count as u64
+    }
+
+    /// Appends the [`IndexedCursor::SERIALIZED_SIZE`] bytes of the cursor to `out`.
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&(self.lexical_pointer as u64).to_be_bytes());
+        out.push(self.lexical_index);
+        out.extend_from_slice(&(self.indexes_pointer as u64).to_be_bytes());
+        out.extend_from_slice(&(self.use_count as u64).to_be_bytes());
+    }
+
+    pub async fn save<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        let mut encoded = Vec::with_capacity(Self::SERIALIZED_SIZE);
+        self.encode(&mut encoded);
+        writer.write_all(&encoded).await
+    }
+
+    pub async fn load<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<IndexedCursor, Error> {
+        Ok(Self {
+            lexical_pointer: reader.read_u64().await? as usize,
+            lexical_index: reader.read_u8().await?,
+            indexes_pointer: reader.read_u64().await? as usize,
+            use_count: reader.read_u64().await? as usize,
+        })
+    }
+}
+
+#[tokio::test]
+async fn loader_tst() -> Result<(), Error> {
+    let root = scratch("loader_tst").await?;
+    let corpus = CorpusSpec::default().generate(&root.join("inp")).await?;
+    let destination = corpus.index(&root).await?;
+
+    let mut reader = IndexTermProvider::<CommonSegments>::new(&destination).await?;
+    let mut found = BTreeMap::new();
+    while let Some(term) = reader.next_term().await {
+        let postings = term.indexes.iter().map(|v| (v.0, v.1.use_count())).collect::<Vec<_>>();
+        found.insert(term.term, postings);
+    }
+    assert_eq!(found, corpus.postings);
+    fs::remove_dir_all(&root).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn loader_tst_buff() -> Result<(), Error> {
+    let root = scratch("loader_tst_buff").await?;
+    let corpus = CorpusSpec {
+        files: 1,
+        ..CorpusSpec::default()
+    }
+    .generate(&root.join("inp"))
+    .await?;
+
+    let config = IndexerConfig::new(100_000, 6)?;
+    let mut builder = IndexedBuilder::new(config, corpus.attributes.clone())?;
+    let mut parser = builder.build();
+    let mut reader = builder.reader_from_file(corpus.files[0].as_ref(), Warnings::default()).await?;
+    let mut ind = 0;
+    while parser.parse(&mut reader, ind).await == ParserCallback::ZoneEnd {
+        ind += 1;
+    }
+    let buffer = root.join("buffer").to_str().unwrap().to_string();
+    parser.flush_to(&buffer).await?;
+
+    let mut provider = IndexParser::provider_from_file(&buffer).await?;
+    assert_eq!(provider.remaining_size, corpus.postings.len());
+    let mut found = BTreeMap::new();
+    while let Some(term) = provider.next_term().await {
+        found.insert(term.term, term.indexes.iter().map(|v| v.0).collect::<Vec<_>>());
+    }
+    assert_eq!(provider.remaining_size, 0);
+    let expected = corpus
+        .postings
+        .keys()
+        .map(|term| (term.clone(), corpus.documents(term)))
+        .collect::<BTreeMap<_, _>>();
+    assert_eq!(found, expected);
+    fs::remove_dir_all(&root).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn buffers_answer_lookups_like_the_merged_index() -> Result<(), Error> {
+    let root = scratch("buffer_lookups").await?;
+    let corpus = CorpusSpec {
+        docs: 90,
+        files: 1,
+        ..CorpusSpec::default()
+    }
+    .generate(&root.join("inp"))
+    .await?;
+
+    // Flushed every 30 documents, the way a task flushes a full tree.
+    let config = IndexerConfig::new(100_000, 6)?;
+    let mut builder = IndexedBuilder::new(config, corpus.attributes.clone())?;
+    let mut parser = builder.build();
+    let mut reader = builder.reader_from_file(corpus.files[0].as_ref(), Warnings::default()).await?;
+    let mut buffers = Vec::new();
+    let mut ind = 0;
+    loop {
+        let callback = parser.parse(&mut reader, ind).await;
+        ind += 1;
+        if (ind % 30 == 0 || callback == ParserCallback::FileEnd) && !parser.is_empty() {
+            let buffer = root.join("flushed").join(buffers.len().to_string()).to_str().unwrap().to_string();
+            fs::create_dir_all(&buffer).await?;
+            parser.flush_to(&buffer).await?;
+            buffers.push(buffer);
+        }
+        if callback == ParserCallback::FileEnd {
+            break;
+        }
+    }
+    assert_eq!(buffers.len(), 3);
+    let destination = corpus.index(&root).await?;
+
+    let mut merged = Dictionary::<CommonSegments>::new(&destination).await?;
+    let mut opened = Vec::new();
+    for buffer in buffers.iter() {
+        verify_index::<CommonSegments>(buffer).await?;
+        opened.push(Dictionary::<CommonSegments>::new(buffer).await?);
+    }
+    assert!(opened.iter().all(|v| v.len() < merged.len()));
+    for (term, expected) in corpus.postings.iter() {
+        let mut postings = Vec::new();
+        for buffer in opened.iter_mut() {
+            if let Some(found) = buffer.find(term).await? {
+                postings.extend(found.indexes.iter().map(|(document, usage)| (document, usage.use_count())));
+            }
+        }
+        assert_eq!(&postings, expected, "{term}");
+        let found = merged.find(term).await?.unwrap();
+        let merged_postings = found.indexes.iter().map(|(document, usage)| (document, usage.use_count()));
+        assert!(merged_postings.eq(postings.iter().copied()), "{term}");
+    }
+    assert!(opened[0].find("missing").await?.is_none());
+    fs::remove_dir_all(&root).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn merge_block_size_changes_only_file_sizes() -> Result<(), Error> {
+    use crate::{
+        execute::{execute, QueryLimits},
+        parser::ParseController,
+        query::parse_query,
+    };
+
+    let root = scratch("merge_block_size").await?;
+    let corpus = CorpusSpec {
+        docs: 120,
+        files: 2,
+        ..CorpusSpec::default()
+    }
+    .generate(&root.join("inp"))
+    .await?;
+    // A run of terms sharing one prefix, longer than the middle bound.
+    let shared = (0..80)
+        .map(|i| format!("shared{}", char::from_u32(0x3041 + i).unwrap()))
+        .collect::<Vec<_>>();
+    let path = root.join("inp").join("shared.xml").to_str().unwrap().to_string();
+    fs::write(&path, format!("<title>\nshared\n</title>\n<text>\n{}\n</text>\n", shared.join(" "))).await?;
+    let mut files = corpus.files.clone();
+    files.push(path);
+    let selector = CommonSegmentSelector::new();
+    let queries = ["aaa OR ba*", "caa daa", "e* NOT aaa", "shared*"];
+    let mut built = Vec::new();
+    for size in [1, 16, 255] {
+        let destination = root.join(format!("res{size}")).to_str().unwrap().to_string();
+        let config = IndexerConfig::new(1000, 6)?;
+        ParseController::<IndexParser, _, _>::new(
+            files.clone(),
+            destination.clone(),
+            root.join(format!("buffer{size}")).to_str().unwrap().to_string(),
+            1,
+            IndexedBuilder::new(config, corpus.attributes.clone())?,
+            IndexMerger::new(MergerConfig::new(size)?).with_permuterm(),
+        )
+        .create_dictionary()
+        .await?;
+        verify_index::<CommonSegments>(&destination).await?;
+
+        let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+        let mut found = BTreeMap::new();
+        for term in corpus.postings.keys().chain(shared.iter()) {
+            let term = dictionary.find(term).await?.unwrap();
+            found.insert(term.term, term.indexes.iter().map(|(d, mut u)| (d, *u.use_count_mut())).collect::<Vec<_>>());
+        }
+        let mut results = Vec::new();
+        for raw in queries {
+            let query = parse_query(raw, &selector).unwrap();
+            results.push(execute(&query, &mut dictionary, QueryLimits::default()).await.unwrap().documents);
+        }
+        let lexical = fs::metadata(IndexLayout::V1.lexical_part(&destination)).await?.len();
+        built.push((found, results, lexical));
+    }
+    let (found, results, _) = &built[0];
+    assert!(results.iter().all(|v| !v.is_empty()));
+    for (other_found, other_results, _) in built.iter().skip(1) {
+        assert_eq!(other_found, found);
+        assert_eq!(other_results, results);
+    }
+    let lexical = built.iter().map(|v| v.2).collect::<Vec<_>>();
+    assert!(lexical[0] > lexical[1] && lexical[1] > lexical[2], "{lexical:?}");
+    fs::remove_dir_all(&root).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn cancelled_merge_returns_promptly() -> Result<(), Error> {
+    use std::time::{Duration, Instant};
+
+    let root = scratch("cancelled_merge").await?;
+    let corpus = CorpusSpec {
+        docs: 4000,
+        vocab: 50_000,
+        zipf_s: 0.0,
+        files: 1,
+        ..CorpusSpec::default()
+    }
+    .generate(&root.join("inp"))
+    .await?;
+
+    // Many small buffers over a wide vocabulary make for a long merge.
+    let config = IndexerConfig::new(100_000, 6)?;
+    let mut builder = IndexedBuilder::new(config, corpus.attributes.clone())?;
+    let mut parser = builder.build();
+    let mut reader = builder.reader_from_file(corpus.files[0].as_ref(), Warnings::default()).await?;
+    let mut buffers = Vec::new();
+    let mut ind = 0;
+    loop {
+        let callback = parser.parse(&mut reader, ind).await;
+        ind += 1;
+        if (ind % 100 == 0 || callback == ParserCallback::FileEnd) && !parser.is_empty() {
+            let buffer = root.join("buffer").join(buffers.len().to_string()).to_str().unwrap().to_string();
+            fs::create_dir_all(&buffer).await?;
+            parser.flush_to(&buffer).await?;
+            buffers.push(buffer);
+        }
+        if callback == ParserCallback::FileEnd {
+            break;
+        }
+    }
+
+    let destination = root.join("res").to_str().unwrap().to_string();
+    let positions = Arc::new(Mutex::new(IndexPositions { names: vec![], ids: vec![], max_id: crate::doc_id::MAX_DOCUMENT_ID }));
+    let cancel = CancellationToken::new();
+    let cancelled = Arc::new(Mutex::new(None));
+    let canceller = tokio::spawn({
+        let (cancel, cancelled) = (cancel.clone(), cancelled.clone());
+        async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            *cancelled.lock().await = Some(Instant::now());
+            cancel.cancel();
+        }
+    });
+    let error = IndexMerger::new(config.merger())
+        .merge(positions, Arc::new(Mutex::new(buffers.clone())), destination.clone(), &cancel)
+        .await
+        .unwrap_err();
+    let returned = Instant::now();
+    canceller.await?;
+    assert!(is_interrupted(&error), "{error}");
+    let cancelled = cancelled.lock().await.expect("merge finished before it was cancelled");
+    assert!(returned - cancelled < Duration::from_secs(1));
+    assert!(fs::metadata(&destination).await.is_err());
+    for buffer in buffers.iter() {
+        assert!(fs::metadata(buffer).await.is_err(), "{buffer}");
+    }
+    fs::remove_dir_all(&root).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn low_open_files_limit_merges_in_rounds() -> Result<(), Error> {
+    use crate::open_files::{FILES_PER_BUFFER, RESERVED_FILES};
+
+    let root = scratch("merge_rounds").await?;
+    let corpus = CorpusSpec { docs: 300, vocab: 2000, files: 1, ..CorpusSpec::default() }
+        .generate(&root.join("inp"))
+        .await?;
+
+    let config = IndexerConfig::new(100_000, 6)?;
+    let mut builder = IndexedBuilder::new(config, corpus.attributes.clone())?;
+    let mut parser = builder.build();
+    let mut reader = builder.reader_from_file(corpus.files[0].as_ref(), Warnings::default()).await?;
+    let mut buffers = Vec::new();
+    let mut ind = 0;
+    loop {
+        let callback = parser.parse(&mut reader, ind).await;
+        ind += 1;
+        if (ind % 20 == 0 || callback == ParserCallback::FileEnd) && !parser.is_empty() {
+            let buffer = root.join("buffer").join(buffers.len().to_string()).to_str().unwrap().to_string();
+            fs::create_dir_all(&buffer).await?;
+            parser.flush_to(&buffer).await?;
+            buffers.push(buffer);
+        }
+        if callback == ParserCallback::FileEnd {
+            break;
+        }
+    }
+    assert!(buffers.len() > 4, "{}", buffers.len());
+
+    // Room for two buffers at a time, so the merge takes several rounds.
+    let destination = root.join("res").to_str().unwrap().to_string();
+    let positions = Arc::new(Mutex::new(IndexPositions { names: vec![], ids: vec![], max_id: crate::doc_id::MAX_DOCUMENT_ID }));
+    let buffer_files = Arc::new(Mutex::new(buffers.clone()));
+    IndexMerger::new(config.merger())
+        .with_open_files_limit(RESERVED_FILES + 2 * FILES_PER_BUFFER)
+        .merge(positions, buffer_files.clone(), destination.clone(), &CancellationToken::new())
+        .await?;
+    // A round names what it makes after the first buffer of the group with
+    // the round appended, so the dots in a name tell the round. Only the
+    // last round's buffers are left listed next to the original ones.
+    let rounds = {
+        let (mut left, mut rounds) = (buffers.len(), 0);
+        while left > 2 {
+            left = left.div_ceil(2);
+            rounds += 1;
+        }
+        rounds
+    };
+    let listed = buffer_files.lock().await.clone();
+    assert!(buffers.iter().all(|v| listed.contains(v)));
+    let made = listed.iter().filter(|v| !buffers.contains(v)).collect::<Vec<_>>();
+    assert_eq!(made.len(), 2, "{made:?}");
+    for v in made {
+        let name = Path::new(v).file_name().unwrap().to_str().unwrap();
+        assert_eq!(name.matches('.').count(), rounds, "{v}");
+    }
+    assert!(fs::read_dir(root.join("buffer")).await?.next_entry().await?.is_none());
+
+    verify_index::<CommonSegments>(&destination).await?;
+    let mut merged = Dictionary::<CommonSegments>::new(&destination).await?;
+    assert_eq!(merged.len(), corpus.postings.len());
+    for (term, expected) in corpus.postings.iter() {
+        let found = merged.find(term).await?.unwrap();
+        let postings = found.indexes.iter().map(|(document, usage)| (document, usage.use_count()));
+        assert!(postings.eq(expected.iter().copied()), "{term}");
+    }
+    fs::remove_dir_all(&root).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn buffers_of_different_settings_are_not_merged() -> Result<(), Error> {
+    use crate::buffer_meta::BufferMeta;
+
+    let root = scratch("buffer_meta").await?;
+    let corpus = CorpusSpec::default().generate(&root.join("inp")).await?;
+    let config = IndexerConfig::new(100_000, 6)?;
+    let builders = [
+        IndexedBuilder::new(config, corpus.attributes.clone())?,
+        IndexedBuilder::new(config, corpus.attributes.clone())?.with_case_preserving(),
+    ];
+    let fingerprints = builders.iter().map(IndexedBuilder::fingerprint).collect::<Vec<_>>();
+    assert_ne!(fingerprints[0], fingerprints[1]);
+
+    let mut buffers = Vec::new();
+    let mut ind = 0;
+    for (mut builder, file) in builders.into_iter().zip(corpus.files.iter()) {
+        let mut parser = builder.build();
+        let mut reader = builder.reader_from_file(file.as_ref(), Warnings::default()).await?;
+        let first = ind;
+        while parser.parse(&mut reader, ind).await != ParserCallback::FileEnd {
+            ind += 1;
+        }
+        let terms = parser.len();
+        let buffer = root.join("buffer").join(buffers.len().to_string()).to_str().unwrap().to_string();
+        fs::create_dir_all(&buffer).await?;
+        parser.flush_to(&buffer).await?;
+        let meta = BufferMeta::load(&buffer).await?.unwrap();
+        assert_eq!(meta.fingerprint, fingerprints[buffers.len()]);
+        assert_eq!(meta.zones.as_ref(), Some(&corpus.attributes));
+        assert_eq!((meta.documents.start, meta.terms), (first, terms));
+        assert!(meta.documents.end > first, "{:?}", meta.documents);
+        assert_eq!(meta.version, env!("CARGO_PKG_VERSION"));
+        buffers.push(buffer);
+        ind += 1;
+    }
+
+    let destination = root.join("res").to_str().unwrap().to_string();
+    let positions = || Arc::new(Mutex::new(IndexPositions { names: vec![], ids: vec![], max_id: crate::doc_id::MAX_DOCUMENT_ID }));
+    let error = IndexMerger::new(config.merger())
+        .merge(positions(), Arc::new(Mutex::new(buffers.clone())), destination.clone(), &CancellationToken::new())
+        .await
+        .unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    let message = error.to_string();
+    assert!(message.contains(&fingerprints[0]) && message.contains(&fingerprints[1]), "{message}");
+    assert!(message.contains("--force"), "{message}");
+    assert!(fs::metadata(&destination).await.is_err());
+
+    IndexMerger::new(config.merger())
+        .with_force()
+        .merge(positions(), Arc::new(Mutex::new(buffers.clone())), destination.clone(), &CancellationToken::new())
+        .await?;
+    verify_index::<CommonSegments>(&destination).await?;
+    fs::remove_dir_all(&root).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn in_memory_build_matches_buffered() -> Result<(), Error> {
+    use crate::parser::ParseController;
+
+    let root = scratch("in_memory").await?;
+    let corpus = CorpusSpec { docs: 500, vocab: 3000, files: 3, ..CorpusSpec::default() }
+        .generate(&root.join("inp"))
+        .await?;
+
+    // A tree that holds everything, so the buffered build cuts no document
+    // between buffers.
+    let config = IndexerConfig::new(1_000_000, 6)?;
+    let mut built = Vec::new();
+    for (name, below) in [("buffered", 1), ("in_memory", 1 << 30)] {
+        let destination = root.join(format!("res_{name}")).to_str().unwrap().to_string();
+        let buffer = root.join(format!("buffer_{name}"));
+        ParseController::<IndexParser, _, _>::new(
+            corpus.files.clone(),
+            destination.clone(),
+            buffer.to_str().unwrap().to_string(),
+            1,
+            IndexedBuilder::new(config, corpus.attributes.clone())?,
+            IndexMerger::new(config.merger()).with_idf_top(10),
+        )
+        .with_in_memory_below(below)
+        .create_dictionary()
+        .await?;
+        assert_eq!(fs::metadata(&buffer).await.is_ok(), name == "buffered");
+        verify_index::<CommonSegments>(&destination).await?;
+        let layout = IndexLayout::V1;
+        let mut files = Vec::new();
+        for path in [
+            layout.dictionary(&destination),
+            layout.lexical_part(&destination),
+            layout.index_part(&destination),
+            layout.lengths(&destination),
+            layout.files(&destination),
+            layout.info(&destination),
+        ] {
+            files.push(fs::read(path).await?);
+        }
+        built.push(files);
+    }
+    assert!(built[0] == built[1]);
+    fs::remove_dir_all(&root).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn reader_tst() -> Result<(), Error> {
+    let root = scratch("reader_tst").await?;
+    let path = root.join("tar.txt");
+    let mut wr = CountedWriter::new(BufWriter::new(File::create(&path).await?));
+    wr.push_u64(3).await?;
+    wr.push_u64(5).await?;
+    wr.push_u64(6).await?;
+    wr.flush().await?;
+    wr.goto(0).await?;
+    wr.push_u64(10).await?;
+    assert_eq!(wr.passed(), 8);
+    wr.flush().await?;
+
+    let mut reader = BufReader::new(File::open(&path).await?);
+    let mut written = Vec::new();
+    for _ in 0..3 {
+        written.push(reader.read_u64().await?);
+    }
+    assert_eq!(written, [10, 5, 6]);
+    assert_eq!(fs::metadata(&path).await?.len(), 24);
+    fs::remove_dir_all(&root).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn buffered_flush_matches_async_writers() -> Result<(), Error> {
+    use save::writer::variable_save_u64;
+
+    let root = scratch("buffered_flush").await?;
+    let path = root.join("reference.bin");
+    let mut writer = BufWriter::new(File::create(&path).await?);
+    let mut encoded = Vec::new();
+    for v in [0, 1, 127, 128, 300, 16_384, u32::MAX as u64, u64::MAX] {
+        assert_eq!(variable_save_u64(v, &mut writer).await?, variable_encode_u64(v, &mut encoded));
+    }
+    writer.write_u64(1 << 33).await?;
+    writer.write_u8(7).await?;
+    writer.write_u64(123_456).await?;
+    writer.write_u64(42).await?;
+    writer.flush().await?;
+    IndexedCursor::new(1 << 33, 7, 123_456, 42).encode(&mut encoded);
+    assert_eq!(fs::read(&path).await?, encoded);
+
+    // Every pair of neighbours shares the same prefix, so the terms make
+    // one block as large as a block gets.
+    let directory = root.join("res").to_str().unwrap().to_string();
+    fs::create_dir_all(&directory).await?;
+    let terms = (0..u8::MAX as u32)
+        .map(|i| format!("sharedprefix{}", char::from_u32(0x100 + i).unwrap()))
+        .collect::<Vec<_>>();
+    let mut saver = IndexMergeSaver::<CommonSegments>::new(directory.clone(), u8::MAX).await?;
+    for (i, t) in terms.iter().enumerate() {
+        let mut term = IndexedTerm::new(t.clone());
+        let mut usage = UsageData::new();
+        *usage.use_count_mut() = 1;
+        term.indexes.push(i, usage);
+        term.use_count = i as u64 + 1;
+        saver.push(term).await?;
+    }
+    saver.finish().await?;
+    assert_eq!(BlockDirectory::load(&directory).await?.len(), 1);
+
+    let mut reader = IndexTermProvider::<CommonSegments>::new(&directory).await?;
+    for (i, t) in terms.iter().enumerate() {
+        let term = reader.next_term().await.unwrap();
+        assert_eq!(&term.term, t);
+        assert_eq!(term.use_count, i as u64 + 1);
+        assert_eq!(term.indexes.iter().map(|v| v.0).collect::<Vec<_>>(), [i]);
+    }
+    assert!(reader.next_term().await.is_none());
+    fs::remove_dir_all(&root).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn long_shared_prefixes_are_clamped() -> Result<(), Error> {
+    assert_eq!((count_same("aé", "aé", 2), count_same("aé", "aé", 3)), (1, 3));
+    assert_eq!((floor_char_boundary("aé", 2), floor_char_boundary("aé", 9)), (1, 3));
+
+    let root = scratch("shared_prefix").await?;
+    // Neighbours sharing more than a u16 counts, and prefixes the clamp
+    // ends inside a two and a three byte character.
+    let long = "x".repeat(70_000);
+    let mut terms = vec![format!("{long}a"), format!("{long}b"), format!("{long}c")];
+    for (pad, c) in [(254, 'é'), (253, '€'), (254, '€')] {
+        let prefix = format!("{}{c}", "y".repeat(pad));
+        terms.extend(["1", "2"].map(|v| format!("{prefix}{v}")));
+    }
+    terms.sort_by(|a, b| term_cmp(a, b));
+    for max in [MAX_SHARED_PREFIX, 3, 0] {
+        let directory = root.join(format!("max_{max}")).to_str().unwrap().to_string();
+        fs::create_dir_all(&directory).await?;
+        let mut saver = IndexMergeSaver::<CommonSegments>::new(directory.clone(), 6)
+            .await?
+            .with_max_shared_prefix(max);
+        for (i, t) in terms.iter().enumerate() {
+            let mut term = IndexedTerm::new(t.clone());
+            let mut usage = UsageData::new();
+            *usage.use_count_mut() = 1;
+            term.indexes.push(i, usage);
+            term.use_count = 1;
+            saver.push(term).await?;
+        }
+        saver.finish().await?;
+        verify_index::<CommonSegments>(&directory).await?;
+
+        let mut reader = IndexTermProvider::<CommonSegments>::new(&directory).await?;
+        for t in terms.iter() {
+            assert_eq!(&reader.next_term().await.unwrap().term, t, "{max}");
+        }
+        assert!(reader.next_term().await.is_none());
+        let mut dictionary = Dictionary::<CommonSegments>::new(&directory).await?;
+        for (i, t) in terms.iter().enumerate() {
+            let found = dictionary.find(t).await?.unwrap();
+            assert_eq!(found.indexes.iter().map(|v| v.0).collect::<Vec<_>>(), [i], "{max}");
+        }
+        // No more than the clamp is shared, so the long terms are mostly written whole.
+        let lexical = fs::metadata(IndexLayout::default().lexical_part(&directory)).await?.len();
+        assert!(lexical >= 3 * (70_001 - max as u64), "{lexical} bytes with {max}");
+    }
+    fs::remove_dir_all(&root).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn cursors_sit_on_fixed_boundaries() -> Result<(), Error> {
+    let mut saved = Vec::new();
+    let cursor = IndexedCursor::new(1 << 40, u8::MAX, 77, 3);
+    cursor.save(&mut saved).await?;
+    assert_eq!(saved.len(), IndexedCursor::SERIALIZED_SIZE);
+    assert_eq!(IndexedCursor::load(&mut saved.as_slice()).await?, cursor);
+
+    let root = scratch("cursor_boundaries").await?;
+    let corpus = CorpusSpec::default().generate(&root.join("inp")).await?;
+    let destination = corpus.index(&root).await?;
+    let path = IndexLayout::V1.dictionary(&destination);
+    let mut file = BufReader::new(File::open(&path).await?);
+    let declared = file.read_u64().await?;
+    assert_eq!(declared, corpus.postings.len() as u64);
+    assert_eq!(IndexedCursor::offset(declared), fs::metadata(&path).await?.len());
+    let mut sequential = Vec::new();
+    for _ in 0..declared {
+        sequential.push(IndexedCursor::load(&mut file).await?);
+    }
+    for (ordinal, expected) in sequential.iter().enumerate().rev() {
+        IndexedCursor::seek_to(&mut file, ordinal).await?;
+        assert_eq!(&IndexedCursor::load(&mut file).await?, expected);
+    }
+    let counts = corpus.postings.values().map(|v| v.iter().map(|p| p.1 as u64).sum::<u64>());
+    assert!(sequential.iter().map(|c| c.use_count()).eq(counts));
+    fs::remove_dir_all(&root).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+fn parsed_fixture(documents: usize) -> String {
+    (0..documents)
+        .map(|i| {
+            format!(
+                "<title>\nword{} shared\n</title>\n<text>\nbody{i} shared text{}\n</text>\n",
+                i % 7,
+                i % 13
+            )
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn estimated_bytes_track_tree() -> Result<(), Error> {
+    let root = std::env::temp_dir().join(format!("estimated_bytes_{}", std::process::id()));
+    fs::create_dir_all(&root).await?;
+    let path = root.join("0.xml");
+    fs::write(&path, parsed_fixture(50)).await?;
+
+    let mut builder = IndexedBuilder::new(
+        IndexerConfig::new(100_000, 6)?,
+        ZoneSet::new(["title", "text"])?,
+    )?;
+    let mut parser = builder.build();
+    let mut reader = builder.reader_from_file(&path, Warnings::default()).await?;
+    assert_eq!((parser.len(), parser.estimated_bytes()), (0, 0));
+    let mut ind = 0;
+    let mut previous = 0;
+    while parser.parse(&mut reader, ind).await == ParserCallback::ZoneEnd {
+        assert!(parser.estimated_bytes() > previous);
+        previous = parser.estimated_bytes();
+        ind += 1;
+    }
+    assert_eq!(ind, 50);
+
+    let stored = parser
+        .b_tree
+        .iter()
+        .map(|(k, v)| {
+            k.len() + v.indexes.len() * size_of::<(usize, UsageData<CommonSegments>)>()
+        })
+        .sum::<usize>();
+    assert_eq!(parser.len(), parser.b_tree.len());
+    assert!(parser.estimated_bytes() >= stored);
+    assert!(parser.estimated_bytes() <= 4 * stored);
+
+    parser
+        .flush_to(&root.join("buffer").to_str().unwrap().to_string())
+        .await?;
+    assert_eq!((parser.len(), parser.estimated_bytes()), (0, 0));
+    fs::remove_dir_all(&root).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+struct RecordingMerger(Arc<Mutex<Vec<String>>>);
+
+#[cfg(test)]
+#[async_trait]
+impl Merger for RecordingMerger {
+    type Parser = IndexParser;
+
+    async fn merge(
+        &mut self,
+        _: Arc<Mutex<IndexPositions>>,
+        buffer_files: Arc<Mutex<Vec<String>>>,
+        destination: String,
+        _: &CancellationToken,
+    ) -> Result<(), Error> {
+        fs::create_dir_all(&destination).await?;
+        self.0
+            .lock()
+            .await
+            .extend(buffer_files.lock().await.iter().cloned());
+        remove_buffer(&buffer_files).await;
+        Ok(())
+    }
+
+    async fn write_terms(
+        &mut self,
+        _: Arc<Mutex<IndexPositions>>,
+        _: BTreeMap<String, IndexedTerm<CommonSegments>>,
+        destination: String,
+        _: &CancellationToken,
+    ) -> Result<(), Error> {
+        fs::create_dir_all(&destination).await
+    }
+}
+
+#[test]
+fn tree_terms_are_stored_once() {
+    use crate::testsupport::{allocations, word};
+
+    let mut parser = IndexParser::new(IndexerConfig::new(100_000, 6).unwrap(), CommonSegmentSelector::new());
+    let applier = parser.segment_selector.applier_for("text");
+    let (terms, documents) = (1000, 10);
+    let words = (0..documents)
+        .map(|_| (0..terms).map(word).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let before = allocations();
+    for (document, words) in words.into_iter().enumerate() {
+        for w in words {
+            parser.insert(w, document, applier);
+        }
+    }
+    let used = allocations() - before;
+    // A posting node each, and the tree and term lists growing: no copies
+    // of the words, which took another allocation per term and posting.
+    let postings = terms * documents;
+    assert!(used < postings + postings / 10, "{used} allocations for {postings} postings");
+    assert_eq!(parser.len(), terms);
+    assert!(parser.b_tree.keys().all(|v| !v.is_heap_allocated()));
+}
+
+#[tokio::test]
+async fn empty_parsers_are_not_flushed() -> Result<(), Error> {
+    use crate::{parser::ParseController, sample::Sampling};
+
+    let root = std::env::temp_dir().join(format!("empty_parsers_{}", std::process::id()));
+    fs::create_dir_all(&root).await?;
+    let path = root.join("0.xml").to_str().unwrap().to_string();
+    fs::write(&path, parsed_fixture(3)).await?;
+
+    let config = IndexerConfig::new(1000, 6)?;
+    for (sampling, buffers) in [(None, 1), (Some(Sampling::new(1e-12, 1)?), 0)] {
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        let mut controller = ParseController::<IndexParser, _, _>::new(
+            vec![path.clone()],
+            root.join("res").to_str().unwrap().to_string(),
+            root.join("buffer").to_str().unwrap().to_string(),
+            4,
+            IndexedBuilder::new(config, ZoneSet::new(["title", "text"])?)?,
+            RecordingMerger(recorded.clone()),
+        );
+        if let Some(sampling) = sampling {
+            controller = controller.with_sampling(sampling);
+        }
+        controller.create_dictionary().await?;
+        assert_eq!(recorded.lock().await.len(), buffers);
+    }
+
+    fs::remove_dir_all(&root).await?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn non_utf8_file_names_are_indexed() -> Result<(), Error> {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    use crate::{parser::ParseController, provenance::verify_inputs, report::ParseReport, resolve::load_input_files};
+
+    let root = scratch("non_utf8_names").await?;
+    let inputs = root.join("inp");
+    fs::create_dir_all(&inputs).await?;
+    fs::write(inputs.join("0.xml"), "<title>\nplain\n</title>\n<text>\nfirst\n</text>\n").await?;
+    let odd = inputs.join(OsStr::from_bytes(b"caf\xe9.xml"));
+    fs::write(&odd, "<title>\nlatin\n</title>\n<text>\nsecond\n</text>\n").await?;
+    let mut files = Vec::new();
+    let mut entries = fs::read_dir(&inputs).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        files.push(entry.path());
+    }
+    files.sort_unstable();
+
+    let destination = root.join("res").to_str().unwrap().to_string();
+    let config = IndexerConfig::new(1000, 6)?;
+    ParseController::<IndexParser, _, _>::new(
+        files,
+        destination.clone(),
+        root.join("buffer").to_str().unwrap().to_string(),
+        2,
+        IndexedBuilder::new(config, ZoneSet::new(["title", "text"])?)?,
+        IndexMerger::new(config.merger()),
+    )
+    .create_dictionary()
+    .await?;
+
+    let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+    assert!(dictionary.find("second").await?.is_some());
+    let encoded = paths::encode(&odd);
+    assert!(encoded.ends_with("/caf%E9.xml"), "{encoded}");
+    let report = ParseReport::load(&destination).await?;
+    assert!(report.files.iter().any(|v| v.path == encoded && v.documents == 1));
+    let sources = load_input_files(IndexLayout::V1.files(&destination)).await?;
+    assert!(sources.iter().any(|(path, _)| *path == encoded));
+    assert!(verify_inputs(&destination).await?.is_clean());
+    fs::remove_dir_all(&root).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn the_last_buffer_of_a_task_is_inside_the_buffer_directory() -> Result<(), Error> {
+    use crate::parser::ParseController;
+
+    let root = std::env::temp_dir().join(format!("last_buffer_{}", std::process::id()));
+    fs::create_dir_all(&root).await?;
+    let path = root.join("0.xml").to_str().unwrap().to_string();
+    fs::write(&path, parsed_fixture(3)).await?;
+    let buffer = root.join("buffer");
+    let recorded = Arc::new(Mutex::new(Vec::new()));
+    // Too few terms to fill the tree, so the only buffer is the one the
+    // task flushes once it runs out of files.
+    ParseController::<IndexParser, _, _>::new(
+        vec![path],
+        root.join("res").to_str().unwrap().to_string(),
+        buffer.to_str().unwrap().to_string(),
+        1,
+        IndexedBuilder::new(IndexerConfig::new(1000, 6)?, ZoneSet::new(["title", "text"])?)?,
+        RecordingMerger(recorded.clone()),
+    )
+    .create_dictionary()
+    .await?;
+    assert_eq!(*recorded.lock().await, [buffer.join("0").to_str().unwrap()]);
+
+    fs::remove_dir_all(&root).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn chunks_of_swapped_zones_fail_the_build() -> Result<(), Error> {
+    use crate::{parser::ParseController, rep_reader::ZoneMismatch};
+
+    let root = scratch("swapped_zones").await?;
+    let path = root.join("0.xml").to_str().unwrap().to_string();
+    fs::write(&path, "<text>\nbody\n</text>\n<title>\nheading\n</title>\n").await?;
+    let config = IndexerConfig::new(1000, 6)?;
+    let e = ParseController::<IndexParser, _, _>::new(
+        vec![path],
+        root.join("res").to_str().unwrap().to_string(),
+        root.join("buffer").to_str().unwrap().to_string(),
+        1,
+        IndexedBuilder::new(config, ZoneSet::new(["title", "text"])?)?.with_checked_zones(),
+        IndexMerger::new(config.merger()),
+    )
+    .create_dictionary()
+    .await
+    .unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidData);
+    assert!(e.get_ref().is_some_and(|v| v.is::<ZoneMismatch>()), "{e}");
+
+    fs::remove_dir_all(&root).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn documents_outgrowing_the_tree_keep_their_id() -> Result<(), Error> {
+    use crate::{parser::ParseController, rank::DocumentLengths, testsupport::word, titles::DocumentTitles};
+
+    let root = scratch("outgrown_tree").await?;
+    let path = root.join("0.xml").to_str().unwrap().to_string();
+    let huge = (0..500).map(word).collect::<Vec<_>>();
+    fs::write(
+        &path,
+        format!(
+            "<title>\nsmall\n</title>\n<text>\nfirst shared\n</text>\n\
+             <title>\nhuge\n</title>\n<text>\nshared {} shared\n</text>\n\
+             <title>\nafter\n</title>\n<text>\nlast shared\n</text>\n",
+            huge.join(" ")
+        ),
+    )
+    .await?;
+    let destination = root.join("res").to_str().unwrap().to_string();
+    let config = IndexerConfig::new(50, 6)?;
+    ParseController::<IndexParser, _, _>::new(
+        vec![path],
+        destination.clone(),
+        root.join("buffer").to_str().unwrap().to_string(),
+        1,
+        IndexedBuilder::new(config, ZoneSet::new(["title", "text"])?)?,
+        IndexMerger::new(config.merger()),
+    )
+    .with_titles()
+    .create_dictionary()
+    .await?;
+
+    let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+    async fn documents(dictionary: &mut Dictionary<CommonSegments>, term: &str) -> Result<Option<Vec<usize>>, Error> {
+        Ok(dictionary.find(term).await?.map(|v| v.indexes.iter().map(|v| v.0).collect()))
+    }
+    for term in huge.iter().map(String::as_str).chain(["huge"]) {
+        assert_eq!(documents(&mut dictionary, term).await?, Some(vec![1]), "{term}");
+    }
+    for (term, expected) in [("small", vec![0]), ("after", vec![2]), ("last", vec![2]), ("shared", vec![0, 1, 2])] {
+        assert_eq!(documents(&mut dictionary, term).await?, Some(expected), "{term}");
+    }
+
+    let lengths = DocumentLengths::load(&destination).await?;
+    assert_eq!(lengths.lengths[..3], [3, 503, 3]);
+    let titles = DocumentTitles::load(&destination).await?;
+    assert_eq!([titles.get(0), titles.get(1), titles.get(2)], [Some("small"), Some("huge"), Some("after")]);
+
+    fs::remove_dir_all(&root).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn stale_buffers_and_indexes_are_refused() -> Result<(), Error> {
+    use crate::parser::ParseController;
+
+    let root = scratch("stale_runs").await?;
+    let corpus = CorpusSpec { docs: 6, ..CorpusSpec::default() }.generate(&root.join("corpus")).await?;
+    let destination = root.join("res").to_str().unwrap().to_string();
+    let buffer = root.join("buffer");
+    let config = IndexerConfig::new(1000, 6)?;
+    let controller = || -> Result<_, Error> {
+        Ok(ParseController::<IndexParser, _, _>::new(
+            corpus.files.clone(),
+            destination.clone(),
+            buffer.to_str().unwrap().to_string(),
+            1,
+            IndexedBuilder::new(config, corpus.attributes.clone())?,
+            IndexMerger::new(config.merger()),
+        ))
+    };
+    controller()?.create_dictionary().await?;
+    assert!(fs::read_dir(&buffer).await?.next_entry().await?.is_none());
+
+    let error = controller()?.create_dictionary().await.unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+    assert_eq!(error.to_string(), format!("{destination} already holds an index"));
+    fs::write(format!("{destination}/phonetic_part.txt"), "left over").await?;
+    controller()?.with_overwrite().create_dictionary().await?;
+    assert!(fs::metadata(format!("{destination}/phonetic_part.txt")).await.is_err());
+    verify_index::<CommonSegments>(&destination).await?;
+
+    fs::remove_dir_all(&destination).await?;
+    fs::create_dir_all(buffer.join("0")).await?;
+    fs::write(buffer.join("0/dictionary.txt"), "stale").await?;
+    fs::write(buffer.join("notes"), "stale").await?;
+    let error = controller()?.create_dictionary().await.unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+    assert!(error.to_string().ends_with("holds 2 entries of an earlier run"), "{error}");
+    assert!(fs::metadata(&destination).await.is_err());
+    controller()?.with_clean_buffer().create_dictionary().await?;
+    assert!(fs::read_dir(&buffer).await?.next_entry().await?.is_none());
+    let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+    for (term, documents) in corpus.postings.iter() {
+        let found = dictionary.find(term).await?.unwrap();
+        assert_eq!(found.indexes.len(), documents.len(), "{term}");
+    }
+
+    fs::remove_dir_all(&root).await?;
+    Ok(())
+}
+
+#[test]
+fn blocking_build_matches_async() -> Result<(), Error> {
+    use std::collections::BTreeMap;
+
+    use crate::{metadata::IndexMetadata, parser::ParseController};
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    let root = runtime.block_on(scratch("blocking_build"))?;
+    let corpus = runtime.block_on(CorpusSpec::default().generate(&root.join("corpus")))?;
+    let config = IndexerConfig::new(40, 6)?;
+    let controller = |name: &str| -> Result<_, Error> {
+        Ok(ParseController::<IndexParser, _, _>::new(
+            corpus.files.clone(),
+            root.join(name).to_str().unwrap().to_string(),
+            root.join(format!("{name}_buffer")).to_str().unwrap().to_string(),
+            1,
+            IndexedBuilder::new(config, corpus.attributes.clone())?,
+            IndexMerger::new(config.merger()).with_phonetic(),
+        )
+        .with_titles())
+    };
+    runtime.block_on(controller("async")?.create_dictionary())?;
+    drop(runtime);
+    controller("blocking")?.create_dictionary_blocking()?;
+
+    let files = |name: &str| -> Result<BTreeMap<String, Vec<u8>>, Error> {
+        let mut files = BTreeMap::new();
+        for entry in std::fs::read_dir(root.join(name))? {
+            let entry = entry?;
+            files.insert(entry.file_name().into_string().unwrap(), std::fs::read(entry.path())?);
+        }
+        Ok(files)
+    };
+    let (mut built, mut blocking) = (files("async")?, files("blocking")?);
+    assert!(built.len() > 10, "{:?}", built.keys());
+    // Only the build stamp tells the two apart.
+    let mut metadata = Vec::new();
+    for files in [&mut built, &mut blocking] {
+        let raw = files.remove("metadata.json").unwrap();
+        let mut read: IndexMetadata = serde_json::from_slice(&raw).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        read.generation = 0;
+        metadata.push(read);
+    }
+    assert_eq!(metadata[0], metadata[1]);
+    assert_eq!(built.keys().collect::<Vec<_>>(), blocking.keys().collect::<Vec<_>>());
+    for (name, content) in built.iter() {
+        assert!(content == &blocking[name], "{name} differs");
+    }
+
+    std::fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn postings_stream_like_the_full_list() -> Result<(), Error> {
+    use crate::{parser::ParseController, titles::DocumentTitles};
+
+    let root = std::env::temp_dir().join(format!("postings_iter_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root).await;
+    fs::create_dir_all(&root).await?;
+    let path = root.join("0.xml").to_str().unwrap().to_string();
+    fs::write(&path, parsed_fixture(300)).await?;
+    let destination = root.join("res").to_str().unwrap().to_string();
+    let config = IndexerConfig::new(1000, 6)?;
+    ParseController::<IndexParser, _, _>::new(
+        vec![path],
+        destination.clone(),
+        root.join("buffer").to_str().unwrap().to_string(),
+        1,
+        IndexedBuilder::new(config, ZoneSet::new(["title", "text"])?)?,
+        IndexMerger::new(config.merger()),
+    )
+    .with_titles()
+    .create_dictionary()
+    .await?;
+
+    let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+    for term in ["shared", "body", "text", "word"] {
+        let expected = dictionary
+            .find(term)
+            .await?
+            .unwrap()
+            .indexes
+            .iter_ref()
+            .map(|(document, usage)| (*document, usage.to_string()))
+            .collect::<Vec<_>>();
+        let mut postings = dictionary.postings(term).await?.unwrap();
+        assert_eq!(postings.len(), expected.len());
+        let mut streamed = Vec::new();
+        while let Some((document, usage)) = postings.next().await? {
+            streamed.push((document, usage.to_string()));
+        }
+        assert_eq!(streamed, expected, "{term}");
+        assert!(postings.next().await?.is_none());
+    }
+    assert!(dictionary.postings("missing").await?.is_none());
+
+    let mut postings = dictionary.postings("shared").await?.unwrap();
+    assert_eq!(postings.len(), 300);
+    let mut first = Vec::new();
+    for _ in 0..3 {
+        first.push(postings.next().await?.map(|(document, usage)| (document, usage.use_count())));
+    }
+    assert_eq!(first, [Some((0, 2)), Some((1, 2)), Some((2, 2))]);
+    assert_eq!(postings.advance_to(2).await?.map(|v| v.0), Some(3));
+    assert_eq!(postings.advance_to(250).await?.map(|v| v.0), Some(250));
+    assert!(postings.advance_to(300).await?.is_none());
+    assert_eq!(dictionary.find("body").await?.unwrap().indexes.len(), 300);
+
+    let titles = DocumentTitles::load(&destination).await?;
+    assert_eq!(titles.get(4), Some("word shared"));
+
+    fs::remove_dir_all(&root).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn batched_lookups_match_single_ones() -> Result<(), Error> {
+    let root = scratch("find_many").await?;
+    let corpus = CorpusSpec {
+        docs: 200,
+        vocab: 3000,
+        zipf_s: 0.5,
+        ..CorpusSpec::default()
     }
+    .generate(&root.join("corpus"))
+    .await?;
+    let destination = corpus.index(&root).await?;
+    let keys = corpus.postings.keys().cloned().collect::<Vec<_>>();
+    assert!(keys.len() > 1000, "{}", keys.len());
 
-    async fn get_term(&mut self, cursor: IndexedCursor) -> Result<IndexedTerm<S>, Error> {
-        self.lexical_part
-            .seek(SeekFrom::Start(cursor.lexical_pointer as u64))
-            .await?;
-        let mut start = String::new();
-        let mut index = variable_load(&mut self.lexical_part).await?;
-        while index > 0 {
-            let next_char = read_char_reader(&mut self.lexical_part).await?;
-            index -= next_char.len_utf8();
-            start.push(next_char);
-        }
+    // A run of neighbouring terms with a miss and a repeat, and a spread too
+    // wide to sweep.
+    let mut clustered = keys[400..420].iter().rev().cloned().collect::<Vec<_>>();
+    clustered.extend([format!("{}~", keys[410]), keys[404].clone()]);
+    let spread = vec![
+        keys[0].clone(),
+        "".to_string(),
+        keys[keys.len() - 1].clone(),
+        keys[700].clone(),
+        "zzzzzz".to_string(),
+    ];
 
-        for _ in 0..cursor.lexical_index {
-            let skip = variable_load(&mut self.lexical_part).await?;
-            self.lexical_part
-                .seek(SeekFrom::Current(skip as i64))
-                .await?;
-        }
-        let mut index = variable_load(&mut self.lexical_part).await?;
-        while index > 0 {
-            let next_char = read_char_reader(&mut self.lexical_part).await?;
-            index -= next_char.len_utf8();
-            start.push(next_char);
+    async fn single(
+        dictionary: &mut Dictionary<CommonSegments>,
+        terms: &[&str],
+    ) -> Result<Vec<Option<(String, Vec<usize>)>>, Error> {
+        let mut found = Vec::new();
+        for term in terms {
+            found.push(
+                dictionary
+                    .find(term)
+                    .await?
+                    .map(|v| (v.term, v.indexes.iter().map(|v| v.0).collect())),
+            );
         }
+        Ok(found)
+    }
+    let mut dictionaries = vec![Dictionary::<CommonSegments>::new(&destination).await?];
+    fs::remove_file(format!("{destination}/block_dir.bin")).await?;
+    dictionaries.push(Dictionary::<CommonSegments>::new(&destination).await?);
+    for dictionary in dictionaries.iter_mut() {
+        for terms in [&clustered, &spread] {
+            let terms = terms.iter().map(String::as_str).collect::<Vec<_>>();
+            let before = dictionary.reads();
+            let expected = single(dictionary, &terms).await?;
+            let single_reads = dictionary.reads() - before;
 
-        self.index_part
-            .seek(SeekFrom::Start(cursor.indexes_pointer as u64))
-            .await?;
-        let list =
-            SortedLinkedMap::<usize, UsageData<S>>::variable_load(&mut self.index_part).await?;
+            let before = dictionary.reads();
+            let cursors = dictionary.find_many(&terms).await?;
+            let cursor_reads = dictionary.reads() - before;
+            let before = dictionary.reads();
+            let found = dictionary.get_terms(cursors).await?;
+            let term_reads = dictionary.reads() - before;
+            let found = found
+                .into_iter()
+                .map(|v| v.map(|v| (v.term, v.indexes.iter().map(|v| v.0).collect())))
+                .collect::<Vec<_>>();
+            assert_eq!(found, expected);
 
-        Ok(IndexedTerm {
-            term: start,
-            use_count: cursor.use_count as u64,
-            indexes: list,
-        })
+            // Without a block directory, deciding against a sweep costs the
+            // two searches for its bounds.
+            let probe = 4 * (usize::BITS - dictionary.len().leading_zeros()) as u64;
+            assert!(
+                cursor_reads + term_reads <= single_reads + probe,
+                "{cursor_reads} + {term_reads} vs {single_reads}"
+            );
+            let hits = expected.iter().filter(|v| v.is_some()).count() as u64;
+            if terms.len() == clustered.len() {
+                assert!(
+                    cursor_reads * 4 < single_reads - 2 * hits,
+                    "{cursor_reads} vs {single_reads}"
+                );
+                assert!(term_reads < 2 * hits, "{term_reads} for {hits}");
+            }
+        }
+        let terms = spread.iter().map(String::as_str).collect::<Vec<_>>();
+        let found = dictionary.find_terms(&terms).await?;
+        assert_eq!(
+            found
+                .iter()
+                .map(|v| v.as_ref().map(|v| v.term.as_str()))
+                .collect::<Vec<_>>(),
+            [Some(terms[0]), None, Some(terms[2]), Some(terms[3]), None]
+        );
+        assert_eq!(dictionary.find_terms(&[]).await?.len(), 0);
     }
-}
 
-pub struct IndexTermProvider<S: Segments> {
-    dictionary: Dictionary<S>,
-    first_part: String,
-    first_part_pointer: Option<usize>,
-    remaining_size: usize,
-    segment_date: PhantomData<S>,
+    fs::remove_dir_all(&root).await?;
+    Ok(())
 }
 
-impl<S: Segments> IndexTermProvider<S> {
-    pub async fn new(directory: &String) -> Result<Self, Error> {
-        let mut dictionary = Dictionary::new(directory).await?;
-        let remaining_size = dictionary.pointer_part.read_u64().await? as usize;
-        Ok(Self {
-            dictionary,
-            first_part: String::new(),
-            first_part_pointer: None,
-            remaining_size,
-            segment_date: PhantomData::<S>,
-        })
+#[tokio::test]
+async fn block_cache_reads_blocks_once() -> Result<(), Error> {
+    let root = scratch("block_cache").await?;
+    let corpus = CorpusSpec {
+        docs: 60,
+        vocab: 500,
+        zipf_s: 0.3,
+        ..CorpusSpec::default()
+    }
+    .generate(&root.join("corpus"))
+    .await?;
+    let destination = corpus.index(&root).await?;
+    let mut prefixes = corpus
+        .postings
+        .keys()
+        .step_by(25)
+        .map(|v| v.chars().take(2).collect::<String>())
+        .collect::<Vec<_>>();
+    prefixes.extend(["", "zzz"].map(String::from));
+
+    async fn scan(
+        dictionary: &mut Dictionary<CommonSegments>,
+        prefixes: &[String],
+    ) -> Result<(Vec<Vec<String>>, u64), Error> {
+        let before = dictionary.reads();
+        let mut found = Vec::new();
+        for prefix in prefixes {
+            found.push(dictionary.terms_with_prefix(prefix).await?);
+        }
+        Ok((found, dictionary.reads() - before))
+    }
+    let mut plain = Dictionary::<CommonSegments>::new(&destination).await?;
+    let (expected, plain_reads) = scan(&mut plain, &prefixes).await?;
+
+    // Every term costs a cursor read and a lexical one; once its block is
+    // held only the cursor is read.
+    let cache = BlockCache::new(1024);
+    let mut cached = Dictionary::<CommonSegments>::new(&destination)
+        .await?
+        .with_block_cache(cache.clone());
+    let (found, first_reads) = scan(&mut cached, &prefixes).await?;
+    assert_eq!(found, expected);
+    assert!(first_reads < plain_reads, "{first_reads} vs {plain_reads}");
+    let (found, second_reads) = scan(&mut cached, &prefixes).await?;
+    assert_eq!(found, expected);
+    assert_eq!(second_reads * 2, plain_reads);
+    let stats = cache.stats();
+    assert!(stats.hits > stats.misses, "{stats:?}");
+    assert_eq!(stats.evictions, 0);
+
+    let mut other = Dictionary::<CommonSegments>::new(&destination)
+        .await?
+        .with_block_cache(cache.clone());
+    assert_eq!(scan(&mut other, &prefixes).await?, (expected.clone(), second_reads));
+    for term in corpus.postings.keys().chain([&"~".to_string()]) {
+        assert_eq!(
+            other.find(term).await?.map(|v| v.term),
+            plain.find(term).await?.map(|v| v.term)
+        );
     }
+
+    cache.set_capacity(2);
+    assert_eq!(scan(&mut cached, &prefixes).await?.0, expected);
+    assert!(cache.stats().evictions > 0);
+    assert_eq!(cache.stats().blocks, 2);
+    cache.set_capacity(0);
+    assert_eq!(scan(&mut cached, &prefixes).await?, (expected, plain_reads));
+
+    fs::remove_dir_all(&root).await?;
+    Ok(())
 }
 
-#[async_trait]
-impl<S: Segments> TermProvider for IndexTermProvider<S> {
-    type Term = IndexedTerm<S>;
+#[tokio::test]
+async fn ranges_hold_their_bounds() -> Result<(), Error> {
+    let root = scratch("term_range").await?;
+    let corpus = CorpusSpec {
+        docs: 60,
+        vocab: 500,
+        zipf_s: 0.3,
+        ..CorpusSpec::default()
+    }
+    .generate(&root.join("corpus"))
+    .await?;
+    let destination = corpus.index(&root).await?;
+    let keys = corpus
+        .postings
+        .keys()
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    let between = format!("{}~", keys[30]);
 
-    async fn next_term(&mut self) -> Option<Self::Term> {
-        // if let Some(st) = read_line(&mut self.reader).await {
-        //     let use_count = read_line(&mut self.reader)
-        //         .await
-        //         .unwrap()
-        //         .parse::<u64>()
-        //         .unwrap();
-
-        //     let index = read_line(&mut self.reader)
-        //         .await
-        //         .unwrap()
-        //         .parse::<usize>()
-        //         .unwrap();
-        //     let mut list = SortedLinkedList::<usize>::new();
-        //     for _ in 0..index {
-        //         let next = read_to_space(&mut self.reader).await.unwrap();
-        //         list.push(next.parse::<usize>().unwrap());
-        //     }
-
-        //     Some(IndexedTerm {
-        //         term: st,
-        //         use_count,
-        //         indexes: list,
-        //     })
-        // } else {
-        //     None
-        // }
-        // IndexedCursor::load(self.)
-        // let load = variable_load(&mut self.pointer_part).await.ok()?;
-        if self.remaining_size == 0 {
-            return None;
+    async fn collect(
+        range: &mut TermRange<'_, CommonSegments>,
+    ) -> Result<Vec<(String, TermStats)>, Error> {
+        let mut found = Vec::new();
+        while let Some(v) = range.next().await? {
+            found.push(v);
         }
-        let next = IndexedCursor::load(&mut self.dictionary.pointer_part)
-            .await
-            .ok()?;
-        // dbg!(&next);
-        if self.first_part_pointer.is_none()
-            || self.first_part_pointer.unwrap() != next.lexical_pointer
-        {
-            self.first_part.clear();
-            self.first_part_pointer = Some(next.lexical_pointer as usize);
-            // dbg!("var");
-            let mut skip = variable_load(&mut self.dictionary.lexical_part)
-                .await
-                .ok()?;
-            // dbg!(skip);
-            while skip > 0 {
-                let next_char = read_char_reader(&mut self.dictionary.lexical_part)
-                    .await
-                    .ok()?;
-                skip -= next_char.len_utf8();
-                self.first_part.push(next_char);
+        assert!(range.next().await?.is_none());
+        Ok(found)
+    }
+    let cases = [
+        (TermBound::Unbounded, TermBound::Unbounded, &keys[..]),
+        (
+            TermBound::Included(keys[10]),
+            TermBound::Excluded(keys[50]),
+            &keys[10..50],
+        ),
+        (
+            TermBound::Excluded(keys[10]),
+            TermBound::Included(keys[50]),
+            &keys[11..=50],
+        ),
+        (
+            TermBound::Included(&between),
+            TermBound::Included(&between),
+            &keys[..0],
+        ),
+        (
+            TermBound::Excluded(&between),
+            TermBound::Unbounded,
+            &keys[31..],
+        ),
+        (
+            TermBound::Unbounded,
+            TermBound::Excluded(keys[0]),
+            &keys[..0],
+        ),
+        (
+            TermBound::Included(keys[keys.len() - 1]),
+            TermBound::Included("zzzzzz"),
+            &keys[keys.len() - 1..],
+        ),
+        (
+            TermBound::Included(keys[40]),
+            TermBound::Included(keys[20]),
+            &keys[..0],
+        ),
+        (
+            TermBound::Included(""),
+            TermBound::Included(keys[0]),
+            &keys[..1],
+        ),
+    ];
+    let mut dictionaries = vec![Dictionary::<CommonSegments>::new(&destination).await?];
+    fs::remove_file(format!("{destination}/block_dir.bin")).await?;
+    dictionaries.push(Dictionary::<CommonSegments>::new(&destination).await?);
+    for dictionary in dictionaries.iter_mut() {
+        for (start, end, expected) in cases.iter() {
+            let found = collect(&mut dictionary.range(*start, *end).await?).await?;
+            assert_eq!(
+                found.iter().map(|v| v.0.as_str()).collect::<Vec<_>>(),
+                *expected,
+                "{start:?} {end:?}"
+            );
+            for (term, stats) in found {
+                assert_eq!(
+                    stats.document_frequency,
+                    corpus.documents(&term).len(),
+                    "{term}"
+                );
             }
         }
-        let mut term = String::new();
-        term.push_str(&self.first_part);
+        // Only the start is searched for; the rest is read in order.
+        let before = dictionary.reads();
+        let found = collect(
+            &mut dictionary
+                .range(TermBound::Included(keys[100]), TermBound::Unbounded)
+                .await?,
+        )
+        .await?;
+        assert!(dictionary.reads() - before <= 2 * 10 + 2 + found.len() as u64);
+    }
 
-        // dbg!("var");
-        let mut skip = variable_load(&mut self.dictionary.lexical_part)
-            .await
-            .ok()?;
-        // dbg!("S", skip);
-        while skip > 0 {
-            let next_char = read_char_reader(&mut self.dictionary.lexical_part)
-                .await
-                .ok()?;
-            skip -= next_char.len_utf8();
-            term.push(next_char);
+    fs::remove_dir_all(&root).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn opening_checks_every_file() -> Result<(), Error> {
+    use crate::parser::ParseController;
+
+    let root = std::env::temp_dir().join(format!("open_checked_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root).await;
+    fs::create_dir_all(&root).await?;
+    let path = root.join("0.xml").to_str().unwrap().to_string();
+    fs::write(&path, parsed_fixture(20)).await?;
+    let destination = root.join("res").to_str().unwrap().to_string();
+    let config = IndexerConfig::new(1000, 6)?;
+    ParseController::<IndexParser, _, _>::new(
+        vec![path],
+        destination.clone(),
+        root.join("buffer").to_str().unwrap().to_string(),
+        1,
+        IndexedBuilder::new(config, ZoneSet::new(["title", "text"])?)?,
+        IndexMerger::new(config.merger()),
+    )
+    .create_dictionary()
+    .await?;
+    Dictionary::<CommonSegments>::open_checked(&destination).await?;
+
+    let copy = root.join("copy").to_str().unwrap().to_string();
+    let fresh_copy = || async {
+        let _ = fs::remove_dir_all(&copy).await;
+        fs::create_dir_all(&copy).await?;
+        let mut files = fs::read_dir(&destination).await?;
+        while let Some(file) = files.next_entry().await? {
+            fs::copy(file.path(), format!("{copy}/{}", file.file_name().to_str().unwrap())).await?;
         }
+        Ok::<_, Error>(())
+    };
+    let open_error = |e: Error| (e.kind(), e.get_ref().and_then(|v| v.downcast_ref::<OpenError>()).cloned());
 
-        // dbg!("list");
-        let indexes =
-            SortedLinkedMap::<usize, UsageData<S>>::variable_load(&mut self.dictionary.index_part)
-                .await
-                .ok()?;
-        // dbg!("list end");
-        self.remaining_size -= 1;
-        Some(IndexedTerm {
-            term,
-            use_count: next.use_count as u64,
-            indexes,
-        })
+    let layout = IndexLayout::V1;
+    for file in [layout.dictionary(&copy), layout.lexical_part(&copy), layout.index_part(&copy)] {
+        fresh_copy().await?;
+        fs::remove_file(&file).await?;
+        let opened = Dictionary::<CommonSegments>::new(&copy).await.map(|_| ());
+        let expected = (ErrorKind::NotFound, Some(OpenError::Missing(file.clone())));
+        assert_eq!(opened.map_err(open_error), Err(expected.clone()));
+        let provided = IndexTermProvider::<CommonSegments>::new(&copy).await.map(|_| ());
+        assert_eq!(provided.map_err(open_error), Err(expected));
     }
+
+    fresh_copy().await?;
+    fs::write(layout.lexical_part(&copy), []).await?;
+    let e = Dictionary::<CommonSegments>::new(&copy).await.map(|_| ()).unwrap_err();
+    assert_eq!(
+        open_error(e).1,
+        Some(OpenError::TooShort { file: layout.lexical_part(&copy), len: 0, min: 1 })
+    );
+
+    fresh_copy().await?;
+    let pointers = fs::read(layout.dictionary(&copy)).await?;
+    fs::write(layout.dictionary(&copy), &pointers[..pointers.len() - 1]).await?;
+    let e = Dictionary::<CommonSegments>::new(&copy).await.map(|_| ()).unwrap_err();
+    assert!(matches!(open_error(e), (ErrorKind::InvalidData, Some(OpenError::Header { .. }))));
+    fs::write(layout.dictionary(&copy), &pointers[..4]).await?;
+    let e = Dictionary::<CommonSegments>::new(&copy).await.map(|_| ()).unwrap_err();
+    assert!(matches!(open_error(e).1, Some(OpenError::TooShort { min: 8, .. })));
+
+    fresh_copy().await?;
+    fs::write(IndexLayout::metadata(&copy), "{ not json").await?;
+    let e = Dictionary::<CommonSegments>::new(&copy).await.map(|_| ()).unwrap_err();
+    assert!(matches!(open_error(e).1, Some(OpenError::Metadata(_))));
+
+    fresh_copy().await?;
+    let postings = fs::read(layout.index_part(&copy)).await?;
+    fs::write(layout.index_part(&copy), &postings[..postings.len() / 2]).await?;
+    let last = Dictionary::<CommonSegments>::new(&copy).await?.len() - 1;
+    let e = Dictionary::<CommonSegments>::open_checked(&copy).await.map(|_| ()).unwrap_err();
+    assert!(matches!(open_error(e).1, Some(OpenError::Term { ordinal, .. }) if ordinal == last));
+
+    fs::remove_dir_all(&root).await?;
+    Ok(())
 }
 
-struct IndexMergeSaver<S: Segments> {
-    directory: String,
-    pointer_part: BufWriter<File>,
-    lexical_part: CountedWriter,
-    index_part: CountedWriter,
-    buffer_items: Vec<IndexedTerm<S>>,
-    current_substr_size: u16,
-    max_part_size: u8,
-    current_directory_size: u64,
+#[test]
+fn edit_distances() {
+    assert_eq!(edit_distance("kitten", "sitting"), 3);
+    assert_eq!(edit_distance("", "abc"), 3);
+    assert_eq!(edit_distance("shard", "shared"), 1);
+    assert_eq!(edit_distance("ёж", "еж"), 1);
 }
 
-impl<S: Segments> IndexMergeSaver<S> {
-    async fn new(directory: String, max_size: u8) -> Result<Self, Error> {
-        let mut pointer_part = BufWriter::with_capacity(
-            1024 * 1024 * 5,
-            File::create(format!("{}/dictionary.txt", &directory)).await?,
-        );
-        pointer_part.write_u64(0).await?;
-        Ok(Self {
-            pointer_part,
-            lexical_part: CountedWriter::new(BufWriter::new(
-                File::create(format!("{}/lexical_part.txt", &directory)).await?,
-            )),
-            index_part: CountedWriter::new(BufWriter::new(
-                File::create(format!("{}/index_part.txt", &directory)).await?,
-            )),
-            directory: directory,
-            buffer_items: Vec::with_capacity(max_size.into()),
-            current_substr_size: 0,
-            max_part_size: max_size,
-            current_directory_size: 0,
-        })
-    }
+#[tokio::test]
+async fn repeated_terms_are_capped() -> Result<(), Error> {
+    use crate::{metadata::IndexMetadata, parser::ParseController};
 
-    async fn flush(&mut self) -> Result<(), Error> {
-        if self.buffer_items.len() == 0 {
-            return Ok(());
-        }
-        let items = std::mem::take(&mut self.buffer_items);
-        let lexical_pointer = self.lexical_part.passed();
-        self.lexical_part
-            .push_variable_u64(self.current_substr_size as u64)
-            .await?;
-        {
-            let first_part = &items[0].term.as_str()[..self.current_substr_size as usize];
-            self.lexical_part.push(first_part.as_bytes()).await?;
-        }
-        for (i, mut v) in items.into_iter().enumerate() {
-            IndexedCursor::new(
-                lexical_pointer as usize,
-                i as u8,
-                self.index_part.passed() as usize,
-                v.use_count as usize,
-            )
-            .save(&mut self.pointer_part)
-            .await?;
-            self.index_part.push_variable(&mut v.indexes).await?;
-            // self.index_part.push_sorted_indexes(v.indexes).await?;
-            let other_part = &v.term.as_str()[self.current_substr_size as usize..];
-            self.lexical_part
-                .push_variable_u64(other_part.len() as u64)
-                .await?;
-            self.lexical_part.push(other_part.as_bytes()).await?;
-        }
-        Ok(())
+    let root = std::env::temp_dir().join(format!("tf_policy_{}", std::process::id()));
+    fs::create_dir_all(&root).await?;
+    let path = root.join("0.xml").to_str().unwrap().to_string();
+    fs::write(
+        &path,
+        format!(
+            "<title>\nmenu\n</title>\n<text>\n{}\n</text>\n\
+             <title>\nrust\n</title>\n<text>\nboiler rust boiler\n</text>\n",
+            "boiler ".repeat(10_000)
+        ),
+    )
+    .await?;
+
+    let policies = [
+        (TfPolicy { max_tf: Some(50), log_scaled: false }, [50, 2]),
+        (TfPolicy { max_tf: None, log_scaled: true }, [10, 1]),
+        (TfPolicy { max_tf: Some(50), log_scaled: true }, [4, 1]),
+    ];
+    let config = IndexerConfig::new(1000, 6)?;
+    for (tf, expected) in policies {
+        let destination = root.join("res").to_str().unwrap().to_string();
+        ParseController::<IndexParser, _, _>::new(
+            vec![path.clone()],
+            destination.clone(),
+            root.join("buffer").to_str().unwrap().to_string(),
+            1,
+            IndexedBuilder::new(config, ZoneSet::new(["title", "text"])?)?
+                .with_tf_policy(tf),
+            IndexMerger::new(config.merger()),
+        )
+        .with_overwrite()
+        .create_dictionary()
+        .await?;
+        assert_eq!(IndexMetadata::load(&destination).await?.tf, tf);
+
+        let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+        let counts = dictionary
+            .find("boiler")
+            .await?
+            .unwrap()
+            .indexes
+            .iter()
+            .map(|(_, mut v)| *v.use_count_mut())
+            .collect::<Vec<_>>();
+        assert_eq!(counts, expected);
+        let mut rust = dictionary.find("rust").await?.unwrap().indexes.iter();
+        assert_eq!(rust.next().map(|(_, mut v)| *v.use_count_mut()), Some(tf.stored(2)));
     }
 
-    async fn finish(&mut self) -> Result<(), Error> {
-        self.flush().await?;
-        self.index_part.flush().await?;
-        self.lexical_part.flush().await?;
-        self.pointer_part.flush().await?;
-        self.pointer_part.seek(SeekFrom::Start(0)).await?;
-        self.pointer_part
-            .write_u64(self.current_directory_size)
-            .await?;
-        self.pointer_part.flush().await?;
-        Ok(())
+    fs::remove_dir_all(&root).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn excluded_elements_are_not_indexed() -> Result<(), Error> {
+    use crate::{metadata::IndexMetadata, parser::ParseController};
+
+    let root = scratch("excluded_elements").await?;
+    let path = root.join("0.xml").to_str().unwrap().to_string();
+    fs::write(
+        &path,
+        "<page>\n<title>\nmenu\n</title>\n<comment>\nreverted <text> vandalism\n</comment>\n\
+         <text>\nboiler <comment>hidden</comment> rust\n</text>\n</page>\n",
+    )
+    .await?;
+    let config = IndexerConfig::new(1000, 6)?;
+    let attributes = ZoneSet::new(["title", "text"])?;
+    let destination = root.join("res").to_str().unwrap().to_string();
+    ParseController::<IndexParser, _, _>::new(
+        vec![path],
+        destination.clone(),
+        root.join("buffer").to_str().unwrap().to_string(),
+        1,
+        IndexedBuilder::new(config, attributes.clone())?.with_excluded_elements(vec!["comment".to_string()])?,
+        IndexMerger::new(config.merger()),
+    )
+    .create_dictionary()
+    .await?;
+    assert_eq!(IndexMetadata::load(&destination).await?.excluded, ["comment"]);
+
+    let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+    for term in ["menu", "boiler", "rust"] {
+        assert!(dictionary.find(term).await?.is_some(), "{term}");
+    }
+    for term in ["reverted", "vandalism", "hidden"] {
+        assert!(dictionary.find(term).await?.is_none(), "{term}");
     }
 
-    async fn push(&mut self, term: IndexedTerm<S>) -> Result<(), Error> {
-        if self.buffer_items.len() == self.max_part_size as usize {
-            self.flush().await?;
-            self.current_substr_size = 0;
-        } else if self.buffer_items.len() > 0 {
-            let last = self.buffer_items.last().unwrap();
-            let size = count_same(&last.term, &term.term) as u16;
-            if size > self.current_substr_size {
-                let last = self.buffer_items.pop().unwrap();
-                self.flush().await?;
-                self.current_substr_size = size;
-                self.buffer_items.push(last);
-            } else if self.buffer_items.len() * (self.current_substr_size as usize)
-                < ((self.buffer_items.len() + 1) * (size as usize))
-            {
-                self.current_substr_size = size;
-            } else {
-                self.flush().await?;
-                self.current_substr_size = 0;
-            }
-        } else {
-            self.current_substr_size = 0;
-        }
-        self.buffer_items.push(term);
-        self.current_directory_size += 1;
-        Ok(())
+    let excluded = |tags: &[&str]| {
+        IndexedBuilder::new(config, attributes.clone())
+            .unwrap()
+            .with_numeric_field("timestamp")
+            .unwrap()
+            .with_excluded_elements(tags.iter().map(|v| v.to_string()).collect())
+            .map(|_| ())
+            .map_err(|e| (e.kind(), e.to_string()))
+    };
+    assert_eq!(excluded(&["comment", "sha"]), Ok(()));
+    for tag in ["text", "timestamp", "Comment"] {
+        assert_eq!(
+            excluded(&["comment", tag]),
+            Err((ErrorKind::InvalidInput, format!("\"{tag}\" can't be excluded")))
+        );
     }
+
+    fs::remove_dir_all(&root).await?;
+    Ok(())
 }
 
-fn count_same(f: &String, s: &String) -> usize {
-    let mut fc = f.chars();
-    let mut sc = s.chars();
-    let mut i = 0;
-    while let (Some(f), Some(s)) = (fc.next(), sc.next()) {
-        if f != s {
-            break;
-        }
-        i += f.len_utf8();
+#[tokio::test]
+async fn embedded_nul_does_not_end_the_file() -> Result<(), Error> {
+    use crate::parser::ParseController;
+
+    let root = scratch("embedded_nul").await?;
+    let path = root.join("0.xml").to_str().unwrap().to_string();
+    fs::write(
+        &path,
+        "<page>\n<title>\nmenu\n</title>\n<text>\nboiler\0rust\0\0 kettle\n</text>\n</page>\n\
+         <page>\n<title>\nlater\n</title>\n<text>\nteapot\n</text>\n</page>\n",
+    )
+    .await?;
+    let config = IndexerConfig::new(1000, 6)?;
+    let destination = root.join("res").to_str().unwrap().to_string();
+    ParseController::<IndexParser, _, _>::new(
+        vec![path],
+        destination.clone(),
+        root.join("buffer").to_str().unwrap().to_string(),
+        1,
+        IndexedBuilder::new(config, ZoneSet::new(["title", "text"])?)?,
+        IndexMerger::new(config.merger()),
+    )
+    .create_dictionary()
+    .await?;
+
+    let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+    for (term, document) in [("boiler", 0), ("rust", 0), ("kettle", 0), ("later", 1), ("teapot", 1)] {
+        let found = dictionary.find(term).await?.unwrap_or_else(|| panic!("{term} is not indexed"));
+        assert_eq!(found.indexes.iter().map(|(v, _)| v).collect::<Vec<_>>(), [document], "{term}");
     }
-    i
+
+    fs::remove_dir_all(&root).await?;
+    Ok(())
 }
 
-#[tokio::test]
-async fn vartst() {
-    let mut b = BufWriter::new(File::create("tst/vartst.txt").await.unwrap());
-    variable_save_usize(255, &mut b).await.unwrap();
-    b.flush().await.unwrap();
+#[test]
+fn attributes_are_validated() {
+    let builder = |attributes: &[&str]| {
+        ZoneSet::new(attributes.iter().copied())
+            .and_then(|zones| IndexedBuilder::new(IndexerConfig::new(1000, 6).unwrap(), zones))
+            .map(|_| ())
+            .map_err(|e| (e.kind(), e.to_string()))
+    };
+    assert_eq!(builder(&["title", "text"]), Ok(()));
+    assert_eq!(builder(&["text"]), Ok(()));
+
+    let invalid = |attributes: &[&str]| {
+        let (kind, message) = builder(attributes).unwrap_err();
+        assert_eq!(kind, ErrorKind::InvalidInput);
+        message
+    };
+    assert_eq!(
+        invalid(&["title", "body"]),
+        "invalid attributes: \"body\" has no segment"
+    );
+    assert_eq!(
+        invalid(&["title", "text", "title"]),
+        "invalid zones: \"title\" is repeated"
+    );
+    assert_eq!(
+        invalid(&["title", ""]),
+        "invalid zones: zone 2 is empty"
+    );
+    assert_eq!(invalid(&[]), "invalid zones: no zones given");
+    assert_eq!(
+        invalid(&["Title", "text"]),
+        "invalid attributes: \"Title\" is not a tag the reader can match, \"Title\" has no segment"
+    );
+    assert_eq!(
+        invalid(&["body", "text", "abstract"]),
+        "invalid attributes: \"body\" has no segment, \"abstract\" has no segment"
+    );
 }
 
-#[derive(Debug)]
-struct IndexedCursor {
-    lexical_pointer: usize,
-    lexical_index: u8,
-    indexes_pointer: usize,
-    use_count: usize,
+#[test]
+fn terms_display_their_postings() {
+    let mut term = IndexedTerm::<CommonSegments>::new("rust".to_string());
+    term.use_count = 12;
+    for (document, title, text, count) in [(7, 0, 1, 4), (1, 1, 1, 6), (3, 0, 0, 2)] {
+        let mut usage = UsageData::<CommonSegments>::new();
+        *usage.use_count_mut() = count;
+        CommonSegments::selector_for("title")(usage.segments_mut(), title);
+        CommonSegments::selector_for("text")(usage.segments_mut(), text);
+        term.indexes.push(document, usage);
+    }
+    assert_eq!(term.to_string(), "rust cf=12 df=3 [1:title+text, 3:-, 7:text]");
+    assert_eq!(term.indexes[1].to_string(), "title+text tf=6");
+    assert_eq!(
+        term.indexes[7].segments.zones().collect::<Vec<_>>(),
+        ["text"]
+    );
+    assert_eq!(
+        IndexedTerm::<CommonSegments>::new("none".to_string()).to_string(),
+        "none cf=0 df=0 []"
+    );
 }
 
-impl IndexedCursor {
-    fn new(
-        lexical_pointer: usize,
-        lexical_index: u8,
-        indexes_pointer: usize,
-        use_count: usize,
-    ) -> Self {
-        Self {
-            lexical_pointer,
-            lexical_index,
-            indexes_pointer,
-            use_count,
+#[test]
+fn combined_terms_merge_the_documents_they_share() -> Result<(), Error> {
+    let term = |postings: &[(usize, &str, usize)]| {
+        let mut term = IndexedTerm::<CommonSegments>::new("rust".to_string());
+        for (document, zone, count) in postings {
+            let mut usage = UsageData::<CommonSegments>::new();
+            *usage.use_count_mut() = *count;
+            CommonSegments::selector_for(zone)(usage.segments_mut(), 1);
+            term.use_count += *count as u64;
+            term.indexes.push(*document, usage);
         }
+        term
+    };
+    // Document 4 was split across two flushes, its title in one, its text
+    // in the other.
+    let mut first = term(&[(1, "text", 2), (4, "title", 1), (9, "text", 3)]);
+    first.combine(term(&[(4, "text", 5), (6, "title", 1), (9, "text", 2)]))?;
+    assert_eq!(first.use_count, 14);
+    assert_eq!(first.to_string(), "rust cf=14 df=4 [1:text, 4:title+text, 6:title, 9:text]");
+    assert_eq!(first.indexes[4].to_string(), "title+text tf=6");
+    assert_eq!(first.indexes[9].to_string(), "text tf=5");
+    let uses = first.indexes.iter_ref().map(|(_, v)| v.use_count() as u64).sum::<u64>();
+    assert_eq!(uses, first.use_count);
+    Ok(())
+}
+
+#[tokio::test]
+async fn corrupt_postings_pointers_are_caught() -> Result<(), Error> {
+    let root = scratch("corrupt_pointers").await?;
+    let corpus = CorpusSpec {
+        docs: 40,
+        vocab: 50,
+        ..CorpusSpec::default()
+    }
+    .generate(&root.join("corpus"))
+    .await?;
+    let destination = corpus.index(&root).await?;
+    let layout = IndexLayout::detect(&destination).await?;
+    assert!(fs::read(layout.index_part(&destination)).await?.starts_with(&POSTINGS_HEADER));
+    let pointers = fs::read(layout.dictionary(&destination)).await?;
+    let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+    let mut expected = Vec::new();
+    for ordinal in 0..dictionary.len() {
+        let cursor = dictionary.cursor_at(ordinal).await?;
+        expected.push(dictionary.get_term(cursor).await?.to_string());
     }
 
-    async fn save(self, writer: &mut BufWriter<File>) -> Result<(), Error> {
-        writer.write_u64(self.lexical_pointer as u64).await?;
-        writer.write_u8(self.lexical_index).await?;
-        writer.write_u64(self.indexes_pointer as u64).await?;
-        writer.write_u64(self.use_count as u64).await?;
-        Ok(())
+    // A pointer a byte off now and then lands on bytes that decode as a
+    // whole block, which no frame tells apart; anything else fails typed.
+    let mut decoded = 0;
+    for ordinal in 0..expected.len() {
+        let at = IndexedCursor::offset(ordinal as u64) as usize + 9;
+        let pointer = u64::from_be_bytes(pointers[at..at + 8].try_into().unwrap());
+        for moved in [pointer - 1, pointer + 1] {
+            let mut corrupt = pointers.clone();
+            corrupt[at..at + 8].copy_from_slice(&moved.to_be_bytes());
+            fs::write(layout.dictionary(&destination), &corrupt).await?;
+            let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+            let cursor = dictionary.cursor_at(ordinal).await?;
+            let error = match dictionary.get_term(cursor).await {
+                Ok(_) => {
+                    decoded += 1;
+                    continue;
+                }
+                Err(e) => e,
+            };
+            assert_eq!(error.kind(), ErrorKind::InvalidData, "{ordinal} at {moved}: {error}");
+            let block = error.get_ref().and_then(|v| v.downcast_ref::<BlockError>());
+            assert!(block.is_some(), "{ordinal} at {moved}: {error}");
+        }
     }
+    assert!(decoded * 20 < expected.len() * 2, "{decoded} of {}", expected.len() * 2);
+    fs::write(layout.dictionary(&destination), &pointers).await?;
 
-    async fn load(reader: &mut BufReader<File>) -> Result<IndexedCursor, Error> {
-        Ok(Self {
-            lexical_pointer: reader.read_u64().await? as usize,
-            lexical_index: reader.read_u8().await?,
-            indexes_pointer: reader.read_u64().await? as usize,
-            use_count: reader.read_u64().await? as usize,
-        })
+    // Blocks written before they were framed read as they did.
+    unframe(&destination).await?;
+    assert!(!fs::read(layout.index_part(&destination)).await?.starts_with(&POSTINGS_HEADER));
+    verify_index::<CommonSegments>(&destination).await?;
+    let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+    for (ordinal, term) in expected.iter().enumerate() {
+        let cursor = dictionary.cursor_at(ordinal).await?;
+        assert_eq!(&dictionary.get_term(cursor).await?.to_string(), term);
     }
-}
 
-// #[tokio::test]
-// async fn merger_tst() -> Result<(), Error> {
-//     let mut merger = IndexMergeSaver::new("res".to_string(), 6).await?;
-//     let mut next = IndexedTerm::new("Tяrаt".to_string());
-//     next.indexes.push(4);
-//     merger.push(next).await?;
-//     next = IndexedTerm::new("Tяrk".to_string());
-//     next.indexes.push(1);
-//     merger.push(next).await?;
-//     merger.finish().await?;
-//     Ok(())
-// }
-
-// #[tokio::test]
-// async fn ano_merger_tst() -> Result<(), Error> {
-//     let mut merger = IndexMergeSaver::new("res".to_string(), 6).await?;
-//     // let mut v = vec![]
-//     let mut next = IndexedTerm::new("'''garfield".to_string());
-//     next.indexes.push(4);
-//     merger.push(next).await?;
-//     let mut next = IndexedTerm::new("'''len".to_string());
-//     next.indexes.push(4);
-//     merger.push(next).await?;
-//     let mut next = IndexedTerm::new("'''hack".to_string());
-//     next.indexes.push(4);
-//     merger.push(next).await?;
-//     next = IndexedTerm::new("Tяrk".to_string());
-//     next.indexes.push(1);
-//     merger.push(next).await?;
-//     dbg!(merger.current_directory_size);
-//     merger.finish().await?;
-//     Ok(())
-// }
+    fs::remove_dir_all(&root).await?;
+    Ok(())
+}
 
 #[tokio::test]
-async fn loader_tst() -> Result<(), Error> {
-    // let mut reader = BufReader::new(File::open("./res/dictionary.txt").await?);
-    // dbg!(IndexedCursor::load(&mut reader).await?);
-    // dbg!(IndexedCursor::load(&mut reader).await?);
-    // dbg!(IndexedCursor::load(&mut reader).await?);
-    // dbg!(IndexedCursor::load(&mut reader).await?);
+async fn io_tuning_sizes_the_buffers() -> Result<(), Error> {
+    use crate::parser::ParseController;
 
-    let mut reader = IndexTermProvider::<CommonSegments>::new(&"./res".to_string()).await?;
-    dbg!(reader.next_term().await);
-    let n = reader.next_term().await.unwrap();
-    println!("{}", n.indexes.len());
-    for i in n.indexes.iter() {
-        println!("{}", i.0);
+    let root = scratch("io_tuning").await?;
+    let corpus = CorpusSpec::default().generate(&root.join("corpus")).await?;
+    let tiny = IoTuning::new(5, 7, 11)?;
+    let mut built = Vec::new();
+    for (name, io) in [("default", IoTuning::default()), ("tiny", tiny)] {
+        let destination = root.join(name).to_str().unwrap().to_string();
+        let config = IndexerConfig::new(1000, 6)?.with_io(io);
+        ParseController::<IndexParser, _, _>::new(
+            corpus.files.clone(),
+            destination.clone(),
+            root.join(format!("{name}_buffer")).to_str().unwrap().to_string(),
+            1,
+            IndexedBuilder::new(config, corpus.attributes.clone())?,
+            IndexMerger::new(config.merger()),
+        )
+        .create_dictionary()
+        .await?;
+        built.push(destination);
     }
-    // dbg!(reader.next_term().await);
-    // dbg!(reader.next_term().await);
-    // dbg!(reader.next_term().await);
-    // dbg!(reader.next_term().await);
+    let layout = IndexLayout::detect(&built[0]).await?;
+    for file in [IndexLayout::dictionary, IndexLayout::lexical_part, IndexLayout::index_part] {
+        assert_eq!(fs::read(file(layout, &built[0])).await?, fs::read(file(layout, &built[1])).await?);
+    }
+
+    // A lookup fills the buffers of the dictionary as far as they go.
+    let term = corpus.postings.keys().next().unwrap();
+    let mut dictionary = Dictionary::<CommonSegments>::new_with_io(&built[1], tiny).await?;
+    assert!(dictionary.find(term).await?.is_some());
+    assert!(dictionary.index_part.buffer().len() <= tiny.reader_buf());
+    assert!(dictionary.lexical_part.buffer().len() <= tiny.reader_buf());
+    let mut dictionary = Dictionary::<CommonSegments>::new(&built[1]).await?;
+    assert!(dictionary.find(term).await?.is_some());
+    assert!(dictionary.index_part.buffer().len() > tiny.reader_buf());
+    fs::remove_dir_all(&root).await?;
     Ok(())
 }
 
-#[tokio::test]
-async fn loader_tst_buff() -> Result<(), Error> {
-    // let mut reader = BufReader::new(File::open("./res/dictionary.txt").await?);
-    // dbg!(IndexedCursor::load(&mut reader).await?);
-    // dbg!(IndexedCursor::load(&mut reader).await?);
-    // dbg!(IndexedCursor::load(&mut reader).await?);
-    // dbg!(IndexedCursor::load(&mut reader).await?);
-
-    let mut reader = IndexTermProvider::<CommonSegments>::new(&"./buffer/11".to_string()).await?;
-    dbg!(reader.remaining_size);
-    dbg!(reader.next_term().await);
-    dbg!(reader.next_term().await);
-    dbg!(reader.next_term().await);
-    dbg!(reader.remaining_size);
-    dbg!(reader.next_term().await);
-    dbg!(reader.next_term().await);
-    dbg!(reader.next_term().await);
-    dbg!(reader.next_term().await);
-    dbg!(reader.next_term().await);
+/// Writes `terms` the way a corrupted buffer would hold them, past the
+/// checks of [`IndexMergeSaver::push`].
+#[cfg(test)]
+async fn write_unchecked(directory: &String, terms: Vec<IndexedTerm<CommonSegments>>) -> Result<(), Error> {
+    fs::create_dir_all(directory).await?;
+    let mut saver = IndexMergeSaver::<CommonSegments>::new(directory.clone(), 6).await?;
+    for mut term in terms {
+        let pointer = saver.postings_writer().passed();
+        postings::push_block(saver.postings_writer(), &mut term.indexes).await?;
+        saver.push_written(term.term, term.use_count, pointer).await?;
+    }
+    saver.finish().await?;
     Ok(())
 }
 
 #[tokio::test]
-async fn reader_tst() -> Result<(), Error> {
-    let mut wr = CountedWriter::new(BufWriter::new(File::create("./res/tar.txt").await?));
-    wr.push_u64(3).await?;
-    wr.push_u64(5).await?;
-    wr.push_u64(6).await?;
-    wr.flush().await?;
-    wr.goto(0).await?;
-    wr.push_u64(10).await?;
-    wr.flush().await?;
+async fn inconsistent_counts_are_caught() -> Result<(), Error> {
+    let root = scratch("term_counts").await?;
+    let directory = |name: &str| root.join(name).to_str().unwrap().to_string();
+    let term = |name: &str, use_count: u64, documents: std::ops::Range<usize>| {
+        let mut term = IndexedTerm::<CommonSegments>::new(name.to_string());
+        term.use_count = use_count;
+        for document in documents {
+            let mut usage = UsageData::new();
+            *usage.use_count_mut() = 1;
+            term.indexes.push(document, usage);
+        }
+        term
+    };
+
+    let mut combined = term("rust", u64::MAX - 1, 0..1);
+    let error = combined.combine(term("rust", 5, 1..2)).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+    assert_eq!(
+        error.to_string(),
+        format!("use count of \"rust\" overflows adding 5 to {}", u64::MAX - 1)
+    );
+    assert_eq!((combined.use_count, combined.indexes.len()), (u64::MAX - 1, 1));
+    combined.combine(term("rust", 1, 1..2))?;
+    assert_eq!((combined.use_count, combined.indexes.len()), (u64::MAX, 2));
+
+    let mut huge = IndexedTerm::<CommonSegments>::new("rust".to_string());
+    let mut usage = UsageData::new();
+    *usage.use_count_mut() = usize::MAX;
+    huge.indexes.push(0, usage);
+    let error = term("rust", 1, 0..1).combine(huge).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+    assert_eq!(error.to_string(), format!("use count of \"rust\" overflows adding {} to 1", usize::MAX));
+
+    let pushed = directory("pushed");
+    fs::create_dir_all(&pushed).await?;
+    let mut saver = IndexMergeSaver::<CommonSegments>::new(pushed.clone(), 6).await?;
+    saver.push(term("async", 2, 0..2)).await?;
+    let written = saver.postings_writer().passed();
+    let error = saver.push(term("rust", 1, 0..3)).await.unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+    assert_eq!(error.to_string(), "\"rust\" is used 1 times but has 3 postings");
+    assert_eq!(saver.postings_writer().passed(), written);
+    saver.finish().await?;
+    verify_index::<CommonSegments>(&pushed).await?;
+    assert_eq!(Dictionary::<CommonSegments>::new(&pushed).await?.len(), 1);
+
+    async fn merge(sources: &[String], destination: String) -> Result<IndexStats, Error> {
+        fs::create_dir_all(&destination).await?;
+        let mut providers = Vec::new();
+        for path in sources {
+            providers.push(IndexTermProvider::<CommonSegments>::new(path).await?);
+        }
+        let mut saver = IndexMergeSaver::new(destination, 6).await?;
+        merge_providers(&mut providers, &mut saver, &CancellationToken::new()).await
+    }
+
+    let short = directory("short");
+    write_unchecked(&short, vec![term("async", 2, 0..2), term("rust", 1, 0..3)]).await?;
+    let error = verify_index::<CommonSegments>(&short).await.unwrap_err();
+    assert_eq!(error.to_string(), "\"rust\" is used 1 times but has 3 postings");
+    let error = merge(&[short.clone()], directory("short_merged")).await.unwrap_err();
+    assert_eq!(error.to_string(), "\"rust\" is used 1 times but has 3 postings");
+
+    let (first, second) = (directory("huge_0"), directory("huge_1"));
+    write_unchecked(&first, vec![term("rust", u64::MAX, 0..1)]).await?;
+    write_unchecked(&second, vec![term("rust", 2, 1..3)]).await?;
+    let error = merge(&[first, second], directory("huge_merged")).await.unwrap_err();
+    assert_eq!(error.to_string(), format!("use count of \"rust\" overflows adding {} to 2", u64::MAX));
+
+    fs::remove_dir_all(&root).await?;
     Ok(())
 }
 
@@ -928,3 +5664,27 @@ const fn tra() {
     let b = 2;
     // let kra = f"{b}";
 }
+
+#[cfg(feature = "build")]
+#[test]
+fn document_ids_stop_at_the_highest() {
+    use crate::doc_id::{as_id_range, IdRangeError};
+
+    let mut positions = IndexPositions {
+        names: vec![(std::path::PathBuf::from("a.xml"), 0)],
+        ids: vec![],
+        max_id: 1,
+    };
+    assert_eq!(positions.put(0).unwrap(), 0);
+    assert_eq!(positions.put(0).unwrap(), 1);
+    let e = positions.put(0).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidData);
+    assert_eq!(
+        as_id_range(&e),
+        Some(&IdRangeError::Document {
+            id: 2,
+            at: "document ids"
+        })
+    );
+    assert_eq!((positions.ids.len(), positions.names[0].1), (2, 2));
+}
@@ -0,0 +1,493 @@
+//! Input files named on the command line. A spec is one of
+//!
+//! - a directory, walked recursively for files with one of the extensions;
+//! - a glob, where `*` and `?` stand for characters within one path
+//!   component and `**` for any number of components;
+//! - a file, taken whatever its extension;
+//! - `@list`, a file naming one spec of the other kinds per line, relative
+//!   to the directory of the list. Blank lines and lines starting with `#`
+//!   are left out.
+//!
+//! A file found by walking a directory or matching a glob is read once
+//! however many specs reach it, but naming one file twice is an error.
+
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    io::{Error, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::token_stream;
+
+/// Extensions a directory is searched for by [`InputSet::resolve`].
+pub const DEFAULT_EXTENSIONS: [&str; 2] = ["xml", token_stream::EXTENSION];
+
+/// The order of the files of an [`InputSet`], which the ids of their
+/// documents follow.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputOrder {
+    /// By path, byte by byte: `10.xml` before `2.xml`.
+    Lexical,
+    /// By path, with runs of digits compared as numbers: `2.xml` before
+    /// `10.xml`, the order the translator numbers its chunks in.
+    #[default]
+    Natural,
+    /// Oldest modification first, by path among files of the same time.
+    Mtime,
+}
+
+impl InputOrder {
+    pub fn parse(raw: &str) -> Result<Self, Error> {
+        match raw {
+            "lexical" => Ok(Self::Lexical),
+            "natural" => Ok(Self::Natural),
+            "mtime" => Ok(Self::Mtime),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("unknown input order {raw:?}, expected lexical, natural or mtime"),
+            )),
+        }
+    }
+}
+
+/// The files specs resolve to, each one once, in their [`InputOrder`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct InputSet {
+    pub files: Vec<PathBuf>,
+    /// Entries found while walking that are no inputs: files of another
+    /// extension and directories a glob matched.
+    pub skipped: usize,
+    pub order: InputOrder,
+}
+
+impl InputSet {
+    /// Resolves `specs` with [`DEFAULT_EXTENSIONS`].
+    pub async fn resolve(specs: &[String]) -> Result<Self, Error> {
+        Self::resolve_filtered(specs, &DEFAULT_EXTENSIONS).await
+    }
+
+    /// Resolves `specs` in the default [`InputOrder`], see
+    /// [`Self::resolve_ordered`].
+    pub async fn resolve_filtered(specs: &[String], extensions: &[&str]) -> Result<Self, Error> {
+        Self::resolve_ordered(specs, extensions, InputOrder::default()).await
+    }
+
+    /// Resolves `specs`, walking directories for files ending in one of
+    /// `extensions`, and puts the files in `order`. Fails if a spec names
+    /// nothing there is, if two specs name the same file or if no file is
+    /// found at all.
+    pub async fn resolve_ordered(
+        specs: &[String],
+        extensions: &[&str],
+        order: InputOrder,
+    ) -> Result<Self, Error> {
+        let mut resolver = Resolver {
+            extensions,
+            found: Vec::new(),
+            seen: HashMap::new(),
+            skipped: 0,
+        };
+        for spec in specs {
+            match spec.strip_prefix('@') {
+                Some(list) => resolver.list(Path::new(list)).await?,
+                None => resolver.spec(Path::new(spec)).await?,
+            }
+        }
+        if resolver.found.is_empty() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("no input files in {}", specs.join(" ")),
+            ));
+        }
+        let mut files = resolver.found;
+        match order {
+            InputOrder::Lexical => files.sort_unstable(),
+            InputOrder::Natural => files.sort_unstable_by(|a, b| natural_cmp(a, b)),
+            InputOrder::Mtime => {
+                let mut timed = Vec::with_capacity(files.len());
+                for file in files {
+                    timed.push((fs::metadata(&file).await?.modified()?, file));
+                }
+                timed.sort_unstable();
+                files = timed.into_iter().map(|(_, v)| v).collect();
+            }
+        }
+        Ok(Self {
+            files,
+            skipped: resolver.skipped,
+            order,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Bytes the files take together.
+    pub async fn size(&self) -> Result<u64, Error> {
+        let mut size = 0;
+        for file in self.files.iter() {
+            size += fs::metadata(file).await?.len();
+        }
+        Ok(size)
+    }
+}
+
+struct Resolver<'a> {
+    extensions: &'a [&'a str],
+    found: Vec<PathBuf>,
+    /// Canonical paths of `found`, to the position there and whether a spec
+    /// named the file itself, so a file reached twice in different ways is
+    /// read once.
+    seen: HashMap<PathBuf, (usize, bool)>,
+    skipped: usize,
+}
+
+impl Resolver<'_> {
+    async fn list(&mut self, list: &Path) -> Result<(), Error> {
+        let text = fs::read_to_string(list)
+            .await
+            .map_err(|e| Error::new(e.kind(), format!("input list {}: {e}", list.display())))?;
+        let base = list.parent().unwrap_or(Path::new(""));
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('@') {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("input list {} names another list {line}", list.display()),
+                ));
+            }
+            self.spec(&base.join(line)).await?;
+        }
+        Ok(())
+    }
+
+    async fn spec(&mut self, spec: &Path) -> Result<(), Error> {
+        if is_glob(spec) {
+            return self.glob(spec).await;
+        }
+        let metadata = fs::metadata(spec)
+            .await
+            .map_err(|e| Error::new(e.kind(), format!("input {}: {e}", spec.display())))?;
+        if metadata.is_dir() {
+            self.walk(spec, None, None).await
+        } else {
+            self.push(spec.to_path_buf(), true).await
+        }
+    }
+
+    async fn glob(&mut self, pattern: &Path) -> Result<(), Error> {
+        let mut base = PathBuf::new();
+        let mut rest = Vec::new();
+        for component in pattern.components() {
+            let component = component.as_os_str().to_string_lossy();
+            if rest.is_empty() && !component.contains(['*', '?']) {
+                base.push(component.as_ref());
+            } else {
+                rest.push(component.into_owned());
+            }
+        }
+        let depth = match rest.iter().any(|v| v == "**") {
+            true => None,
+            false => Some(rest.len()),
+        };
+        let matcher = Regex::new(&glob_regex(&rest)).map_err(|e| {
+            Error::new(ErrorKind::InvalidInput, format!("input {}: {e}", pattern.display()))
+        })?;
+        let start = if base.as_os_str().is_empty() { PathBuf::from(".") } else { base };
+        if fs::metadata(&start).await.is_ok_and(|v| v.is_dir()) {
+            self.walk(&start, depth, Some(&matcher)).await?;
+        }
+        Ok(())
+    }
+
+    /// Pushes the files under `directory`: those matching `matcher` by their
+    /// path relative to it, or else those of the extensions. Goes `depth`
+    /// levels down at most.
+    async fn walk(
+        &mut self,
+        directory: &Path,
+        depth: Option<usize>,
+        matcher: Option<&Regex>,
+    ) -> Result<(), Error> {
+        let mut pending = vec![(directory.to_path_buf(), String::new(), 0)];
+        while let Some((directory, relative, level)) = pending.pop() {
+            let mut entries = fs::read_dir(&directory).await?;
+            let mut listed = Vec::new();
+            while let Some(entry) = entries.next_entry().await? {
+                listed.push(entry);
+            }
+            listed.sort_by_key(|v| v.file_name());
+            for entry in listed {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let path = entry.path();
+                let relative = match relative.is_empty() {
+                    true => name,
+                    false => format!("{relative}/{name}"),
+                };
+                let is_dir = fs::metadata(&path).await?.is_dir();
+                let wanted = match matcher {
+                    Some(matcher) => matcher.is_match(&relative),
+                    None => !is_dir && self.has_extension(&path),
+                };
+                if is_dir && depth.is_none_or(|v| level + 1 < v) {
+                    pending.push((path.clone(), relative, level + 1));
+                    if wanted {
+                        self.skipped += 1;
+                    }
+                } else if wanted && !is_dir {
+                    self.push(path, false).await?;
+                } else if wanted || matcher.is_none() && !is_dir {
+                    self.skipped += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn has_extension(&self, path: &Path) -> bool {
+        path.extension()
+            .is_some_and(|v| self.extensions.iter().any(|e| v == *e))
+    }
+
+    /// Adds `path` unless it was found before. Fails if it was `named` by a
+    /// spec both times, which would read its documents twice over if the
+    /// specs were taken at their word.
+    async fn push(&mut self, path: PathBuf, named: bool) -> Result<(), Error> {
+        let canonical = fs::canonicalize(&path).await?;
+        match self.seen.get_mut(&canonical) {
+            Some((at, true)) if named => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("input {} is {} again", path.display(), self.found[*at].display()),
+            )),
+            Some((_, before)) => {
+                *before |= named;
+                Ok(())
+            }
+            None => {
+                self.seen.insert(canonical, (self.found.len(), named));
+                self.found.push(path);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Compares paths as [`InputOrder::Natural`] orders them. Numbers equal but
+/// for leading zeros, and so the paths, fall back to comparing bytes.
+fn natural_cmp(a: &Path, b: &Path) -> Ordering {
+    let (a_text, b_text) = (a.to_string_lossy(), b.to_string_lossy());
+    let (mut a_rest, mut b_rest) = (a_text.as_bytes(), b_text.as_bytes());
+    while let (Some(x), Some(y)) = (a_rest.first(), b_rest.first()) {
+        let order = match x.is_ascii_digit() && y.is_ascii_digit() {
+            true => {
+                let (x_digits, x_tail) = split_digits(a_rest);
+                let (y_digits, y_tail) = split_digits(b_rest);
+                (a_rest, b_rest) = (x_tail, y_tail);
+                let x_digits = trim_zeros(x_digits);
+                let y_digits = trim_zeros(y_digits);
+                x_digits.len().cmp(&y_digits.len()).then(x_digits.cmp(y_digits))
+            }
+            false => {
+                (a_rest, b_rest) = (&a_rest[1..], &b_rest[1..]);
+                x.cmp(y)
+            }
+        };
+        if order.is_ne() {
+            return order;
+        }
+    }
+    a_rest.len().cmp(&b_rest.len()).then_with(|| a.cmp(b))
+}
+
+fn split_digits(bytes: &[u8]) -> (&[u8], &[u8]) {
+    bytes.split_at(bytes.iter().position(|v| !v.is_ascii_digit()).unwrap_or(bytes.len()))
+}
+
+fn trim_zeros(digits: &[u8]) -> &[u8] {
+    &digits[digits.iter().position(|v| *v != b'0').unwrap_or(digits.len())..]
+}
+
+fn is_glob(spec: &Path) -> bool {
+    spec.to_string_lossy().contains(['*', '?'])
+}
+
+/// The glob `components` as a regex over `/` separated relative paths.
+fn glob_regex(components: &[String]) -> String {
+    let mut regex = String::from("^");
+    for (i, component) in components.iter().enumerate() {
+        let last = i + 1 == components.len();
+        if component == "**" {
+            regex.push_str(if last { ".*" } else { "(?:[^/]+/)*" });
+            continue;
+        }
+        for c in component.chars() {
+            match c {
+                '*' => regex.push_str("[^/]*"),
+                '?' => regex.push_str("[^/]"),
+                c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+            }
+        }
+        if !last {
+            regex.push('/');
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+#[cfg(test)]
+mod tst {
+    use std::io::{Error, ErrorKind};
+
+    use tokio::fs;
+
+    use crate::testsupport::scratch;
+
+    use super::{InputOrder, InputSet};
+
+    /// `a.xml`, `b.txt`, `nested/c.xml`, `nested/deeper/d.xml` and
+    /// `nested/deeper/e.tok` under `root`.
+    async fn tree(name: &str) -> Result<std::path::PathBuf, Error> {
+        let root = scratch(name).await?;
+        fs::create_dir_all(root.join("nested/deeper")).await?;
+        for file in ["a.xml", "b.txt", "nested/c.xml", "nested/deeper/d.xml", "nested/deeper/e.tok"] {
+            fs::write(root.join(file), file).await?;
+        }
+        Ok(root)
+    }
+
+    fn names(inputs: &InputSet, root: &std::path::Path) -> Vec<String> {
+        inputs
+            .files
+            .iter()
+            .map(|v| v.strip_prefix(root).unwrap().to_string_lossy().replace('\\', "/"))
+            .collect()
+    }
+
+    fn spec(root: &std::path::Path, rest: &str) -> String {
+        root.join(rest).to_string_lossy().into_owned()
+    }
+
+    #[tokio::test]
+    async fn directories_are_walked() -> Result<(), Error> {
+        let root = tree("inputs_directory").await?;
+        let inputs = InputSet::resolve(&[spec(&root, "")]).await?;
+        assert_eq!(
+            names(&inputs, &root),
+            ["a.xml", "nested/c.xml", "nested/deeper/d.xml", "nested/deeper/e.tok"]
+        );
+        assert_eq!(inputs.skipped, 1);
+        let inputs = InputSet::resolve_filtered(&[spec(&root, "nested")], &["tok"]).await?;
+        assert_eq!(names(&inputs, &root), ["nested/deeper/e.tok"]);
+        assert_eq!(inputs.skipped, 2);
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn globs_match_within_and_across_directories() -> Result<(), Error> {
+        let root = tree("inputs_glob").await?;
+        let inputs = InputSet::resolve(&[spec(&root, "*")]).await?;
+        assert_eq!(names(&inputs, &root), ["a.xml", "b.txt"]);
+        assert_eq!(inputs.skipped, 1);
+        let inputs = InputSet::resolve(&[spec(&root, "**/*.xml")]).await?;
+        assert_eq!(names(&inputs, &root), ["a.xml", "nested/c.xml", "nested/deeper/d.xml"]);
+        let inputs = InputSet::resolve(&[spec(&root, "nested/*/?.tok")]).await?;
+        assert_eq!(names(&inputs, &root), ["nested/deeper/e.tok"]);
+        let missing = InputSet::resolve(&[spec(&root, "*.json")]).await.unwrap_err();
+        assert_eq!(missing.kind(), ErrorKind::NotFound);
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn files_and_lists() -> Result<(), Error> {
+        let root = tree("inputs_files").await?;
+        let inputs = InputSet::resolve(&[spec(&root, "b.txt")]).await?;
+        assert_eq!(names(&inputs, &root), ["b.txt"]);
+        let missing = InputSet::resolve(&[spec(&root, "none.xml")]).await.unwrap_err();
+        assert_eq!(missing.kind(), ErrorKind::NotFound);
+        assert!(missing.to_string().contains("none.xml"), "{missing}");
+
+        fs::write(root.join("list.txt"), "# inputs\nb.txt\n\nnested/*.xml\n").await?;
+        let inputs = InputSet::resolve(&[format!("@{}", spec(&root, "list.txt"))]).await?;
+        assert_eq!(names(&inputs, &root), ["b.txt", "nested/c.xml"]);
+        fs::write(root.join("nested.txt"), "@list.txt\n").await?;
+        let nested = InputSet::resolve(&[format!("@{}", spec(&root, "nested.txt"))]).await;
+        assert_eq!(nested.unwrap_err().kind(), ErrorKind::InvalidInput);
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn mixed_specs_are_deduplicated() -> Result<(), Error> {
+        let root = tree("inputs_mixed").await?;
+        fs::write(root.join("list.txt"), "nested/deeper/d.xml\nnested/deeper\n").await?;
+        let inputs = InputSet::resolve(&[
+            spec(&root, "nested"),
+            spec(&root, "a.xml"),
+            spec(&root, "**/d.xml"),
+            format!("@{}", spec(&root, "list.txt")),
+        ])
+        .await?;
+        assert_eq!(
+            names(&inputs, &root),
+            ["a.xml", "nested/c.xml", "nested/deeper/d.xml", "nested/deeper/e.tok"]
+        );
+        assert_eq!(inputs.size().await?, 5 + 12 + 19 + 19);
+
+        // Named by a spec and by the list, the file would be read twice.
+        let twice = InputSet::resolve(&[
+            spec(&root, "nested/deeper/d.xml"),
+            spec(&root, "nested"),
+            format!("@{}", spec(&root, "list.txt")),
+        ])
+        .await
+        .unwrap_err();
+        assert_eq!(twice.kind(), ErrorKind::InvalidInput);
+        assert!(twice.to_string().contains("d.xml is"), "{twice}");
+        let twice = InputSet::resolve(&[spec(&root, "a.xml"), spec(&root, "nested/../a.xml")]).await;
+        assert_eq!(twice.unwrap_err().kind(), ErrorKind::InvalidInput);
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn numbered_chunks_keep_their_order() -> Result<(), Error> {
+        let root = scratch("inputs_order").await?;
+        let written = ["10.xml", "2.xml", "1.xml", "b/01.xml", "a/3.xml"];
+        for (i, file) in written.iter().enumerate() {
+            fs::create_dir_all(root.join(file).parent().unwrap()).await?;
+            fs::write(root.join(file), file).await?;
+            let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000 + i as u64);
+            std::fs::File::options()
+                .write(true)
+                .open(root.join(file))?
+                .set_modified(modified)?;
+        }
+        let specs = [spec(&root, "")];
+        let inputs = InputSet::resolve(&specs).await?;
+        assert_eq!(inputs.order, InputOrder::Natural);
+        assert_eq!(names(&inputs, &root), ["1.xml", "2.xml", "10.xml", "a/3.xml", "b/01.xml"]);
+        let inputs = InputSet::resolve_ordered(&specs, &["xml"], InputOrder::Lexical).await?;
+        assert_eq!(names(&inputs, &root), ["1.xml", "10.xml", "2.xml", "a/3.xml", "b/01.xml"]);
+        let inputs = InputSet::resolve_ordered(&specs, &["xml"], InputOrder::Mtime).await?;
+        assert_eq!(names(&inputs, &root), written);
+        assert!(InputOrder::parse("size").is_err());
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
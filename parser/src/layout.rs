@@ -0,0 +1,244 @@
+use std::io::{Error, ErrorKind};
+
+use tokio::fs;
+
+/// Names of the files of an index inside its directory, the only place they
+/// are spelled out.
+///
+/// [`IndexLayout::V1`] is what every index was written with so far and stays
+/// the default. [`IndexLayout::V2`] names binary files `.bin` and moves the
+/// auxiliary structures to `aux/` and per-document values to `docdata/`.
+/// `metadata.json`, `report.json` and `stats.json` are at the top of both.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IndexLayout {
+    #[default]
+    V1,
+    V2,
+}
+
+impl IndexLayout {
+    /// What [`IndexLayout::parse`] reads back as this layout.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::V1 => "v1",
+            Self::V2 => "v2",
+        }
+    }
+
+    pub fn parse(raw: &str) -> Result<Self, Error> {
+        match raw {
+            "v1" => Ok(Self::V1),
+            "v2" => Ok(Self::V2),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("unknown index layout {raw:?}, expected v1 or v2"),
+            )),
+        }
+    }
+
+    /// The layout of the index in `directory`, told apart by its pointer
+    /// file. Directories holding neither are taken as [`IndexLayout::V1`].
+    pub async fn detect(directory: &str) -> Result<Self, Error> {
+        match fs::metadata(Self::V2.dictionary(directory)).await {
+            Ok(_) => Ok(Self::V2),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::V1),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Creates the subdirectories the layout puts files in.
+    pub async fn create_directories(self, directory: &str) -> Result<(), Error> {
+        if self == Self::V2 {
+            fs::create_dir_all(format!("{directory}/aux")).await?;
+            fs::create_dir_all(format!("{directory}/docdata")).await?;
+        }
+        Ok(())
+    }
+
+    fn pick(self, directory: &str, v1: &str, v2: &str) -> String {
+        match self {
+            Self::V1 => format!("{directory}/{v1}"),
+            Self::V2 => format!("{directory}/{v2}"),
+        }
+    }
+
+    pub fn metadata(directory: &str) -> String {
+        format!("{directory}/metadata.json")
+    }
+
+    pub fn report(directory: &str) -> String {
+        format!("{directory}/report.json")
+    }
+
+    pub fn stats(directory: &str) -> String {
+        format!("{directory}/stats.json")
+    }
+
+    pub fn dictionary(self, directory: &str) -> String {
+        self.pick(directory, "dictionary.txt", "dictionary.bin")
+    }
+
+    pub fn lexical_part(self, directory: &str) -> String {
+        self.pick(directory, "lexical_part.txt", "lexical_part.bin")
+    }
+
+    pub fn index_part(self, directory: &str) -> String {
+        self.pick(directory, "index_part.txt", "index_part.bin")
+    }
+
+    pub fn files(self, directory: &str) -> String {
+        self.pick(directory, "files.txt", "files.bin")
+    }
+
+    pub fn info(self, directory: &str) -> String {
+        self.pick(directory, "info.txt", "info.txt")
+    }
+
+    pub fn block_dir(self, directory: &str) -> String {
+        self.pick(directory, "block_dir.bin", "aux/block_dir.bin")
+    }
+
+    pub fn phonetic(self, directory: &str) -> String {
+        self.pick(directory, "phonetic_part.txt", "aux/phonetic.bin")
+    }
+
+    /// Directory of the rotation dictionary, itself written in this layout.
+    pub fn permuterm(self, directory: &str) -> String {
+        self.pick(directory, "permuterm", "aux/permuterm")
+    }
+
+    pub fn term_fst(self, directory: &str) -> String {
+        self.pick(directory, "terms.fst", "aux/terms.fst")
+    }
+
+    pub fn bitmaps(self, directory: &str) -> String {
+        self.pick(directory, "bitmap_part.bin", "aux/bitmap_part.bin")
+    }
+
+    pub fn idf_top(self, directory: &str) -> String {
+        self.pick(directory, "idf_top.bin", "aux/idf_top.bin")
+    }
+
+    pub fn max_tf(self, directory: &str) -> String {
+        self.pick(directory, "max_tf.bin", "aux/max_tf.bin")
+    }
+
+    pub fn lengths(self, directory: &str) -> String {
+        self.pick(directory, "lengths.txt", "docdata/lengths.bin")
+    }
+
+    pub fn boosts(self, directory: &str) -> String {
+        self.pick(directory, "boosts.txt", "docdata/boosts.bin")
+    }
+
+    pub fn numeric(self, directory: &str) -> String {
+        self.pick(directory, "numeric.txt", "docdata/numeric.bin")
+    }
+
+    pub fn titles(self, directory: &str) -> String {
+        self.pick(directory, "titles.txt", "docdata/titles.bin")
+    }
+
+    pub fn forward(self, directory: &str) -> String {
+        self.pick(directory, "forward_part.bin", "docdata/forward.bin")
+    }
+}
+
+#[cfg(test)]
+mod tst {
+    use std::io::Error;
+
+    use tokio::fs;
+
+    use crate::{
+        config::IndexerConfig,
+        indexed::{verify_index, Dictionary, IndexMerger, IndexParser, IndexedBuilder},
+        metadata::IndexMetadata,
+        parser::ParseController,
+        rank::{DocumentLengths, IdfTable},
+        segment::CommonSegments,
+        titles::DocumentTitles,
+        zones::ZoneSet,
+    };
+
+    use super::IndexLayout;
+
+    #[tokio::test]
+    async fn both_layouts_open() -> Result<(), Error> {
+        let root = std::env::temp_dir().join(format!("layout_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root).await;
+        fs::create_dir_all(&root).await?;
+        let path = root.join("0.xml").to_str().unwrap().to_string();
+        let content = ["testing ring", "robert rupert", "tenant text"]
+            .iter()
+            .map(|text| format!("<title>\nsing\n</title>\n<text>\n{text}\n</text>\n"))
+            .collect::<String>();
+        fs::write(&path, content).await?;
+
+        let mut found = Vec::new();
+        let config = IndexerConfig::new(1000, 6)?;
+        for layout in [IndexLayout::V1, IndexLayout::V2] {
+            let destination = root.join(format!("{layout:?}")).to_str().unwrap().to_string();
+            ParseController::<IndexParser, _, _>::new(
+                vec![path.clone()],
+                destination.clone(),
+                root.join("buffer").to_str().unwrap().to_string(),
+                1,
+                IndexedBuilder::new(config, ZoneSet::new(["title", "text"])?)?,
+                IndexMerger::new(config.merger())
+                    .with_phonetic()
+                    .with_permuterm()
+                    .with_idf_top(2)
+                    .with_score_bounds()
+                    .with_layout(layout),
+            )
+            .with_titles()
+            .create_dictionary()
+            .await?;
+            assert_eq!(IndexLayout::detect(&destination).await?, layout);
+            for file in [
+                layout.dictionary(&destination),
+                layout.lexical_part(&destination),
+                layout.index_part(&destination),
+                layout.files(&destination),
+                layout.info(&destination),
+                layout.block_dir(&destination),
+                layout.phonetic(&destination),
+                layout.idf_top(&destination),
+                layout.max_tf(&destination),
+                layout.lengths(&destination),
+                layout.titles(&destination),
+                layout.dictionary(&layout.permuterm(&destination)),
+                IndexLayout::metadata(&destination),
+                IndexLayout::report(&destination),
+            ] {
+                assert!(fs::metadata(&file).await.is_ok(), "{file}");
+            }
+            verify_index::<CommonSegments>(&destination).await?;
+
+            let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+            assert_eq!(dictionary.layout(), layout);
+            found.push((
+                dictionary.len(),
+                dictionary.find("tenant").await?.map(|v| v.to_string()),
+                dictionary.wildcard("*ing").await?,
+                dictionary.phonetic("robert").await?,
+                DocumentLengths::load(&destination).await?.lengths,
+                DocumentTitles::load(&destination).await?.titles,
+                IdfTable::load_current(&destination).await?.map(|v| v.len()),
+                IndexMetadata::load(&destination).await?.permuterm,
+            ));
+        }
+        assert_eq!(found[0], found[1]);
+        assert_eq!(found[0].2, ["ring", "sing", "testing"]);
+        assert_eq!(found[0].3, ["robert", "rupert"]);
+        let v2 = root.join("V2").to_str().unwrap().to_string();
+        assert!(fs::metadata(format!("{v2}/dictionary.txt")).await.is_err());
+        assert!(fs::metadata(format!("{v2}/aux/block_dir.bin")).await.is_ok());
+        assert!(fs::metadata(format!("{v2}/docdata/lengths.bin")).await.is_ok());
+        assert!(IndexLayout::parse("v3").is_err());
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
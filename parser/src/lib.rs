@@ -1,9 +1,107 @@
+#[cfg(feature = "query")]
+pub mod analyzer;
+#[cfg(feature = "roaring")]
+pub mod bitmaps;
+#[cfg(feature = "query")]
+pub mod block_cache;
+pub mod block_dir;
+#[cfg(feature = "query")]
+pub mod boost;
+#[cfg(feature = "query")]
+pub mod bounds;
+#[cfg(feature = "build")]
+pub mod buffer_meta;
+pub mod cancel;
+#[cfg(feature = "query")]
+pub mod config;
+#[cfg(feature = "query")]
+pub mod case;
+#[cfg(feature = "query")]
+pub mod delta;
+pub mod doc_id;
+#[cfg(feature = "build")]
+pub mod estimate;
+#[cfg(feature = "query")]
+pub mod execute;
+pub mod filter;
+#[cfg(feature = "query")]
+pub mod forward;
+pub mod gallop;
+pub mod generation;
+#[cfg(feature = "build")]
+pub mod inputs;
+#[cfg(feature = "query")]
 pub mod indexed;
+pub mod layout;
 pub mod list;
+#[cfg(feature = "build")]
 pub mod parser;
+pub mod paths;
+#[cfg(feature = "query")]
+pub mod permuterm;
+#[cfg(feature = "query")]
+pub mod phonetic;
+#[cfg(feature = "build")]
+pub mod pipeline;
+#[cfg(feature = "build")]
+pub mod postings;
+#[cfg(feature = "query")]
+pub mod provenance;
+#[cfg(feature = "query")]
+pub mod query;
+#[cfg(feature = "query")]
+pub mod rank;
+#[cfg(feature = "build")]
 pub mod reader;
+#[cfg(feature = "build")]
+pub mod reindex;
+#[cfg(feature = "build")]
+pub mod reorder;
+#[cfg(feature = "build")]
+pub mod run_log;
 
+#[cfg(feature = "build")]
 pub mod rep_reader;
+#[cfg(feature = "query")]
+pub mod report;
+#[cfg(feature = "query")]
+pub mod resolve;
 pub mod listmap;
+#[cfg(feature = "query")]
+pub mod metadata;
+#[cfg(feature = "query")]
+pub mod numeric;
+#[cfg(feature = "build")]
+pub mod open_files;
+#[cfg(feature = "query")]
+pub mod sample;
 pub mod save;
-pub mod segment;
\ No newline at end of file
+pub mod segment;
+#[cfg(feature = "query")]
+pub mod stats;
+#[cfg(feature = "query")]
+pub mod strategy;
+#[cfg(feature = "query")]
+pub mod synonym;
+#[cfg(feature = "query")]
+pub mod tasks;
+#[cfg(feature = "fst")]
+pub mod term_fst;
+pub mod term_ord;
+#[cfg(feature = "build")]
+pub mod text_sink;
+#[cfg(all(test, feature = "build"))]
+pub(crate) mod testsupport;
+#[cfg(feature = "build")]
+pub mod throttle;
+#[cfg(feature = "query")]
+pub mod titles;
+#[cfg(feature = "build")]
+pub mod token_stream;
+#[cfg(feature = "query")]
+pub mod verify;
+#[cfg(feature = "query")]
+pub mod warnings;
+#[cfg(feature = "query")]
+pub mod watcher;
+pub mod zones;
@@ -1,4 +1,7 @@
-use std::{io::Error, mem};
+use std::{
+    io::{Error, ErrorKind},
+    mem,
+};
 
 use tokio::{
     fs::File,
@@ -21,6 +24,48 @@ impl<T: Ord> SortedLinkedList<T> {
             size: 0,
         }
     }
+    /// Chain of `values` exactly as given, for breaking the invariants on purpose.
+    #[cfg(test)]
+    fn from_raw(values: Vec<T>, size: usize) -> Self {
+        let mut start = None;
+        for value in values.into_iter().rev() {
+            start = Some(Box::new(Value(value, start)));
+        }
+        Self { start, size }
+    }
+
+    /// Checks that values strictly increase along the chain and that `size`
+    /// matches it. The walk stops one node past `size`.
+    pub fn validate(&self) -> Result<(), Error> {
+        let invalid = |message: String| Err(Error::new(ErrorKind::InvalidData, message));
+        let mut nodes = 0;
+        let mut previous: Option<&T> = None;
+        let mut next = &self.start;
+        while let Some(v) = next {
+            if previous.is_some_and(|p| *p >= v.0) {
+                return invalid(format!("value at node {nodes} is not above the previous one"));
+            }
+            nodes += 1;
+            if nodes > self.size {
+                return invalid(format!("chain is longer than size {}", self.size));
+            }
+            previous = Some(&v.0);
+            next = &v.1;
+        }
+        match nodes == self.size {
+            true => Ok(()),
+            false => invalid(format!("size is {} but the chain has {nodes} nodes", self.size)),
+        }
+    }
+
+    #[inline]
+    fn debug_validate(&self) {
+        #[cfg(debug_assertions)]
+        if let Err(e) = self.validate() {
+            panic!("SortedLinkedList corrupted: {e}");
+        }
+    }
+
     fn addFirst(&mut self, value: T) {
         self.start = Some(Box::new(Value(value, None)));
         self.size += 1;
@@ -31,6 +76,7 @@ impl<T: Ord> SortedLinkedList<T> {
         match current {
             None => {
                 self.addFirst(value);
+                self.debug_validate();
                 return;
             }
             Some(current) => {
@@ -63,6 +109,7 @@ impl<T: Ord> SortedLinkedList<T> {
                 }
             }
         }
+        self.debug_validate();
     }
 
     pub fn len(&self) -> usize {
@@ -76,7 +123,12 @@ impl<T: Ord> SortedLinkedList<T> {
     }
 
     pub fn or(&mut self, mut oth: SortedLinkedList<T>) {
-        if self.len() == 0 {}
+        if self.len() == 0 {
+            self.start = oth.start.take();
+            self.size = oth.size;
+            self.debug_validate();
+            return;
+        }
         let mut fc = self.start.as_mut().unwrap();
         let mut sc = oth.start.take();
         'outer: while sc.is_some() {
@@ -110,9 +162,29 @@ impl<T: Ord> SortedLinkedList<T> {
         while sc.is_some() {
             let usc = sc.unwrap();
             fc.1 = Some(Box::new(Value(usc.0, None)));
+            fc = fc.1.as_mut().unwrap();
             sc = usc.1;
             self.size += 1;
         }
+        self.debug_validate();
+    }
+}
+
+impl<T: Ord + Clone> Clone for SortedLinkedList<T> {
+    /// Copies node by node: a derived clone would recurse once per node.
+    fn clone(&self) -> Self {
+        let mut start = None;
+        let mut tail = &mut start;
+        let mut next = &self.start;
+        while let Some(v) = next {
+            let node = tail.insert(Box::new(Value(v.0.clone(), None)));
+            tail = &mut node.1;
+            next = &v.1;
+        }
+        Self {
+            start,
+            size: self.size,
+        }
     }
 }
 
@@ -199,9 +271,52 @@ fn lst_tst() {
         println!("{}", i);
     }
 }
+#[test]
+fn or_appends_whole_tail() {
+    let mut first = SortedLinkedList::<i32>::new();
+    first.push(1);
+    first.push(3);
+    let mut second = SortedLinkedList::<i32>::new();
+    for v in [2, 3, 7, 8, 9] {
+        second.push(v);
+    }
+    let copy = second.clone();
+    first.or(second);
+    first.validate().unwrap();
+    assert_eq!(first.iter().collect::<Vec<_>>(), [1, 2, 3, 7, 8, 9]);
+
+    let mut empty = SortedLinkedList::<i32>::new();
+    empty.or(copy.clone());
+    assert_eq!(empty.iter().collect::<Vec<_>>(), copy.iter().collect::<Vec<_>>());
+}
+
+#[test]
+fn validate_catches_corrupted_chains() {
+    assert!(SortedLinkedList::from_raw(vec![1, 4, 9], 3).validate().is_ok());
+    let message = |list: SortedLinkedList<i32>| list.validate().unwrap_err().to_string();
+    assert_eq!(
+        message(SortedLinkedList::from_raw(vec![4, 1], 2)),
+        "value at node 1 is not above the previous one"
+    );
+    assert_eq!(
+        message(SortedLinkedList::from_raw(vec![1, 4, 9], 1)),
+        "chain is longer than size 1"
+    );
+    assert_eq!(
+        message(SortedLinkedList::from_raw(vec![], 2)),
+        "size is 2 but the chain has 0 nodes"
+    );
+}
+
+#[cfg(test)]
+fn scratch_file(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("{name}_{}.bin", std::process::id()))
+}
+
 #[tokio::test]
 async fn write_tst() -> Result<(), Error> {
-    let mut buf = BufWriter::new(File::create("tst/tar.txt").await?);
+    let path = scratch_file("list_write_tst");
+    let mut buf = BufWriter::new(File::create(&path).await?);
 
     let mut f = SortedLinkedList::<usize>::new();
 
@@ -218,27 +333,37 @@ async fn write_tst() -> Result<(), Error> {
     s.push(3);
 
     s.or(f);
-    // f = s;
 
-    s.save(&mut buf).await?;
+    let passed = s.save(&mut buf).await?;
     buf.flush().await?;
+    assert_eq!(passed as u64, tokio::fs::metadata(&path).await?.len());
+
+    let loaded = SortedLinkedList::<usize>::load(&mut BufReader::new(File::open(&path).await?)).await?;
+    assert_eq!(loaded.iter().collect::<Vec<_>>(), [1, 2, 3, 4, 6, 10]);
+    tokio::fs::remove_file(&path).await?;
     Ok(())
 }
 
 #[tokio::test]
 async fn read_tst() -> Result<(), Error> {
-    async fn next(buf: &mut BufReader<File>) -> Result<(), Error> {
-        let f = SortedLinkedList::<usize>::load(buf).await?;
-        for v in f.iter() {
-            println!("{}", v);
+    let path = scratch_file("list_read_tst");
+    let lists = [vec![0], vec![5, 6, 7], vec![1, 200, 70_000, 1 << 40], vec![3, 4]];
+    let mut buf = BufWriter::new(File::create(&path).await?);
+    for values in lists.iter() {
+        let mut list = SortedLinkedList::<usize>::new();
+        for v in values.iter().rev() {
+            list.push(*v);
         }
-        Ok(())
+        list.save(&mut buf).await?;
     }
-    let mut buf = BufReader::new(File::open("res/index_part.txt").await?);
+    buf.flush().await?;
 
-    for _ in 0..4 {
-        next(&mut buf).await?;
-        println!();
+    let mut buf = BufReader::new(File::open(&path).await?);
+    for values in lists.iter() {
+        let list = SortedLinkedList::<usize>::load(&mut buf).await?;
+        list.validate().unwrap();
+        assert_eq!(&list.iter().collect::<Vec<_>>(), values);
     }
+    tokio::fs::remove_file(&path).await?;
     Ok(())
 }
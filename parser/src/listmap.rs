@@ -1,6 +1,6 @@
 use std::{
     collections::LinkedList,
-    io::Error,
+    io::{Error, ErrorKind},
     mem,
     ops::{Index, IndexMut},
     ptr::NonNull,
@@ -10,11 +10,13 @@ use async_trait::async_trait;
 use save::save::VariableSave;
 use tokio::{
     fs::File,
-    io::{BufReader, BufWriter},
+    io::{AsyncSeekExt, BufReader, BufWriter},
 };
 
 use save::writer::{variable_load, variable_save_usize};
 
+use crate::doc_id::{document_id, IdRangeError};
+
 #[derive(Debug)]
 struct Value<T, G>(T, G, Option<Box<Value<T, G>>>);
 #[derive(Debug)]
@@ -30,6 +32,48 @@ impl<T: Ord, G> SortedLinkedMap<T, G> {
             size: 0,
         }
     }
+    /// Chain of `entries` exactly as given, for breaking the invariants on purpose.
+    #[cfg(test)]
+    fn from_raw(entries: Vec<(T, G)>, size: usize) -> Self {
+        let mut start = None;
+        for (key, value) in entries.into_iter().rev() {
+            start = Some(Box::new(Value(key, value, start)));
+        }
+        Self { start, size }
+    }
+
+    /// Checks that keys strictly increase along the chain and that `size`
+    /// matches it. The walk stops one node past `size`.
+    pub fn validate(&self) -> Result<(), Error> {
+        let invalid = |message: String| Err(Error::new(ErrorKind::InvalidData, message));
+        let mut nodes = 0;
+        let mut previous: Option<&T> = None;
+        let mut next = &self.start;
+        while let Some(v) = next {
+            if previous.is_some_and(|p| *p >= v.0) {
+                return invalid(format!("key at node {nodes} is not above the previous one"));
+            }
+            nodes += 1;
+            if nodes > self.size {
+                return invalid(format!("chain is longer than size {}", self.size));
+            }
+            previous = Some(&v.0);
+            next = &v.2;
+        }
+        match nodes == self.size {
+            true => Ok(()),
+            false => invalid(format!("size is {} but the chain has {nodes} nodes", self.size)),
+        }
+    }
+
+    #[inline]
+    fn debug_validate(&self) {
+        #[cfg(debug_assertions)]
+        if let Err(e) = self.validate() {
+            panic!("SortedLinkedMap corrupted: {e}");
+        }
+    }
+
     #[inline]
     fn add_first(&mut self, key: T, value: G) {
         self.start = Some(Box::new(Value(key, value, None)));
@@ -41,6 +85,7 @@ impl<T: Ord, G> SortedLinkedMap<T, G> {
         match current {
             None => {
                 self.add_first(key, value);
+                self.debug_validate();
                 return;
             }
             Some(current) => {
@@ -66,14 +111,16 @@ impl<T: Ord, G> SortedLinkedMap<T, G> {
                             let v = mem::replace(&mut current.1, value);
                             let w = mem::replace(&mut current.2, Some(Box::new(Value(k, v, None))));
                             current.2.as_mut().unwrap().2 = w;
+                            self.size += 1;
                         } else if key > current.0 {
                             current.2 = Some(Box::new(Value(key, value, None)));
+                            self.size += 1;
                         }
-                        self.size += 1;
                     }
                 }
             }
         }
+        self.debug_validate();
     }
 
     pub fn len(&self) -> usize {
@@ -86,14 +133,29 @@ impl<T: Ord, G> SortedLinkedMap<T, G> {
         }
     }
 
+    pub fn iter_ref(&self) -> impl Iterator<Item = (&T, &G)> {
+        std::iter::successors(self.start.as_deref(), |v| v.2.as_deref()).map(|v| (&v.0, &v.1))
+    }
+
     pub fn iter_mut(&mut self) -> RefLinkedMapIterator<'_, T, G> {
         RefLinkedMapIterator {
             current: self.start.as_deref_mut(),
         }
     }
 
-    pub fn or(&mut self, mut oth: SortedLinkedMap<T, G>, map: fn(&mut G, &mut G) -> ()) {
-        if self.len() == 0 {}
+    /// Takes in the entries of `oth`, with `map` joining the values of a
+    /// key found in both. Stops at the first error of `map`.
+    pub fn or<E>(
+        &mut self,
+        mut oth: SortedLinkedMap<T, G>,
+        mut map: impl FnMut(&mut G, &mut G) -> Result<(), E>,
+    ) -> Result<(), E> {
+        if self.len() == 0 {
+            self.start = oth.start.take();
+            self.size = oth.size;
+            self.debug_validate();
+            return Ok(());
+        }
         let mut fc = self.start.as_mut().unwrap();
         let mut sc = oth.start.take();
         'outer: while sc.is_some() {
@@ -116,7 +178,7 @@ impl<T: Ord, G> SortedLinkedMap<T, G> {
                 }
             }
             if sc.is_some() && fc.as_ref().0 == sc.as_ref().unwrap().0 {
-                map(&mut fc.1, &mut sc.as_mut().unwrap().1);
+                map(&mut fc.1, &mut sc.as_mut().unwrap().1)?;
                 sc = sc.unwrap().2;
             }
 
@@ -129,9 +191,12 @@ impl<T: Ord, G> SortedLinkedMap<T, G> {
         while sc.is_some() {
             let usc = sc.unwrap();
             fc.2 = Some(Box::new(Value(usc.0, usc.1, None)));
+            fc = fc.2.as_mut().unwrap();
             sc = usc.2;
             self.size += 1;
         }
+        self.debug_validate();
+        Ok(())
     }
 
     pub fn element_at(&self, index: T) -> Option<&G> {
@@ -170,11 +235,37 @@ impl<T: Ord, G> SortedLinkedMap<T, G> {
         None
     }
 
+    pub fn remove(&mut self, index: T) -> Option<G> {
+        let mut next = &mut self.start;
+        loop {
+            let order = match next {
+                Some(v) => v.0.cmp(&index),
+                None => return None,
+            };
+            match order {
+                std::cmp::Ordering::Less => {
+                    next = &mut next.as_mut().unwrap().2;
+                }
+                std::cmp::Ordering::Equal => {
+                    let node = next.take().unwrap();
+                    *next = node.2;
+                    self.size -= 1;
+                    self.debug_validate();
+                    return Some(node.1);
+                }
+                std::cmp::Ordering::Greater => {
+                    return None;
+                }
+            }
+        }
+    }
+
     pub fn push_or_apply(&mut self, key : T, value: impl FnOnce() ->  G, apply : impl FnOnce(&mut G) -> ()) {
         let current = &mut self.start;
         match current {
             None => {
                 self.add_first(key, value());
+                self.debug_validate();
                 return;
             }
             Some(current) => {
@@ -202,17 +293,37 @@ impl<T: Ord, G> SortedLinkedMap<T, G> {
                             let v = mem::replace(&mut current.1, value());
                             let w = mem::replace(&mut current.2, Some(Box::new(Value(k, v, None))));
                             current.2.as_mut().unwrap().2 = w;
+                            self.size += 1;
                         } else if key > current.0 {
                             current.2 = Some(Box::new(Value(key, value(), None)));
+                            self.size += 1;
                         }
                         else {
                             apply(&mut current.1)
                         }
-                        self.size += 1;
                     }
                 }
             }
         }
+        self.debug_validate();
+    }
+}
+
+impl<T: Ord + Clone, G: Clone> Clone for SortedLinkedMap<T, G> {
+    /// Copies node by node: a derived clone would recurse once per node.
+    fn clone(&self) -> Self {
+        let mut start = None;
+        let mut tail = &mut start;
+        let mut next = &self.start;
+        while let Some(v) = next {
+            let node = tail.insert(Box::new(Value(v.0.clone(), v.1.clone(), None)));
+            tail = &mut node.2;
+            next = &v.2;
+        }
+        Self {
+            start,
+            size: self.size,
+        }
     }
 }
 
@@ -275,14 +386,12 @@ impl<T: Ord, G> Iterator for LinkedMapIterator<T, G> {
 impl<S: VariableSave + Send + Sync> VariableSave for SortedLinkedMap<usize, S> {
     async fn variable_save(&mut self, writer: &mut BufWriter<File>) -> Result<usize, Error> {
         let mut passed = variable_save_usize(self.len(), writer).await? as usize;
-        let mut iter = self.iter_mut();
-        let (mut v, mut vs) = iter.next().unwrap();
-        passed += variable_save_usize(*v, writer).await? as usize;
-        for (i, s) in iter {
-            passed += variable_save_usize((*i) - (*v), writer).await? as usize;
-            passed += vs.variable_save(writer).await?;
-            v = i;
-            vs = s;
+        let mut previous = 0;
+        for (i, s) in self.iter_mut() {
+            document_id(*i, "postings")?;
+            passed += variable_save_usize(*i - previous, writer).await? as usize;
+            passed += s.variable_save(writer).await?;
+            previous = *i;
         }
         Ok(passed)
     }
@@ -290,17 +399,147 @@ impl<S: VariableSave + Send + Sync> VariableSave for SortedLinkedMap<usize, S> {
     async fn variable_load(
         reader: &mut BufReader<File>,
     ) -> Result<SortedLinkedMap<usize, S>, Error> {
-        let mut list = SortedLinkedMap::<usize, S>::new();
-
         let size = variable_load(reader).await?;
-        if size > 0 {
-            let mut previous = variable_load(reader).await?;
-            list.push(previous, S::variable_load(reader).await?);
-            for _ in 0..size - 1 {
-                previous += variable_load(reader).await?;
-                list.push(previous, S::variable_load(reader).await?);
+        Self::load_entries(reader, size).await
+    }
+}
+
+impl<S: VariableSave + Send + Sync> SortedLinkedMap<usize, S> {
+    /// Reads the `size` entries [`VariableSave::variable_load`] would after
+    /// the count, for a reader that has read the count itself.
+    pub async fn load_entries(reader: &mut BufReader<File>, size: usize) -> Result<Self, Error> {
+        let mut list = SortedLinkedMap::<usize, S>::new();
+        let mut previous = 0usize;
+        for i in 0..size {
+            let entry = match variable_load(reader).await {
+                Ok(delta) if i > 0 && delta == 0 => Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("key {previous} repeats"),
+                )),
+                Ok(delta) => match previous.checked_add(delta) {
+                    Some(key) if document_id(key, "postings").is_ok() => match S::variable_load(reader).await {
+                        Ok(value) => Ok((key, value)),
+                        Err(e) => Err(e),
+                    },
+                    key => Err(IdRangeError::Document {
+                        id: key.map_or(u64::MAX, |v| v as u64),
+                        at: "postings",
+                    }
+                    .into()),
+                },
+                Err(e) => Err(e),
+            };
+            match entry {
+                Ok((key, value)) => {
+                    list.push(key, value);
+                    previous = key;
+                }
+                Err(e) => return Err(entry_error(reader, e, i, size).await),
             }
         }
         Ok(list)
     }
 }
+
+/// `e` with where the read of entry `entry` of `size` stopped, keeping its kind.
+async fn entry_error(reader: &mut BufReader<File>, e: Error, entry: usize, size: usize) -> Error {
+    let offset = match reader.stream_position().await {
+        Ok(v) => v.to_string(),
+        Err(_) => "?".to_string(),
+    };
+    Error::new(
+        e.kind(),
+        format!(
+            "entry {entry} of {size} at byte {offset}, {} expected still: {e}",
+            size - entry
+        ),
+    )
+}
+
+#[test]
+fn or_keeps_invariants() {
+    let mut first = SortedLinkedMap::<usize, usize>::new();
+    for v in [6, 3, 1, 3, 4] {
+        first.push(v, v);
+    }
+    let mut second = SortedLinkedMap::<usize, usize>::new();
+    for v in [2, 10, 3, 12] {
+        second.push(v, v * 10);
+    }
+    let copy = first.clone();
+    first
+        .or(second, |a, b| {
+            *a += *b;
+            Ok::<_, ()>(())
+        })
+        .unwrap();
+    first.validate().unwrap();
+    assert_eq!(
+        first.iter().collect::<Vec<_>>(),
+        [(1, 1), (2, 20), (3, 33), (4, 4), (6, 6), (10, 100), (12, 120)]
+    );
+
+    let mut empty = SortedLinkedMap::<usize, usize>::new();
+    empty.or(copy.clone(), |_, _| Ok::<_, ()>(())).unwrap();
+    empty.validate().unwrap();
+    assert_eq!(empty.iter().collect::<Vec<_>>(), copy.iter().collect::<Vec<_>>());
+}
+
+#[test]
+fn validate_catches_corrupted_chains() {
+    let message = |map: SortedLinkedMap<usize, ()>| map.validate().unwrap_err().to_string();
+    assert!(SortedLinkedMap::from_raw(vec![(1, ()), (4, ()), (9, ())], 3).validate().is_ok());
+    assert_eq!(
+        message(SortedLinkedMap::from_raw(vec![(1, ()), (9, ()), (4, ())], 3)),
+        "key at node 2 is not above the previous one"
+    );
+    assert_eq!(
+        message(SortedLinkedMap::from_raw(vec![(1, ()), (4, ()), (4, ())], 3)),
+        "key at node 2 is not above the previous one"
+    );
+    assert_eq!(
+        message(SortedLinkedMap::from_raw(vec![(1, ()), (4, ()), (9, ())], 2)),
+        "chain is longer than size 2"
+    );
+    assert_eq!(
+        message(SortedLinkedMap::from_raw(vec![(1, ())], 3)),
+        "size is 3 but the chain has 1 nodes"
+    );
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "SortedLinkedMap corrupted")]
+fn corrupted_chain_panics_on_push() {
+    let mut map = SortedLinkedMap::<usize, ()>::from_raw(vec![(1, ()), (4, ())], 5);
+    map.push(9, ());
+}
+
+#[cfg(target_pointer_width = "64")]
+#[tokio::test]
+async fn keys_past_u32_fail_both_ways() -> Result<(), Error> {
+    use tokio::io::AsyncWriteExt;
+
+    let path = std::env::temp_dir().join(format!("listmap_ids_{}", std::process::id()));
+    let mut map = SortedLinkedMap::<usize, ()>::new();
+    map.push(u32::MAX as usize + 1, ());
+    let mut writer = BufWriter::new(File::create(&path).await?);
+    let e = map.variable_save(&mut writer).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidData);
+    assert!(e.to_string().contains("document id 4294967296 in postings"), "{e}");
+
+    // Deltas that each fit but add up past the range.
+    let mut writer = BufWriter::new(File::create(&path).await?);
+    for v in [2, u32::MAX as usize, 1] {
+        variable_save_usize(v, &mut writer).await?;
+    }
+    writer.flush().await?;
+    let mut reader = BufReader::new(File::open(&path).await?);
+    let e = SortedLinkedMap::<usize, ()>::variable_load(&mut reader).await.unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidData);
+    assert!(e.to_string().starts_with("entry 1 of 2"), "{e}");
+    assert!(e.to_string().contains("document id 4294967296"), "{e}");
+
+    tokio::fs::remove_file(&path).await?;
+    Ok(())
+}
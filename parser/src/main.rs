@@ -4,17 +4,117 @@ use std::sync::Arc;
 use sysinfo::SystemExt;
 
 
+use crate::config::{IndexerConfig, IoTuning, MergerConfig, OutputFormat};
 use crate::indexed::{IndexedBuilder, IndexMerger, IndexParser};
+use crate::zones::ZoneSet;
 
+#[cfg(feature = "query")]
+pub mod analyzer;
+#[cfg(feature = "roaring")]
+pub mod bitmaps;
+#[cfg(feature = "query")]
+pub mod block_cache;
+pub mod block_dir;
+#[cfg(feature = "query")]
+pub mod boost;
+#[cfg(feature = "query")]
+pub mod bounds;
+#[cfg(feature = "build")]
+pub mod buffer_meta;
+pub mod cancel;
+#[cfg(feature = "query")]
+pub mod config;
+#[cfg(feature = "query")]
+pub mod case;
+#[cfg(feature = "query")]
+pub mod delta;
+pub mod doc_id;
+#[cfg(feature = "build")]
+pub mod estimate;
+#[cfg(feature = "query")]
+pub mod execute;
+pub mod filter;
+#[cfg(feature = "query")]
+pub mod forward;
+pub mod gallop;
+pub mod generation;
+#[cfg(feature = "build")]
+pub mod inputs;
+#[cfg(feature = "query")]
 pub mod indexed;
+pub mod layout;
 pub mod list;
+#[cfg(feature = "build")]
 pub mod parser;
+pub mod paths;
+#[cfg(feature = "query")]
+pub mod permuterm;
+#[cfg(feature = "query")]
+pub mod phonetic;
+#[cfg(feature = "build")]
+pub mod pipeline;
+#[cfg(feature = "build")]
+pub mod postings;
+#[cfg(feature = "query")]
+pub mod provenance;
+#[cfg(feature = "query")]
+pub mod query;
+#[cfg(feature = "query")]
+pub mod rank;
+#[cfg(feature = "build")]
 pub mod reader;
+#[cfg(feature = "build")]
+pub mod reindex;
+#[cfg(feature = "build")]
+pub mod reorder;
+#[cfg(feature = "build")]
+pub mod run_log;
 
+#[cfg(feature = "build")]
 pub mod rep_reader;
+#[cfg(feature = "query")]
+pub mod report;
+#[cfg(feature = "query")]
+pub mod resolve;
 pub mod listmap;
+#[cfg(feature = "query")]
+pub mod metadata;
+#[cfg(feature = "query")]
+pub mod numeric;
+#[cfg(feature = "build")]
+pub mod open_files;
+#[cfg(feature = "query")]
+pub mod sample;
 pub mod save;
 pub mod segment;
+#[cfg(feature = "query")]
+pub mod stats;
+#[cfg(feature = "query")]
+pub mod strategy;
+#[cfg(feature = "query")]
+pub mod synonym;
+#[cfg(feature = "query")]
+pub mod tasks;
+#[cfg(feature = "fst")]
+pub mod term_fst;
+pub mod term_ord;
+#[cfg(feature = "build")]
+pub mod text_sink;
+#[cfg(all(test, feature = "build"))]
+pub(crate) mod testsupport;
+#[cfg(feature = "build")]
+pub mod throttle;
+#[cfg(feature = "query")]
+pub mod titles;
+#[cfg(feature = "build")]
+pub mod token_stream;
+#[cfg(feature = "query")]
+pub mod verify;
+#[cfg(feature = "query")]
+pub mod warnings;
+#[cfg(feature = "query")]
+pub mod watcher;
+pub mod zones;
 
 static mut SYSTEM: Option<sysinfo::System> = None;
 
@@ -22,18 +122,61 @@ pub fn get_system() -> &'static sysinfo::System {
     unsafe { SYSTEM.as_ref().unwrap() }
 }
 
+fn arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a String> {
+    args.iter()
+        .position(|v| v == name)
+        .and_then(|i| args.get(i + 1))
+}
+
+fn arg_values(args: &[String], name: &str) -> Vec<String> {
+    args.windows(2)
+        .filter(|v| v[0] == name)
+        .map(|v| v[1].clone())
+        .collect()
+}
+
+/// The index `--dump`, `--query`, `postings` and `verify` read: the
+/// directory after `--index`, which may also be a buffer a parser flushed,
+/// or else the current index in `../res`.
+async fn index_directory(args: &[String]) -> String {
+    match arg_value(args, "--index") {
+        Some(v) => v.clone(),
+        None => crate::generation::resolve("../res").await.unwrap(),
+    }
+}
+
+async fn load_synonyms(
+    path: &str,
+    dictionary: &mut crate::indexed::Dictionary<crate::segment::CommonSegments>,
+) -> crate::synonym::Thesaurus {
+    let thesaurus = crate::synonym::Thesaurus::load(path, dictionary).await.unwrap();
+    for synonym in thesaurus.unknown() {
+        eprintln!("{path}: synonym {synonym} is not in the index");
+    }
+    thesaurus
+}
+
 #[tokio::main]
 async fn main() {
     use std::fs::{self};
+    use std::path::PathBuf;
 
     use chrono::Local;
-    use log4rs::{
-        append::file::FileAppender,
-        config::{Appender, Root},
-        Config,
-    };
 
+    use crate::boost::Boosts;
+    use crate::cancel::CancellationToken;
+    use crate::estimate::{estimate, EstimateConfig};
+    use crate::tasks::{cores, TaskPlan, AUTO_TASKS};
+    use crate::filter::{FilterPatterns, TermFilter};
+    use crate::inputs::{InputOrder, InputSet, DEFAULT_EXTENSIONS};
+    use crate::layout::IndexLayout;
     use crate::parser::ParseController;
+    use crate::pipeline::IndexController;
+    use crate::rank::TfPolicy;
+    use crate::run_log;
+    use crate::sample::Sampling;
+
+    let args = std::env::args().collect::<Vec<_>>();
 
     unsafe {
         SYSTEM = Some(sysinfo::System::new_with_specifics(
@@ -41,50 +184,565 @@ async fn main() {
         ));
     }
 
-    let mut files = fs::read_dir("../gex").unwrap();
-    let mut files_vec = Vec::<String>::new();
-    let mut files_size = 0u64;
-    while let Some(w) = files.next() {
-        let w = w.unwrap();
-        files_vec.push(w.path().to_str().unwrap().to_string());
-        files_size += w.metadata().unwrap().len();
+    if let Some(raw) = arg_value(&args, "--dump") {
+        use crate::indexed::Dictionary;
+        use crate::segment::CommonSegments;
+
+        let destination = index_directory(&args).await;
+        let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await.unwrap();
+        for term in raw.split_whitespace() {
+            match dictionary.find(term).await.unwrap() {
+                Some(found) => println!("{found}"),
+                None => println!("{term} not found"),
+            }
+        }
+        return;
     }
 
-    files_vec.sort_unstable();
+    if args.get(1).map(String::as_str) == Some("stats") {
+        let destination = crate::generation::resolve("../res").await.unwrap();
+        match crate::stats::IndexStats::load(&destination).await {
+            Ok(v) => print!("{v}"),
+            Err(e) => println!("{destination}: {e}"),
+        }
+        return;
+    }
 
-    let log_pipe = FileAppender::builder()
-        .append(false)
-        .build("info.txt")
-        .unwrap();
-    let config = Config::builder()
-        .appender(Appender::builder().build("pipe", Box::new(log_pipe)))
-        .build(
-            Root::builder()
-                .appender("pipe")
-                .build(log::LevelFilter::Info),
+    if args.get(1).map(String::as_str) == Some("upgrade") {
+        let destination = crate::generation::resolve("../res").await.unwrap();
+        match crate::metadata::upgrade_index(&destination).await {
+            Ok(true) => println!("{destination}: upgraded"),
+            Ok(false) => println!("{destination}: already current"),
+            Err(e) => println!("{destination}: {e}"),
+        }
+        return;
+    }
+
+    if args.get(1).map(String::as_str) == Some("verify") {
+        use crate::indexed::{repair_header, verify_index};
+        use crate::provenance::verify_inputs;
+        use crate::segment::CommonSegments;
+        use crate::verify::verify_index_jobs;
+
+        let destination = index_directory(&args).await;
+        if args.iter().any(|v| v == "--repair") {
+            match repair_header(&destination).await {
+                Ok(v) => println!("{destination}: {v}"),
+                Err(e) => println!("{destination}: can't repair the header: {e}"),
+            }
+        }
+        match arg_value(&args, "--jobs") {
+            Some(jobs) => match verify_index_jobs::<CommonSegments>(&destination, jobs.parse().unwrap()).await {
+                Ok(counted) => {
+                    println!("{destination}: index is consistent");
+                    if let Ok(saved) = crate::stats::IndexStats::load(&destination).await {
+                        if (saved.occurrences, saved.postings, saved.vocabulary)
+                            != (counted.occurrences, counted.postings, counted.vocabulary)
+                        {
+                            println!("{destination}: stats.json doesn't match the index, which holds");
+                        }
+                    }
+                    print!("{counted}");
+                }
+                Err(e) => println!("{destination}: {e}"),
+            },
+            None => match verify_index::<CommonSegments>(&destination).await {
+                Ok(()) => println!("{destination}: index is consistent"),
+                Err(e) => println!("{destination}: {e}"),
+            },
+        }
+        if args.iter().any(|v| v == "--inputs") {
+            match verify_inputs(&destination).await {
+                Ok(v) => print!("{v}"),
+                Err(e) => println!("{e}"),
+            }
+        }
+        return;
+    }
+
+    if args.get(1).map(String::as_str) == Some("postings") {
+        use crate::indexed::Dictionary;
+        use crate::resolve::DocResolver;
+        use crate::segment::CommonSegments;
+
+        let destination = index_directory(&args).await;
+        let term = arg_value(&args, "--term").expect("postings needs --term");
+        let limit = arg_value(&args, "--limit").map_or(usize::MAX, |v| v.parse().unwrap());
+        let resolver = DocResolver::load(&destination).await.unwrap_or_default();
+        let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await.unwrap();
+        let Some(mut postings) = dictionary.postings(term).await.unwrap() else {
+            println!("{term} not found");
+            return;
+        };
+        println!("{term} df={}", postings.len());
+        for _ in 0..limit {
+            let Some((document, usage)) = postings.next().await.unwrap() else {
+                break;
+            };
+            println!(
+                "{document}\t{}\t{}\t{usage}",
+                resolver.source_of(document).map_or("-".into(), |v| v.display().to_string()),
+                resolver.title_of(document).unwrap_or("-")
+            );
+        }
+        return;
+    }
+
+    if args.get(1).map(String::as_str) == Some("doc") {
+        use crate::forward::terms_of_document;
+        use crate::indexed::Dictionary;
+        use crate::segment::CommonSegments;
+
+        let destination = index_directory(&args).await;
+        let document = arg_value(&args, "--terms").expect("doc needs --terms <id>").parse().unwrap();
+        let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await.unwrap();
+        match terms_of_document(&mut dictionary, document).await {
+            Ok(terms) => {
+                println!("{document} terms={}", terms.len());
+                for (term, count) in terms {
+                    println!("{term}\t{count}");
+                }
+            }
+            Err(e) => println!("{destination}: {e}"),
+        }
+        return;
+    }
+
+    if let Some(raw) = arg_value(&args, "--query") {
+        use crate::analyzer::Analyzer;
+        use crate::execute::{execute_raw, QueryLimits, QueryLog, QueryOptions};
+        use crate::indexed::Dictionary;
+        use crate::metadata::IndexMetadata;
+        use crate::query::QueryCache;
+        use crate::resolve::DocResolver;
+        use crate::segment::{CommonSegmentSelector, CommonSegments};
+        use crate::synonym::Thesaurus;
+
+        let log = QueryLog {
+            all: args.iter().any(|v| v == "--log-queries"),
+            slow: arg_value(&args, "--slow-query-ms")
+                .map(|v| std::time::Duration::from_millis(v.parse().unwrap())),
+        };
+        if log.all || log.slow.is_some() {
+            crate::run_log::init_stderr(log::LevelFilter::Info);
+        }
+        let destination = index_directory(&args).await;
+        let metadata = IndexMetadata::load_or_legacy(&destination).await.unwrap_or_default();
+        let analyzer = Analyzer::from_metadata(&destination).await.unwrap_or_default();
+        let mut cache = QueryCache::<CommonSegments>::new(0).with_analyzer(analyzer);
+        if let Some(zones) = metadata.zones.clone() {
+            cache = cache.with_zones(zones);
+        }
+        let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await.unwrap();
+        let synonyms = match arg_value(&args, "--synonyms") {
+            Some(path) => load_synonyms(path, &mut dictionary).await,
+            None => Thesaurus::default(),
+        };
+        let selector = CommonSegmentSelector::new();
+        let limits = QueryLimits {
+            max_term_bytes: metadata.token_limit(),
+            ..QueryLimits::default()
+        };
+        let cancel = CancellationToken::new();
+        let options = QueryOptions { limits, synonyms: &synonyms, cancel: &cancel };
+        match execute_raw(raw, &cache, metadata.generation, &selector, &mut dictionary, options).await {
+            Ok(result) => {
+                log.record(raw, &result);
+                let resolver = match args.iter().any(|v| v == "--resolve") {
+                    true => Some(DocResolver::load(&destination).await.unwrap()),
+                    false => None,
+                };
+                for document in result.documents.iter() {
+                    match &resolver {
+                        Some(resolver) => println!(
+                            "{document}\t{}\t{}",
+                            resolver.source_of(*document).map_or("-".into(), |v| v.display().to_string()),
+                            resolver.title_of(*document).unwrap_or("-")
+                        ),
+                        None => println!("{document}"),
+                    }
+                }
+                if args.iter().any(|v| v == "--timings") {
+                    println!("{} terms, {}", result.expanded_terms, result.timings);
+                }
+            }
+            Err(e) => println!("{e}"),
+        }
+        return;
+    }
+
+    if let Some(raw) = arg_value(&args, "--search") {
+        use crate::indexed::Dictionary;
+        use crate::boost::DocumentBoosts;
+        use crate::metadata::IndexMetadata;
+        use crate::rank::{Concurrency, DocumentLengths, IdfTable, Scorer};
+        use crate::segment::{CommonSegmentSelector, CommonSegments};
+
+        let destination = crate::generation::resolve("../res").await.unwrap();
+        let mut scorer = Scorer::new(
+            &CommonSegmentSelector::new(),
+            &[("title", 2.0), ("text", 1.0)],
+            DocumentLengths::load(&destination).await.unwrap(),
         )
         .unwrap();
+        if let Some(table) = IdfTable::load_current(&destination).await.unwrap() {
+            scorer = scorer.with_idf_table(table);
+        }
+        let metadata = IndexMetadata::load_or_legacy(&destination).await.unwrap();
+        scorer = scorer
+            .with_tf_policy(metadata.tf)
+            .with_max_term_bytes(metadata.token_limit());
+        if let Ok(boosts) = DocumentBoosts::load(&destination).await {
+            scorer = scorer.with_boosts(boosts);
+        }
+        if let Some(boost) = arg_value(&args, "--exact-case") {
+            scorer = scorer.with_exact_case(boost.parse().unwrap());
+        }
+        let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await.unwrap();
+        if args.iter().any(|v| v == "--zone-idf") {
+            scorer = scorer.with_zone_idf(&dictionary).await.unwrap();
+        }
+        if args.iter().any(|v| v == "--bm25f") {
+            let k1 = arg_value(&args, "--k1").map_or(1.2, |v| v.parse().unwrap());
+            let b = arg_value(&args, "--b").map_or(0.75, |v| v.parse().unwrap());
+            scorer = scorer.with_bm25f(k1, b).unwrap();
+        }
+        if let Some(path) = arg_value(&args, "--synonyms") {
+            let discount = arg_value(&args, "--synonym-discount").map_or(0.5, |v| v.parse().unwrap());
+            scorer = scorer.with_synonyms(load_synonyms(path, &mut dictionary).await, discount);
+        }
+        let terms = raw.split_whitespace().collect::<Vec<_>>();
+        match arg_value(&args, "--explain") {
+            Some(document) => {
+                let document = document.parse().unwrap();
+                let explanation = scorer
+                    .search_explain(&mut dictionary, &terms, document)
+                    .await
+                    .unwrap();
+                print!("{explanation}");
+            }
+            None if args.iter().any(|v| v == "--all") || arg_value(&args, "--strategy").is_some() => {
+                use crate::strategy::{search, Combine, Strategy};
+
+                let combine = match args.iter().any(|v| v == "--all") {
+                    true => Combine::All,
+                    false => Combine::Any,
+                };
+                let strategy = arg_value(&args, "--strategy")
+                    .filter(|v| *v != "auto")
+                    .map(|v| Strategy::parse(v).unwrap());
+                let (ranked, strategy) = search(&scorer, &mut dictionary, &terms, combine, strategy)
+                    .await
+                    .unwrap();
+                log::info!("searched {} as {:?}", strategy.name(), combine);
+                for (document, score) in ranked {
+                    println!("{document} {score:.6}");
+                }
+            }
+            None => {
+                let ranked = match arg_value(&args, "--search-tasks") {
+                    Some(tasks) => {
+                        let concurrency = Concurrency {
+                            max_tasks: tasks.parse().unwrap(),
+                            ..Concurrency::default()
+                        };
+                        Arc::new(scorer)
+                            .search_concurrent(&mut dictionary, &terms, concurrency)
+                            .await
+                    }
+                    None => match arg_value(&args, "--top") {
+                        Some(k) => scorer
+                            .search_top(&mut dictionary, &terms, k.parse().unwrap())
+                            .await
+                            .map(|(ranked, stats)| {
+                                log::info!("{} postings scored, {} skipped", stats.scored, stats.skipped);
+                                ranked
+                            }),
+                        None => scorer.search(&mut dictionary, &terms).await,
+                    },
+                };
+                for (document, score) in ranked.unwrap() {
+                    println!("{document} {score:.6}");
+                }
+            }
+        }
+        return;
+    }
+
+    let mut specs = arg_values(&args, "--input");
+    if specs.is_empty() {
+        specs.push(arg_value(&args, "--only").map_or("../gex".to_string(), String::clone));
+    }
+    let extensions = arg_values(&args, "--extension");
+    let order = arg_value(&args, "--input-order").map_or(Ok(InputOrder::default()), |v| InputOrder::parse(v));
+    let inputs = match (order, extensions.is_empty()) {
+        (Err(e), _) => Err(e),
+        (Ok(order), true) => InputSet::resolve_ordered(&specs, &DEFAULT_EXTENSIONS, order).await,
+        (Ok(order), false) => {
+            let extensions = extensions.iter().map(String::as_str).collect::<Vec<_>>();
+            InputSet::resolve_ordered(&specs, &extensions, order).await
+        }
+    };
+    let inputs = match inputs {
+        Ok(v) => v,
+        Err(e) => {
+            println!("{e}");
+            return;
+        }
+    };
+    let files_vec = inputs.files.clone();
+    let files_size = inputs.size().await.unwrap();
+
+    if args.get(1).map(String::as_str) == Some("audit-case") {
+        use crate::case::audit_case;
+
+        let attributes = ZoneSet::new(["title", "text"]).unwrap();
+        match audit_case(&files_vec, attributes).await {
+            Ok(v) => print!("{v}"),
+            Err(e) => println!("{e}"),
+        }
+        return;
+    }
+
+    let defaults = IoTuning::default();
+    let io = match IoTuning::new(
+        arg_value(&args, "--reader-buf").map_or(defaults.reader_buf(), |v| v.parse().unwrap()),
+        arg_value(&args, "--writer-buf").map_or(defaults.writer_buf(), |v| v.parse().unwrap()),
+        arg_value(&args, "--chunk-buf").map_or(defaults.chunk_buf(), |v| v.parse().unwrap()),
+    ) {
+        Ok(v) => v,
+        Err(e) => {
+            println!("{e}");
+            return;
+        }
+    };
+    let indexer = match IndexerConfig::new(
+        arg_value(&args, "--tree-max-terms").map_or(100000, |v| v.parse().unwrap()),
+        arg_value(&args, "--lexical-block-size").map_or(100, |v| v.parse().unwrap()),
+    ) {
+        Ok(v) => v.with_io(io),
+        Err(e) => {
+            println!("{e}");
+            return;
+        }
+    };
+    let merger_config = match arg_value(&args, "--merge-block-size") {
+        Some(size) => match MergerConfig::new(size.parse().unwrap()) {
+            Ok(v) => v.with_io(io),
+            Err(e) => {
+                println!("{e}");
+                return;
+            }
+        },
+        None => indexer.merger(),
+    };
+    let merger_config = match arg_value(&args, "--output-format") {
+        Some(format) => merger_config.with_format(OutputFormat::parse(format).unwrap()),
+        None => merger_config,
+    };
 
-    let _handle = log4rs::init_config(config).unwrap();
+    let tasks = arg_value(&args, "--tasks").map_or(AUTO_TASKS, |v| v.parse().unwrap());
+    if args.iter().any(|v| v == "--dry-run") {
+        let requested = (tasks != AUTO_TASKS).then_some(tasks);
+        let config = EstimateConfig {
+            sample_fraction: arg_value(&args, "--estimate-fraction").map_or(0.01, |v| v.parse().unwrap()),
+            indexer,
+            tasks_count: TaskPlan::new(requested, cores(), files_vec.len(), None).tasks,
+            attributes: ZoneSet::new(["title", "text"]).unwrap(),
+        };
+        match estimate(&files_vec, &config).await {
+            Ok(v) => println!("{v}"),
+            Err(e) => println!("{e}"),
+        }
+        return;
+    }
 
     let destination = "../res".to_string();
+    let log_file = arg_value(&args, "--log-file").map_or_else(|| run_log::default_path(&destination), PathBuf::from);
+    let _handle = run_log::init(&log_file, log::LevelFilter::Info);
+
     let buffer = ".\\buffer".to_string();
 
     log::info!("Files' overall size {} kb", files_size / 1024);
     log::info!("{}", Local::now().format("Start at %H:%M:%S").to_string());
 
-    match ParseController::<IndexParser, _, _>::new(
-        files_vec,
-        destination,
-        buffer,
-        12,
-        IndexedBuilder::new(100000, 6, Arc::new(vec!["title".to_string(), "text".to_string()])),
-        IndexMerger::new(100),
-    )
-    .create_dictionary()
-    .await {
-        Ok(_) => {},
-        Err(e) => println!("{e}"),
+    let mut builder = IndexedBuilder::new(indexer, ZoneSet::new(["title", "text"]).unwrap()).unwrap();
+    if let Some(path) = arg_value(&args, "--analyzer") {
+        let config = serde_json::from_slice(&tokio::fs::read(path).await.unwrap()).unwrap();
+        builder = builder.with_analyzer(config).unwrap();
+    }
+    let patterns = FilterPatterns {
+        drop: arg_values(&args, "--drop-terms"),
+        keep: arg_values(&args, "--keep-terms"),
+    };
+    if !patterns.drop.is_empty() || !patterns.keep.is_empty() {
+        builder = builder.with_filter(TermFilter::new(patterns).unwrap());
+    }
+    builder = builder.with_tf_policy(TfPolicy {
+        max_tf: arg_value(&args, "--max-tf").map(|v| v.parse().unwrap()),
+        log_scaled: args.iter().any(|v| v == "--log-tf"),
+    });
+    if let Some(tag) = arg_value(&args, "--numeric-field") {
+        builder = builder.with_numeric_field(tag).unwrap();
+    }
+    if args.iter().any(|v| v == "--case-preserving") {
+        builder = builder.with_case_preserving();
+    }
+    if args.iter().any(|v| v == "--checked-zones") {
+        builder = builder.with_checked_zones();
+    }
+    let excluded = arg_values(&args, "--exclude-element");
+    if !excluded.is_empty() {
+        builder = builder.with_excluded_elements(excluded).unwrap();
+    }
+    if let Some(path) = arg_value(&args, "--only") {
+        use crate::reindex::reindex_file;
+
+        match reindex_file("../res", path, builder, merger_config).await {
+            Ok(v) => log::info!("{v}"),
+            Err(e) => println!("{e}"),
+        }
+        return;
+    }
+
+    let mut merger = IndexMerger::new(merger_config);
+    if args.iter().any(|v| v == "--phonetic") {
+        merger = merger.with_phonetic();
+    }
+    if args.iter().any(|v| v == "--permuterm") {
+        merger = merger.with_permuterm();
+    }
+    if let Some(k) = arg_value(&args, "--idf-top") {
+        merger = merger.with_idf_top(k.parse().unwrap());
+    }
+    if args.iter().any(|v| v == "--score-bounds") {
+        merger = merger.with_score_bounds();
+    }
+    if let Some(bytes) = arg_value(&args, "--max-shared-prefix") {
+        merger = merger.with_max_shared_prefix(bytes.parse().unwrap());
+    }
+    #[cfg(feature = "fst")]
+    if args.iter().any(|v| v == "--fst") {
+        merger = merger.with_fst();
+    }
+    #[cfg(feature = "roaring")]
+    if let Some(min_df) = arg_value(&args, "--bitmaps") {
+        merger = merger.with_bitmaps(min_df.parse().unwrap());
+    }
+    if let Some(layout) = arg_value(&args, "--layout") {
+        merger = merger.with_layout(IndexLayout::parse(layout).unwrap());
+    }
+    if let Some(n) = arg_value(&args, "--fan-in") {
+        merger = merger.with_fan_in(n.parse().unwrap());
+    }
+    if let Some(bytes) = arg_value(&args, "--spill-budget") {
+        merger = merger.with_spill_budget(bytes.parse().unwrap());
+    }
+    if args.iter().any(|v| v == "--force") {
+        merger = merger.with_force();
+    }
+    if args.iter().any(|v| v == "--forward-index") {
+        merger = merger.with_forward_index();
+    }
+    let boosts = match arg_value(&args, "--boosts") {
+        Some(path) => Some(Boosts::load(path).await.unwrap()),
+        None => None,
+    };
+    let cancel = CancellationToken::new();
+    tokio::spawn({
+        let cancel = cancel.clone();
+        async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                log::warn!("Ctrl-C, stopping the build");
+                cancel.cancel();
+            }
+        }
+    });
+    let configure = {
+        let args = args.clone();
+        move |mut controller: IndexController| {
+            // sysinfo counts memory in KiB.
+            controller = controller.with_memory_budget(get_system().available_memory() * 1024);
+            if let Some(fraction) = arg_value(&args, "--sample") {
+                let seed = arg_value(&args, "--seed").map_or(0, |v| v.parse().unwrap());
+                controller = controller.with_sampling(Sampling::new(fraction.parse().unwrap(), seed).unwrap());
+            }
+            if let Some(boosts) = boosts {
+                controller = controller.with_boosts(boosts);
+            }
+            if args.iter().any(|v| v == "--store-titles") {
+                controller = controller.with_titles();
+            }
+            if args.iter().any(|v| v == "--strict") {
+                controller = controller.with_warning_policy(crate::warnings::WarningPolicy::Strict);
+            }
+            if let Some(keep) = arg_value(&args, "--generations") {
+                controller = controller.with_generations(keep.parse().unwrap());
+            }
+            if args.iter().any(|v| v == "--overwrite") {
+                controller = controller.with_overwrite();
+            }
+            if args.iter().any(|v| v == "--clean-buffer") {
+                controller = controller.with_clean_buffer();
+            }
+            if args.iter().any(|v| v == "--in-memory") {
+                controller = controller.with_in_memory();
+            } else {
+                // Inputs under 64 MiB are built without buffers.
+                controller = controller
+                    .with_in_memory_below(arg_value(&args, "--in-memory-below").map_or(64 << 20, |v| v.parse().unwrap()));
+            }
+            if let Some(raw) = arg_value(&args, "--throttle") {
+                use crate::throttle::Throttle;
+
+                let mut throttle = Throttle::new(Throttle::parse_rate(raw).unwrap()).unwrap();
+                if let Some(ms) = arg_value(&args, "--throttle-pause-ms") {
+                    throttle = throttle.with_pause(std::time::Duration::from_millis(ms.parse().unwrap()));
+                }
+                if let Some(percent) = arg_value(&args, "--throttle-cpu") {
+                    throttle = throttle.with_cpu_threshold(percent.parse().unwrap());
+                }
+                controller = controller.with_throttle(throttle);
+            }
+            controller.with_cancellation(cancel)
+        }
+    };
+
+    if args.get(1).map(String::as_str) == Some("index") {
+        use crate::pipeline::{index_directory, IndexOptions};
+
+        let [input] = specs.as_slice() else {
+            println!("index takes a single --input");
+            return;
+        };
+        let mut options = IndexOptions::new(builder, merger)
+            .with_tasks(tasks)
+            .with_buffer(&buffer)
+            .with_input_order(inputs.order)
+            .with_controller(configure);
+        if !extensions.is_empty() {
+            options = options.with_extensions(extensions);
+        }
+        match index_directory(input, &destination, options).await {
+            Ok(v) => print!("{v}"),
+            Err(e) => println!("{e}"),
+        }
+    } else {
+        let controller = ParseController::from_inputs(inputs, destination, buffer, tasks, builder, merger);
+        match configure(controller).create_dictionary().await {
+            Ok(_) => {},
+            Err(e) => println!("{e}"),
+        }
+    }
+
+    if let Some(by) = arg_value(&args, "--reorder") {
+        use crate::reorder::{reorder, ReorderBy};
+        use crate::segment::CommonSegments;
+
+        match reorder::<CommonSegments>("../res", merger_config, ReorderBy::parse(by).unwrap()).await {
+            Ok(v) => log::info!("{v}"),
+            Err(e) => println!("{e}"),
+        }
     }
 
     log::info!("{}", Local::now().format("End at %H:%M:%S").to_string());
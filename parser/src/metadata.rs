@@ -0,0 +1,210 @@
+use std::{
+    collections::BTreeMap,
+    io::{Error, ErrorKind},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::{
+    analyzer::AnalyzerConfig, filter::FilterPatterns, layout::IndexLayout, provenance::BuildRecord,
+    rank::TfPolicy, sample::Sampling, warnings::MAX_TOKEN_BYTES, zones::ZoneSet,
+};
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SampleRecord {
+    pub sampling: Option<Sampling>,
+    pub documents: usize,
+    pub included: usize,
+}
+
+/// Build parameters recorded next to the index in `metadata.json`.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IndexMetadata {
+    pub sample: SampleRecord,
+    /// Terms matching these never reached the index.
+    pub filter: Option<FilterPatterns>,
+    /// Whether `phonetic_part.txt` was written.
+    pub phonetic: bool,
+    /// Whether the `permuterm` rotation dictionary was written.
+    pub permuterm: bool,
+    /// Whether `terms.fst` was written.
+    pub fst: bool,
+    /// Document frequency from which terms got a bitmap in
+    /// `bitmap_part.bin`, `None` if it wasn't written.
+    pub bitmaps: Option<usize>,
+    /// How per-document counts in the postings were capped or scaled.
+    pub tf: TfPolicy,
+    /// Tag stored as a numeric field in `numeric.txt`.
+    pub numeric: Option<String>,
+    /// Whether original spellings were indexed next to the folded words.
+    pub case_preserving: bool,
+    /// Steps words went through to become terms, `None` for indexes built
+    /// before they were recorded; see [`crate::analyzer::Analyzer::from_metadata`].
+    pub analyzer: Option<AnalyzerConfig>,
+    /// Elements the reader skipped whole.
+    pub excluded: Vec<String>,
+    /// Zones of every document in reading order, `None` for indexes built
+    /// before they were recorded.
+    pub zones: Option<ZoneSet>,
+    /// Documents with a token in each zone, by zone name; a zone no
+    /// document has is left out. `None` for indexes built before they
+    /// were counted.
+    pub zone_documents: Option<BTreeMap<String, usize>>,
+    /// Stamp of the build, tables derived from an index carry it to be checked against.
+    pub generation: u64,
+    /// Inputs and settings of the build, `None` for indexes built before they were recorded.
+    pub build: Option<BuildRecord>,
+    /// Bytes words were cut to, `None` for indexes built before it was
+    /// recorded; see [`Self::token_limit`].
+    pub max_token_bytes: Option<usize>,
+}
+
+impl IndexMetadata {
+    pub async fn save(&self, directory: &str) -> Result<(), Error> {
+        let data = serde_json::to_vec_pretty(self)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        fs::write(IndexLayout::metadata(directory), data).await
+    }
+
+    pub async fn load(directory: &str) -> Result<Self, Error> {
+        let data = fs::read(IndexLayout::metadata(directory)).await?;
+        serde_json::from_slice(&data).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    /// What an index from before `metadata.json` was written was built
+    /// with: zones `title` and `text`, and case folding alone.
+    pub fn legacy() -> Self {
+        Self {
+            zones: Some(ZoneSet::new(["title", "text"]).unwrap()),
+            analyzer: Some(AnalyzerConfig::default()),
+            ..Self::default()
+        }
+    }
+
+    /// Like [`Self::load`], taking an index without `metadata.json` for a
+    /// [`Self::legacy`] one. [`crate::indexed::Dictionary::new`] warns
+    /// about opening it.
+    pub async fn load_or_legacy(directory: &str) -> Result<Self, Error> {
+        match Self::load(directory).await {
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::legacy()),
+            v => v,
+        }
+    }
+
+    /// Longest term the index can hold, in bytes. Older indexes were cut at
+    /// [`MAX_TOKEN_BYTES`] too.
+    pub fn token_limit(&self) -> usize {
+        self.max_token_bytes.unwrap_or(MAX_TOKEN_BYTES)
+    }
+}
+
+/// Gives the legacy index in `directory` the `metadata.json` of
+/// [`IndexMetadata::legacy`] with a fresh generation, so it opens like one
+/// built now. Nothing else of the index is rewritten. The file is written
+/// aside and renamed in place, so a reader sees either index whole.
+///
+/// Returns whether there was anything to upgrade; fails with
+/// [`ErrorKind::NotFound`] if `directory` holds no index.
+pub async fn upgrade_index(directory: &str) -> Result<bool, Error> {
+    let path = IndexLayout::metadata(directory);
+    if fs::metadata(&path).await.is_ok() {
+        return Ok(false);
+    }
+    let dictionary = IndexLayout::detect(directory).await?.dictionary(directory);
+    if let Err(e) = fs::metadata(&dictionary).await {
+        return Err(Error::new(e.kind(), format!("{dictionary}: {e}")));
+    }
+    let metadata = IndexMetadata {
+        generation: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |v| v.as_nanos() as u64),
+        max_token_bytes: Some(MAX_TOKEN_BYTES),
+        ..IndexMetadata::legacy()
+    };
+    let data = serde_json::to_vec_pretty(&metadata).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    let temporary = format!("{path}.tmp");
+    fs::write(&temporary, data).await?;
+    fs::rename(&temporary, &path).await?;
+    Ok(true)
+}
+
+#[cfg(all(test, feature = "build"))]
+mod tst {
+    use std::{
+        io::{Error, ErrorKind},
+        path::Path,
+    };
+
+    use tokio::fs;
+
+    use crate::{
+        analyzer::Analyzer,
+        indexed::Dictionary,
+        layout::IndexLayout,
+        segment::CommonSegments,
+        testsupport::{scratch, word, Corpus, CorpusSpec},
+    };
+
+    use super::{upgrade_index, IndexMetadata};
+
+    /// An index as written before `metadata.json` was: the same files but
+    /// that one.
+    async fn legacy_index(corpus: &Corpus, root: &Path) -> Result<String, Error> {
+        let destination = corpus.index(root).await?;
+        fs::remove_file(IndexLayout::metadata(&destination)).await?;
+        Ok(destination)
+    }
+
+    async fn postings(directory: &String, term: &str) -> Result<Vec<usize>, Error> {
+        let mut dictionary = Dictionary::<CommonSegments>::new(directory).await?;
+        let mut found = Vec::new();
+        if let Some(mut postings) = dictionary.postings(term).await? {
+            while let Some((document, _)) = postings.next().await? {
+                found.push(document);
+            }
+        }
+        Ok(found)
+    }
+
+    #[tokio::test]
+    async fn legacy_indexes_read_and_upgrade() -> Result<(), Error> {
+        let root = scratch("legacy").await?;
+        let corpus = CorpusSpec {
+            docs: 60,
+            ..CorpusSpec::default()
+        }
+        .generate(&root.join("corpus"))
+        .await?;
+        let destination = legacy_index(&corpus, &root).await?;
+        let terms = [word(0), word(3), word(40)];
+
+        for term in terms.iter() {
+            assert_eq!(postings(&destination, term).await?, corpus.documents(term), "{term}");
+        }
+        let metadata = IndexMetadata::load_or_legacy(&destination).await?;
+        assert_eq!(metadata, IndexMetadata::legacy());
+        assert_eq!(metadata.zones.unwrap().iter().collect::<Vec<_>>(), ["title", "text"]);
+        assert_eq!(Analyzer::from_metadata(&destination).await?.config(), &Default::default());
+
+        assert!(upgrade_index(&destination).await?);
+        let upgraded = IndexMetadata::load(&destination).await?;
+        assert!(upgraded.generation > 0);
+        assert_eq!(upgraded.zones, IndexMetadata::legacy().zones);
+        assert!(fs::metadata(format!("{}.tmp", IndexLayout::metadata(&destination))).await.is_err());
+        for term in terms.iter() {
+            assert_eq!(postings(&destination, term).await?, corpus.documents(term), "{term}");
+        }
+        assert!(!upgrade_index(&destination).await?);
+        assert_eq!(IndexMetadata::load(&destination).await?, upgraded);
+
+        let empty = root.join("empty").to_str().unwrap().to_string();
+        fs::create_dir_all(&empty).await?;
+        assert_eq!(upgrade_index(&empty).await.unwrap_err().kind(), ErrorKind::NotFound);
+        assert!(fs::metadata(IndexLayout::metadata(&empty)).await.is_err());
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,238 @@
+use std::{
+    io::{Error, ErrorKind},
+    ops::Bound,
+};
+
+use chrono::{DateTime, NaiveDate};
+use save::writer::{variable_load, variable_save_usize};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter},
+};
+
+use crate::{doc_id::check_count, layout::IndexLayout};
+
+/// A numeric field value: a plain integer, a `YYYY-MM-DD` date or an RFC 3339
+/// timestamp. Dates and timestamps become seconds since the epoch.
+pub fn parse_number(raw: &str) -> Option<u64> {
+    let raw = raw.trim();
+    if let Ok(v) = raw.parse::<u64>() {
+        return Some(v);
+    }
+    let seconds = match NaiveDate::parse_from_str(raw, "%Y-%m-%d") {
+        Ok(date) => date.and_hms(0, 0, 0).timestamp(),
+        Err(_) => DateTime::parse_from_rfc3339(raw).ok()?.timestamp(),
+    };
+    u64::try_from(seconds).ok()
+}
+
+/// Bounds of a `field:[low TO high]` filter; `[`/`]` include the bound,
+/// `{`/`}` exclude it and `*` leaves that side open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumericRange {
+    pub low: Bound<u64>,
+    pub high: Bound<u64>,
+}
+
+impl NumericRange {
+    /// Parses the bracketed part of a range, e.g. `[2020-01-01 TO 2021-01-01}`.
+    pub fn parse(raw: &str) -> Option<Self> {
+        let mut chars = raw.trim().chars();
+        let open = chars.next()?;
+        let close = chars.next_back()?;
+        let (low, high) = chars.as_str().split_once(" TO ")?;
+        let bound = |raw: &str, inclusive: bool| match raw.trim() {
+            "*" => Some(Bound::Unbounded),
+            raw => {
+                let v = parse_number(raw)?;
+                Some(if inclusive {
+                    Bound::Included(v)
+                } else {
+                    Bound::Excluded(v)
+                })
+            }
+        };
+        let low = match open {
+            '[' => bound(low, true)?,
+            '{' => bound(low, false)?,
+            _ => return None,
+        };
+        let high = match close {
+            ']' => bound(high, true)?,
+            '}' => bound(high, false)?,
+            _ => return None,
+        };
+        Some(Self { low, high })
+    }
+
+    pub fn contains(&self, v: u64) -> bool {
+        std::ops::RangeBounds::contains(&(self.low, self.high), &v)
+    }
+}
+
+/// One numeric field of every document, by document id, stored as
+/// `numeric.txt`. Documents without the field hold `None`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct NumericValues {
+    pub field: String,
+    pub values: Vec<Option<u64>>,
+}
+
+/// Stored in place of a missing value.
+const MISSING: u64 = u64::MAX;
+
+impl NumericValues {
+    pub fn get(&self, document: usize) -> Option<u64> {
+        self.values.get(document).copied().flatten()
+    }
+
+    /// Documents whose value falls in `range`, in id order.
+    pub fn matching(&self, range: &NumericRange) -> impl Iterator<Item = usize> + '_ {
+        let range = *range;
+        self.values
+            .iter()
+            .enumerate()
+            .filter(move |(_, v)| v.is_some_and(|v| range.contains(v)))
+            .map(|(document, _)| document)
+    }
+
+    pub async fn save(&self, directory: &str) -> Result<(), Error> {
+        check_count(self.values.len(), "numeric values")?;
+        let mut writer = BufWriter::new(File::create(IndexLayout::detect(directory).await?.numeric(directory)).await?);
+        variable_save_usize(self.field.len(), &mut writer).await?;
+        writer.write_all(self.field.as_bytes()).await?;
+        writer.write_u64(self.values.len() as u64).await?;
+        for v in self.values.iter() {
+            writer.write_u64(v.unwrap_or(MISSING)).await?;
+        }
+        writer.flush().await
+    }
+
+    pub async fn load(directory: &str) -> Result<Self, Error> {
+        let mut reader = BufReader::new(File::open(IndexLayout::detect(directory).await?.numeric(directory)).await?);
+        let mut field = vec![0u8; variable_load(&mut reader).await?];
+        reader.read_exact(&mut field).await?;
+        let field = String::from_utf8(field).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        let len = reader.read_u64().await? as usize;
+        check_count(len, "numeric values")?;
+        let mut values = Vec::with_capacity(len);
+        for _ in 0..len {
+            values.push(Some(reader.read_u64().await?).filter(|v| *v != MISSING));
+        }
+        Ok(Self { field, values })
+    }
+}
+
+#[cfg(test)]
+mod tst {
+    use std::{io::Error, ops::Bound};
+
+    use tokio::fs;
+
+    use crate::{
+        config::IndexerConfig,
+        execute::{execute, QueryLimits},
+        indexed::{Dictionary, IndexMerger, IndexParser, IndexedBuilder},
+        metadata::IndexMetadata,
+        parser::ParseController,
+        query::{parse_query, parse_query_with_fields, QueryError},
+        segment::{CommonSegmentSelector, CommonSegments},
+        zones::ZoneSet,
+    };
+
+    use super::{parse_number, NumericRange, NumericValues};
+
+    #[test]
+    fn values_and_ranges() {
+        assert_eq!(parse_number(" 42 "), Some(42));
+        assert_eq!(parse_number("2020-01-01"), Some(1_577_836_800));
+        assert_eq!(parse_number("2020-01-01T01:00:00Z"), Some(1_577_840_400));
+        assert_eq!(parse_number("1969-12-31"), None);
+        assert_eq!(parse_number("soon"), None);
+
+        let range = NumericRange::parse("[10 TO 20}").unwrap();
+        assert_eq!((range.low, range.high), (Bound::Included(10), Bound::Excluded(20)));
+        assert!(range.contains(10) && range.contains(19) && !range.contains(20));
+        let range = NumericRange::parse("{* TO 2020-01-01]").unwrap();
+        assert_eq!(range.low, Bound::Unbounded);
+        assert!(range.contains(0) && range.contains(1_577_836_800));
+        assert_eq!(NumericRange::parse("[10 20]"), None);
+        assert_eq!(NumericRange::parse("(10 TO 20)"), None);
+    }
+
+    #[tokio::test]
+    async fn ranges_filter_documents() -> Result<(), Error> {
+        let root = std::env::temp_dir().join(format!("numeric_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root).await;
+        fs::create_dir_all(&root).await?;
+        let path = root.join("0.xml").to_str().unwrap().to_string();
+        let documents = [
+            ("rust", Some("2020-01-01"), "async release"),
+            ("tokio", Some("2020-06-15T12:00:00Z"), "async runtime"),
+            ("java", None, "threads"),
+            ("python", Some("2021-01-01"), "async asyncio"),
+        ];
+        let content = documents
+            .iter()
+            .map(|(title, timestamp, text)| {
+                let timestamp = timestamp.map_or(String::new(), |v| format!("<timestamp>{v}</timestamp>\n"));
+                format!("<title>\n{title}\n</title>\n{timestamp}<text>\n{text}\n</text>\n")
+            })
+            .collect::<String>();
+        fs::write(&path, content).await?;
+
+        let attributes = ZoneSet::new(["title", "text"])?;
+        let config = IndexerConfig::new(1000, 6)?;
+        assert!(IndexedBuilder::new(config, attributes.clone())?.with_numeric_field("title").is_err());
+        assert!(IndexedBuilder::new(config, attributes.clone())?.with_numeric_field("Time").is_err());
+        let destination = root.join("res").to_str().unwrap().to_string();
+        ParseController::<IndexParser, _, _>::new(
+            vec![path],
+            destination.clone(),
+            root.join("buffer").to_str().unwrap().to_string(),
+            1,
+            IndexedBuilder::new(config, attributes)?.with_numeric_field("timestamp")?,
+            IndexMerger::new(config.merger()),
+        )
+        .create_dictionary()
+        .await?;
+
+        let metadata = IndexMetadata::load(&destination).await?;
+        assert_eq!(metadata.numeric.as_deref(), Some("timestamp"));
+        let values = NumericValues::load(&destination).await?;
+        assert_eq!(
+            &values.values[..4],
+            [Some(1_577_836_800), Some(1_592_222_400), None, Some(1_609_459_200)]
+        );
+
+        let selector = CommonSegmentSelector::new();
+        let fields = [metadata.numeric.unwrap()];
+        let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+        let mut run = async |raw: &str| {
+            let query = parse_query_with_fields(raw, &selector, &fields).unwrap();
+            execute(&query, &mut dictionary, QueryLimits::default())
+                .await
+                .unwrap()
+                .documents
+        };
+        assert_eq!(run("timestamp:[2020-01-01 TO 2021-01-01]").await, [0, 1, 3]);
+        assert_eq!(run("timestamp:{2020-01-01 TO 2021-01-01}").await, [1]);
+        assert_eq!(run("timestamp:[2020-01-01 TO 2021-01-01}").await, [0, 1]);
+        assert_eq!(run("timestamp:{2020-01-01 TO *]").await, [1, 3]);
+        assert_eq!(run("async timestamp:{2020-01-01 TO *]").await, [1, 3]);
+        assert_eq!(run("async AND timestamp:[* TO 2020-12-31] NOT tokio").await, [0]);
+        assert_eq!(run("threads OR timestamp:[2021-01-01 TO 2021-01-01]").await, [2, 3]);
+
+        assert_eq!(
+            parse_query("timestamp:[1 TO 2]", &selector).unwrap_err(),
+            QueryError::UnknownZone("timestamp".to_string())
+        );
+        assert!(matches!(
+            parse_query_with_fields("timestamp:[1 2]", &selector, &fields),
+            Err(QueryError::Syntax(_))
+        ));
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
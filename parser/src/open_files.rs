@@ -0,0 +1,112 @@
+//! How many files a merge may hold open at once. Every buffer read by the
+//! merge keeps [`FILES_PER_BUFFER`] files open, so the number of buffers
+//! merged in one pass, the fan-in, is bounded by the process limit on open
+//! files; past it the merge goes in rounds, see
+//! [`crate::indexed::IndexMerger::with_fan_in`].
+
+use std::{
+    fmt::{self, Display, Formatter},
+    io::Error,
+};
+
+/// Files a [`crate::indexed::IndexTermProvider`] keeps open: the pointer,
+/// lexical and index parts of its buffer.
+pub const FILES_PER_BUFFER: usize = 3;
+
+/// Files left over for everything besides the buffers: the merged index
+/// with its auxiliary parts, the log and standard streams.
+pub const RESERVED_FILES: usize = 32;
+
+/// Limit assumed where it can't be asked for, as on Windows.
+pub const ASSUMED_LIMIT: usize = 512;
+
+/// The soft limit on open files of this process.
+#[cfg(unix)]
+pub fn open_files_limit() -> usize {
+    let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    // SAFETY: getrlimit only writes to the struct it is given.
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+        return ASSUMED_LIMIT;
+    }
+    usize::try_from(limit.rlim_cur).unwrap_or(usize::MAX)
+}
+
+#[cfg(not(unix))]
+pub fn open_files_limit() -> usize {
+    ASSUMED_LIMIT
+}
+
+/// Most buffers one merge pass may open under `limit` open files. Never
+/// less than 2, or a round wouldn't reduce anything.
+pub fn fan_in_for(limit: usize) -> usize {
+    (limit.saturating_sub(RESERVED_FILES) / FILES_PER_BUFFER).max(2)
+}
+
+/// Whether `error` is the system refusing to open another file.
+pub fn is_too_many_open_files(error: &Error) -> bool {
+    #[cfg(unix)]
+    let codes = [libc::EMFILE, libc::ENFILE];
+    // ERROR_TOO_MANY_OPEN_FILES
+    #[cfg(not(unix))]
+    let codes = [4];
+    error.raw_os_error().is_some_and(|v| codes.contains(&v))
+        || error.get_ref().is_some_and(|v| v.is::<TooManyOpenFiles>())
+}
+
+/// A merge ran out of file handles opening its buffers.
+#[derive(Debug)]
+pub struct TooManyOpenFiles {
+    /// Buffers the pass tried to open.
+    pub buffers: usize,
+    /// Fan-in the pass was allowed.
+    pub fan_in: usize,
+}
+
+impl Display for TooManyOpenFiles {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "too many open files merging {} buffers at once (fan-in {}); raise the open files limit or pass a lower --fan-in",
+            self.buffers, self.fan_in
+        )
+    }
+}
+
+impl std::error::Error for TooManyOpenFiles {}
+
+/// `error` as [`TooManyOpenFiles`] if it is the system running out of file
+/// handles, otherwise unchanged.
+pub fn explain(error: Error, buffers: usize, fan_in: usize) -> Error {
+    if !is_too_many_open_files(&error) {
+        return error;
+    }
+    Error::other(TooManyOpenFiles { buffers, fan_in })
+}
+
+#[cfg(test)]
+mod tst {
+    use std::io::{Error, ErrorKind};
+
+    use super::{explain, fan_in_for, is_too_many_open_files, open_files_limit, TooManyOpenFiles};
+
+    #[test]
+    fn fan_in_follows_the_limit() {
+        assert_eq!(fan_in_for(1024), (1024 - 32) / 3);
+        assert_eq!(fan_in_for(38), 2);
+        assert_eq!(fan_in_for(0), 2);
+        assert!(open_files_limit() > 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn running_out_of_files_suggests_the_fan_in() {
+        let error = explain(Error::from_raw_os_error(libc::EMFILE), 900, 300);
+        assert!(is_too_many_open_files(&error));
+        let typed = error.get_ref().unwrap().downcast_ref::<TooManyOpenFiles>().unwrap();
+        assert_eq!((typed.buffers, typed.fan_in), (900, 300));
+        assert!(error.to_string().contains("--fan-in"));
+
+        let other = explain(Error::new(ErrorKind::NotFound, "gone"), 900, 300);
+        assert_eq!(other.kind(), ErrorKind::NotFound);
+    }
+}
@@ -1,28 +1,58 @@
 use std::{
-    collections::BTreeMap,
+    cmp::Reverse,
+    collections::{btree_map::Entry, BTreeMap, BinaryHeap, HashMap},
     fmt::Debug,
-    io::Error,
-    sync::{atomic::AtomicUsize, Arc},
+    io::{Error, ErrorKind},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
 use crate::{
+    analyzer::AnalyzerConfig,
+    boost::Boosts,
+    cancel::{interrupted, is_interrupted, CancellationToken},
+    doc_id::{IdRangeError, MAX_DOCUMENT_ID},
+    filter::FilterPatterns,
+    generation::Generations,
+    inputs::{InputOrder, InputSet},
+    layout::IndexLayout,
+    metadata::{IndexMetadata, SampleRecord},
+    numeric::NumericValues,
+    paths,
+    provenance::{hash_file, BuildRecord, InputDigest},
     reader::*,
-    segment::{CommonSegmentSelector, SegmentSelector},
+    rank::{DocumentLengths, IdfTable, TfPolicy},
+    report::{FileReport, ParseReport, StrictError},
+    sample::Sampling,
+    segment::SegmentSelector,
+    tasks::{cores, TaskPlan, AUTO_TASKS},
+    throttle::Throttle,
+    titles::DocumentTitles,
+    warnings::{WarningPolicy, Warnings, MAX_TOKEN_BYTES, WARNINGS_PER_FILE},
+    zones::ZoneSet,
 };
 use async_trait::async_trait;
+use save::u8::ReadRate;
 
 use futures::future::join_all;
-use sysinfo::DiskExt;
+use serde_json::{json, Value};
 
 use crate::segment::Segments;
 use tokio::{
     fs::{self, File},
+    io::{AsyncBufReadExt, BufReader},
     sync::Mutex,
     task::{self, JoinHandle},
 };
 
 pub trait Term: Ord + Debug {
-    fn combine(&mut self, other: Self);
+    /// Adds the uses and postings of `other`, the same term read elsewhere.
+    /// Fails, leaving `self` as it was, if the counts can't be added up.
+    fn combine(&mut self, other: Self) -> Result<(), Error>;
 
     fn get_use_count(&self) -> u64;
 }
@@ -34,24 +64,60 @@ pub trait TermProvider {
     async fn next_term(&mut self) -> Option<Self::Term>;
 }
 
+/// Where the merge writes terms, in order. [`crate::config::OutputFormat`]
+/// picks one for [`crate::indexed::IndexMerger`].
 #[async_trait]
-pub trait TermSaver {
-    type Provider: TermProvider<Term = Self::Term>;
+pub trait TermSink: Send {
     type Term: Term;
 
-    async fn save(path: &String, unique: BTreeMap<String, Self::Term>);
+    /// Adds `term`, which comes after every term pushed before.
+    async fn push(&mut self, term: Self::Term) -> Result<(), Error>;
 
-    async fn provider(path: &String) -> Self::Provider;
+    /// Writes whatever is still held back and closes the sink.
+    async fn finish(self) -> Result<(), Error>;
+}
+
+/// Merges sorted `providers` into `sink`, joining a term found in several
+/// of them with [`Term::combine`]. `cancel` is checked before every term.
+pub async fn merge_terms<T: Term + Send, P: TermProvider<Term = T> + Send, K: TermSink<Term = T>>(
+    providers: &mut [P],
+    mut sink: K,
+    cancel: &CancellationToken,
+) -> Result<(), Error> {
+    let mut heads = BinaryHeap::<(Reverse<T>, usize)>::new();
+    for (i, provider) in providers.iter_mut().enumerate() {
+        if let Some(term) = provider.next_term().await {
+            heads.push((Reverse(term), i));
+        }
+    }
+    while let Some((Reverse(mut term), first)) = heads.pop() {
+        cancel.check()?;
+        let mut taken = vec![first];
+        while heads.peek().is_some_and(|(Reverse(next), _)| *next == term) {
+            let (Reverse(next), i) = heads.pop().unwrap();
+            term.combine(next)?;
+            taken.push(i);
+        }
+        sink.push(term).await?;
+        for i in taken {
+            if let Some(term) = providers[i].next_term().await {
+                heads.push((Reverse(term), i));
+            }
+        }
+    }
+    sink.finish().await
 }
 #[derive(PartialEq, Eq)]
 pub enum ParserCallback {
+    /// The tree is full. A document may be half read; after the flush the
+    /// next call goes on with it under the same id.
     Full,
     FileEnd,
     ZoneEnd,
 }
 #[async_trait]
 pub trait Parser: Send {
-    type Term: Term;
+    type Term: Term + Send + 'static;
     type Provider: TermProvider<Term = Self::Term>;
     type Reader: Reader + Send;
     type Segments: Segments;
@@ -59,37 +125,220 @@ pub trait Parser: Send {
 
     async fn parse(&mut self, reader: &mut Self::Reader, ind: usize) -> ParserCallback;
 
+    /// Reads up to the next document of `reader`, false once it has none.
+    /// Ids are reserved only past this, so every id has a document.
+    async fn next_document(&mut self, reader: &mut Self::Reader) -> bool;
+
+    /// Number of terms held in memory.
+    fn len(&self) -> usize;
+
+    /// Whether no terms are held in memory.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Rough size in bytes of what is held in memory.
+    fn estimated_bytes(&self) -> usize;
+
+    /// Called at every document boundary; excluded documents are read but not indexed.
+    fn include_document(&mut self, included: bool);
+
+    /// Hands out what was gathered for the file being parsed and starts a new report.
+    fn take_report(&mut self, path: String) -> FileReport;
+
+    /// An error of the file being parsed that fails the build rather than
+    /// being reported, like chunks of other zones than the reader's.
+    fn take_error(&mut self) -> Option<Error> {
+        None
+    }
+
+    /// Token counts of the documents parsed so far, by document id.
+    fn take_document_lengths(&mut self) -> Vec<(usize, u32)>;
+
+    /// How many of the documents parsed so far have a token in each zone.
+    fn take_zone_documents(&mut self) -> BTreeMap<String, usize> {
+        BTreeMap::new()
+    }
+
+    /// Token counts of the documents parsed so far within each zone they
+    /// have a token in, by document id.
+    fn take_zone_lengths(&mut self) -> Vec<(usize, &'static str, u32)> {
+        Vec::new()
+    }
+
+    /// Starts keeping the title of every document for [`Self::take_titles`].
+    fn record_titles(&mut self) {}
+
+    /// Normalized titles of the documents parsed so far, by document id.
+    fn take_titles(&mut self) -> Vec<(usize, String)> {
+        Vec::new()
+    }
+
+    /// Numeric field values of the documents parsed so far, by document id.
+    fn take_numeric_values(&mut self) -> Vec<(usize, u64)> {
+        Vec::new()
+    }
+
     async fn provider_from_file(file: &String) -> Result<Self::Provider, Error>;
 
     async fn flush_to(&mut self, file: &String) -> Result<(), Error>;
+
+    /// Lifts the bound on the terms held in memory, so parsing never stops
+    /// with [`ParserCallback::Full`] and every term waits for [`Self::take_terms`].
+    fn keep_all(&mut self);
+
+    /// Hands out every term held in memory, as [`Self::flush_to`] would write them.
+    fn take_terms(&mut self) -> BTreeMap<String, Self::Term>;
 }
 
 #[async_trait]
 pub trait Merger: Send {
     type Parser: Parser;
 
+    /// Fails with [`interrupted`] once `cancel` is cancelled, having
+    /// removed the buffer files and the destination if the merge created it.
     async fn merge(
         &mut self,
         input_file: Arc<Mutex<IndexPositions>>,
         buffer_files: Arc<Mutex<Vec<String>>>,
         destination: String,
+        cancel: &CancellationToken,
     ) -> Result<(), Error>;
+
+    /// Writes the index from `terms` gathered in memory instead of merging
+    /// buffers, the same index [`Self::merge`] writes of them. Fails with
+    /// [`interrupted`] like it, having removed the destination if it
+    /// created it.
+    async fn write_terms(
+        &mut self,
+        input_file: Arc<Mutex<IndexPositions>>,
+        terms: BTreeMap<String, <Self::Parser as Parser>::Term>,
+        destination: String,
+        cancel: &CancellationToken,
+    ) -> Result<(), Error>;
+
+    /// Whether the merge also writes a phonetic index.
+    fn phonetic_index(&self) -> bool {
+        false
+    }
+
+    /// Whether the merge also writes a permuterm index.
+    fn permuterm_index(&self) -> bool {
+        false
+    }
+
+    /// Whether the merge also writes a term FST.
+    fn fst_index(&self) -> bool {
+        false
+    }
+
+    /// Document frequency from which the merge writes a bitmap of a term,
+    /// if it writes them.
+    fn bitmap_min_df(&self) -> Option<usize> {
+        None
+    }
+
+    /// Document frequencies the last merge kept for an idf table, most frequent first.
+    fn take_top_terms(&mut self) -> Vec<(String, usize)> {
+        Vec::new()
+    }
+
+    /// Every setting of the merge by name, for the [`BuildRecord`].
+    fn settings(&self) -> Vec<(&'static str, Value)> {
+        Vec::new()
+    }
 }
 
 #[async_trait]
 pub trait ParserBuilder: Send {
     type Parser: Parser;
     fn build(&mut self) -> Self::Parser;
-    async fn reader_from_file(&mut self, file: File) -> <Self::Parser as Parser>::Reader;
+
+    /// Term filter applied by the built parsers, if any.
+    fn filter_patterns(&self) -> Option<FilterPatterns> {
+        None
+    }
+
+    /// How the built parsers store per-document term counts.
+    fn tf_policy(&self) -> TfPolicy {
+        TfPolicy::default()
+    }
+
+    /// Tag the built readers keep as a numeric field, if any.
+    fn numeric_field(&self) -> Option<String> {
+        None
+    }
+
+    /// Whether the built parsers also index original spellings.
+    fn case_preserving(&self) -> bool {
+        false
+    }
+
+    /// Steps the built parsers make terms of words with, if they use an
+    /// [`crate::analyzer::Analyzer`].
+    fn analyzer(&self) -> Option<AnalyzerConfig> {
+        None
+    }
+
+    /// Elements the built readers skip whole.
+    fn excluded_elements(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Read the files opened from here on through `std::io`, for builds
+    /// driven without a runtime. Builders whose readers can't ignore it.
+    fn read_blocking(&mut self) {}
+
+    /// Zones the built readers split documents into, if they read zones.
+    fn zones(&self) -> Option<ZoneSet> {
+        None
+    }
+
+    /// Every setting of the built parsers and readers by name, for the
+    /// [`BuildRecord`].
+    fn settings(&self) -> Vec<(&'static str, Value)> {
+        Vec::new()
+    }
+
+    /// Hold the readers built from here on to `rate`. Builders whose
+    /// readers can't be held ignore it.
+    fn throttle_reads(&mut self, _rate: ReadRate) {}
+
+    /// Rough bytes a parser of the builder holds at most before it
+    /// flushes, None if there's no telling.
+    fn task_memory(&self) -> Option<u64> {
+        None
+    }
+
+    /// Opens the input file at `path`, with a reader that sends what it
+    /// gets past to `warnings`.
+    async fn reader_from_file(
+        &mut self,
+        path: &Path,
+        warnings: Warnings,
+    ) -> Result<<Self::Parser as Parser>::Reader, Error>;
 }
 
 pub struct ParseController<P: Parser, M: Merger<Parser = P>, Pb: ParserBuilder<Parser = P>> {
-    files: Vec<String>,
+    files: Vec<PathBuf>,
     destination: String,
     buffer_directory: String,
     tasks_count: u16,
     builder: Pb,
     merger: M,
+    sampling: Option<Sampling>,
+    boosts: Option<Boosts>,
+    store_titles: bool,
+    generations: Option<usize>,
+    overwrite: bool,
+    clean_buffer: bool,
+    cancel: CancellationToken,
+    in_memory_below: Option<u64>,
+    skipped_inputs: usize,
+    input_order: Option<InputOrder>,
+    warning_policy: WarningPolicy,
+    throttle: Option<Throttle>,
+    memory_budget: Option<u64>,
 }
 
 macro_rules! clone_all {
@@ -104,30 +353,63 @@ macro_rules! clone_mut_all {
 }
 
 pub struct IndexPositions {
-    pub names: Vec<(String, usize)>,
+    pub names: Vec<(PathBuf, usize)>,
     pub ids: Vec<(usize, usize)>,
+    /// Highest id handed out, [`MAX_DOCUMENT_ID`] unless lowered.
+    pub max_id: usize,
 }
 
 impl IndexPositions {
-    fn new<'a>(names: Vec<String>) -> Self {
+    fn new<'a>(names: Vec<PathBuf>) -> Self {
         Self {
             names: names.into_iter().map(|v| (v, 0)).collect::<Vec<_>>(),
             ids: vec![],
+            max_id: MAX_DOCUMENT_ID,
         }
     }
 
-    fn put(&mut self, name_index: usize) -> usize {
+    /// Hands out the id of the next document of file `name_index`.
+    pub(crate) fn put(&mut self, name_index: usize) -> Result<usize, Error> {
+        let id = self.ids.len();
+        if id > self.max_id.min(MAX_DOCUMENT_ID) {
+            return Err(IdRangeError::Document {
+                id: id as u64,
+                at: "document ids",
+            }
+            .into());
+        }
         self.ids.push((name_index, self.names[name_index].1));
         self.names[name_index].1 += 1;
-        self.ids.len() - 1
+        Ok(id)
+    }
+}
+
+struct SampleCounter {
+    sampling: Option<Sampling>,
+    documents: AtomicUsize,
+    included: AtomicUsize,
+}
+
+impl SampleCounter {
+    fn includes(&self, files: &IndexPositions, id: usize) -> bool {
+        let (file, document) = files.ids[id];
+        let included = self.sampling.is_none_or(|s| s.includes(file, document));
+        self.documents.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        if included {
+            self.included.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }
+        included
     }
 }
 
 impl<P: Parser, M: Merger<Parser = P>, Pb: 'static + ParserBuilder<Parser = P>>
     ParseController<P, M, Pb>
 {
+    /// Paths in `files` may be of any encoding, and on Windows of any
+    /// length, see [`paths::extended`]. With `tasks_count` of
+    /// [`AUTO_TASKS`] the controller picks them, see [`TaskPlan`].
     pub fn new(
-        files: Vec<String>,
+        files: impl IntoIterator<Item = impl Into<PathBuf>>,
         destination: String,
         buffer_directory: String,
         tasks_count: u16,
@@ -135,129 +417,694 @@ impl<P: Parser, M: Merger<Parser = P>, Pb: 'static + ParserBuilder<Parser = P>>
         merger: M,
     ) -> Self {
         Self {
-            files,
+            files: files.into_iter().map(|v| paths::extended(v.into())).collect(),
             destination,
             buffer_directory,
             tasks_count,
             builder,
             merger,
+            sampling: None,
+            boosts: None,
+            store_titles: false,
+            generations: None,
+            overwrite: false,
+            clean_buffer: false,
+            cancel: CancellationToken::new(),
+            in_memory_below: None,
+            skipped_inputs: 0,
+            input_order: None,
+            warning_policy: WarningPolicy::default(),
+            throttle: None,
+            memory_budget: None,
         }
     }
 
-    async fn invert(mut self) -> Result<(), Error> {
-        let mut tasks = Vec::<JoinHandle<()>>::new();
-        let files = Arc::new(Mutex::new(IndexPositions::new(self.files)));
-        match fs::create_dir(self.buffer_directory.clone()).await {
-            Ok(_) => {
-                log::info!("Directory created for parser");
+    /// [`Self::new`] over resolved input specs, whose skipped entries go
+    /// into the report and whose order into the [`BuildRecord`].
+    pub fn from_inputs(
+        inputs: InputSet,
+        destination: String,
+        buffer_directory: String,
+        tasks_count: u16,
+        builder: Pb,
+        merger: M,
+    ) -> Self {
+        let mut controller = Self::new(
+            inputs.files,
+            destination,
+            buffer_directory,
+            tasks_count,
+            builder,
+            merger,
+        );
+        controller.skipped_inputs = inputs.skipped;
+        controller.input_order = Some(inputs.order);
+        controller
+    }
+
+    /// Index only a deterministic fraction of the documents.
+    pub fn with_sampling(mut self, sampling: Sampling) -> Self {
+        self.sampling = Some(sampling);
+        self
+    }
+
+    /// Store static per-document boosts, see [`Boosts`].
+    pub fn with_boosts(mut self, boosts: Boosts) -> Self {
+        self.boosts = Some(boosts);
+        self
+    }
+
+    /// Store the title of every document, see [`DocumentTitles`].
+    pub fn with_titles(mut self) -> Self {
+        self.store_titles = true;
+        self
+    }
+
+    /// Treat the destination as the parent of [`Generations`]: build into a
+    /// fresh generation, flip `CURRENT` to it once everything is written and
+    /// keep only the newest `keep` generations.
+    pub fn with_generations(mut self, keep: usize) -> Self {
+        self.generations = Some(keep);
+        self
+    }
+
+    /// Replace an index already in the destination. The directory is
+    /// emptied first, so no file of the old index outlives the build.
+    pub fn with_overwrite(mut self) -> Self {
+        self.overwrite = true;
+        self
+    }
+
+    /// Remove whatever the buffer directory holds before parsing instead of
+    /// failing on it.
+    pub fn with_clean_buffer(mut self) -> Self {
+        self.clean_buffer = true;
+        self
+    }
+
+    /// Stop once `cancel` is cancelled: parsing at the next document
+    /// boundary, the merge before the next term. The build then fails with
+    /// [`interrupted`] and leaves neither buffers nor a destination behind,
+    /// unless the destination was there before the build.
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Build without buffer files when the inputs take fewer than `bytes`
+    /// together: every task keeps all it parsed in memory and the index is
+    /// written once from the joined trees, see [`Merger::write_terms`].
+    pub fn with_in_memory_below(mut self, bytes: u64) -> Self {
+        self.in_memory_below = Some(bytes);
+        self
+    }
+
+    /// Build in memory whatever the size of the inputs, see
+    /// [`Self::with_in_memory_below`].
+    pub fn with_in_memory(self) -> Self {
+        self.with_in_memory_below(u64::MAX)
+    }
+
+    /// Read every input no faster than `throttle` allows and step aside
+    /// between documents, see [`Throttle`].
+    pub fn with_throttle(mut self, throttle: Throttle) -> Self {
+        self.builder.throttle_reads(throttle.rate());
+        self.throttle = Some(throttle);
+        self
+    }
+
+    /// Fail the build instead of skipping what the readers can't take, see
+    /// [`WarningPolicy`].
+    pub fn with_warning_policy(mut self, policy: WarningPolicy) -> Self {
+        self.warning_policy = policy;
+        self
+    }
+
+    /// Run no more tasks than `bytes` holds parsers of the builder, see
+    /// [`ParserBuilder::task_memory`].
+    pub fn with_memory_budget(mut self, bytes: u64) -> Self {
+        self.memory_budget = Some(bytes);
+        self
+    }
+
+    /// Tasks to parse the inputs with, and why.
+    fn plan_tasks(&self) -> TaskPlan {
+        let requested = (self.tasks_count != AUTO_TASKS).then_some(self.tasks_count);
+        let memory = self.memory_budget.zip(self.builder.task_memory());
+        let plan = TaskPlan::new(requested, cores(), self.files.len(), memory);
+        if plan.lowered() {
+            log::warn!("{} tasks requested, running {plan}", self.tasks_count);
+        } else {
+            log::info!("Parsing with {plan}");
+        }
+        plan
+    }
+
+    /// Whether the inputs are small enough to be built in memory.
+    async fn in_memory(&self) -> Result<bool, Error> {
+        let Some(below) = self.in_memory_below else {
+            return Ok(false);
+        };
+        let mut size = 0u64;
+        for path in self.files.iter() {
+            size += fs::metadata(path).await?.len();
+        }
+        Ok(size < below)
+    }
+
+    /// Fails before anything is read if the buffer directory holds entries
+    /// of an earlier run, or the destination already holds an index, unless
+    /// told to clean or overwrite them. A build `in_memory` has no buffer
+    /// directory to look at.
+    async fn prepare(&self, in_memory: bool) -> Result<(), Error> {
+        if !in_memory {
+            self.prepare_buffer().await?;
+        }
+        if self.generations.is_some() {
+            return Ok(());
+        }
+        let layout = IndexLayout::detect(&self.destination).await?;
+        match fs::metadata(layout.dictionary(&self.destination)).await {
+            Ok(_) if self.overwrite => {
+                log::warn!("Overwriting the index in {}", self.destination);
+                fs::remove_dir_all(&self.destination).await
             }
-            Err(w) => {
-                log::info!("{}", w);
+            Ok(_) => Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{} already holds an index", self.destination),
+            )),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn prepare_buffer(&self) -> Result<(), Error> {
+        fs::create_dir_all(&self.buffer_directory).await?;
+        let mut stale = Vec::new();
+        let mut entries = fs::read_dir(&self.buffer_directory).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            stale.push(entry);
+        }
+        if !stale.is_empty() && !self.clean_buffer {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!(
+                    "buffer directory {} holds {} entries of an earlier run",
+                    self.buffer_directory,
+                    stale.len()
+                ),
+            ));
+        }
+        for entry in stale {
+            log::warn!("Removing stale buffer {}", entry.path().display());
+            match entry.file_type().await?.is_dir() {
+                true => fs::remove_dir_all(entry.path()).await?,
+                false => fs::remove_file(entry.path()).await?,
             }
-        };
+        }
+        Ok(())
+    }
+
+    async fn invert(mut self, in_memory: bool) -> Result<(), Error> {
+        let mut tasks = Vec::<JoinHandle<Result<(), Error>>>::new();
+        let mut skipped_files = Vec::new();
+        let given = self.files.clone();
+        let mut digests = HashMap::new();
+        let mut inputs = Vec::with_capacity(self.files.len());
+        for path in std::mem::take(&mut self.files) {
+            if has_content(&path).await? {
+                inputs.push(path);
+            } else {
+                log::warn!("Skipping {}, it holds nothing but whitespace", path.display());
+                digests.insert(path.clone(), hash_file(&path).await?);
+                skipped_files.push(paths::encode(&path));
+            }
+        }
+        self.files = inputs;
+        let plan = self.plan_tasks();
+        let mut config = BTreeMap::new();
+        for (name, value) in self.builder.settings() {
+            config.insert(format!("parser.{name}"), value);
+        }
+        for (name, value) in self.merger.settings() {
+            config.insert(format!("merger.{name}"), value);
+        }
+        for (name, value) in [
+            ("tasks", json!(plan.tasks)),
+            ("sampling", json!(self.sampling)),
+            ("boosts", json!(self.boosts.is_some())),
+            ("store_titles", json!(self.store_titles)),
+            ("generations", json!(self.generations)),
+            ("in_memory", json!(in_memory)),
+            ("input_order", json!(self.input_order)),
+            ("throttle", json!(self.throttle.as_ref().map(|v| v.rate().get()))),
+            ("warning_policy", json!(self.warning_policy)),
+        ] {
+            config.insert(name.to_string(), value);
+        }
+        let record_titles = self.store_titles
+            || self.boosts.as_ref().is_some_and(|v| v.has_titles(&self.files));
+        let files = Arc::new(Mutex::new(IndexPositions::new(self.files)));
         let buffer_directory = Arc::new(self.buffer_directory);
         let file_index = Arc::new(AtomicUsize::new(0));
         let output_index: Arc<AtomicUsize> = Arc::new(AtomicUsize::new(0));
         let output_files = Arc::new(Mutex::new(Vec::<String>::new()));
+        let filter = self.builder.filter_patterns();
+        let tf = self.builder.tf_policy();
+        let numeric = self.builder.numeric_field();
+        let case_preserving = self.builder.case_preserving();
+        let analyzer = self.builder.analyzer();
+        let excluded = self.builder.excluded_elements();
+        let zones = self.builder.zones();
         let builder = Arc::new(Mutex::new(self.builder));
-        for _ in 0..self.tasks_count {
+        let counter = Arc::new(SampleCounter {
+            sampling: self.sampling,
+            documents: AtomicUsize::new(0),
+            included: AtomicUsize::new(0),
+        });
+        let reports = Arc::new(Mutex::new(Vec::<(usize, FileReport)>::new()));
+        let lengths = Arc::new(Mutex::new(Vec::<(usize, u32)>::new()));
+        let zone_documents = Arc::new(Mutex::new(BTreeMap::<String, usize>::new()));
+        let zone_lengths = Arc::new(Mutex::new(Vec::<(usize, &'static str, u32)>::new()));
+        let titles = Arc::new(Mutex::new(Vec::<(usize, String)>::new()));
+        let numeric_values = Arc::new(Mutex::new(Vec::<(usize, u64)>::new()));
+        let read_digests = Arc::new(Mutex::new(Vec::<(usize, Option<[u8; 32]>)>::new()));
+        let trees = Arc::new(Mutex::new(Vec::<BTreeMap<String, P::Term>>::new()));
+        let cancel = self.cancel.clone();
+        let throttle = self.throttle.clone();
+        let cpu_watch = throttle.as_ref().and_then(Throttle::watch_cpu);
+        let bytes_read = Arc::new(AtomicU64::new(0));
+        let parse_started = Instant::now();
+        if in_memory {
+            log::info!("Building {} in memory", self.destination);
+        }
+        for _ in 0..plan.tasks {
             clone_all![
                 files,
                 buffer_directory,
                 file_index,
                 output_index,
                 output_files,
-                builder
+                builder,
+                counter,
+                reports,
+                lengths,
+                zone_documents,
+                zone_lengths,
+                titles,
+                numeric_values,
+                read_digests,
+                trees,
+                cancel,
+                throttle,
+                bytes_read
             ];
-            // println!("T {}", files.lock().await.names.len());
             tasks.push(task::spawn(async move {
                 let mut parser = builder.lock().await.build();
-                let (mut current_file_index, mut current_output, files_count) = {
-                    let next_file = file_index.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-                    let mut files = files.lock().await;
-                    if next_file >= files.names.len() {
-                        println!("Escape");
-                        return;
-                    }
-                    (next_file, files.put(next_file), files.names.len())
-                };
-                println!("{current_file_index} {files_count}");
+                if record_titles {
+                    parser.record_titles();
+                }
+                if in_memory {
+                    parser.keep_all();
+                }
+                let files_count = files.lock().await.names.len();
+                let mut current_file_index = file_index.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
                 while current_file_index < files_count {
-                    // let next_file = file_index.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-                    // let mut flush_index = {
-                    //     let files = files.lock().await;
-                    //     if next_file >= files.names.len() {
-                    //         break;
-                    //     }
-                    //     let flush_index = files.put(next_file);
-                    //     let path = format!("{}\\{}", buffer_directory, flush_index);
-                    //     output_files.lock().await.push(path.clone());
-                    //     parser.flush_to(&path).await.unwrap();
-                    // };
-                    // let file = File::from_std(
-                    //     std::fs::File::open(&files.lock().await.names[next_file].0).unwrap(),
-                    // );
-                    // let mut reader = builder.lock().await.reader_from_file(file).await;
-                    // while {
-                    //     match parser.parse(&mut reader, next_file).await {
-                    //         ParserCallback::Full => false,
-                    //         ParserCallback::FileEnd => true,
-                    //         ParserCallback::ZoneEnd => todo!(),
-                    //     }
-                    // } {}
-                    // while let ParserCallback::Full = parser.parse(&mut reader, next_file).await {
-                    //     let flush_index =
-                    //         output_index.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-                    //     let path = format!("{}\\{}", buffer_directory, flush_index);
-                    //     output_files.lock().await.push(path.clone());
-                    //     parser.flush_to(&path).await.unwrap();
-                    // }
-
-                    let file = File::from_std(
-                        std::fs::File::open(&files.lock().await.names[current_file_index].0)
-                            .unwrap(),
+                    let path = files.lock().await.names[current_file_index].0.clone();
+                    let started = Instant::now();
+                    log::debug!(
+                        "parse start: path={} file={}/{}",
+                        path.display(),
+                        current_file_index + 1,
+                        files_count
                     );
-                    let mut reader = builder.lock().await.reader_from_file(file).await;
-                    while {
-                        match parser.parse(&mut reader, current_output).await {
-                            ParserCallback::Full => {
-                                let flush_index =
-                                    output_index.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-                                let path = format!("{buffer_directory}\\{flush_index}");
-                                parser.flush_to(&path).await.unwrap();
-                                output_files.lock().await.push(path);
-                                true
+                    let warnings = Warnings::new(WARNINGS_PER_FILE);
+                    let mut reader = builder.lock().await.reader_from_file(&path, warnings.clone()).await?;
+                    // Id of the document being parsed, reserved once there is one.
+                    let mut document = None;
+                    loop {
+                        let id = match document {
+                            Some(id) => id,
+                            None => {
+                                if !parser.next_document(&mut reader).await {
+                                    break;
+                                }
+                                let mut files = files.lock().await;
+                                let id = files.put(current_file_index)?;
+                                parser.include_document(counter.includes(&files, id));
+                                id
                             }
-                            ParserCallback::FileEnd => {
-                                current_file_index =
-                                    file_index.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-                                current_output = files.lock().await.put(current_file_index);
-                                false
+                        };
+                        document = Some(id);
+                        match parser.parse(&mut reader, id).await {
+                            ParserCallback::Full => {
+                                if !parser.is_empty() {
+                                    let flush_index = output_index
+                                        .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                                    let path = buffer_path(&buffer_directory, flush_index);
+                                    // Listed first, so a failed flush is cleaned up too.
+                                    output_files.lock().await.push(path.clone());
+                                    flush(&mut parser, &path).await?;
+                                }
                             }
+                            ParserCallback::FileEnd => break,
                             ParserCallback::ZoneEnd => {
-                                current_output = files.lock().await.put(current_file_index);
-                                true
+                                if cancel.is_cancelled() {
+                                    return Ok(());
+                                }
+                                document = None;
+                                if let Some(throttle) = throttle.as_ref() {
+                                    throttle.between_documents().await;
+                                }
                             }
                         }
-                    } {}
+                    }
+                    if cancel.is_cancelled() {
+                        return Ok(());
+                    }
+                    if let Some(e) = parser.take_error() {
+                        return Err(e);
+                    }
+                    read_digests.lock().await.push((current_file_index, reader.input_digest()));
+                    bytes_read.fetch_add(reader.position(), std::sync::atomic::Ordering::Relaxed);
+                    let report = FileReport {
+                        reader_warnings: warnings.take(),
+                        ..parser.take_report(paths::encode(&path))
+                    };
+                    log::debug!(
+                        "parse done: path={} documents={} tokens={} warnings={} elapsed={:?} rate={} KiB/s",
+                        report.path,
+                        report.documents,
+                        report.tokens,
+                        report.reader_warnings.total(),
+                        started.elapsed(),
+                        kib_per_sec(reader.position(), started.elapsed())
+                    );
+                    reports.lock().await.push((current_file_index, report));
+                    current_file_index = file_index.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    if let Some(throttle) = throttle.as_ref() {
+                        throttle.between_documents().await;
+                    }
+                }
+                lengths.lock().await.extend(parser.take_document_lengths());
+                let mut zones = zone_documents.lock().await;
+                for (zone, count) in parser.take_zone_documents() {
+                    *zones.entry(zone).or_default() += count;
+                }
+                drop(zones);
+                zone_lengths.lock().await.extend(parser.take_zone_lengths());
+                titles.lock().await.extend(parser.take_titles());
+                numeric_values.lock().await.extend(parser.take_numeric_values());
+                if in_memory {
+                    trees.lock().await.push(parser.take_terms());
+                    return Ok(());
+                }
+                if parser.is_empty() {
+                    return Ok(());
                 }
                 let flush_index = output_index.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-                let path = format!("{}\\{}", buffer_directory, flush_index);
+                let path = buffer_path(&buffer_directory, flush_index);
                 output_files.lock().await.push(path.clone());
-                parser.flush_to(&path).await.unwrap();
-                ()
+                flush(&mut parser, &path).await?;
+                Ok(())
             }));
         }
-        join_all(tasks).await;
-        self.merger
-            .merge(files, output_files, self.destination)
-            .await?;
+        let parsed = join_all(tasks)
+            .await
+            .into_iter()
+            .map(|v| v.map_err(Error::other)?)
+            .collect::<Result<Vec<_>, Error>>();
+        if let Some(watch) = cpu_watch {
+            watch.abort();
+        }
+        let read = bytes_read.load(std::sync::atomic::Ordering::Relaxed);
+        log::info!(
+            "Parsed {} KiB in {:?}, {} KiB/s",
+            read / 1024,
+            parse_started.elapsed(),
+            kib_per_sec(read, parse_started.elapsed())
+        );
+        if let Err(e) = parsed {
+            log::error!("Build of {} failed while parsing: {e}", self.destination);
+            remove_buffer(&output_files).await;
+            return Err(e);
+        }
+        if self.cancel.is_cancelled() {
+            log::warn!("Build of {} cancelled while parsing", self.destination);
+            remove_buffer(&output_files).await;
+            return Err(interrupted());
+        }
+        if self.warning_policy == WarningPolicy::Strict {
+            let mut reports = reports.lock().await.clone();
+            reports.sort_unstable_by_key(|(i, _)| *i);
+            let report = ParseReport {
+                files: reports.into_iter().map(|(_, v)| v).collect(),
+                skipped_files: skipped_files.clone(),
+                skipped_inputs: self.skipped_inputs,
+                tasks: Some(plan),
+                ..ParseReport::default()
+            };
+            if report.lossy_files().next().is_some() {
+                report.log_table();
+                log::error!("Build of {} lost input, not merging", self.destination);
+                remove_buffer(&output_files).await;
+                return Err(StrictError { report }.into());
+            }
+        }
+        let (documents, sources, read) = {
+            let files = files.lock().await;
+            let sources = files
+                .ids
+                .iter()
+                .map(|(name, _)| paths::encode(&files.names[*name].0))
+                .collect::<Vec<_>>();
+            let read = std::mem::take(&mut *read_digests.lock().await)
+                .into_iter()
+                .map(|(i, digest)| (files.names[i].0.clone(), digest))
+                .collect::<Vec<_>>();
+            (files.ids.len(), sources, read)
+        };
+        for (path, digest) in read {
+            // Readers over providers that don't hash leave it to a second read.
+            let digest = match digest {
+                Some(v) => InputDigest::new(paths::encode(&path), fs::metadata(&path).await?.len(), v),
+                None => hash_file(&path).await?,
+            };
+            digests.insert(path, digest);
+        }
+        let inputs = given
+            .into_iter()
+            .filter_map(|path| digests.get(&path).cloned())
+            .collect();
+        if in_memory {
+            let terms = combine_trees(std::mem::take(&mut *trees.lock().await))?;
+            self.merger
+                .write_terms(files, terms, self.destination.clone(), &self.cancel)
+                .await?;
+        } else {
+            self.merger
+                .merge(files, output_files, self.destination.clone(), &self.cancel)
+                .await?;
+        }
+        let generation = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |v| v.as_nanos() as u64);
+        let metadata = IndexMetadata {
+            sample: SampleRecord {
+                sampling: counter.sampling,
+                documents: counter.documents.load(std::sync::atomic::Ordering::Relaxed),
+                included: counter.included.load(std::sync::atomic::Ordering::Relaxed),
+            },
+            filter,
+            phonetic: self.merger.phonetic_index(),
+            permuterm: self.merger.permuterm_index(),
+            fst: self.merger.fst_index(),
+            bitmaps: self.merger.bitmap_min_df(),
+            tf,
+            numeric: numeric.clone(),
+            case_preserving,
+            analyzer,
+            excluded,
+            zones,
+            zone_documents: Some(std::mem::take(&mut *zone_documents.lock().await)),
+            generation,
+            build: Some(BuildRecord::new(config, inputs)),
+            max_token_bytes: Some(MAX_TOKEN_BYTES),
+        };
+        metadata.save(&self.destination).await?;
+
+        let lengths = std::mem::take(&mut *lengths.lock().await);
+        let titles = std::mem::take(&mut *titles.lock().await)
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+        if self.store_titles {
+            let mut stored = DocumentTitles {
+                titles: vec![String::new(); documents],
+            };
+            for (document, title) in titles.iter() {
+                stored.titles[*document] = title.clone();
+            }
+            stored.save(&self.destination).await?;
+        }
+        let mut unknown_boosts = Vec::new();
+        if let Some(boosts) = &self.boosts {
+            let (resolved, unknown) = boosts.resolve(
+                documents,
+                lengths.iter().map(|(document, _)| {
+                    (
+                        *document,
+                        sources[*document].as_str(),
+                        titles.get(document).map(|v| v.as_str()),
+                    )
+                }),
+            );
+            resolved.save(&self.destination).await?;
+            unknown_boosts = unknown;
+        }
+        if let Some(field) = numeric {
+            let mut values = NumericValues {
+                field,
+                values: vec![None; documents],
+            };
+            for (document, v) in std::mem::take(&mut *numeric_values.lock().await) {
+                values.values[document] = Some(v);
+            }
+            values.save(&self.destination).await?;
+        }
+        let mut document_lengths = DocumentLengths {
+            documents: lengths.len(),
+            lengths: vec![0; documents],
+            zones: Vec::new(),
+        };
+        for (document, length) in lengths {
+            document_lengths.lengths[document] = length;
+        }
+        let mut zones = BTreeMap::<&str, Vec<u32>>::new();
+        for (document, zone, length) in std::mem::take(&mut *zone_lengths.lock().await) {
+            zones.entry(zone).or_insert_with(|| vec![0; documents])[document] = length;
+        }
+        document_lengths.zones = zones.into_iter().map(|(zone, v)| (zone.to_string(), v)).collect();
+        document_lengths.save(&self.destination).await?;
+
+        let top_terms = self.merger.take_top_terms();
+        if !top_terms.is_empty() {
+            IdfTable::new(generation, document_lengths.documents, top_terms)
+                .save(&self.destination)
+                .await?;
+        }
+
+        let mut reports = std::mem::take(&mut *reports.lock().await);
+        reports.sort_unstable_by_key(|(i, _)| *i);
+        let report = ParseReport {
+            files: reports.into_iter().map(|(_, v)| v).collect(),
+            unknown_boosts,
+            skipped_files,
+            skipped_inputs: self.skipped_inputs,
+            tasks: Some(plan),
+        };
+        report.log_table();
+        report.save(&self.destination).await?;
+        Ok(())
+    }
+
+    pub async fn create_dictionary(mut self) -> Result<(), Error> {
+        let in_memory = self.in_memory().await?;
+        self.prepare(in_memory).await?;
+        let Some(keep) = self.generations else {
+            return self.invert(in_memory).await;
+        };
+        let generations = Generations::new(self.destination.clone());
+        let (generation, directory) = generations.create_next().await?;
+        self.destination = directory.clone();
+        if let Err(e) = self.invert(in_memory).await {
+            // The generation is fresh, so a cancelled build leaves nothing of it.
+            if is_interrupted(&e) || e.get_ref().is_some_and(|v| v.is::<StrictError>()) {
+                fs::remove_dir_all(&directory).await?;
+            }
+            return Err(e);
+        }
+        generations.flip(generation).await?;
+        log::info!("{} now points at generation {}", generations.parent(), generation);
+        for removed in generations.retain(keep).await? {
+            log::info!("Removed generation {} of {}", removed, generations.parent());
+        }
         Ok(())
     }
 
-    pub async fn create_dictionary(self) -> Result<(), Error> {
-        self.invert().await
+    /// [`Self::create_dictionary`] for callers without a runtime. It gets
+    /// one of its own on the current thread, where the tasks take turns
+    /// instead of running in parallel, and the inputs are read through
+    /// `std::io`, see [`ParserBuilder::read_blocking`]. Built with a single
+    /// task the index is the same as the async one. Panics if called inside
+    /// a runtime.
+    pub fn create_dictionary_blocking(mut self) -> Result<(), Error> {
+        self.builder.read_blocking();
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(self.create_dictionary())
+    }
+}
+
+/// Joins the trees the parse tasks kept in memory, see [`Term::combine`].
+fn combine_trees<T: Term>(trees: Vec<BTreeMap<String, T>>) -> Result<BTreeMap<String, T>, Error> {
+    let mut trees = trees.into_iter();
+    let mut terms = trees.next().unwrap_or_default();
+    for tree in trees {
+        for (word, term) in tree {
+            match terms.entry(word) {
+                Entry::Vacant(v) => {
+                    v.insert(term);
+                }
+                Entry::Occupied(mut v) => v.get_mut().combine(term)?,
+            }
+        }
     }
+    Ok(terms)
+}
+
+/// Whether `path` holds anything but whitespace. Reads only up to the first
+/// byte that isn't, so real chunk files cost a single read.
+async fn has_content(path: &Path) -> Result<bool, Error> {
+    if fs::metadata(path).await?.len() == 0 {
+        return Ok(false);
+    }
+    let mut reader = BufReader::new(File::open(path).await?);
+    loop {
+        let buffer = reader.fill_buf().await?;
+        if buffer.is_empty() {
+            return Ok(false);
+        }
+        if buffer.iter().any(|v| !v.is_ascii_whitespace()) {
+            return Ok(true);
+        }
+        let len = buffer.len();
+        reader.consume(len);
+    }
+}
+
+/// KiB a second of reading `bytes` in `elapsed`.
+fn kib_per_sec(bytes: u64, elapsed: Duration) -> u64 {
+    (bytes as f64 / 1024.0 / elapsed.as_secs_f64().max(f64::EPSILON)) as u64
+}
+
+/// The `index`th buffer the tasks flush, inside `directory`.
+fn buffer_path(directory: &str, index: usize) -> String {
+    Path::new(directory).join(index.to_string()).to_string_lossy().into_owned()
+}
+
+/// Writes what `parser` holds to the buffer `path`.
+async fn flush<P: Parser>(parser: &mut P, path: &String) -> Result<(), Error> {
+    let (terms, bytes, started) = (parser.len(), parser.estimated_bytes(), Instant::now());
+    parser.flush_to(path).await?;
+    log::debug!(
+        "flush done: path={} terms={} bytes~={} elapsed={:?}",
+        path,
+        terms,
+        bytes,
+        started.elapsed()
+    );
+    Ok(())
 }
 
 pub async fn remove_buffer(files: &Arc<Mutex<Vec<String>>>) {
@@ -0,0 +1,191 @@
+//! Input paths as the index records them. Paths are kept as [`PathBuf`]
+//! while building, so a file name that isn't UTF-8 is read like any other;
+//! only reports, metadata and `files.txt` hold them as text, through
+//! [`encode`].
+//!
+//! [`encode`] keeps valid UTF-8 as it is and writes every byte that isn't
+//! (an unpaired surrogate on Windows) as `%XX` (`%uXXXX`), `%` itself as
+//! `%25`, so [`decode`] gives back the same path.
+
+use std::path::{Path, PathBuf};
+
+/// Longest path Windows opens without the `\\?\` prefix.
+pub const MAX_PATH: usize = 260;
+
+#[cfg(windows)]
+const VERBATIM: &str = r"\\?\";
+#[cfg(windows)]
+const VERBATIM_UNC: &str = r"\\?\UNC\";
+
+/// `path` as text that [`decode`] turns back into it. Windows extended-length
+/// prefixes are left out.
+pub fn encode(path: &Path) -> String {
+    let mut out = String::new();
+    encode_into(path, &mut out);
+    strip_verbatim(out)
+}
+
+#[cfg(unix)]
+fn encode_into(path: &Path, out: &mut String) {
+    use std::os::unix::ffi::OsStrExt;
+
+    for chunk in path.as_os_str().as_bytes().utf8_chunks() {
+        push_escaped(chunk.valid(), out);
+        for byte in chunk.invalid() {
+            out.push_str(&format!("%{byte:02X}"));
+        }
+    }
+}
+
+#[cfg(windows)]
+fn encode_into(path: &Path, out: &mut String) {
+    use std::os::windows::ffi::OsStrExt;
+
+    for v in char::decode_utf16(path.as_os_str().encode_wide()) {
+        match v {
+            Ok(c) => push_escaped(c.encode_utf8(&mut [0; 4]), out),
+            Err(e) => out.push_str(&format!("%u{:04X}", e.unpaired_surrogate())),
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn encode_into(path: &Path, out: &mut String) {
+    push_escaped(&path.to_string_lossy(), out);
+}
+
+fn push_escaped(valid: &str, out: &mut String) {
+    for c in valid.chars() {
+        match c {
+            '%' => out.push_str("%25"),
+            c => out.push(c),
+        }
+    }
+}
+
+#[cfg(windows)]
+fn strip_verbatim(encoded: String) -> String {
+    if let Some(rest) = encoded.strip_prefix(VERBATIM_UNC) {
+        return format!(r"\\{rest}");
+    }
+    match encoded.strip_prefix(VERBATIM) {
+        Some(rest) => rest.to_string(),
+        None => encoded,
+    }
+}
+
+#[cfg(not(windows))]
+fn strip_verbatim(encoded: String) -> String {
+    encoded
+}
+
+/// The path [`encode`] made `raw` of. Text that [`encode`] wouldn't have
+/// written, such as a `%` not followed by hex digits, is taken as it is.
+pub fn decode(raw: &str) -> PathBuf {
+    let mut bytes = Vec::<u8>::with_capacity(raw.len());
+    #[cfg(windows)]
+    let mut wide = Vec::<u16>::new();
+    let mut rest = raw;
+    while let Some(at) = rest.find('%') {
+        bytes.extend_from_slice(&rest.as_bytes()[..at]);
+        rest = &rest[at..];
+        #[cfg(windows)]
+        if let Some(v) = rest.get(2..6).filter(|_| rest[1..].starts_with('u')) {
+            if let Ok(v) = u16::from_str_radix(v, 16) {
+                flush_wide(&mut bytes, &mut wide);
+                wide.push(v);
+                rest = &rest[6..];
+                continue;
+            }
+        }
+        match rest.get(1..3).and_then(|v| u8::from_str_radix(v, 16).ok()) {
+            Some(v) => {
+                bytes.push(v);
+                rest = &rest[3..];
+            }
+            None => {
+                bytes.push(b'%');
+                rest = &rest[1..];
+            }
+        }
+    }
+    bytes.extend_from_slice(rest.as_bytes());
+    #[cfg(windows)]
+    {
+        flush_wide(&mut bytes, &mut wide);
+        use std::os::windows::ffi::OsStringExt;
+        return PathBuf::from(std::ffi::OsString::from_wide(&wide));
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+        PathBuf::from(std::ffi::OsString::from_vec(bytes))
+    }
+    #[cfg(not(any(unix, windows)))]
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Moves the UTF-8 collected in `bytes` over to `wide`.
+#[cfg(windows)]
+fn flush_wide(bytes: &mut Vec<u8>, wide: &mut Vec<u16>) {
+    wide.extend(String::from_utf8_lossy(bytes).encode_utf16());
+    bytes.clear();
+}
+
+/// `path` in a form it can be opened by: on Windows an absolute path too
+/// long for the usual API gets the `\\?\` prefix. Anywhere else, and for
+/// short paths, it is `path` itself.
+#[cfg(windows)]
+pub fn extended(path: PathBuf) -> PathBuf {
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+
+    if !path.is_absolute() || path.as_os_str().len() < MAX_PATH {
+        return path;
+    }
+    let wide = path.as_os_str().encode_wide().collect::<Vec<_>>();
+    let starts = |prefix: &str| prefix.encode_utf16().eq(wide.iter().copied().take(prefix.len()));
+    if starts(VERBATIM) {
+        return path;
+    }
+    // Verbatim paths aren't normalized, so separators have to be backslashes.
+    let (prefix, rest) = match starts(r"\\") {
+        true => (VERBATIM_UNC, &wide[2..]),
+        false => (VERBATIM, &wide[..]),
+    };
+    let mut out = prefix.encode_utf16().collect::<Vec<_>>();
+    out.extend(rest.iter().map(|v| if *v == u16::from(b'/') { u16::from(b'\\') } else { *v }));
+    PathBuf::from(std::ffi::OsString::from_wide(&out))
+}
+
+#[cfg(not(windows))]
+pub fn extended(path: PathBuf) -> PathBuf {
+    path
+}
+
+#[cfg(test)]
+mod tst {
+    use std::path::PathBuf;
+
+    use super::{decode, encode, extended};
+
+    #[test]
+    fn encoded_paths_round_trip() {
+        for raw in ["res/0.xml", "100%/ünïcode.xml", "a%zz", ""] {
+            let path = PathBuf::from(raw);
+            assert_eq!(decode(&encode(&path)), path, "{raw}");
+        }
+        assert_eq!(encode(&PathBuf::from("100%/a.xml")), "100%25/a.xml");
+        assert_eq!(decode("a%zz"), PathBuf::from("a%zz"));
+        assert_eq!(extended(PathBuf::from("res/0.xml")), PathBuf::from("res/0.xml"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_names_round_trip() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let path = PathBuf::from(OsStr::from_bytes(b"gex/caf\xe9 %41.xml"));
+        assert_eq!(encode(&path), "gex/caf%E9 %2541.xml");
+        assert_eq!(decode(&encode(&path)), path);
+    }
+}
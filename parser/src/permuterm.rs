@@ -0,0 +1,170 @@
+use std::io::{Error, ErrorKind};
+
+#[cfg(feature = "build")]
+use tokio::fs;
+
+use crate::query::QueryError;
+#[cfg(feature = "build")]
+use crate::{config::IoTuning, indexed::IndexMergeSaver, layout::IndexLayout, segment::Segments, term_ord::term_cmp};
+
+/// Rotations of `term$` gathered during the merge.
+///
+/// They are kept in memory until the main dictionary is written, which is
+/// why the permuterm index is opt-in.
+#[cfg(feature = "build")]
+#[derive(Debug, Default)]
+pub(crate) struct Rotations {
+    items: Vec<(String, u64)>,
+}
+
+#[cfg(feature = "build")]
+impl Rotations {
+    pub(crate) fn push(&mut self, term: &str, ordinal: u64) {
+        let marked = format!("{term}$");
+        for (i, _) in marked.char_indices() {
+            self.items
+                .push((format!("{}{}", &marked[i..], &marked[..i]), ordinal));
+        }
+    }
+
+    /// Writes the sorted rotations as a dictionary whose use counts hold term ordinals.
+    pub(crate) async fn save<S: Segments>(
+        mut self,
+        directory: &str,
+        lexical_max_size: u8,
+        layout: IndexLayout,
+        io: IoTuning,
+    ) -> Result<(), Error> {
+        self.items
+            .sort_unstable_by(|a, b| term_cmp(&a.0, &b.0).then(a.1.cmp(&b.1)));
+        let directory = layout.permuterm(directory);
+        fs::create_dir_all(&directory).await?;
+        let mut saver = IndexMergeSaver::<S>::create(directory, lexical_max_size, layout, io).await?;
+        for (rotation, ordinal) in self.items {
+            saver.push_written(rotation, ordinal, 0).await?;
+        }
+        saver.finish().await?;
+        Ok(())
+    }
+}
+
+fn too_broad(pattern: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidInput,
+        QueryError::TooBroad(pattern.to_string()),
+    )
+}
+
+/// Rotation prefix every term matching `pattern` has.
+pub fn rotation_key(pattern: &str) -> Result<String, Error> {
+    let key = match (pattern.find('*'), pattern.rfind('*')) {
+        (None, _) | (_, None) => format!("{pattern}$"),
+        (Some(first), Some(last)) if first == 0 && last == pattern.len() - 1 && first != last => {
+            let inner = pattern[1..last].split('*').max_by_key(|v| v.len());
+            inner.unwrap_or("").to_string()
+        }
+        (Some(first), Some(last)) => format!("{}${}", &pattern[last + 1..], &pattern[..first]),
+    };
+    if key.is_empty() || key == "$" {
+        return Err(too_broad(pattern));
+    }
+    Ok(key)
+}
+
+/// Whether `term` matches `pattern`, where `*` stands for any run of characters.
+pub fn matches(pattern: &str, term: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let Some(mut rest) = term.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts = parts.collect::<Vec<_>>();
+    let Some(last) = parts.pop() else {
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tst {
+    use std::io::Error;
+
+    use tokio::fs;
+
+    use crate::{
+        config::IndexerConfig,
+        indexed::{Dictionary, IndexMerger, IndexParser, IndexedBuilder},
+        metadata::IndexMetadata,
+        parser::ParseController,
+        query::QueryError,
+        segment::CommonSegments,
+        zones::ZoneSet,
+    };
+
+    use super::{matches, rotation_key};
+
+    #[test]
+    fn keys() {
+        assert_eq!(rotation_key("ology").unwrap(), "ology$");
+        assert_eq!(rotation_key("*ing").unwrap(), "ing$");
+        assert_eq!(rotation_key("te*t").unwrap(), "t$te");
+        assert_eq!(rotation_key("mon*").unwrap(), "$mon");
+        assert_eq!(rotation_key("*ter*").unwrap(), "ter");
+        assert_eq!(rotation_key("a*b*c").unwrap(), "c$a");
+        assert!(matches("a*b*c", "axbyc"));
+        assert!(!matches("a*b*c", "axyc"));
+        assert!(!matches("te*t", "te"));
+        assert_eq!(rotation_key("*a*bc*").unwrap(), "bc");
+    }
+
+    #[tokio::test]
+    async fn leading_and_inner_wildcards() -> Result<(), Error> {
+        let root = std::env::temp_dir().join(format!("permuterm_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root).await;
+        fs::create_dir_all(&root).await?;
+        let path = root.join("0.xml").to_str().unwrap().to_string();
+        fs::write(
+            &path,
+            "<title>\ntesting text\n</title>\n<text>\nsing a ring of tenant test tea\n</text>\n",
+        )
+        .await?;
+
+        let destination = root.join("res").to_str().unwrap().to_string();
+        let config = IndexerConfig::new(1000, 6)?;
+        ParseController::<IndexParser, _, _>::new(
+            vec![path],
+            destination.clone(),
+            root.join("buffer").to_str().unwrap().to_string(),
+            1,
+            IndexedBuilder::new(config, ZoneSet::new(["title", "text"])?)?,
+            IndexMerger::new(config.merger()).with_permuterm(),
+        )
+        .create_dictionary()
+        .await?;
+        assert!(IndexMetadata::load(&destination).await?.permuterm);
+
+        let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+        assert_eq!(dictionary.wildcard("*ing").await?, ["ring", "sing", "testing"]);
+        assert_eq!(dictionary.wildcard("te*t").await?, ["tenant", "test", "text"]);
+        assert_eq!(dictionary.wildcard("t*s*g").await?, ["testing"]);
+        assert_eq!(dictionary.wildcard("*en*").await?, ["tenant"]);
+        assert_eq!(dictionary.wildcard("tea").await?, ["tea"]);
+        assert!(dictionary.wildcard("*xyz").await?.is_empty());
+        for pattern in ["*", "**"] {
+            let err = dictionary.wildcard(pattern).await.unwrap_err();
+            assert_eq!(
+                err.get_ref().unwrap().downcast_ref::<QueryError>(),
+                Some(&QueryError::TooBroad(pattern.to_string()))
+            );
+        }
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
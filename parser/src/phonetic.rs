@@ -0,0 +1,180 @@
+use std::{collections::BTreeMap, io::Error};
+
+use save::writer::{variable_load, variable_save_usize};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter},
+};
+
+use crate::layout::IndexLayout;
+
+/// American Soundex code of the ASCII letters of `term`.
+pub fn soundex(term: &str) -> Option<[u8; 4]> {
+    fn digit(c: u8) -> u8 {
+        match c {
+            b'b' | b'f' | b'p' | b'v' => b'1',
+            b'c' | b'g' | b'j' | b'k' | b'q' | b's' | b'x' | b'z' => b'2',
+            b'd' | b't' => b'3',
+            b'l' => b'4',
+            b'm' | b'n' => b'5',
+            b'r' => b'6',
+            b'h' | b'w' => b'-',
+            _ => b'0',
+        }
+    }
+    let mut letters = term
+        .bytes()
+        .filter(u8::is_ascii_alphabetic)
+        .map(|c| c.to_ascii_lowercase());
+    let first = letters.next()?;
+    let mut code = [first.to_ascii_uppercase(), b'0', b'0', b'0'];
+    let mut previous = digit(first);
+    let mut len = 1;
+    for c in letters {
+        let next = digit(c);
+        if next == b'-' {
+            continue;
+        }
+        if next != b'0' && next != previous {
+            code[len] = next;
+            len += 1;
+            if len == code.len() {
+                break;
+            }
+        }
+        previous = next;
+    }
+    Some(code)
+}
+
+/// Soundex code to term ordinals, stored as `phonetic_part.txt`.
+#[derive(Debug, Default)]
+pub struct PhoneticIndex {
+    codes: BTreeMap<[u8; 4], Vec<usize>>,
+}
+
+impl PhoneticIndex {
+    /// Ordinals have to be pushed in increasing order.
+    pub fn push(&mut self, term: &str, ordinal: usize) {
+        if let Some(code) = soundex(term) {
+            self.codes.entry(code).or_default().push(ordinal);
+        }
+    }
+
+    /// Ordinals of the terms sharing the code of `term`.
+    pub fn candidates(&self, term: &str) -> &[usize] {
+        soundex(term)
+            .and_then(|v| self.codes.get(&v))
+            .map_or(&[], |v| v.as_slice())
+    }
+
+    pub async fn save(&self, directory: &str) -> Result<(), Error> {
+        let mut writer =
+            BufWriter::new(File::create(IndexLayout::detect(directory).await?.phonetic(directory)).await?);
+        writer.write_u64(self.codes.len() as u64).await?;
+        for (code, ordinals) in self.codes.iter() {
+            writer.write_all(code).await?;
+            variable_save_usize(ordinals.len(), &mut writer).await?;
+            let mut previous = 0;
+            for v in ordinals {
+                variable_save_usize(v - previous, &mut writer).await?;
+                previous = *v;
+            }
+        }
+        writer.flush().await
+    }
+
+    pub async fn load(directory: &str) -> Result<Self, Error> {
+        let mut reader =
+            BufReader::new(File::open(IndexLayout::detect(directory).await?.phonetic(directory)).await?);
+        let mut codes = BTreeMap::new();
+        for _ in 0..reader.read_u64().await? {
+            let mut code = [0u8; 4];
+            reader.read_exact(&mut code).await?;
+            let len = variable_load(&mut reader).await?;
+            let mut ordinals = Vec::with_capacity(len);
+            let mut previous = 0;
+            for _ in 0..len {
+                previous += variable_load(&mut reader).await?;
+                ordinals.push(previous);
+            }
+            codes.insert(code, ordinals);
+        }
+        Ok(Self { codes })
+    }
+}
+
+#[cfg(test)]
+mod tst {
+    use std::{collections::BTreeSet, io::Error};
+
+    use tokio::fs;
+
+    use crate::{
+        config::IndexerConfig,
+        indexed::{Dictionary, IndexMerger, IndexParser, IndexedBuilder},
+        metadata::IndexMetadata,
+        parser::ParseController,
+        segment::CommonSegments,
+        zones::ZoneSet,
+    };
+
+    use super::soundex;
+
+    #[test]
+    fn codes() {
+        assert_eq!(soundex("robert"), Some(*b"R163"));
+        assert_eq!(soundex("rupert"), Some(*b"R163"));
+        assert_eq!(soundex("ashcraft"), Some(*b"A261"));
+        assert_eq!(soundex("tymczak"), Some(*b"T522"));
+        assert_eq!(soundex("pfister"), Some(*b"P236"));
+        assert_eq!(soundex("smith"), soundex("smyth"));
+        assert_ne!(soundex("smith"), soundex("rust"));
+        assert_eq!(soundex("ёж"), None);
+    }
+
+    #[tokio::test]
+    async fn variants_share_candidates() -> Result<(), Error> {
+        let root = std::env::temp_dir().join(format!("phonetic_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root).await;
+        fs::create_dir_all(&root).await?;
+        let path = root.join("0.xml").to_str().unwrap().to_string();
+        fs::write(
+            &path,
+            "<title>\nsmith\n</title>\n<text>\nblacksmith john\n</text>\n\
+             <title>\nsmyth\n</title>\n<text>\nweaver\n</text>\n\
+             <title>\nrust\n</title>\n<text>\nmetal\n</text>\n",
+        )
+        .await?;
+
+        let destination = root.join("res").to_str().unwrap().to_string();
+        let config = IndexerConfig::new(1000, 6)?;
+        ParseController::<IndexParser, _, _>::new(
+            vec![path],
+            destination.clone(),
+            root.join("buffer").to_str().unwrap().to_string(),
+            1,
+            IndexedBuilder::new(config, ZoneSet::new(["title", "text"])?)?,
+            IndexMerger::new(config.merger()).with_phonetic(),
+        )
+        .create_dictionary()
+        .await?;
+        assert!(IndexMetadata::load(&destination).await?.phonetic);
+
+        let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+        assert_eq!(dictionary.phonetic("smith").await?, ["smith", "smyth"]);
+        assert_eq!(dictionary.phonetic("smyth").await?, ["smith", "smyth"]);
+        assert_eq!(dictionary.phonetic("rust").await?, ["rust"]);
+        assert!(dictionary.phonetic("zzz").await?.is_empty());
+
+        let mut documents = BTreeSet::new();
+        for term in dictionary.phonetic("smith").await? {
+            let found = dictionary.find(&term).await?.unwrap();
+            documents.extend(found.indexes.iter().map(|(doc, _)| doc));
+        }
+        assert_eq!(documents.into_iter().collect::<Vec<_>>(), [0, 1]);
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,346 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    io::{Error, ErrorKind},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+use tokio::{fs, io::AsyncReadExt};
+
+use crate::{
+    generation,
+    indexed::{IndexMerger, IndexParser, IndexedBuilder},
+    inputs::{InputOrder, InputSet, DEFAULT_EXTENSIONS},
+    parser::ParseController,
+    reorder::remove_if_exists,
+    segment::CommonSegments,
+    stats::IndexStats,
+    tasks::AUTO_TASKS,
+    token_stream::{self, is_token_stream},
+    verify::verify_index_jobs,
+    zones::ZoneSet,
+};
+
+/// The controller [`index_directory`] builds with.
+pub type IndexController = ParseController<IndexParser, IndexMerger, IndexedBuilder>;
+
+/// Documents per chunk a dump is split into unless set, as many as the
+/// translator writes.
+pub const CHUNK_DOCUMENTS: u16 = 1000;
+
+/// Bytes [`sniff`] reads looking for the first tag.
+pub const SNIFF_BYTES: usize = 4096;
+
+/// How [`index_directory`] builds.
+pub struct IndexOptions {
+    builder: IndexedBuilder,
+    merger: IndexMerger,
+    tasks: u16,
+    extensions: Vec<String>,
+    order: InputOrder,
+    buffer: Option<String>,
+    chunks: Option<String>,
+    chunk_documents: u16,
+    verify_jobs: usize,
+    controller: Option<Box<dyn FnOnce(IndexController) -> IndexController + Send>>,
+}
+
+impl IndexOptions {
+    /// Tasks picked by the controller, [`DEFAULT_EXTENSIONS`] in the
+    /// default order and the index verified by a single job.
+    pub fn new(builder: IndexedBuilder, merger: IndexMerger) -> Self {
+        Self {
+            builder,
+            merger,
+            tasks: AUTO_TASKS,
+            extensions: DEFAULT_EXTENSIONS.iter().map(|v| v.to_string()).collect(),
+            order: InputOrder::default(),
+            buffer: None,
+            chunks: None,
+            chunk_documents: CHUNK_DOCUMENTS,
+            verify_jobs: 1,
+            controller: None,
+        }
+    }
+
+    /// Parse with `tasks` tasks, or as many as the controller picks with
+    /// [`AUTO_TASKS`], see [`crate::tasks::TaskPlan`].
+    pub fn with_tasks(mut self, tasks: u16) -> Self {
+        self.tasks = tasks;
+        self
+    }
+
+    /// Index files ending in one of `extensions` found under the input.
+    pub fn with_extensions(mut self, extensions: Vec<String>) -> Self {
+        self.extensions = extensions;
+        self
+    }
+
+    pub fn with_input_order(mut self, order: InputOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Keep the buffers of the build in `directory`, `{destination}.buffer`
+    /// unless set.
+    pub fn with_buffer(mut self, directory: impl AsRef<Path>) -> Self {
+        self.buffer = Some(directory.as_ref().to_string_lossy().into_owned());
+        self
+    }
+
+    /// Split dumps into `directory`, `{destination}.chunks` unless set. The
+    /// chunks are what the index records as its sources, so they stay.
+    pub fn with_chunks(mut self, directory: impl AsRef<Path>) -> Self {
+        self.chunks = Some(directory.as_ref().to_string_lossy().into_owned());
+        self
+    }
+
+    /// Put `documents` documents in every chunk a dump is split into.
+    pub fn with_chunk_documents(mut self, documents: u16) -> Self {
+        self.chunk_documents = documents.max(1);
+        self
+    }
+
+    /// Verify the index with `jobs` readers, see [`verify_index_jobs`].
+    pub fn with_verify_jobs(mut self, jobs: usize) -> Self {
+        self.verify_jobs = jobs.max(1);
+        self
+    }
+
+    /// Set up the controller with `configure` before it builds: sampling,
+    /// boosts, titles, generations and whatever else it takes.
+    pub fn with_controller(mut self, configure: impl FnOnce(IndexController) -> IndexController + Send + 'static) -> Self {
+        self.controller = Some(Box::new(configure));
+        self
+    }
+}
+
+/// Why [`index_directory`] failed, by the step that did.
+#[derive(Debug)]
+pub enum IndexError {
+    /// The input names nothing to index.
+    Inputs(Error),
+    /// A dump could not be split into chunks.
+    Split { file: PathBuf, error: Error },
+    /// Parsing or merging failed.
+    Build(Error),
+    /// The index written doesn't verify, or doesn't match its stats.
+    Verify(Error),
+}
+
+impl IndexError {
+    fn error(&self) -> &Error {
+        match self {
+            IndexError::Inputs(e) | IndexError::Build(e) | IndexError::Verify(e) => e,
+            IndexError::Split { error, .. } => error,
+        }
+    }
+}
+
+impl Display for IndexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            IndexError::Inputs(e) => write!(f, "no inputs to index: {e}"),
+            IndexError::Split { file, error } => write!(f, "can't split {}: {error}", file.display()),
+            IndexError::Build(e) => write!(f, "build failed: {e}"),
+            IndexError::Verify(e) => write!(f, "index doesn't verify: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for IndexError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.error())
+    }
+}
+
+impl From<IndexError> for Error {
+    fn from(e: IndexError) -> Self {
+        Error::new(e.error().kind(), e)
+    }
+}
+
+/// What a file of the inputs holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputKind {
+    /// Documents made of the zones alone, as the translator writes them.
+    Chunk,
+    /// Documents inside other elements, a `<mediawiki>` dump or the like,
+    /// to be split into chunks first.
+    Dump,
+}
+
+/// Tells the kind of `path` by its first tag within [`SNIFF_BYTES`]: a
+/// chunk opens with the first of `zones`. Declarations and comments are
+/// passed over; a token stream, or a file without a tag, is a chunk.
+pub async fn sniff(path: &Path, zones: &ZoneSet) -> Result<InputKind, Error> {
+    if is_token_stream(path) {
+        return Ok(InputKind::Chunk);
+    }
+    let mut head = Vec::with_capacity(SNIFF_BYTES);
+    fs::File::open(path).await?.take(SNIFF_BYTES as u64).read_to_end(&mut head).await?;
+    let head = String::from_utf8_lossy(&head);
+    for tag in head.split('<').skip(1) {
+        if tag.starts_with(['?', '!']) {
+            continue;
+        }
+        let name = tag
+            .split(|c: char| c.is_whitespace() || c == '>' || c == '/')
+            .next()
+            .unwrap_or_default();
+        return Ok(match name.eq_ignore_ascii_case(zones.first()) {
+            true => InputKind::Chunk,
+            false => InputKind::Dump,
+        });
+    }
+    Ok(InputKind::Chunk)
+}
+
+/// Builds an index of everything under `input` into `destination` in one
+/// call: finds the files, splits those that [`sniff`] takes for dumps,
+/// parses and merges them, and verifies what it wrote against the stats of
+/// the build. Returns the stats the verification counted.
+///
+/// A `destination` holding generations gets a new one, see
+/// [`ParseController::with_generations`].
+pub async fn index_directory(
+    input: impl AsRef<Path>,
+    destination: impl AsRef<Path>,
+    options: IndexOptions,
+) -> Result<IndexStats, IndexError> {
+    let destination = destination
+        .as_ref()
+        .to_str()
+        .ok_or_else(|| {
+            IndexError::Inputs(Error::new(
+                ErrorKind::InvalidInput,
+                format!("destination {} isn't valid UTF-8", destination.as_ref().display()),
+            ))
+        })?
+        .to_string();
+    let extensions = options.extensions.iter().map(String::as_str).collect::<Vec<_>>();
+    let spec = input.as_ref().to_string_lossy().into_owned();
+    let mut inputs = InputSet::resolve_ordered(&[spec], &extensions, options.order)
+        .await
+        .map_err(IndexError::Inputs)?;
+
+    let chunks = options.chunks.unwrap_or_else(|| format!("{destination}.chunks"));
+    inputs.files = split_dumps(inputs.files, &options.builder, &chunks, options.chunk_documents).await?;
+
+    let buffer = options.buffer.unwrap_or_else(|| format!("{destination}.buffer"));
+    let mut controller = IndexController::from_inputs(
+        inputs,
+        destination.clone(),
+        buffer,
+        options.tasks,
+        options.builder,
+        options.merger,
+    );
+    if let Some(configure) = options.controller {
+        controller = configure(controller);
+    }
+    controller.create_dictionary().await.map_err(IndexError::Build)?;
+
+    let directory = generation::resolve(&destination).await.map_err(IndexError::Verify)?;
+    let counted = verify_index_jobs::<CommonSegments>(&directory, options.verify_jobs)
+        .await
+        .map_err(IndexError::Verify)?;
+    let saved = IndexStats::load(&directory).await.map_err(IndexError::Verify)?;
+    if (saved.occurrences, saved.postings, saved.vocabulary) != (counted.occurrences, counted.postings, counted.vocabulary)
+    {
+        return Err(IndexError::Verify(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "stats.json holds {} occurrences, {} postings and {} terms, the index {}, {} and {}",
+                saved.occurrences,
+                saved.postings,
+                saved.vocabulary,
+                counted.occurrences,
+                counted.postings,
+                counted.vocabulary
+            ),
+        )));
+    }
+    log::info!("{directory}: index is consistent");
+    Ok(counted)
+}
+
+/// `files` with every dump replaced by its chunks in `directory`, which is
+/// emptied first if any dump is there.
+async fn split_dumps(
+    files: Vec<PathBuf>,
+    builder: &IndexedBuilder,
+    directory: &str,
+    documents: u16,
+) -> Result<Vec<PathBuf>, IndexError> {
+    let mut kinds = Vec::with_capacity(files.len());
+    for file in files.iter() {
+        match sniff(file, builder.zones()).await {
+            Ok(v) => kinds.push(v),
+            Err(error) => return Err(IndexError::Split { file: file.clone(), error }),
+        }
+    }
+    if !kinds.contains(&InputKind::Dump) {
+        return Ok(files);
+    }
+    let prepared = async {
+        remove_if_exists(directory).await?;
+        fs::create_dir_all(directory).await
+    };
+    prepared.await.map_err(|error| IndexError::Split {
+        file: PathBuf::from(directory),
+        error,
+    })?;
+
+    let counter = Arc::new(AtomicU32::new(0));
+    let mut split = Vec::with_capacity(files.len());
+    for (file, kind) in files.into_iter().zip(kinds) {
+        if kind == InputKind::Chunk {
+            split.push(file);
+            continue;
+        }
+        let first = counter.load(Ordering::SeqCst);
+        if let Err(error) = builder.split(&file, directory, documents, counter.clone()).await {
+            return Err(IndexError::Split { file, error });
+        }
+        let last = counter.load(Ordering::SeqCst);
+        log::info!("Split {} into {} chunks", file.display(), last - first);
+        split.extend(
+            (first..last).map(|v| Path::new(directory).join(format!("{v}.{}", token_stream::EXTENSION))),
+        );
+    }
+    Ok(split)
+}
+
+#[cfg(test)]
+mod tst {
+    use std::io::Error;
+
+    use tokio::fs;
+
+    use crate::{testsupport::scratch, zones::ZoneSet};
+
+    use super::{sniff, InputKind};
+
+    #[tokio::test]
+    async fn the_first_tag_tells_a_chunk_from_a_dump() -> Result<(), Error> {
+        let root = scratch("sniff").await?;
+        let zones = ZoneSet::new(["title", "text"])?;
+        for (name, content, kind) in [
+            ("chunk.xml", "<title>\nfirst\n</title>\n<text>\nbody\n</text>\n", InputKind::Chunk),
+            ("declared.xml", "<?xml version=\"1.0\"?>\n<!-- c -->\n<TITLE>x</TITLE>", InputKind::Chunk),
+            ("empty.xml", "", InputKind::Chunk),
+            ("dump.xml", "<mediawiki xmlns=\"x\">\n<page>\n<title>first</title>", InputKind::Dump),
+            ("text.xml", "<text>\nbody\n</text>\n", InputKind::Dump),
+        ] {
+            let path = root.join(name);
+            fs::write(&path, content).await?;
+            assert_eq!(sniff(&path, &zones).await?, kind, "{name}");
+        }
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,514 @@
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    io::{Error, ErrorKind, SeekFrom},
+    marker::PhantomData,
+    path::Path,
+};
+
+use save::{
+    save::VariableSave,
+    writer::{variable_load, CountedWriter},
+};
+use tokio::{
+    fs::File,
+    io::{AsyncSeekExt, BufReader},
+};
+
+use crate::{
+    indexed::{postings_bytes, variable_len, UsageData},
+    listmap::SortedLinkedMap,
+    segment::Segments,
+};
+
+/// Bytes of encoded postings a merge combines in memory for a single term
+/// unless told otherwise. A term over it is spilled, see [`merge_runs`].
+pub const SPILL_BUDGET: u64 = 256 * 1024 * 1024;
+
+/// Name of the file runs are spilled to while a term is merged, in the
+/// directory being written.
+pub const SPILL_FILE: &str = "postings.spill";
+
+/// A postings block kept as it was read from `index_part`, without its frame.
+///
+/// Only the header and the doc-id range are decoded, which is enough to join
+/// blocks over disjoint doc ids by re-encoding a single delta.
+#[derive(Debug)]
+pub(crate) struct RawBlock {
+    bytes: Vec<u8>,
+    len: usize,
+    first: usize,
+    last: usize,
+    body: usize,
+    max_tf: usize,
+}
+
+fn truncated() -> Error {
+    Error::new(ErrorKind::InvalidData, "truncated postings block")
+}
+
+fn decode(bytes: &[u8], at: &mut usize) -> Result<usize, Error> {
+    let mut v = 0usize;
+    let mut shift = 0;
+    loop {
+        let byte = *bytes.get(*at).ok_or_else(truncated)?;
+        *at += 1;
+        v += ((byte & 0b111_1111) as usize) << shift;
+        if byte & 0b1000_0000 != 0 {
+            return Ok(v);
+        }
+        shift += 7;
+    }
+}
+
+/// Number of postings in a block starting with `bytes`, past its frame if
+/// `framed`.
+pub(crate) fn block_len(bytes: &[u8], framed: bool) -> Result<usize, Error> {
+    let mut at = 0;
+    if framed {
+        decode(bytes, &mut at)?;
+    }
+    decode(bytes, &mut at)
+}
+
+/// Writes `postings` as a block led by its frame, the bytes it takes.
+pub(crate) async fn push_block<S: Segments>(
+    writer: &mut CountedWriter,
+    postings: &mut SortedLinkedMap<usize, UsageData<S>>,
+) -> Result<(), Error> {
+    writer.push_variable_u64(postings_bytes(postings)).await?;
+    writer.push_variable(postings).await
+}
+
+impl RawBlock {
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Highest use count among the postings.
+    pub(crate) fn max_tf(&self) -> usize {
+        self.max_tf
+    }
+
+    /// Scans a block whose values take `segments_size` bytes each. A
+    /// `framed` one has to take the bytes its frame says.
+    pub(crate) fn parse(bytes: Vec<u8>, segments_size: usize, framed: bool) -> Result<Self, Error> {
+        let mut at = 0;
+        if framed && decode(&bytes, &mut at)? != bytes.len() - at {
+            return Err(truncated());
+        }
+        let len = decode(&bytes, &mut at)?;
+        let (mut first, mut last, mut body, mut max_tf) = (0, 0, at, 0);
+        for i in 0..len {
+            last += decode(&bytes, &mut at)?;
+            if i == 0 {
+                first = last;
+                body = at;
+            }
+            max_tf = max_tf.max(decode(&bytes, &mut at)?);
+            at += segments_size;
+        }
+        if at != bytes.len() {
+            return Err(truncated());
+        }
+        Ok(Self {
+            bytes,
+            len,
+            first,
+            last,
+            body,
+            max_tf,
+        })
+    }
+}
+
+/// Orders the blocks by their first doc id and tells whether their ranges are disjoint.
+pub(crate) fn sort_disjoint(blocks: &mut Vec<RawBlock>) -> bool {
+    blocks.retain(|v| v.len > 0);
+    blocks.sort_unstable_by_key(|v| v.first);
+    blocks.windows(2).all(|v| v[0].last < v[1].first)
+}
+
+/// Writes sorted disjoint blocks as one block, fixing up the first delta of each.
+pub(crate) async fn write_joined(
+    blocks: &[RawBlock],
+    writer: &mut CountedWriter,
+) -> Result<(), Error> {
+    let len = blocks.iter().map(|v| v.len as u64).sum();
+    let mut frame = variable_len(len);
+    let mut previous = 0;
+    for block in blocks {
+        frame += variable_len((block.first - previous) as u64) + (block.bytes.len() - block.body) as u64;
+        previous = block.last;
+    }
+    writer.push_variable_u64(frame).await?;
+    writer.push_variable_u64(len).await?;
+    let mut previous = 0;
+    for block in blocks {
+        writer
+            .push_variable_u64((block.first - previous) as u64)
+            .await?;
+        writer.push(&block.bytes[block.body..]).await?;
+        previous = block.last;
+    }
+    Ok(())
+}
+
+/// A postings block in a spill file, read a posting at a time.
+struct Run<S: Segments> {
+    reader: BufReader<File>,
+    remaining: usize,
+    document: usize,
+    segment: PhantomData<S>,
+}
+
+impl<S: Segments> Run<S> {
+    async fn open(spill: &Path, offset: u64) -> Result<Self, Error> {
+        let mut reader = BufReader::new(File::open(spill).await?);
+        reader.seek(SeekFrom::Start(offset)).await?;
+        // The frame, runs are read to their end.
+        variable_load(&mut reader).await?;
+        let remaining = variable_load(&mut reader).await?;
+        Ok(Self {
+            reader,
+            remaining,
+            document: 0,
+            segment: PhantomData,
+        })
+    }
+
+    async fn next(&mut self) -> Result<Option<(usize, UsageData<S>)>, Error> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        self.document += variable_load(&mut self.reader).await?;
+        let usage = UsageData::<S>::variable_load(&mut self.reader).await?;
+        Ok(Some((self.document, usage)))
+    }
+}
+
+/// The postings of several runs in doc id order, a document found in more
+/// than one with its posting of the first.
+struct Runs<'a, S: Segments> {
+    term: &'a str,
+    runs: Vec<Run<S>>,
+    heads: Vec<Option<UsageData<S>>>,
+    queue: BinaryHeap<Reverse<(usize, usize)>>,
+}
+
+impl<'a, S: Segments> Runs<'a, S> {
+    async fn open(term: &'a str, spill: &Path, offsets: &[u64]) -> Result<Self, Error> {
+        let mut runs = Self {
+            term,
+            runs: Vec::with_capacity(offsets.len()),
+            heads: Vec::with_capacity(offsets.len()),
+            queue: BinaryHeap::with_capacity(offsets.len()),
+        };
+        for offset in offsets {
+            runs.runs.push(Run::open(spill, *offset).await?);
+            runs.heads.push(None);
+            runs.advance(runs.runs.len() - 1).await?;
+        }
+        Ok(runs)
+    }
+
+    async fn advance(&mut self, run: usize) -> Result<(), Error> {
+        if let Some((document, usage)) = self.runs[run].next().await? {
+            self.heads[run] = Some(usage);
+            self.queue.push(Reverse((document, run)));
+        }
+        Ok(())
+    }
+
+    async fn next(&mut self) -> Result<Option<(usize, UsageData<S>)>, Error> {
+        let Some(Reverse((document, run))) = self.queue.pop() else {
+            return Ok(None);
+        };
+        let mut usage = self.heads[run].take().unwrap();
+        self.advance(run).await?;
+        while let Some(Reverse((next, _))) = self.queue.peek() {
+            if *next != document {
+                break;
+            }
+            let Reverse((_, other)) = self.queue.pop().unwrap();
+            usage.merge(self.term, &self.heads[other].take().unwrap())?;
+            self.advance(other).await?;
+        }
+        Ok(Some((document, usage)))
+    }
+}
+
+/// Merges the runs of postings at `offsets` of `spill`, blocks of `term`
+/// each in doc id order, into one block on `writer`. Only a posting of every
+/// run is held at a time; they are read twice, once to count the documents
+/// and the bytes the block starts with. A document in several runs gets
+/// the postings of all of them merged, as combining in memory does. Returns
+/// the postings written and the highest use count among them.
+pub(crate) async fn merge_runs<S: Segments>(
+    term: &str,
+    spill: &Path,
+    offsets: &[u64],
+    writer: &mut CountedWriter,
+) -> Result<(usize, usize), Error> {
+    let mut runs = Runs::<S>::open(term, spill, offsets).await?;
+    let (mut documents, mut bytes, mut previous) = (0usize, 0, 0);
+    while let Some((document, usage)) = runs.next().await? {
+        documents += 1;
+        bytes += variable_len((document - previous) as u64) + variable_len(usage.use_count() as u64);
+        bytes += S::ENCODED_SIZE as u64;
+        previous = document;
+    }
+
+    let mut runs = Runs::<S>::open(term, spill, offsets).await?;
+    writer.push_variable_u64(variable_len(documents as u64) + bytes).await?;
+    writer.push_variable_u64(documents as u64).await?;
+    let (mut previous, mut max_tf) = (0, 0);
+    while let Some((document, mut usage)) = runs.next().await? {
+        writer.push_variable_u64((document - previous) as u64).await?;
+        max_tf = max_tf.max(usage.use_count());
+        writer.push_variable(&mut usage).await?;
+        previous = document;
+    }
+    Ok((documents, max_tf))
+}
+
+#[cfg(test)]
+mod tst {
+    use std::io::Error;
+
+    use tokio::fs;
+
+    use crate::{
+        cancel::CancellationToken,
+        indexed::{merge_providers, Dictionary, IndexMergeSaver, IndexTermProvider, IndexedTerm, UsageData},
+        listmap::SortedLinkedMap,
+        parser::{Term, TermProvider},
+        segment::{CommonSegments, Segments},
+        testsupport::allocations,
+    };
+
+    use super::{SPILL_BUDGET, SPILL_FILE};
+
+    fn term(name: &str, docs: impl Iterator<Item = usize>) -> IndexedTerm<CommonSegments> {
+        let mut term = IndexedTerm::new(name.to_string());
+        for doc in docs {
+            let mut usage = UsageData::new();
+            *usage.use_count_mut() = doc % 3 + 1;
+            if doc % 2 == 0 {
+                CommonSegments::selector_for("title")(usage.segments_mut(), 1);
+            }
+            term.use_count += *usage.use_count_mut() as u64;
+            term.indexes.push(doc, usage);
+        }
+        term
+    }
+
+    async fn buffer(
+        path: &String,
+        terms: Vec<IndexedTerm<CommonSegments>>,
+    ) -> Result<(), Error> {
+        fs::create_dir_all(path).await?;
+        let mut saver = IndexMergeSaver::new(path.clone(), 6).await?;
+        for term in terms {
+            saver.push(term).await?;
+        }
+        saver.finish().await?;
+        Ok(())
+    }
+
+    fn flatten(
+        indexes: SortedLinkedMap<usize, UsageData<CommonSegments>>,
+    ) -> Vec<(usize, usize, String)> {
+        indexes
+            .iter()
+            .map(|(doc, mut usage)| {
+                let segments = format!("{:?}", usage.segments_mut());
+                (doc, *usage.use_count_mut(), segments)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn streamed_postings_match_decoded() -> Result<(), Error> {
+        let root = std::env::temp_dir().join(format!("postings_stream_{}", std::process::id()));
+        let dir = |name: &str| root.join(name).to_str().unwrap().to_string();
+        let buffers = vec![dir("0"), dir("1"), dir("2")];
+        buffer(
+            &buffers[0],
+            vec![
+                term("alone", 3..7),
+                term("overlap", 0..6),
+                term("split", 0..4),
+                term("the", (0..40).step_by(3)),
+            ],
+        )
+        .await?;
+        buffer(
+            &buffers[1],
+            vec![
+                term("overlap", 4..9),
+                term("split", 300..302),
+                term("the", 200..260),
+            ],
+        )
+        .await?;
+        buffer(
+            &buffers[2],
+            vec![term("split", 10..12), term("the", 1000..1001), term("zebra", 5..6)],
+        )
+        .await?;
+
+        let mut expected = Vec::<IndexedTerm<CommonSegments>>::new();
+        for path in buffers.iter() {
+            let mut provider = IndexTermProvider::<CommonSegments>::new(path).await?;
+            while let Some(next) = provider.next_term().await {
+                match expected.iter_mut().find(|v| v.term == next.term) {
+                    Some(v) => v.combine(next)?,
+                    None => expected.push(next),
+                }
+            }
+        }
+        expected.sort();
+
+        let merged = dir("merged");
+        fs::create_dir_all(&merged).await?;
+        let mut providers = Vec::new();
+        for path in buffers.iter() {
+            providers.push(IndexTermProvider::<CommonSegments>::new(path).await?);
+        }
+        let mut saver = IndexMergeSaver::new(merged.clone(), 6).await?;
+        let stats = merge_providers(&mut providers, &mut saver, &CancellationToken::new()).await?;
+        saver.finish().await?;
+        assert_eq!(stats.vocabulary, expected.len() as u64);
+        assert_eq!(
+            stats.occurrences,
+            expected.iter().map(|v| v.use_count).sum::<u64>()
+        );
+        assert_eq!(
+            stats.postings,
+            expected.iter().map(|v| v.indexes.len() as u64).sum::<u64>()
+        );
+
+        let mut dictionary = Dictionary::<CommonSegments>::new(&merged).await?;
+        assert_eq!(dictionary.len(), expected.len());
+        for v in expected {
+            let found = dictionary.find(&v.term).await?.unwrap();
+            assert_eq!(found.use_count, v.use_count, "{}", v.term);
+            assert_eq!(flatten(found.indexes), flatten(v.indexes), "{}", v.term);
+        }
+
+        let mut provider = IndexTermProvider::<CommonSegments>::new(&merged).await?;
+        let mut names = Vec::new();
+        while let Some(next) = provider.next_term().await {
+            names.push(next.term);
+        }
+        assert_eq!(names, ["alone", "overlap", "split", "the", "zebra"]);
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn a_term_over_the_spill_budget_merges_through_runs() -> Result<(), Error> {
+        let root = std::env::temp_dir().join(format!("postings_spill_{}", std::process::id()));
+        let dir = |name: &str| root.join(name).to_str().unwrap().to_string();
+        let buffers = vec![dir("0"), dir("1"), dir("2"), dir("3")];
+        // Every buffer of "the" is over the budget and copied as a run of its
+        // own, "mid" takes 25 bytes in each, so two of them are combined in
+        // a run; both repeat documents across buffers.
+        buffer(&buffers[0], vec![term("mid", 0..8), term("the", (0..600).step_by(2)), term("wide", 0..500)]).await?;
+        buffer(&buffers[1], vec![term("mid", 4..12), term("the", (1..600).step_by(2)), term("wide", 400..401)]).await?;
+        buffer(&buffers[2], vec![term("mid", 8..16), term("rare", 3..4), term("the", 10..20)]).await?;
+        buffer(&buffers[3], vec![term("mid", 0..8), term("the", 590..900)]).await?;
+
+        let mut files = Vec::new();
+        for (name, budget) in [("memory", SPILL_BUDGET), ("spilled", 64)] {
+            let merged = dir(name);
+            fs::create_dir_all(&merged).await?;
+            let mut providers = Vec::new();
+            for path in buffers.iter() {
+                providers.push(IndexTermProvider::<CommonSegments>::new(path).await?);
+            }
+            let mut saver = IndexMergeSaver::new(merged.clone(), 6).await?.with_spill_budget(budget);
+            let stats = merge_providers(&mut providers, &mut saver, &CancellationToken::new()).await?;
+            saver.finish().await?;
+            assert_eq!(stats.vocabulary, 4);
+            assert!(fs::metadata(format!("{merged}/{SPILL_FILE}")).await.is_err());
+            files.push(merged);
+        }
+
+        for file in ["dictionary.txt", "lexical_part.txt", "index_part.txt"] {
+            let memory = fs::read(format!("{}/{file}", files[0])).await?;
+            assert_eq!(memory, fs::read(format!("{}/{file}", files[1])).await?, "{file}");
+        }
+        let mut dictionary = Dictionary::<CommonSegments>::new(&files[1]).await?;
+        let the = flatten(dictionary.find("the").await?.unwrap().indexes);
+        assert_eq!(the.iter().map(|v| v.0).collect::<Vec<_>>(), (0..900).collect::<Vec<_>>());
+        assert_eq!(flatten(dictionary.find("wide").await?.unwrap().indexes).len(), 500);
+        assert_eq!(flatten(dictionary.find("mid").await?.unwrap().indexes).len(), 16);
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+
+    /// Merges `buffers` into `merged` and returns the allocations it took.
+    async fn merge(buffers: &[String], merged: &String) -> Result<usize, Error> {
+        fs::create_dir_all(merged).await?;
+        let mut providers = Vec::new();
+        for path in buffers.iter() {
+            providers.push(IndexTermProvider::<CommonSegments>::new(path).await?);
+        }
+        let mut saver = IndexMergeSaver::new(merged.clone(), 6).await?;
+        let before = allocations();
+        merge_providers(&mut providers, &mut saver, &CancellationToken::new()).await?;
+        let used = allocations() - before;
+        saver.finish().await?;
+        Ok(used)
+    }
+
+    #[tokio::test]
+    async fn single_source_terms_are_copied() -> Result<(), Error> {
+        let root = std::env::temp_dir().join(format!("postings_copy_{}", std::process::id()));
+        let dir = |name: &str| root.join(name).to_str().unwrap().to_string();
+        let terms = || {
+            vec![
+                term("common", 0..4_000),
+                term("rare", (0..4_000).step_by(500)),
+                term("zone", (5..3_000).step_by(3)),
+            ]
+        };
+        let (first, second) = (dir("0"), dir("1"));
+        buffer(&first, terms()).await?;
+        buffer(&second, vec![term("apart", 0..2)]).await?;
+
+        let copied = merge(&[first.clone(), second.clone()], &dir("copied")).await?;
+        let decoded = dir("decoded");
+        fs::create_dir_all(&decoded).await?;
+        let mut saver = IndexMergeSaver::new(decoded.clone(), 6).await?;
+        let before = allocations();
+        let mut loaded = Vec::new();
+        for path in [&first, &second] {
+            let mut provider = IndexTermProvider::<CommonSegments>::new(path).await?;
+            while let Some(next) = provider.next_term().await {
+                loaded.push(next);
+            }
+        }
+        loaded.sort();
+        for v in loaded {
+            saver.push(v).await?;
+        }
+        let rebuilt = allocations() - before;
+        saver.finish().await?;
+        assert!(copied * 100 < rebuilt, "{copied} vs {rebuilt}");
+        assert!(copied < 1000, "{copied}");
+
+        for file in ["dictionary.txt", "lexical_part.txt", "index_part.txt"] {
+            let copied = fs::read(format!("{}/{file}", dir("copied"))).await?;
+            assert_eq!(copied, fs::read(format!("{decoded}/{file}")).await?, "{file}");
+        }
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,233 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    io::{Error, ErrorKind},
+    path::Path,
+};
+
+use save::sha256::{hex, Sha256};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{
+    fs::File,
+    io::{AsyncBufReadExt, BufReader},
+};
+
+use crate::{metadata::IndexMetadata, paths};
+
+/// An input file as the build read it.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputDigest {
+    pub path: String,
+    pub bytes: u64,
+    /// SHA-256 of the file, in hex.
+    pub sha256: String,
+}
+
+impl InputDigest {
+    pub fn new(path: String, bytes: u64, digest: [u8; 32]) -> Self {
+        Self {
+            path,
+            bytes,
+            sha256: hex(&digest),
+        }
+    }
+}
+
+/// Reads `path` once more to hash it.
+pub async fn hash_file(path: &Path) -> Result<InputDigest, Error> {
+    let mut reader = BufReader::new(File::open(path).await?);
+    let mut hash = Sha256::new();
+    let mut bytes = 0;
+    loop {
+        let buffer = reader.fill_buf().await?;
+        if buffer.is_empty() {
+            break;
+        }
+        hash.update(buffer);
+        let len = buffer.len();
+        bytes += len as u64;
+        reader.consume(len);
+    }
+    Ok(InputDigest::new(paths::encode(path), bytes, hash.finish()))
+}
+
+/// What an index was built from and with, recorded in `metadata.json` so a
+/// build can be audited and repeated.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BuildRecord {
+    /// Version of the crate that built the index.
+    pub version: String,
+    /// `{arch}-{os}` of the host.
+    pub platform: String,
+    /// Every setting of the build, by name.
+    pub config: BTreeMap<String, Value>,
+    /// SHA-256 of `config` as JSON, equal for builds configured the same.
+    pub fingerprint: String,
+    /// Input files in the order they were given, skipped ones included.
+    pub inputs: Vec<InputDigest>,
+}
+
+impl BuildRecord {
+    pub fn new(config: BTreeMap<String, Value>, inputs: Vec<InputDigest>) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            platform: format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS),
+            fingerprint: fingerprint(&config),
+            config,
+            inputs,
+        }
+    }
+}
+
+pub fn fingerprint(config: &BTreeMap<String, Value>) -> String {
+    let mut hash = Sha256::new();
+    hash.update(Value::Object(config.clone().into_iter().collect()).to_string().as_bytes());
+    hex(&hash.finish())
+}
+
+/// An input that no longer is what the build read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputDrift {
+    Changed { recorded: InputDigest, found: InputDigest },
+    Missing(InputDigest),
+}
+
+impl Display for InputDrift {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InputDrift::Changed { recorded, found } => write!(
+                f,
+                "{} changed: {} bytes, sha256 {} (built from {} bytes, sha256 {})",
+                recorded.path, found.bytes, found.sha256, recorded.bytes, recorded.sha256
+            ),
+            InputDrift::Missing(recorded) => write!(f, "{} is missing", recorded.path),
+        }
+    }
+}
+
+/// What `verify --inputs` found.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct InputCheck {
+    pub checked: usize,
+    pub drift: Vec<InputDrift>,
+}
+
+impl InputCheck {
+    pub fn is_clean(&self) -> bool {
+        self.drift.is_empty()
+    }
+}
+
+impl Display for InputCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for v in self.drift.iter() {
+            writeln!(f, "{v}")?;
+        }
+        writeln!(f, "{} inputs checked, {} drifted", self.checked, self.drift.len())
+    }
+}
+
+/// Hashes the inputs recorded for the index in `directory` again. Fails if
+/// the index has no [`BuildRecord`].
+pub async fn verify_inputs(directory: &String) -> Result<InputCheck, Error> {
+    let record = IndexMetadata::load(directory).await?.build.ok_or_else(|| {
+        Error::new(ErrorKind::NotFound, format!("{directory} records no inputs to verify"))
+    })?;
+    let mut check = InputCheck::default();
+    for recorded in record.inputs {
+        check.checked += 1;
+        match hash_file(&paths::decode(&recorded.path)).await {
+            Ok(found) if found == recorded => {}
+            Ok(found) => check.drift.push(InputDrift::Changed { recorded, found }),
+            Err(e) if e.kind() == ErrorKind::NotFound => check.drift.push(InputDrift::Missing(recorded)),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(check)
+}
+
+#[cfg(test)]
+mod tst {
+    use std::io::Error;
+
+    use save::sha256::{hex, Sha256};
+    use tokio::fs;
+
+    use crate::{
+        metadata::IndexMetadata,
+        testsupport::{scratch, CorpusSpec},
+    };
+
+    use super::{hash_file, verify_inputs, InputDrift};
+
+    #[test]
+    fn digests_match_known_answers() {
+        for (input, expected) in [
+            ("", "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"),
+            ("abc", "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+            (
+                "abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+                "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+            ),
+        ] {
+            let mut hash = Sha256::new();
+            hash.update(input.as_bytes());
+            assert_eq!(hex(&hash.finish()), expected, "{input:?}");
+        }
+        let mut hash = Sha256::new();
+        hash.update(&[b'a'; 1_000_000]);
+        assert_eq!(
+            hex(&hash.finish()),
+            "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+        );
+    }
+
+    #[tokio::test]
+    async fn tampered_inputs_are_reported() -> Result<(), Error> {
+        let root = scratch("input_drift").await?;
+        let corpus = CorpusSpec {
+            files: 3,
+            ..CorpusSpec::default()
+        }
+        .generate(&root.join("corpus"))
+        .await?;
+        let destination = corpus.index(&root).await?;
+
+        let record = IndexMetadata::load(&destination).await?.build.unwrap();
+        assert_eq!(record.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(record.inputs.len(), 3);
+        for (recorded, path) in record.inputs.iter().zip(corpus.files.iter()) {
+            // The digest taken while indexing is the one of the whole file.
+            assert_eq!(recorded, &hash_file(path.as_ref()).await?);
+        }
+        assert_eq!(record.config["parser.lexical_block_size"], 6);
+        assert_eq!(record.config["merger.lexical_block_size"], 6);
+        assert_eq!(record.config["tasks"], 1);
+        assert_eq!(record.fingerprint, super::fingerprint(&record.config));
+
+        let check = verify_inputs(&destination).await?;
+        assert!(check.is_clean(), "{check}");
+        assert_eq!(check.checked, 3);
+
+        let mut tampered = fs::read(&corpus.files[1]).await?;
+        *tampered.iter_mut().find(|v| v.is_ascii_lowercase()).unwrap() = b'z';
+        fs::write(&corpus.files[1], &tampered).await?;
+        fs::remove_file(&corpus.files[2]).await?;
+        let check = verify_inputs(&destination).await?;
+        match check.drift.as_slice() {
+            [InputDrift::Changed { recorded, found }, InputDrift::Missing(missing)] => {
+                assert_eq!(recorded.path, corpus.files[1]);
+                assert_eq!(found.bytes, recorded.bytes);
+                assert_ne!(found.sha256, recorded.sha256);
+                assert_eq!(missing.path, corpus.files[2]);
+            }
+            v => panic!("{v:?}"),
+        }
+        assert!(check.to_string().ends_with("3 inputs checked, 2 drifted\n"), "{check}");
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,491 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    iter::Peekable,
+    str::CharIndices,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crate::{
+    analyzer::Analyzer,
+    execute::Limit,
+    numeric::NumericRange,
+    segment::{SegmentSelector, Segments},
+    zones::ZoneSet,
+};
+
+/// Parsed boolean query. Zone restrictions (`title:rust`) are resolved to a
+/// segments mask once, at parse time.
+#[derive(Debug)]
+pub enum Query<S: Segments> {
+    Term { term: String, zone: Option<S> },
+    /// `field:[low TO high]` over a numeric field.
+    Range { field: String, range: NumericRange },
+    And(Vec<Query<S>>),
+    Or(Vec<Query<S>>),
+    Not(Box<Query<S>>),
+}
+
+impl<S: Segments> Query<S> {
+    /// Every term the query mentions, in order of appearance.
+    pub fn terms(&self) -> Vec<&str> {
+        match self {
+            Query::Term { term, .. } => vec![term.as_str()],
+            Query::Range { .. } => vec![],
+            Query::And(v) | Query::Or(v) => v.iter().flat_map(|v| v.terms()).collect(),
+            Query::Not(v) => v.terms(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryError {
+    Syntax(String),
+    UnknownZone(String),
+    TooBroad(String),
+    /// A term longer than any the index holds, see
+    /// [`crate::execute::QueryLimits::max_term_bytes`].
+    TermTooLong { bytes: usize, max: usize },
+    Limit(Limit),
+    /// Stopped by a [`crate::cancel::CancellationToken`].
+    Interrupted,
+    Io(String),
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryError::Syntax(v) => write!(f, "query syntax error: {v}"),
+            QueryError::UnknownZone(v) => write!(f, "unknown zone {v}"),
+            QueryError::TooBroad(v) => write!(f, "wildcard {v} would match every term"),
+            QueryError::TermTooLong { bytes, max } => {
+                write!(f, "query term of {bytes} bytes is longer than the {max} the index holds")
+            }
+            QueryError::Limit(v) => write!(f, "query stopped: {v}"),
+            QueryError::Interrupted => write!(f, "query cancelled"),
+            QueryError::Io(v) => write!(f, "{v}"),
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Word(String),
+}
+
+fn tokenize(raw: &str) -> Vec<Token> {
+    fn word(chars: &mut Peekable<CharIndices>) -> String {
+        let mut word = String::new();
+        while let Some((_, c)) = chars.peek() {
+            if c.is_whitespace() || *c == '(' || *c == ')' {
+                break;
+            }
+            let opens_range = word.ends_with(':') && matches!(c, '[' | '{');
+            word.push(*c);
+            chars.next();
+            if opens_range {
+                // A range runs to its closing bracket, spaces included.
+                for (_, c) in chars.by_ref() {
+                    word.push(c);
+                    if matches!(c, ']' | '}') {
+                        break;
+                    }
+                }
+            }
+        }
+        word
+    }
+
+    let mut tokens = Vec::new();
+    let mut chars = raw.char_indices().peekable();
+    while let Some((_, c)) = chars.peek() {
+        match c {
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open)
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close)
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            _ => {
+                let w = word(&mut chars);
+                tokens.push(match w.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Word(w),
+                })
+            }
+        }
+    }
+    tokens
+}
+
+/// Parts of a query whose every term the analyzer dropped are left out of
+/// it, so they stand for `None`.
+struct QueryParser<'a, Sel: SegmentSelector> {
+    tokens: Peekable<std::vec::IntoIter<Token>>,
+    selector: &'a Sel,
+    numeric_fields: &'a [String],
+    analyzer: &'a Analyzer,
+    /// Zones the index was built with, `None` to take every zone of the selector.
+    zones: Option<&'a ZoneSet>,
+}
+
+/// `items` joined by `join`, a single one as it is.
+fn joined<S: Segments>(mut items: Vec<Query<S>>, join: fn(Vec<Query<S>>) -> Query<S>) -> Option<Query<S>> {
+    match items.len() {
+        0 => None,
+        1 => items.pop(),
+        _ => Some(join(items)),
+    }
+}
+
+impl<'a, Sel: SegmentSelector> QueryParser<'a, Sel> {
+    fn or(&mut self) -> Result<Option<Query<Sel::Segments>>, QueryError> {
+        let mut items = vec![self.and()?];
+        while self.tokens.peek() == Some(&Token::Or) {
+            self.tokens.next();
+            items.push(self.and()?);
+        }
+        Ok(joined(items.into_iter().flatten().collect(), Query::Or))
+    }
+
+    fn and(&mut self) -> Result<Option<Query<Sel::Segments>>, QueryError> {
+        let mut items = vec![self.unary()?];
+        loop {
+            match self.tokens.peek() {
+                Some(Token::And) => {
+                    self.tokens.next();
+                }
+                Some(Token::Not | Token::Open | Token::Word(_)) => {}
+                _ => break,
+            }
+            items.push(self.unary()?);
+        }
+        Ok(joined(items.into_iter().flatten().collect(), Query::And))
+    }
+
+    fn unary(&mut self) -> Result<Option<Query<Sel::Segments>>, QueryError> {
+        match self.tokens.next() {
+            Some(Token::Not) => Ok(self.unary()?.map(|v| Query::Not(Box::new(v)))),
+            Some(Token::Open) => {
+                let inner = self.or()?;
+                match self.tokens.next() {
+                    Some(Token::Close) => Ok(inner),
+                    _ => Err(QueryError::Syntax("unclosed parenthesis".to_string())),
+                }
+            }
+            Some(Token::Word(w)) => self.term(w),
+            Some(t) => Err(QueryError::Syntax(format!("unexpected {t:?}"))),
+            None => Err(QueryError::Syntax("unexpected end of query".to_string())),
+        }
+    }
+
+    fn term(&self, word: String) -> Result<Option<Query<Sel::Segments>>, QueryError> {
+        let (zone, term) = match word.split_once(':') {
+            Some((field, range)) if range.starts_with(['[', '{']) => {
+                if !self.numeric_fields.iter().any(|v| v == field) {
+                    return Err(QueryError::UnknownZone(field.to_string()));
+                }
+                let range = NumericRange::parse(range)
+                    .ok_or_else(|| QueryError::Syntax(format!("invalid range {word}")))?;
+                return Ok(Some(Query::Range {
+                    field: field.to_string(),
+                    range,
+                }));
+            }
+            Some((zone, term)) => {
+                let applier = self
+                    .selector
+                    .find_applier(zone)
+                    .filter(|_| self.zones.is_none_or(|v| v.contains(zone)))
+                    .ok_or_else(|| QueryError::UnknownZone(zone.to_string()))?;
+                let mut segments = Sel::Segments::default();
+                applier(&mut segments);
+                (Some(segments), term)
+            }
+            None => (None, word.as_str()),
+        };
+        if term.is_empty() {
+            return Err(QueryError::Syntax(format!("empty term in {word}")));
+        }
+        Ok(self.analyzer.term(term).map(|term| Query::Term { term, zone }))
+    }
+}
+
+pub fn parse_query<Sel: SegmentSelector>(
+    raw: &str,
+    selector: &Sel,
+) -> Result<Query<Sel::Segments>, QueryError> {
+    parse_query_with_fields(raw, selector, &[])
+}
+
+/// Like [`parse_query`], also accepting ranges over `numeric_fields`.
+pub fn parse_query_with_fields<Sel: SegmentSelector>(
+    raw: &str,
+    selector: &Sel,
+    numeric_fields: &[String],
+) -> Result<Query<Sel::Segments>, QueryError> {
+    parse_query_analyzed(raw, selector, numeric_fields, &Analyzer::default())
+}
+
+/// Like [`parse_query_with_fields`], with the terms made by `analyzer`
+/// rather than case folding alone. Terms it drops are left out, a query
+/// left with none fails.
+pub fn parse_query_analyzed<Sel: SegmentSelector>(
+    raw: &str,
+    selector: &Sel,
+    numeric_fields: &[String],
+    analyzer: &Analyzer,
+) -> Result<Query<Sel::Segments>, QueryError> {
+    parse_query_in_zones(raw, selector, numeric_fields, analyzer, None)
+}
+
+/// Like [`parse_query_analyzed`], also refusing a zone outside `zones`, the
+/// ones the index was built with.
+pub fn parse_query_in_zones<Sel: SegmentSelector>(
+    raw: &str,
+    selector: &Sel,
+    numeric_fields: &[String],
+    analyzer: &Analyzer,
+    zones: Option<&ZoneSet>,
+) -> Result<Query<Sel::Segments>, QueryError> {
+    let mut parser = QueryParser {
+        tokens: tokenize(raw).into_iter().peekable(),
+        selector,
+        numeric_fields,
+        analyzer,
+        zones,
+    };
+    let query = parser.or()?;
+    match parser.tokens.next() {
+        None => query.ok_or_else(|| QueryError::Syntax(format!("no term of {raw} is left after analysis"))),
+        Some(t) => Err(QueryError::Syntax(format!("unexpected {t:?}"))),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheStats {
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f64 / total as f64,
+        }
+    }
+}
+
+struct CacheInner<S: Segments> {
+    generation: u64,
+    tick: u64,
+    entries: HashMap<String, (Arc<Query<S>>, u64)>,
+    order: BTreeMap<u64, String>,
+}
+
+/// LRU of parsed queries keyed by the raw query string. Every entry belongs
+/// to the index generation it was parsed for; a different generation drops the
+/// whole cache since zone masks may no longer apply.
+pub struct QueryCache<S: Segments> {
+    capacity: usize,
+    analyzer: Analyzer,
+    zones: Option<ZoneSet>,
+    inner: Mutex<CacheInner<S>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<S: Segments> QueryCache<S> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            analyzer: Analyzer::default(),
+            zones: None,
+            inner: Mutex::new(CacheInner {
+                generation: 0,
+                tick: 0,
+                entries: HashMap::new(),
+                order: BTreeMap::new(),
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Parse the queries with `analyzer`, the one of the index they run on.
+    pub fn with_analyzer(mut self, analyzer: Analyzer) -> Self {
+        self.analyzer = analyzer;
+        self
+    }
+
+    /// Refuse zones outside `zones`, the ones of the index the queries run on.
+    pub fn with_zones(mut self, zones: ZoneSet) -> Self {
+        self.zones = Some(zones);
+        self
+    }
+
+    pub fn get<Sel: SegmentSelector<Segments = S>>(
+        &self,
+        raw: &str,
+        generation: u64,
+        selector: &Sel,
+    ) -> Result<Arc<Query<S>>, QueryError> {
+        self.lookup(raw, generation, selector).map(|(query, _)| query)
+    }
+
+    /// Like [`Self::get`], also telling whether the query came from the cache.
+    pub fn lookup<Sel: SegmentSelector<Segments = S>>(
+        &self,
+        raw: &str,
+        generation: u64,
+        selector: &Sel,
+    ) -> Result<(Arc<Query<S>>, bool), QueryError> {
+        {
+            let mut inner = self.inner.lock().unwrap();
+            if inner.generation != generation {
+                inner.generation = generation;
+                inner.entries.clear();
+                inner.order.clear();
+            }
+            inner.tick += 1;
+            let tick = inner.tick;
+            if let Some((query, last)) = inner.entries.get_mut(raw) {
+                let query = query.clone();
+                let previous = std::mem::replace(last, tick);
+                let key = inner.order.remove(&previous).unwrap();
+                inner.order.insert(tick, key);
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok((query, true));
+            }
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let query = Arc::new(parse_query_in_zones(raw, selector, &[], &self.analyzer, self.zones.as_ref())?);
+
+        let mut inner = self.inner.lock().unwrap();
+        if inner.generation == generation && self.capacity > 0 {
+            inner.tick += 1;
+            let tick = inner.tick;
+            if let Some((_, last)) = inner.entries.insert(raw.to_string(), (query.clone(), tick)) {
+                inner.order.remove(&last);
+            }
+            inner.order.insert(tick, raw.to_string());
+            while inner.entries.len() > self.capacity {
+                let (_, oldest) = inner.order.pop_first().unwrap();
+                inner.entries.remove(&oldest);
+            }
+        }
+        Ok((query, false))
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tst {
+    use std::sync::Arc;
+
+    use futures::future::join_all;
+    use tokio::task;
+
+    use crate::segment::{CommonSegmentSelector, CommonSegments};
+
+    use super::{parse_query, Query, QueryCache, QueryError};
+
+    #[test]
+    fn parses_zones_and_operators() {
+        let selector = CommonSegmentSelector::new();
+        let query = parse_query("title:Rust (async OR tokio) NOT java", &selector).unwrap();
+        match query {
+            Query::And(mut items) => {
+                assert_eq!(items.len(), 3);
+                match items.remove(0) {
+                    Query::Term { term, zone } => {
+                        assert_eq!(term, "rust");
+                        assert_eq!(zone.unwrap().into_bytes(), [0b01]);
+                    }
+                    v => panic!("{v:?}"),
+                }
+                assert!(matches!(&items[0], Query::Or(v) if v.len() == 2));
+                assert!(matches!(&items[1], Query::Not(_)));
+            }
+            v => panic!("{v:?}"),
+        }
+        assert_eq!(
+            parse_query("body:rust", &selector).unwrap_err(),
+            QueryError::UnknownZone("body".to_string())
+        );
+        assert!(parse_query("(rust", &selector).is_err());
+    }
+
+    #[test]
+    fn invalidates_on_generation() {
+        let selector = CommonSegmentSelector::new();
+        let cache = QueryCache::<CommonSegments>::new(2);
+        let first = cache.get("rust", 0, &selector).unwrap();
+        assert!(Arc::ptr_eq(&first, &cache.get("rust", 0, &selector).unwrap()));
+        assert_eq!(cache.stats().hits, 1);
+
+        let reloaded = cache.get("rust", 1, &selector).unwrap();
+        assert!(!Arc::ptr_eq(&first, &reloaded));
+        assert_eq!(cache.stats().misses, 2);
+
+        cache.get("a", 1, &selector).unwrap();
+        cache.get("rust", 1, &selector).unwrap();
+        cache.get("b", 1, &selector).unwrap();
+        assert_eq!(cache.len(), 2);
+        assert!(Arc::ptr_eq(&reloaded, &cache.get("rust", 1, &selector).unwrap()));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_handlers() {
+        let cache = Arc::new(QueryCache::<CommonSegments>::new(16));
+        let tasks = (0..8).map(|i| {
+            let cache = cache.clone();
+            task::spawn(async move {
+                let selector = CommonSegmentSelector::new();
+                for j in 0..100 {
+                    let raw = format!("text:w{} OR w{}", (i + j) % 4, j % 3);
+                    cache.get(&raw, 0, &selector).unwrap();
+                }
+            })
+        });
+        for t in join_all(tasks).await {
+            t.unwrap();
+        }
+        let stats = cache.stats();
+        assert_eq!(stats.hits + stats.misses, 800);
+        assert!(stats.hit_rate() > 0.5);
+        assert!(cache.len() <= 16);
+    }
+}
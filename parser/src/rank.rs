@@ -0,0 +1,1521 @@
+#[cfg(feature = "build")]
+use std::{cmp::Reverse, collections::BinaryHeap};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    io::{Error, ErrorKind},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use futures::future::join_all;
+use save::writer::{variable_load, variable_save_usize};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter},
+    sync::Semaphore,
+    task,
+};
+
+use crate::{
+    boost::DocumentBoosts,
+    case::{exact_case_term, fold_case},
+    doc_id::check_count,
+    indexed::{Dictionary, IndexedTerm, UsageData},
+    layout::IndexLayout,
+    metadata::IndexMetadata,
+    segment::{SegmentSelector, Segments},
+    synonym::Thesaurus,
+    warnings::MAX_TOKEN_BYTES,
+};
+
+pub(crate) fn idf(documents: usize, df: usize) -> f64 {
+    (documents.max(1) as f64 / df.max(1) as f64).ln()
+}
+
+/// Token count of every document, by document id, stored as `lengths.txt`.
+///
+/// Ids of documents dropped as malformed stay at zero, so `documents` is
+/// kept apart from the number of ids.
+///
+/// The token counts within every zone follow as one column per zone, by
+/// the same ids. A file without them, as written before they were, loads
+/// with no zones, and readers that only know the first column still read
+/// one with them.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DocumentLengths {
+    pub documents: usize,
+    pub lengths: Vec<u32>,
+    /// Zones by name with their column, in name order.
+    pub zones: Vec<(String, Vec<u32>)>,
+}
+
+impl DocumentLengths {
+    pub async fn save(&self, directory: &str) -> Result<(), Error> {
+        check_count(self.lengths.len(), "lengths")?;
+        let mut writer = BufWriter::new(File::create(IndexLayout::detect(directory).await?.lengths(directory)).await?);
+        writer.write_u64(self.documents as u64).await?;
+        writer.write_u64(self.lengths.len() as u64).await?;
+        for v in self.lengths.iter() {
+            writer.write_u32(*v).await?;
+        }
+        writer.write_u64(self.zones.len() as u64).await?;
+        for (zone, column) in self.zones.iter() {
+            if column.len() != self.lengths.len() {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("zone {zone} has {} lengths for {} ids", column.len(), self.lengths.len()),
+                ));
+            }
+            variable_save_usize(zone.len(), &mut writer).await?;
+            writer.write_all(zone.as_bytes()).await?;
+            for v in column.iter() {
+                writer.write_u32(*v).await?;
+            }
+        }
+        writer.flush().await
+    }
+
+    pub async fn load(directory: &str) -> Result<Self, Error> {
+        let mut reader = BufReader::new(File::open(IndexLayout::detect(directory).await?.lengths(directory)).await?);
+        let documents = reader.read_u64().await? as usize;
+        let len = reader.read_u64().await? as usize;
+        check_count(len, "lengths")?;
+        let mut lengths = Vec::with_capacity(len);
+        for _ in 0..len {
+            lengths.push(reader.read_u32().await?);
+        }
+        let zone_count = match reader.read_u64().await {
+            Ok(v) => v as usize,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => 0,
+            Err(e) => return Err(e),
+        };
+        let mut zones = Vec::with_capacity(zone_count);
+        for _ in 0..zone_count {
+            let mut zone = vec![0; variable_load(&mut reader).await?];
+            reader.read_exact(&mut zone).await?;
+            let zone = String::from_utf8(zone).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+            let mut column = Vec::with_capacity(len);
+            for _ in 0..len {
+                column.push(reader.read_u32().await?);
+            }
+            zones.push((zone, column));
+        }
+        Ok(Self { documents, lengths, zones })
+    }
+
+    /// Token counts within `zone`, by document id.
+    pub fn zone(&self, zone: &str) -> Option<&[u32]> {
+        self.zones
+            .iter()
+            .find(|v| v.0 == zone)
+            .map(|v| v.1.as_slice())
+    }
+
+    /// Mean token count within `zone` over the documents.
+    pub fn zone_average(&self, zone: &str) -> Option<f64> {
+        let column = self.zone(zone)?;
+        let total = column.iter().map(|v| *v as u64).sum::<u64>();
+        Some(total as f64 / self.documents.max(1) as f64)
+    }
+}
+
+/// How per-document term counts were stored, recorded in `metadata.json`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TfPolicy {
+    /// Occurrences of a term counted in one document at most.
+    pub max_tf: Option<usize>,
+    /// Counts were stored as `1 + ln tf`, rounded down.
+    pub log_scaled: bool,
+}
+
+impl TfPolicy {
+    /// Whether a term already counted `tf` times in a document counts once more.
+    pub fn counts(&self, tf: usize) -> bool {
+        self.max_tf.is_none_or(|v| tf < v)
+    }
+
+    /// The count stored for a document once it ends.
+    pub fn stored(&self, tf: usize) -> usize {
+        match self.log_scaled {
+            true => 1 + (tf.max(1) as f64).ln() as usize,
+            false => tf,
+        }
+    }
+
+    /// The tf weight a stored count stands for.
+    pub fn weight(&self, stored: usize) -> f64 {
+        match self.log_scaled {
+            true => stored.max(1) as f64,
+            false => 1.0 + (stored.max(1) as f64).ln(),
+        }
+    }
+}
+
+/// The `k` terms with the highest document frequency seen so far.
+#[cfg(feature = "build")]
+#[derive(Debug)]
+pub(crate) struct TopTerms {
+    k: usize,
+    heap: BinaryHeap<Reverse<(usize, String)>>,
+}
+
+#[cfg(feature = "build")]
+impl TopTerms {
+    pub(crate) fn new(k: usize) -> Self {
+        Self {
+            k,
+            heap: BinaryHeap::with_capacity(k + 1),
+        }
+    }
+
+    pub(crate) fn push(&mut self, term: &str, df: usize) {
+        if self.heap.len() == self.k && self.heap.peek().is_none_or(|v| v.0 .0 >= df) {
+            return;
+        }
+        self.heap.push(Reverse((df, term.to_string())));
+        if self.heap.len() > self.k {
+            self.heap.pop();
+        }
+    }
+
+    /// Terms with their document frequency, most frequent first.
+    pub(crate) fn into_sorted(self) -> Vec<(String, usize)> {
+        let mut terms = self
+            .heap
+            .into_iter()
+            .map(|Reverse((df, term))| (term, df))
+            .collect::<Vec<_>>();
+        terms.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        terms
+    }
+}
+
+/// Precomputed idf of the most frequent terms, stored as `idf_top.bin`.
+///
+/// The table is only valid for the build whose `generation` it carries.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct IdfTable {
+    generation: u64,
+    documents: usize,
+    terms: HashMap<String, (usize, f64)>,
+}
+
+impl IdfTable {
+    pub fn new(generation: u64, documents: usize, terms: Vec<(String, usize)>) -> Self {
+        Self {
+            generation,
+            documents,
+            terms: terms
+                .into_iter()
+                .map(|(term, df)| (term, (df, idf(documents, df))))
+                .collect(),
+        }
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// The same table stamped for `generation`, for a pass that rewrites
+    /// the index without changing what documents hold which terms.
+    pub fn with_generation(mut self, generation: u64) -> Self {
+        self.generation = generation;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.terms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Document frequency and idf of `term`, if it is covered.
+    pub fn get(&self, term: &str) -> Option<(usize, f64)> {
+        self.terms.get(term).copied()
+    }
+
+    pub async fn save(&self, directory: &str) -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(IndexLayout::detect(directory).await?.idf_top(directory)).await?);
+        writer.write_u64(self.generation).await?;
+        writer.write_u64(self.documents as u64).await?;
+        writer.write_u64(self.terms.len() as u64).await?;
+        for (term, (df, idf)) in self.terms.iter() {
+            variable_save_usize(term.len(), &mut writer).await?;
+            writer.write_all(term.as_bytes()).await?;
+            variable_save_usize(*df, &mut writer).await?;
+            writer.write_f64(*idf).await?;
+        }
+        writer.flush().await
+    }
+
+    pub async fn load(directory: &str) -> Result<Self, Error> {
+        let mut reader = BufReader::new(File::open(IndexLayout::detect(directory).await?.idf_top(directory)).await?);
+        let generation = reader.read_u64().await?;
+        let documents = reader.read_u64().await? as usize;
+        let len = reader.read_u64().await? as usize;
+        let mut terms = HashMap::with_capacity(len);
+        for _ in 0..len {
+            let mut term = vec![0u8; variable_load(&mut reader).await?];
+            reader.read_exact(&mut term).await?;
+            let term = String::from_utf8(term).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+            let df = variable_load(&mut reader).await?;
+            terms.insert(term, (df, reader.read_f64().await?));
+        }
+        Ok(Self {
+            generation,
+            documents,
+            terms,
+        })
+    }
+
+    /// The table of the index in `directory`, or `None` if there is none or
+    /// it was left over from an earlier build.
+    pub async fn load_current(directory: &str) -> Result<Option<Self>, Error> {
+        let table = match Self::load(directory).await {
+            Ok(v) => v,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let generation = IndexMetadata::load(directory).await?.generation;
+        if table.generation != generation {
+            log::info!(
+                "Ignoring idf table of generation {} for index generation {}",
+                table.generation,
+                generation
+            );
+            return Ok(None);
+        }
+        Ok(Some(table))
+    }
+}
+
+/// One step of a score, with the values it was computed from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Explanation {
+    pub description: String,
+    pub value: f64,
+    pub details: Vec<Explanation>,
+}
+
+impl Explanation {
+    fn leaf(description: String, value: f64) -> Self {
+        Self {
+            description,
+            value,
+            details: vec![],
+        }
+    }
+
+    fn write(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
+        writeln!(f, "{:indent$}{:.6} {}", "", self.value, self.description, indent = depth * 2)?;
+        for v in self.details.iter() {
+            v.write(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Explanation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+/// How [`Scorer::search_concurrent`] spreads the work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Concurrency {
+    /// Terms decoded and scored at the same time.
+    pub max_tasks: usize,
+    /// Candidates, as summed document frequencies, scored concurrently at
+    /// most; larger queries go term at a time.
+    pub max_candidates: usize,
+    /// Partitions of the score map, by document id.
+    pub shards: usize,
+}
+
+impl Default for Concurrency {
+    fn default() -> Self {
+        Self {
+            max_tasks: 4,
+            max_candidates: 1_000_000,
+            shards: 16,
+        }
+    }
+}
+
+/// What [`Scorer::search_top`] did with the postings of the query terms.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TopStats {
+    /// Postings scored into a candidate.
+    pub scored: usize,
+    /// Postings of documents that had already been ruled out of the top.
+    pub skipped: usize,
+}
+
+/// Relative margin kept on every pruning comparison, for scores summed in
+/// another order than [`Scorer::search`] sums them.
+const PRUNE_MARGIN: f64 = 1e-9;
+
+/// Scores by document, one slot per query term.
+type Shard = Mutex<HashMap<usize, Vec<f64>>>;
+
+/// Parameters of [`Scorer::with_bm25f`], with the length column and mean
+/// length of every weighted zone in the order of its weights.
+struct Bm25f {
+    k1: f64,
+    b: f64,
+    zones: Vec<(usize, f64)>,
+}
+
+/// tf-idf ranking over zones.
+///
+/// A term contributes `(1 + ln tf) * ln(N / df) * w * 1 / sqrt(len)` to a
+/// document, where `w` sums the weights of the zones the term occurs in and
+/// `len` is the document's token count. With [`Scorer::with_zone_idf`] every
+/// zone's weight is multiplied by the idf within that zone instead, and
+/// [`Scorer::with_bm25f`] scores BM25F over the zone lengths.
+pub struct Scorer<S: Segments> {
+    weights: Vec<(String, S, f64)>,
+    lengths: DocumentLengths,
+    /// Documents having each weighted zone, in the order of `weights`.
+    zone_documents: Option<Vec<usize>>,
+    bm25f: Option<Bm25f>,
+    table: Option<IdfTable>,
+    table_hits: AtomicUsize,
+    tf: TfPolicy,
+    boosts: Option<DocumentBoosts>,
+    exact_case: Option<f64>,
+    synonyms: Option<(Thesaurus, f64)>,
+    max_term_bytes: usize,
+    running: AtomicUsize,
+    peak_tasks: AtomicUsize,
+}
+
+impl<S: Segments> Scorer<S> {
+    pub fn new<Sel: SegmentSelector<Segments = S>>(
+        selector: &Sel,
+        weights: &[(&str, f64)],
+        lengths: DocumentLengths,
+    ) -> Result<Self, Error> {
+        let weights = weights
+            .iter()
+            .map(|(zone, weight)| {
+                let applier = selector.find_applier(zone).ok_or_else(|| {
+                    Error::new(ErrorKind::InvalidInput, format!("unknown zone {zone}"))
+                })?;
+                let mut mask = S::default();
+                applier(&mut mask);
+                Ok((zone.to_string(), mask, *weight))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(Self {
+            weights,
+            lengths,
+            zone_documents: None,
+            bm25f: None,
+            table: None,
+            table_hits: AtomicUsize::new(0),
+            tf: TfPolicy::default(),
+            boosts: None,
+            exact_case: None,
+            synonyms: None,
+            max_term_bytes: MAX_TOKEN_BYTES,
+            running: AtomicUsize::new(0),
+            peak_tasks: AtomicUsize::new(0),
+        })
+    }
+
+    /// Multiplies the score of every document by its static boost.
+    pub fn with_boosts(mut self, boosts: DocumentBoosts) -> Self {
+        self.boosts = Some(boosts);
+        self
+    }
+
+    fn boost(&self, document: usize) -> f64 {
+        self.boosts.as_ref().map_or(1.0, |v| v.get(document))
+    }
+
+    /// Reads stored counts the way the index was built, see [`IndexMetadata::tf`].
+    pub fn with_tf_policy(mut self, tf: TfPolicy) -> Self {
+        self.tf = tf;
+        self
+    }
+
+    /// Takes idf of the terms `table` covers from it rather than from their postings.
+    pub fn with_idf_table(mut self, table: IdfTable) -> Self {
+        self.table = Some(table);
+        self
+    }
+
+    /// Weighs a term in every zone by its idf among the documents having
+    /// that zone, `ln(N_zone / df_zone)`, as [`Dictionary::zone_document_count`]
+    /// has them. A zone the index didn't count, as in one built before they
+    /// were, falls back to all documents with a warning.
+    pub async fn with_zone_idf(mut self, dictionary: &Dictionary<S>) -> Result<Self, Error> {
+        if self.bm25f.is_some() {
+            return Err(Error::new(ErrorKind::InvalidInput, "zone idf can't be used with BM25F"));
+        }
+        let mut documents = Vec::with_capacity(self.weights.len());
+        for (zone, _, _) in self.weights.iter() {
+            documents.push(match dictionary.zone_document_count(zone).await? {
+                Some(v) => v,
+                None => {
+                    log::warn!(
+                        "{} has no document count of zone {zone}, taking all {} documents",
+                        dictionary.directory(),
+                        self.lengths.documents
+                    );
+                    self.lengths.documents
+                }
+            });
+        }
+        self.zone_documents = Some(documents);
+        Ok(self)
+    }
+
+    /// Scores BM25F instead of tf-idf: a term contributes
+    /// `ln(N / df) * tf' / (k1 + tf')` to a document, where `tf'` sums
+    /// `w * tf_zone / (1 - b + b * len_zone / avg_zone)` over the weighted
+    /// zones the term occurs in.
+    ///
+    /// Postings keep one count per document, so `tf_zone` is that count
+    /// shared out between those zones by their lengths in the document.
+    /// Every weighted zone needs its lengths, which indexes built before
+    /// they were stored don't have.
+    pub fn with_bm25f(mut self, k1: f64, b: f64) -> Result<Self, Error> {
+        if self.zone_documents.is_some() {
+            return Err(Error::new(ErrorKind::InvalidInput, "zone idf can't be used with BM25F"));
+        }
+        if !(k1 >= 0.0 && (0.0..=1.0).contains(&b)) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("BM25F needs k1 >= 0 and b in [0, 1], not k1 = {k1}, b = {b}"),
+            ));
+        }
+        let zones = self
+            .weights
+            .iter()
+            .map(|(zone, _, _)| {
+                let column = self.lengths.zones.iter().position(|v| v.0 == *zone).ok_or_else(|| {
+                    Error::new(ErrorKind::NotFound, format!("the index has no lengths of zone {zone}"))
+                })?;
+                Ok((column, self.lengths.zone_average(zone).unwrap_or(0.0)))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        self.bm25f = Some(Bm25f { k1, b, zones });
+        Ok(self)
+    }
+
+    /// The BM25F contribution of a term counted `tf` times in `document`
+    /// within the weighted zones `zones`, see [`Self::with_bm25f`].
+    fn weigh_bm25f(
+        &self,
+        bm25f: &Bm25f,
+        term: &str,
+        (df, idf): (usize, f64),
+        zones: &[(usize, &(String, S, f64))],
+        (document, tf): (usize, usize),
+        explain: bool,
+    ) -> (f64, Option<Explanation>) {
+        let length = |i: usize| {
+            self.lengths.zones[bm25f.zones[i].0]
+                .1
+                .get(document)
+                .copied()
+                .unwrap_or(0) as f64
+        };
+        let total = zones.iter().map(|(i, _)| length(*i)).sum::<f64>();
+        let parts = zones
+            .iter()
+            .map(|(i, (zone, _, w))| {
+                let (len, avg) = (length(*i), bm25f.zones[*i].1);
+                let share = match total > 0.0 {
+                    true => tf as f64 * len / total,
+                    false => tf as f64 / zones.len() as f64,
+                };
+                let norm = 1.0 - bm25f.b + bm25f.b * len / avg.max(f64::MIN_POSITIVE);
+                (zone, *w, share, len, avg, norm)
+            })
+            .collect::<Vec<_>>();
+        let pseudo_tf = parts.iter().map(|(_, w, share, _, _, norm)| w * share / norm).sum::<f64>();
+        let saturation = pseudo_tf / (bm25f.k1 + pseudo_tf);
+        let value = idf * saturation;
+        if !explain {
+            return (value, None);
+        }
+        let explanation = Explanation {
+            description: format!("term {term}"),
+            value,
+            details: vec![
+                Explanation::leaf(format!("idf, ln({} / {df})", self.lengths.documents), idf),
+                Explanation {
+                    description: format!("tf saturation, tf' / ({} + tf')", bm25f.k1),
+                    value: saturation,
+                    details: vec![Explanation {
+                        description: format!("tf', {tf} occurrences over the zones"),
+                        value: pseudo_tf,
+                        details: parts
+                            .iter()
+                            .map(|(zone, w, share, len, avg, norm)| {
+                                Explanation::leaf(
+                                    format!(
+                                        "zone {zone}, {w} * {share:.6} / (1 - {b} + {b} * {len} / {avg:.6})",
+                                        b = bm25f.b
+                                    ),
+                                    w * share / norm,
+                                )
+                            })
+                            .collect(),
+                    }],
+                },
+            ],
+        };
+        (value, Some(explanation))
+    }
+
+    /// Document frequency and idf of a term in every weighted zone, when
+    /// scoring [`Self::with_zone_idf`].
+    fn zone_idf(&self, found: &IndexedTerm<S>) -> Option<Vec<(usize, f64)>> {
+        let documents = self.zone_documents.as_ref()?;
+        let mut df = vec![0; self.weights.len()];
+        for (_, usage) in found.indexes.iter_ref() {
+            for (i, (_, mask, _)) in self.weights.iter().enumerate() {
+                if usage.segments().intersects(mask) {
+                    df[i] += 1;
+                }
+            }
+        }
+        Some(documents.iter().zip(df).map(|(n, df)| (df, idf(*n, df))).collect())
+    }
+
+    /// Looks query terms up case-folded and multiplies the score of a
+    /// document by `boost` for every term it spells exactly as asked. Only
+    /// terms with capitals can match exactly, and only in an index built
+    /// [`crate::indexed::IndexedBuilder::with_case_preserving`].
+    pub fn with_exact_case(mut self, boost: f64) -> Self {
+        self.exact_case = Some(boost);
+        self
+    }
+
+    /// Scores a query term by its best match among itself and its synonyms,
+    /// a synonym counting `discount` times what the term would. The
+    /// thesaurus is keyed by the terms as the dictionary is asked for them.
+    pub fn with_synonyms(mut self, thesaurus: Thesaurus, discount: f64) -> Self {
+        self.synonyms = Some((thesaurus, discount));
+        self
+    }
+
+    /// Query terms longer than `bytes` match nothing and are left out of the
+    /// search with a warning, [`MAX_TOKEN_BYTES`] unless set; see
+    /// [`IndexMetadata::token_limit`].
+    pub fn with_max_term_bytes(mut self, bytes: usize) -> Self {
+        self.max_term_bytes = bytes;
+        self
+    }
+
+    pub fn max_term_bytes(&self) -> usize {
+        self.max_term_bytes
+    }
+
+    /// A looked up term with its synonyms, each with the factor it scores by.
+    fn group<'a>(&'a self, term: &'a str) -> Vec<(&'a str, f64)> {
+        let mut group = vec![(term, 1.0)];
+        if let Some((thesaurus, discount)) = self.synonyms.as_ref() {
+            group.extend(thesaurus.synonyms(term).iter().map(|v| (v.as_str(), *discount)));
+        }
+        group
+    }
+
+    /// Terms as the dictionary is asked for them, without those over
+    /// [`Self::max_term_bytes`].
+    pub(crate) fn lookup_terms(&self, terms: &[&str]) -> Vec<String> {
+        terms
+            .iter()
+            .filter(|v| {
+                let fits = v.len() <= self.max_term_bytes;
+                if !fits {
+                    log::warn!("query term of {} bytes is longer than any indexed, left out", v.len());
+                }
+                fits
+            })
+            .map(|v| match self.exact_case {
+                Some(_) => fold_case(v).into_owned(),
+                None => v.to_string(),
+            })
+            .collect()
+    }
+
+    /// Exact-case factor of every document spelling one of `terms` as given.
+    async fn exact_case_boosts(
+        &self,
+        dictionary: &mut Dictionary<S>,
+        terms: &[&str],
+    ) -> Result<HashMap<usize, f64>, Error> {
+        let mut boosts = HashMap::new();
+        let Some(boost) = self.exact_case else {
+            return Ok(boosts);
+        };
+        for term in terms {
+            if fold_case(term) == *term || term.len() > self.max_term_bytes {
+                continue;
+            }
+            let Some(found) = dictionary.find(&exact_case_term(term)).await? else {
+                continue;
+            };
+            for (document, _) in found.indexes.iter() {
+                *boosts.entry(document).or_insert(1.0) *= boost;
+            }
+        }
+        Ok(boosts)
+    }
+
+    /// How many times an idf was answered by the table.
+    pub fn table_hits(&self) -> usize {
+        self.table_hits.load(Ordering::Relaxed)
+    }
+
+    /// Most scoring tasks [`Self::search_concurrent`] had running at once.
+    pub fn peak_tasks(&self) -> usize {
+        self.peak_tasks.load(Ordering::Relaxed)
+    }
+
+    fn cached_idf(&self, term: &str) -> Option<(usize, f64)> {
+        let found = self.table.as_ref()?.get(term)?;
+        self.table_hits.fetch_add(1, Ordering::Relaxed);
+        Some(found)
+    }
+
+    /// Document frequency and idf of `term`, from the table when it covers
+    /// the term and otherwise from a dictionary lookup.
+    pub async fn idf_of(
+        &self,
+        dictionary: &mut Dictionary<S>,
+        term: &str,
+    ) -> Result<(usize, f64), Error> {
+        if let Some(found) = self.cached_idf(term) {
+            return Ok(found);
+        }
+        let df = dictionary.find(term).await?.map_or(0, |v| v.indexes.len());
+        Ok((df, idf(self.lengths.documents, df)))
+    }
+
+    fn term_idf(&self, term: &str, df: usize) -> f64 {
+        self.cached_idf(term)
+            .map_or_else(|| idf(self.lengths.documents, df), |v| v.1)
+    }
+
+    /// The contribution of one posting; the breakdown is only assembled when `explain` is set.
+    /// `zone_idf` is the term's [`Self::zone_idf`], which takes the place of `idf`.
+    fn weigh(
+        &self,
+        term: &str,
+        (df, idf): (usize, f64),
+        zone_idf: Option<&[(usize, f64)]>,
+        document: usize,
+        usage: &mut UsageData<S>,
+        explain: bool,
+    ) -> (f64, Option<Explanation>) {
+        let tf = *usage.use_count_mut();
+        let tf_weight = self.tf.weight(tf);
+        let zones = self
+            .weights
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, mask, _))| usage.segments_mut().intersects(mask))
+            .collect::<Vec<_>>();
+        if let Some(bm25f) = self.bm25f.as_ref() {
+            return self.weigh_bm25f(bm25f, term, (df, idf), &zones, (document, tf), explain);
+        }
+        let zone_weight = match zone_idf {
+            Some(zone_idf) => zones.iter().map(|(i, (_, _, w))| w * zone_idf[*i].1).sum::<f64>(),
+            None => zones.iter().map(|(_, (_, _, w))| w).sum::<f64>(),
+        };
+        let length = self.lengths.lengths.get(document).copied().unwrap_or(0);
+        let norm = 1.0 / (length.max(1) as f64).sqrt();
+        let value = match zone_idf {
+            Some(_) => tf_weight * zone_weight * norm,
+            None => tf_weight * idf * zone_weight * norm,
+        };
+        if !explain {
+            return (value, None);
+        }
+        let tf_leaf = Explanation::leaf(
+            match self.tf.log_scaled {
+                true => format!("tf weight, stored as 1 + ln tf = {tf}"),
+                false => format!("tf weight, 1 + ln({tf})"),
+            },
+            tf_weight,
+        );
+        let norm_leaf = Explanation::leaf(format!("length norm, 1 / sqrt({length})"), norm);
+        let details = match (zone_idf, self.zone_documents.as_ref()) {
+            (Some(zone_idf), Some(documents)) => vec![
+                tf_leaf,
+                Explanation {
+                    description: "zone weight by idf in the zone".to_string(),
+                    value: zone_weight,
+                    details: zones
+                        .iter()
+                        .map(|(i, (zone, _, w))| {
+                            let (df, idf) = zone_idf[*i];
+                            Explanation {
+                                description: format!("zone {zone}"),
+                                value: w * idf,
+                                details: vec![
+                                    Explanation::leaf("weight".to_string(), *w),
+                                    Explanation::leaf(format!("idf, ln({} / {df})", documents[*i]), idf),
+                                ],
+                            }
+                        })
+                        .collect(),
+                },
+                norm_leaf,
+            ],
+            _ => vec![
+                tf_leaf,
+                Explanation::leaf(
+                    format!("idf, ln({} / {df})", self.lengths.documents),
+                    idf,
+                ),
+                Explanation {
+                    description: "zone weight".to_string(),
+                    value: zone_weight,
+                    details: zones
+                        .iter()
+                        .map(|(_, (zone, _, w))| Explanation::leaf(format!("zone {zone}"), *w))
+                        .collect(),
+                },
+                norm_leaf,
+            ],
+        };
+        let explanation = Explanation {
+            description: format!("term {term}"),
+            value,
+            details,
+        };
+        (value, Some(explanation))
+    }
+
+    /// Documents containing any of `terms`, best first.
+    pub async fn search(
+        &self,
+        dictionary: &mut Dictionary<S>,
+        terms: &[&str],
+    ) -> Result<Vec<(usize, f64)>, Error> {
+        let mut scores = HashMap::<usize, f64>::new();
+        for term in self.lookup_terms(terms).iter() {
+            for (document, value) in self.term_scores(dictionary, term).await? {
+                *scores.entry(document).or_default() += value;
+            }
+        }
+        self.finish(dictionary, terms, scores.into_iter().collect()).await
+    }
+
+    /// What a looked up term adds to every document it or a synonym is in,
+    /// by document id.
+    pub(crate) async fn term_scores(
+        &self,
+        dictionary: &mut Dictionary<S>,
+        term: &str,
+    ) -> Result<Vec<(usize, f64)>, Error> {
+        let mut best = HashMap::<usize, f64>::new();
+        for (member, factor) in self.group(term) {
+            let Some(found) = dictionary.find(member).await? else {
+                continue;
+            };
+            let df = found.indexes.len();
+            let idf = self.term_idf(member, df);
+            let zone_idf = self.zone_idf(&found);
+            for (document, mut usage) in found.indexes.iter() {
+                let (value, _) = self.weigh(member, (df, idf), zone_idf.as_deref(), document, &mut usage, false);
+                let best = best.entry(document).or_default();
+                *best = best.max(value * factor);
+            }
+        }
+        let mut best = best.into_iter().collect::<Vec<_>>();
+        best.sort_unstable_by_key(|v| v.0);
+        Ok(best)
+    }
+
+    /// Summed term `scores` with the boosts applied, best first.
+    pub(crate) async fn finish(
+        &self,
+        dictionary: &mut Dictionary<S>,
+        terms: &[&str],
+        scores: Vec<(usize, f64)>,
+    ) -> Result<Vec<(usize, f64)>, Error> {
+        let exact = self.exact_case_boosts(dictionary, terms).await?;
+        let mut scores = scores
+            .into_iter()
+            .map(|(document, score)| {
+                let exact = exact.get(&document).copied().unwrap_or(1.0);
+                (document, score * self.boost(document) * exact)
+            })
+            .collect::<Vec<_>>();
+        scores.sort_unstable_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        Ok(scores)
+    }
+
+    /// The first `k` of [`Self::search`], with documents that can't make
+    /// them left unscored.
+    ///
+    /// Terms are scored highest [`Dictionary::max_tf`] bound first. Once
+    /// what is left of the query can't lift a new document to the `k`th
+    /// score found so far, no new documents are taken, and candidates that
+    /// can't reach it any more are dropped. The survivors keep one score per
+    /// term and are summed in term order at the end, the way the exhaustive
+    /// search sums them.
+    ///
+    /// Without bounds in the index, or with synonyms, zone idf, BM25F or
+    /// negative weights, boosts or idf, every document is scored.
+    pub async fn search_top(
+        &self,
+        dictionary: &mut Dictionary<S>,
+        terms: &[&str],
+        k: usize,
+    ) -> Result<(Vec<(usize, f64)>, TopStats), Error> {
+        let lookup = self.lookup_terms(terms);
+        let negative = self.weights.iter().any(|v| v.2 < 0.0)
+            || self.exact_case.is_some_and(|v| v < 0.0)
+            || self.boosts.as_ref().is_some_and(|v| v.boosts.iter().any(|v| *v < 0.0));
+        let mut postings = Vec::with_capacity(lookup.len());
+        let mut exhaustive =
+            negative || self.synonyms.is_some() || self.zone_documents.is_some() || self.bm25f.is_some();
+        for (slot, term) in lookup.iter().enumerate() {
+            if exhaustive {
+                break;
+            }
+            let Some(found) = dictionary.find(term).await? else {
+                continue;
+            };
+            let max_tf = match dictionary.max_tf(term).await {
+                Ok(v) => v,
+                Err(e) if e.kind() == ErrorKind::NotFound => None,
+                Err(e) => return Err(e),
+            };
+            let df = found.indexes.len();
+            let idf = self.term_idf(term, df);
+            match max_tf {
+                Some(max_tf) if idf >= 0.0 => postings.push((slot, found, (df, idf), self.bound(max_tf, idf))),
+                _ => exhaustive = true,
+            }
+        }
+        if exhaustive {
+            let mut stats = TopStats::default();
+            for term in lookup.iter() {
+                stats.scored += dictionary.document_frequency(term).await?.unwrap_or(0);
+            }
+            let mut ranked = self.search(dictionary, terms).await?;
+            ranked.truncate(k);
+            return Ok((ranked, stats));
+        }
+
+        let exact = self.exact_case_boosts(dictionary, terms).await?;
+        let factor = |document: usize| self.boost(document) * exact.get(&document).copied().unwrap_or(1.0);
+        let max_factor = self.boosts.as_ref().map_or(1.0, |v| v.boosts.iter().fold(1.0, |m, v| f64::max(m, *v as f64)))
+            * exact.values().fold(1.0, |m, v| f64::max(m, *v));
+        postings.sort_by(|a, b| b.3.total_cmp(&a.3));
+        let bounds = postings.iter().map(|v| v.3).collect::<Vec<_>>();
+        let mut stats = TopStats::default();
+        let mut candidates = HashMap::<usize, (f64, Vec<f64>)>::new();
+        let mut admitting = k > 0;
+        for (i, (slot, found, (df, idf), _)) in postings.into_iter().enumerate() {
+            for (document, mut usage) in found.indexes.iter() {
+                let candidate = match candidates.get_mut(&document) {
+                    Some(v) => v,
+                    None if admitting => candidates.entry(document).or_insert_with(|| (0.0, vec![0.0; lookup.len()])),
+                    None => {
+                        stats.skipped += 1;
+                        continue;
+                    }
+                };
+                let (value, _) = self.weigh(&lookup[slot], (df, idf), None, document, &mut usage, false);
+                candidate.0 += value;
+                candidate.1[slot] += value;
+                stats.scored += 1;
+            }
+            if k == 0 || candidates.len() < k {
+                continue;
+            }
+            let mut lower = candidates.iter().map(|(d, v)| v.0 * factor(*d)).collect::<Vec<_>>();
+            let threshold = *lower.select_nth_unstable_by(k - 1, |a, b| b.total_cmp(a)).1 * (1.0 - PRUNE_MARGIN);
+            let rest = bounds[i + 1..].iter().sum::<f64>();
+            admitting = admitting && rest * max_factor >= threshold;
+            if !admitting {
+                candidates.retain(|d, v| (v.0 + rest) * factor(*d) >= threshold);
+            }
+        }
+
+        let mut ranked = candidates
+            .into_iter()
+            .map(|(document, (_, slots))| {
+                let score = slots.into_iter().fold(0.0, |sum, v| sum + v);
+                (document, score * factor(document))
+            })
+            .collect::<Vec<_>>();
+        ranked.sort_unstable_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        ranked.truncate(k);
+        Ok((ranked, stats))
+    }
+
+    /// The most a term counted at most `max_tf` times in a document can add
+    /// to a score before boosts: the highest tf weight, every positive zone
+    /// weight and the norm of the shortest document.
+    fn bound(&self, max_tf: usize, idf: f64) -> f64 {
+        let weights = self.weights.iter().map(|v| v.2.max(0.0)).sum::<f64>();
+        let shortest = match self.lengths.lengths.len() >= self.lengths.documents {
+            true => self.lengths.lengths.iter().filter(|v| **v > 0).min().copied().unwrap_or(1),
+            false => 1,
+        };
+        self.tf.weight(max_tf) * idf * weights * (1.0 / (shortest as f64).sqrt())
+    }
+
+    /// Same as [`Self::search`], with the postings of every term decoded and
+    /// scored by its own task, `concurrency.max_tasks` at a time.
+    ///
+    /// Documents keep one score per term, summed in term order, so the
+    /// result matches the serial one exactly. With synonyms the query is
+    /// scored serially.
+    pub async fn search_concurrent(
+        self: &Arc<Self>,
+        dictionary: &mut Dictionary<S>,
+        terms: &[&str],
+        concurrency: Concurrency,
+    ) -> Result<Vec<(usize, f64)>, Error>
+    where
+        S: 'static,
+    {
+        let lookup = self.lookup_terms(terms);
+        let mut candidates = 0;
+        for term in lookup.iter() {
+            candidates += dictionary.document_frequency(term).await?.unwrap_or(0);
+        }
+        if candidates > concurrency.max_candidates {
+            log::debug!("{candidates} candidates over budget, scoring term at a time");
+            return self.search(dictionary, terms).await;
+        }
+        if self.synonyms.is_some() {
+            return self.search(dictionary, terms).await;
+        }
+
+        let shards = Arc::new(
+            (0..concurrency.shards.max(1))
+                .map(|_| Shard::default())
+                .collect::<Vec<_>>(),
+        );
+        let permits = Arc::new(Semaphore::new(concurrency.max_tasks.max(1)));
+        let mut tasks = Vec::with_capacity(terms.len());
+        for (i, term) in lookup.iter().enumerate() {
+            let (scorer, shards, permits) = (self.clone(), shards.clone(), permits.clone());
+            let (term, directory, count) = (term.to_string(), dictionary.directory().clone(), terms.len());
+            tasks.push(task::spawn(async move {
+                let _permit = permits.acquire_owned().await.unwrap();
+                let running = scorer.running.fetch_add(1, Ordering::Relaxed) + 1;
+                scorer.peak_tasks.fetch_max(running, Ordering::Relaxed);
+                let scored = scorer.score_term(&directory, &term, i, count, &shards).await;
+                scorer.running.fetch_sub(1, Ordering::Relaxed);
+                scored
+            }));
+        }
+        for scored in join_all(tasks).await {
+            scored.map_err(Error::other)??;
+        }
+        let exact = self.exact_case_boosts(dictionary, terms).await?;
+
+        let shards = Arc::try_unwrap(shards).ok().unwrap();
+        let mut scores = shards
+            .into_iter()
+            .flat_map(|v| v.into_inner().unwrap())
+            .map(|(document, slots)| {
+                let score = slots.into_iter().fold(0.0, |sum, v| sum + v);
+                let exact = exact.get(&document).copied().unwrap_or(1.0);
+                (document, score * self.boost(document) * exact)
+            })
+            .collect::<Vec<_>>();
+        scores.sort_unstable_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        Ok(scores)
+    }
+
+    /// Scores the postings of the `slot`th of `count` terms into `shards`.
+    async fn score_term(
+        &self,
+        directory: &String,
+        term: &str,
+        slot: usize,
+        count: usize,
+        shards: &[Shard],
+    ) -> Result<(), Error> {
+        let mut dictionary = Dictionary::<S>::new(directory).await?;
+        let Some(found) = dictionary.find(term).await? else {
+            return Ok(());
+        };
+        let df = found.indexes.len();
+        let idf = self.term_idf(term, df);
+        let zone_idf = self.zone_idf(&found);
+        let mut partitions = vec![Vec::new(); shards.len()];
+        for (document, mut usage) in found.indexes.iter() {
+            let (value, _) = self.weigh(term, (df, idf), zone_idf.as_deref(), document, &mut usage, false);
+            partitions[document % shards.len()].push((document, value));
+        }
+        for (shard, partition) in shards.iter().zip(partitions) {
+            let mut shard = shard.lock().unwrap();
+            for (document, value) in partition {
+                shard.entry(document).or_insert_with(|| vec![0.0; count])[slot] += value;
+            }
+        }
+        Ok(())
+    }
+
+    /// How [`Self::search`] scored `document`.
+    pub async fn search_explain(
+        &self,
+        dictionary: &mut Dictionary<S>,
+        terms: &[&str],
+        document: usize,
+    ) -> Result<Explanation, Error> {
+        let exact = self
+            .exact_case_boosts(dictionary, terms)
+            .await?
+            .get(&document)
+            .copied()
+            .unwrap_or(1.0);
+        let mut details = Vec::new();
+        for term in self.lookup_terms(terms).iter() {
+            let mut indexed = false;
+            let mut explained: Option<Explanation> = None;
+            for (member, factor) in self.group(term) {
+                let Some(found) = dictionary.find(member).await? else {
+                    continue;
+                };
+                indexed = true;
+                let df = found.indexes.len();
+                let idf = self.term_idf(member, df);
+                let zone_idf = self.zone_idf(&found);
+                for (v, mut usage) in found.indexes.iter() {
+                    if v != document {
+                        continue;
+                    }
+                    let Some(mut found) =
+                        self.weigh(member, (df, idf), zone_idf.as_deref(), document, &mut usage, true).1
+                    else {
+                        break;
+                    };
+                    if member != term {
+                        found = Explanation {
+                            description: format!("synonym {member} of {term}"),
+                            value: found.value * factor,
+                            details: vec![found, Explanation::leaf("synonym discount".to_string(), factor)],
+                        };
+                    }
+                    if explained.as_ref().is_none_or(|v| found.value > v.value) {
+                        explained = Some(found);
+                    }
+                    break;
+                }
+            }
+            details.push(explained.unwrap_or_else(|| match indexed {
+                true => Explanation::leaf(format!("term {term}, not in document"), 0.0),
+                false => Explanation::leaf(format!("term {term}, not indexed"), 0.0),
+            }));
+        }
+        let terms = Explanation {
+            description: format!("score of document {document}"),
+            value: details.iter().map(|v| v.value).sum(),
+            details,
+        };
+        let boost = self.boost(document);
+        if boost == 1.0 && exact == 1.0 {
+            return Ok(terms);
+        }
+        let value = terms.value * boost * exact;
+        let mut details = vec![terms];
+        if boost != 1.0 {
+            details.push(Explanation::leaf("document boost".to_string(), boost));
+        }
+        if exact != 1.0 {
+            details.push(Explanation::leaf("exact case boost".to_string(), exact));
+        }
+        Ok(Explanation {
+            description: format!("boosted score of document {document}"),
+            value,
+            details,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tst {
+    use std::{io::Error, sync::Arc};
+
+    use std::path::PathBuf;
+
+    use tokio::fs;
+
+    use crate::{
+        config::IndexerConfig,
+        indexed::{Dictionary, IndexMerger, IndexParser, IndexedBuilder},
+        layout::IndexLayout,
+        metadata::IndexMetadata,
+        parser::ParseController,
+        segment::{CommonSegmentSelector, CommonSegments},
+        testsupport::{scratch, word, CorpusSpec},
+        zones::ZoneSet,
+    };
+
+    use super::{Concurrency, DocumentLengths, Explanation, IdfTable, Scorer};
+
+    async fn fixture(name: &str, merger: fn(IndexMerger) -> IndexMerger) -> Result<(PathBuf, String), Error> {
+        let root = std::env::temp_dir().join(format!("{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root).await;
+        fs::create_dir_all(&root).await?;
+        let path = root.join("0.xml").to_str().unwrap().to_string();
+        let documents = [
+            ("rust async", "tokio runtime for rust with async io"),
+            ("java", "threads and a runtime"),
+            ("tokio", "rust rust rust"),
+            ("python", "asyncio"),
+        ];
+        let content = documents
+            .iter()
+            .map(|(title, text)| format!("<title>\n{title}\n</title>\n<text>\n{text}\n</text>\n"))
+            .collect::<String>();
+        fs::write(&path, content).await?;
+
+        let destination = root.join("res").to_str().unwrap().to_string();
+        let config = IndexerConfig::new(1000, 6)?;
+        ParseController::<IndexParser, _, _>::new(
+            vec![path],
+            destination.clone(),
+            root.join("buffer").to_str().unwrap().to_string(),
+            1,
+            IndexedBuilder::new(config, ZoneSet::new(["title", "text"])?)?,
+            merger(IndexMerger::new(config.merger())),
+        )
+        .create_dictionary()
+        .await?;
+        Ok((root, destination))
+    }
+
+    #[tokio::test]
+    async fn explanation_sums_to_score() -> Result<(), Error> {
+        let (root, destination) = fixture("rank", |v| v).await?;
+        let lengths = DocumentLengths::load(&destination).await?;
+        assert_eq!(lengths.documents, 4);
+        assert_eq!(lengths.lengths[..4], [9, 5, 4, 2]);
+        let scorer = Scorer::new(
+            &CommonSegmentSelector::new(),
+            &[("title", 2.0), ("text", 1.0)],
+            lengths,
+        )?;
+        let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+        let terms = ["rust", "runtime", "missing"];
+        let ranked = scorer.search(&mut dictionary, &terms).await?;
+        assert_eq!(
+            ranked.iter().map(|v| v.0).collect::<Vec<_>>(),
+            [0, 2, 1]
+        );
+
+        for (document, score) in ranked {
+            let explanation = scorer
+                .search_explain(&mut dictionary, &terms, document)
+                .await?;
+            assert!((explanation.value - score).abs() < 1e-9);
+            let sum = explanation.details.iter().map(|v| v.value).sum::<f64>();
+            assert!((sum - score).abs() < 1e-9);
+            for term in explanation.details.iter().filter(|v| !v.details.is_empty()) {
+                let product = term.details.iter().map(|v| v.value).product::<f64>();
+                assert!((product - term.value).abs() < 1e-9);
+            }
+            let text = explanation.to_string();
+            assert!(text.starts_with(&format!("{:.6} score of document {document}", score)));
+            let json = serde_json::to_string(&explanation).unwrap();
+            let parsed = serde_json::from_str::<Explanation>(&json).unwrap();
+            assert_eq!(parsed.details.len(), explanation.details.len());
+            assert!((parsed.value - explanation.value).abs() < 1e-9);
+        }
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+    #[tokio::test]
+    async fn idf_table_gives_same_scores() -> Result<(), Error> {
+        let (root, destination) = fixture("idf_top", |v| v.with_idf_top(2)).await?;
+        let table = IdfTable::load_current(&destination).await?.unwrap();
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.get("rust"), Some((2, 2f64.ln())));
+        assert_eq!(table.get("runtime"), Some((2, 2f64.ln())));
+        assert_eq!(table.get("tokio"), None);
+
+        let selector = CommonSegmentSelector::new();
+        let weights = [("title", 2.0), ("text", 1.0)];
+        let plain = Scorer::new(&selector, &weights, DocumentLengths::load(&destination).await?)?;
+        let cached = Scorer::new(&selector, &weights, DocumentLengths::load(&destination).await?)?
+            .with_idf_table(table.clone());
+        let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+        let terms = ["rust", "runtime", "tokio", "missing"];
+        assert_eq!(
+            plain.search(&mut dictionary, &terms).await?,
+            cached.search(&mut dictionary, &terms).await?
+        );
+        assert_eq!((plain.table_hits(), cached.table_hits()), (0, 2));
+        assert_eq!(
+            cached.idf_of(&mut dictionary, "tokio").await?,
+            plain.idf_of(&mut dictionary, "tokio").await?
+        );
+        assert_eq!(cached.idf_of(&mut dictionary, "rust").await?, (2, 2f64.ln()));
+        assert_eq!(cached.table_hits(), 3);
+
+        let mut metadata = IndexMetadata::load(&destination).await?;
+        assert_eq!(metadata.generation, table.generation());
+        metadata.generation += 1;
+        metadata.save(&destination).await?;
+        assert!(IdfTable::load_current(&destination).await?.is_none());
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn bm25f_uses_zone_lengths() -> Result<(), Error> {
+        let (root, destination) = fixture("rank_bm25f", |v| v).await?;
+        let lengths = DocumentLengths::load(&destination).await?;
+        assert_eq!(lengths.zone("title").unwrap()[..4], [2, 1, 1, 1]);
+        assert_eq!(lengths.zone("text").unwrap()[..4], [7, 4, 3, 1]);
+        assert_eq!(lengths.zone_average("text"), Some(15.0 / 4.0));
+
+        let scorer = Scorer::new(
+            &CommonSegmentSelector::new(),
+            &[("title", 2.0), ("text", 1.0)],
+            lengths.clone(),
+        )?
+        .with_bm25f(1.2, 0.75)?;
+        let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+        let ranked = scorer.search(&mut dictionary, &["tokio"]).await?;
+        // Document 2 has it once in a 1 token title, averaging 1.25, and
+        // document 0 once in a 7 token text, averaging 3.75.
+        let title = 2.0 * 1.0 / (0.25 + 0.75 * 1.0 / 1.25);
+        let text = 1.0 / (0.25 + 0.75 * 7.0 / 3.75);
+        let expected = [(2, 2f64.ln() * title / (1.2 + title)), (0, 2f64.ln() * text / (1.2 + text))];
+        assert_eq!(ranked.len(), 2);
+        for ((document, score), (expected_document, expected_score)) in ranked.iter().zip(expected) {
+            assert_eq!(*document, expected_document);
+            assert!((score - expected_score).abs() < 1e-9);
+        }
+        let (top, _) = scorer.search_top(&mut dictionary, &["tokio"], 1).await?;
+        assert_eq!(top, ranked[..1]);
+        let explanation = scorer.search_explain(&mut dictionary, &["tokio"], 2).await?;
+        assert!((explanation.value - ranked[0].1).abs() < 1e-9);
+
+        assert!(Scorer::new(&CommonSegmentSelector::new(), &[("title", 1.0)], DocumentLengths {
+            zones: Vec::new(),
+            ..lengths.clone()
+        })?
+        .with_bm25f(1.2, 0.75)
+        .is_err());
+
+        // Files from before zone lengths end after the first column.
+        let path = IndexLayout::detect(&destination).await?.lengths(&destination);
+        let old = DocumentLengths {
+            zones: Vec::new(),
+            ..lengths.clone()
+        };
+        old.save(&destination).await?;
+        let bytes = fs::read(&path).await?;
+        fs::write(&path, &bytes[..bytes.len() - 8]).await?;
+        assert_eq!(DocumentLengths::load(&destination).await?, old);
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn zone_idf_counts_documents_having_the_zone() -> Result<(), Error> {
+        let root = std::env::temp_dir().join(format!("rank_zone_idf_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root).await;
+        fs::create_dir_all(&root).await?;
+        let path = root.join("0.xml").to_str().unwrap().to_string();
+        // Every other document has an empty title.
+        let content = (0..8)
+            .map(|d| {
+                let title = match d {
+                    0 | 2 => "guide",
+                    4 | 6 => "intro",
+                    _ => "",
+                };
+                format!("<title>\n{title}\n</title>\n<text>\nnotes\n</text>\n")
+            })
+            .collect::<String>();
+        fs::write(&path, content).await?;
+        let destination = root.join("res").to_str().unwrap().to_string();
+        let config = IndexerConfig::new(1000, 6)?;
+        ParseController::<IndexParser, _, _>::new(
+            vec![path],
+            destination.clone(),
+            root.join("buffer").to_str().unwrap().to_string(),
+            1,
+            IndexedBuilder::new(config, ZoneSet::new(["title", "text"])?)?,
+            IndexMerger::new(config.merger()),
+        )
+        .create_dictionary()
+        .await?;
+
+        let mut metadata = IndexMetadata::load(&destination).await?;
+        let counts = metadata.zone_documents.clone().unwrap();
+        assert_eq!(counts.into_iter().collect::<Vec<_>>(), [("text".to_string(), 8), ("title".to_string(), 4)]);
+        let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+        assert_eq!(dictionary.zone_document_count("title").await?, Some(4));
+        assert_eq!(dictionary.zone_document_count("text").await?, Some(8));
+
+        let scorer = || async {
+            Scorer::new(&CommonSegmentSelector::new(), &[("title", 1.0)], DocumentLengths::load(&destination).await?)
+        };
+        let plain = scorer().await?.search(&mut dictionary, &["guide"]).await?;
+        let zoned = scorer().await?.with_zone_idf(&dictionary).await?;
+        let found = zoned.search(&mut dictionary, &["guide"]).await?;
+        assert_eq!(found.iter().map(|v| v.0).collect::<Vec<_>>(), [0, 2]);
+        // ln(8 / 2) over all documents against ln(4 / 2) over the titled ones.
+        for (plain, zoned) in plain.iter().zip(found.iter()) {
+            assert!((plain.1 / zoned.1 - 4f64.ln() / 2f64.ln()).abs() < 1e-9);
+        }
+        let explanation = zoned.search_explain(&mut dictionary, &["guide"], 0).await?;
+        assert!((explanation.value - found[0].1).abs() < 1e-9);
+        assert!(explanation.to_string().contains("idf, ln(4 / 2)"), "{explanation}");
+
+        // An index from before the counts ranks as if every document had the zone.
+        metadata.zone_documents = None;
+        metadata.save(&destination).await?;
+        assert_eq!(dictionary.zone_document_count("title").await?, None);
+        let fallback = scorer().await?.with_zone_idf(&dictionary).await?;
+        assert_eq!(fallback.search(&mut dictionary, &["guide"]).await?, plain);
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_search_matches_serial() -> Result<(), Error> {
+        let root = std::env::temp_dir().join(format!("rank_concurrent_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root).await;
+        fs::create_dir_all(&root).await?;
+        let words = [
+            "alpha", "beta", "gamma", "delta", "epsilon", "zeta", "eta", "theta", "iota", "kappa",
+        ];
+        let path = root.join("0.xml").to_str().unwrap().to_string();
+        let content = (0..300)
+            .map(|d: usize| {
+                let text = (0..12)
+                    .map(|i| words[(d * 7 + i * i * 3) % (d % words.len() + 1)])
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                format!("<title>\n{}\n</title>\n<text>\n{text}\n</text>\n", words[d % 10])
+            })
+            .collect::<String>();
+        fs::write(&path, content).await?;
+        let destination = root.join("res").to_str().unwrap().to_string();
+        let config = IndexerConfig::new(1000, 6)?;
+        ParseController::<IndexParser, _, _>::new(
+            vec![path],
+            destination.clone(),
+            root.join("buffer").to_str().unwrap().to_string(),
+            1,
+            IndexedBuilder::new(config, ZoneSet::new(["title", "text"])?)?,
+            IndexMerger::new(config.merger()),
+        )
+        .create_dictionary()
+        .await?;
+
+        let scorer = |lengths| {
+            Scorer::new(&CommonSegmentSelector::new(), &[("title", 2.0), ("text", 1.0)], lengths)
+                .map(Arc::new)
+        };
+        let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+        let mut terms = words.to_vec();
+        terms.extend(["missing", "alpha"]);
+        let serial = scorer(DocumentLengths::load(&destination).await?)?
+            .search(&mut dictionary, &terms)
+            .await?;
+        assert_eq!(serial.len(), 300);
+
+        let concurrent = scorer(DocumentLengths::load(&destination).await?)?;
+        let concurrency = Concurrency {
+            max_tasks: 3,
+            shards: 5,
+            ..Concurrency::default()
+        };
+        let found = concurrent
+            .search_concurrent(&mut dictionary, &terms, concurrency)
+            .await?;
+        assert_eq!(found, serial);
+        assert!((1..=3).contains(&concurrent.peak_tasks()), "{}", concurrent.peak_tasks());
+
+        let budgeted = scorer(DocumentLengths::load(&destination).await?)?;
+        let concurrency = Concurrency {
+            max_candidates: 100,
+            ..Concurrency::default()
+        };
+        let found = budgeted
+            .search_concurrent(&mut dictionary, &terms, concurrency)
+            .await?;
+        assert_eq!(found, serial);
+        assert_eq!(budgeted.peak_tasks(), 0);
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn top_search_matches_exhaustive() -> Result<(), Error> {
+        let scorer = |lengths| Scorer::new(&CommonSegmentSelector::new(), &[("title", 2.0), ("text", 1.0)], lengths);
+        let (fixture_root, destination) = fixture("rank_top", |v| v.with_score_bounds()).await?;
+        let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+        assert_eq!(dictionary.max_tf("rust").await?, Some(3));
+        assert_eq!(dictionary.max_tf("missing").await?, None);
+        let top = scorer(DocumentLengths::load(&destination).await?)?;
+        for terms in [&["rust", "runtime", "missing"][..], &["tokio", "asyncio", "threads"], &["rust", "rust"]] {
+            let exhaustive = top.search(&mut dictionary, terms).await?;
+            for k in 0..=5 {
+                let (found, _) = top.search_top(&mut dictionary, terms, k).await?;
+                assert_eq!(found, exhaustive[..k.min(exhaustive.len())], "{terms:?} {k}");
+            }
+        }
+
+        let root = scratch("rank_top_corpus").await?;
+        let corpus = CorpusSpec { docs: 2000, vocab: 500, files: 3, ..CorpusSpec::default() }
+            .generate(&root.join("inp"))
+            .await?;
+        let destination = corpus.index_with(&root, |v| v.with_score_bounds()).await?;
+        let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+        let top = scorer(DocumentLengths::load(&destination).await?)?;
+        let words = [0, 1, 2, 3, 40, 300].map(word);
+        let mut skipped = 0;
+        for terms in [&words[..], &words[..4], &words[3..]] {
+            let terms = terms.iter().map(String::as_str).collect::<Vec<_>>();
+            let exhaustive = top.search(&mut dictionary, &terms).await?;
+            for k in [1, 10, 100, exhaustive.len() + 1] {
+                let (found, stats) = top.search_top(&mut dictionary, &terms, k).await?;
+                assert_eq!(found, exhaustive[..k.min(exhaustive.len())], "{terms:?} {k}");
+                skipped += stats.skipped;
+            }
+        }
+        assert!(skipped > 0);
+
+        fs::create_dir_all(root.join("unbounded")).await?;
+        let unbounded = corpus.index_with(&root.join("unbounded"), |v| v).await?;
+        let mut dictionary = Dictionary::<CommonSegments>::new(&unbounded).await?;
+        assert!(dictionary.max_tf(&words[0]).await.is_err());
+        let terms = words.iter().map(String::as_str).collect::<Vec<_>>();
+        let (found, stats) = top.search_top(&mut dictionary, &terms, 10).await?;
+        assert_eq!(found, top.search(&mut dictionary, &terms).await?[..10]);
+        assert_eq!(stats.skipped, 0);
+
+        fs::remove_dir_all(&fixture_root).await?;
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
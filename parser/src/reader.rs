@@ -6,22 +6,27 @@ use std::{
 };
 
 use async_trait::async_trait;
-use egui::TextBuffer;
 
 use futures::future::join_all;
 use save::u8::{U8Provider, read_char, CommU8Provider};
 use tokio::{
-    fs::{self, File},
-    io::{AsyncSeekExt, AsyncWriteExt, BufReader, BufWriter},
+    fs::File,
+    io::{AsyncWriteExt, BufWriter},
     task::{self, JoinHandle},
 };
+#[cfg(test)]
+use tokio::io::BufReader;
 
+use crate::{
+    config::IoTuning,
+    doc_id::next_chunk,
+    warnings::{WarningKind, Warnings, MAX_TOKEN_BYTES},
+};
 
 pub enum CharType {
-    Letter(ToLowercase),
+    Letter(Letters),
     Ordinary(char),
     Delimiter(char),
-    EOF,
 }
 
 trait Between {
@@ -35,6 +40,34 @@ impl<T: Ord> Between for T {
     }
 }
 
+/// What a letter is read as: its lowercase mapping, or the letter itself
+/// for interpreters that keep case.
+pub enum Letters {
+    Lower(ToLowercase),
+    Kept(Option<char>),
+}
+
+impl Iterator for Letters {
+    type Item = char;
+
+    #[inline(always)]
+    fn next(&mut self) -> Option<char> {
+        match self {
+            Letters::Lower(v) => v.next(),
+            Letters::Kept(v) => v.take(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            Letters::Lower(v) => v.size_hint(),
+            Letters::Kept(v) => (v.is_some() as usize, Some(v.is_some() as usize)),
+        }
+    }
+}
+
+impl ExactSizeIterator for Letters {}
+
 pub trait CharInterpretation {
     fn interpret_character(c: char) -> CharType;
 }
@@ -45,7 +78,22 @@ impl CharInterpretation for CommCharInterpreter {
     #[inline(always)]
     fn interpret_character(c: char) -> CharType {
         if c.is_alphabetic() {
-            CharType::Letter(c.to_lowercase())
+            CharType::Letter(Letters::Lower(c.to_lowercase()))
+        } else {
+            CaseKeepingInterpreter::interpret_character(c)
+        }
+    }
+}
+
+/// Splits words like [`CommCharInterpreter`] but leaves letters as they are,
+/// for callers that fold case themselves.
+pub struct CaseKeepingInterpreter;
+
+impl CharInterpretation for CaseKeepingInterpreter {
+    #[inline(always)]
+    fn interpret_character(c: char) -> CharType {
+        if c.is_alphabetic() {
+            CharType::Letter(Letters::Kept(Some(c)))
         } else if c.is_whitespace()
             || c.is_ascii_digit()
             || matches!(
@@ -76,10 +124,10 @@ impl CharInterpretation for CommCharInterpreter {
                     | '。'
                     | '、'
             )
+            // A NUL in the text splits words; the input ends only where its bytes do.
+            || c == '\0'
         {
             CharType::Delimiter(c)
-        } else if c == '\0' {
-            CharType::EOF
         } else {
             CharType::Ordinary(c)
         }
@@ -100,11 +148,43 @@ impl WordOption {
     }
 }
 
+/// Which delimiters a [`WordProvider`] keeps to be read again after the
+/// word it ends. The readers choose it per call; the kept character is
+/// handed out by [`XmlWordProvider::consume`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PushBack {
+    /// A `<` stops reading with [`WordOption::Empty`] and is kept, so the
+    /// caller reads the tag it opens.
+    pub tag_open: bool,
+    /// The delimiter ending a word is kept, as is a `;` closing an entity.
+    pub delimiters: bool,
+}
+
+impl PushBack {
+    /// What both XML readers use: tags and delimiters are kept.
+    pub const XML: PushBack = PushBack {
+        tag_open: true,
+        delimiters: true,
+    };
+    /// Nothing is kept; a `<` is a delimiter like any other.
+    pub const NONE: PushBack = PushBack {
+        tag_open: false,
+        delimiters: false,
+    };
+}
+
+impl Default for PushBack {
+    fn default() -> Self {
+        Self::XML
+    }
+}
+
 #[async_trait]
 pub trait WordProvider {
     async fn next_word<Interpreter, Reader>(
         &mut self,
         reader: &mut Reader,
+        pushback: PushBack,
         mut start: Option<String>,
     ) -> Option<WordOption>
     where
@@ -112,19 +192,76 @@ pub trait WordProvider {
         Reader: U8Provider + std::marker::Send;
 }
 
+/// What an [`XmlWordProvider`] keeps after a word for the reader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kept {
+    /// The character read past the word, kept as [`PushBack`] says.
+    Char(char),
+    /// The input ended with the word.
+    Eof,
+}
+
+/// Splits text into words, decoding the XML entities in them. The one
+/// character read past a word is kept as [`PushBack`] says, the reader
+/// takes it with [`XmlWordProvider::consume`].
+///
+/// Other named entities are left out of the words and words over
+/// [`MAX_TOKEN_BYTES`] are cut, either with a warning if there are
+/// [`Warnings`] to send it to.
 pub struct XmlWordProvider {
-    previous: Option<char>,
+    previous: Option<Kept>,
+    warnings: Option<Warnings>,
 }
 
 impl XmlWordProvider {
     pub fn new() -> Self {
-        Self { previous: None }
+        Self {
+            previous: None,
+            warnings: None,
+        }
     }
 
-    pub fn consume(&mut self) -> Option<char> {
-        let res = self.previous;
+    pub fn with_warnings(mut self, warnings: Warnings) -> Self {
+        self.warnings = Some(warnings);
+        self
+    }
+
+    fn warn(&self, kind: WarningKind, byte_offset: u64, context: String) {
+        if let Some(warnings) = &self.warnings {
+            warnings.emit(kind, byte_offset, context);
+        }
+    }
+
+    pub fn consume(&mut self) -> Option<Kept> {
+        self.previous.take()
+    }
+
+    /// The kept character, left in place.
+    pub fn peek_previous(&self) -> Option<Kept> {
+        self.previous
+    }
+
+    /// The kept character, or the next one of `reader` if there is none;
+    /// `None` once the input has ended.
+    pub async fn next_char(&mut self, reader: &mut (impl U8Provider + Send)) -> Option<char> {
+        match self.consume() {
+            Some(Kept::Char(c)) => Some(c),
+            Some(Kept::Eof) => None,
+            None => read_char(reader).await,
+        }
+    }
+
+    /// Forgets the kept character, as when the reader drops what it was in.
+    pub fn reset(&mut self) {
         self.previous = None;
-        res
+    }
+
+    /// Bytes of the delimiter read past the last word and not consumed yet.
+    pub fn pending_bytes(&self) -> u64 {
+        match self.previous {
+            Some(Kept::Char(c)) => c.len_utf8() as u64,
+            Some(Kept::Eof) | None => 0,
+        }
     }
 }
 
@@ -134,6 +271,7 @@ impl WordProvider for XmlWordProvider {
     async fn next_word<Interpreter, Reader>(
         &mut self,
         reader: &mut Reader,
+        pushback: PushBack,
         start: Option<String>,
     ) -> Option<WordOption>
     where
@@ -161,7 +299,14 @@ impl WordProvider for XmlWordProvider {
                 None => String::new(),
             }
         };
-        while let Some(c) = read_char(reader).await {
+        loop {
+            let c = match read_char(reader).await {
+                Some(c) => c,
+                None => {
+                    self.previous = Some(Kept::Eof);
+                    break;
+                }
+            };
             match Interpreter::interpret_character(c) {
                 CharType::Letter(chars) => {
                     start.reserve(chars.len());
@@ -173,32 +318,44 @@ impl WordProvider for XmlWordProvider {
                     start.push(c);
                 }
                 CharType::Delimiter(c) => {
-                    if c == '<' {
-                        self.previous = Some('<');
+                    if c == '<' && pushback.tag_open {
+                        self.previous = Some(Kept::Char('<'));
                         return Some(WordOption::Empty);
                     }
                     if c == ';' {
-                        self.previous = Some(c);
+                        if pushback.delimiters {
+                            self.previous = Some(Kept::Char(c));
+                        }
                         if start.ends_with(APOS) {
-                            start.delete_char_range(start.len() - 5..start.len());
+                            start.truncate(start.len() - 5);
                             start.push('\'');
                         } else if start.ends_with(AMP) {
-                            start.delete_char_range(start.len() - 4..start.len());
+                            start.truncate(start.len() - 4);
                             if passable::<Interpreter>(&start) {
                                 break;
                             }
                         } else if start.ends_with(GT) {
-                            start.delete_char_range(start.len() - 3..start.len());
+                            start.truncate(start.len() - 3);
                             if passable::<Interpreter>(&start) {
                                 break;
                             }
                         } else if start.ends_with(LT) {
-                            start.delete_char_range(start.len() - 3..start.len());
+                            start.truncate(start.len() - 3);
                             if passable::<Interpreter>(&start) {
                                 break;
                             }
                         } else if start.ends_with(QUOT) {
-                            start.delete_char_range(start.len() - 5..start.len());
+                            start.truncate(start.len() - 5);
+                            if passable::<Interpreter>(&start) {
+                                break;
+                            }
+                        } else if let Some(at) = unknown_entity(&start) {
+                            self.warn(
+                                WarningKind::UnknownEntity,
+                                reader.consumed_bytes(),
+                                format!("{};", &start[at..]),
+                            );
+                            start.truncate(at);
                             if passable::<Interpreter>(&start) {
                                 break;
                             }
@@ -208,17 +365,23 @@ impl WordProvider for XmlWordProvider {
                             }
                         }
                     } else if passable::<Interpreter>(&start) {
-                        self.previous = Some(c);
+                        if pushback.delimiters {
+                            self.previous = Some(Kept::Char(c));
+                        }
                         break;
                     }
                     start.clear();
                 }
-                CharType::EOF => {
-                    self.previous = Some('\0');
-                    break;
-                }
             }
         }
+        if start.len() > MAX_TOKEN_BYTES {
+            self.warn(
+                WarningKind::OverlongToken,
+                reader.consumed_bytes(),
+                format!("{} bytes, starting {}", start.len(), &start[..floor_boundary(&start, 32)]),
+            );
+            start.truncate(floor_boundary(&start, MAX_TOKEN_BYTES));
+        }
         if passable::<Interpreter>(&start) {
             Some(WordOption::Word(start))
         } else {
@@ -227,6 +390,35 @@ impl WordProvider for XmlWordProvider {
     }
 }
 
+/// Where the `&name` ending `word` starts, if the name is all ASCII letters.
+fn unknown_entity(word: &str) -> Option<usize> {
+    let at = word.rfind('&')?;
+    let name = &word[at + 1..];
+    (!name.is_empty() && name.chars().all(|c| c.is_ascii_alphabetic())).then_some(at)
+}
+
+/// The most bytes of `word` up to `len` that end on a character.
+fn floor_boundary(word: &str, len: usize) -> usize {
+    let mut end = len.min(word.len());
+    while !word.is_char_boundary(end) {
+        end -= 1;
+    }
+    end
+}
+
+async fn wr(resdir: &str, index: &mut Arc<AtomicU32>) -> Option<BufWriter<File>> {
+    let index = match next_chunk(index) {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("{e}");
+            return None;
+        }
+    };
+    let name = format!("{}/{}.xml", resdir, index);
+    log::debug!("chunk start: path={}", name);
+    Some(BufWriter::new(File::create(name).await.unwrap()))
+}
+
 #[derive(PartialEq, Eq)]
 enum XmlPosition {
     InsideText,
@@ -236,6 +428,7 @@ enum XmlPosition {
 pub struct XmlReader<Provider: U8Provider + Send, Interpreter: CharInterpretation> {
     reader: Provider,
     word_provider: XmlWordProvider,
+    pushback: PushBack,
     position: XmlPosition,
     interpreter: PhantomData<Interpreter>,
 }
@@ -247,27 +440,38 @@ impl<Provider: U8Provider + Send, Interpreter: CharInterpretation>
         Ok(Self {
             reader,
             word_provider: XmlWordProvider::new(),
+            pushback: PushBack::XML,
             position: XmlPosition::OutsideText,
             interpreter: PhantomData::<Interpreter>,
         })
     }
 
+    /// Send what the word provider warns about to `warnings`.
+    pub fn with_warnings(mut self, warnings: Warnings) -> Self {
+        self.word_provider = XmlWordProvider::new().with_warnings(warnings);
+        self
+    }
+
     pub async fn divide_write(
         &mut self,
         resdir: String,
         skips: u16,
         mut index: Arc<AtomicU32>,
     ) -> Option<()> {
-        async fn wr(resdir: &String, index: &mut Arc<AtomicU32>) -> Option<BufWriter<File>> {
-            let index = index.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-            let name = format!("{}\\{}.xml", resdir.clone(), index);
-            fs::remove_file(name.clone()).await.unwrap();
-            println!("{}", name);
-            Some(BufWriter::new(File::create(name).await.unwrap()))
-        }
+        let mut cur_file = wr(&resdir, &mut index).await?;
+        self.write_chunks(&resdir, skips, &mut index, &mut cur_file).await;
+        // The input may end anywhere in there; the last chunk is still buffered.
+        cur_file.flush().await.ok()
+    }
 
+    async fn write_chunks(
+        &mut self,
+        resdir: &str,
+        skips: u16,
+        index: &mut Arc<AtomicU32>,
+        cur_file: &mut BufWriter<File>,
+    ) -> Option<()> {
         const TEXT: &'static str = "text";
-        let mut cur_file = wr(&resdir, &mut index).await?;
         let mut skip = skips;
         loop {
             while XmlPosition::OutsideText == self.position {
@@ -282,10 +486,10 @@ impl<Provider: U8Provider + Send, Interpreter: CharInterpretation>
 
                             let str = self
                                 .word_provider
-                                .next_word::<Interpreter, Provider>(&mut self.reader, str)
+                                .next_word::<Interpreter, Provider>(&mut self.reader, self.pushback, str)
                                 .await?;
                             if str.contains(TEXT) {
-                                if self.word_provider.consume() != Some('>') {
+                                if self.word_provider.consume() != Some(Kept::Char('>')) {
                                     while read_char(&mut self.reader).await? != '>' {}
                                 }
                                 self.position = XmlPosition::InsideText;
@@ -297,11 +501,7 @@ impl<Provider: U8Provider + Send, Interpreter: CharInterpretation>
                 }
             }
 
-            let next = if let Some(v) = self.word_provider.consume() {
-                v
-            } else {
-                read_char(&mut self.reader).await?
-            };
+            let next = self.word_provider.next_char(&mut self.reader).await?;
             let next = Interpreter::interpret_character(next);
             match next {
                 CharType::Letter(next) => {
@@ -311,7 +511,7 @@ impl<Provider: U8Provider + Send, Interpreter: CharInterpretation>
                     }
                     if let WordOption::Word(w) = self
                         .word_provider
-                        .next_word::<Interpreter, Provider>(&mut self.reader, Some(str))
+                        .next_word::<Interpreter, Provider>(&mut self.reader, self.pushback, Some(str))
                         .await?
                     {
                         cur_file.write(w.as_bytes()).await.ok()?;
@@ -323,7 +523,7 @@ impl<Provider: U8Provider + Send, Interpreter: CharInterpretation>
                     str.push(next);
                     if let WordOption::Word(w) = self
                         .word_provider
-                        .next_word::<Interpreter, Provider>(&mut self.reader, Some(str))
+                        .next_word::<Interpreter, Provider>(&mut self.reader, self.pushback, Some(str))
                         .await?
                     {
                         cur_file.write(w.as_bytes()).await.ok()?;
@@ -335,7 +535,7 @@ impl<Provider: U8Provider + Send, Interpreter: CharInterpretation>
                         && read_char(&mut self.reader).await? == '/'
                         && self
                             .word_provider
-                            .next_word::<Interpreter, Provider>(&mut self.reader, None)
+                            .next_word::<Interpreter, Provider>(&mut self.reader, self.pushback, None)
                             .await?
                             .contains(TEXT)
                     {
@@ -343,7 +543,7 @@ impl<Provider: U8Provider + Send, Interpreter: CharInterpretation>
                         if self.position == XmlPosition::InsideText {
                             if skip == 0 {
                                 cur_file.flush().await.ok()?;
-                                cur_file = wr(&resdir, &mut index).await?;
+                                *cur_file = wr(resdir, index).await?;
                                 skip = skips;
                             } else {
                                 skip -= 1;
@@ -353,7 +553,6 @@ impl<Provider: U8Provider + Send, Interpreter: CharInterpretation>
                         // wr(&resdir, &mut index).await?;
                     }
                 }
-                CharType::EOF => return None,
             }
         }
     }
@@ -362,6 +561,9 @@ impl<Provider: U8Provider + Send, Interpreter: CharInterpretation>
 pub enum ReaderResult {
     Word(String),
     AttributeEnd,
+    /// The current document is broken; the reader has dropped it and will
+    /// continue from the next document.
+    Malformed(String),
 }
 
 #[async_trait]
@@ -369,7 +571,17 @@ pub trait Reader {
     type UProvider: U8Provider + Send;
     type Interpreter: CharInterpretation;
 
-    async fn next_word(&mut self) -> Option<ReaderResult>;
+    /// `Ok(None)` is a clean end of input; errors mean the rest of the input
+    /// could not be read at all.
+    async fn next_word(&mut self) -> Result<Option<ReaderResult>, Error>;
+
+    /// Byte offset in the input just past the last word returned. The
+    /// delimiter the reader had to look at to end the word is not counted.
+    fn position(&self) -> u64;
+
+    /// SHA-256 of the input read so far, if the provider keeps one. Once
+    /// [`Reader::next_word`] has returned `Ok(None)` it covers the whole file.
+    fn input_digest(&self) -> Option<[u8; 32]>;
 }
 
 #[async_trait]
@@ -381,25 +593,44 @@ impl<
     type UProvider = Provider;
     type Interpreter = Interpreter;
 
-    async fn next_word(&mut self) -> Option<ReaderResult> {
+    async fn next_word(&mut self) -> Result<Option<ReaderResult>, Error> {
+        match self.read_next().await {
+            None => match self.reader.take_error() {
+                Some(e) => Err(e),
+                None => Ok(None),
+            },
+            v => Ok(v),
+        }
+    }
+
+    fn position(&self) -> u64 {
+        self.reader.consumed_bytes() - self.word_provider.pending_bytes()
+    }
+
+    fn input_digest(&self) -> Option<[u8; 32]> {
+        self.reader.digest()
+    }
+}
+
+impl<Provider: U8Provider + Send, Interpreter: CharInterpretation + Send>
+    XmlReader<Provider, Interpreter>
+{
+    async fn read_next(&mut self) -> Option<ReaderResult> {
         const TEXT: &'static str = "text";
         loop {
             while XmlPosition::OutsideText == self.position {
                 if read_char(&mut self.reader).await? == '<' {
-                    let c = read_char(&mut self.reader).await?;
-                    if c == '/' {
+                    if self.reader.peek_u8().await? == b'/' {
                         while read_char(&mut self.reader).await? != '>' {}
                     } else {
-                        let str = if c == ' ' { None } else { Some(c.to_string()) };
-
                         let str = self
                             .word_provider
-                            .next_word::<Interpreter, Provider>(&mut self.reader, str)
+                            .next_word::<Interpreter, Provider>(&mut self.reader, self.pushback, None)
                             .await?;
                         match str {
                             WordOption::Word(str) => {
                                 if str == TEXT {
-                                    if self.word_provider.consume() != Some('>') {
+                                    if self.word_provider.consume() != Some(Kept::Char('>')) {
                                         while read_char(&mut self.reader).await? != '>' {}
                                     }
                                     self.position = XmlPosition::InsideText;
@@ -412,11 +643,7 @@ impl<
                 }
             }
 
-            let next = if let Some(v) = self.word_provider.consume() {
-                v
-            } else {
-                read_char(&mut self.reader).await?
-            };
+            let next = self.word_provider.next_char(&mut self.reader).await?;
 
             let next = Interpreter::interpret_character(next);
             match next {
@@ -427,7 +654,7 @@ impl<
                     }
                     if let WordOption::Word(w) = self
                         .word_provider
-                        .next_word::<Interpreter, Provider>(&mut self.reader, Some(str))
+                        .next_word::<Interpreter, Provider>(&mut self.reader, self.pushback, Some(str))
                         .await?
                     {
                         return Some(ReaderResult::Word(w));
@@ -438,7 +665,7 @@ impl<
                     str.push(next);
                     if let WordOption::Word(w) = self
                         .word_provider
-                        .next_word::<Interpreter, Provider>(&mut self.reader, Some(str))
+                        .next_word::<Interpreter, Provider>(&mut self.reader, self.pushback, Some(str))
                         .await?
                     {
                         return Some(ReaderResult::Word(w));
@@ -449,7 +676,7 @@ impl<
                         && read_char(&mut self.reader).await? == '/'
                         && self
                             .word_provider
-                            .next_word::<Interpreter, Provider>(&mut self.reader, None)
+                            .next_word::<Interpreter, Provider>(&mut self.reader, self.pushback, None)
                             .await?
                             .contains(TEXT)
                     {
@@ -457,7 +684,6 @@ impl<
                         return Some(ReaderResult::AttributeEnd);
                     }
                 }
-                CharType::EOF => return None,
             }
         }
     }
@@ -465,15 +691,36 @@ impl<
 
 #[tokio::test]
 async fn nya() -> Result<(), Error> {
+    let root = std::env::temp_dir().join(format!("divide_write_{}", std::process::id()));
+    let _ = tokio::fs::remove_dir_all(&root).await;
+    tokio::fs::create_dir_all(&root).await?;
+    let dump = root.join("dump.xml");
+    let pages = ["first page", "second page", "third page"]
+        .iter()
+        .map(|text| {
+            format!("<page>\n<title>Skipped</title>\n<text xml:space=\"preserve\">{text}\n</text>\n</page>\n")
+        })
+        .collect::<String>();
+    tokio::fs::write(&dump, format!("<mediawiki>\n{pages}</mediawiki>\n")).await?;
+
     let mut xml = XmlReader::<CommU8Provider, CommCharInterpreter>::new(CommU8Provider::new(BufReader::new(
-        File::open(r#"C:\Dataset\enwiki-20211101-pages-articles1.xml-p1p41242\enwiki-20211101-pages-articles1.xml-p1p41242"#).await?,
-        // File::open(r#".\inp\5.xml"#).await?,
+        File::open(&dump).await?,
     )))
     .await?;
-    xml.divide_write(".\\inp".to_string(), 100, Arc::new(AtomicU32::new(1163)))
+    let index = Arc::new(AtomicU32::new(0));
+    xml.divide_write(root.to_str().unwrap().to_string(), 1, index.clone())
         .await;
 
-    println!();
+    assert_eq!(index.load(std::sync::atomic::Ordering::SeqCst), 2);
+    assert_eq!(
+        tokio::fs::read_to_string(root.join("0.xml")).await?,
+        "<text>first page \n</text>\n<text>second page \n</text>\n"
+    );
+    assert_eq!(
+        tokio::fs::read_to_string(root.join("1.xml")).await?,
+        "<text>third page \n</text>\n"
+    );
+    tokio::fs::remove_dir_all(&root).await?;
     Ok(())
 }
 
@@ -499,7 +746,7 @@ pub async fn gra() -> Result<(), Error> {
         let index = index.clone();
         tasks.push(task::spawn(async move {
             let mut xml = XmlReader::<_, CommCharInterpreter>::new(CommU8Provider::new(
-                BufReader::with_capacity(1024 * 1024, File::open(file).await.unwrap()),
+                IoTuning::default().chunk_reader(File::open(file).await.unwrap()),
             ))
             .await
             .unwrap();
@@ -507,22 +754,155 @@ pub async fn gra() -> Result<(), Error> {
         }));
     }
     join_all(tasks).await;
-    println!();
     Ok(())
 }
 
 #[tokio::test]
 async fn reader_test() -> Result<(), Error> {
     let mut xml = XmlReader::<_, CommCharInterpreter>::new(CommU8Provider::new(BufReader::new(
-        File::open(r#".\test\ha.xml"#).await?,
+        File::open(concat!(env!("CARGO_MANIFEST_DIR"), "/test/ha.xml")).await?,
+    )))
+    .await?;
+    let mut read = vec![];
+    while let Some(kar) = xml.next_word().await? {
+        read.push(match kar {
+            ReaderResult::Word(w) => w,
+            ReaderResult::AttributeEnd => "AttributeEnd".to_string(),
+            ReaderResult::Malformed(w) => format!("Malformed {w}"),
+        });
+    }
+    assert_eq!(read, ["gra", "AttributeEnd", "lgra", "AttributeEnd"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn position_follows_words() -> Result<(), Error> {
+    let path = std::env::temp_dir().join(format!("reader_position_{}.xml", std::process::id()));
+    let content = "<text>\nнаївна café，über。 end\n</text>\n";
+    tokio::fs::write(&path, content).await?;
+    let mut xml = XmlReader::<_, CommCharInterpreter>::new(CommU8Provider::new(BufReader::new(
+        File::open(&path).await?,
     )))
     .await?;
-    while let Some(kar) = xml.next_word().await {
-        match kar {
-            ReaderResult::Word(w) => println!("{w}",),
-            ReaderResult::AttributeEnd => println!("AttributeEnd"),
+    let mut ends = vec![];
+    while let Some(kar) = xml.next_word().await? {
+        if let ReaderResult::Word(w) = kar {
+            ends.push((content.find(&w).unwrap() + w.len()) as u64);
+            assert_eq!(xml.position(), *ends.last().unwrap(), "{w}");
         }
     }
+    tokio::fs::remove_file(&path).await?;
+    assert_eq!(ends.len(), 4);
+    Ok(())
+}
+
+/// Words [`XmlWordProvider`] reads from `text` one call after another, each
+/// with the character it kept; the kept character is consumed before the
+/// next call, as the readers do. [`WordOption::Empty`] is an empty word.
+#[cfg(test)]
+async fn provider_words(name: &str, text: &str, pushback: PushBack) -> Result<Vec<(String, Option<Kept>)>, Error> {
+    let path = std::env::temp_dir().join(format!("word_provider_{name}_{}.txt", std::process::id()));
+    tokio::fs::write(&path, text).await?;
+    let mut reader = CommU8Provider::new(BufReader::new(File::open(&path).await?));
+    let mut provider = XmlWordProvider::new();
+    let mut words = vec![];
+    while let Some(word) = provider
+        .next_word::<CommCharInterpreter, _>(&mut reader, pushback, None)
+        .await
+    {
+        let word = match word {
+            WordOption::Word(w) => w,
+            WordOption::Empty => String::new(),
+        };
+        words.push((word, provider.peek_previous()));
+        provider.consume();
+    }
+    tokio::fs::remove_file(&path).await?;
+    Ok(words)
+}
+
+#[cfg(test)]
+fn words(expected: &[(&str, Option<Kept>)]) -> Vec<(String, Option<Kept>)> {
+    expected.iter().map(|(w, c)| (w.to_string(), *c)).collect()
+}
+
+#[tokio::test]
+async fn word_provider_decodes_entities_up_to_eof() -> Result<(), Error> {
+    let read = provider_words("entity_split", "rock&amp;roll", PushBack::XML).await?;
+    assert_eq!(read, words(&[("rock", Some(Kept::Char(';'))), ("roll", Some(Kept::Eof))]));
+    let read = provider_words("entity_last", "tom&amp;", PushBack::XML).await?;
+    assert_eq!(read, words(&[("tom", Some(Kept::Char(';')))]));
+    assert!(provider_words("entity_alone", "&lt;", PushBack::XML).await?.is_empty());
+    // Without its `;` an entity is part of the word.
+    let read = provider_words("entity_open", "x&amp", PushBack::XML).await?;
+    assert_eq!(read, words(&[("x&amp", Some(Kept::Eof))]));
+    Ok(())
+}
+
+#[tokio::test]
+async fn word_provider_warns_of_what_it_changes() -> Result<(), Error> {
+    let path = std::env::temp_dir().join(format!("word_provider_warnings_{}.txt", std::process::id()));
+    let long = "ж".repeat(MAX_TOKEN_BYTES);
+    tokio::fs::write(&path, format!("a&nbsp;b x&copy; &amp; {long} &lt;")).await?;
+    let mut reader = CommU8Provider::new(BufReader::new(File::open(&path).await?));
+    let warnings = Warnings::new(10);
+    let mut provider = XmlWordProvider::new().with_warnings(warnings.clone());
+    let mut read = vec![];
+    while let Some(word) = provider
+        .next_word::<CommCharInterpreter, _>(&mut reader, PushBack::NONE, None)
+        .await
+    {
+        if let WordOption::Word(w) = word {
+            read.push(w);
+        }
+    }
+    tokio::fs::remove_file(&path).await?;
+    assert_eq!(read, ["a", "b", "x", &"ж".repeat(MAX_TOKEN_BYTES / 2)]);
+    let summary = warnings.take();
+    assert_eq!(summary.counts[&WarningKind::UnknownEntity], 2);
+    assert_eq!(summary.counts[&WarningKind::OverlongToken], 1);
+    assert_eq!(
+        summary.kept.iter().map(|v| (v.byte_offset, v.context.clone())).collect::<Vec<_>>()[..2],
+        [(7, "&nbsp;".to_string()), (16, "&copy;".to_string())]
+    );
+    assert!(summary.kept[2].context.starts_with(&format!("{} bytes, starting жжж", long.len())));
+    Ok(())
+}
+
+#[tokio::test]
+async fn word_provider_keeps_tag_open_as_told() -> Result<(), Error> {
+    let read = provider_words("tag_xml", "a <tag>b", PushBack::XML).await?;
+    assert_eq!(
+        read,
+        words(&[
+            ("a", Some(Kept::Char(' '))),
+            ("", Some(Kept::Char('<'))),
+            ("tag", Some(Kept::Char('>'))),
+            ("b", Some(Kept::Eof)),
+        ])
+    );
+    let read = provider_words("tag_none", "a <tag>b", PushBack::NONE).await?;
+    assert_eq!(read, words(&[("a", None), ("tag", None), ("b", Some(Kept::Eof))]));
+    Ok(())
+}
+
+#[tokio::test]
+async fn word_provider_skips_consecutive_delimiters() -> Result<(), Error> {
+    let read = provider_words("delimiters", "one,, two", PushBack::XML).await?;
+    assert_eq!(read, words(&[("one", Some(Kept::Char(','))), ("two", Some(Kept::Eof))]));
+
+    let path = std::env::temp_dir().join(format!("word_provider_reset_{}.txt", std::process::id()));
+    tokio::fs::write(&path, "one,, two").await?;
+    let mut reader = CommU8Provider::new(BufReader::new(File::open(&path).await?));
+    let mut provider = XmlWordProvider::new();
+    provider
+        .next_word::<CommCharInterpreter, _>(&mut reader, PushBack::XML, None)
+        .await;
+    assert_eq!(provider.pending_bytes(), 1);
+    provider.reset();
+    assert_eq!(provider.peek_previous(), None);
+    assert_eq!(provider.pending_bytes(), 0);
+    tokio::fs::remove_file(&path).await?;
     Ok(())
 }
 
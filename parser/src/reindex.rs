@@ -0,0 +1,633 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BTreeMap,
+    fmt::Display,
+    io::{Error, ErrorKind},
+    ops::Range,
+    path::Path,
+};
+
+use tokio::fs;
+
+use crate::{
+    boost::DocumentBoosts,
+    config::MergerConfig,
+    generation::Generations,
+    indexed::{
+        save_input_files, IndexMergeSaver, IndexMerger, IndexParser, IndexTermProvider, IndexedBuilder, IndexedTerm,
+    },
+    layout::IndexLayout,
+    metadata::IndexMetadata,
+    numeric::NumericValues,
+    parser::ParseController,
+    rank::{DocumentLengths, IdfTable, TopTerms},
+    reorder::{exists, recover, remove_if_exists, replaced, staged},
+    resolve::{load_input_files, DocResolver},
+    segment::CommonSegments,
+    stats::IndexStats,
+    term_ord::term_cmp,
+    titles::DocumentTitles,
+};
+
+#[cfg(feature = "roaring")]
+use crate::bitmaps::write_bitmaps;
+
+/// What [`reindex_file`] did to an index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReindexReport {
+    pub source: String,
+    /// Ids the source had before, now tombstones.
+    pub tombstoned: usize,
+    /// Ids the source was given instead, past every earlier one.
+    pub added: Range<usize>,
+}
+
+impl Display for ReindexReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "reindexed {}: {} ids tombstoned, {} added from id {}",
+            self.source,
+            self.tombstoned,
+            self.added.len(),
+            self.added.start
+        )
+    }
+}
+
+fn only(directory: &str) -> String {
+    format!("{directory}.only")
+}
+
+/// Indexes `source` again into the index in `directory`, for when one input
+/// was fixed and the rest doesn't need a rebuild. `builder` and `config`
+/// have to be set up as the build was; settings that end up in
+/// `metadata.json` are checked against it.
+///
+/// The ids [`DocResolver`] gives the source become tombstones: they keep
+/// their place with no postings, no length and an empty source, so no
+/// other document changes id. The file is indexed on its own into
+/// `<directory>.only` and its documents join the index with fresh ids past
+/// the last one. Lengths, titles, stats, the idf table and the zone counts
+/// are redone for the merged index; the bitmaps, rotations and whatever
+/// else the index was built with are written again.
+///
+/// The merged index takes the place of the old one the way [`crate::reorder::reorder`]
+/// swaps them in, as a fresh generation or through [`recover`].
+pub async fn reindex_file(
+    directory: &str,
+    source: &str,
+    builder: IndexedBuilder,
+    config: MergerConfig,
+) -> Result<ReindexReport, Error> {
+    rebuild(directory, source, builder, config, true).await
+}
+
+/// Indexes `source`, which the index in `directory` doesn't hold yet, into
+/// it the way [`reindex_file`] does, with nothing tombstoned: its documents
+/// join with fresh ids past the last one. Fails if it is a source already.
+pub async fn append_file(
+    directory: &str,
+    source: &str,
+    builder: IndexedBuilder,
+    config: MergerConfig,
+) -> Result<ReindexReport, Error> {
+    rebuild(directory, source, builder, config, false).await
+}
+
+async fn rebuild(
+    directory: &str,
+    source: &str,
+    builder: IndexedBuilder,
+    config: MergerConfig,
+    replace: bool,
+) -> Result<ReindexReport, Error> {
+    let generations = Generations::new(directory);
+    let generation = generations.current().await?;
+    let current = match generation {
+        Some(v) => generations.directory(v),
+        None => {
+            recover(directory).await?;
+            directory.to_string()
+        }
+    };
+    let tombstoned = DocResolver::load(&current).await?.ids_for_source(Path::new(source)).to_vec();
+    match (replace, tombstoned.is_empty()) {
+        (true, true) => {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("{source} is not a source of {directory}"),
+            ))
+        }
+        (false, false) => {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{source} is a source of {directory} already"),
+            ))
+        }
+        _ => {}
+    }
+
+    let only = only(directory);
+    remove_if_exists(&only).await?;
+    let mut controller = ParseController::<IndexParser, _, _>::new(
+        vec![source.to_string()],
+        only.clone(),
+        format!("{only}.buffer"),
+        1,
+        builder,
+        IndexMerger::new(config),
+    )
+    .with_in_memory_below(64 << 20);
+    if exists(&IndexLayout::detect(&current).await?.titles(&current)).await? {
+        controller = controller.with_titles();
+    }
+    controller.create_dictionary().await?;
+    remove_if_exists(&format!("{only}.buffer")).await?;
+
+    let report = match generation {
+        Some(_) => {
+            let (generation, next) = generations.create_next().await?;
+            let report = merge(&current, &only, &next, source, &tombstoned, config).await?;
+            generations.flip(generation).await?;
+            report
+        }
+        None => {
+            let (staged, replaced) = (staged(directory), replaced(directory));
+            let report = merge(directory, &only, &staged, source, &tombstoned, config).await?;
+            fs::rename(directory, &replaced).await?;
+            fs::rename(&staged, directory).await?;
+            fs::remove_dir_all(&replaced).await?;
+            report
+        }
+    };
+    fs::remove_dir_all(&only).await?;
+    Ok(report)
+}
+
+/// Settings the file was indexed with that the index wasn't.
+fn differing(current: &IndexMetadata, fresh: &IndexMetadata) -> Vec<&'static str> {
+    let mut names = Vec::new();
+    if current.analyzer.is_some() && current.analyzer != fresh.analyzer {
+        names.push("analyzer");
+    }
+    if current.filter != fresh.filter {
+        names.push("term filter");
+    }
+    if current.tf != fresh.tf {
+        names.push("tf policy");
+    }
+    if current.numeric != fresh.numeric {
+        names.push("numeric field");
+    }
+    if current.case_preserving != fresh.case_preserving {
+        names.push("case preserving");
+    }
+    if current.excluded != fresh.excluded {
+        names.push("excluded elements");
+    }
+    if current.zones.is_some() && current.zones != fresh.zones {
+        names.push("zones");
+    }
+    names
+}
+
+/// Values kept by document id: those of `current` with the tombstones set
+/// to `empty`, then those of `fresh`.
+fn join<T: Clone>(mut current: Vec<T>, removed: &[bool], fresh: Vec<T>, empty: T) -> Vec<T> {
+    current.resize(removed.len(), empty.clone());
+    for (v, removed) in current.iter_mut().zip(removed.iter()) {
+        if *removed {
+            *v = empty.clone();
+        }
+    }
+    current.extend(fresh);
+    current
+}
+
+async fn merge(
+    current: &str,
+    only: &str,
+    destination: &str,
+    source: &str,
+    tombstoned: &[usize],
+    config: MergerConfig,
+) -> Result<ReindexReport, Error> {
+    let (current, only, destination) = (current.to_string(), only.to_string(), destination.to_string());
+    let metadata = IndexMetadata::load_or_legacy(&current).await?;
+    let fresh = IndexMetadata::load(&only).await?;
+    let differing = differing(&metadata, &fresh);
+    if !differing.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{source} was indexed with another {} than {current}", differing.join(", ")),
+        ));
+    }
+    let layout = IndexLayout::detect(&current).await?;
+    let fresh_layout = IndexLayout::detect(&only).await?;
+    let sources = load_input_files(layout.files(&current)).await?;
+    let fresh_sources = load_input_files(fresh_layout.files(&only)).await?;
+    let base = sources.len();
+    let mut removed = vec![false; base];
+    for document in tombstoned {
+        removed[*document] = true;
+    }
+    fs::create_dir_all(&destination).await?;
+
+    let mut saver =
+        IndexMergeSaver::<CommonSegments>::create(destination.clone(), config.lexical_block_size(), layout, config.io())
+            .await?;
+    if metadata.phonetic {
+        saver = saver.with_phonetic();
+    }
+    if metadata.permuterm {
+        saver = saver.with_permuterm();
+    }
+    if exists(&layout.max_tf(&current)).await? {
+        saver = saver.with_score_bounds();
+    }
+    #[cfg(feature = "fst")]
+    if metadata.fst {
+        saver = saver.with_fst();
+    }
+    let mut top = IdfTable::load_current(&current).await?.map(|v| TopTerms::new(v.len()));
+    let mut stats = IndexStats::default();
+    let mut old = IndexTermProvider::<CommonSegments>::new_with_io(&current, config.io()).await?;
+    let mut new = IndexTermProvider::<CommonSegments>::new_with_io(&only, config.io()).await?;
+    let (mut old_head, mut new_head) = (old.next_head().await?, new.next_head().await?);
+    let mut postings = Vec::new();
+    loop {
+        let order = match (&old_head, &new_head) {
+            (None, None) => break,
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(a), Some(b)) => term_cmp(&a.term, &b.term),
+        };
+        let mut name = String::new();
+        if order.is_le() {
+            let head = old_head.take().unwrap();
+            for (document, usage) in old.load_postings(&head).await?.iter() {
+                if document >= base {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("{:?} is in document {document} of {base}", head.term),
+                    ));
+                }
+                if !removed[document] {
+                    postings.push((document, usage));
+                }
+            }
+            name = head.term;
+            old_head = old.next_head().await?;
+        }
+        if order.is_ge() {
+            let head = new_head.take().unwrap();
+            for (document, usage) in new.load_postings(&head).await?.iter() {
+                postings.push((base + document, usage));
+            }
+            name = head.term;
+            new_head = new.next_head().await?;
+        }
+        // Found in the tombstones alone.
+        if postings.is_empty() {
+            continue;
+        }
+        // Pushed from the highest id down, each posting goes in at the head of the list.
+        postings.sort_unstable_by_key(|(document, _)| Reverse(*document));
+        let mut term = IndexedTerm::new(name);
+        for (document, usage) in postings.drain(..) {
+            term.use_count += usage.use_count() as u64;
+            term.indexes.push(document, usage);
+        }
+        if let Some(top) = &mut top {
+            top.push(&term.term, term.indexes.len());
+        }
+        stats.occurrences += term.use_count;
+        stats.postings += term.indexes.len() as u64;
+        stats.vocabulary += 1;
+        saver.push(term).await?;
+    }
+    let finished = saver.finish().await?;
+    stats.bytes = finished.bytes;
+    if let Some(rotations) = finished.permuterm {
+        rotations
+            .save::<CommonSegments>(&destination, config.lexical_block_size(), layout, config.io())
+            .await?;
+    }
+    #[cfg(feature = "roaring")]
+    if let Some(min_df) = metadata.bitmaps {
+        write_bitmaps::<CommonSegments>(&destination, min_df).await?;
+    }
+
+    let added = base..base + fresh_sources.len();
+    save_input_files(
+        layout.files(&destination),
+        join(sources, &removed, fresh_sources, (String::new(), 0))
+            .iter()
+            .map(|(name, i)| (name.as_str(), *i)),
+    )
+    .await?;
+    let lengths = DocumentLengths::load(&current).await?;
+    let fresh_lengths = DocumentLengths::load(&only).await?;
+    let mut zone_documents = BTreeMap::<String, usize>::new();
+    let mut zones = BTreeMap::<String, Vec<u32>>::new();
+    for (zone, column) in lengths.zones.iter() {
+        let gone = tombstoned.iter().filter(|v| column.get(**v).is_some_and(|v| *v > 0)).count();
+        zone_documents.insert(zone.clone(), gone);
+        zones.insert(zone.clone(), column.clone());
+    }
+    for (zone, column) in fresh_lengths.zones {
+        let current = zones.remove(&zone).unwrap_or_default();
+        zones.insert(zone, join(current, &removed, column, 0));
+    }
+    for column in zones.values_mut() {
+        if column.len() < added.end {
+            *column = join(std::mem::take(column), &removed, vec![0; added.len()], 0);
+        }
+    }
+    // Ids of documents dropped as malformed have no length and were never
+    // counted.
+    let counted = tombstoned.iter().filter(|v| lengths.lengths.get(**v).is_some_and(|v| *v > 0)).count();
+    DocumentLengths {
+        documents: lengths.documents - counted + fresh_lengths.documents,
+        lengths: join(lengths.lengths, &removed, fresh_lengths.lengths, 0),
+        zones: zones.into_iter().collect(),
+    }
+    .save(&destination)
+    .await?;
+    if exists(&layout.titles(&current)).await? {
+        DocumentTitles {
+            titles: join(
+                DocumentTitles::load(&current).await?.titles,
+                &removed,
+                DocumentTitles::load(&only).await?.titles,
+                String::new(),
+            ),
+        }
+        .save(&destination)
+        .await?;
+    }
+    if exists(&layout.boosts(&current)).await? {
+        let boosts = DocumentBoosts::load(&current).await?.boosts;
+        DocumentBoosts {
+            boosts: join(boosts, &removed, vec![1.0; added.len()], 1.0),
+        }
+        .save(&destination)
+        .await?;
+    }
+    if metadata.numeric.is_some() {
+        let values = NumericValues::load(&current).await?;
+        NumericValues {
+            values: join(values.values, &removed, NumericValues::load(&only).await?.values, None),
+            field: values.field,
+        }
+        .save(&destination)
+        .await?;
+    }
+
+    // Ids cached for the old generation may now be tombstones.
+    let generation = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |v| v.as_nanos() as u64)
+        .max(metadata.generation + 1);
+    if let Some(top) = top {
+        let documents = DocumentLengths::load(&destination).await?.documents;
+        IdfTable::new(generation, documents, top.into_sorted())
+            .save(&destination)
+            .await?;
+    }
+    if exists(&layout.info(&current)).await? {
+        fs::write(
+            layout.info(&destination),
+            format!("{}\n{}\n", stats.occurrences, stats.vocabulary),
+        )
+        .await?;
+    }
+    if exists(&IndexLayout::report(&current)).await? {
+        fs::copy(IndexLayout::report(&current), IndexLayout::report(&destination)).await?;
+    }
+    stats.save(&destination).await?;
+    let zone_documents = match (metadata.zone_documents, fresh.zone_documents) {
+        (Some(mut counts), Some(fresh)) => {
+            for (zone, gone) in zone_documents {
+                if let Some(v) = counts.get_mut(&zone) {
+                    *v = v.saturating_sub(gone);
+                }
+            }
+            for (zone, count) in fresh {
+                *counts.entry(zone).or_default() += count;
+            }
+            counts.retain(|_, v| *v > 0);
+            Some(counts)
+        }
+        _ => None,
+    };
+    let mut build = metadata.build;
+    if let (Some(build), Some(read)) = (&mut build, fresh.build.and_then(|v| v.inputs.into_iter().next())) {
+        let mut known = false;
+        for input in build.inputs.iter_mut().filter(|v| v.path == read.path) {
+            *input = read.clone();
+            known = true;
+        }
+        if !known {
+            build.inputs.push(read);
+        }
+    }
+    IndexMetadata {
+        zone_documents,
+        build,
+        generation,
+        ..metadata
+    }
+    .save(&destination)
+    .await?;
+
+    Ok(ReindexReport {
+        source: source.to_string(),
+        tombstoned: tombstoned.len(),
+        added,
+    })
+}
+
+#[cfg(test)]
+mod tst {
+    use std::{
+        collections::BTreeMap,
+        io::{Error, ErrorKind},
+        path::{Path, PathBuf},
+    };
+
+    use tokio::fs;
+
+    use crate::{
+        config::IndexerConfig,
+        execute::{execute, QueryLimits},
+        indexed::{verify_index, Dictionary, IndexMerger, IndexedBuilder, TermBound},
+        metadata::IndexMetadata,
+        query::parse_query,
+        rank::{DocumentLengths, IdfTable, Scorer},
+        resolve::DocResolver,
+        segment::{CommonSegmentSelector, CommonSegments},
+        stats::IndexStats,
+        testsupport::{scratch, word, CorpusSpec},
+    };
+
+    use super::reindex_file;
+
+    type Document = (PathBuf, u64);
+
+    /// Source and position of every document in `documents`.
+    fn resolved(resolver: &DocResolver, documents: impl IntoIterator<Item = usize>) -> Vec<Document> {
+        let mut resolved = documents
+            .into_iter()
+            .map(|v| (resolver.source_of(v).unwrap().to_path_buf(), resolver.position_of(v).unwrap()))
+            .collect::<Vec<_>>();
+        resolved.sort_unstable();
+        resolved
+    }
+
+    /// Documents with their counts of every term, known by source and position.
+    async fn postings(directory: &String) -> Result<BTreeMap<String, Vec<(Document, usize)>>, Error> {
+        let resolver = DocResolver::load(directory).await?;
+        let mut dictionary = Dictionary::<CommonSegments>::new(directory).await?;
+        let mut terms = Vec::new();
+        let mut range = dictionary.range(TermBound::Unbounded, TermBound::Unbounded).await?;
+        while let Some((term, _)) = range.next().await? {
+            terms.push(term);
+        }
+        let mut found = BTreeMap::new();
+        for term in terms {
+            let indexes = dictionary.find(&term).await?.unwrap().indexes;
+            let mut documents = indexes
+                .iter()
+                .map(|(document, usage)| (resolved(&resolver, [document]).remove(0), usage.use_count()))
+                .collect::<Vec<_>>();
+            documents.sort_unstable();
+            found.insert(term, documents);
+        }
+        Ok(found)
+    }
+
+    async fn answers(directory: &String, queries: &[String]) -> Result<Vec<Vec<Document>>, Error> {
+        let resolver = DocResolver::load(directory).await?;
+        let mut dictionary = Dictionary::<CommonSegments>::new(directory).await?;
+        let selector = CommonSegmentSelector::new();
+        let mut answers = Vec::new();
+        for raw in queries.iter() {
+            let query = parse_query(raw, &selector).unwrap();
+            let found = execute(&query, &mut dictionary, QueryLimits::default()).await.unwrap();
+            answers.push(resolved(&resolver, found.documents));
+        }
+        Ok(answers)
+    }
+
+    async fn ranked(directory: &String) -> Result<Vec<(Document, f64)>, Error> {
+        let resolver = DocResolver::load(directory).await?;
+        let scorer = Scorer::new(
+            &CommonSegmentSelector::new(),
+            &[("title", 2.0), ("text", 1.0)],
+            DocumentLengths::load(directory).await?,
+        )?;
+        let mut dictionary = Dictionary::<CommonSegments>::new(directory).await?;
+        let mut ranked = scorer
+            .search(&mut dictionary, &[&word(2), &word(9), &word(40)])
+            .await?
+            .into_iter()
+            .map(|(document, score)| (resolved(&resolver, [document]).remove(0), score))
+            .collect::<Vec<_>>();
+        ranked.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        Ok(ranked)
+    }
+
+    #[tokio::test]
+    async fn a_fixed_file_reindexes_like_a_fresh_build() -> Result<(), Error> {
+        let root = scratch("reindex").await?;
+        let corpus = CorpusSpec {
+            docs: 90,
+            files: 3,
+            ..CorpusSpec::default()
+        }
+        .generate(&root.join("corpus"))
+        .await?;
+        let source = corpus.files[1].clone();
+        let fixed = fs::read_to_string(&source).await?;
+        // The chunk lost its second half and got another document in its place.
+        let half = fixed.match_indices("<title>").nth(15).unwrap().0;
+        let broken = "<title>\nbroken\n</title>\n<text>\nbroken chunk\n</text>\n";
+        fs::write(&source, format!("{}{broken}", &fixed[..half])).await?;
+        let merger = |v: IndexMerger| v.with_idf_top(5).with_score_bounds().with_permuterm();
+        let index = corpus.index_with(&root.join("corrupt"), merger).await?;
+        fs::write(&source, fixed).await?;
+        let fresh = corpus.index_with(&root.join("fresh"), merger).await?;
+
+        let before = DocResolver::load(&index).await?;
+        let tombstones = before.ids_for_source(Path::new(&source)).to_vec();
+        let config = IndexerConfig::new(1000, 6)?;
+        let builder = IndexedBuilder::new(config, corpus.attributes.clone())?;
+        let report = reindex_file(&index, &source, builder, config.merger()).await?;
+        // 15 documents and the broken one, then 30.
+        assert_eq!((report.tombstoned, tombstones.len()), (16, 16));
+        assert_eq!(report.added, before.len()..before.len() + 30);
+        assert!(report.to_string().ends_with("16 ids tombstoned, 30 added from id 76"), "{report}");
+        verify_index::<CommonSegments>(&index).await?;
+
+        let after = DocResolver::load(&index).await?;
+        assert_eq!(after.ids_for_source(Path::new(&source)), report.added.collect::<Vec<_>>());
+        assert_eq!(after.source_of(tombstones[0]), Some(Path::new("")));
+        assert_eq!(postings(&index).await?, postings(&fresh).await?);
+        assert!(Dictionary::<CommonSegments>::new(&index).await?.find("broken").await?.is_none());
+
+        let (a, b, c) = (word(0), word(3), word(17));
+        let queries = [
+            a.clone(),
+            format!("{a} {b}"),
+            format!("{a} OR {c}"),
+            format!("{b} NOT {a}"),
+            format!("title:{c}"),
+            "broken".to_string(),
+        ];
+        assert_eq!(answers(&index, &queries).await?, answers(&fresh, &queries).await?);
+        let (ranked_after, ranked_fresh) = (ranked(&index).await?, ranked(&fresh).await?);
+        assert_eq!(ranked_after.len(), ranked_fresh.len());
+        for (after, fresh) in ranked_after.iter().zip(ranked_fresh.iter()) {
+            assert_eq!(after.0, fresh.0);
+            assert!((after.1 - fresh.1).abs() < 1e-9, "{after:?} {fresh:?}");
+        }
+        let mut dictionary = Dictionary::<CommonSegments>::new(&index).await?;
+        assert_eq!(
+            dictionary.wildcard("*a*").await?,
+            Dictionary::<CommonSegments>::new(&fresh).await?.wildcard("*a*").await?
+        );
+
+        let (stats, fresh_stats) = (IndexStats::load(&index).await?, IndexStats::load(&fresh).await?);
+        assert_eq!(
+            (stats.occurrences, stats.postings, stats.vocabulary),
+            (fresh_stats.occurrences, fresh_stats.postings, fresh_stats.vocabulary)
+        );
+        assert_eq!(
+            DocumentLengths::load(&index).await?.documents,
+            DocumentLengths::load(&fresh).await?.documents
+        );
+        let (metadata, fresh_metadata) = (IndexMetadata::load(&index).await?, IndexMetadata::load(&fresh).await?);
+        assert_eq!(metadata.zone_documents, fresh_metadata.zone_documents);
+        assert_eq!(metadata.build.unwrap().inputs, fresh_metadata.build.unwrap().inputs);
+        let (table, fresh_table) = (
+            IdfTable::load_current(&index).await?.unwrap(),
+            IdfTable::load_current(&fresh).await?.unwrap(),
+        );
+        for rank in 0..20 {
+            assert_eq!(table.get(&word(rank)), fresh_table.get(&word(rank)), "{}", word(rank));
+        }
+        for leftover in [format!("{index}.only"), format!("{index}.reorder"), format!("{index}.replaced")] {
+            assert!(fs::metadata(&leftover).await.is_err(), "{leftover}");
+        }
+
+        let missing = root.join("corpus").join("9.xml").to_str().unwrap().to_string();
+        let error = reindex_file(&index, &missing, IndexedBuilder::new(config, corpus.attributes.clone())?, config.merger())
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,552 @@
+use std::{
+    cmp::Reverse,
+    fmt::Display,
+    io::{Error, ErrorKind},
+};
+
+use tokio::fs;
+
+use crate::{
+    boost::DocumentBoosts,
+    config::MergerConfig,
+    generation::Generations,
+    indexed::{save_input_files, IndexMergeSaver, IndexTermProvider, IndexedTerm},
+    layout::IndexLayout,
+    metadata::IndexMetadata,
+    numeric::NumericValues,
+    rank::{DocumentLengths, IdfTable},
+    resolve::load_input_files,
+    segment::Segments,
+    stats::IndexStats,
+    titles::DocumentTitles,
+};
+
+#[cfg(feature = "roaring")]
+use crate::bitmaps::write_bitmaps;
+
+/// What the new document ids are ordered by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReorderBy {
+    /// Longest documents first. They hold most of the terms, so packing them
+    /// together shortens the gaps most postings are written as.
+    Length,
+    /// Ascending value of the numeric field, documents without one last.
+    Numeric,
+}
+
+impl ReorderBy {
+    pub fn parse(raw: &str) -> Result<Self, Error> {
+        match raw {
+            "length" => Ok(Self::Length),
+            "numeric" => Ok(Self::Numeric),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("unknown document order {raw:?}, expected length or numeric"),
+            )),
+        }
+    }
+}
+
+/// The new id of every document and the old id of every new one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Permutation {
+    new: Vec<usize>,
+    old: Vec<usize>,
+}
+
+impl Permutation {
+    /// `old` lists the current ids in their new order.
+    fn from_order(old: Vec<usize>) -> Self {
+        let mut new = vec![0; old.len()];
+        for (i, v) in old.iter().enumerate() {
+            new[*v] = i;
+        }
+        Self { new, old }
+    }
+
+    pub fn len(&self) -> usize {
+        self.new.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.new.is_empty()
+    }
+
+    pub fn new_id(&self, old: usize) -> usize {
+        self.new[old]
+    }
+
+    pub fn old_id(&self, new: usize) -> usize {
+        self.old[new]
+    }
+
+    /// Values kept by document id, put in the new order.
+    fn apply<T: Clone>(&self, values: &[T], what: &str) -> Result<Vec<T>, Error> {
+        if values.len() != self.len() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("{what} holds {} documents, the index {}", values.len(), self.len()),
+            ));
+        }
+        Ok(self.old.iter().map(|v| values[*v].clone()).collect())
+    }
+}
+
+/// What [`reorder`] did to an index.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReorderReport {
+    pub permutation: Permutation,
+    /// Size of `index_part` before the pass.
+    pub postings_before: u64,
+    pub postings_after: u64,
+}
+
+impl Display for ReorderReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "reordered {} documents, postings {} -> {} bytes ({:+})",
+            self.permutation.len(),
+            self.postings_before,
+            self.postings_after,
+            self.postings_after as i64 - self.postings_before as i64
+        )
+    }
+}
+
+pub(crate) async fn exists(path: &str) -> Result<bool, Error> {
+    match fs::metadata(path).await {
+        Ok(_) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+pub(crate) async fn remove_if_exists(directory: &str) -> Result<(), Error> {
+    match fs::remove_dir_all(directory).await {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+pub(crate) fn staged(directory: &str) -> String {
+    format!("{directory}.reorder")
+}
+
+pub(crate) fn replaced(directory: &str) -> String {
+    format!("{directory}.replaced")
+}
+
+/// Finishes or undoes a swap [`reorder`] or [`crate::reindex::reindex_file`]
+/// was cut short in. The rewritten index is complete before the original
+/// moves aside, so a staged one found without the original takes its place.
+pub async fn recover(directory: &str) -> Result<(), Error> {
+    let (staged, replaced) = (staged(directory), replaced(directory));
+    if exists(directory).await? {
+        remove_if_exists(&staged).await?;
+        remove_if_exists(&replaced).await
+    } else if exists(&staged).await? {
+        fs::rename(&staged, directory).await?;
+        remove_if_exists(&replaced).await
+    } else if exists(&replaced).await? {
+        fs::rename(&replaced, directory).await
+    } else {
+        Ok(())
+    }
+}
+
+/// Gives the documents of the index in `directory` new ids ordered `by`, so
+/// postings of documents alike sit together and their gaps encode shorter.
+/// The postings, `files.txt` and every per-document file are rewritten with
+/// the new ids; terms, their counts and ordinals stay as they were.
+///
+/// A parent of [`Generations`] gets the result as a fresh generation that
+/// `CURRENT` is flipped to. A plain index is rewritten next to itself as
+/// `<directory>.reorder` and then renamed over the original, see [`recover`].
+pub async fn reorder<S: Segments>(
+    directory: &str,
+    config: MergerConfig,
+    by: ReorderBy,
+) -> Result<ReorderReport, Error> {
+    let generations = Generations::new(directory);
+    if let Some(current) = generations.current().await? {
+        let (generation, next) = generations.create_next().await?;
+        let report = rewrite::<S>(&generations.directory(current), &next, config, by).await?;
+        generations.flip(generation).await?;
+        return Ok(report);
+    }
+    recover(directory).await?;
+    let (staged, replaced) = (staged(directory), replaced(directory));
+    let report = rewrite::<S>(directory, &staged, config, by).await?;
+    fs::rename(directory, &replaced).await?;
+    fs::rename(&staged, directory).await?;
+    fs::remove_dir_all(&replaced).await?;
+    Ok(report)
+}
+
+async fn order(
+    source: &String,
+    by: ReorderBy,
+    metadata: &IndexMetadata,
+    lengths: &DocumentLengths,
+) -> Result<Permutation, Error> {
+    let mut ids = (0..lengths.lengths.len()).collect::<Vec<_>>();
+    match by {
+        ReorderBy::Length => ids.sort_by_key(|v| Reverse(lengths.lengths[*v])),
+        ReorderBy::Numeric => {
+            if metadata.numeric.is_none() {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("{source} has no numeric field to order by"),
+                ));
+            }
+            let values = NumericValues::load(source).await?;
+            ids.sort_by_key(|v| {
+                let v = values.get(*v);
+                (v.is_none(), v)
+            });
+        }
+    }
+    Ok(Permutation::from_order(ids))
+}
+
+async fn rewrite<S: Segments>(
+    source: &str,
+    destination: &str,
+    config: MergerConfig,
+    by: ReorderBy,
+) -> Result<ReorderReport, Error> {
+    let (source, destination) = (source.to_string(), destination.to_string());
+    let metadata = IndexMetadata::load(&source).await?;
+    let lengths = DocumentLengths::load(&source).await?;
+    let permutation = order(&source, by, &metadata, &lengths).await?;
+    let layout = IndexLayout::detect(&source).await?;
+    fs::create_dir_all(&destination).await?;
+
+    let mut saver =
+        IndexMergeSaver::<S>::create(destination.clone(), config.lexical_block_size(), layout, config.io()).await?;
+    if metadata.phonetic {
+        saver = saver.with_phonetic();
+    }
+    if metadata.permuterm {
+        saver = saver.with_permuterm();
+    }
+    #[cfg(feature = "fst")]
+    if metadata.fst {
+        saver = saver.with_fst();
+    }
+    let mut provider = IndexTermProvider::<S>::new_with_io(&source, config.io()).await?;
+    let mut postings = Vec::new();
+    while let Some(head) = provider.next_head().await? {
+        for (document, usage) in provider.load_postings(&head).await?.iter() {
+            if document >= permutation.len() {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{:?} is in document {document} of {}", head.term, permutation.len()),
+                ));
+            }
+            postings.push((permutation.new_id(document), usage));
+        }
+        // Pushed from the highest id down, each posting goes in at the head of the list.
+        postings.sort_unstable_by_key(|(document, _)| Reverse(*document));
+        let mut term = IndexedTerm::new(head.term);
+        term.use_count = head.use_count;
+        for (document, usage) in postings.drain(..) {
+            term.indexes.push(document, usage);
+        }
+        saver.push(term).await?;
+    }
+    let finished = saver.finish().await?;
+    if let Some(rotations) = finished.permuterm {
+        rotations
+            .save::<S>(&destination, config.lexical_block_size(), layout, config.io())
+            .await?;
+    }
+    #[cfg(feature = "roaring")]
+    if let Some(min_df) = metadata.bitmaps {
+        write_bitmaps::<S>(&destination, min_df).await?;
+    }
+
+    let sources = load_input_files(layout.files(&source)).await?;
+    let sources = permutation.apply(&sources, "files")?;
+    save_input_files(
+        layout.files(&destination),
+        sources.iter().map(|(name, i)| (name.as_str(), *i)),
+    )
+    .await?;
+    let mut zones = Vec::with_capacity(lengths.zones.len());
+    for (zone, column) in lengths.zones.iter() {
+        zones.push((zone.clone(), permutation.apply(column, "zone lengths")?));
+    }
+    DocumentLengths {
+        documents: lengths.documents,
+        lengths: permutation.apply(&lengths.lengths, "lengths")?,
+        zones,
+    }
+    .save(&destination)
+    .await?;
+    if exists(&layout.titles(&source)).await? {
+        let titles = DocumentTitles::load(&source).await?;
+        DocumentTitles {
+            titles: permutation.apply(&titles.titles, "titles")?,
+        }
+        .save(&destination)
+        .await?;
+    }
+    if exists(&layout.boosts(&source)).await? {
+        let boosts = DocumentBoosts::load(&source).await?;
+        DocumentBoosts {
+            boosts: permutation.apply(&boosts.boosts, "boosts")?,
+        }
+        .save(&destination)
+        .await?;
+    }
+    if metadata.numeric.is_some() {
+        let values = NumericValues::load(&source).await?;
+        NumericValues {
+            values: permutation.apply(&values.values, "numeric")?,
+            field: values.field,
+        }
+        .save(&destination)
+        .await?;
+    }
+
+    // Ids cached for the old generation mean other documents now.
+    let generation = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |v| v.as_nanos() as u64)
+        .max(metadata.generation + 1);
+    if let Some(table) = IdfTable::load_current(&source).await? {
+        table.with_generation(generation).save(&destination).await?;
+    }
+    for file in [layout.info(&source), IndexLayout::report(&source)] {
+        if exists(&file).await? {
+            let name = &file[source.len()..];
+            fs::copy(&file, format!("{destination}{name}")).await?;
+        }
+    }
+    if exists(&IndexLayout::stats(&source)).await? {
+        IndexStats {
+            bytes: finished.bytes,
+            ..IndexStats::load(&source).await?
+        }
+        .save(&destination)
+        .await?;
+    }
+    IndexMetadata {
+        generation,
+        ..metadata
+    }
+    .save(&destination)
+    .await?;
+
+    Ok(ReorderReport {
+        permutation,
+        postings_before: fs::metadata(layout.index_part(&source)).await?.len(),
+        postings_after: fs::metadata(layout.index_part(&destination)).await?.len(),
+    })
+}
+
+#[cfg(test)]
+mod tst {
+    use std::{collections::BTreeMap, io::Error, path::Path};
+
+    use tokio::fs;
+
+    use crate::{
+        config::IndexerConfig,
+        generation::Generations,
+        indexed::{verify_index, Dictionary, IndexMerger, IndexParser, IndexedBuilder, TermBound},
+        layout::IndexLayout,
+        metadata::IndexMetadata,
+        parser::ParseController,
+        rank::{DocumentLengths, IdfTable, Scorer},
+        resolve::load_input_files,
+        segment::{CommonSegmentSelector, CommonSegments},
+        stats::IndexStats,
+        testsupport::{scratch, word},
+        titles::DocumentTitles,
+        zones::ZoneSet,
+    };
+
+    use super::{recover, reorder, Permutation, ReorderBy};
+
+    /// Words in the text of each document.
+    const LENGTHS: [usize; 8] = [2, 9, 4, 12, 1, 7, 9, 3];
+
+    async fn build(root: &Path, destination: &str, generations: bool) -> Result<(), Error> {
+        let path = root.join("0.xml").to_str().unwrap().to_string();
+        let content = LENGTHS
+            .iter()
+            .enumerate()
+            .map(|(i, len)| {
+                let text = (0..*len).map(|k| word((i + k) % 12)).collect::<Vec<_>>().join(" ");
+                format!("<title>\n{}\n</title>\n<text>\n{text}\n</text>\n", word(100 + i))
+            })
+            .collect::<String>();
+        fs::write(&path, content).await?;
+        let config = IndexerConfig::new(1000, 6)?;
+        let mut controller = ParseController::<IndexParser, _, _>::new(
+            vec![path],
+            destination.to_string(),
+            root.join("buffer").to_str().unwrap().to_string(),
+            1,
+            IndexedBuilder::new(config, ZoneSet::new(["title", "text"])?)?,
+            IndexMerger::new(config.merger()).with_permuterm().with_idf_top(3),
+        )
+        .with_titles();
+        if generations {
+            controller = controller.with_generations(2);
+        }
+        controller.create_dictionary().await
+    }
+
+    /// Documents with their counts of every term, the ids mapped through `old`.
+    async fn postings(
+        directory: &String,
+        old: impl Fn(usize) -> usize,
+    ) -> Result<BTreeMap<String, Vec<(usize, usize)>>, Error> {
+        let mut dictionary = Dictionary::<CommonSegments>::new(directory).await?;
+        let mut terms = Vec::new();
+        let mut range = dictionary.range(TermBound::Unbounded, TermBound::Unbounded).await?;
+        while let Some((term, _)) = range.next().await? {
+            terms.push(term);
+        }
+        let mut found = BTreeMap::new();
+        for term in terms {
+            let indexes = dictionary.find(&term).await?.unwrap().indexes;
+            let mut documents = indexes
+                .iter()
+                .map(|(document, mut usage)| (old(document), *usage.use_count_mut()))
+                .collect::<Vec<_>>();
+            documents.sort_unstable();
+            found.insert(term, documents);
+        }
+        Ok(found)
+    }
+
+    async fn ranked(directory: &String, old: impl Fn(usize) -> usize) -> Result<Vec<(usize, f64)>, Error> {
+        let scorer = Scorer::new(
+            &CommonSegmentSelector::new(),
+            &[("title", 2.0), ("text", 1.0)],
+            DocumentLengths::load(directory).await?,
+        )?;
+        let mut dictionary = Dictionary::<CommonSegments>::new(directory).await?;
+        let mut ranked = scorer
+            .search(&mut dictionary, &[&word(3), &word(7), &word(102)])
+            .await?
+            .into_iter()
+            .map(|(document, score)| (old(document), score))
+            .collect::<Vec<_>>();
+        ranked.sort_unstable_by_key(|v| v.0);
+        Ok(ranked)
+    }
+
+    #[tokio::test]
+    async fn reordered_ids_map_back_to_the_same_results() -> Result<(), Error> {
+        let root = scratch("reorder").await?;
+        let destination = root.join("res").to_str().unwrap().to_string();
+        build(&root, &destination, false).await?;
+        let before = postings(&destination, |v| v).await?;
+        let ranked_before = ranked(&destination, |v| v).await?;
+        let lengths = DocumentLengths::load(&destination).await?;
+        let titles = DocumentTitles::load(&destination).await?;
+        let layout = IndexLayout::default();
+        let sources = load_input_files(layout.files(&destination)).await?;
+        let wildcard = Dictionary::<CommonSegments>::new(&destination).await?.wildcard("*a*").await?;
+        let generation = IndexMetadata::load(&destination).await?.generation;
+        let stats = IndexStats::load(&destination).await?;
+
+        let report = reorder::<CommonSegments>(&destination, IndexerConfig::new(1000, 6)?.merger(), ReorderBy::Length).await?;
+        let permutation = &report.permutation;
+        assert_eq!(permutation.len(), LENGTHS.len());
+        assert_eq!(report.postings_after, fs::metadata(layout.index_part(&destination)).await?.len());
+        assert!(report.to_string().starts_with("reordered 8 documents"), "{report}");
+        verify_index::<CommonSegments>(&destination).await?;
+        let restated = IndexStats::load(&destination).await?;
+        assert_eq!((restated.occurrences, restated.postings), (stats.occurrences, stats.postings));
+        assert_eq!(restated.bytes.postings, report.postings_after);
+
+        let old = |v| permutation.old_id(v);
+        assert_eq!(postings(&destination, old).await?, before);
+        let ranked_after = ranked(&destination, old).await?;
+        assert_eq!(ranked_after.len(), ranked_before.len());
+        for (after, before) in ranked_after.iter().zip(ranked_before.iter()) {
+            assert_eq!(after.0, before.0);
+            assert!((after.1 - before.1).abs() < 1e-9);
+        }
+
+        let reordered = DocumentLengths::load(&destination).await?;
+        assert!(reordered.lengths.windows(2).all(|v| v[0] >= v[1]), "{:?}", reordered.lengths);
+        let reordered_titles = DocumentTitles::load(&destination).await?;
+        let reordered_sources = load_input_files(layout.files(&destination)).await?;
+        for new in 0..permutation.len() {
+            assert_eq!(permutation.new_id(old(new)), new);
+            assert_eq!(reordered.lengths[new], lengths.lengths[old(new)]);
+            assert_eq!(reordered_titles.titles[new], titles.titles[old(new)]);
+            assert_eq!(reordered_sources[new], sources[old(new)]);
+        }
+        assert_eq!(reordered_titles.get(0), Some(word(103).as_str()));
+        assert_eq!(sources[3], (root.join("0.xml").to_str().unwrap().to_string(), 3));
+
+        let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+        assert_eq!(dictionary.wildcard("*a*").await?, wildcard);
+        assert!(IndexMetadata::load(&destination).await?.generation > generation);
+        assert_eq!(IdfTable::load_current(&destination).await?.map(|v| v.len()), Some(3));
+        for leftover in [format!("{destination}.reorder"), format!("{destination}.replaced")] {
+            assert!(fs::metadata(&leftover).await.is_err(), "{leftover}");
+        }
+        assert!(reorder::<CommonSegments>(&destination, IndexerConfig::new(1000, 6)?.merger(), ReorderBy::Numeric)
+            .await
+            .is_err());
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn interrupted_swaps_are_recovered() -> Result<(), Error> {
+        let root = scratch("reorder_recover").await?;
+        let destination = root.join("res").to_str().unwrap().to_string();
+        let (staged, replaced) = (format!("{destination}.reorder"), format!("{destination}.replaced"));
+        build(&root, &destination, false).await?;
+        let before = postings(&destination, |v| v).await?;
+        let merger = IndexerConfig::new(1000, 6)?.merger();
+
+        // Cut short while staging: the partial copy is dropped.
+        fs::create_dir_all(&staged).await?;
+        fs::write(format!("{staged}/index_part.txt"), "partial").await?;
+        let report = reorder::<CommonSegments>(&destination, merger, ReorderBy::Length).await?;
+        assert_eq!(postings(&destination, |v| report.permutation.old_id(v)).await?, before);
+
+        // Cut short between the renames: the staged copy is complete and moves in.
+        fs::rename(&destination, &staged).await?;
+        fs::create_dir_all(&replaced).await?;
+        recover(&destination).await?;
+        assert_eq!(postings(&destination, |v| report.permutation.old_id(v)).await?, before);
+        assert!(fs::metadata(&staged).await.is_err());
+        assert!(fs::metadata(&replaced).await.is_err());
+
+        let parent = root.join("gens").to_str().unwrap().to_string();
+        build(&root, &parent, true).await?;
+        let generations = Generations::new(parent.clone());
+        let report = reorder::<CommonSegments>(&parent, merger, ReorderBy::Length).await?;
+        assert_eq!(generations.current().await?, Some(2));
+        assert_eq!(generations.list().await?, [1, 2]);
+        let current = generations.current_directory().await?;
+        assert_eq!(postings(&current, |v| report.permutation.old_id(v)).await?, before);
+        assert_eq!(postings(&generations.directory(1), |v| v).await?, before);
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+
+    #[test]
+    fn permutations_invert() {
+        let permutation = Permutation::from_order(vec![2, 0, 1]);
+        assert_eq!((0..3).map(|v| permutation.new_id(v)).collect::<Vec<_>>(), [1, 2, 0]);
+        assert_eq!(permutation.apply(&["a", "b", "c"], "names").unwrap(), ["c", "a", "b"]);
+        assert!(permutation.apply(&["a"], "names").is_err());
+        assert!(ReorderBy::parse("random").is_err());
+    }
+}
@@ -1,6 +1,6 @@
-use core::panic;
 use std::{
-    io::Error,
+    fmt::{self, Display, Formatter},
+    io::{Error, ErrorKind},
     marker::PhantomData,
     sync::{atomic::AtomicU32, Arc},
 };
@@ -8,20 +8,77 @@ use std::{
 use async_trait::async_trait;
 use tokio::io::AsyncWriteExt;
 use tokio::{
-    fs::{self, File},
+    fs::File,
     io::BufWriter,
 };
 
+use crate::config::IoTuning;
+use crate::doc_id::next_chunk;
+use crate::numeric::parse_number;
+use crate::token_stream;
+use crate::warnings::{WarningKind, Warnings};
+use crate::zones::{ZoneId, ZoneSet};
 use crate::reader::{
-    CharInterpretation, CharType, Reader, ReaderResult, WordOption, WordProvider, XmlWordProvider,
+    CharInterpretation, CharType, CommCharInterpreter, Kept, PushBack, Reader, ReaderResult, WordOption,
+    WordProvider, XmlWordProvider,
 };
 
-use save::u8::{read_char, CommU8Provider, U8Provider};
+use save::u8::{read_char, OffsetU8Provider, U8Provider};
+use save::writer::variable_encode_u64;
+
+/// Whether the reader can match `<tag>`: tag names are read like words, so
+/// the name has to be lowercase letters only.
+pub fn reads_tag<Interpreter: CharInterpretation>(tag: &str) -> bool {
+    tag.chars().all(|c| match Interpreter::interpret_character(c) {
+        CharType::Letter(mut lower) => lower.next() == Some(c) && lower.next().is_none(),
+        _ => false,
+    })
+}
+
+/// A chunk read with [`RepeatedXmlReader::with_checked_zones`] holds other
+/// zones than the reader's.
+#[derive(Debug)]
+pub struct ZoneMismatch(pub String);
+
+impl Display for ZoneMismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "zones don't match: {}", self.0)
+    }
+}
+
+impl std::error::Error for ZoneMismatch {}
+
+/// Whether `error` is a [`ZoneMismatch`].
+pub fn is_zone_mismatch(error: &Error) -> bool {
+    error.get_ref().is_some_and(|v| v.is::<ZoneMismatch>())
+}
 
 // struct Position {
 //     inside : String
 // }
 
+/// Bytes of words [`RepeatedXmlReader::divide_write`] gathers before
+/// writing them.
+pub const WRITE_BATCH_BYTES: usize = 64 * 1024;
+
+/// What [`RepeatedXmlReader::divide_write`] puts after every word. The
+/// reader takes both for a delimiter, so the chunks index the same.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Separator {
+    #[default]
+    Space,
+    Newline,
+}
+
+impl Separator {
+    pub fn as_char(self) -> char {
+        match self {
+            Self::Space => ' ',
+            Self::Newline => '\n',
+        }
+    }
+}
+
 #[derive(PartialEq, Eq)]
 enum Position {
     Inside,
@@ -29,31 +86,171 @@ enum Position {
 }
 
 pub struct RepeatedXmlReader<Provider: U8Provider + Send, Interpreter: CharInterpretation + Send> {
-    reader: Provider,
+    reader: OffsetU8Provider<Provider>,
     word_provider: XmlWordProvider,
+    pushback: PushBack,
     position: Position,
-    attribute_order: Arc<Vec<String>>,
+    attribute_order: ZoneSet,
     attribute_index: usize,
+    numeric_tag: Option<String>,
+    numeric: Option<u64>,
+    excluded: Arc<Vec<String>>,
+    warnings: Option<Warnings>,
+    checked_zones: bool,
+    /// Why a reader checking its zones stopped, handed out by `next_word`.
+    mismatch: Option<Error>,
+    separator: Separator,
+    write_batch: usize,
+    io: IoTuning,
     interpreter: PhantomData<Interpreter>,
 }
 
 impl<Provider: U8Provider + Send, Interpreter: CharInterpretation + Send>
     RepeatedXmlReader<Provider, Interpreter>
 {
-    pub async fn new(reader: Provider, attribute_order: Arc<Vec<String>>) -> Result<Self, Error> {
-        if attribute_order.len() == 0 {
-            panic!("attribute_order.len() is 0")
-        }
+    pub async fn new(reader: Provider, attribute_order: ZoneSet) -> Result<Self, Error> {
         Ok(Self {
-            reader,
+            reader: OffsetU8Provider::new(reader),
             word_provider: XmlWordProvider::new(),
+            pushback: PushBack::XML,
             position: Position::Outside,
             attribute_order,
             attribute_index: 0,
+            numeric_tag: None,
+            numeric: None,
+            excluded: Arc::new(Vec::new()),
+            warnings: None,
+            checked_zones: false,
+            mismatch: None,
+            separator: Separator::default(),
+            write_batch: WRITE_BATCH_BYTES,
+            io: IoTuning::default(),
             interpreter: PhantomData::<Interpreter>,
         })
     }
 
+    /// Skip `<tag>` elements of `excluded` whole, wherever they open: their
+    /// content is neither tokenized nor scanned for zone tags.
+    pub fn with_excluded(mut self, excluded: Arc<Vec<String>>) -> Self {
+        self.excluded = excluded;
+        self
+    }
+
+    /// Send what the reader gets past without dropping the document to
+    /// `warnings`, see [`crate::warnings::WarningKind`].
+    pub fn with_warnings(mut self, warnings: Warnings) -> Self {
+        self.word_provider = XmlWordProvider::new().with_warnings(warnings.clone());
+        self.warnings = Some(warnings);
+        self
+    }
+
+    /// Fail on a zone tag, opening or closing, other than the one expected
+    /// next instead of dropping the document, for chunks of
+    /// [`Self::divide_write`]: those hold nothing but the zones, so a
+    /// mismatch means they were written with other zones than the reader's.
+    pub fn with_checked_zones(mut self) -> Self {
+        self.checked_zones = true;
+        self
+    }
+
+    /// Put `separator` after every word [`Self::divide_write`] writes.
+    pub fn with_separator(mut self, separator: Separator) -> Self {
+        self.separator = separator;
+        self
+    }
+
+    /// Write the words [`Self::divide_write`] gathered once they reach
+    /// `bytes`, [`WRITE_BATCH_BYTES`] unless set; 0 writes every word on its own.
+    pub fn with_write_batch(mut self, bytes: usize) -> Self {
+        self.write_batch = bytes;
+        self
+    }
+
+    /// Write the chunks of [`Self::divide_write`] and
+    /// [`Self::divide_write_binary`] with the buffers of `io`.
+    pub fn with_io(mut self, io: IoTuning) -> Self {
+        self.io = io;
+        self
+    }
+
+    /// Also read the value of `<tag>` when it stands outside the zones, see
+    /// [`ZoneRepeatedReader::take_numeric`].
+    pub fn with_numeric_tag(mut self, tag: String) -> Self {
+        self.numeric_tag = Some(tag);
+        self
+    }
+
+    /// Reads up to the next `<` and keeps the value if it parses as a number.
+    async fn read_numeric(&mut self) -> Option<()> {
+        if self.word_provider.consume() != Some(Kept::Char('>')) {
+            while read_char(&mut self.reader).await? != '>' {}
+        }
+        let mut raw = String::new();
+        loop {
+            match read_char(&mut self.reader).await? {
+                '<' => break,
+                c => raw.push(c),
+            }
+        }
+        self.numeric = parse_number(&raw);
+        Some(())
+    }
+
+    /// Reads past the element whose name `tag` was just read, up to the
+    /// close matching it. Other tags inside are not looked at, nested
+    /// elements of the same name are counted.
+    async fn skip_element(&mut self, tag: &str) -> Option<()> {
+        let mut previous = match self.word_provider.consume() {
+            Some(Kept::Char(c)) => c,
+            Some(Kept::Eof) => return None,
+            None => ' ',
+        };
+        while previous != '>' {
+            let c = read_char(&mut self.reader).await?;
+            if c == '>' && previous == '/' {
+                return Some(());
+            }
+            previous = c;
+        }
+        let mut depth = 1;
+        while depth > 0 {
+            if read_char(&mut self.reader).await? != '<' {
+                continue;
+            }
+            let closing = self.reader.peek_u8().await? == b'/';
+            if closing {
+                self.reader.next_u8().await?;
+            }
+            let mut c = read_char(&mut self.reader).await?;
+            let mut name = String::new();
+            while c.is_alphabetic() {
+                name.extend(c.to_lowercase());
+                c = read_char(&mut self.reader).await?;
+            }
+            if name != tag {
+                continue;
+            }
+            if closing {
+                depth -= 1;
+                continue;
+            }
+            let mut previous = c;
+            while previous != '>' {
+                let c = read_char(&mut self.reader).await?;
+                if c == '>' && previous == '/' {
+                    depth -= 1;
+                }
+                previous = c;
+            }
+            depth += 1;
+        }
+        Some(())
+    }
+
+    /// Writes `skips` documents per `{index}.xml` chunk to `resdir`, every
+    /// zone between its tags on lines of their own and its words joined by
+    /// the [`Separator`]. Words are gathered into batches of the size of
+    /// [`Self::with_write_batch`], each zone ending one.
     pub async fn divide_write(
         &mut self,
         resdir: String,
@@ -61,58 +258,124 @@ impl<Provider: U8Provider + Send, Interpreter: CharInterpretation + Send>
         mut index: Arc<AtomicU32>,
     ) -> Option<()> {
         let skips = skips as u64 * self.zones_len() as u64;
-        async fn wr(resdir: &String, index: &mut Arc<AtomicU32>) -> Option<BufWriter<File>> {
-            let index = index.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-            let name = format!("{}\\{}.xml", resdir.clone(), index);
-            fs::remove_file(&name).await;
-            // println!("{}", name);
-            // dbg!(&name);
-            Some(BufWriter::new(File::create(name).await.unwrap()))
+        async fn wr(resdir: &str, index: &mut Arc<AtomicU32>, io: IoTuning) -> Option<BufWriter<File>> {
+            let index = match next_chunk(index) {
+                Ok(v) => v,
+                Err(e) => {
+                    log::error!("{e}");
+                    return None;
+                }
+            };
+            let name = format!("{}/{}.xml", resdir, index);
+            Some(io.writer(File::create(name).await.unwrap()))
         }
 
-        let mut cur_file = wr(&resdir, &mut index).await?;
+        let mut cur_file = wr(&resdir, &mut index, self.io).await?;
         let mut skip = skips;
-        // cur_file
-        //     .write(format!("<{}>\n", self.zone()).as_bytes())
-        //     .await
-        //     .ok()?;
+        let mut batch = String::with_capacity(self.write_batch);
         let mut has_next = true;
-        while let Some(s) = self.next_word().await {
+        while let Some(s) = self.next_word().await.ok()? {
             if skip == 0 {
-                // println!("Zero");
                 skip = skips;
                 cur_file.flush().await.unwrap();
-                cur_file = wr(&resdir, &mut index).await?;
+                cur_file = wr(&resdir, &mut index, self.io).await?;
             }
             if has_next {
-                cur_file
-                    .write(format!("<{}>\n", self.zone()).as_bytes())
-                    .await
-                    .ok()?;
+                batch.push('<');
+                batch.push_str(self.zone());
+                batch.push_str(">\n");
                 has_next = false;
             }
             match s {
                 ReaderResult::Word(w) => {
-                    cur_file.write(w.as_bytes()).await.ok()?;
-                    cur_file.write(" ".as_bytes()).await.ok()?;
+                    batch.push_str(&w);
+                    batch.push(self.separator.as_char());
+                    if batch.len() < self.write_batch {
+                        continue;
+                    }
                 }
                 ReaderResult::AttributeEnd => {
-                    // println!("AttributeEndP {} {skip}", &self.zone());
-                    cur_file
-                        .write(format!("\n<{}/>\n", self.zone()).as_bytes())
-                        .await
-                        .ok()?;
+                    batch.push_str("\n</");
+                    batch.push_str(self.zone());
+                    batch.push_str(">\n");
                     self.transform_zone().await;
                     skip -= 1;
                     has_next = true;
-                    // if ((skips - skip) as usize) % self.zones_len() == 0 {
+                }
+                ReaderResult::Malformed(w) => {
+                    log::warn!("{w}");
+                    continue;
                 }
             }
+            cur_file.write_all(batch.as_bytes()).await.ok()?;
+            batch.clear();
         }
+        cur_file.write_all(batch.as_bytes()).await.ok()?;
         cur_file.flush().await.unwrap();
 
         Some(())
-        // loop {}
+    }
+
+    /// Like [`Self::divide_write`], but writes `skips` documents per
+    /// `{index}.tok` chunk in the format of [`crate::token_stream`], so the
+    /// indexer reads the words as they are. Malformed documents are left out
+    /// whole.
+    pub async fn divide_write_binary(
+        &mut self,
+        resdir: String,
+        skips: u16,
+        index: Arc<AtomicU32>,
+    ) -> Result<(), Error> {
+        let mut header = Vec::new();
+        token_stream::encode_header(&self.attribute_order, &mut header);
+        let mut cur_file: Option<BufWriter<File>> = None;
+        let mut written = 0u16;
+        let mut document = Vec::new();
+        while let Some(s) = self.next_word().await? {
+            match s {
+                ReaderResult::Word(w) => token_stream::encode_word(&w, &mut document),
+                ReaderResult::AttributeEnd => {
+                    variable_encode_u64(token_stream::ZONE_END, &mut document);
+                    self.transform_zone().await;
+                    if self.attribute_index != 0 {
+                        continue;
+                    }
+                    let file = match &mut cur_file {
+                        Some(file) => file,
+                        None => {
+                            let index = next_chunk(&index)?;
+                            let name = format!("{resdir}/{index}.{}", token_stream::EXTENSION);
+                            let mut file = self.io.writer(File::create(name).await?);
+                            file.write_all(&header).await?;
+                            cur_file.insert(file)
+                        }
+                    };
+                    if let Some(v) = self.take_numeric() {
+                        let mut numeric = Vec::new();
+                        variable_encode_u64(token_stream::NUMERIC, &mut numeric);
+                        variable_encode_u64(v, &mut numeric);
+                        file.write_all(&numeric).await?;
+                    }
+                    variable_encode_u64(token_stream::DOCUMENT_END, &mut document);
+                    file.write_all(&document).await?;
+                    document.clear();
+                    written += 1;
+                    if written >= skips.max(1) {
+                        file.flush().await?;
+                        cur_file = None;
+                        written = 0;
+                    }
+                }
+                ReaderResult::Malformed(w) => {
+                    log::warn!("{w}");
+                    document.clear();
+                }
+            }
+        }
+        if let Some(mut file) = cur_file {
+            file.flush().await?;
+        }
+        Ok(())
     }
 }
 
@@ -124,30 +387,113 @@ impl<
 {
     type UProvider = Provider;
     type Interpreter = Interpreter;
-    async fn next_word(&mut self) -> Option<ReaderResult> {
-        let current_attribute = self.attribute_order[self.attribute_index].as_str();
+    async fn next_word(&mut self) -> Result<Option<ReaderResult>, Error> {
+        match self.read_next().await {
+            Some(v) => Ok(Some(v)),
+            None => {
+                if let Some(e) = self.mismatch.take() {
+                    return Err(e);
+                }
+                if let Some(e) = self.reader.take_error() {
+                    return Err(e);
+                }
+                if self.position == Position::Inside || self.attribute_index != 0 {
+                    let warning = format!(
+                        "unexpected end of file inside <{}> at byte {}",
+                        self.zone(),
+                        self.reader.offset()
+                    );
+                    self.drop_document();
+                    return Ok(Some(ReaderResult::Malformed(warning)));
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    fn position(&self) -> u64 {
+        self.reader.consumed_bytes() - self.word_provider.pending_bytes()
+    }
+
+    fn input_digest(&self) -> Option<[u8; 32]> {
+        self.reader.digest()
+    }
+}
+
+impl<Provider: U8Provider + Send, Interpreter: CharInterpretation + Send>
+    RepeatedXmlReader<Provider, Interpreter>
+{
+    /// Stops the reader with `message` if it checks its zones.
+    fn check_zones(&mut self, message: String) -> Option<()> {
+        if self.checked_zones {
+            self.mismatch = Some(Error::new(ErrorKind::InvalidData, ZoneMismatch(message)));
+            return None;
+        }
+        Some(())
+    }
+
+    fn drop_document(&mut self) {
+        self.position = Position::Outside;
+        self.attribute_index = 0;
+        self.numeric = None;
+        self.word_provider.reset();
+    }
+
+    async fn read_next(&mut self) -> Option<ReaderResult> {
+        let current_attribute = self.zone();
         loop {
             while Position::Outside == self.position {
                 if read_char(&mut self.reader).await? == '<' {
-                    let c = read_char(&mut self.reader).await?;
-                    if c == '/' {
-                        while read_char(&mut self.reader).await? != '>' {}
+                    if self.reader.peek_u8().await? == b'/' {
+                        self.reader.next_u8().await?;
+                        let mut c = read_char(&mut self.reader).await?;
+                        let mut name = String::new();
+                        while c.is_alphabetic() {
+                            name.extend(c.to_lowercase());
+                            c = read_char(&mut self.reader).await?;
+                        }
+                        if self.attribute_order.contains(&name) {
+                            self.check_zones(format!(
+                                "</{name}> outside of <{current_attribute}> at byte {}",
+                                self.reader.offset()
+                            ))?;
+                            if let Some(warnings) = &self.warnings {
+                                warnings.emit(
+                                    WarningKind::UnexpectedClose,
+                                    self.reader.offset(),
+                                    format!("</{name}> outside of <{current_attribute}>"),
+                                );
+                            }
+                        }
+                        while c != '>' {
+                            c = read_char(&mut self.reader).await?;
+                        }
                     } else {
-                        let str = if c == ' ' { None } else { Some(c.to_string()) };
-
+                        // Tag names are folded whatever the interpreter does to words.
                         let str = self
                             .word_provider
-                            .next_word::<Interpreter, Provider>(&mut self.reader, str)
+                            .next_word::<CommCharInterpreter, OffsetU8Provider<Provider>>(&mut self.reader, self.pushback, None)
                             .await?;
                         match str {
                             WordOption::Word(str) => {
                                 if str == current_attribute {
-                                    if self.word_provider.consume() != Some('>') {
+                                    if self.word_provider.consume() != Some(Kept::Char('>')) {
                                         while read_char(&mut self.reader).await? != '>' {}
                                     }
                                     self.position = Position::Inside;
                                     break;
                                 }
+                                if self.attribute_order.contains(&str) {
+                                    self.check_zones(format!(
+                                        "<{str}> where <{current_attribute}> was expected at byte {}",
+                                        self.reader.offset()
+                                    ))?;
+                                }
+                                if self.numeric_tag.as_ref() == Some(&str) {
+                                    self.read_numeric().await?;
+                                } else if self.excluded.contains(&str) {
+                                    self.skip_element(&str).await?;
+                                }
                             }
                             WordOption::Empty => {}
                         }
@@ -155,11 +501,7 @@ impl<
                 }
             }
 
-            let next = if let Some(v) = self.word_provider.consume() {
-                v
-            } else {
-                read_char(&mut self.reader).await?
-            };
+            let next = self.word_provider.next_char(&mut self.reader).await?;
 
             let next = Interpreter::interpret_character(next);
             match next {
@@ -170,7 +512,7 @@ impl<
                     }
                     if let WordOption::Word(w) = self
                         .word_provider
-                        .next_word::<Interpreter, Provider>(&mut self.reader, Some(str))
+                        .next_word::<Interpreter, OffsetU8Provider<Provider>>(&mut self.reader, self.pushback, Some(str))
                         .await?
                     {
                         return Some(ReaderResult::Word(w));
@@ -181,26 +523,60 @@ impl<
                     str.push(next);
                     if let WordOption::Word(w) = self
                         .word_provider
-                        .next_word::<Interpreter, Provider>(&mut self.reader, Some(str))
+                        .next_word::<Interpreter, OffsetU8Provider<Provider>>(&mut self.reader, self.pushback, Some(str))
                         .await?
                     {
                         return Some(ReaderResult::Word(w));
                     }
                 }
                 CharType::Delimiter(d) => {
-                    if d == '<'
-                        && read_char(&mut self.reader).await? == '/'
-                        && self
+                    if d == '<' {
+                        let closing = self.reader.peek_u8().await? == b'/';
+                        if closing {
+                            self.reader.next_u8().await?;
+                        }
+                        let tag = match self
                             .word_provider
-                            .next_word::<Interpreter, Provider>(&mut self.reader, None)
+                            .next_word::<CommCharInterpreter, OffsetU8Provider<Provider>>(&mut self.reader, self.pushback, None)
                             .await?
-                            .contains(current_attribute)
-                    {
-                        self.position = Position::Outside;
-                        return Some(ReaderResult::AttributeEnd);
+                        {
+                            WordOption::Word(tag) => tag,
+                            WordOption::Empty => continue,
+                        };
+                        if closing && tag == current_attribute {
+                            self.position = Position::Outside;
+                            return Some(ReaderResult::AttributeEnd);
+                        }
+                        if !closing && self.excluded.contains(&tag) {
+                            self.skip_element(&tag).await?;
+                            continue;
+                        }
+                        if self.attribute_order.contains(&tag) {
+                            let warning = if closing {
+                                format!(
+                                    "unbalanced </{tag}> inside <{current_attribute}> at byte {}",
+                                    self.reader.offset()
+                                )
+                            } else {
+                                format!(
+                                    "unclosed <{current_attribute}> before <{tag}> at byte {}",
+                                    self.reader.offset()
+                                )
+                            };
+                            self.check_zones(warning.clone())?;
+                            let tag_closed = self.word_provider.consume() == Some(Kept::Char('>'));
+                            self.drop_document();
+                            if !closing && tag == self.attribute_order.first() {
+                                // Already standing at the start of the next document.
+                                if !tag_closed {
+                                    while read_char(&mut self.reader).await? != '>' {}
+                                }
+                                self.position = Position::Inside;
+                            }
+                            return Some(ReaderResult::Malformed(warning));
+                        }
                     }
                 }
-                CharType::EOF => return None,
             }
         }
     }
@@ -215,12 +591,16 @@ impl<Provider: U8Provider + Send, Interpreter: CharInterpretation + Send> ZoneRe
         self.attribute_index %= self.attribute_order.len();
     }
 
-    fn zone(&self) -> &'_ str {
-        self.attribute_order[self.attribute_index].as_str()
+    fn zone_id(&self) -> ZoneId {
+        self.attribute_order.id_at(self.attribute_index).unwrap()
     }
 
-    fn zones_len(&self) -> usize {
-        self.attribute_order.len()
+    fn zone_set(&self) -> &ZoneSet {
+        &self.attribute_order
+    }
+
+    fn take_numeric(&mut self) -> Option<u64> {
+        self.numeric.take()
     }
 }
 
@@ -228,61 +608,453 @@ impl<Provider: U8Provider + Send, Interpreter: CharInterpretation + Send> ZoneRe
 pub trait ZoneRepeatedReader: Reader {
     async fn transform_zone(&mut self);
 
-    fn zone(&self) -> &'_ str;
+    /// The zone being read.
+    fn zone_id(&self) -> ZoneId;
+
+    /// Zones the reader goes through, in order.
+    fn zone_set(&self) -> &ZoneSet;
 
-    fn zones_len(&self) -> usize;
+    fn zone(&self) -> &'static str {
+        self.zone_set().name(self.zone_id())
+    }
+
+    fn zones_len(&self) -> usize {
+        self.zone_set().len()
+    }
+
+    /// Numeric field of the current document, once it has been read.
+    fn take_numeric(&mut self) -> Option<u64> {
+        None
+    }
 }
 
 #[cfg(test)]
 mod tst {
     use std::{
-        io::Error,
+        io::{Error, ErrorKind},
+        path::Path,
         sync::{atomic::AtomicU32, Arc},
     };
 
-    use save::u8::CommU8Provider;
+    use save::u8::{CommU8Provider, SyncU8Provider, U8Provider};
     use tokio::{
         fs::File,
         io::BufReader,
         task::{self, JoinHandle},
     };
 
-    use crate::reader::{CommCharInterpreter, Reader, ReaderResult};
+    use crate::{
+        config::IoTuning,
+        reader::{CommCharInterpreter, Reader, ReaderResult},
+        testsupport::{scratch, CorpusSpec},
+        warnings::{WarningKind, Warnings, MAX_TOKEN_BYTES, WARNINGS_PER_FILE},
+        zones::ZoneSet,
+    };
 
-    use super::{RepeatedXmlReader, ZoneRepeatedReader};
+    use super::{RepeatedXmlReader, Separator, ZoneRepeatedReader};
+
+    /// Splits `file` into chunks of 10 documents under `directory`, with
+    /// the buffers of `io`, and returns them in order.
+    async fn split(
+        file: &str,
+        directory: &Path,
+        separator: Separator,
+        batch: usize,
+        io: IoTuning,
+    ) -> Result<Vec<String>, Error> {
+        tokio::fs::create_dir_all(directory).await?;
+        let index = Arc::new(AtomicU32::new(0));
+        RepeatedXmlReader::<_, CommCharInterpreter>::new(
+            CommU8Provider::new(io.chunk_reader(File::open(file).await?)),
+            ZoneSet::new(["title", "text"])?,
+        )
+        .await?
+        .with_io(io)
+        .with_separator(separator)
+        .with_write_batch(batch)
+        .divide_write(directory.to_str().unwrap().to_string(), 10, index.clone())
+        .await;
+        let mut chunks = Vec::new();
+        for i in 0..index.load(std::sync::atomic::Ordering::SeqCst) {
+            chunks.push(tokio::fs::read_to_string(directory.join(format!("{i}.xml"))).await?);
+        }
+        Ok(chunks)
+    }
+
+    /// Zone and word of everything the checked reader finds in `chunk`.
+    async fn read_chunk(chunk: &Path) -> Result<Vec<String>, Error> {
+        let mut xml = RepeatedXmlReader::<_, CommCharInterpreter>::new(
+            CommU8Provider::new(BufReader::new(File::open(chunk).await?)),
+            ZoneSet::new(["title", "text"])?,
+        )
+        .await?
+        .with_checked_zones();
+        let mut read = vec![];
+        while let Some(v) = xml.next_word().await? {
+            match v {
+                ReaderResult::Word(w) => read.push(format!("{}:{w}", xml.zone())),
+                ReaderResult::AttributeEnd => xml.transform_zone().await,
+                ReaderResult::Malformed(w) => panic!("{w}"),
+            }
+        }
+        Ok(read)
+    }
 
     #[tokio::test]
     async fn reader_test() -> Result<(), Error> {
         let mut xml = RepeatedXmlReader::<_, CommCharInterpreter>::new(
-            CommU8Provider::new(BufReader::new(File::open(r#".\test\ha.xml"#).await?)),
-            Arc::new(vec!["title".to_string(), "text".to_string()]),
+            CommU8Provider::new(BufReader::new(File::open(concat!(env!("CARGO_MANIFEST_DIR"), "/test/ha.xml")).await?)),
+            ZoneSet::new(["title", "text"])?,
+        )
+        .await?;
+        let mut read = vec![];
+        while let Some(kar) = xml.next_word().await? {
+            match kar {
+                ReaderResult::Word(w) => read.push(w),
+                ReaderResult::AttributeEnd => {
+                    read.push(format!("AttributeEnd {}", &xml.zone()));
+                    xml.transform_zone().await;
+                }
+                ReaderResult::Malformed(w) => read.push(format!("Malformed {w}")),
+            }
+        }
+        assert_eq!(
+            read,
+            [
+                "cra",
+                "AttributeEnd title",
+                "gra",
+                "AttributeEnd text",
+                "hcra",
+                "AttributeEnd title",
+                "lgra",
+                "AttributeEnd text"
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn resync_after_truncated_close() -> Result<(), Error> {
+        let path = std::env::temp_dir().join(format!("resync_{}.xml", std::process::id()));
+        tokio::fs::write(
+            &path,
+            "<title>\none\n</title>\n<text>\nfirst\n</text>\n\
+             <title>\ntwo\n</title>\n<text>\nbroken\n</tex\n\
+             <title>\nthree\n</title>\n<text>\nthird\n</text>\n\
+             <title>\nfour\n</title>\n<text>\nfourth\n</text>\n",
+        )
+        .await?;
+        let mut xml = RepeatedXmlReader::<_, CommCharInterpreter>::new(
+            CommU8Provider::new(BufReader::new(File::open(&path).await?)),
+            ZoneSet::new(["title", "text"])?,
+        )
+        .await?;
+        let mut documents = vec![vec![]];
+        let mut warnings = vec![];
+        while let Some(kar) = xml.next_word().await? {
+            match kar {
+                ReaderResult::Word(w) => documents.last_mut().unwrap().push(w),
+                ReaderResult::AttributeEnd => {
+                    xml.transform_zone().await;
+                    if xml.zone() == "title" {
+                        documents.push(vec![]);
+                    }
+                }
+                ReaderResult::Malformed(w) => {
+                    warnings.push(w);
+                    documents.last_mut().unwrap().clear();
+                }
+            }
+        }
+        tokio::fs::remove_file(&path).await?;
+
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("at byte 90"), "{}", warnings[0]);
+        assert_eq!(
+            documents,
+            vec![
+                vec!["one", "first"],
+                vec!["three", "third"],
+                vec!["four", "fourth"],
+                vec![]
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn position_follows_words() -> Result<(), Error> {
+        let path = std::env::temp_dir().join(format!("rep_position_{}.xml", std::process::id()));
+        let content = "<title>\nstraße\n</title>\n<text>\nнаївна café，über。 end\n</text>\n";
+        tokio::fs::write(&path, content).await?;
+        let mut xml = RepeatedXmlReader::<_, CommCharInterpreter>::new(
+            CommU8Provider::new(BufReader::new(File::open(&path).await?)),
+            ZoneSet::new(["title", "text"])?,
+        )
+        .await?;
+        let mut ends = vec![];
+        while let Some(kar) = xml.next_word().await? {
+            match kar {
+                ReaderResult::Word(w) => {
+                    ends.push((content.find(&w).unwrap() + w.len()) as u64);
+                    assert_eq!(xml.position(), *ends.last().unwrap(), "{w}");
+                }
+                ReaderResult::AttributeEnd => xml.transform_zone().await,
+                ReaderResult::Malformed(w) => panic!("{w}"),
+            }
+        }
+        tokio::fs::remove_file(&path).await?;
+        assert_eq!(ends.len(), 5);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sync_provider_reads_the_same() -> Result<(), Error> {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/test/ha.xml").to_string();
+        async fn words<P: U8Provider + Send>(provider: P) -> Result<Vec<(String, u64)>, Error> {
+            let mut xml = RepeatedXmlReader::<_, CommCharInterpreter>::new(
+                provider,
+                ZoneSet::new(["title", "text"])?,
+            )
+            .await?;
+            let mut read = vec![];
+            while let Some(kar) = xml.next_word().await? {
+                match kar {
+                    ReaderResult::Word(w) => read.push((w, xml.position())),
+                    ReaderResult::AttributeEnd => xml.transform_zone().await,
+                    ReaderResult::Malformed(w) => read.push((w, xml.position())),
+                }
+            }
+            Ok(read)
+        }
+        let expected = words(CommU8Provider::from_path(&path).await?).await?;
+        assert_eq!(expected.len(), 4);
+        assert_eq!(words(SyncU8Provider::from_path(&path).await?).await?, expected);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn warnings_leave_documents_whole() -> Result<(), Error> {
+        let path = std::env::temp_dir().join(format!("rep_warnings_{}.xml", std::process::id()));
+        tokio::fs::write(
+            &path,
+            format!(
+                "<title>\none&hellip;\n</title>\n<text>\nfirst &nbsp; {}\n</text>\n</text>\n\
+                 <title>\ntwo\n</title>\n</title>\n<text>\nsecond\n</text>\n</page>\n",
+                "q".repeat(MAX_TOKEN_BYTES + 1)
+            ),
+        )
+        .await?;
+        let warnings = Warnings::new(WARNINGS_PER_FILE);
+        let mut xml = RepeatedXmlReader::<_, CommCharInterpreter>::new(
+            CommU8Provider::new(BufReader::new(File::open(&path).await?)),
+            ZoneSet::new(["title", "text"])?,
+        )
+        .await?
+        .with_warnings(warnings.clone());
+        let mut read = vec![];
+        while let Some(kar) = xml.next_word().await? {
+            match kar {
+                ReaderResult::Word(w) => read.push(w),
+                ReaderResult::AttributeEnd => xml.transform_zone().await,
+                ReaderResult::Malformed(w) => panic!("{w}"),
+            }
+        }
+        tokio::fs::remove_file(&path).await?;
+        assert_eq!(read, ["one", "first", &"q".repeat(MAX_TOKEN_BYTES), "two", "second"]);
+        let summary = warnings.take();
+        assert_eq!(summary.to_string(), "unknown_entity 2, overlong_token 1, unexpected_close 2");
+        let closes = summary
+            .kept
+            .iter()
+            .filter(|v| v.kind == WarningKind::UnexpectedClose)
+            .map(|v| v.context.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(closes, ["</text> outside of <title>", "</title> outside of <text>"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn excluded_elements_are_skipped() -> Result<(), Error> {
+        let path = std::env::temp_dir().join(format!("excluded_{}.xml", std::process::id()));
+        tokio::fs::write(
+            &path,
+            "<page>\n<title>\nfirst\n</title>\n\
+             <comment>\nfixed <text> typo in title\n<comment>nested</comment> after nested\n</comment>\n\
+             <text>\nbody <comment kind=\"inline\">hidden <text>inside</text></comment> visible\n</text>\n</page>\n\
+             <page>\n<title>\nsecond\n</title>\n<comment/>\n<text>\nplain\n</text>\n</page>\n",
         )
         .await?;
-        while let Some(kar) = xml.next_word().await {
+        let mut xml = RepeatedXmlReader::<_, CommCharInterpreter>::new(
+            CommU8Provider::new(BufReader::new(File::open(&path).await?)),
+            ZoneSet::new(["title", "text"])?,
+        )
+        .await?
+        .with_excluded(Arc::new(vec!["comment".to_string()]));
+        let mut read = vec![];
+        while let Some(kar) = xml.next_word().await? {
             match kar {
-                ReaderResult::Word(w) => println!("{w}",),
+                ReaderResult::Word(w) => read.push(w),
                 ReaderResult::AttributeEnd => {
-                    println!("AttributeEnd {}", &xml.zone());
+                    read.push(format!("AttributeEnd {}", &xml.zone()));
                     xml.transform_zone().await;
                 }
+                ReaderResult::Malformed(w) => read.push(format!("Malformed {w}")),
             }
         }
+        tokio::fs::remove_file(&path).await?;
+        assert_eq!(
+            read,
+            [
+                "first",
+                "AttributeEnd title",
+                "body",
+                "visible",
+                "AttributeEnd text",
+                "second",
+                "AttributeEnd title",
+                "plain",
+                "AttributeEnd text"
+            ]
+        );
         Ok(())
     }
 
     #[tokio::test]
     async fn gra() -> Result<(), Error> {
-        let file = File::open(r#".\test\ha.xml"#).await?;
+        let root = std::env::temp_dir().join(format!("rep_divide_write_{}", std::process::id()));
+        let _ = tokio::fs::remove_dir_all(&root).await;
+        tokio::fs::create_dir_all(&root).await?;
+        let file = File::open(concat!(env!("CARGO_MANIFEST_DIR"), "/test/ha.xml")).await?;
         let index = Arc::new(AtomicU32::new(0));
         let mut xml = RepeatedXmlReader::<_, CommCharInterpreter>::new(
-            CommU8Provider::new(BufReader::with_capacity(1024 * 1024, file)),
-            Arc::new(vec!["title".to_string(), "text".to_string()]),
+            CommU8Provider::new(IoTuning::default().chunk_reader(file)),
+            ZoneSet::new(["title", "text"])?,
         )
         .await
         .unwrap();
-        xml.divide_write(".\\tvex".to_string(), 1, index).await;
+        xml.divide_write(root.to_str().unwrap().to_string(), 1, index.clone()).await;
 
-        println!();
+        // One document of both zones per chunk.
+        assert_eq!(index.load(std::sync::atomic::Ordering::SeqCst), 2);
+        for (i, words) in [["cra", "gra"], ["hcra", "lgra"]].iter().enumerate() {
+            let chunk = tokio::fs::read_to_string(root.join(format!("{i}.xml"))).await?;
+            assert!(chunk.starts_with("<title>\n"), "{chunk}");
+            assert!(chunk.contains("\n</title>\n<text>\n") && chunk.ends_with("\n</text>\n"), "{chunk}");
+            assert!(words.iter().all(|w| chunk.contains(&format!("{w} "))), "{chunk}");
+        }
+        tokio::fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn batched_chunks_keep_tags_on_their_own_lines() -> Result<(), Error> {
+        let root = scratch("rep_batches").await?;
+        let corpus = CorpusSpec {
+            docs: 45,
+            files: 1,
+            ..CorpusSpec::default()
+        }
+        .generate(&root.join("corpus"))
+        .await?;
+        let file = &corpus.files[0];
+        let io = IoTuning::default();
+        let unbatched = split(file, &root.join("unbatched"), Separator::Space, 0, io).await?;
+        let batched = split(file, &root.join("batched"), Separator::Space, 64, io).await?;
+        let lines = split(file, &root.join("lines"), Separator::Newline, 64, io).await?;
+        // Buffers of a few bytes split the same.
+        let tiny = split(file, &root.join("tiny"), Separator::Space, 64, IoTuning::new(3, 5, 7)?).await?;
+        assert_eq!(unbatched.len(), 5);
+        assert_eq!(batched, unbatched);
+        assert_eq!(tiny, unbatched);
+        assert_eq!(lines.len(), unbatched.len());
+
+        for (i, lined) in lines.iter().enumerate() {
+            for line in lined.lines() {
+                let tag = ["<title>", "</title>", "<text>", "</text>"].contains(&line);
+                assert!(tag || !line.contains(['<', '>']), "{line:?}");
+                assert!(tag || line.is_empty() || !line.contains(' '), "{line:?}");
+            }
+            assert_eq!(lined.matches("\n<title>\n").count() + 1, lined.matches("<title>").count());
+            let chunk = format!("{i}.xml");
+            assert_eq!(read_chunk(&root.join("lines").join(&chunk)).await?, read_chunk(&root.join("unbatched").join(&chunk)).await?);
+        }
+        tokio::fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn chunks_are_read_with_their_zones() -> Result<(), Error> {
+        let root = scratch("rep_chunk_zones").await?;
+        let index = Arc::new(AtomicU32::new(0));
+        let zones = |order: [&str; 2]| ZoneSet::new(order).unwrap();
+        let mut xml = RepeatedXmlReader::<_, CommCharInterpreter>::new(
+            CommU8Provider::new(BufReader::new(File::open(concat!(env!("CARGO_MANIFEST_DIR"), "/test/ha.xml")).await?)),
+            zones(["title", "text"]),
+        )
+        .await?;
+        xml.divide_write(root.to_str().unwrap().to_string(), 1, index.clone()).await;
+
+        let chunk = root.join("0.xml");
+        let mut xml = RepeatedXmlReader::<_, CommCharInterpreter>::new(
+            CommU8Provider::new(BufReader::new(File::open(&chunk).await?)),
+            zones(["title", "text"]),
+        )
+        .await?
+        .with_checked_zones();
+        let mut read = vec![];
+        while let Some(v) = xml.next_word().await? {
+            match v {
+                ReaderResult::Word(w) => read.push(format!("{}:{w}", xml.zone())),
+                ReaderResult::AttributeEnd => xml.transform_zone().await,
+                ReaderResult::Malformed(w) => panic!("{w}"),
+            }
+        }
+        assert_eq!(read, ["title:cra", "text:gra"]);
+
+        let mut xml = RepeatedXmlReader::<_, CommCharInterpreter>::new(
+            CommU8Provider::new(BufReader::new(File::open(&chunk).await?)),
+            zones(["text", "title"]),
+        )
+        .await?
+        .with_checked_zones();
+        let e = loop {
+            match xml.next_word().await {
+                Ok(Some(_)) => {}
+                Ok(None) => panic!("a chunk of other zones was read"),
+                Err(e) => break e,
+            }
+        };
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        assert_eq!(e.to_string(), "zones don't match: <title> where <text> was expected at byte 7");
+        tokio::fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn checked_zones_fail_on_a_close_of_another_zone() -> Result<(), Error> {
+        let root = scratch("rep_chunk_close").await?;
+        let chunk = root.join("0.xml");
+        tokio::fs::write(&chunk, "<title>\ncra\n</title>\n<text>\ngra\n</title>\n").await?;
+        let mut xml = RepeatedXmlReader::<_, CommCharInterpreter>::new(
+            CommU8Provider::new(BufReader::new(File::open(&chunk).await?)),
+            ZoneSet::new(["title", "text"])?,
+        )
+        .await?
+        .with_checked_zones();
+        let e = loop {
+            match xml.next_word().await {
+                Ok(Some(ReaderResult::AttributeEnd)) => xml.transform_zone().await,
+                Ok(Some(ReaderResult::Malformed(w))) => panic!("{w}"),
+                Ok(Some(_)) => {}
+                Ok(None) => panic!("</title> closed the <text> zone"),
+                Err(e) => break e,
+            }
+        };
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        assert!(e.to_string().starts_with("zones don't match: unbalanced </title> inside <text>"), "{e}");
+        tokio::fs::remove_dir_all(&root).await?;
         Ok(())
     }
 }
@@ -0,0 +1,468 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    io::{Error, ErrorKind},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::{layout::IndexLayout, tasks::TaskPlan, warnings::WarningSummary};
+
+/// What a single input file contributed to the index.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileReport {
+    pub path: String,
+    pub documents: usize,
+    pub tokens: usize,
+    pub skipped_docs: usize,
+    pub parse_warnings: Vec<String>,
+    /// Tokens the analyzer dropped, as stopwords or by its term filter.
+    #[serde(default)]
+    pub dropped_terms: usize,
+    /// What the reader got past without dropping a document.
+    #[serde(default)]
+    pub reader_warnings: WarningSummary,
+}
+
+/// Per-file reports of one run, stored as `report.json` in the destination.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParseReport {
+    pub files: Vec<FileReport>,
+    /// Entries of the boosts sidecar that matched no document.
+    #[serde(default)]
+    pub unknown_boosts: Vec<String>,
+    /// Input files left out for holding nothing but whitespace. They get no
+    /// document ids.
+    #[serde(default)]
+    pub skipped_files: Vec<String>,
+    /// Entries the input specs came across that are no inputs, see
+    /// `InputSet`.
+    #[serde(default)]
+    pub skipped_inputs: usize,
+    /// Parse tasks the build ran, None in a report of before they were
+    /// picked.
+    #[serde(default)]
+    pub tasks: Option<TaskPlan>,
+}
+
+impl ParseReport {
+    pub async fn save(&self, directory: &str) -> Result<(), Error> {
+        let data = serde_json::to_vec_pretty(self)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        fs::write(IndexLayout::report(directory), data).await
+    }
+
+    pub async fn load(directory: &str) -> Result<Self, Error> {
+        let data = fs::read(IndexLayout::report(directory)).await?;
+        serde_json::from_slice(&data).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    /// Files that lost part of their input: a document skipped, or a
+    /// warning of the reader.
+    pub fn lossy_files(&self) -> impl Iterator<Item = &FileReport> {
+        self.files
+            .iter()
+            .filter(|v| v.skipped_docs > 0 || !v.parse_warnings.is_empty() || !v.reader_warnings.is_empty())
+    }
+
+    pub fn log_table(&self) {
+        let width = self.files.iter().map(|v| v.path.len()).max().unwrap_or(0).max(4);
+        log::info!(
+            "{:<width$} {:>10} {:>12} {:>8} {:>8} {:>8}",
+            "file",
+            "documents",
+            "tokens",
+            "skipped",
+            "warnings",
+            "dropped"
+        );
+        for v in &self.files {
+            log::info!(
+                "{:<width$} {:>10} {:>12} {:>8} {:>8} {:>8}",
+                v.path,
+                v.documents,
+                v.tokens,
+                v.skipped_docs,
+                v.parse_warnings.len(),
+                v.dropped_terms
+            );
+            for w in &v.parse_warnings {
+                log::warn!("{}: {}", v.path, w);
+            }
+            if !v.reader_warnings.is_empty() {
+                log::warn!("{}: reader warnings: {}", v.path, v.reader_warnings);
+            }
+            for w in &v.reader_warnings.kept {
+                log::debug!("{}: {}", v.path, w);
+            }
+        }
+        let mut warnings = WarningSummary::default();
+        for v in &self.files {
+            warnings.add_counts(&v.reader_warnings);
+        }
+        if !warnings.is_empty() {
+            log::info!("reader warnings over all files: {}", warnings);
+        }
+        for v in &self.unknown_boosts {
+            log::warn!("boost for unknown document {}", v);
+        }
+        for v in &self.skipped_files {
+            log::warn!("{}: skipped, holds nothing but whitespace", v);
+        }
+        if self.skipped_inputs > 0 {
+            log::info!("{} entries of the input specs skipped", self.skipped_inputs);
+        }
+    }
+}
+
+/// A build under [`crate::warnings::WarningPolicy::Strict`] that lost part
+/// of its input. Nothing is merged; the report covers every file parsed.
+#[derive(Debug)]
+pub struct StrictError {
+    pub report: ParseReport,
+}
+
+impl Display for StrictError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let lossy = self
+            .report
+            .lossy_files()
+            .map(|v| match v.reader_warnings.is_empty() {
+                true => format!("{} ({} skipped)", v.path, v.skipped_docs),
+                false => format!("{} ({} skipped, {})", v.path, v.skipped_docs, v.reader_warnings),
+            })
+            .collect::<Vec<_>>();
+        write!(f, "strict build lost input in {}", lossy.join(", "))
+    }
+}
+
+impl std::error::Error for StrictError {}
+
+impl From<StrictError> for Error {
+    fn from(e: StrictError) -> Self {
+        Error::new(ErrorKind::InvalidData, e)
+    }
+}
+
+#[cfg(test)]
+mod tst {
+    use std::{
+        collections::BTreeMap,
+        io::{Error, ErrorKind},
+        path::Path,
+    };
+
+    use tokio::fs;
+
+    use crate::{
+        config::IndexerConfig,
+        indexed::{Dictionary, IndexMerger, IndexParser, IndexedBuilder},
+        inputs::InputSet,
+        parser::ParseController,
+        rank::DocumentLengths,
+        segment::CommonSegments,
+        tasks::{TaskLimit, AUTO_TASKS},
+        testsupport::{scratch, Corpus, CorpusSpec},
+        warnings::{WarningKind, WarningPolicy, MAX_TOKEN_BYTES},
+        zones::ZoneSet,
+    };
+
+    use super::{ParseReport, StrictError};
+
+    /// Two files with a broken document in each.
+    async fn write_malformed(root: &Path) -> Result<Vec<String>, Error> {
+        let document = |title: &str, text: &str| {
+            format!("<title>\n{title}\n</title>\n<text>\n{text}\n</text>\n")
+        };
+        let first = [
+            document("first one", "alpha beta"),
+            document("second", "gamma"),
+            "<title>\nbroken lost\n<text>\nmissing words\n</text>\n".to_string(),
+            document("fourth", "delta epsilon"),
+        ]
+        .concat();
+        let second = [
+            document("fifth", "alpha"),
+            "<title>\nsixth\n</title>\n<text>\ncut off".to_string(),
+        ]
+        .concat();
+        let mut files = vec![];
+        for (i, content) in [first, second].iter().enumerate() {
+            let path = root.join(format!("{i}.xml")).to_str().unwrap().to_string();
+            fs::write(&path, content).await?;
+            files.push(path);
+        }
+        Ok(files)
+    }
+
+    fn controller(
+        files: &[String],
+        root: &Path,
+        destination: &String,
+    ) -> Result<ParseController<IndexParser, IndexMerger, IndexedBuilder>, Error> {
+        let config = IndexerConfig::new(1000, 6)?;
+        Ok(ParseController::new(
+            files.to_vec(),
+            destination.clone(),
+            root.join("buffer").to_str().unwrap().to_string(),
+            2,
+            IndexedBuilder::new(config, ZoneSet::new(["title", "text"])?)?,
+            IndexMerger::new(config.merger()),
+        ))
+    }
+
+    #[tokio::test]
+    async fn malformed_document_is_skipped() -> Result<(), Error> {
+        let root = std::env::temp_dir().join(format!("report_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root).await;
+        fs::create_dir_all(&root).await?;
+        let files = write_malformed(&root).await?;
+
+        let destination = root.join("res").to_str().unwrap().to_string();
+        controller(&files, &root, &destination)?.create_dictionary().await?;
+
+        let report = ParseReport::load(&destination).await?;
+        assert_eq!(report.files.len(), 2);
+        assert_eq!(report.files[0].path, files[0]);
+        assert_eq!(report.files[0].documents, 3);
+        assert_eq!(report.files[0].tokens, 9);
+        assert_eq!(report.files[0].skipped_docs, 1);
+        assert_eq!(report.files[0].parse_warnings.len(), 1);
+        assert_eq!(report.files[1].documents, 1);
+        assert_eq!(report.files[1].tokens, 2);
+        assert_eq!(report.files[1].skipped_docs, 1);
+        let tasks = report.tasks.unwrap();
+        assert_eq!((tasks.tasks, tasks.limit, tasks.files), (2, TaskLimit::Requested, 2));
+
+        let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+        for term in ["broken", "lost", "missing", "sixth", "cut"] {
+            assert!(dictionary.find(term).await?.is_none(), "{term}");
+        }
+        for term in ["alpha", "gamma", "epsilon", "fifth"] {
+            assert!(dictionary.find(term).await?.is_some(), "{term}");
+        }
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn picked_tasks_are_reported() -> Result<(), Error> {
+        let root = scratch("report_tasks").await?;
+        let files = write_malformed(&root).await?;
+        let config = IndexerConfig::new(1000, 6)?;
+        let build = |name: &str, tasks: u16| {
+            ParseController::new(
+                files.clone(),
+                root.join(name).to_str().unwrap().to_string(),
+                root.join(format!("{name}_buffer")).to_str().unwrap().to_string(),
+                tasks,
+                IndexedBuilder::new(config, ZoneSet::new(["title", "text"]).unwrap()).unwrap(),
+                IndexMerger::new(config.merger()),
+            )
+        };
+
+        build("many", 8).create_dictionary().await?;
+        let tasks = ParseReport::load(&root.join("many").to_str().unwrap().to_string()).await?.tasks.unwrap();
+        assert_eq!((tasks.tasks, tasks.limit, tasks.requested), (2, TaskLimit::Files, Some(8)));
+        assert!(tasks.lowered());
+
+        // A byte is no room for a parser, one runs whatever the cores.
+        build("tight", AUTO_TASKS).with_memory_budget(1).create_dictionary().await?;
+        let tasks = ParseReport::load(&root.join("tight").to_str().unwrap().to_string()).await?.tasks.unwrap();
+        assert_eq!((tasks.tasks, tasks.requested, tasks.memory_tasks), (1, None, Some(1)));
+        assert!(tasks.limit == TaskLimit::Memory || tasks.cores == 1, "{tasks:?}");
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn strict_build_fails_on_malformed_document() -> Result<(), Error> {
+        let root = scratch("report_strict").await?;
+        let files = write_malformed(&root).await?;
+
+        let lenient = root.join("lenient").to_str().unwrap().to_string();
+        controller(&files, &root, &lenient)?
+            .with_warning_policy(WarningPolicy::Lenient)
+            .create_dictionary()
+            .await?;
+        let report = ParseReport::load(&lenient).await?;
+        assert_eq!(report.lossy_files().count(), 2);
+
+        let strict = root.join("strict").to_str().unwrap().to_string();
+        let e = controller(&files, &root, &strict)?
+            .with_warning_policy(WarningPolicy::Strict)
+            .create_dictionary()
+            .await
+            .unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        let failed = e.get_ref().and_then(|v| v.downcast_ref::<StrictError>()).unwrap();
+        // The same report, but nothing was merged to save it with.
+        assert_eq!(failed.report.files, report.files);
+        assert!(failed.to_string().contains(&files[0]), "{failed}");
+        assert!(fs::metadata(&strict).await.is_err());
+
+        let clean = root.join("clean.xml").to_str().unwrap().to_string();
+        fs::write(&clean, "<title>\nfine\n</title>\n<text>\nall here\n</text>\n").await?;
+        let destination = root.join("clean").to_str().unwrap().to_string();
+        controller(&[clean], &root, &destination)?
+            .with_warning_policy(WarningPolicy::Strict)
+            .create_dictionary()
+            .await?;
+        assert_eq!(ParseReport::load(&destination).await?.lossy_files().count(), 0);
+
+        assert_eq!(WarningPolicy::parse("strict")?, WarningPolicy::Strict);
+        assert_eq!(WarningPolicy::parse("loose").unwrap_err().kind(), ErrorKind::InvalidInput);
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reader_warnings_are_counted_per_file() -> Result<(), Error> {
+        let root = scratch("report_warnings").await?;
+        let document = |title: &str, text: &str| {
+            format!("<title>\n{title}\n</title>\n<text>\n{text}\n</text>\n")
+        };
+        let first = [
+            document("one&hellip;", "alpha &nbsp; beta"),
+            "</text>\n".to_string(),
+            document("two", &"z".repeat(MAX_TOKEN_BYTES + 5)),
+        ]
+        .concat();
+        let second = [document("three", "gamma"), document("four", "x&bogus; delta")].concat();
+        let mut files = vec![];
+        for (i, content) in [first, second].iter().enumerate() {
+            let path = root.join(format!("{i}.xml")).to_str().unwrap().to_string();
+            fs::write(&path, content).await?;
+            files.push(path);
+        }
+        let destination = Corpus {
+            files: files.clone(),
+            attributes: ZoneSet::new(["title", "text"])?,
+            postings: BTreeMap::new(),
+        }
+        .index(&root)
+        .await?;
+
+        let report = ParseReport::load(&destination).await?;
+        let counts = report
+            .files
+            .iter()
+            .map(|v| {
+                v.reader_warnings
+                    .counts
+                    .iter()
+                    .map(|(kind, count)| (*kind, *count))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            counts,
+            [
+                vec![
+                    (WarningKind::UnknownEntity, 2),
+                    (WarningKind::OverlongToken, 1),
+                    (WarningKind::UnexpectedClose, 1)
+                ],
+                vec![(WarningKind::UnknownEntity, 1)]
+            ]
+        );
+        assert_eq!(report.files[0].reader_warnings.kept.len(), 4);
+        assert_eq!((report.files[0].documents, report.files[0].skipped_docs), (2, 0));
+        assert!(report.files.iter().all(|v| v.parse_warnings.is_empty()));
+
+        let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+        for term in ["one", "beta", "x", "delta", &"z".repeat(MAX_TOKEN_BYTES)] {
+            assert!(dictionary.find(term).await?.is_some(), "{term}");
+        }
+        assert!(dictionary.find("nbsp").await?.is_none());
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn blank_files_take_no_ids() -> Result<(), Error> {
+        let root = scratch("report_blank").await?;
+        let corpus = CorpusSpec {
+            docs: 20,
+            ..CorpusSpec::default()
+        }
+        .generate(&root.join("corpus"))
+        .await?;
+        let empty = root.join("empty.xml").to_str().unwrap().to_string();
+        let blank = root.join("blank.xml").to_str().unwrap().to_string();
+        fs::write(&empty, "").await?;
+        fs::write(&blank, " \n\t\n").await?;
+        let mut files = corpus.files.clone();
+        files.insert(0, empty.clone());
+        files.insert(2, blank.clone());
+        let destination = Corpus {
+            files,
+            ..corpus.clone()
+        }
+        .index(&root)
+        .await?;
+
+        let report = ParseReport::load(&destination).await?;
+        assert_eq!(report.skipped_files, [empty, blank]);
+        assert_eq!(
+            report.files.iter().map(|v| v.path.clone()).collect::<Vec<_>>(),
+            corpus.files
+        );
+        let lengths = DocumentLengths::load(&destination).await?;
+        assert_eq!(lengths.documents, 20);
+        assert_eq!(lengths.lengths.len(), lengths.documents);
+        let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+        for term in corpus.postings.keys() {
+            let found = dictionary.find(term).await?.unwrap();
+            assert_eq!(
+                found.indexes.iter().map(|v| v.0).collect::<Vec<_>>(),
+                corpus.documents(term),
+                "{term}"
+            );
+        }
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn skipped_inputs_are_counted() -> Result<(), Error> {
+        let root = scratch("report_inputs").await?;
+        let corpus = CorpusSpec {
+            docs: 10,
+            files: 2,
+            ..CorpusSpec::default()
+        }
+        .generate(&root.join("corpus"))
+        .await?;
+        fs::write(root.join("corpus/notes.txt"), "not an input").await?;
+        fs::create_dir_all(root.join("corpus/empty")).await?;
+        let inputs = InputSet::resolve(&[root.join("corpus").to_str().unwrap().to_string()]).await?;
+        assert_eq!(inputs.len(), corpus.files.len());
+
+        let destination = root.join("res").to_str().unwrap().to_string();
+        let config = IndexerConfig::new(1000, 6)?;
+        ParseController::<IndexParser, _, _>::from_inputs(
+            inputs,
+            destination.clone(),
+            root.join("buffer").to_str().unwrap().to_string(),
+            1,
+            IndexedBuilder::new(config, corpus.attributes.clone())?,
+            IndexMerger::new(config.merger()),
+        )
+        .create_dictionary()
+        .await?;
+
+        let report = ParseReport::load(&destination).await?;
+        assert_eq!(report.skipped_inputs, 1);
+        assert_eq!(
+            report.files.iter().map(|v| v.path.clone()).collect::<Vec<_>>(),
+            corpus.files
+        );
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,179 @@
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use tokio::fs;
+
+use crate::{layout::IndexLayout, paths, titles::DocumentTitles};
+
+/// Where every document of an index came from: its source file, where it
+/// stands in that file and its title, by document id. Read once from
+/// `files.txt` and `titles.txt`, so whatever shows results doesn't parse
+/// them itself.
+///
+/// Sources are the paths the build was given, decoded with
+/// [`paths::decode`]; [`Self::ids_for_source`] compares them the same way.
+#[derive(Debug, Default, Clone)]
+pub struct DocResolver {
+    sources: Vec<PathBuf>,
+    /// Source and position in it of every document, by id.
+    documents: Vec<(usize, u64)>,
+    titles: DocumentTitles,
+    /// Ids of the documents of every source, in order.
+    by_source: HashMap<PathBuf, Vec<usize>>,
+}
+
+impl DocResolver {
+    /// Fails if `files.txt` is missing or malformed; an index built without
+    /// titles resolves no title.
+    pub async fn load(directory: &str) -> Result<Self, Error> {
+        let layout = IndexLayout::detect(directory).await?;
+        let titles = match DocumentTitles::load(directory).await {
+            Ok(v) => v,
+            Err(e) if e.kind() == ErrorKind::NotFound => DocumentTitles::default(),
+            Err(e) => return Err(e),
+        };
+        let mut resolver = Self {
+            titles,
+            ..Self::default()
+        };
+        let mut indexes = HashMap::<String, usize>::new();
+        for (id, (source, position)) in load_input_files(layout.files(directory)).await?.into_iter().enumerate() {
+            let index = *indexes.entry(source).or_insert_with_key(|source| {
+                resolver.sources.push(paths::decode(source));
+                resolver.sources.len() - 1
+            });
+            resolver.documents.push((index, position));
+            resolver
+                .by_source
+                .entry(resolver.sources[index].clone())
+                .or_default()
+                .push(id);
+        }
+        Ok(resolver)
+    }
+
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    pub fn source_of(&self, document: usize) -> Option<&Path> {
+        self.documents.get(document).map(|(v, _)| self.sources[*v].as_path())
+    }
+
+    /// How many documents of its source come before `document`.
+    pub fn position_of(&self, document: usize) -> Option<u64> {
+        self.documents.get(document).map(|(_, v)| *v)
+    }
+
+    pub fn title_of(&self, document: usize) -> Option<&str> {
+        self.titles.get(document)
+    }
+
+    /// Ids of the documents read from `source`, in order. The path is
+    /// compared as the build recorded it, so a Windows extended-length
+    /// prefix makes no difference.
+    pub fn ids_for_source(&self, source: &Path) -> &[usize] {
+        self.by_source
+            .get(&paths::decode(&paths::encode(source)))
+            .map_or(&[], |v| v.as_slice())
+    }
+}
+
+/// Reads what [`crate::indexed::save_input_files`] wrote. A path holds no
+/// zero byte and a position is far below 2^56, so a path ends at the first
+/// zero byte.
+pub(crate) async fn load_input_files(path: String) -> Result<Vec<(String, u64)>, Error> {
+    let bytes = fs::read(&path).await?;
+    let mut sources = Vec::new();
+    let mut rest = &bytes[..];
+    while !rest.is_empty() {
+        let end = rest
+            .iter()
+            .position(|v| *v == 0)
+            .filter(|end| rest.get(end + 8) == Some(&b'\n'))
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("{path} holds a malformed entry")))?;
+        let name = String::from_utf8(rest[..end].to_vec()).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        sources.push((name, u64::from_be_bytes(rest[end..end + 8].try_into().unwrap())));
+        rest = &rest[end + 9..];
+    }
+    Ok(sources)
+}
+
+#[cfg(all(test, feature = "build"))]
+mod tst {
+    use std::{io::Error, path::Path, time::Duration};
+
+    use tokio::fs;
+
+    use crate::{
+        config::IndexerConfig,
+        indexed::{IndexMerger, IndexParser, IndexedBuilder},
+        parser::ParseController,
+        testsupport::{scratch, word},
+        throttle::Throttle,
+        zones::ZoneSet,
+    };
+
+    use super::DocResolver;
+
+    #[tokio::test]
+    async fn every_id_resolves_to_its_source() -> Result<(), Error> {
+        let root = scratch("resolve").await?;
+        let mut files = Vec::new();
+        for name in ["alpha", "beta"] {
+            let path = root.join(format!("{name}.xml"));
+            let documents = (0..20)
+                .map(|i| format!("<title>\n{name} {}\n</title>\n<text>\nbody\n</text>\n", word(i)))
+                .collect::<String>();
+            fs::write(&path, documents).await?;
+            files.push(path.to_str().unwrap().to_string());
+        }
+        let destination = root.join("res").to_str().unwrap().to_string();
+        let config = IndexerConfig::new(1000, 6)?;
+        // Two tasks pausing after every document take turns handing out
+        // ids, so the ids of the files interleave.
+        ParseController::<IndexParser, _, _>::new(
+            files.clone(),
+            destination.clone(),
+            root.join("buffer").to_str().unwrap().to_string(),
+            2,
+            IndexedBuilder::new(config, ZoneSet::new(["title", "text"])?)?,
+            IndexMerger::new(config.merger()),
+        )
+        .with_titles()
+        .with_throttle(Throttle::new(u64::MAX)?.with_pause(Duration::from_millis(5)))
+        .create_dictionary()
+        .await?;
+
+        let resolver = DocResolver::load(&destination).await?;
+        assert_eq!(resolver.len(), 40);
+        for document in 0..resolver.len() {
+            let source = resolver.source_of(document).unwrap();
+            let position = resolver.position_of(document).unwrap();
+            let title = resolver.title_of(document).unwrap();
+            let name = source.file_stem().unwrap().to_str().unwrap();
+            assert_eq!(title, format!("{name} {}", word(position as usize)), "{document}");
+        }
+        assert_eq!(resolver.source_of(resolver.len()), None);
+
+        for file in files.iter() {
+            let ids = resolver.ids_for_source(Path::new(file));
+            assert_eq!(ids.len(), 20, "{file}");
+            assert!(ids.windows(2).any(|v| v[1] > v[0] + 1), "{ids:?}");
+            for (position, document) in ids.iter().enumerate() {
+                assert_eq!(resolver.source_of(*document), Some(Path::new(file)));
+                assert_eq!(resolver.position_of(*document), Some(position as u64));
+            }
+        }
+        assert!(resolver.ids_for_source(&root.join("gamma.xml")).is_empty());
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,121 @@
+use std::{
+    io::{Error, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use log::LevelFilter;
+use log4rs::{
+    append::{
+        console::{ConsoleAppender, Target},
+        file::FileAppender,
+    },
+    config::{Appender, Root},
+    Config, Handle,
+};
+
+/// Name of the log a build writes, apart from the `info.txt` the merger
+/// leaves in the index.
+pub const RUN_LOG: &str = "index_run.log";
+
+/// Where the log of a build into `destination` goes unless told otherwise:
+/// [`RUN_LOG`] next to the destination, not in it, as overwriting, a
+/// reorder or a reindex replaces the destination directory whole.
+pub fn default_path(destination: &str) -> PathBuf {
+    let destination = Path::new(destination);
+    match destination.parent() {
+        Some(parent) => parent.join(RUN_LOG),
+        None => PathBuf::from(RUN_LOG),
+    }
+}
+
+/// Everything at `level` to stderr.
+pub fn stderr_config(level: LevelFilter) -> Result<Config, Error> {
+    let console = ConsoleAppender::builder().target(Target::Stderr).build();
+    Config::builder()
+        .appender(Appender::builder().build("console", Box::new(console)))
+        .build(Root::builder().appender("console").build(level))
+        .map_err(|e| Error::new(ErrorKind::InvalidInput, e))
+}
+
+/// Everything at `level` to a fresh file at `path`. Fails if the file can't
+/// be created.
+pub fn file_config(path: &Path, level: LevelFilter) -> Result<Config, Error> {
+    let file = FileAppender::builder().append(false).build(path)?;
+    Config::builder()
+        .appender(Appender::builder().build("file", Box::new(file)))
+        .build(Root::builder().appender("file").build(level))
+        .map_err(|e| Error::new(ErrorKind::InvalidInput, e))
+}
+
+/// Logs to a file at `path`, or to stderr alone with a warning if it can't
+/// be created, so a read-only working directory never stops a build. Never
+/// panics: if no logger can be set at all, says so on stderr and returns
+/// None.
+pub fn init(path: &Path, level: LevelFilter) -> Option<Handle> {
+    match file_config(path, level) {
+        Ok(v) => set(Ok(v)),
+        Err(e) => {
+            let handle = set(stderr_config(level));
+            log::warn!("Cannot create run log {}: {e}, logging to stderr only", path.display());
+            handle
+        }
+    }
+}
+
+/// Logs to stderr alone; see [`init`].
+pub fn init_stderr(level: LevelFilter) -> Option<Handle> {
+    set(stderr_config(level))
+}
+
+fn set(config: Result<Config, Error>) -> Option<Handle> {
+    match config.and_then(|v| log4rs::init_config(v).map_err(|e| Error::new(ErrorKind::AlreadyExists, e))) {
+        Ok(v) => Some(v),
+        Err(e) => {
+            eprintln!("Logging is off: {e}");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tst {
+    use std::{io::Error, path::Path};
+
+    use log::LevelFilter;
+    use tokio::fs;
+
+    use crate::{
+        resolve::DocResolver,
+        testsupport::{scratch, CorpusSpec},
+    };
+
+    use super::{default_path, file_config, init, RUN_LOG};
+
+    #[test]
+    fn the_default_log_sits_beside_the_destination() {
+        assert_eq!(default_path("../res"), Path::new("..").join(RUN_LOG));
+        assert_eq!(default_path("res"), Path::new(RUN_LOG));
+    }
+
+    #[tokio::test]
+    async fn an_unwritable_log_falls_back_to_stderr() -> Result<(), Error> {
+        let root = scratch("run_log").await?;
+        // Nothing can be created under a regular file, whoever runs this.
+        let blocker = root.join("blocker");
+        fs::write(&blocker, "").await?;
+        let path = blocker.join(RUN_LOG);
+        assert!(file_config(&path, LevelFilter::Warn).is_err());
+
+        // Warn keeps the other tests of this process from flooding stderr
+        // once the logger is set.
+        assert!(init(&path, LevelFilter::Warn).is_some());
+        assert_eq!(log::max_level(), LevelFilter::Warn);
+        assert!(fs::metadata(&path).await.is_err());
+
+        let corpus = CorpusSpec::default().generate(&root).await?;
+        let destination = corpus.index(&root).await?;
+        assert_eq!(DocResolver::load(&destination).await?.len(), 50);
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
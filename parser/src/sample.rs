@@ -0,0 +1,143 @@
+use std::io::{Error, ErrorKind};
+
+use serde::{Deserialize, Serialize};
+
+/// Deterministic document sampling. Inclusion only depends on the seed and
+/// the document's position in its source file, so every `tasks_count` picks
+/// the same documents.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Sampling {
+    pub fraction: f64,
+    pub seed: u64,
+}
+
+impl Sampling {
+    pub fn new(fraction: f64, seed: u64) -> Result<Self, Error> {
+        if !(fraction > 0.0 && fraction <= 1.0) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("sample fraction must be in (0, 1], got {fraction}"),
+            ));
+        }
+        Ok(Self { fraction, seed })
+    }
+
+    pub fn includes(&self, file: usize, document: usize) -> bool {
+        let hash = mix(mix(self.seed ^ file as u64) ^ document as u64);
+        (hash as f64) < self.fraction * u64::MAX as f64
+    }
+}
+
+// splitmix64 finalizer
+fn mix(mut v: u64) -> u64 {
+    v = v.wrapping_add(0x9e3779b97f4a7c15);
+    v = (v ^ (v >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    v = (v ^ (v >> 27)).wrapping_mul(0x94d049bb133111eb);
+    v ^ (v >> 31)
+}
+
+#[cfg(test)]
+mod tst {
+    use std::{collections::BTreeSet, io::Error, path::PathBuf};
+
+    use tokio::fs;
+
+    use crate::{
+        config::IndexerConfig,
+        indexed::{IndexMerger, IndexParser, IndexTermProvider, IndexedBuilder},
+        metadata::IndexMetadata,
+        parser::{ParseController, TermProvider},
+        segment::CommonSegments,
+        zones::ZoneSet,
+    };
+
+    use super::Sampling;
+
+    fn marker(file: usize, document: usize) -> String {
+        fn letters(mut v: usize) -> String {
+            let mut out = String::new();
+            loop {
+                out.push((b'a' + (v % 25) as u8) as char);
+                v /= 25;
+                if v == 0 {
+                    return out;
+                }
+            }
+        }
+        format!("m{}z{}", letters(file), letters(document))
+    }
+
+    async fn write_corpus(root: &PathBuf, files: usize, documents: usize) -> Vec<String> {
+        fs::create_dir_all(root).await.unwrap();
+        let mut out = vec![];
+        for f in 0..files {
+            let mut content = String::new();
+            for d in 0..documents {
+                content.push_str(&format!(
+                    "<title>\n{} common\n</title>\n<text>\nalpha beta gamma\n</text>\n",
+                    marker(f, d)
+                ));
+            }
+            let path = root.join(format!("{f}.xml"));
+            fs::write(&path, content).await.unwrap();
+            out.push(path.to_str().unwrap().to_string());
+        }
+        out
+    }
+
+    async fn sampled(root: &PathBuf, files: Vec<String>, tasks: u16) -> Result<BTreeSet<String>, Error> {
+        let destination = root.join(format!("res{tasks}")).to_str().unwrap().to_string();
+        let buffer = root.join(format!("buffer{tasks}")).to_str().unwrap().to_string();
+        let config = IndexerConfig::new(1000, 6)?;
+        ParseController::<IndexParser, _, _>::new(
+            files,
+            destination.clone(),
+            buffer,
+            tasks,
+            IndexedBuilder::new(config, ZoneSet::new(["title", "text"])?)?,
+            IndexMerger::new(config.merger()),
+        )
+        .with_sampling(Sampling::new(0.5, 7)?)
+        .create_dictionary()
+        .await?;
+
+        let metadata = IndexMetadata::load(&destination).await?;
+        assert_eq!(metadata.sample.sampling, Some(Sampling::new(0.5, 7)?));
+        assert!(metadata.sample.documents >= 60);
+
+        let mut provider = IndexTermProvider::<CommonSegments>::new(&destination).await?;
+        let mut markers = BTreeSet::new();
+        while let Some(term) = provider.next_term().await {
+            if term.term.starts_with('m') && term.term.contains('z') {
+                markers.insert(term.term);
+            }
+        }
+        Ok(markers)
+    }
+
+    #[tokio::test]
+    async fn same_documents_for_any_task_count() -> Result<(), Error> {
+        let root = std::env::temp_dir().join(format!("sample_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root).await;
+        let files = write_corpus(&root, 3, 20).await;
+
+        let expected = (0..3)
+            .flat_map(|f| (0..20).map(move |d| (f, d)))
+            .filter(|(f, d)| Sampling::new(0.5, 7).unwrap().includes(*f, *d))
+            .map(|(f, d)| marker(f, d))
+            .collect::<BTreeSet<_>>();
+        assert!(expected.len() > 18 && expected.len() < 42);
+
+        assert_eq!(sampled(&root, files.clone(), 1).await?, expected);
+        assert_eq!(sampled(&root, files, 3).await?, expected);
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_bad_fraction() {
+        assert!(Sampling::new(0.0, 1).is_err());
+        assert!(Sampling::new(1.5, 1).is_err());
+        assert!(Sampling::new(1.0, 1).unwrap().includes(4, 2));
+    }
+}
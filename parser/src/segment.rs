@@ -7,14 +7,23 @@ use modular_bitfield::{
     Specifier,
 };
 use save::save::VariableSave;
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
 use tokio::{
     fs::File,
     io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter},
 };
 
-pub trait Segments: Default + VariableSave + Debug + Send + Sync {
+pub trait Segments: Default + VariableSave + Debug + Display + Send + Sync {
+    /// Bytes one value takes in a postings block.
+    const ENCODED_SIZE: usize;
+
     fn selector_for(value: &'_ str) -> fn(&mut Self, u8) -> ();
+
+    /// Whether any zone is set in both.
+    fn intersects(&self, other: &Self) -> bool;
+
+    /// Sets every zone set in `other` as well.
+    fn union(&mut self, other: &Self);
 }
 
 #[bitfield]
@@ -34,11 +43,32 @@ impl VariableSave for CommonSegments {
 
     async fn variable_load(reader: &mut BufReader<File>) -> Result<Self, Error> {
         let mut out = CommonSegments::new();
-        reader.read(&mut out.bytes).await?;
+        reader.read_exact(&mut out.bytes).await?;
         Ok(out)
     }
 }
 
+impl CommonSegments {
+    /// Names of the zones that are set.
+    pub fn zones(&self) -> impl Iterator<Item = &'static str> {
+        [("title", self.title()), ("text", self.text())]
+            .into_iter()
+            .filter(|v| v.1 != 0)
+            .map(|v| v.0)
+    }
+}
+
+/// Set zones joined with `+`, or `-` if there are none.
+impl Display for CommonSegments {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let zones = self.zones().collect::<Vec<_>>();
+        match zones.is_empty() {
+            true => write!(f, "-"),
+            false => write!(f, "{}", zones.join("+")),
+        }
+    }
+}
+
 impl Default for CommonSegments {
     #[inline]
     fn default() -> Self {
@@ -47,6 +77,8 @@ impl Default for CommonSegments {
 }
 
 impl Segments for CommonSegments {
+    const ENCODED_SIZE: usize = 1;
+
     #[inline]
     fn selector_for(value: &'_ str) -> fn(&mut CommonSegments, <B1 as Specifier>::InOut) -> () {
         match value {
@@ -55,11 +87,26 @@ impl Segments for CommonSegments {
             _ => panic!("Unexpected value {}", value),
         }
     }
+
+    fn intersects(&self, other: &Self) -> bool {
+        self.bytes[0] & other.bytes[0] != 0
+    }
+
+    fn union(&mut self, other: &Self) {
+        self.bytes[0] |= other.bytes[0];
+    }
 }
 
 pub trait SegmentSelector: Sync + Send {
     type Segments: Segments;
-    fn applier_for(&self, value: &str) -> fn(&mut Self::Segments) -> ();
+    fn find_applier(&self, value: &str) -> Option<fn(&mut Self::Segments) -> ()>;
+
+    fn applier_for(&self, value: &str) -> fn(&mut Self::Segments) -> () {
+        match self.find_applier(value) {
+            Some(applier) => applier,
+            None => panic!("Unexpected value {}", value),
+        }
+    }
 }
 
 pub struct CommonSegmentSelector {}
@@ -73,11 +120,11 @@ impl CommonSegmentSelector {
 impl SegmentSelector for CommonSegmentSelector {
     type Segments = CommonSegments;
 
-    fn applier_for(&self, value: &str) -> fn(&mut Self::Segments) -> () {
+    fn find_applier(&self, value: &str) -> Option<fn(&mut Self::Segments) -> ()> {
         match value {
-            "text" => |v| v.set_text(1),
-            "title" => |v| v.set_title(1),
-            _ => panic!("Unexpected value {}", value),
+            "text" => Some(|v| v.set_text(1)),
+            "title" => Some(|v| v.set_title(1)),
+            _ => None,
         }
     }
 }
@@ -0,0 +1,179 @@
+use std::{
+    fmt::Display,
+    io::{Error, ErrorKind},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::layout::IndexLayout;
+
+/// Sizes of the sections of a dictionary, in bytes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SectionBytes {
+    pub dictionary: u64,
+    pub lexical: u64,
+    pub postings: u64,
+    /// 0 if the index has no block directory.
+    pub block_dir: u64,
+}
+
+impl SectionBytes {
+    /// Sizes of the files in `directory` as they are now.
+    pub async fn measure(directory: &str, layout: IndexLayout) -> Result<Self, Error> {
+        async fn size(path: String) -> Result<u64, Error> {
+            match fs::metadata(path).await {
+                Ok(v) => Ok(v.len()),
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok(0),
+                Err(e) => Err(e),
+            }
+        }
+        Ok(Self {
+            dictionary: fs::metadata(layout.dictionary(directory)).await?.len(),
+            lexical: fs::metadata(layout.lexical_part(directory)).await?.len(),
+            postings: fs::metadata(layout.index_part(directory)).await?.len(),
+            block_dir: size(layout.block_dir(directory)).await?,
+        })
+    }
+
+    pub fn total(&self) -> u64 {
+        self.dictionary + self.lexical + self.postings + self.block_dir
+    }
+}
+
+/// Totals of a merged index, stored as `stats.json` next to it.
+///
+/// `info.txt` keeps its two numbers, [`IndexStats::occurrences`] and
+/// [`IndexStats::vocabulary`]; this tells them apart from the postings
+/// entries the occurrences are stored in.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IndexStats {
+    /// Tokens indexed, every occurrence of a term counted.
+    pub occurrences: u64,
+    /// Postings entries, one per term and document.
+    pub postings: u64,
+    /// Distinct terms.
+    pub vocabulary: u64,
+    pub bytes: SectionBytes,
+}
+
+impl IndexStats {
+    /// Occurrences a postings entry stands for on average.
+    pub fn occurrences_per_posting(&self) -> f64 {
+        ratio(self.occurrences, self.postings)
+    }
+
+    /// Postings entries of a term on average.
+    pub fn postings_per_term(&self) -> f64 {
+        ratio(self.postings, self.vocabulary)
+    }
+
+    /// Bytes of the postings section per postings entry.
+    pub fn bytes_per_posting(&self) -> f64 {
+        ratio(self.bytes.postings, self.postings)
+    }
+
+    pub async fn save(&self, directory: &str) -> Result<(), Error> {
+        let data = serde_json::to_vec_pretty(self)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        fs::write(IndexLayout::stats(directory), data).await
+    }
+
+    pub async fn load(directory: &str) -> Result<Self, Error> {
+        let data = fs::read(IndexLayout::stats(directory)).await?;
+        serde_json::from_slice(&data).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+}
+
+fn ratio(a: u64, b: u64) -> f64 {
+    match b {
+        0 => 0.0,
+        b => a as f64 / b as f64,
+    }
+}
+
+impl Display for IndexStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "occurrences\t{}", self.occurrences)?;
+        writeln!(f, "postings\t{}", self.postings)?;
+        writeln!(f, "vocabulary\t{}", self.vocabulary)?;
+        writeln!(f, "occurrences per posting\t{:.3}", self.occurrences_per_posting())?;
+        writeln!(f, "postings per term\t{:.3}", self.postings_per_term())?;
+        writeln!(f, "bytes per posting\t{:.3}", self.bytes_per_posting())?;
+        for (section, bytes) in [
+            ("dictionary", self.bytes.dictionary),
+            ("lexical", self.bytes.lexical),
+            ("postings", self.bytes.postings),
+            ("block_dir", self.bytes.block_dir),
+            ("total", self.bytes.total()),
+        ] {
+            writeln!(f, "{section} bytes\t{bytes}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tst {
+    use std::io::Error;
+
+    use tokio::fs;
+
+    use crate::{
+        indexed::Dictionary,
+        layout::IndexLayout,
+        segment::CommonSegments,
+        testsupport::{scratch, CorpusSpec},
+    };
+
+    use super::{IndexStats, SectionBytes};
+
+    #[tokio::test]
+    async fn totals_match_the_corpus() -> Result<(), Error> {
+        let root = scratch("index_stats").await?;
+        let corpus = CorpusSpec {
+            docs: 60,
+            files: 3,
+            ..CorpusSpec::default()
+        }
+        .generate(&root.join("corpus"))
+        .await?;
+        let destination = corpus.index(&root).await?;
+
+        let stats = IndexStats::load(&destination).await?;
+        let postings = corpus.postings.values().map(|v| v.len() as u64).sum::<u64>();
+        let occurrences = corpus.postings.values().flatten().map(|(_, count)| *count as u64).sum::<u64>();
+        assert_eq!(occurrences, 60 * 23);
+        assert_eq!(
+            (stats.occurrences, stats.postings, stats.vocabulary),
+            (occurrences, postings, corpus.postings.len() as u64)
+        );
+        assert!(stats.occurrences > stats.postings);
+
+        let layout = IndexLayout::V1;
+        assert_eq!(stats.bytes.postings, fs::metadata(layout.index_part(&destination)).await?.len());
+        assert_eq!(stats.bytes.lexical, fs::metadata(layout.lexical_part(&destination)).await?.len());
+        assert_eq!(stats.bytes, SectionBytes::measure(&destination, layout).await?);
+        assert_eq!(
+            stats.bytes_per_posting(),
+            stats.bytes.postings as f64 / postings as f64
+        );
+
+        // The old two numbers stay where they were.
+        let info = fs::read_to_string(layout.info(&destination)).await?;
+        assert_eq!(info, format!("{occurrences}\n{}\n", corpus.postings.len()));
+        assert_eq!(Dictionary::<CommonSegments>::new(&destination).await?.len() as u64, stats.vocabulary);
+
+        let shown = stats.to_string();
+        assert!(shown.contains(&format!("postings\t{postings}\n")), "{shown}");
+        assert!(
+            shown.contains(&format!("occurrences per posting\t{:.3}", occurrences as f64 / postings as f64)),
+            "{shown}"
+        );
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,334 @@
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind},
+};
+
+use async_trait::async_trait;
+
+use crate::{gallop::intersect, indexed::Dictionary, rank::Scorer, segment::Segments};
+
+/// Shortest postings list at most this long makes walking the lists side by
+/// side cheaper than accumulating them.
+const SHORT_LIST: usize = 4096;
+
+/// A conjunction also goes document at a time when its longest list is this
+/// many times its shortest, most of the longer ones then being skipped.
+const SKEW: usize = 8;
+
+/// Which documents a ranked query takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Combine {
+    /// Documents with any of the terms.
+    Any,
+    /// Documents with every term.
+    All,
+}
+
+/// How an [`Executor`] walks the postings lists of a query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// One list after the other into a score per document.
+    TermAtATime,
+    /// Every list at once in document order, skipping ahead by
+    /// [`crate::gallop`] when all terms are needed.
+    DocumentAtATime,
+}
+
+impl Strategy {
+    /// What [`Strategy::parse`] reads back as this strategy.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::TermAtATime => "taat",
+            Self::DocumentAtATime => "daat",
+        }
+    }
+
+    pub fn parse(raw: &str) -> Result<Self, Error> {
+        match raw {
+            "taat" => Ok(Self::TermAtATime),
+            "daat" => Ok(Self::DocumentAtATime),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("unknown strategy {raw:?}, expected taat or daat"),
+            )),
+        }
+    }
+
+    pub fn executor<S: Segments>(self) -> Box<dyn Executor<S>> {
+        match self {
+            Self::TermAtATime => Box::new(TermAtATime),
+            Self::DocumentAtATime => Box::new(DocumentAtATime),
+        }
+    }
+}
+
+/// Scores the documents a ranked query takes.
+///
+/// Every strategy sums the terms of a document in query order, so they
+/// agree with each other and with [`Scorer::search`] to the bit.
+#[async_trait]
+pub trait Executor<S: Segments>: Send + Sync {
+    fn strategy(&self) -> Strategy;
+
+    /// Documents `combine` takes of `terms`, scored by `scorer`, best first.
+    async fn execute(
+        &self,
+        scorer: &Scorer<S>,
+        dictionary: &mut Dictionary<S>,
+        terms: &[&str],
+        combine: Combine,
+    ) -> Result<Vec<(usize, f64)>, Error>;
+}
+
+pub struct TermAtATime;
+
+#[async_trait]
+impl<S: Segments> Executor<S> for TermAtATime {
+    fn strategy(&self) -> Strategy {
+        Strategy::TermAtATime
+    }
+
+    async fn execute(
+        &self,
+        scorer: &Scorer<S>,
+        dictionary: &mut Dictionary<S>,
+        terms: &[&str],
+        combine: Combine,
+    ) -> Result<Vec<(usize, f64)>, Error> {
+        let lookup = scorer.lookup_terms(terms);
+        // Score and terms matched by document.
+        let mut scores = HashMap::<usize, (f64, usize)>::new();
+        for term in lookup.iter() {
+            let list = scorer.term_scores(dictionary, term).await?;
+            if list.is_empty() && combine == Combine::All {
+                return Ok(Vec::new());
+            }
+            for (document, value) in list {
+                let score = scores.entry(document).or_default();
+                score.0 += value;
+                score.1 += 1;
+            }
+        }
+        let scores = scores
+            .into_iter()
+            .filter(|(_, (_, matched))| combine == Combine::Any || *matched == lookup.len())
+            .map(|(document, (score, _))| (document, score))
+            .collect();
+        scorer.finish(dictionary, terms, scores).await
+    }
+}
+
+pub struct DocumentAtATime;
+
+#[async_trait]
+impl<S: Segments> Executor<S> for DocumentAtATime {
+    fn strategy(&self) -> Strategy {
+        Strategy::DocumentAtATime
+    }
+
+    async fn execute(
+        &self,
+        scorer: &Scorer<S>,
+        dictionary: &mut Dictionary<S>,
+        terms: &[&str],
+        combine: Combine,
+    ) -> Result<Vec<(usize, f64)>, Error> {
+        let mut lists = Vec::with_capacity(terms.len());
+        for term in scorer.lookup_terms(terms).iter() {
+            lists.push(scorer.term_scores(dictionary, term).await?);
+        }
+        let scores = match combine {
+            Combine::Any => union(&lists),
+            Combine::All => intersection(&lists),
+        };
+        scorer.finish(dictionary, terms, scores).await
+    }
+}
+
+/// Documents in any of `lists`, walking them together in document order.
+fn union(lists: &[Vec<(usize, f64)>]) -> Vec<(usize, f64)> {
+    let mut positions = vec![0; lists.len()];
+    let mut scores = Vec::new();
+    loop {
+        let next = lists
+            .iter()
+            .zip(positions.iter())
+            .filter_map(|(list, at)| list.get(*at).map(|v| v.0))
+            .min();
+        let Some(document) = next else {
+            return scores;
+        };
+        let mut score = 0.0;
+        for (list, at) in lists.iter().zip(positions.iter_mut()) {
+            if list.get(*at).is_some_and(|v| v.0 == document) {
+                score += list[*at].1;
+                *at += 1;
+            }
+        }
+        scores.push((document, score));
+    }
+}
+
+/// Documents in all of `lists`, galloping from the shortest, see [`intersect`].
+fn intersection(lists: &[Vec<(usize, f64)>]) -> Vec<(usize, f64)> {
+    let lists = lists.iter().map(Vec::as_slice).collect::<Vec<_>>();
+    intersect(&lists, |v| v.0)
+        .into_iter()
+        .map(|positions| {
+            let score = lists
+                .iter()
+                .zip(positions.iter())
+                .fold(0.0, |sum, (list, at)| sum + list[*at].1);
+            (lists[0][positions[0]].0, score)
+        })
+        .collect()
+}
+
+/// The strategy for a query whose terms are in `document_frequencies`
+/// documents: side by side for a conjunction led by a short or much the
+/// shortest list, otherwise one list at a time.
+pub fn choose(document_frequencies: &[usize], combine: Combine) -> Strategy {
+    let shortest = document_frequencies.iter().copied().min().unwrap_or(0);
+    let longest = document_frequencies.iter().copied().max().unwrap_or(0);
+    match combine {
+        Combine::All if shortest <= SHORT_LIST || shortest.saturating_mul(SKEW) <= longest => {
+            Strategy::DocumentAtATime
+        }
+        _ => Strategy::TermAtATime,
+    }
+}
+
+/// [`choose`] with the document frequencies the dictionary has for `terms`.
+pub async fn plan<S: Segments>(
+    scorer: &Scorer<S>,
+    dictionary: &mut Dictionary<S>,
+    terms: &[&str],
+    combine: Combine,
+) -> Result<Strategy, Error> {
+    let mut document_frequencies = Vec::with_capacity(terms.len());
+    for term in scorer.lookup_terms(terms).iter() {
+        document_frequencies.push(dictionary.document_frequency(term).await?.unwrap_or(0));
+    }
+    Ok(choose(&document_frequencies, combine))
+}
+
+/// Runs `terms` the way `strategy` says, or as [`plan`] picks without one.
+pub async fn search<S: Segments>(
+    scorer: &Scorer<S>,
+    dictionary: &mut Dictionary<S>,
+    terms: &[&str],
+    combine: Combine,
+    strategy: Option<Strategy>,
+) -> Result<(Vec<(usize, f64)>, Strategy), Error> {
+    let strategy = match strategy {
+        Some(v) => v,
+        None => plan(scorer, dictionary, terms, combine).await?,
+    };
+    // A term too long to be indexed is in no document.
+    if combine == Combine::All && terms.iter().any(|v| v.len() > scorer.max_term_bytes()) {
+        return Ok((Vec::new(), strategy));
+    }
+    log::debug!("{} over {terms:?} as {combine:?}", strategy.name());
+    let ranked = strategy.executor().execute(scorer, dictionary, terms, combine).await?;
+    Ok((ranked, strategy))
+}
+
+#[cfg(test)]
+mod tst {
+    use std::io::Error;
+
+    use tokio::fs;
+
+    use crate::{
+        indexed::Dictionary,
+        rank::{DocumentLengths, Scorer},
+        segment::{CommonSegmentSelector, CommonSegments},
+        testsupport::{scratch, word, CorpusSpec},
+    };
+
+    use super::{choose, intersection, search, Combine, Strategy};
+
+    #[test]
+    fn intersection_sums_in_term_order() {
+        let lists = vec![
+            vec![(1, 0.1), (5, 0.2), (7, 0.3), (12, 0.4)],
+            vec![(5, 1.0), (12, 2.0)],
+            vec![(0, 0.5), (5, 0.25), (6, 0.0), (12, 0.125), (30, 1.0)],
+        ];
+        assert_eq!(
+            intersection(&lists),
+            [(5, 0.0 + 0.2 + 1.0 + 0.25), (12, 0.0 + 0.4 + 2.0 + 0.125)]
+        );
+        assert!(intersection(&[vec![(1, 1.0)], vec![]]).is_empty());
+        assert!(intersection(&[]).is_empty());
+    }
+
+    #[test]
+    fn planner_follows_list_lengths() {
+        assert_eq!(choose(&[10, 1_000_000], Combine::All), Strategy::DocumentAtATime);
+        assert_eq!(choose(&[50_000, 60_000], Combine::All), Strategy::TermAtATime);
+        assert_eq!(choose(&[50_000, 500_000], Combine::All), Strategy::DocumentAtATime);
+        assert_eq!(choose(&[10, 20], Combine::Any), Strategy::TermAtATime);
+        assert_eq!(choose(&[], Combine::Any), Strategy::TermAtATime);
+        assert_eq!(Strategy::parse("daat").unwrap(), Strategy::DocumentAtATime);
+        assert_eq!(Strategy::parse(Strategy::TermAtATime.name()).unwrap(), Strategy::TermAtATime);
+        assert!(Strategy::parse("auto").is_err());
+    }
+
+    #[tokio::test]
+    async fn strategies_agree() -> Result<(), Error> {
+        let root = scratch("strategy_agree").await?;
+        let corpus = CorpusSpec { docs: 2000, vocab: 500, files: 3, ..CorpusSpec::default() }
+            .generate(&root.join("inp"))
+            .await?;
+        let destination = corpus.index(&root).await?;
+        let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+        let scorer = Scorer::new(
+            &CommonSegmentSelector::new(),
+            &[("title", 2.0), ("text", 1.0)],
+            DocumentLengths::load(&destination).await?,
+        )?;
+        let words = [0, 1, 2, 5, 40, 120, 300, 499].map(word);
+        let shapes: [&[usize]; 8] = [&[0], &[0, 1], &[0, 7], &[4, 5, 6], &[0, 1, 2, 3], &[6, 6], &[3, 0, 5], &[]];
+        for shape in shapes {
+            let mut terms = shape.iter().map(|v| words[*v].as_str()).collect::<Vec<_>>();
+            for missing in [false, true] {
+                if missing {
+                    terms.push("missing");
+                }
+                let exhaustive = scorer.search(&mut dictionary, &terms).await?;
+                for combine in [Combine::Any, Combine::All] {
+                    let (term, _) =
+                        search(&scorer, &mut dictionary, &terms, combine, Some(Strategy::TermAtATime)).await?;
+                    let (document, _) =
+                        search(&scorer, &mut dictionary, &terms, combine, Some(Strategy::DocumentAtATime)).await?;
+                    let (planned, _) = search(&scorer, &mut dictionary, &terms, combine, None).await?;
+                    assert_eq!(term, document, "{terms:?} {combine:?}");
+                    assert_eq!(term, planned, "{terms:?} {combine:?}");
+                    let expected = match combine {
+                        Combine::Any => exhaustive.clone(),
+                        Combine::All if terms.is_empty() => Vec::new(),
+                        Combine::All => exhaustive
+                            .iter()
+                            .filter(|(found, _)| terms.iter().all(|v| corpus.documents(v).contains(found)))
+                            .copied()
+                            .collect(),
+                    };
+                    assert_eq!(term, expected, "{terms:?} {combine:?}");
+                }
+            }
+        }
+        let (all, _) = search(&scorer, &mut dictionary, &[&words[0], &words[1]], Combine::All, None).await?;
+        assert!(!all.is_empty());
+
+        // A term no index could hold matches nothing rather than failing.
+        let long = "a".repeat(10_000);
+        let (any, _) = search(&scorer, &mut dictionary, &[&words[0], &long], Combine::Any, None).await?;
+        assert_eq!(any, scorer.search(&mut dictionary, &[&words[0]]).await?);
+        let (all, _) = search(&scorer, &mut dictionary, &[&words[0], &long], Combine::All, None).await?;
+        assert!(all.is_empty());
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,205 @@
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind},
+};
+
+use tokio::fs;
+
+use crate::{case::fold_case, indexed::Dictionary, segment::Segments};
+
+/// Synonyms of query terms, read from a TSV of `term<TAB>synonym,synonym`.
+///
+/// A term expands to itself and the synonyms of its line, in that order, and
+/// only that way: `automobile` finds `car` only if a line says so too.
+/// Terms are expanded after the query is normalized, so every entry has to
+/// be written the way the index stores it, lowercased.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Thesaurus {
+    synonyms: HashMap<String, Vec<String>>,
+    /// Synonyms the index has no postings for, see [`Thesaurus::load`].
+    unknown: Vec<String>,
+}
+
+impl Thesaurus {
+    /// Fails on a line without a tab or with an entry that is empty or not
+    /// normalized. Blank lines and lines starting with `#` are skipped.
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut synonyms = HashMap::<String, Vec<String>>::new();
+        for (i, line) in text.lines().enumerate() {
+            let invalid = |reason: String| Error::new(ErrorKind::InvalidData, format!("line {}: {reason}", i + 1));
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (term, line_synonyms) = line
+                .split_once('\t')
+                .ok_or_else(|| invalid("expected term<TAB>synonyms".to_string()))?;
+            let term = normalized(term.trim()).map_err(invalid)?;
+            let group = synonyms.entry(term.to_string()).or_default();
+            for synonym in line_synonyms.split(',') {
+                let synonym = normalized(synonym.trim()).map_err(invalid)?;
+                if synonym != term && !group.iter().any(|v| v == synonym) {
+                    group.push(synonym.to_string());
+                }
+            }
+        }
+        Ok(Self {
+            synonyms,
+            unknown: vec![],
+        })
+    }
+
+    /// Reads the thesaurus at `path` and notes every synonym not in
+    /// `dictionary` in [`Thesaurus::unknown`]. Those are kept, a later build
+    /// may have them.
+    pub async fn load<S: Segments>(path: &str, dictionary: &mut Dictionary<S>) -> Result<Self, Error> {
+        let text = fs::read_to_string(path).await?;
+        let mut thesaurus = Self::parse(&text).map_err(|e| Error::new(e.kind(), format!("{path}: {e}")))?;
+        let mut unknown = thesaurus.synonyms.values().flatten().cloned().collect::<Vec<_>>();
+        unknown.sort_unstable();
+        unknown.dedup();
+        let mut kept = Vec::new();
+        for synonym in unknown {
+            if dictionary.document_frequency(&synonym).await?.is_none() {
+                kept.push(synonym);
+            }
+        }
+        thesaurus.unknown = kept;
+        Ok(thesaurus)
+    }
+
+    /// Synonyms of the normalized `term`, without the term itself.
+    pub fn synonyms(&self, term: &str) -> &[String] {
+        self.synonyms.get(term).map_or(&[], |v| v.as_slice())
+    }
+
+    /// Synonyms [`Thesaurus::load`] found no postings for, sorted.
+    pub fn unknown(&self) -> &[String] {
+        &self.unknown
+    }
+
+    /// Terms that have synonyms.
+    pub fn len(&self) -> usize {
+        self.synonyms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.synonyms.is_empty()
+    }
+}
+
+fn normalized(entry: &str) -> Result<&str, String> {
+    if entry.is_empty() {
+        return Err("empty entry".to_string());
+    }
+    if entry.contains(char::is_whitespace) || entry.contains(['*', ':']) {
+        return Err(format!("{entry} is not a single term"));
+    }
+    match fold_case(entry) {
+        v if v == entry => Ok(entry),
+        v => Err(format!("{entry} is not normalized, queries look it up as {v}")),
+    }
+}
+
+#[cfg(test)]
+mod tst {
+    use std::{io::Error, path::PathBuf};
+
+    use tokio::fs;
+
+    use crate::{
+        config::IndexerConfig,
+        execute::{execute, execute_with_synonyms, QueryLimits},
+        indexed::{Dictionary, IndexMerger, IndexParser, IndexedBuilder},
+        parser::ParseController,
+        query::parse_query,
+        rank::{DocumentLengths, Scorer},
+        segment::{CommonSegmentSelector, CommonSegments},
+        testsupport::scratch,
+        zones::ZoneSet,
+    };
+
+    use super::Thesaurus;
+
+    async fn fixture(name: &str) -> Result<(PathBuf, String), Error> {
+        let root = scratch(name).await?;
+        let path = root.join("0.xml").to_str().unwrap().to_string();
+        let documents = [
+            ("car", "red car engine"),
+            ("automobile", "red automobile engine"),
+            ("boat", "blue boat engine"),
+        ];
+        let content = documents
+            .iter()
+            .map(|(title, text)| format!("<title>\n{title}\n</title>\n<text>\n{text}\n</text>\n"))
+            .collect::<String>();
+        fs::write(&path, content).await?;
+        let destination = root.join("res").to_str().unwrap().to_string();
+        let config = IndexerConfig::new(1000, 6)?;
+        ParseController::<IndexParser, _, _>::new(
+            vec![path],
+            destination.clone(),
+            root.join("buffer").to_str().unwrap().to_string(),
+            1,
+            IndexedBuilder::new(config, ZoneSet::new(["title", "text"])?)?,
+            IndexMerger::new(config.merger()),
+        )
+        .create_dictionary()
+        .await?;
+        Ok((root, destination))
+    }
+
+    #[test]
+    fn entries_are_checked() {
+        let thesaurus = Thesaurus::parse("# vehicles\ncar\tautomobile, auto,car\n\ncar\tauto,motorcar\n").unwrap();
+        assert_eq!(thesaurus.synonyms("car"), ["automobile", "auto", "motorcar"]);
+        assert!(thesaurus.synonyms("automobile").is_empty());
+        assert_eq!(thesaurus.len(), 1);
+
+        for (text, error) in [
+            ("car automobile", "line 1: expected term<TAB>synonyms"),
+            ("car\tautomobile\nCar\tauto", "line 2: Car is not normalized, queries look it up as car"),
+            ("car\tautomobile,,auto", "line 1: empty entry"),
+            ("car\tmotor car", "line 1: motor car is not a single term"),
+        ] {
+            assert_eq!(Thesaurus::parse(text).unwrap_err().to_string(), error);
+        }
+    }
+
+    #[tokio::test]
+    async fn synonyms_match_with_a_discount() -> Result<(), Error> {
+        let (root, destination) = fixture("synonyms").await?;
+        let path = root.join("synonyms.tsv").to_str().unwrap().to_string();
+        fs::write(&path, "car\tautomobile,motorcar\n").await?;
+        let mut dictionary = Dictionary::<CommonSegments>::new(&destination).await?;
+        let thesaurus = Thesaurus::load(&path, &mut dictionary).await?;
+        assert_eq!(thesaurus.unknown(), ["motorcar"]);
+
+        let selector = CommonSegmentSelector::new();
+        let query = parse_query("CAR engine", &selector).unwrap();
+        let plain = execute(&query, &mut dictionary, QueryLimits::default()).await.unwrap();
+        assert_eq!(plain.documents, [0]);
+        let expanded = execute_with_synonyms(&query, &mut dictionary, QueryLimits::default(), &thesaurus)
+            .await
+            .unwrap();
+        assert_eq!(expanded.documents, [0, 1]);
+        assert_eq!(expanded.expanded_terms, plain.expanded_terms + 2);
+
+        let scorer = |lengths| Scorer::new(&selector, &[("title", 2.0), ("text", 1.0)], lengths);
+        let exact_only = scorer(DocumentLengths::load(&destination).await?)?
+            .search(&mut dictionary, &["car"])
+            .await?;
+        assert_eq!(exact_only.iter().map(|v| v.0).collect::<Vec<_>>(), [0]);
+        let scorer = scorer(DocumentLengths::load(&destination).await?)?.with_synonyms(thesaurus, 0.5);
+        let ranked = scorer.search(&mut dictionary, &["car"]).await?;
+        assert_eq!(ranked.iter().map(|v| v.0).collect::<Vec<_>>(), [0, 1]);
+        assert_eq!(ranked[0], exact_only[0]);
+        assert!((ranked[1].1 - 0.5 * ranked[0].1).abs() < 1e-9, "{ranked:?}");
+
+        let explanation = scorer.search_explain(&mut dictionary, &["car"], 1).await?;
+        assert!((explanation.value - ranked[1].1).abs() < 1e-9);
+        assert!(explanation.to_string().contains("synonym automobile of car"), "{explanation}");
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    num::NonZeroUsize,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Tasks to give [`crate::parser::ParseController::new`] for it to pick
+/// them, see [`TaskPlan`].
+pub const AUTO_TASKS: u16 = 0;
+
+/// What held a build to the tasks it ran.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskLimit {
+    /// As many as were asked for.
+    Requested,
+    /// A task per core.
+    Cores,
+    /// A task per input file, as a task parses a file at a time.
+    Files,
+    /// As many as the memory budget has room for.
+    Memory,
+}
+
+/// Parse tasks of a build and why that many, kept in its
+/// [`crate::report::ParseReport`].
+///
+/// Tasks asked for, or a task per core unless they are, are lowered to the
+/// input files, past which tasks find nothing to parse, and to the parsers
+/// the memory budget holds at what one takes before it flushes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskPlan {
+    pub tasks: u16,
+    pub limit: TaskLimit,
+    /// Tasks asked for, None if they were picked.
+    pub requested: Option<u16>,
+    pub cores: usize,
+    pub files: usize,
+    /// Parsers the memory budget holds, None without a budget.
+    pub memory_tasks: Option<usize>,
+}
+
+impl TaskPlan {
+    /// `memory` is the budget and the bytes a parser takes at most, see
+    /// [`crate::parser::ParserBuilder::task_memory`].
+    pub fn new(requested: Option<u16>, cores: usize, files: usize, memory: Option<(u64, u64)>) -> Self {
+        let (mut tasks, mut limit) = match requested {
+            Some(v) => (v.max(1) as usize, TaskLimit::Requested),
+            None => (cores.max(1), TaskLimit::Cores),
+        };
+        if files < tasks {
+            tasks = files.max(1);
+            limit = TaskLimit::Files;
+        }
+        let memory_tasks = memory
+            .filter(|(_, per_task)| *per_task > 0)
+            .map(|(budget, per_task)| (budget / per_task).max(1) as usize);
+        if let Some(held) = memory_tasks.filter(|v| *v < tasks) {
+            tasks = held;
+            limit = TaskLimit::Memory;
+        }
+        Self {
+            tasks: tasks.min(u16::MAX as usize) as u16,
+            limit,
+            requested,
+            cores,
+            files,
+            memory_tasks,
+        }
+    }
+
+    /// Whether fewer tasks run than were asked for.
+    pub fn lowered(&self) -> bool {
+        self.requested.is_some_and(|v| v > self.tasks)
+    }
+}
+
+/// `4 tasks, one per file of 4 (8 cores)`.
+impl Display for TaskPlan {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} tasks, ", self.tasks)?;
+        match self.limit {
+            TaskLimit::Requested => write!(f, "as requested")?,
+            TaskLimit::Cores => write!(f, "one per core")?,
+            TaskLimit::Files => write!(f, "one per file of {}", self.files)?,
+            TaskLimit::Memory => write!(f, "as many as the memory holds")?,
+        }
+        write!(f, " ({} cores)", self.cores)
+    }
+}
+
+/// Cores the build can run on, 1 if there's no telling.
+pub fn cores() -> usize {
+    std::thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
+
+#[cfg(test)]
+mod tst {
+    use super::{TaskLimit, TaskPlan};
+
+    #[test]
+    fn tasks_are_held_to_cores_files_and_memory() {
+        let plan = |requested, cores, files, memory| {
+            let v = TaskPlan::new(requested, cores, files, memory);
+            (v.tasks, v.limit)
+        };
+        assert_eq!(plan(None, 8, 100, None), (8, TaskLimit::Cores));
+        assert_eq!(plan(None, 64, 10, None), (10, TaskLimit::Files));
+        assert_eq!(plan(None, 0, 10, None), (1, TaskLimit::Cores));
+        assert_eq!(plan(None, 8, 0, None), (1, TaskLimit::Files));
+        assert_eq!(plan(Some(12), 4, 100, None), (12, TaskLimit::Requested));
+        assert_eq!(plan(Some(12), 4, 3, None), (3, TaskLimit::Files));
+        assert_eq!(plan(Some(0), 4, 3, None), (1, TaskLimit::Requested));
+
+        // 1 GiB at 300 MiB a parser.
+        let memory = Some((1 << 30, 300 << 20));
+        assert_eq!(plan(None, 8, 100, memory), (3, TaskLimit::Memory));
+        assert_eq!(plan(None, 8, 2, memory), (2, TaskLimit::Files));
+        assert_eq!(plan(Some(2), 8, 100, memory), (2, TaskLimit::Requested));
+        assert_eq!(plan(None, 8, 100, Some((1 << 20, 300 << 20))), (1, TaskLimit::Memory));
+        assert_eq!(plan(None, 8, 100, Some((1 << 30, 0))), (8, TaskLimit::Cores));
+
+        let lowered = TaskPlan::new(Some(12), 4, 3, memory);
+        assert!(lowered.lowered());
+        assert_eq!(lowered.memory_tasks, Some(3));
+        assert_eq!(lowered.to_string(), "3 tasks, one per file of 3 (4 cores)");
+        assert!(!TaskPlan::new(Some(3), 4, 3, None).lowered());
+        assert!(!TaskPlan::new(None, 64, 3, None).lowered());
+        assert_eq!(TaskPlan::new(None, 8, 100, memory).to_string(), "3 tasks, as many as the memory holds (8 cores)");
+    }
+}
@@ -0,0 +1,172 @@
+use std::io::{Error, ErrorKind};
+
+use fst::{
+    automaton::{Levenshtein, Str},
+    Automaton, IntoStreamer, Map, MapBuilder, Streamer,
+};
+use tokio::fs;
+
+use crate::layout::IndexLayout;
+
+fn invalid(e: impl std::error::Error + Send + Sync + 'static) -> Error {
+    Error::new(ErrorKind::InvalidData, e)
+}
+
+/// Collects terms in dictionary order for [`TermFst`].
+pub(crate) struct TermFstBuilder {
+    builder: MapBuilder<Vec<u8>>,
+}
+
+impl TermFstBuilder {
+    pub(crate) fn new() -> Self {
+        Self {
+            builder: MapBuilder::memory(),
+        }
+    }
+
+    pub(crate) fn push(&mut self, term: &str, ordinal: u64) -> Result<(), Error> {
+        self.builder.insert(term, ordinal).map_err(invalid)
+    }
+
+    pub(crate) async fn save(self, directory: &str) -> Result<(), Error> {
+        let bytes = self.builder.into_inner().map_err(invalid)?;
+        fs::write(IndexLayout::detect(directory).await?.term_fst(directory), bytes).await
+    }
+}
+
+/// Term to cursor ordinal, stored as `terms.fst`.
+///
+/// Only an accelerator: the dictionary files stay the source of truth and
+/// indexes built without it work as before.
+pub struct TermFst {
+    map: Map<Vec<u8>>,
+}
+
+impl TermFst {
+    /// The map of the index in `directory`, or `None` if it was built without one.
+    pub async fn load(directory: &str) -> Result<Option<Self>, Error> {
+        match fs::read(IndexLayout::detect(directory).await?.term_fst(directory)).await {
+            Ok(bytes) => Ok(Some(Self {
+                map: Map::new(bytes).map_err(invalid)?,
+            })),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn get(&self, term: &str) -> Option<usize> {
+        self.map.get(term).map(|v| v as usize)
+    }
+
+    fn collect<A: Automaton>(&self, automaton: A) -> Vec<(String, usize)> {
+        let mut stream = self.map.search(automaton).into_stream();
+        let mut found = Vec::new();
+        while let Some((term, ordinal)) = stream.next() {
+            found.push((String::from_utf8_lossy(term).into_owned(), ordinal as usize));
+        }
+        found
+    }
+
+    /// Terms starting with `prefix`, in dictionary order.
+    pub fn with_prefix(&self, prefix: &str) -> Vec<(String, usize)> {
+        self.collect(Str::new(prefix).starts_with())
+    }
+
+    /// Terms within `distance` edits of `term`, in dictionary order.
+    pub fn fuzzy(&self, term: &str, distance: u32) -> Result<Vec<(String, usize)>, Error> {
+        let automaton = Levenshtein::new(term, distance)
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+        Ok(self.collect(automaton))
+    }
+}
+
+#[cfg(test)]
+mod tst {
+    use std::{io::Error, path::PathBuf};
+
+    use tokio::fs;
+
+    use crate::{
+        config::IndexerConfig,
+        indexed::{Dictionary, IndexMerger, IndexParser, IndexedBuilder},
+        metadata::IndexMetadata,
+        parser::ParseController,
+        segment::CommonSegments,
+        zones::ZoneSet,
+    };
+
+    fn word(mut i: usize) -> String {
+        let mut word = String::new();
+        for _ in 0..4 {
+            word.push((b'a' + (i % 26) as u8) as char);
+            i /= 26;
+        }
+        word
+    }
+
+    /// Builds a 10k-term index with `terms.fst` and opens it with and without the map.
+    async fn dictionaries(
+        name: &str,
+    ) -> Result<(PathBuf, Dictionary<CommonSegments>, Dictionary<CommonSegments>), Error> {
+        let root = std::env::temp_dir().join(format!("{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root).await;
+        fs::create_dir_all(&root).await?;
+        let path = root.join("0.xml").to_str().unwrap().to_string();
+        let content = (0..100)
+            .map(|d| {
+                let text = (0..100).map(|i| word(d * 100 + i)).collect::<Vec<_>>().join(" ");
+                format!("<title>\nshared\n</title>\n<text>\n{text}\n</text>\n")
+            })
+            .collect::<String>();
+        fs::write(&path, content).await?;
+
+        let destination = root.join("res").to_str().unwrap().to_string();
+        let config = IndexerConfig::new(100_000, 6)?;
+        ParseController::<IndexParser, _, _>::new(
+            vec![path],
+            destination.clone(),
+            root.join("buffer").to_str().unwrap().to_string(),
+            1,
+            IndexedBuilder::new(config, ZoneSet::new(["title", "text"])?)?,
+            IndexMerger::new(config.merger()).with_fst(),
+        )
+        .create_dictionary()
+        .await?;
+        assert!(IndexMetadata::load(&destination).await?.fst);
+
+        let accelerated = Dictionary::new(&destination).await?;
+        let copy = root.join("plain").to_str().unwrap().to_string();
+        fs::create_dir_all(&copy).await?;
+        for file in ["dictionary.txt", "lexical_part.txt", "index_part.txt", "block_dir.bin"] {
+            fs::copy(format!("{destination}/{file}"), format!("{copy}/{file}")).await?;
+        }
+        let plain = Dictionary::new(&copy).await?;
+        Ok((root, accelerated, plain))
+    }
+
+    #[tokio::test]
+    async fn lookups_agree_with_dictionary_files() -> Result<(), Error> {
+        let (root, mut accelerated, mut plain) = dictionaries("term_fst").await?;
+        assert_eq!(accelerated.len(), 10_001);
+
+        let mut terms = (0..10_000).step_by(7).map(word).collect::<Vec<_>>();
+        terms.extend(["", "shared", "zzzzz", "aaa", "shar"].map(String::from));
+        for term in terms.iter() {
+            let found = accelerated.find(term).await?.map(|v| v.term);
+            assert_eq!(found, plain.find(term).await?.map(|v| v.term), "{term}");
+        }
+        for prefix in ["", "a", "ab", "sha", "zzz", "qwer", "shared"] {
+            let found = accelerated.terms_with_prefix(prefix).await?;
+            assert_eq!(found, plain.terms_with_prefix(prefix).await?, "{prefix}");
+        }
+        assert_eq!(accelerated.terms_with_prefix("").await?.len(), 10_001);
+        for (term, distance) in [("shard", 1), ("abcd", 1), ("zzzz", 2), ("sharedd", 1), ("q", 0)] {
+            let found = accelerated.fuzzy(term, distance).await?;
+            assert_eq!(found, plain.fuzzy(term, distance).await?, "{term}");
+        }
+        assert_eq!(accelerated.fuzzy("shard", 1).await?, ["shared"]);
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+use std::cmp::Ordering;
+
+/// The one order terms are kept in: by their UTF-8 bytes, which is code
+/// point order and independent of locale. Flushed trees, the merge, the
+/// block directory and lookups all rely on it, so any normalization has to
+/// happen before a term reaches them.
+pub fn term_cmp(a: &str, b: &str) -> Ordering {
+    bytes_cmp(a.as_bytes(), b.as_bytes())
+}
+
+/// [`term_cmp`] over parts of terms, which need not split on a char boundary.
+pub(crate) fn bytes_cmp(a: &[u8], b: &[u8]) -> Ordering {
+    a.cmp(b)
+}
+
+/// A term ordered by [`term_cmp`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TermOrd(pub String);
+
+impl Ord for TermOrd {
+    fn cmp(&self, other: &Self) -> Ordering {
+        term_cmp(&self.0, &other.0)
+    }
+}
+
+impl PartialOrd for TermOrd {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(test)]
+mod tst {
+    use std::{collections::BTreeSet, io::Error};
+
+    use tokio::fs;
+
+    use crate::{
+        indexed::{verify_index, Dictionary, IndexMergeSaver, IndexedTerm, UsageData},
+        segment::CommonSegments,
+    };
+
+    use super::{term_cmp, TermOrd};
+
+    /// Case, combining marks and characters past the BMP, in term order.
+    const TERMS: [&str; 9] = [
+        "Zeta", "alpha", "ez", "e\u{301}", "e\u{301}t", "\u{e9}", "\u{fffd}", "\u{1f600}", "\u{1f601}",
+    ];
+
+    async fn write_index(directory: &String, terms: &[&str]) -> Result<(), Error> {
+        fs::create_dir_all(directory).await?;
+        let mut saver = IndexMergeSaver::<CommonSegments>::new(directory.clone(), 2).await?;
+        for (i, t) in terms.iter().enumerate() {
+            let mut term = IndexedTerm::new(t.to_string());
+            let mut usage = UsageData::new();
+            *usage.use_count_mut() = 1;
+            term.indexes.push(i, usage);
+            term.use_count = 1;
+            saver.push(term).await?;
+        }
+        saver.finish().await?;
+        Ok(())
+    }
+
+    #[test]
+    fn order_is_by_code_point() {
+        assert!(TERMS.windows(2).all(|v| term_cmp(v[0], v[1]).is_lt()));
+        assert!(TERMS.windows(2).all(|v| TermOrd(v[0].into()) < TermOrd(v[1].into())));
+        let parsed = TERMS.iter().rev().collect::<BTreeSet<_>>();
+        assert!(parsed.into_iter().eq(TERMS.iter()));
+        let utf16 = |v: &str| v.encode_utf16().collect::<Vec<_>>();
+        assert!(utf16("\u{1f600}") < utf16("\u{fffd}"));
+    }
+
+    #[tokio::test]
+    async fn unicode_terms_round_trip() -> Result<(), Error> {
+        let root = std::env::temp_dir().join(format!("term_ord_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root).await;
+        let directory = root.join("res").to_str().unwrap().to_string();
+        write_index(&directory, &TERMS).await?;
+        verify_index::<CommonSegments>(&directory).await?;
+
+        let mut dictionary = Dictionary::<CommonSegments>::new(&directory).await?;
+        for term in TERMS {
+            assert_eq!(dictionary.find(term).await?.map(|v| v.term).as_deref(), Some(term));
+        }
+        for missing in ["zeta", "e", "\u{1f602}", "Zet"] {
+            assert!(dictionary.find(missing).await?.is_none(), "{missing}");
+        }
+        assert_eq!(dictionary.terms_with_prefix("e").await?, ["ez", "e\u{301}", "e\u{301}t"]);
+        assert_eq!(dictionary.terms_with_prefix("e\u{301}").await?, ["e\u{301}", "e\u{301}t"]);
+        assert_eq!(dictionary.terms_with_prefix("\u{1f600}").await?, ["\u{1f600}"]);
+        assert_eq!(dictionary.terms_with_prefix("").await?, TERMS);
+
+        let saved = write_index(&root.join("bad").to_str().unwrap().to_string(), &["beta", "alpha"]).await;
+        assert_eq!(saved.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        let repeated = write_index(&root.join("again").to_str().unwrap().to_string(), &["beta", "beta"]).await;
+        assert!(repeated.is_err());
+
+        // Swapping the first two cursors leaves a well-formed but misordered index.
+        let path = format!("{directory}/dictionary.txt");
+        let mut bytes = fs::read(&path).await?;
+        let (first, rest) = bytes[8..].split_at_mut(25);
+        first.swap_with_slice(&mut rest[..25]);
+        fs::write(&path, bytes).await?;
+        let error = verify_index::<CommonSegments>(&directory).await.unwrap_err();
+        assert!(error.to_string().contains("\"Zeta\""), "{error}");
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,246 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    collections::BTreeMap,
+    io::Error,
+    path::{Path, PathBuf},
+};
+
+use tokio::fs;
+
+use crate::{
+    config::IndexerConfig,
+    indexed::{IndexMerger, IndexParser, IndexedBuilder},
+    parser::ParseController,
+    zones::ZoneSet,
+};
+
+/// Shape of a generated corpus. The same spec always yields the same files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorpusSpec {
+    pub docs: usize,
+    /// Distinct words the generator draws from.
+    pub vocab: usize,
+    /// Zipf exponent of the word ranks; 0 draws every word equally often.
+    pub zipf_s: f64,
+    /// Zone tags in document order, with the words put in each.
+    pub zones: Vec<(&'static str, usize)>,
+    pub seed: u64,
+    /// Number of chunk files the documents are split over.
+    pub files: usize,
+}
+
+impl Default for CorpusSpec {
+    fn default() -> Self {
+        Self {
+            docs: 50,
+            vocab: 200,
+            zipf_s: 1.0,
+            zones: vec![("title", 3), ("text", 20)],
+            seed: 1,
+            files: 2,
+        }
+    }
+}
+
+/// Chunk files in the format `RepeatedXmlReader` reads, with what indexing
+/// them has to produce.
+#[derive(Debug, Clone)]
+pub struct Corpus {
+    pub files: Vec<String>,
+    pub attributes: ZoneSet,
+    /// Documents of every term with how often it occurs in them, by id as
+    /// a single-task build assigns them: in file order, one per document.
+    pub postings: BTreeMap<String, Vec<(usize, usize)>>,
+}
+
+/// The word of a vocabulary rank: letters only, so the tokenizer keeps it
+/// whole, and at least three of them.
+pub fn word(rank: usize) -> String {
+    let mut out = String::new();
+    let mut v = rank;
+    while out.len() < 3 || v > 0 {
+        out.push((b'a' + (v % 26) as u8) as char);
+        v /= 26;
+    }
+    out
+}
+
+struct Zipf {
+    cumulative: Vec<f64>,
+    state: u64,
+}
+
+impl Zipf {
+    fn new(vocab: usize, s: f64, seed: u64) -> Self {
+        let mut total = 0.0;
+        let cumulative = (0..vocab.max(1))
+            .map(|rank| {
+                total += 1.0 / ((rank + 1) as f64).powf(s);
+                total
+            })
+            .collect();
+        Self {
+            cumulative,
+            state: seed,
+        }
+    }
+
+    fn next(&mut self) -> usize {
+        self.state = self
+            .state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        let r = (self.state >> 11) as f64 / (1u64 << 53) as f64 * self.cumulative.last().unwrap();
+        self.cumulative.partition_point(|v| *v <= r).min(self.cumulative.len() - 1)
+    }
+}
+
+impl CorpusSpec {
+    /// Writes the chunk files to `directory`, creating it.
+    pub async fn generate(&self, directory: &Path) -> Result<Corpus, Error> {
+        fs::create_dir_all(directory).await?;
+        let mut zipf = Zipf::new(self.vocab, self.zipf_s, self.seed);
+        let files = self.files.max(1);
+        let per_file = self.docs.div_ceil(files);
+        let mut postings = BTreeMap::<String, Vec<(usize, usize)>>::new();
+        let mut paths = Vec::new();
+        for f in 0..files {
+            let mut content = String::new();
+            for document in f * per_file..((f + 1) * per_file).min(self.docs) {
+                let mut counts = BTreeMap::<String, usize>::new();
+                for (zone, words) in self.zones.iter() {
+                    let words = (0..*words).map(|_| word(zipf.next())).collect::<Vec<_>>();
+                    for w in words.iter() {
+                        *counts.entry(w.clone()).or_default() += 1;
+                    }
+                    content.push_str(&format!("<{zone}>\n{}\n</{zone}>\n", words.join(" ")));
+                }
+                for (term, count) in counts {
+                    postings.entry(term).or_default().push((document, count));
+                }
+            }
+            let path = directory.join(format!("{f}.xml")).to_str().unwrap().to_string();
+            fs::write(&path, content).await?;
+            paths.push(path);
+        }
+        Ok(Corpus {
+            files: paths,
+            attributes: ZoneSet::new(self.zones.iter().map(|(zone, _)| *zone))?,
+            postings,
+        })
+    }
+}
+
+impl Corpus {
+    /// Indexes the corpus into `root/res` with a single task and returns
+    /// the destination.
+    pub async fn index(&self, root: &Path) -> Result<String, Error> {
+        self.index_with(root, |v| v).await
+    }
+
+    /// [`Self::index`] with the merger set up by `merger`.
+    pub async fn index_with(&self, root: &Path, merger: fn(IndexMerger) -> IndexMerger) -> Result<String, Error> {
+        let destination = root.join("res").to_str().unwrap().to_string();
+        let config = IndexerConfig::new(1000, 6)?;
+        ParseController::<IndexParser, _, _>::new(
+            self.files.clone(),
+            destination.clone(),
+            root.join("buffer").to_str().unwrap().to_string(),
+            1,
+            IndexedBuilder::new(config, self.attributes.clone())?,
+            merger(IndexMerger::new(config.merger())),
+        )
+        .create_dictionary()
+        .await?;
+        Ok(destination)
+    }
+
+    /// Documents of `term`, in id order.
+    pub fn documents(&self, term: &str) -> Vec<usize> {
+        self.postings
+            .get(term)
+            .map_or(Vec::new(), |v| v.iter().map(|(document, _)| *document).collect())
+    }
+}
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Counts allocations made by the current thread, which is the whole of a
+/// `#[tokio::test]` apart from the blocking file calls.
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|v| v.set(v.get() + 1));
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Allocations the current thread made so far.
+pub fn allocations() -> usize {
+    ALLOCATIONS.with(|v| v.get())
+}
+
+/// A fresh scratch directory named after `name` and the process.
+pub async fn scratch(name: &str) -> Result<PathBuf, Error> {
+    let root = std::env::temp_dir().join(format!("{name}_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root).await;
+    fs::create_dir_all(&root).await?;
+    Ok(root)
+}
+
+#[cfg(test)]
+mod tst {
+    use std::io::Error;
+
+    use tokio::fs;
+
+    use super::{scratch, word, CorpusSpec};
+
+    #[tokio::test]
+    async fn generation_is_deterministic() -> Result<(), Error> {
+        let root = scratch("testsupport").await?;
+        let spec = CorpusSpec {
+            docs: 30,
+            vocab: 100,
+            zipf_s: 1.2,
+            files: 3,
+            ..CorpusSpec::default()
+        };
+        let first = spec.generate(&root.join("a")).await?;
+        let second = spec.generate(&root.join("b")).await?;
+        assert_eq!(first.files.len(), 3);
+        assert_eq!(first.postings, second.postings);
+        for (a, b) in first.files.iter().zip(second.files.iter()) {
+            assert_eq!(fs::read(a).await?, fs::read(b).await?);
+        }
+        let other = CorpusSpec { seed: 2, ..spec.clone() }.generate(&root.join("c")).await?;
+        assert_ne!(first.postings, other.postings);
+
+        // Zipf skew puts the top rank in most documents.
+        let top = first.documents(&word(0)).len();
+        let tail = first.documents(&word(99)).len();
+        assert!(top > 20 && top > 4 * tail, "{top} vs {tail}");
+        let words = first
+            .postings
+            .values()
+            .flatten()
+            .map(|(_, count)| count)
+            .sum::<usize>();
+        assert_eq!(words, 30 * 23);
+        assert!(first.postings.keys().all(|v| v.len() >= 3));
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,210 @@
+//! The merge output of [`crate::config::OutputFormat::Text`]: every term as
+//! a block of lines, for debugging and for reading an index by eye.
+//!
+//! ```text
+//! rust
+//! 5 2
+//! 0 title+text 3
+//! 4 text 2
+//!
+//! ```
+//!
+//! A block holds the term, its uses and postings, then one line per posting
+//! with the document, its zones and the uses in it. An empty line ends the
+//! block. [`TextTermProvider`] reads the terms back.
+
+use std::{
+    io::{Error, ErrorKind},
+    marker::PhantomData,
+    path::Path,
+};
+
+use async_trait::async_trait;
+use tokio::{
+    fs::File,
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter, Lines},
+};
+
+use crate::{
+    indexed::{IndexedTerm, UsageData},
+    parser::{TermProvider, TermSink},
+    segment::Segments,
+};
+
+/// File of the terms in the destination.
+pub const TERMS_FILE: &str = "terms.txt";
+
+pub struct TextTermSink<S: Segments> {
+    writer: BufWriter<File>,
+    segment: PhantomData<S>,
+}
+
+impl<S: Segments> TextTermSink<S> {
+    /// Writes [`TERMS_FILE`] in `directory`, which has to exist.
+    pub async fn create(directory: &str) -> Result<Self, Error> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(Path::new(directory).join(TERMS_FILE)).await?),
+            segment: PhantomData::<S>,
+        })
+    }
+}
+
+#[async_trait]
+impl<S: Segments> TermSink for TextTermSink<S> {
+    type Term = IndexedTerm<S>;
+
+    async fn push(&mut self, term: Self::Term) -> Result<(), Error> {
+        let mut block = format!("{}\n{} {}\n", term.term, term.use_count, term.indexes.len());
+        for (document, usage) in term.indexes.iter_ref() {
+            block.push_str(&format!("{document} {} {}\n", usage.segments(), usage.use_count()));
+        }
+        block.push('\n');
+        self.writer.write_all(block.as_bytes()).await
+    }
+
+    async fn finish(mut self) -> Result<(), Error> {
+        self.writer.flush().await
+    }
+}
+
+/// Reads back the terms of a [`TextTermSink`], in the order they were
+/// written.
+pub struct TextTermProvider<S: Segments> {
+    lines: Lines<BufReader<File>>,
+    segment: PhantomData<S>,
+}
+
+impl<S: Segments> TextTermProvider<S> {
+    /// Opens [`TERMS_FILE`] in `directory`.
+    pub async fn open(directory: &str) -> Result<Self, Error> {
+        Ok(Self {
+            lines: BufReader::new(File::open(Path::new(directory).join(TERMS_FILE)).await?).lines(),
+            segment: PhantomData::<S>,
+        })
+    }
+
+    /// The next term, `None` once the file ends between blocks.
+    pub async fn read_term(&mut self) -> Result<Option<IndexedTerm<S>>, Error> {
+        let Some(name) = self.lines.next_line().await? else {
+            return Ok(None);
+        };
+        let counts = self.line().await?;
+        let (use_count, postings) = counts.split_once(' ').ok_or_else(|| malformed(&counts))?;
+        let mut term = IndexedTerm::new(name);
+        term.use_count = use_count.parse().map_err(|_| malformed(&counts))?;
+        for _ in 0..postings.parse::<usize>().map_err(|_| malformed(&counts))? {
+            let line = self.line().await?;
+            let mut fields = line.split(' ');
+            let (Some(document), Some(zones), Some(uses), None) =
+                (fields.next(), fields.next(), fields.next(), fields.next())
+            else {
+                return Err(malformed(&line));
+            };
+            let mut usage = UsageData::<S>::new();
+            *usage.use_count_mut() = uses.parse().map_err(|_| malformed(&line))?;
+            for zone in zones.split('+').filter(|v| *v != "-") {
+                S::selector_for(zone)(usage.segments_mut(), 1);
+            }
+            term.indexes.push(document.parse().map_err(|_| malformed(&line))?, usage);
+        }
+        let end = self.line().await?;
+        if !end.is_empty() {
+            return Err(malformed(&end));
+        }
+        Ok(Some(term))
+    }
+
+    async fn line(&mut self) -> Result<String, Error> {
+        self.lines
+            .next_line()
+            .await?
+            .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "terms file ends inside a term"))
+    }
+}
+
+fn malformed(line: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("malformed line {line:?} in the terms file"))
+}
+
+#[async_trait]
+impl<S: Segments> TermProvider for TextTermProvider<S> {
+    type Term = IndexedTerm<S>;
+
+    async fn next_term(&mut self) -> Option<Self::Term> {
+        self.read_term().await.ok()?
+    }
+}
+
+#[cfg(test)]
+mod tst {
+    use std::io::Error;
+
+    use tokio::fs;
+
+    use super::{TextTermProvider, TERMS_FILE};
+    use crate::{
+        config::{IndexerConfig, OutputFormat},
+        indexed::{Dictionary, IndexMerger, IndexParser, IndexedBuilder, IndexedTerm},
+        parser::{ParseController, TermProvider},
+        segment::CommonSegments,
+        testsupport::{scratch, CorpusSpec},
+    };
+
+    #[tokio::test]
+    async fn text_output_reads_back_as_the_binary_index() -> Result<(), Error> {
+        let root = scratch("text_sink").await?;
+        let corpus = CorpusSpec { docs: 200, vocab: 1000, files: 2, ..CorpusSpec::default() }
+            .generate(&root.join("inp"))
+            .await?;
+        // The config of `corpus.index`, so both merge the same buffers.
+        let config = IndexerConfig::new(1000, 6)?;
+        let text = root.join("res_text").to_str().unwrap().to_string();
+        ParseController::<IndexParser, _, _>::new(
+            corpus.files.clone(),
+            text.clone(),
+            root.join("buffer").to_str().unwrap().to_string(),
+            1,
+            IndexedBuilder::new(config, corpus.attributes.clone())?,
+            IndexMerger::new(config.merger().with_format(OutputFormat::Text)),
+        )
+        .create_dictionary()
+        .await?;
+        let binary = corpus.index(&root).await?;
+
+        let written = fs::read_to_string(root.join("res_text").join(TERMS_FILE)).await?;
+        assert!(written.ends_with("\n\n"));
+        let mut provider = TextTermProvider::<CommonSegments>::open(&text).await?;
+        let mut dictionary = Dictionary::<CommonSegments>::new(&binary).await?;
+        let mut read = 0;
+        while let Some(term) = provider.next_term().await {
+            let expected = dictionary.find(&term.term).await?.unwrap();
+            assert_eq!(term.to_string(), expected.to_string());
+            assert_eq!(term.use_count, expected.use_count);
+            let uses = |v: &IndexedTerm<CommonSegments>| {
+                v.indexes.iter_ref().map(|(d, u)| (*d, u.use_count())).collect::<Vec<_>>()
+            };
+            assert_eq!(uses(&term), uses(&expected));
+            read += 1;
+        }
+        assert_eq!(read, dictionary.len());
+        assert!(provider.read_term().await?.is_none());
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn truncated_text_is_an_error() -> Result<(), Error> {
+        let root = scratch("text_sink_truncated").await?;
+        let directory = root.to_str().unwrap().to_string();
+        fs::write(root.join(TERMS_FILE), "rust\n2 1\n0 title 2\n\nasync\n3 2\n1 text 1\n").await?;
+        let mut provider = TextTermProvider::<CommonSegments>::open(&directory).await?;
+        let first = provider.read_term().await?.unwrap();
+        assert_eq!(first.to_string(), "rust cf=2 df=1 [0:title]");
+        assert!(provider.read_term().await.is_err());
+        fs::write(root.join(TERMS_FILE), "rust\ntwo 1\n").await?;
+        let mut provider = TextTermProvider::<CommonSegments>::open(&directory).await?;
+        assert!(provider.next_term().await.is_none());
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
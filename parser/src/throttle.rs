@@ -0,0 +1,149 @@
+use std::{
+    io::{Error, ErrorKind},
+    time::Duration,
+};
+
+use save::u8::ReadRate;
+use sysinfo::{ProcessorExt, RefreshKind, System, SystemExt};
+use tokio::task::JoinHandle;
+
+/// Slowest a [`Throttle`] watching the CPU slows reads to, as a fraction of
+/// its rate.
+const SLOWEST_FRACTION: u64 = 16;
+
+/// How often a [`Throttle`] watching the CPU looks at it.
+const CPU_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Keeps a build from taking all of a shared machine: every parser task
+/// reads its input at no more than the rate, and steps aside between
+/// documents.
+#[derive(Debug, Clone)]
+pub struct Throttle {
+    rate: ReadRate,
+    bytes_per_sec: u64,
+    pause: Duration,
+    cpu_threshold: Option<f32>,
+}
+
+impl Throttle {
+    /// Fails on a rate of zero, which would never read anything.
+    pub fn new(bytes_per_sec: u64) -> Result<Self, Error> {
+        if bytes_per_sec == 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, "a throttle needs a rate above zero"));
+        }
+        Ok(Self {
+            rate: ReadRate::new(bytes_per_sec),
+            bytes_per_sec,
+            pause: Duration::ZERO,
+            cpu_threshold: None,
+        })
+    }
+
+    /// Reads `512k`, `10M` or `1G` bytes per second, or a bare count of
+    /// bytes.
+    pub fn parse_rate(raw: &str) -> Result<u64, Error> {
+        let raw = raw.trim();
+        let (digits, scale) = match raw.chars().last().map(|v| v.to_ascii_lowercase()) {
+            Some('k') => (&raw[..raw.len() - 1], 1 << 10),
+            Some('m') => (&raw[..raw.len() - 1], 1 << 20),
+            Some('g') => (&raw[..raw.len() - 1], 1 << 30),
+            _ => (raw, 1),
+        };
+        digits
+            .parse::<u64>()
+            .ok()
+            .and_then(|v| v.checked_mul(scale))
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("{raw} is not a read rate")))
+    }
+
+    /// Sleep for `pause` after every document instead of only yielding.
+    pub fn with_pause(mut self, pause: Duration) -> Self {
+        self.pause = pause;
+        self
+    }
+
+    /// Slow reads down while the system CPU is busier than `percent`, see
+    /// [`Self::adjust`].
+    pub fn with_cpu_threshold(mut self, percent: f32) -> Self {
+        self.cpu_threshold = Some(percent);
+        self
+    }
+
+    /// The rate the readers are held to, shared with them.
+    pub fn rate(&self) -> ReadRate {
+        self.rate.clone()
+    }
+
+    /// Lets the other tasks on the worker run before the next document.
+    pub async fn between_documents(&self) {
+        match self.pause.is_zero() {
+            true => tokio::task::yield_now().await,
+            false => tokio::time::sleep(self.pause).await,
+        }
+    }
+
+    /// Halves the rate while `cpu_usage` is over the threshold, down to a
+    /// sixteenth of the one given, and doubles it back once it isn't.
+    pub fn adjust(&self, cpu_usage: f32) {
+        let Some(threshold) = self.cpu_threshold else {
+            return;
+        };
+        let current = self.rate.get();
+        let adjusted = match cpu_usage > threshold {
+            true => (current / 2).max(self.bytes_per_sec / SLOWEST_FRACTION).max(1),
+            false => current.saturating_mul(2).min(self.bytes_per_sec),
+        };
+        if adjusted != current {
+            log::info!("CPU at {cpu_usage:.0}%, reading at {} KiB/s", adjusted / 1024);
+            self.rate.set(adjusted);
+        }
+    }
+
+    /// Looks at the CPU every second and [`Self::adjust`]s to it, if there
+    /// is a threshold. The task runs until aborted.
+    pub fn watch_cpu(&self) -> Option<JoinHandle<()>> {
+        self.cpu_threshold?;
+        let throttle = self.clone();
+        Some(tokio::spawn(async move {
+            let mut system = System::new_with_specifics(RefreshKind::new().with_cpu());
+            loop {
+                tokio::time::sleep(CPU_INTERVAL).await;
+                system.refresh_cpu();
+                throttle.adjust(system.global_processor_info().cpu_usage());
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tst {
+    use super::Throttle;
+
+    #[test]
+    fn rate_backs_off_under_load() {
+        assert_eq!(Throttle::parse_rate("512k").unwrap(), 512 * 1024);
+        assert_eq!(Throttle::parse_rate("10M").unwrap(), 10 * 1024 * 1024);
+        assert_eq!(Throttle::parse_rate("300").unwrap(), 300);
+        assert!(Throttle::parse_rate("fast").is_err());
+        assert!(Throttle::new(0).is_err());
+
+        let throttle = Throttle::new(1600).unwrap();
+        throttle.adjust(99.0);
+        assert_eq!(throttle.rate().get(), 1600);
+
+        let throttle = throttle.with_cpu_threshold(80.0);
+        let rate = throttle.rate();
+        throttle.adjust(90.0);
+        assert_eq!(rate.get(), 800);
+        for _ in 0..10 {
+            throttle.adjust(90.0);
+        }
+        assert_eq!(rate.get(), 100);
+        throttle.adjust(40.0);
+        assert_eq!(rate.get(), 200);
+        for _ in 0..10 {
+            throttle.adjust(40.0);
+        }
+        assert_eq!(rate.get(), 1600);
+    }
+}
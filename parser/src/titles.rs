@@ -0,0 +1,49 @@
+use std::io::{Error, ErrorKind};
+
+use save::writer::{variable_load, variable_save_usize};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter},
+};
+
+use crate::layout::IndexLayout;
+
+/// Title of every document, by document id, stored as `titles.txt`.
+///
+/// Titles are kept the way the tokenizer saw them, see
+/// [`crate::boost::normalize_title`]. Documents without one hold an empty string.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DocumentTitles {
+    pub titles: Vec<String>,
+}
+
+impl DocumentTitles {
+    pub fn get(&self, document: usize) -> Option<&str> {
+        self.titles
+            .get(document)
+            .map(|v| v.as_str())
+            .filter(|v| !v.is_empty())
+    }
+
+    pub async fn save(&self, directory: &str) -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(IndexLayout::detect(directory).await?.titles(directory)).await?);
+        writer.write_u64(self.titles.len() as u64).await?;
+        for v in self.titles.iter() {
+            variable_save_usize(v.len(), &mut writer).await?;
+            writer.write_all(v.as_bytes()).await?;
+        }
+        writer.flush().await
+    }
+
+    pub async fn load(directory: &str) -> Result<Self, Error> {
+        let mut reader = BufReader::new(File::open(IndexLayout::detect(directory).await?.titles(directory)).await?);
+        let len = reader.read_u64().await? as usize;
+        let mut titles = Vec::with_capacity(len);
+        for _ in 0..len {
+            let mut title = vec![0u8; variable_load(&mut reader).await?];
+            reader.read_exact(&mut title).await?;
+            titles.push(String::from_utf8(title).map_err(|e| Error::new(ErrorKind::InvalidData, e))?);
+        }
+        Ok(Self { titles })
+    }
+}
@@ -0,0 +1,541 @@
+//! Pre-tokenized chunks, written by
+//! [`RepeatedXmlReader::divide_write_binary`] so the indexer doesn't have to
+//! tokenize the text a second time.
+//!
+//! A chunk starts with [`MAGIC`] and the zone names in document order, each
+//! a varint length and UTF-8 bytes after their varint count. Records follow,
+//! each opened by a varint tag:
+//!
+//! - [`ZONE_END`] closes the current zone;
+//! - [`DOCUMENT_END`] follows the last zone of a document;
+//! - [`NUMERIC`] is followed by the varint numeric value of the document;
+//! - any larger tag is a word of `tag - WORD` UTF-8 bytes.
+//!
+//! Varints are those of [`variable_encode_u64`]. Words are stored as the
+//! writer's interpreter produced them, so the reader never folds them again.
+
+use std::{
+    io::{Error, ErrorKind},
+    marker::PhantomData,
+    path::Path,
+};
+
+use async_trait::async_trait;
+use save::{
+    u8::{OffsetU8Provider, U8Provider},
+    writer::variable_encode_u64,
+};
+
+use crate::{
+    reader::{CharInterpretation, Reader, ReaderResult},
+    rep_reader::{RepeatedXmlReader, ZoneRepeatedReader},
+    zones::{ZoneId, ZoneSet},
+};
+
+pub const MAGIC: &[u8; 4] = b"TOK1";
+/// Extension of chunk files in this format, the rest are read as XML.
+pub const EXTENSION: &str = "tok";
+
+pub const ZONE_END: u64 = 0;
+pub const DOCUMENT_END: u64 = 1;
+pub const NUMERIC: u64 = 2;
+pub const WORD: u64 = 3;
+
+/// Whether `path` names a pre-tokenized chunk.
+pub fn is_token_stream(path: &Path) -> bool {
+    path.extension().is_some_and(|v| v == EXTENSION)
+}
+
+/// The header of a chunk over the zones of `attribute_order`.
+pub fn encode_header(attribute_order: &ZoneSet, out: &mut Vec<u8>) {
+    out.extend_from_slice(MAGIC);
+    variable_encode_u64(attribute_order.len() as u64, out);
+    for zone in attribute_order.iter() {
+        variable_encode_u64(zone.len() as u64, out);
+        out.extend_from_slice(zone.as_bytes());
+    }
+}
+
+pub fn encode_word(word: &str, out: &mut Vec<u8>) {
+    variable_encode_u64(WORD + word.len() as u64, out);
+    out.extend_from_slice(word.as_bytes());
+}
+
+/// Reads the chunks of [`RepeatedXmlReader::divide_write_binary`] with the
+/// results the XML reader gave while writing them.
+pub struct TokenStreamReader<Provider: U8Provider + Send, Interpreter: CharInterpretation + Send> {
+    reader: OffsetU8Provider<Provider>,
+    attribute_order: ZoneSet,
+    attribute_index: usize,
+    numeric: Option<u64>,
+    position: u64,
+    interpreter: PhantomData<Interpreter>,
+}
+
+impl<Provider: U8Provider + Send, Interpreter: CharInterpretation + Send>
+    TokenStreamReader<Provider, Interpreter>
+{
+    /// Fails unless the chunk was written over the zones of `attribute_order`.
+    pub async fn new(reader: Provider, attribute_order: ZoneSet) -> Result<Self, Error> {
+        let mut reader = Self {
+            reader: OffsetU8Provider::new(reader),
+            attribute_order,
+            attribute_index: 0,
+            numeric: None,
+            position: 0,
+            interpreter: PhantomData::<Interpreter>,
+        };
+        let invalid = |v: &str| Error::new(ErrorKind::InvalidData, v.to_string());
+        match reader.reader.take::<4>().await {
+            Some(v) if &v == MAGIC => {}
+            _ => return Err(reader.error_or(invalid("not a token stream"))),
+        }
+        let mut zones = Vec::new();
+        let count = reader.varint().await.ok_or_else(|| invalid("truncated header"))?;
+        for _ in 0..count {
+            let zone = reader.bytes().await.ok_or_else(|| invalid("truncated header"))?;
+            zones.push(String::from_utf8(zone).map_err(|e| invalid(&e.to_string()))?);
+        }
+        if !zones.iter().map(String::as_str).eq(reader.attribute_order.iter()) {
+            return Err(invalid(&format!(
+                "token stream has zones {zones:?}, not {:?}",
+                reader.attribute_order.iter().collect::<Vec<_>>()
+            )));
+        }
+        reader.position = reader.reader.offset();
+        Ok(reader)
+    }
+
+    fn error_or(&mut self, e: Error) -> Error {
+        self.reader.take_error().unwrap_or(e)
+    }
+
+    async fn varint(&mut self) -> Option<u64> {
+        let mut v = 0u64;
+        let mut shift = 0;
+        loop {
+            let next = self.reader.next_u8().await?;
+            v |= ((next & 0b111_1111) as u64).checked_shl(shift)?;
+            if next & 0b1000_0000 != 0 {
+                return Some(v);
+            }
+            shift += 7;
+        }
+    }
+
+    async fn bytes(&mut self) -> Option<Vec<u8>> {
+        let len = self.varint().await?;
+        self.read(len).await
+    }
+
+    async fn read(&mut self, len: u64) -> Option<Vec<u8>> {
+        let mut out = Vec::with_capacity(len as usize);
+        for _ in 0..len {
+            out.push(self.reader.next_u8().await?);
+        }
+        Some(out)
+    }
+
+    fn drop_document(&mut self) -> ReaderResult {
+        let warning = format!(
+            "unexpected end of file inside <{}> at byte {}",
+            self.zone(),
+            self.reader.offset()
+        );
+        self.attribute_index = 0;
+        self.numeric = None;
+        ReaderResult::Malformed(warning)
+    }
+}
+
+#[async_trait]
+impl<Provider: U8Provider + Send, Interpreter: CharInterpretation + Send> Reader
+    for TokenStreamReader<Provider, Interpreter>
+{
+    type UProvider = Provider;
+    type Interpreter = Interpreter;
+
+    async fn next_word(&mut self) -> Result<Option<ReaderResult>, Error> {
+        loop {
+            let tag = self.varint().await;
+            if tag.is_none() {
+                if let Some(e) = self.reader.take_error() {
+                    return Err(e);
+                }
+                // A record cut short is dropped with its document.
+                if self.attribute_index != 0 || self.reader.offset() != self.position {
+                    self.position = self.reader.offset();
+                    return Ok(Some(self.drop_document()));
+                }
+                return Ok(None);
+            }
+            match tag.unwrap() {
+                ZONE_END => {
+                    self.position = self.reader.offset();
+                    return Ok(Some(ReaderResult::AttributeEnd));
+                }
+                DOCUMENT_END if self.attribute_index == 0 => self.position = self.reader.offset(),
+                DOCUMENT_END => {
+                    let warning = format!(
+                        "document ends inside <{}> at byte {}",
+                        self.zone(),
+                        self.reader.offset()
+                    );
+                    self.position = self.reader.offset();
+                    self.attribute_index = 0;
+                    self.numeric = None;
+                    return Ok(Some(ReaderResult::Malformed(warning)));
+                }
+                NUMERIC => match self.varint().await {
+                    Some(v) => {
+                        self.numeric = Some(v);
+                        self.position = self.reader.offset();
+                    }
+                    None => continue,
+                },
+                len => {
+                    let word = match self.read(len - WORD).await {
+                        Some(v) => v,
+                        None => continue,
+                    };
+                    self.position = self.reader.offset();
+                    return match String::from_utf8(word) {
+                        Ok(word) => Ok(Some(ReaderResult::Word(word))),
+                        Err(e) => Err(Error::new(ErrorKind::InvalidData, e.to_string())),
+                    };
+                }
+            }
+        }
+    }
+
+    fn position(&self) -> u64 {
+        self.position
+    }
+
+    fn input_digest(&self) -> Option<[u8; 32]> {
+        self.reader.digest()
+    }
+}
+
+#[async_trait]
+impl<Provider: U8Provider + Send, Interpreter: CharInterpretation + Send> ZoneRepeatedReader
+    for TokenStreamReader<Provider, Interpreter>
+{
+    async fn transform_zone(&mut self) {
+        self.attribute_index += 1;
+        self.attribute_index %= self.attribute_order.len();
+    }
+
+    fn zone_id(&self) -> ZoneId {
+        self.attribute_order.id_at(self.attribute_index).unwrap()
+    }
+
+    fn zone_set(&self) -> &ZoneSet {
+        &self.attribute_order
+    }
+
+    fn take_numeric(&mut self) -> Option<u64> {
+        self.numeric.take()
+    }
+}
+
+/// An XML chunk or a pre-tokenized one, told apart by [`is_token_stream`].
+pub enum ChunkReader<Provider: U8Provider + Send, Interpreter: CharInterpretation + Send> {
+    Xml(RepeatedXmlReader<Provider, Interpreter>),
+    Tokens(TokenStreamReader<Provider, Interpreter>),
+}
+
+#[async_trait]
+impl<Provider: U8Provider + Send, Interpreter: CharInterpretation + Send> Reader
+    for ChunkReader<Provider, Interpreter>
+{
+    type UProvider = Provider;
+    type Interpreter = Interpreter;
+
+    async fn next_word(&mut self) -> Result<Option<ReaderResult>, Error> {
+        match self {
+            ChunkReader::Xml(v) => v.next_word().await,
+            ChunkReader::Tokens(v) => v.next_word().await,
+        }
+    }
+
+    fn position(&self) -> u64 {
+        match self {
+            ChunkReader::Xml(v) => v.position(),
+            ChunkReader::Tokens(v) => v.position(),
+        }
+    }
+
+    fn input_digest(&self) -> Option<[u8; 32]> {
+        match self {
+            ChunkReader::Xml(v) => v.input_digest(),
+            ChunkReader::Tokens(v) => v.input_digest(),
+        }
+    }
+}
+
+#[async_trait]
+impl<Provider: U8Provider + Send, Interpreter: CharInterpretation + Send> ZoneRepeatedReader
+    for ChunkReader<Provider, Interpreter>
+{
+    async fn transform_zone(&mut self) {
+        match self {
+            ChunkReader::Xml(v) => v.transform_zone().await,
+            ChunkReader::Tokens(v) => v.transform_zone().await,
+        }
+    }
+
+    fn zone_id(&self) -> ZoneId {
+        match self {
+            ChunkReader::Xml(v) => v.zone_id(),
+            ChunkReader::Tokens(v) => v.zone_id(),
+        }
+    }
+
+    fn zone_set(&self) -> &ZoneSet {
+        match self {
+            ChunkReader::Xml(v) => v.zone_set(),
+            ChunkReader::Tokens(v) => v.zone_set(),
+        }
+    }
+
+    fn take_numeric(&mut self) -> Option<u64> {
+        match self {
+            ChunkReader::Xml(v) => v.take_numeric(),
+            ChunkReader::Tokens(v) => v.take_numeric(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tst {
+    use std::{
+        io::Error,
+        sync::{atomic::AtomicU32, Arc},
+    };
+
+    use save::u8::{CommU8Provider, U8Provider};
+    use tokio::fs;
+
+    use crate::{
+        config::IndexerConfig,
+        indexed::{IndexMerger, IndexParser, IndexedBuilder},
+        layout::IndexLayout,
+        metadata::IndexMetadata,
+        parser::ParseController,
+        query::{QueryCache, QueryError},
+        rank::DocumentLengths,
+        reader::{CaseKeepingInterpreter, Reader, ReaderResult},
+        rep_reader::{RepeatedXmlReader, ZoneRepeatedReader},
+        segment::{CommonSegmentSelector, CommonSegments},
+        testsupport::{scratch, CorpusSpec},
+        zones::ZoneSet,
+    };
+
+    use super::{TokenStreamReader, EXTENSION};
+
+    async fn read_all<R: ZoneRepeatedReader + Send>(reader: &mut R) -> Result<Vec<String>, Error> {
+        let mut read = vec![];
+        while let Some(v) = reader.next_word().await? {
+            match v {
+                ReaderResult::Word(w) => read.push(w),
+                ReaderResult::AttributeEnd => {
+                    read.push(format!("AttributeEnd {}", reader.zone()));
+                    if reader.zone() == "text" {
+                        read.push(format!("Numeric {:?}", reader.take_numeric()));
+                    }
+                    reader.transform_zone().await;
+                }
+                ReaderResult::Malformed(w) => read.push(format!("Malformed {w}")),
+            }
+        }
+        Ok(read)
+    }
+
+    #[tokio::test]
+    async fn token_stream_reads_like_the_xml() -> Result<(), Error> {
+        let root = scratch("token_stream_reads").await?;
+        let path = root.join("0.xml").to_str().unwrap().to_string();
+        fs::write(
+            &path,
+            "<page><ts>12</ts>\n<title>\nStraße One\n</title>\n<text>\nFirst naïve text\n</text>\n</page>\n\
+             <page>\n<title>\nbroken\n</title>\n<text>\ncut\n<title>\nThree\n</title>\n<text>\nlast\n</text>\n",
+        )
+        .await?;
+        let attributes = ZoneSet::new(["title", "text"])?;
+        let xml = || async {
+            Ok::<_, Error>(
+                RepeatedXmlReader::<_, CaseKeepingInterpreter>::new(
+                    CommU8Provider::from_path(&path).await?,
+                    attributes.clone(),
+                )
+                .await?
+                .with_numeric_tag("ts".to_string()),
+            )
+        };
+        let expected = read_all(&mut xml().await?).await?;
+        xml()
+            .await?
+            .divide_write_binary(root.to_str().unwrap().to_string(), 1, Arc::new(AtomicU32::new(0)))
+            .await?;
+
+        // The malformed document is left out, the two others get a chunk each.
+        let mut read = vec![];
+        for i in 0..2 {
+            let chunk = root.join(format!("{i}.{EXTENSION}")).to_str().unwrap().to_string();
+            let mut tokens = TokenStreamReader::<_, CaseKeepingInterpreter>::new(
+                CommU8Provider::from_path(&chunk).await?,
+                attributes.clone(),
+            )
+            .await?;
+            read.extend(read_all(&mut tokens).await?);
+            assert_eq!(tokens.position(), fs::metadata(&chunk).await?.len());
+        }
+        assert!(fs::metadata(root.join(format!("2.{EXTENSION}"))).await.is_err());
+        let mut kept = expected.clone();
+        let broken = kept.iter().position(|v| v == "broken").unwrap();
+        let malformed = kept.iter().position(|v| v.starts_with("Malformed")).unwrap();
+        kept.drain(broken..=malformed);
+        assert_eq!(read, kept);
+        assert!(read.contains(&"Straße".to_string()) && read.contains(&"Numeric Some(12)".to_string()));
+
+        // A chunk cut short drops the document it ends in.
+        let chunk = root.join(format!("0.{EXTENSION}"));
+        let bytes = fs::read(&chunk).await?;
+        fs::write(&chunk, &bytes[..bytes.len() - 4]).await?;
+        let mut tokens = TokenStreamReader::<_, CaseKeepingInterpreter>::new(
+            CommU8Provider::from_path(&chunk.to_str().unwrap().to_string()).await?,
+            attributes.clone(),
+        )
+        .await?;
+        let read = read_all(&mut tokens).await?;
+        assert!(read.last().unwrap().starts_with("Malformed unexpected end of file inside <text>"), "{read:?}");
+
+        let other = ZoneSet::new(["title"])?;
+        let error = TokenStreamReader::<_, CaseKeepingInterpreter>::new(
+            CommU8Provider::from_path(&chunk.to_str().unwrap().to_string()).await?,
+            other,
+        )
+        .await
+        .err()
+        .unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn binary_chunks_index_like_xml_chunks() -> Result<(), Error> {
+        let root = scratch("binary_chunks").await?;
+        let corpus = CorpusSpec {
+            docs: 2000,
+            vocab: 5000,
+            zones: vec![("title", 5), ("text", 200)],
+            files: 4,
+            ..CorpusSpec::default()
+        }
+        .generate(&root.join("xml"))
+        .await?;
+        // One binary chunk per XML chunk, so documents get the same ids.
+        let binary = root.join("binary");
+        fs::create_dir_all(&binary).await?;
+        let index = Arc::new(AtomicU32::new(0));
+        let mut binary_files = Vec::new();
+        for path in corpus.files.iter() {
+            RepeatedXmlReader::<_, CaseKeepingInterpreter>::new(
+                CommU8Provider::from_path(path).await?,
+                corpus.attributes.clone(),
+            )
+            .await?
+            .divide_write_binary(binary.to_str().unwrap().to_string(), u16::MAX, index.clone())
+            .await?;
+            let chunk = index.load(std::sync::atomic::Ordering::SeqCst) - 1;
+            binary_files.push(binary.join(format!("{chunk}.{EXTENSION}")).to_str().unwrap().to_string());
+        }
+
+        let mut built = Vec::new();
+        for (name, files) in [("xml", corpus.files.clone()), ("binary", binary_files)] {
+            let destination = root.join(format!("res_{name}")).to_str().unwrap().to_string();
+            let config = IndexerConfig::new(100_000, 6)?;
+            ParseController::<IndexParser, _, _>::new(
+                files,
+                destination.clone(),
+                root.join(format!("buffer_{name}")).to_str().unwrap().to_string(),
+                1,
+                IndexedBuilder::new(config, corpus.attributes.clone())?,
+                IndexMerger::new(config.merger()),
+            )
+            .create_dictionary()
+            .await?;
+            let layout = IndexLayout::V1;
+            let mut files = Vec::new();
+            for path in [
+                layout.dictionary(&destination),
+                layout.lexical_part(&destination),
+                layout.index_part(&destination),
+                layout.lengths(&destination),
+            ] {
+                files.push(fs::read(path).await?);
+            }
+            built.push(files);
+        }
+        assert!(built[0] == built[1]);
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn zones_keep_their_order_through_the_build() -> Result<(), Error> {
+        let root = scratch("zone_order").await?;
+        let corpus = CorpusSpec {
+            docs: 30,
+            zones: vec![("text", 7), ("title", 2)],
+            files: 1,
+            ..CorpusSpec::default()
+        }
+        .generate(&root.join("xml"))
+        .await?;
+        assert_eq!(corpus.attributes.index_of("text"), Some(0));
+        let binary = root.join("binary");
+        fs::create_dir_all(&binary).await?;
+        RepeatedXmlReader::<_, CaseKeepingInterpreter>::new(
+            CommU8Provider::from_path(&corpus.files[0]).await?,
+            corpus.attributes.clone(),
+        )
+        .await?
+        .divide_write_binary(binary.to_str().unwrap().to_string(), u16::MAX, Arc::new(AtomicU32::new(0)))
+        .await?;
+        let chunk = binary.join(format!("0.{EXTENSION}")).to_str().unwrap().to_string();
+
+        let destination = root.join("res").to_str().unwrap().to_string();
+        let config = IndexerConfig::new(100_000, 6)?;
+        ParseController::<IndexParser, _, _>::new(
+            vec![chunk],
+            destination.clone(),
+            root.join("buffer").to_str().unwrap().to_string(),
+            1,
+            IndexedBuilder::new(config, corpus.attributes.clone())?,
+            IndexMerger::new(config.merger()),
+        )
+        .create_dictionary()
+        .await?;
+
+        let metadata = IndexMetadata::load(&destination).await?;
+        assert_eq!(metadata.zones.as_ref(), Some(&corpus.attributes));
+        let lengths = DocumentLengths::load(&destination).await?;
+        assert!(lengths.zone("text").unwrap()[..30].iter().all(|v| *v == 7));
+        assert!(lengths.zone("title").unwrap()[..30].iter().all(|v| *v == 2));
+
+        let selector = CommonSegmentSelector::new();
+        let cache = QueryCache::<CommonSegments>::new(0).with_zones(ZoneSet::new(["text"])?);
+        assert!(cache.get("text:abc", metadata.generation, &selector).is_ok());
+        assert_eq!(
+            cache.get("title:abc", metadata.generation, &selector).err(),
+            Some(QueryError::UnknownZone("title".to_string()))
+        );
+        let cache = cache.with_zones(metadata.zones.unwrap());
+        assert!(cache.get("title:abc", metadata.generation, &selector).is_ok());
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,177 @@
+use std::{future::Future, io::Error, ops::Range, sync::Arc};
+
+use futures::future::join_all;
+use tokio::task;
+
+use crate::{
+    indexed::{unordered, Dictionary},
+    segment::Segments,
+    stats::{IndexStats, SectionBytes},
+    term_ord::term_cmp,
+    watcher::DictionaryPool,
+};
+
+/// [`crate::indexed::verify_index`] with the terms split by ordinal into
+/// `jobs` ranges, at most one a term, each one checked by its own reader of
+/// a [`DictionaryPool`]. The checks run one after the other as there, every
+/// one over all ranges at once, so a broken index fails with the error the
+/// single walk would give: that of the first range that fails.
+///
+/// Ranges check their own terms; the order across two of them is checked
+/// once all are walked, the postings of the last term of a range against
+/// the term after it. Returns the counts the walk found, with the sizes of
+/// the files, for comparing against the stats of the build.
+pub async fn verify_index_jobs<S: Segments + 'static>(directory: &String, jobs: usize) -> Result<IndexStats, Error> {
+    let len = Dictionary::<S>::new(directory).await?.len();
+    let ranges = split(len, jobs.max(1));
+    let pool = Arc::new(DictionaryPool::<S>::open(directory, ranges.len(), 0, 0).await?);
+
+    let bounds = in_ranges(&pool, &ranges, |pool, range| async move {
+        pool.reader().await.verify_order_in(range).await
+    })
+    .await?;
+    // The seam comes before the terms of the range after it.
+    let mut previous: Option<String> = None;
+    for (range, bounds) in ranges.iter().zip(bounds) {
+        let Some((first, last)) = bounds? else {
+            continue;
+        };
+        if let Some(previous) = previous.filter(|v| term_cmp(v, &first).is_ge()) {
+            return Err(unordered(range.start, &first, &previous));
+        }
+        previous = Some(last);
+    }
+
+    let counts = in_ranges(&pool, &ranges, |pool, range| async move {
+        pool.reader().await.verify_counts_in(range).await
+    })
+    .await?
+    .into_iter()
+    .collect::<Result<Vec<_>, _>>()?;
+    in_ranges(&pool, &ranges, |pool, range| async move {
+        pool.reader().await.verify_postings_in(range).await
+    })
+    .await?
+    .into_iter()
+    .collect::<Result<(), _>>()?;
+
+    let layout = pool.reader().await.layout();
+    Ok(IndexStats {
+        occurrences: counts.iter().map(|(uses, _)| uses).sum(),
+        postings: counts.iter().map(|(_, entries)| entries).sum(),
+        vocabulary: len as u64,
+        bytes: SectionBytes::measure(directory, layout).await?,
+    })
+}
+
+/// `0..len` in `jobs` ranges as even as can be, none of them empty.
+fn split(len: usize, jobs: usize) -> Vec<Range<usize>> {
+    let step = len.div_ceil(jobs).max(1);
+    (0..len).step_by(step).map(|start| start..(start + step).min(len)).collect()
+}
+
+/// Runs `check` over every range on a task of its own, and returns what
+/// each one found, in range order. Fails only if a task does.
+async fn in_ranges<S, T, F, R>(
+    pool: &Arc<DictionaryPool<S>>,
+    ranges: &[Range<usize>],
+    check: F,
+) -> Result<Vec<Result<T, Error>>, Error>
+where
+    S: Segments + 'static,
+    T: Send + 'static,
+    F: Fn(Arc<DictionaryPool<S>>, Range<usize>) -> R,
+    R: Future<Output = Result<T, Error>> + Send + 'static,
+{
+    let tasks = ranges
+        .iter()
+        .map(|range| task::spawn(check(pool.clone(), range.clone())))
+        .collect::<Vec<_>>();
+    let mut found = Vec::with_capacity(ranges.len());
+    for checked in join_all(tasks).await {
+        found.push(checked.map_err(Error::other)?);
+    }
+    Ok(found)
+}
+
+#[cfg(all(test, feature = "build"))]
+mod tst {
+    use std::io::Error;
+
+    use tokio::fs;
+
+    use crate::{
+        indexed::{verify_index, IndexedCursor},
+        layout::IndexLayout,
+        segment::CommonSegments,
+        stats::IndexStats,
+        testsupport::{scratch, CorpusSpec},
+    };
+
+    use super::{split, verify_index_jobs};
+
+    /// Both verifiers over `directory`, which has to fail the same way.
+    async fn same_failure(directory: &String, jobs: usize) -> String {
+        let single = verify_index::<CommonSegments>(directory).await.unwrap_err();
+        let ranged = verify_index_jobs::<CommonSegments>(directory, jobs).await.unwrap_err();
+        assert_eq!((ranged.kind(), ranged.to_string()), (single.kind(), single.to_string()));
+        single.to_string()
+    }
+
+    #[tokio::test]
+    async fn jobs_agree_with_the_single_walk() -> Result<(), Error> {
+        assert_eq!(split(10, 3), [0..4, 4..8, 8..10]);
+        assert_eq!(split(2, 4), [0..1, 1..2]);
+        assert!(split(0, 4).is_empty());
+
+        let root = scratch("verify_jobs").await?;
+        let corpus = CorpusSpec {
+            docs: 80,
+            ..CorpusSpec::default()
+        }
+        .generate(&root.join("corpus"))
+        .await?;
+        let destination = corpus.index(&root).await?;
+        let saved = IndexStats::load(&destination).await?;
+        verify_index::<CommonSegments>(&destination).await?;
+        for jobs in [1, 3, 8, 50] {
+            assert_eq!(verify_index_jobs::<CommonSegments>(&destination, jobs).await?, saved, "{jobs}");
+        }
+
+        let layout = IndexLayout::detect(&destination).await?;
+        let pointers = layout.dictionary(&destination);
+        let valid = fs::read(&pointers).await?;
+        let len = saved.vocabulary;
+        let entry = |ordinal: u64| IndexedCursor::offset(ordinal) as usize;
+
+        // Swapped across the seam of the first two of four ranges, every
+        // range is in order by itself.
+        let seam = split(len as usize, 4)[1].start as u64;
+        let mut swapped = valid.clone();
+        let (before, after) = swapped[entry(seam - 1)..].split_at_mut(IndexedCursor::SERIALIZED_SIZE);
+        before.swap_with_slice(&mut after[..IndexedCursor::SERIALIZED_SIZE]);
+        fs::write(&pointers, &swapped).await?;
+        let error = same_failure(&destination, 4).await;
+        assert!(error.starts_with(&format!("term {seam} ")), "{error}");
+
+        // A term used less often than it has postings in the second range
+        // and one in the last: the second fails first.
+        let mut unused = valid.clone();
+        for ordinal in [len / 3, len - 1] {
+            unused[entry(ordinal) + 17..entry(ordinal + 1)].fill(0);
+        }
+        fs::write(&pointers, &unused).await?;
+        same_failure(&destination, 3).await;
+        fs::write(&pointers, &valid).await?;
+
+        // Postings cut short fail the last range, checked against the end
+        // of the file.
+        let index_part = layout.index_part(&destination);
+        let postings = fs::read(&index_part).await?;
+        fs::write(&index_part, &postings[..postings.len() - 1]).await?;
+        same_failure(&destination, 3).await;
+
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
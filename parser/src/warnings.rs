@@ -0,0 +1,187 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+    io::{Error, ErrorKind},
+    sync::{Arc, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Warnings of one file kept whole; past them only the counts go on.
+pub const WARNINGS_PER_FILE: usize = 100;
+
+/// Longest word a reader hands out, in bytes. Longer ones are cut at the
+/// last character that fits.
+pub const MAX_TOKEN_BYTES: usize = 256;
+
+/// Something odd a reader got past without dropping the document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningKind {
+    /// An `&name;` other than the five XML ones, left out of the word.
+    UnknownEntity,
+    /// A word over [`MAX_TOKEN_BYTES`], cut to it.
+    OverlongToken,
+    /// A closing tag of a zone outside of any zone, skipped.
+    UnexpectedClose,
+}
+
+impl WarningKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WarningKind::UnknownEntity => "unknown_entity",
+            WarningKind::OverlongToken => "overlong_token",
+            WarningKind::UnexpectedClose => "unexpected_close",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReaderWarning {
+    pub kind: WarningKind,
+    /// Bytes of the input read when the reader noticed.
+    pub byte_offset: u64,
+    /// What the reader was looking at, the entity or the start of the word.
+    pub context: String,
+}
+
+impl Display for ReaderWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}: {}", self.kind.name(), self.byte_offset, self.context)
+    }
+}
+
+/// What the readers of one file warned about.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WarningSummary {
+    /// Every warning, by kind.
+    pub counts: BTreeMap<WarningKind, usize>,
+    /// The first warnings, up to the cap of the [`Warnings`] they went to.
+    pub kept: Vec<ReaderWarning>,
+}
+
+impl WarningSummary {
+    pub fn total(&self) -> usize {
+        self.counts.values().sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// Adds the counts of `other`, leaving its warnings out.
+    pub fn add_counts(&mut self, other: &WarningSummary) {
+        for (kind, count) in other.counts.iter() {
+            *self.counts.entry(*kind).or_default() += count;
+        }
+    }
+}
+
+impl Display for WarningSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let counts = self
+            .counts
+            .iter()
+            .map(|(kind, count)| format!("{} {count}", kind.name()))
+            .collect::<Vec<_>>();
+        write!(f, "{}", counts.join(", "))
+    }
+}
+
+/// What a build does when its readers drop or change part of the input:
+/// an unknown entity left out, a token cut, a document skipped.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningPolicy {
+    /// Note it in the report and go on.
+    #[default]
+    Lenient,
+    /// Fail the build once parsing is done, see
+    /// [`crate::report::StrictError`].
+    Strict,
+}
+
+impl WarningPolicy {
+    pub fn parse(raw: &str) -> Result<Self, Error> {
+        match raw {
+            "lenient" => Ok(Self::Lenient),
+            "strict" => Ok(Self::Strict),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("unknown warning policy {raw:?}, expected lenient or strict"),
+            )),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct WarningLog {
+    cap: usize,
+    summary: WarningSummary,
+}
+
+/// Where the readers of a file send their warnings. Clones share it, so the
+/// one that made a reader takes what it gathered.
+#[derive(Debug, Clone)]
+pub struct Warnings {
+    log: Arc<Mutex<WarningLog>>,
+}
+
+impl Warnings {
+    /// Keeps the first `cap` warnings and counts the rest.
+    pub fn new(cap: usize) -> Self {
+        Self {
+            log: Arc::new(Mutex::new(WarningLog {
+                cap,
+                summary: WarningSummary::default(),
+            })),
+        }
+    }
+
+    pub fn emit(&self, kind: WarningKind, byte_offset: u64, context: String) {
+        let mut log = self.log.lock().unwrap();
+        *log.summary.counts.entry(kind).or_default() += 1;
+        if log.summary.kept.len() < log.cap {
+            log.summary.kept.push(ReaderWarning {
+                kind,
+                byte_offset,
+                context,
+            });
+        }
+    }
+
+    /// Hands out what was gathered and starts over.
+    pub fn take(&self) -> WarningSummary {
+        std::mem::take(&mut self.log.lock().unwrap().summary)
+    }
+}
+
+impl Default for Warnings {
+    fn default() -> Self {
+        Self::new(WARNINGS_PER_FILE)
+    }
+}
+
+#[cfg(test)]
+mod tst {
+    use super::{WarningKind, Warnings};
+
+    #[test]
+    fn counts_past_the_cap() {
+        let warnings = Warnings::new(2);
+        let reader = warnings.clone();
+        for i in 0..5 {
+            reader.emit(WarningKind::UnknownEntity, i, format!("&e{i};"));
+        }
+        reader.emit(WarningKind::OverlongToken, 9, "long".to_string());
+        let summary = warnings.take();
+        assert_eq!(summary.total(), 6);
+        assert_eq!(summary.counts[&WarningKind::UnknownEntity], 5);
+        assert_eq!(
+            summary.kept.iter().map(|v| v.to_string()).collect::<Vec<_>>(),
+            ["unknown_entity at byte 0: &e0;", "unknown_entity at byte 1: &e1;"]
+        );
+        assert_eq!(summary.to_string(), "unknown_entity 5, overlong_token 1");
+        assert!(warnings.take().is_empty());
+    }
+}
@@ -0,0 +1,270 @@
+use std::{
+    io::{Error, ErrorKind},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, SystemTime},
+};
+
+use tokio::{
+    fs,
+    sync::{Mutex, MutexGuard},
+    task::{self, JoinHandle},
+    time,
+};
+
+use crate::{
+    block_cache::{BlockCache, DEFAULT_BLOCK_CACHE},
+    generation::resolve,
+    indexed::{Dictionary, IndexedCursor},
+    layout::IndexLayout,
+    segment::Segments,
+};
+
+/// A fixed set of readers over one generation of an index directory, sharing
+/// one [`BlockCache`].
+pub struct DictionaryPool<S: Segments> {
+    generation: u64,
+    readers: Vec<Mutex<Dictionary<S>>>,
+    next: AtomicUsize,
+    block_cache: BlockCache,
+}
+
+impl<S: Segments> DictionaryPool<S> {
+    /// Opens `size` readers whose block cache holds up to `block_cache`
+    /// lexical blocks; 0 leaves it off.
+    pub async fn open(
+        directory: &String,
+        size: usize,
+        generation: u64,
+        block_cache: usize,
+    ) -> Result<Self, Error> {
+        let cache = BlockCache::new(block_cache);
+        let mut readers = Vec::with_capacity(size.max(1));
+        for _ in 0..size.max(1) {
+            let dictionary = Dictionary::new(directory).await?.with_block_cache(cache.clone());
+            readers.push(Mutex::new(dictionary));
+        }
+        Ok(Self {
+            generation,
+            readers,
+            next: AtomicUsize::new(0),
+            block_cache: cache,
+        })
+    }
+
+    pub fn block_cache(&self) -> &BlockCache {
+        &self.block_cache
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Hands out readers round-robin, waiting if the chosen one is busy.
+    pub async fn reader(&self) -> MutexGuard<'_, Dictionary<S>> {
+        let next = self.next.fetch_add(1, Ordering::Relaxed) % self.readers.len();
+        self.readers[next].lock().await
+    }
+}
+
+pub type PoolHandle<S> = Arc<RwLock<Arc<DictionaryPool<S>>>>;
+
+/// Polls the destination directory and swaps in a fresh `DictionaryPool` once
+/// a rebuilt index lands there. Queries already holding the previous pool keep
+/// using it until they drop their `Arc`.
+///
+/// Built with [`Self::follow_current`] it watches a parent of generations
+/// instead and reloads when the `CURRENT` pointer moves.
+pub struct IndexWatcher<S: Segments> {
+    directory: String,
+    pool_size: usize,
+    block_cache: usize,
+    current: PoolHandle<S>,
+    stamp: Stamp,
+    follow: bool,
+}
+
+/// Live directory with the modification time and length of its pointer file.
+type Stamp = (String, SystemTime, u64);
+
+impl<S: Segments + 'static> IndexWatcher<S> {
+    pub async fn new(directory: String, pool_size: usize) -> Result<Self, Error> {
+        Self::open(directory, pool_size, false).await
+    }
+
+    /// Watches the generation `CURRENT` in `parent` points at.
+    pub async fn follow_current(parent: String, pool_size: usize) -> Result<Self, Error> {
+        Self::open(parent, pool_size, true).await
+    }
+
+    async fn open(directory: String, pool_size: usize, follow: bool) -> Result<Self, Error> {
+        let stamp = stamp_of(&directory, follow).await?;
+        verify(&stamp.0).await?;
+        let pool = DictionaryPool::open(&stamp.0, pool_size, 0, DEFAULT_BLOCK_CACHE).await?;
+        Ok(Self {
+            directory,
+            pool_size,
+            block_cache: DEFAULT_BLOCK_CACHE,
+            current: Arc::new(RwLock::new(Arc::new(pool))),
+            stamp,
+            follow,
+        })
+    }
+
+    /// Lexical blocks each pool caches, [`DEFAULT_BLOCK_CACHE`] unless set;
+    /// 0 turns the cache off.
+    pub fn with_block_cache(mut self, capacity: usize) -> Self {
+        self.block_cache = capacity;
+        self.current().block_cache().set_capacity(capacity);
+        self
+    }
+
+    pub fn handle(&self) -> PoolHandle<S> {
+        self.current.clone()
+    }
+
+    pub fn current(&self) -> Arc<DictionaryPool<S>> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Reloads the pool if the index changed since the last poll. Returns
+    /// whether a new generation was installed.
+    pub async fn poll(&mut self) -> Result<bool, Error> {
+        let stamp = stamp_of(&self.directory, self.follow).await?;
+        if stamp == self.stamp {
+            return Ok(false);
+        }
+        verify(&stamp.0).await?;
+        let generation = self.current().generation() + 1;
+        let pool = DictionaryPool::open(&stamp.0, self.pool_size, generation, self.block_cache).await?;
+        *self.current.write().unwrap() = Arc::new(pool);
+        self.stamp = stamp;
+        log::info!(
+            "Index at {} reloaded, generation {}",
+            self.directory,
+            generation
+        );
+        Ok(true)
+    }
+
+    pub fn spawn(mut self, period: Duration) -> JoinHandle<()> {
+        task::spawn(async move {
+            let mut interval = time::interval(period);
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.poll().await {
+                    log::warn!("Index reload at {} skipped: {}", self.directory, e);
+                }
+            }
+        })
+    }
+}
+
+async fn stamp_of(directory: &str, follow: bool) -> Result<Stamp, Error> {
+    let directory = if follow {
+        resolve(directory).await?
+    } else {
+        directory.to_string()
+    };
+    let path = IndexLayout::detect(&directory).await?.dictionary(&directory);
+    let metadata = fs::metadata(path).await?;
+    Ok((directory, metadata.modified()?, metadata.len()))
+}
+
+/// Cheap sanity check: the header term count must account for the pointer
+/// file length exactly, which rules out half-written indexes.
+async fn verify(directory: &str) -> Result<(), Error> {
+    use tokio::io::AsyncReadExt;
+
+    let path = IndexLayout::detect(directory).await?.dictionary(directory);
+    let mut pointer_part = fs::File::open(path).await?;
+    let len = pointer_part.metadata().await?.len();
+    let declared = pointer_part.read_u64().await?;
+    if IndexedCursor::offset(declared) != len {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("dictionary header declares {declared} terms but file has {len} bytes"),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tst {
+    use std::{
+        io::Error,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+    };
+
+    use tokio::{fs, task};
+
+    use crate::{
+        indexed::{IndexMergeSaver, IndexedTerm, UsageData},
+        segment::CommonSegments,
+    };
+
+    use super::IndexWatcher;
+
+    async fn write_index(directory: &String, terms: &[&str]) -> Result<(), Error> {
+        fs::create_dir_all(directory).await?;
+        let mut saver = IndexMergeSaver::<CommonSegments>::new(directory.clone(), 6).await?;
+        for (i, t) in terms.iter().enumerate() {
+            let mut term = IndexedTerm::new(t.to_string());
+            let mut usage = UsageData::new();
+            *usage.use_count_mut() = 1;
+            term.indexes.push(i, usage);
+            term.use_count = 1;
+            saver.push(term).await?;
+        }
+        saver.finish().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reload_on_swap() -> Result<(), Error> {
+        let root = std::env::temp_dir().join(format!("watcher_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root).await;
+        let live = root.join("res").to_str().unwrap().to_string();
+        let next = root.join("next").to_str().unwrap().to_string();
+        write_index(&live, &["alpha", "beta"]).await?;
+
+        let mut watcher = IndexWatcher::<CommonSegments>::new(live.clone(), 2).await?;
+        assert!(!watcher.poll().await?);
+
+        let handle = watcher.handle();
+        let stop = Arc::new(AtomicBool::new(false));
+        let queries = {
+            let stop = stop.clone();
+            task::spawn(async move {
+                while !stop.load(Ordering::SeqCst) {
+                    let pool = handle.read().unwrap().clone();
+                    let term = pool.reader().await.find("alpha").await.unwrap();
+                    assert!(term.is_some());
+                    task::yield_now().await;
+                }
+            })
+        };
+
+        write_index(&next, &["alpha", "gamma", "zeta"]).await?;
+        let old = root.join("old");
+        fs::rename(&live, &old).await?;
+        let _ = watcher.poll().await;
+        fs::rename(&next, &live).await?;
+        assert!(watcher.poll().await?);
+
+        let pool = watcher.current();
+        assert_eq!(pool.generation(), 1);
+        assert!(pool.reader().await.find("gamma").await?.is_some());
+        assert!(pool.reader().await.find("beta").await?.is_none());
+
+        stop.store(true, Ordering::SeqCst);
+        queries.await.unwrap();
+        fs::remove_dir_all(&root).await?;
+        Ok(())
+    }
+}
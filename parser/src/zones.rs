@@ -0,0 +1,184 @@
+use std::{
+    collections::HashSet,
+    io::{Error, ErrorKind},
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Most zones a [`ZoneSet`] holds, so a [`ZoneId`] fits a byte.
+pub const MAX_ZONES: usize = 256;
+
+/// The one copy of `name` kept for the whole process, leaked on first use.
+/// Meant for the few short strings read over and over, zone names and the
+/// like, never for words.
+pub fn intern(name: &str) -> &'static str {
+    static NAMES: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+    let mut names = NAMES.get_or_init(Default::default).lock().unwrap();
+    match names.get(name) {
+        Some(v) => v,
+        None => {
+            let name: &'static str = Box::leak(name.into());
+            names.insert(name);
+            name
+        }
+    }
+}
+
+/// Place of a zone in its [`ZoneSet`]. Readers and the parser compare these
+/// per word instead of the names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ZoneId(u8);
+
+impl ZoneId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Zones of a document in the order the reader meets them, each named once.
+/// A zone is known by its place in the set everywhere past the reader: the
+/// token stream header, the zone lengths and `metadata.json` all keep this
+/// order.
+///
+/// The names are [`intern`]ed, and clones share them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<String>", into = "Vec<String>")]
+pub struct ZoneSet {
+    names: Arc<[&'static str]>,
+}
+
+impl ZoneSet {
+    /// Fails on no names at all, an empty name, one given twice or more
+    /// than [`MAX_ZONES`] of them.
+    pub fn new<I: IntoIterator<Item = N>, N: Into<String>>(names: I) -> Result<Self, Error> {
+        let names = names.into_iter().map(Into::into).collect::<Vec<String>>();
+        let mut problems = Vec::new();
+        if names.is_empty() {
+            problems.push("no zones given".to_string());
+        }
+        if names.len() > MAX_ZONES {
+            problems.push(format!("{} zones given, at most {MAX_ZONES} fit", names.len()));
+        }
+        for (i, name) in names.iter().enumerate() {
+            if name.is_empty() {
+                problems.push(format!("zone {} is empty", i + 1));
+            } else if names[..i].contains(name) {
+                problems.push(format!("\"{name}\" is repeated"));
+            }
+        }
+        match problems.is_empty() {
+            true => Ok(Self {
+                names: names.iter().map(|v| intern(v)).collect(),
+            }),
+            false => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("invalid zones: {}", problems.join(", ")),
+            )),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Always false, a set holds at least one zone.
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|v| *v == name)
+    }
+
+    pub fn name_of(&self, index: usize) -> Option<&'static str> {
+        self.names.get(index).copied()
+    }
+
+    pub fn id_of(&self, name: &str) -> Option<ZoneId> {
+        self.index_of(name).map(|v| ZoneId(v as u8))
+    }
+
+    /// The zone at `index` in reading order.
+    pub fn id_at(&self, index: usize) -> Option<ZoneId> {
+        (index < self.len()).then_some(ZoneId(index as u8))
+    }
+
+    /// Panics on an id of a larger set.
+    pub fn name(&self, id: ZoneId) -> &'static str {
+        self.names[id.index()]
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.index_of(name).is_some()
+    }
+
+    /// The zone a document starts with.
+    pub fn first(&self) -> &'static str {
+        self.names[0]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.names.iter().copied()
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = ZoneId> {
+        (0..self.len()).map(|v| ZoneId(v as u8))
+    }
+}
+
+impl TryFrom<Vec<String>> for ZoneSet {
+    type Error = Error;
+
+    fn try_from(names: Vec<String>) -> Result<Self, Error> {
+        Self::new(names)
+    }
+}
+
+impl From<ZoneSet> for Vec<String> {
+    fn from(zones: ZoneSet) -> Self {
+        zones.names.iter().map(|v| v.to_string()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tst {
+    use super::{intern, ZoneSet, MAX_ZONES};
+
+    #[test]
+    fn construction_refuses_empty_and_repeated() {
+        let zones = ZoneSet::new(["title", "text"]).unwrap();
+        assert_eq!((zones.len(), zones.first()), (2, "title"));
+        assert_eq!((zones.index_of("text"), zones.index_of("body")), (Some(1), None));
+        assert_eq!((zones.name_of(0), zones.name_of(2)), (Some("title"), None));
+        assert_eq!(zones.iter().collect::<Vec<_>>(), ["title", "text"]);
+        let text = zones.id_of("text").unwrap();
+        assert_eq!((text.index(), zones.name(text)), (1, "text"));
+        assert_eq!((zones.id_at(1), zones.id_at(2)), (Some(text), None));
+        assert_eq!(zones.ids().collect::<Vec<_>>(), [zones.id_of("title").unwrap(), text]);
+        // Sets over the same names share one copy of each.
+        let other = ZoneSet::new(["text".to_string()]).unwrap();
+        assert!(std::ptr::eq(other.first(), zones.name(text)));
+        assert!(std::ptr::eq(intern(&"text".to_string()), other.first()));
+
+        let invalid = |names: &[&str]| ZoneSet::new(names.iter().copied()).unwrap_err().to_string();
+        assert_eq!(invalid(&[]), "invalid zones: no zones given");
+        assert_eq!(invalid(&["title", ""]), "invalid zones: zone 2 is empty");
+        assert_eq!(
+            invalid(&["title", "text", "title", "", "text"]),
+            "invalid zones: \"title\" is repeated, zone 4 is empty, \"text\" is repeated"
+        );
+
+        let many = (0..=MAX_ZONES).map(|v| v.to_string());
+        assert_eq!(
+            ZoneSet::new(many).unwrap_err().to_string(),
+            "invalid zones: 257 zones given, at most 256 fit"
+        );
+
+        let json = serde_json::to_string(&zones).unwrap();
+        assert_eq!(json, r#"["title","text"]"#);
+        assert_eq!(serde_json::from_str::<ZoneSet>(&json).unwrap(), zones);
+        let error = serde_json::from_str::<ZoneSet>(r#"["text","text"]"#).unwrap_err();
+        assert!(error.to_string().starts_with("invalid zones: \"text\" is repeated"), "{error}");
+    }
+}
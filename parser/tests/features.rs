@@ -0,0 +1,57 @@
+//! Checks the library alone under every feature of the package, the way a
+//! crate embedding only part of it pulls it in.
+
+use std::process::Command;
+
+use serde_json::Value;
+
+const MANIFEST: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+
+fn cargo() -> Command {
+    Command::new(std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string()))
+}
+
+/// Features of the package as `cargo metadata` lists them, without `default`.
+fn features() -> Vec<String> {
+    let output = cargo()
+        .args(["metadata", "--no-deps", "--format-version", "1", "--manifest-path", MANIFEST])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let metadata = serde_json::from_slice::<Value>(&output.stdout).unwrap();
+    let package = metadata["packages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|v| v["name"] == env!("CARGO_PKG_NAME"))
+        .unwrap();
+    package["features"]
+        .as_object()
+        .unwrap()
+        .keys()
+        .filter(|v| *v != "default")
+        .cloned()
+        .collect()
+}
+
+#[test]
+#[ignore]
+fn every_feature_checks_alone() {
+    let target = format!("{}/features", env!("CARGO_TARGET_TMPDIR"));
+    let mut sets = vec![String::new()];
+    sets.extend(features());
+    let mut failed = Vec::new();
+    for set in sets.iter() {
+        let status = cargo()
+            .args(["check", "--lib", "--no-default-features", "--manifest-path", MANIFEST])
+            .args(["--features", set, "--target-dir", &target])
+            .status()
+            .unwrap();
+        println!("[{set}]: {status}");
+        if !status.success() {
+            failed.push(set.clone());
+        }
+    }
+    assert!(sets.iter().any(|v| v == "query"));
+    assert!(failed.is_empty(), "failed with features {failed:?}");
+}
@@ -0,0 +1,105 @@
+//! Builds indexes through the one-call API and queries what it wrote.
+#![cfg(feature = "build")]
+
+use std::path::{Path, PathBuf};
+
+use parser::{
+    cancel::CancellationToken,
+    config::IndexerConfig,
+    execute::{execute_raw, QueryLimits, QueryOptions},
+    indexed::{Dictionary, IndexMerger, IndexedBuilder},
+    pipeline::{index_directory, IndexOptions},
+    query::QueryCache,
+    resolve::DocResolver,
+    segment::{CommonSegmentSelector, CommonSegments},
+    synonym::Thesaurus,
+    zones::ZoneSet,
+};
+use tokio::fs;
+
+fn scratch(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("{name}_{}", std::process::id()))
+}
+
+fn options() -> IndexOptions {
+    let config = IndexerConfig::new(1000, 6).unwrap();
+    let builder = IndexedBuilder::new(config, ZoneSet::new(["title", "text"]).unwrap()).unwrap();
+    IndexOptions::new(builder, IndexMerger::new(config.merger())).with_tasks(1)
+}
+
+async fn query(destination: &Path, raw: &str) -> Vec<usize> {
+    let mut dictionary = Dictionary::<CommonSegments>::new(&destination.to_str().unwrap().to_string())
+        .await
+        .unwrap();
+    let result = execute_raw(
+        raw,
+        &QueryCache::<CommonSegments>::new(0),
+        0,
+        &CommonSegmentSelector::new(),
+        &mut dictionary,
+        QueryOptions {
+            limits: QueryLimits::default(),
+            synonyms: &Thesaurus::default(),
+            cancel: &CancellationToken::new(),
+        },
+    )
+    .await
+    .unwrap();
+    result.documents
+}
+
+#[tokio::test]
+async fn the_bundled_fixture_indexes_in_one_call() {
+    let root = scratch("index_directory");
+    let _ = fs::remove_dir_all(&root).await;
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("test");
+    let destination = root.join("res");
+
+    let stats = index_directory(&fixture, &destination, options().with_buffer(root.join("buffer")))
+        .await
+        .unwrap();
+    // "cra", "gra", "hcra" and "lgra", once each.
+    assert_eq!((stats.occurrences, stats.postings, stats.vocabulary), (4, 4, 4));
+    assert!(stats.bytes.total() > 0);
+    assert_eq!(query(&destination, "hcra").await, [1]);
+    assert_eq!(query(&destination, "cra OR lgra").await, [0, 1]);
+
+    // An index is there already.
+    let error = index_directory(&fixture, &destination, options().with_buffer(root.join("buffer")))
+        .await
+        .unwrap_err();
+    assert!(error.to_string().starts_with("build failed: "), "{error}");
+    fs::remove_dir_all(&root).await.unwrap();
+}
+
+#[tokio::test]
+async fn a_dump_is_split_before_it_is_indexed() {
+    let root = scratch("index_dump");
+    let _ = fs::remove_dir_all(&root).await;
+    fs::create_dir_all(root.join("input")).await.unwrap();
+    let pages = ["alpha", "beta", "gamma", "delta", "epsilon"]
+        .iter()
+        .map(|v| format!("<page>\n<title> {v} </title>\n<revision>\n<text bytes=\"9\">shared {v}s </text>\n</revision>\n</page>\n"))
+        .collect::<String>();
+    fs::write(root.join("input/dump.xml"), format!("<?xml version=\"1.0\"?>\n<mediawiki>\n{pages}</mediawiki>\n"))
+        .await
+        .unwrap();
+    let destination = root.join("res");
+
+    let stats = index_directory(
+        root.join("input"),
+        &destination,
+        options().with_chunk_documents(2).with_buffer(root.join("buffer")),
+    )
+    .await
+    .unwrap();
+    assert_eq!((stats.occurrences, stats.vocabulary), (15, 11));
+    assert_eq!(query(&destination, "shared").await, [0, 1, 2, 3, 4]);
+
+    let resolver = DocResolver::load(&destination.to_str().unwrap().to_string()).await.unwrap();
+    let chunks = root.join("res.chunks");
+    for (document, chunk) in [(0, "0.tok"), (1, "0.tok"), (2, "1.tok"), (4, "2.tok")] {
+        assert_eq!(resolver.source_of(document), Some(chunks.join(chunk).as_path()), "{document}");
+    }
+    fs::remove_dir_all(&root).await.unwrap();
+}
@@ -0,0 +1,41 @@
+//! Runs a whole build through the binary and checks the library wrote
+//! nothing to stdout along the way; progress goes to the log.
+#![cfg(feature = "build")]
+
+use std::{fs, path::PathBuf, process::Command};
+
+fn document(title: &str, text: &str) -> String {
+    format!("<title>\n{title}\n</title>\n<text>\n{text}\n</text>\n")
+}
+
+#[test]
+fn build_prints_nothing() {
+    let root = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("quiet_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    let (corpus, work) = (root.join("corpus"), root.join("work"));
+    fs::create_dir_all(corpus.join("nested")).unwrap();
+    fs::create_dir_all(&work).unwrap();
+    fs::write(
+        corpus.join("0.xml"),
+        [document("first", "alpha beta"), document("second", "gamma")].concat(),
+    )
+    .unwrap();
+    fs::write(corpus.join("nested/1.xml"), document("third", "alpha delta")).unwrap();
+
+    // The binary builds into `../res` of where it runs.
+    for in_memory in [true, false] {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_parser"));
+        command
+            .current_dir(&work)
+            .args(["--input", corpus.to_str().unwrap(), "--overwrite", "--clean-buffer"]);
+        if !in_memory {
+            command.args(["--in-memory-below", "0", "--tree-max-terms", "2"]);
+        }
+        let output = command.output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "", "in memory: {in_memory}");
+        assert!(root.join("res/report.json").exists());
+    }
+
+    fs::remove_dir_all(&root).unwrap();
+}
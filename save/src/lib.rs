@@ -1,3 +1,4 @@
 pub mod save;
+pub mod sha256;
 pub mod u8;
 pub mod writer;
\ No newline at end of file
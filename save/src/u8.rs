@@ -1,11 +1,20 @@
-use std::io::{Error, SeekFrom};
+use std::{
+    io::{Cursor, Error, ErrorKind, SeekFrom},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
 use async_trait::async_trait;
 use tokio::{
     fs::File,
-    io::{AsyncReadExt, AsyncSeekExt, BufReader},
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, BufReader},
 };
 
+use crate::sha256::Sha256;
+
 #[async_trait]
 pub trait U8Provider: Sized {
     type Reader;
@@ -15,7 +24,38 @@ pub trait U8Provider: Sized {
 
     async fn take<const SIZE: usize>(&mut self) -> Option<[u8; SIZE]>;
 
+    /// [`U8Provider::take`] telling the end of the stream, `Ok(None)`, from
+    /// a failed read, `Err`. A stream ending inside the `SIZE` bytes is an
+    /// [`ErrorKind::UnexpectedEof`] error.
+    async fn try_take<const SIZE: usize>(&mut self) -> Result<Option<[u8; SIZE]>, Error> {
+        match self.take::<SIZE>().await {
+            Some(v) => Ok(Some(v)),
+            None => self.take_error().map_or(Ok(None), Err),
+        }
+    }
+
+    /// The next byte without handing it out, the following read returns it
+    /// again. `None` at the end of the stream, and from providers that can't
+    /// look ahead, which is what the default does.
+    async fn peek_u8(&mut self) -> Option<u8> {
+        None
+    }
+
     async fn from_path(path: &String) -> Result<Self, Error>;
+
+    /// Bytes of the underlying file handed out so far, whatever the provider
+    /// has buffered ahead of them.
+    fn consumed_bytes(&self) -> u64;
+
+    /// The I/O error that ended the stream, if it was not a plain end of file.
+    fn take_error(&mut self) -> Option<Error> {
+        None
+    }
+
+    /// SHA-256 of the bytes handed out so far, if the provider keeps one.
+    fn digest(&self) -> Option<[u8; 32]> {
+        None
+    }
 }
 #[async_trait]
 pub trait MovableU8Provider: U8Provider {
@@ -23,34 +63,89 @@ pub trait MovableU8Provider: U8Provider {
 }
 
 pub struct CommU8Provider {
-    buf: [u8; 1],
     reader: BufReader<File>,
+    error: Option<Error>,
+    consumed: u64,
 }
 
 impl CommU8Provider {
     pub fn new(reader: BufReader<File>) -> Self {
-        Self { buf: [0], reader }
+        Self {
+            reader,
+            error: None,
+            consumed: 0,
+        }
+    }
+
+    fn keep_error(&mut self, error: Error) {
+        if error.kind() != ErrorKind::UnexpectedEof {
+            self.error = Some(error);
+        }
     }
 
     #[inline(always)]
     pub async fn next_u8(&mut self) -> Option<u8> {
-        if let Ok(out) = self.reader.read_exact(&mut self.buf).await {
-            if out != 0 {
-                return Some(self.buf[0]);
-            }
-        }
-        None
+        self.take::<1>().await.map(|v| v[0])
     }
 
     #[inline(always)]
     pub async fn take<const SIZE: usize>(&mut self) -> Option<[u8; SIZE]> {
+        match self.try_take::<SIZE>().await {
+            Ok(v) => v,
+            Err(e) => {
+                self.keep_error(e);
+                None
+            }
+        }
+    }
+
+    /// Reads until `SIZE` bytes are in, retrying interrupted reads. The
+    /// bytes of a short read still count as consumed.
+    pub async fn try_take<const SIZE: usize>(&mut self) -> Result<Option<[u8; SIZE]>, Error> {
         let mut res = [0u8; SIZE];
-        if self.reader.read_exact(&mut res).await.is_err() {
-            return None;
+        let mut filled = 0;
+        while filled < SIZE {
+            match self.reader.read(&mut res[filled..]).await {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.consumed += filled as u64;
+                    return Err(e);
+                }
+            }
+        }
+        self.consumed += filled as u64;
+        short_read(res, filled)
+    }
+
+    /// Looks at the buffer of the reader, filling it if it ran out.
+    pub async fn peek_u8(&mut self) -> Option<u8> {
+        loop {
+            match self.reader.fill_buf().await {
+                Ok(buf) => return buf.first().copied(),
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.keep_error(e);
+                    return None;
+                }
+            }
         }
-        Some(res)
     }
 }
+
+/// What a read of `SIZE` bytes that got `filled` of them comes to.
+fn short_read<const SIZE: usize>(res: [u8; SIZE], filled: usize) -> Result<Option<[u8; SIZE]>, Error> {
+    match filled {
+        0 if SIZE != 0 => Ok(None),
+        v if v == SIZE => Ok(Some(res)),
+        v => Err(Error::new(
+            ErrorKind::UnexpectedEof,
+            format!("stream ends {v} bytes into a read of {SIZE}"),
+        )),
+    }
+}
+
 #[async_trait]
 impl U8Provider for CommU8Provider {
     type Reader = BufReader<File>;
@@ -61,36 +156,598 @@ impl U8Provider for CommU8Provider {
 
     #[inline(always)]
     async fn next_u8(&mut self) -> Option<u8> {
-        if let Ok(out) = self.reader.read_exact(&mut self.buf).await {
-            if out != 0 {
-                return Some(self.buf[0]);
+        CommU8Provider::next_u8(self).await
+    }
+
+    #[inline(always)]
+    async fn take<const SIZE: usize>(&mut self) -> Option<[u8; SIZE]> {
+        CommU8Provider::take::<SIZE>(self).await
+    }
+
+    async fn try_take<const SIZE: usize>(&mut self) -> Result<Option<[u8; SIZE]>, Error> {
+        CommU8Provider::try_take::<SIZE>(self).await
+    }
+
+    async fn peek_u8(&mut self) -> Option<u8> {
+        CommU8Provider::peek_u8(self).await
+    }
+
+    async fn from_path(path: &String) -> Result<Self, Error> {
+        Ok(CommU8Provider::new(BufReader::new(File::open(path).await?)))
+    }
+
+    fn consumed_bytes(&self) -> u64 {
+        self.consumed
+    }
+
+    fn take_error(&mut self) -> Option<Error> {
+        self.error.take()
+    }
+}
+
+#[async_trait]
+impl MovableU8Provider for CommU8Provider {
+    async fn seek(&mut self, from: SeekFrom) -> Result<(), Error> {
+        self.consumed = self.reader.seek(from).await?;
+        Ok(())
+    }
+}
+
+/// Reads a file through `std::io`, every call completes without yielding.
+/// For readers driven outside a runtime or on a single thread.
+pub struct SyncU8Provider {
+    reader: std::io::BufReader<std::fs::File>,
+    error: Option<Error>,
+    consumed: u64,
+}
+
+impl SyncU8Provider {
+    pub fn new(reader: std::io::BufReader<std::fs::File>) -> Self {
+        Self {
+            reader,
+            error: None,
+            consumed: 0,
+        }
+    }
+
+    fn read_exact<const SIZE: usize>(&mut self) -> Option<[u8; SIZE]> {
+        match self.try_read::<SIZE>() {
+            Ok(v) => v,
+            Err(e) => {
+                if e.kind() != ErrorKind::UnexpectedEof {
+                    self.error = Some(e);
+                }
+                None
             }
         }
-        None
+    }
+
+    fn try_read<const SIZE: usize>(&mut self) -> Result<Option<[u8; SIZE]>, Error> {
+        use std::io::Read;
+
+        let mut res = [0u8; SIZE];
+        let mut filled = 0;
+        while filled < SIZE {
+            match self.reader.read(&mut res[filled..]) {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.consumed += filled as u64;
+                    return Err(e);
+                }
+            }
+        }
+        self.consumed += filled as u64;
+        short_read(res, filled)
+    }
+}
+
+#[async_trait]
+impl U8Provider for SyncU8Provider {
+    type Reader = std::io::BufReader<std::fs::File>;
+
+    fn reader(&mut self) -> &mut Self::Reader {
+        &mut self.reader
+    }
+
+    #[inline(always)]
+    async fn next_u8(&mut self) -> Option<u8> {
+        self.read_exact::<1>().map(|v| v[0])
     }
 
     #[inline(always)]
     async fn take<const SIZE: usize>(&mut self) -> Option<[u8; SIZE]> {
-        let mut res = [0u8; SIZE];
-        if self.reader.read_exact(&mut res).await.is_err() {
-            return None;
+        self.read_exact::<SIZE>()
+    }
+
+    async fn try_take<const SIZE: usize>(&mut self) -> Result<Option<[u8; SIZE]>, Error> {
+        self.try_read::<SIZE>()
+    }
+
+    async fn peek_u8(&mut self) -> Option<u8> {
+        use std::io::BufRead;
+
+        loop {
+            match self.reader.fill_buf() {
+                Ok(buf) => return buf.first().copied(),
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => {
+                    self.error = Some(e);
+                    return None;
+                }
+            }
         }
-        Some(res)
     }
 
     async fn from_path(path: &String) -> Result<Self, Error> {
-        Ok(CommU8Provider::new(BufReader::new(File::open(path).await?)))
+        Ok(Self::new(std::io::BufReader::new(std::fs::File::open(path)?)))
+    }
+
+    fn consumed_bytes(&self) -> u64 {
+        self.consumed
+    }
+
+    fn take_error(&mut self) -> Option<Error> {
+        self.error.take()
     }
 }
 
 #[async_trait]
-impl MovableU8Provider for CommU8Provider {
+impl MovableU8Provider for SyncU8Provider {
     async fn seek(&mut self, from: SeekFrom) -> Result<(), Error> {
-        self.reader.seek(from).await?;
+        use std::io::Seek;
+
+        self.consumed = self.reader.seek(from)?;
         Ok(())
     }
 }
 
+/// A file read through tokio, or through `std::io` for builds driven
+/// without a runtime.
+pub enum FileU8Provider {
+    Async(CommU8Provider),
+    Sync(SyncU8Provider),
+}
+
+#[async_trait]
+impl U8Provider for FileU8Provider {
+    type Reader = Self;
+
+    fn reader(&mut self) -> &mut Self::Reader {
+        self
+    }
+
+    #[inline(always)]
+    async fn next_u8(&mut self) -> Option<u8> {
+        match self {
+            Self::Async(v) => v.next_u8().await,
+            Self::Sync(v) => U8Provider::next_u8(v).await,
+        }
+    }
+
+    #[inline(always)]
+    async fn take<const SIZE: usize>(&mut self) -> Option<[u8; SIZE]> {
+        match self {
+            Self::Async(v) => v.take::<SIZE>().await,
+            Self::Sync(v) => U8Provider::take::<SIZE>(v).await,
+        }
+    }
+
+    async fn try_take<const SIZE: usize>(&mut self) -> Result<Option<[u8; SIZE]>, Error> {
+        match self {
+            Self::Async(v) => v.try_take::<SIZE>().await,
+            Self::Sync(v) => U8Provider::try_take::<SIZE>(v).await,
+        }
+    }
+
+    async fn peek_u8(&mut self) -> Option<u8> {
+        match self {
+            Self::Async(v) => v.peek_u8().await,
+            Self::Sync(v) => U8Provider::peek_u8(v).await,
+        }
+    }
+
+    async fn from_path(path: &String) -> Result<Self, Error> {
+        Ok(Self::Async(CommU8Provider::from_path(path).await?))
+    }
+
+    fn consumed_bytes(&self) -> u64 {
+        match self {
+            Self::Async(v) => v.consumed_bytes(),
+            Self::Sync(v) => v.consumed_bytes(),
+        }
+    }
+
+    fn take_error(&mut self) -> Option<Error> {
+        match self {
+            Self::Async(v) => v.take_error(),
+            Self::Sync(v) => v.take_error(),
+        }
+    }
+}
+
+#[async_trait]
+impl MovableU8Provider for FileU8Provider {
+    async fn seek(&mut self, from: SeekFrom) -> Result<(), Error> {
+        match self {
+            Self::Async(v) => v.seek(from).await,
+            Self::Sync(v) => v.seek(from).await,
+        }
+    }
+}
+
+/// Counts the bytes handed out by the wrapped provider.
+pub struct OffsetU8Provider<P: U8Provider> {
+    inner: P,
+    offset: u64,
+}
+
+impl<P: U8Provider> OffsetU8Provider<P> {
+    pub fn new(inner: P) -> Self {
+        Self { inner, offset: 0 }
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+#[async_trait]
+impl<P: U8Provider + Send> U8Provider for OffsetU8Provider<P> {
+    type Reader = P::Reader;
+
+    fn reader(&mut self) -> &mut Self::Reader {
+        self.inner.reader()
+    }
+
+    #[inline(always)]
+    async fn next_u8(&mut self) -> Option<u8> {
+        let next = self.inner.next_u8().await;
+        if next.is_some() {
+            self.offset += 1;
+        }
+        next
+    }
+
+    #[inline(always)]
+    async fn take<const SIZE: usize>(&mut self) -> Option<[u8; SIZE]> {
+        let next = self.inner.take::<SIZE>().await;
+        if next.is_some() {
+            self.offset += SIZE as u64;
+        }
+        next
+    }
+
+    async fn try_take<const SIZE: usize>(&mut self) -> Result<Option<[u8; SIZE]>, Error> {
+        let next = self.inner.try_take::<SIZE>().await?;
+        if next.is_some() {
+            self.offset += SIZE as u64;
+        }
+        Ok(next)
+    }
+
+    async fn peek_u8(&mut self) -> Option<u8> {
+        self.inner.peek_u8().await
+    }
+
+    async fn from_path(path: &String) -> Result<Self, Error> {
+        Ok(Self::new(P::from_path(path).await?))
+    }
+
+    fn consumed_bytes(&self) -> u64 {
+        self.inner.consumed_bytes()
+    }
+
+    fn take_error(&mut self) -> Option<Error> {
+        self.inner.take_error()
+    }
+
+    fn digest(&self) -> Option<[u8; 32]> {
+        self.inner.digest()
+    }
+}
+
+/// Hashes the bytes handed out by the wrapped provider, so a file read to
+/// its end is hashed by the same pass.
+pub struct HashingU8Provider<P: U8Provider> {
+    inner: P,
+    hash: Sha256,
+}
+
+impl<P: U8Provider> HashingU8Provider<P> {
+    pub fn new(inner: P) -> Self {
+        Self {
+            inner,
+            hash: Sha256::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl<P: U8Provider + Send> U8Provider for HashingU8Provider<P> {
+    type Reader = P::Reader;
+
+    fn reader(&mut self) -> &mut Self::Reader {
+        self.inner.reader()
+    }
+
+    #[inline(always)]
+    async fn next_u8(&mut self) -> Option<u8> {
+        let next = self.inner.next_u8().await;
+        if let Some(v) = next {
+            self.hash.push(v);
+        }
+        next
+    }
+
+    #[inline(always)]
+    async fn take<const SIZE: usize>(&mut self) -> Option<[u8; SIZE]> {
+        let next = self.inner.take::<SIZE>().await;
+        if let Some(v) = next.as_ref() {
+            self.hash.update(v);
+        }
+        next
+    }
+
+    async fn try_take<const SIZE: usize>(&mut self) -> Result<Option<[u8; SIZE]>, Error> {
+        let next = self.inner.try_take::<SIZE>().await?;
+        if let Some(v) = next.as_ref() {
+            self.hash.update(v);
+        }
+        Ok(next)
+    }
+
+    async fn peek_u8(&mut self) -> Option<u8> {
+        self.inner.peek_u8().await
+    }
+
+    async fn from_path(path: &String) -> Result<Self, Error> {
+        Ok(Self::new(P::from_path(path).await?))
+    }
+
+    fn consumed_bytes(&self) -> u64 {
+        self.inner.consumed_bytes()
+    }
+
+    fn take_error(&mut self) -> Option<Error> {
+        self.inner.take_error()
+    }
+
+    fn digest(&self) -> Option<[u8; 32]> {
+        Some(self.hash.finish())
+    }
+}
+
+/// Bytes per second the [`TokenBucket`]s sharing it let through, zero for
+/// no limit. Clones share it, so it can be changed while they read.
+#[derive(Debug, Clone, Default)]
+pub struct ReadRate(Arc<AtomicU64>);
+
+impl ReadRate {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self(Arc::new(AtomicU64::new(bytes_per_sec)))
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, bytes_per_sec: u64) {
+        self.0.store(bytes_per_sec, Ordering::Relaxed)
+    }
+}
+
+/// Holds reads back to a [`ReadRate`]. A bucket fills at the rate up to a
+/// tenth of a second of it, which a read may take at once.
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: ReadRate,
+    tokens: f64,
+    filled: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: ReadRate) -> Self {
+        Self {
+            tokens: Self::capacity(rate.get()),
+            rate,
+            filled: Instant::now(),
+        }
+    }
+
+    fn capacity(rate: u64) -> f64 {
+        (rate as f64 / 10.0).max(1.0)
+    }
+
+    /// Takes `bytes` tokens, waiting for the bucket to refill if it runs
+    /// into debt.
+    pub async fn take(&mut self, bytes: u64) {
+        self.tokens -= bytes as f64;
+        while self.tokens < 0.0 {
+            let rate = self.rate.get();
+            if rate == 0 {
+                self.tokens = 0.0;
+                return;
+            }
+            let now = Instant::now();
+            let refill = now.duration_since(self.filled).as_secs_f64() * rate as f64;
+            self.tokens = (self.tokens + refill).min(Self::capacity(rate));
+            self.filled = now;
+            if self.tokens < 0.0 {
+                // Oversleeping is made up for by the refill after it.
+                tokio::time::sleep(Duration::from_secs_f64(-self.tokens / rate as f64)).await;
+            }
+        }
+    }
+}
+
+/// Hands out the bytes of the wrapped provider no faster than its bucket
+/// lets them through, unthrottled without one.
+pub struct ThrottledU8Provider<P: U8Provider> {
+    inner: P,
+    bucket: Option<TokenBucket>,
+}
+
+impl<P: U8Provider> ThrottledU8Provider<P> {
+    pub fn new(inner: P) -> Self {
+        Self { inner, bucket: None }
+    }
+
+    pub fn with_rate(mut self, rate: ReadRate) -> Self {
+        self.bucket = Some(TokenBucket::new(rate));
+        self
+    }
+}
+
+#[async_trait]
+impl<P: U8Provider + Send> U8Provider for ThrottledU8Provider<P> {
+    type Reader = P::Reader;
+
+    fn reader(&mut self) -> &mut Self::Reader {
+        self.inner.reader()
+    }
+
+    #[inline(always)]
+    async fn next_u8(&mut self) -> Option<u8> {
+        if let Some(bucket) = self.bucket.as_mut() {
+            bucket.take(1).await;
+        }
+        self.inner.next_u8().await
+    }
+
+    #[inline(always)]
+    async fn take<const SIZE: usize>(&mut self) -> Option<[u8; SIZE]> {
+        if let Some(bucket) = self.bucket.as_mut() {
+            bucket.take(SIZE as u64).await;
+        }
+        self.inner.take::<SIZE>().await
+    }
+
+    async fn try_take<const SIZE: usize>(&mut self) -> Result<Option<[u8; SIZE]>, Error> {
+        if let Some(bucket) = self.bucket.as_mut() {
+            bucket.take(SIZE as u64).await;
+        }
+        self.inner.try_take::<SIZE>().await
+    }
+
+    async fn peek_u8(&mut self) -> Option<u8> {
+        self.inner.peek_u8().await
+    }
+
+    async fn from_path(path: &String) -> Result<Self, Error> {
+        Ok(Self::new(P::from_path(path).await?))
+    }
+
+    fn consumed_bytes(&self) -> u64 {
+        self.inner.consumed_bytes()
+    }
+
+    fn take_error(&mut self) -> Option<Error> {
+        self.inner.take_error()
+    }
+
+    fn digest(&self) -> Option<[u8; 32]> {
+        self.inner.digest()
+    }
+}
+
+/// Hands out bytes held in memory. [`MemoryU8Provider::failing_at`] breaks
+/// one read, to see how a reader takes a failing stream.
+pub struct MemoryU8Provider {
+    bytes: Cursor<Vec<u8>>,
+    failure: Option<(u64, ErrorKind)>,
+    error: Option<Error>,
+}
+
+impl MemoryU8Provider {
+    pub fn new(bytes: impl Into<Vec<u8>>) -> Self {
+        Self {
+            bytes: Cursor::new(bytes.into()),
+            failure: None,
+            error: None,
+        }
+    }
+
+    /// The first read reaching byte `at` fails with `kind` and hands out
+    /// nothing, the reads after it go on as if it hadn't happened.
+    pub fn failing_at(mut self, at: u64, kind: ErrorKind) -> Self {
+        self.failure = Some((at, kind));
+        self
+    }
+
+    fn inject(&mut self, size: u64) -> Result<(), Error> {
+        let at = self.bytes.position();
+        match self.failure {
+            Some((fail, kind)) if (at..at + size).contains(&fail) => {
+                self.failure = None;
+                Err(Error::new(kind, format!("failure injected at byte {fail}")))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn read_exact<const SIZE: usize>(&mut self) -> Result<Option<[u8; SIZE]>, Error> {
+        self.inject(SIZE as u64)?;
+        let at = self.bytes.position() as usize;
+        let rest = self.bytes.get_ref().get(at..).unwrap_or_default();
+        let filled = rest.len().min(SIZE);
+        let mut res = [0u8; SIZE];
+        res[..filled].copy_from_slice(&rest[..filled]);
+        self.bytes.set_position((at + filled) as u64);
+        short_read(res, filled)
+    }
+}
+
+#[async_trait]
+impl U8Provider for MemoryU8Provider {
+    type Reader = Cursor<Vec<u8>>;
+
+    fn reader(&mut self) -> &mut Self::Reader {
+        &mut self.bytes
+    }
+
+    async fn next_u8(&mut self) -> Option<u8> {
+        self.take::<1>().await.map(|v| v[0])
+    }
+
+    async fn take<const SIZE: usize>(&mut self) -> Option<[u8; SIZE]> {
+        match self.read_exact::<SIZE>() {
+            Ok(v) => v,
+            Err(e) => {
+                if e.kind() != ErrorKind::UnexpectedEof {
+                    self.error = Some(e);
+                }
+                None
+            }
+        }
+    }
+
+    async fn try_take<const SIZE: usize>(&mut self) -> Result<Option<[u8; SIZE]>, Error> {
+        self.read_exact::<SIZE>()
+    }
+
+    async fn peek_u8(&mut self) -> Option<u8> {
+        if let Err(e) = self.inject(1) {
+            self.error = Some(e);
+            return None;
+        }
+        self.bytes.get_ref().get(self.bytes.position() as usize).copied()
+    }
+
+    async fn from_path(path: &String) -> Result<Self, Error> {
+        Ok(Self::new(tokio::fs::read(path).await?))
+    }
+
+    fn consumed_bytes(&self) -> u64 {
+        self.bytes.position()
+    }
+
+    fn take_error(&mut self) -> Option<Error> {
+        self.error.take()
+    }
+}
+
 pub async fn read_char(reader: &mut impl U8Provider) -> Option<char> {
     let char_buf: u32;
     if let Some(r) = reader.next_u8().await {
@@ -126,7 +783,8 @@ pub async fn read_char(reader: &mut impl U8Provider) -> Option<char> {
         } else {
             char_buf = r as u32;
         }
-        char::from_u32(char_buf)
+        // A bad code point is not the end of the stream.
+        Some(char::from_u32(char_buf).unwrap_or(char::REPLACEMENT_CHARACTER))
     } else {
         None
     }
@@ -195,4 +853,138 @@ async fn take_u8<const SIZE: usize>(reader : &mut BufReader<File>) -> Result<[u8
     let mut res = [0u8; SIZE];
     reader.read_exact(&mut res).await?;
     Ok(res)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tst {
+    use std::{
+        io::{Error, ErrorKind},
+        time::{Duration, Instant},
+    };
+
+    use tokio::{fs::File, io::BufReader};
+
+    use super::{
+        read_char, CommU8Provider, HashingU8Provider, MemoryU8Provider, OffsetU8Provider, ReadRate,
+        ThrottledU8Provider, U8Provider,
+    };
+    use crate::sha256::Sha256;
+
+    #[tokio::test]
+    async fn peek_leaves_the_byte_to_read() {
+        let mut provider = MemoryU8Provider::new("ab");
+        assert_eq!(provider.peek_u8().await, Some(b'a'));
+        assert_eq!(provider.peek_u8().await, Some(b'a'));
+        assert_eq!(provider.next_u8().await, Some(b'a'));
+        assert_eq!(provider.peek_u8().await, Some(b'b'));
+        assert_eq!(provider.take::<1>().await, Some([b'b']));
+        assert_eq!(provider.peek_u8().await, None);
+        assert!(provider.try_take::<1>().await.unwrap().is_none());
+        assert_eq!(provider.consumed_bytes(), 2);
+    }
+
+    #[tokio::test]
+    async fn wrappers_dont_count_peeked_bytes() {
+        let mut provider = OffsetU8Provider::new(HashingU8Provider::new(MemoryU8Provider::new("<a>")));
+        assert_eq!(provider.peek_u8().await, Some(b'<'));
+        assert_eq!(provider.offset(), 0);
+        assert_eq!(provider.try_take::<2>().await.unwrap(), Some(*b"<a"));
+        assert_eq!(provider.peek_u8().await, Some(b'>'));
+        assert_eq!(provider.offset(), 2);
+
+        let mut hash = Sha256::new();
+        hash.update(b"<a");
+        assert_eq!(provider.digest(), Some(hash.finish()));
+    }
+
+    #[tokio::test]
+    async fn short_read_is_an_error_only_for_try_take() {
+        let mut provider = MemoryU8Provider::new("abc");
+        assert_eq!(provider.try_take::<2>().await.unwrap(), Some(*b"ab"));
+        let error = provider.try_take::<2>().await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(provider.consumed_bytes(), 3);
+
+        let mut provider = MemoryU8Provider::new("abc");
+        assert_eq!(provider.take::<2>().await, Some(*b"ab"));
+        assert_eq!(provider.take::<2>().await, None);
+        assert!(provider.take_error().is_none());
+    }
+
+    #[tokio::test]
+    async fn injected_failure_is_not_the_end() {
+        let mut provider = MemoryU8Provider::new("abc").failing_at(1, ErrorKind::Interrupted);
+        assert_eq!(provider.next_u8().await, Some(b'a'));
+        let error = provider.try_take::<2>().await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Interrupted);
+        assert_eq!(provider.try_take::<2>().await.unwrap(), Some(*b"bc"));
+
+        let mut provider = MemoryU8Provider::new("aé").failing_at(2, ErrorKind::Other);
+        assert_eq!(provider.peek_u8().await, Some(b'a'));
+        assert_eq!(read_char(&mut provider).await, Some('a'));
+        assert_eq!(read_char(&mut provider).await, None);
+        assert_eq!(provider.take_error().map(|v| v.kind()), Some(ErrorKind::Other));
+        assert_eq!(provider.peek_u8().await, Some(b'\xa9'));
+    }
+
+    /// Time `provider` takes to hand out all of its bytes, a byte or a
+    /// block of them at a time.
+    async fn read_all(mut provider: impl U8Provider + Send) -> (u64, Duration) {
+        let started = Instant::now();
+        loop {
+            let read = match provider.consumed_bytes() % 3 {
+                0 => provider.next_u8().await.is_some(),
+                _ => provider.take::<64>().await.is_some(),
+            };
+            if !read {
+                break;
+            }
+        }
+        (provider.consumed_bytes(), started.elapsed())
+    }
+
+    #[tokio::test]
+    async fn bucket_holds_reads_to_the_rate() {
+        let bytes = vec![b'x'; 60_000];
+        // A tenth of a second of the rate goes through at once.
+        let rate = ReadRate::new(200_000);
+        let throttled = ThrottledU8Provider::new(MemoryU8Provider::new(bytes.clone())).with_rate(rate);
+        let (read, elapsed) = read_all(throttled).await;
+        assert_eq!(read, 60_000);
+        assert!(elapsed >= Duration::from_millis(180), "{elapsed:?}");
+        assert!(elapsed < Duration::from_millis(600), "{elapsed:?}");
+
+        let (read, elapsed) = read_all(ThrottledU8Provider::new(MemoryU8Provider::new(bytes.clone()))).await;
+        assert_eq!(read, 60_000);
+        assert!(elapsed < Duration::from_millis(180), "{elapsed:?}");
+
+        // A rate lifted to nothing lets the rest through.
+        let rate = ReadRate::new(1_000);
+        let lifted = rate.clone();
+        let throttled = ThrottledU8Provider::new(MemoryU8Provider::new(bytes)).with_rate(rate);
+        let reading = tokio::spawn(read_all(throttled));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        lifted.set(0);
+        let (read, elapsed) = reading.await.unwrap();
+        assert_eq!(read, 60_000);
+        assert!(elapsed < Duration::from_secs(2), "{elapsed:?}");
+    }
+
+    #[tokio::test]
+    async fn file_provider_peeks_across_its_buffer() -> Result<(), Error> {
+        let path = std::env::temp_dir().join(format!("u8_peek_{}", std::process::id()));
+        tokio::fs::write(&path, "abcde").await?;
+        let mut provider = CommU8Provider::new(BufReader::with_capacity(2, File::open(&path).await?));
+        assert_eq!(provider.peek_u8().await, Some(b'a'));
+        assert_eq!(provider.try_take::<3>().await?, Some(*b"abc"));
+        assert_eq!(provider.peek_u8().await, Some(b'd'));
+        assert_eq!(provider.next_u8().await, Some(b'd'));
+        assert_eq!(provider.consumed_bytes(), 4);
+        assert_eq!(provider.try_take::<2>().await.unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(provider.peek_u8().await, None);
+        assert_eq!(provider.try_take::<1>().await?, None);
+        assert!(provider.take_error().is_none());
+        tokio::fs::remove_file(&path).await?;
+        Ok(())
+    }
+}
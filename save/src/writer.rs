@@ -10,6 +10,13 @@ use tokio::{
 
 use crate::save::VariableSave;
 
+/// A file a [`CountedWriter`] is done with, flushed and synced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FinishedFile {
+    /// Bytes in the file, wherever the writer last was.
+    pub length: u64,
+}
+
 pub struct CountedWriter {
     writer: BufWriter<File>,
     passed: u64,
@@ -44,6 +51,8 @@ impl CountedWriter {
 
     pub async fn goto(&mut self, index: u64) -> Result<(), Error> {
         self.flush().await?;
+        self.writer.seek(SeekFrom::Start(index)).await?;
+        self.passed = index;
         Ok(())
     }
 
@@ -56,6 +65,25 @@ impl CountedWriter {
     pub fn passed(&self) -> u64 {
         self.passed
     }
+
+    /// Flushes, syncs and closes the file. The writer is gone after, so
+    /// nothing can be written past the end:
+    ///
+    /// ```compile_fail
+    /// # async fn twice(mut writer: save::writer::CountedWriter) -> Result<(), std::io::Error> {
+    /// writer.finish().await?;
+    /// writer.push(b"late").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn finish(mut self) -> Result<FinishedFile, Error> {
+        self.writer.flush().await?;
+        let file = self.writer.get_ref();
+        file.sync_data().await?;
+        Ok(FinishedFile {
+            length: file.metadata().await?.len(),
+        })
+    }
 }
 
 pub async fn variable_save_usize(mut v: usize, writer: &mut BufWriter<File>) -> Result<u8, Error> {
@@ -92,18 +120,29 @@ pub async fn variable_save_u64(mut v: u64, writer: &mut BufWriter<File>) -> Resu
     Ok(writes)
 }
 
+/// Appends `v` to `out` in the encoding of [`variable_save_u64`].
+pub fn variable_encode_u64(mut v: u64, out: &mut Vec<u8>) -> u8 {
+    let mut writes = 1u8;
+    while v >> 7 > 0 {
+        out.push((v & 0b111_1111) as u8);
+        v >>= 7;
+        writes += 1;
+    }
+    out.push((v & 0b111_1111) as u8 | (1 << 7));
+    writes
+}
+
 pub async fn variable_load(reader: &mut BufReader<File>) -> Result<usize, Error> {
     let mut v = 0usize;
     let mut shift = 0;
     let mut read_slice = [0u8; 1];
-    reader.read(&mut read_slice).await?;
+    reader.read_exact(&mut read_slice).await?;
     loop {
-        // dbg!(read_slice[0]);
         if read_slice[0] & 0b1000_0000 != 0 {
             break;
         }
         v += (read_slice[0] as usize) << shift;
-        reader.read(&mut read_slice).await?;
+        reader.read_exact(&mut read_slice).await?;
         shift += 7;
     }
     v += (read_slice[0] as usize & 0b111_1111) << shift;
@@ -125,3 +164,33 @@ pub async fn variable_load(reader: &mut BufReader<File>) -> Result<usize, Error>
 //     v += (next as usize & 0b111_1111) << shift;
 //     Some(v)
 // }
+
+#[cfg(test)]
+mod tst {
+    use std::io::Error;
+
+    use tokio::{
+        fs::{self, File},
+        io::BufWriter,
+    };
+
+    use super::{CountedWriter, FinishedFile};
+
+    #[tokio::test]
+    async fn finished_length_is_on_disk() -> Result<(), Error> {
+        let path = std::env::temp_dir().join(format!("counted_{}", std::process::id()));
+        let mut writer = CountedWriter::new(BufWriter::new(File::create(&path).await?));
+        writer.push_u64(0).await?;
+        writer.push(&[7; 100]).await?;
+        writer.push_variable_u64(1 << 20).await?;
+        // A header filled in last leaves the writer before the end.
+        writer.goto(0).await?;
+        writer.push_u64(1).await?;
+        assert_eq!(writer.passed(), 8);
+        let finished = writer.finish().await?;
+        assert_eq!(finished, FinishedFile { length: 111 });
+        assert_eq!(fs::metadata(&path).await?.len(), finished.length);
+        fs::remove_file(&path).await?;
+        Ok(())
+    }
+}
@@ -6,13 +6,14 @@ use std::{
 
 use futures::future::join_all;
 use parser::{
-    reader::{CommCharInterpreter, XmlReader},
+    config::IoTuning,
+    reader::{CaseKeepingInterpreter, CommCharInterpreter, XmlReader},
     rep_reader::RepeatedXmlReader,
+    zones::ZoneSet,
 };
 use save::u8::CommU8Provider;
 use tokio::{
     fs::File,
-    io::BufReader,
     task::{self, JoinHandle},
 };
 #[tokio::main]
@@ -31,21 +32,29 @@ async fn main() -> Result<(), Error> {
         // r#"C:\Dataset\13\file.xml"#,
         // r#"C:\Dataset\14\file.xml"#,
     ];
+    // Pre-tokenized chunks keep the case, the indexer folds words itself.
+    let binary = std::env::args().any(|v| v == "--binary");
+    let io = IoTuning::default();
     let index = Arc::new(AtomicU32::new(0));
     let mut tasks = Vec::<JoinHandle<()>>::new();
     for _ in 0..files.len() {
         let file = files.pop().unwrap();
         let index = index.clone();
         tasks.push(task::spawn(async move {
-            let mut xml = RepeatedXmlReader::<_, CommCharInterpreter>::new(
-                CommU8Provider::new(BufReader::with_capacity(
-                    1024 * 1024,
-                    File::open(file).await.unwrap(),
-                )),
-                Arc::new(vec!["title".to_string(), "text".to_string()]),
-            )
-            .await
-            .unwrap();
+            let provider = CommU8Provider::new(io.chunk_reader(File::open(file).await.unwrap()));
+            let attributes = ZoneSet::new(["title", "text"]).unwrap();
+            if binary {
+                let mut xml = RepeatedXmlReader::<_, CaseKeepingInterpreter>::new(provider, attributes)
+                    .await
+                    .unwrap()
+                    .with_io(io);
+                xml.divide_write_binary(".\\gex".to_string(), 1000, index).await.unwrap();
+                return;
+            }
+            let mut xml = RepeatedXmlReader::<_, CommCharInterpreter>::new(provider, attributes)
+                .await
+                .unwrap()
+                .with_io(io);
             xml.divide_write(".\\gex".to_string(), 1000, index).await;
         }));
     }